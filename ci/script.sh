@@ -12,3 +12,7 @@ cd ..
 #cargo test --target $TARGET
 
 cargo build --target $TARGET --release
+
+# The railML bindings for the browser only build for wasm32.
+rustup target add wasm32-unknown-unknown
+cargo build --target wasm32-unknown-unknown -p railmlio-wasm
@@ -0,0 +1,159 @@
+use railmlio::topo::{self, Topological, TopoNode, AB};
+use railmlio::write;
+use std::fmt::Write;
+
+const USAGE :&str = "usage: railml-convert <input.xml> --to railml3|json|dot|svg [-o <output>]";
+
+#[derive(Debug, Copy, Clone)]
+enum Format { RailML3, Json, Dot, Svg }
+
+impl Format {
+    fn parse(s :&str) -> Result<Format, String> {
+        match s {
+            "railml3" => Ok(Format::RailML3),
+            "json" => Ok(Format::Json),
+            "dot" => Ok(Format::Dot),
+            "svg" => Ok(Format::Svg),
+            _ => Err(format!("unknown output format {:?}", s)),
+        }
+    }
+}
+
+struct Args {
+    input :String,
+    format :Format,
+    output :Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut format = None;
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => format = Some(Format::parse(&args.next().ok_or("missing format after --to")?)?),
+            "-o" => output = Some(args.next().ok_or("missing file name after -o")?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(Args {
+        input: input.ok_or("missing input file")?,
+        format: format.ok_or("missing --to")?,
+        output,
+    })
+}
+
+fn convert(data :&str, format :Format) -> Result<String, String> {
+    let railml = railmlio::xml::parse_railml(data).map_err(|e| format!("parse error: {}", e))?;
+    match format {
+        Format::RailML3 => Ok(write::write_railml3(&railml)),
+        Format::Json => serde_json::to_string_pretty(&railml).map_err(|e| format!("{}", e)),
+        Format::Dot => {
+            let topo = topo::convert_railml_topo(railml).map_err(|e| format!("topology error: {:?}", e))?;
+            Ok(to_dot(&topo))
+        },
+        Format::Svg => {
+            let topo = topo::convert_railml_topo(railml).map_err(|e| format!("topology error: {:?}", e))?;
+            to_svg(&topo)
+        },
+    }
+}
+
+/// The nodes at the A and B ends of each track.
+fn track_ends(topo :&Topological) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut ends = vec![(None, None); topo.tracks.len()];
+    for ((track, ab), (node, _port)) in &topo.connections {
+        match ab {
+            AB::A => ends[*track].0 = Some(*node),
+            AB::B => ends[*track].1 = Some(*node),
+        }
+    }
+    ends
+}
+
+fn node_label(node :&TopoNode) -> &'static str {
+    match node {
+        TopoNode::BufferStop => "buffer stop",
+        TopoNode::OpenEnd => "open end",
+        TopoNode::MacroscopicNode(_, _) => "boundary",
+        TopoNode::Switch(_) => "switch",
+        TopoNode::Crossing => "crossing",
+        TopoNode::Continuation => "continuation",
+    }
+}
+
+fn to_dot(topo :&Topological) -> String {
+    let mut out = String::new();
+    writeln!(out, "graph railml {{").unwrap();
+    for (i, node) in topo.nodes.iter().enumerate() {
+        writeln!(out, "  n{} [label=\"{}\"];", i, node_label(node)).unwrap();
+    }
+    for (track, (a, b)) in topo.tracks.iter().zip(track_ends(topo)) {
+        if let (Some(a), Some(b)) = (a, b) {
+            writeln!(out, "  n{} -- n{} [label=\"{} ({:.0} m)\"];",
+                     a, b, track.segment_id.replace('"', "\\\""), track.length).unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Draw the tracks as straight lines between their end nodes. Requires the
+/// input to have geographical coordinates on all nodes.
+fn to_svg(topo :&Topological) -> Result<String, String> {
+    let coords = topo.node_coords.iter().cloned().collect::<Option<Vec<(f64, f64)>>>()
+        .ok_or("SVG output requires geoCoords on all nodes")?;
+    if coords.is_empty() { return Err("no nodes to draw".to_string()); }
+
+    let (mut x0, mut y0, mut x1, mut y1) = (std::f64::INFINITY, std::f64::INFINITY,
+                                            std::f64::NEG_INFINITY, std::f64::NEG_INFINITY);
+    for (x, y) in &coords {
+        x0 = x0.min(*x); y0 = y0.min(*y);
+        x1 = x1.max(*x); y1 = y1.max(*y);
+    }
+    let size = (x1 - x0).max(y1 - y0).max(1e-9);
+    let margin = 0.05 * size;
+    // SVG y axis points down, so flip the coordinates.
+    let pt = |(x, y) :(f64, f64)| (x - x0 + margin, y1 - y + margin);
+
+    let mut out = String::new();
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">",
+             x1 - x0 + 2.0 * margin, y1 - y0 + 2.0 * margin).unwrap();
+    let stroke = size / 300.0;
+    for (a, b) in track_ends(topo) {
+        if let (Some(a), Some(b)) = (a, b) {
+            let ((ax, ay), (bx, by)) = (pt(coords[a]), pt(coords[b]));
+            writeln!(out, "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"{}\"/>",
+                     ax, ay, bx, by, stroke).unwrap();
+        }
+    }
+    for (node, c) in topo.nodes.iter().zip(coords.iter()) {
+        let (x, y) = pt(*c);
+        writeln!(out, "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\"><title>{}</title></circle>",
+                 x, y, 2.0 * stroke, node_label(node)).unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+    Ok(out)
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args()?;
+    let data = std::fs::read_to_string(&args.input)
+        .map_err(|e| format!("could not read {:?}: {}", args.input, e))?;
+    let result = convert(&data, args.format)?;
+    match &args.output {
+        Some(f) => std::fs::write(f, result).map_err(|e| format!("could not write {:?}: {}", f, e)),
+        None => { print!("{}", result); Ok(()) },
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("railml-convert: {}", e);
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+}
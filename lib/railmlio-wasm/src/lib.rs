@@ -0,0 +1,55 @@
+//! JavaScript bindings for checking railML in the browser. Build with
+//! `wasm-pack build --target web` in this directory. CI checks that the
+//! crate builds with
+//! `cargo build --target wasm32-unknown-unknown -p railmlio-wasm`.
+
+use wasm_bindgen::prelude::*;
+
+use railmlio::model::RailML;
+use railmlio::{topo, validate, write, xml};
+use serde_json::json;
+
+fn js_err(e :impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// Parse a railML document and return the model as JSON.
+#[wasm_bindgen(js_name = parseRailml)]
+pub fn parse_railml(data :&str) -> Result<String, JsValue> {
+    let railml = xml::parse_railml(data).map_err(js_err)?;
+    serde_json::to_string(&railml).map_err(js_err)
+}
+
+/// Check a railML document with `railmlio::validate`, and that its track
+/// topology is consistent. Returns a JSON array of issues, like
+/// `{"severity": "Error", "message": "...", "id": "sw1", "line": 12}`,
+/// empty if there are none. The id and line are null when not known.
+#[wasm_bindgen(js_name = validateRailml)]
+pub fn validate_railml(data :&str) -> String {
+    let line = |offset :usize| data[..offset.min(data.len())].matches('\n').count() + 1;
+    let issue = |severity :&str, message :String, id :Option<String>, line :Option<usize>| {
+        json!({ "severity": severity, "message": message, "id": id, "line": line })
+    };
+    let mut issues = Vec::new();
+    match xml::parse_railml(data) {
+        Ok(railml) => {
+            let mut found = validate::validate(&railml);
+            validate::locate(&mut found, data);
+            for i in found {
+                issues.push(issue(i.severity.name(), i.message, i.id, i.byte_offset.map(line)));
+            }
+            if let Err(e) = topo::convert_railml_topo(railml) {
+                issues.push(issue("Error", format!("Topology error: {:?}", e), None, None));
+            }
+        },
+        Err(e) => issues.push(issue("Error", format!("Parse error: {}", e), None, None)),
+    }
+    serde_json::to_string(&issues).unwrap()
+}
+
+/// Write a model, as returned by `parseRailml`, as railML 2.5.
+#[wasm_bindgen(js_name = writeRailml)]
+pub fn write_railml(json :&str) -> Result<String, JsValue> {
+    let railml :RailML = serde_json::from_str(json).map_err(js_err)?;
+    Ok(write::write_railml(&railml))
+}
@@ -0,0 +1,335 @@
+//! Semantic comparison of railML documents. Elements are matched by their
+//! tag name and id, so the order of elements and the formatting of the XML
+//! do not matter, and lists that the schema does not order, like the
+//! demarcations of a TVD section, are compared as sets. Differences are
+//! reported as added, removed and changed elements, with the changed fields.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Debug;
+
+use crate::model::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    /// The fields that differ, as (field, old value, new value).
+    Changed(Vec<(&'static str, String, String)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Tag name of the element, like `track` or `signal`.
+    pub element: &'static str,
+    /// Id of the element, or empty for elements that occur once in the
+    /// document, like `metadata`.
+    pub id: Id,
+    pub change: Change,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.change {
+            Change::Added => "+",
+            Change::Removed => "-",
+            Change::Changed(_) => "~",
+        };
+        write!(f, "{} {}", sign, self.element)?;
+        if !self.id.is_empty() {
+            write!(f, " {}", self.id)?;
+        }
+        if let Change::Changed(fields) = &self.change {
+            for (field, old, new) in fields {
+                write!(f, "\n    {}: {} -> {}", field, old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare two documents. The differences are ordered by element and id,
+/// and are empty if the documents describe the same railway.
+pub fn diff(a: &RailML, b: &RailML) -> Vec<Difference> {
+    let (a, b) = (Elements::of(a), Elements::of(b));
+    let mut diffs = Vec::new();
+    for ((element, id), fields_a) in &a.0 {
+        let element = *element;
+        let change = match b.0.get(&(element, id.clone())) {
+            None => Change::Removed,
+            Some(fields_b) => {
+                let changed = fields_a
+                    .iter()
+                    .zip(fields_b.iter())
+                    .filter(|((_, x), (_, y))| x != y)
+                    .map(|((field, x), (_, y))| (*field, x.clone(), y.clone()))
+                    .collect::<Vec<_>>();
+                if changed.is_empty() {
+                    continue;
+                }
+                Change::Changed(changed)
+            }
+        };
+        diffs.push(Difference { element, id: id.clone(), change });
+    }
+    for (element, id) in b.0.keys() {
+        let element = *element;
+        if !a.0.contains_key(&(element, id.clone())) {
+            diffs.push(Difference { element, id: id.clone(), change: Change::Added });
+        }
+    }
+    diffs.sort_by(|x, y| (x.element, &x.id).cmp(&(y.element, &y.id)));
+    diffs
+}
+
+/// Formatted values of the named fields of a struct.
+macro_rules! fields {
+    ($x:expr; $($f:ident),* $(,)?) => {
+        vec![$((stringify!($f).trim_start_matches("r#"), format!("{:?}", $x.$f))),*]
+    };
+}
+
+/// A list compared without regard to its order.
+fn unordered<T: Debug>(xs: &[T]) -> String {
+    let mut xs = xs.iter().map(|x| format!("{:?}", x)).collect::<Vec<_>>();
+    xs.sort();
+    format!("[{}]", xs.join(", "))
+}
+
+fn extras(extras: &Extras) -> (&'static str, String) {
+    ("extras", format!("{} {}", unordered(&extras.attributes), unordered(&extras.elements)))
+}
+
+type Fields = Vec<(&'static str, String)>;
+
+/// The elements of a document by tag name and id, with their fields
+/// formatted for comparison. Child elements with an id are separate
+/// entries that refer to their parent.
+struct Elements(BTreeMap<(&'static str, Id), Fields>);
+
+impl Elements {
+    fn of(railml: &RailML) -> Elements {
+        let mut es = Elements(BTreeMap::new());
+        es.add("railml", "", vec![extras(&railml.extras)]);
+        if let Some(m) = &railml.metadata {
+            es.metadata(m);
+        }
+        if let Some(inf) = &railml.infrastructure {
+            es.infrastructure(inf);
+        }
+        if let Some(il) = &railml.interlocking {
+            es.interlocking(il);
+        }
+        if let Some(tt) = &railml.timetable {
+            es.timetable(tt);
+        }
+        if let Some(rs) = &railml.rollingstock {
+            for v in &rs.vehicles {
+                es.add("vehicle", &v.id, fields!(v; name, description, length, speed));
+            }
+        }
+        es
+    }
+
+    /// Add an element. Duplicate ids are numbered, so that they are compared
+    /// in document order.
+    fn add(&mut self, element: &'static str, id: &str, fields: Fields) {
+        let mut key = (element, id.to_string());
+        let mut n = 1;
+        while self.0.contains_key(&key) {
+            n += 1;
+            key.1 = format!("{} ({})", id, n);
+        }
+        self.0.insert(key, fields);
+    }
+
+    fn metadata(&mut self, m: &Metadata) {
+        self.add(
+            "metadata",
+            "",
+            fields!(m; dc_format, dc_identifier, dc_source, dc_title, dc_language,
+                    dc_creator, dc_description, dc_rights, version),
+        );
+        for u in &m.organizational_units {
+            self.add("organizationalUnit", &u.id, fields!(u; code, name, contact));
+        }
+    }
+
+    fn infrastructure(&mut self, inf: &Infrastructure) {
+        self.add("infrastructure", "", vec![extras(&inf.extras)]);
+        for t in &inf.tracks {
+            self.track(t);
+        }
+        for g in &inf.track_groups {
+            let mut fs = fields!(g; code, name, infrastructure_manager_ref, line_category, line_type);
+            fs.push(("track_refs", unordered(&g.track_refs)));
+            fs.push(("additional_names", unordered(&g.additional_names)));
+            fs.push(extras(&g.extras));
+            self.add("line", &g.id, fs);
+        }
+        for o in &inf.ocps {
+            let mut fs = fields!(o; name, lang, r#type, geo_coord, prop_operational, prop_equipment,
+                                 prop_service, designator);
+            fs.push(("additional_names", unordered(&o.additional_names)));
+            fs.push(extras(&o.extras));
+            self.add("ocp", &o.id, fs);
+        }
+        for s in &inf.states {
+            self.add("state", &s.id, fields!(s; disabled, status));
+        }
+    }
+
+    fn track(&mut self, t: &Track) {
+        let mut fs = fields!(t; code, name, description, track_type, main_dir, begin, end);
+        fs.push(extras(&t.extras));
+        self.add("track", &t.id, fs);
+
+        // Elements on the track refer to it, so that moving an element to
+        // another track is a change of the element.
+        let track = |mut fs: Fields| {
+            fs.insert(0, ("track", t.id.clone()));
+            fs
+        };
+        for sw in &t.switches {
+            match sw {
+                Switch::Switch { id, pos, name, description, length, connections,
+                                 track_continue_course, track_continue_radius } => {
+                    let fs = vec![
+                        ("pos", format!("{:?}", pos)),
+                        ("name", format!("{:?}", name)),
+                        ("description", format!("{:?}", description)),
+                        ("length", format!("{:?}", length)),
+                        ("connections", unordered(connections)),
+                        ("track_continue_course", format!("{:?}", track_continue_course)),
+                        ("track_continue_radius", format!("{:?}", track_continue_radius)),
+                    ];
+                    self.add("switch", id, track(fs));
+                }
+                Switch::Crossing { id, pos, crossing_type, track_continue_course, track_continue_radius,
+                                   normal_position, length, connections } => {
+                    let fs = vec![
+                        ("pos", format!("{:?}", pos)),
+                        ("crossing_type", format!("{:?}", crossing_type)),
+                        ("track_continue_course", format!("{:?}", track_continue_course)),
+                        ("track_continue_radius", format!("{:?}", track_continue_radius)),
+                        ("normal_position", format!("{:?}", normal_position)),
+                        ("length", format!("{:?}", length)),
+                        ("connections", unordered(connections)),
+                    ];
+                    self.add("crossing", id, track(fs));
+                }
+            }
+        }
+
+        let e = &t.track_elements;
+        for x in &e.platform_edges {
+            self.add("platformEdge", &x.id, track(fields!(x; name, pos, dir, side, height, length)));
+        }
+        for x in &e.speed_changes {
+            self.add("speedChange", &x.id, track(fields!(x; pos, dir, vmax, signalised)));
+        }
+        for x in &e.gradient_changes {
+            self.add(
+                "gradientChange",
+                &x.id,
+                track(fields!(x; pos, dir, slope, transition_length, transition_radius)),
+            );
+        }
+        for x in &e.radius_changes {
+            self.add("radiusChange", &x.id, track(fields!(x; pos, dir, radius, superelevation)));
+        }
+        for x in &e.electrification_changes {
+            self.add(
+                "electrificationChange",
+                &x.id,
+                track(fields!(x; pos, dir, electrification_type, voltage, frequency, vmax, isolated_section)),
+            );
+        }
+        for x in &e.track_conditions {
+            self.add("trackCondition", &x.id, track(fields!(x; pos, dir, length, condition_type)));
+        }
+        for x in &e.level_crossings {
+            self.add("levelCrossing", &x.id, track(fields!(x; pos, protection, angle)));
+        }
+        for x in &e.cross_sections {
+            self.add("crossSection", &x.id, track(fields!(x; name, ocp_ref, pos, section_type)));
+        }
+        for x in &e.geo_mappings {
+            self.add("geoMapping", &x.id, track(fields!(x; pos, name, code, description)));
+        }
+
+        let o = &t.objects;
+        for x in &o.signals {
+            let mut fs = fields!(x; pos, name, dir, sight, r#type, function, code, switchable,
+                                 ocp_station_ref, etcs);
+            fs.push(("speeds", unordered(&x.speeds)));
+            self.add("signal", &x.id, track(fs));
+        }
+        for x in &o.balises {
+            self.add("balise", &x.id, track(fields!(x; pos, name, group_ref)));
+        }
+        for x in &o.balise_groups {
+            self.add(
+                "baliseGroup",
+                &x.id,
+                track(fields!(x; pos, name, dir, country_id, group_id, ndx, linking_accuracy)),
+            );
+        }
+        for x in &o.train_detectors {
+            self.add(
+                "trainDetector",
+                &x.id,
+                track(fields!(x; pos, axle_counting, direction_detection, medium)),
+            );
+        }
+        for x in &o.track_circuit_borders {
+            self.add("trackCircuitBorder", &x.id, track(fields!(x; pos, insulated_rail)));
+        }
+        for x in &o.derailers {
+            self.add("derailer", &x.id, track(fields!(x; pos, dir, derail_side, code)));
+        }
+        for x in &o.train_protection_elements {
+            self.add("trainProtectionElement", &x.id, track(fields!(x; pos, dir, medium, system)));
+        }
+        for x in &o.train_protection_element_groups {
+            self.add(
+                "trainProtectionElementGroup",
+                &x.id,
+                track(vec![("element_refs", unordered(&x.element_refs))]),
+            );
+        }
+    }
+
+    fn interlocking(&mut self, il: &Interlocking) {
+        self.add("assetsForIL", il.assets_id.as_deref().unwrap_or(""), vec![extras(&il.extras)]);
+        for x in &il.tvd_sections {
+            let mut fs = fields!(x; name);
+            fs.push(("demarcations", unordered(&x.demarcations)));
+            self.add("tvdSection", &x.id, fs);
+        }
+        for x in &il.signals {
+            let mut fs = fields!(x; refers_to, is_virtual);
+            fs.push(("aspects", unordered(&x.aspects)));
+            self.add("signalIL", &x.id, fs);
+        }
+        for x in &il.routes {
+            let mut fs = fields!(x; name, entry, exit);
+            fs.push(("tvd_sections", unordered(&x.tvd_sections)));
+            fs.push(("switch_positions", unordered(&x.switch_positions)));
+            self.add("route", &x.id, fs);
+        }
+    }
+
+    fn timetable(&mut self, tt: &Timetable) {
+        self.add("timetable", tt.id.as_deref().unwrap_or(""), fields!(tt; name));
+        for x in &tt.train_parts {
+            let mut fs = fields!(x; name, train_number, formation_ref);
+            fs.push(("ocps", unordered(&x.ocps)));
+            self.add("trainPart", &x.id, fs);
+        }
+        for x in &tt.trains {
+            // The train parts are run in sequence, so their order matters.
+            self.add("train", &x.id, fields!(x; name, train_number, train_parts));
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Coordinate reference systems of geoCoord values. Planar coordinates are
+//! used as they are, while geographic coordinates (latitude and longitude
+//! in degrees) are projected to a local transverse Mercator grid in meters,
+//! so that lengths and angles are kept near the infrastructure.
+
+use crate::model::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Crs {
+    /// Projected or unknown system, with x and y used as written.
+    Planar,
+    /// WGS84 (EPSG:4326) or ETRS89 (EPSG:4258), written as latitude and
+    /// longitude in degrees.
+    Geographic,
+}
+
+impl Crs {
+    /// The system of an epsgCode value, such as `4326`, `EPSG:4326` or
+    /// `urn:ogc:def:crs:EPSG::4326`.
+    pub fn from_epsg_code(code: &str) -> Option<Crs> {
+        let number = code.rsplit([':', '/']).next()?.trim();
+        match number.parse::<u32>().ok()? {
+            4326 | 4258 => Some(Crs::Geographic),
+            _ => Some(Crs::Planar),
+        }
+    }
+
+    /// The system given by the epsgCode values of the OCPs. Only OCPs carry
+    /// an epsgCode, and the other geoCoords of the document are taken to be
+    /// in the same system. Without any code, coordinates are planar.
+    pub fn of_infrastructure(inf: &Infrastructure) -> Crs {
+        inf.ocps
+            .iter()
+            .filter_map(|o| o.geo_coord.as_ref()?.epsg_code.as_ref())
+            .find_map(|c| Crs::from_epsg_code(c))
+            .unwrap_or(Crs::Planar)
+    }
+}
+
+// WGS84 ellipsoid
+const A: f64 = 6378137.0;
+const F: f64 = 1.0 / 298.257223563;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Projection {
+    pub crs: Crs,
+    /// Latitude and longitude of the origin, in radians.
+    origin: (f64, f64),
+}
+
+impl Projection {
+    pub fn planar() -> Projection {
+        Projection { crs: Crs::Planar, origin: (0.0, 0.0) }
+    }
+
+    /// A projection with its origin at the mean of the given coordinates,
+    /// which are as written in the document.
+    pub fn new(crs: Crs, coords: impl IntoIterator<Item = (f64, f64)>) -> Projection {
+        let (mut lat, mut lon, mut n) = (0.0, 0.0, 0);
+        for (a, b) in coords {
+            lat += a;
+            lon += b;
+            n += 1;
+        }
+        let origin = if n > 0 {
+            ((lat / n as f64).to_radians(), (lon / n as f64).to_radians())
+        } else {
+            (0.0, 0.0)
+        };
+        Projection { crs, origin }
+    }
+
+    /// x and y of a coordinate pair as written in the document. Geographic
+    /// coordinates give easting and northing in meters from the origin.
+    pub fn project(&self, coord: (f64, f64)) -> (f64, f64) {
+        match self.crs {
+            Crs::Planar => coord,
+            Crs::Geographic => transverse_mercator(self.origin, (coord.0.to_radians(), coord.1.to_radians())),
+        }
+    }
+}
+
+/// Length of the meridian arc from the equator to latitude `phi`.
+fn meridian_arc(phi: f64) -> f64 {
+    let e2 = F * (2.0 - F);
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    A * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+        - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+        + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+        - (35.0 * e6 / 3072.0) * (6.0 * phi).sin())
+}
+
+/// Transverse Mercator with scale 1 on the central meridian, from Snyder,
+/// "Map Projections: A Working Manual", eqs. 8-9 and 8-10. Accurate to
+/// well below a meter within a few degrees of the origin.
+fn transverse_mercator(origin: (f64, f64), (phi, lambda): (f64, f64)) -> (f64, f64) {
+    let e2 = F * (2.0 - F);
+    let ep2 = e2 / (1.0 - e2);
+    let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+    let n = A / (1.0 - e2 * sin * sin).sqrt();
+    let t = tan * tan;
+    let c = ep2 * cos * cos;
+    let a = (lambda - origin.1) * cos;
+    let x = n * (a
+        + (1.0 - t + c) * a.powi(3) / 6.0
+        + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+    let y = meridian_arc(phi) - meridian_arc(origin.0)
+        + n * tan
+            * (a * a / 2.0
+                + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0);
+    (x, y)
+}
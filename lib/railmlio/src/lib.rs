@@ -1,13 +1,21 @@
 pub mod model;
 pub mod topo;
 pub mod xml;
+pub mod stream;
 pub mod write;
+pub mod validate;
+pub mod geo;
+pub mod diff;
 
 #[cfg(test)]
 mod tests {
     use crate::xml;
+    use crate::stream;
+    use crate::validate;
     use crate::topo;
     use crate::write;
+    use crate::geo;
+    use crate::diff;
     use std::path::PathBuf;
 
     fn sample_railml_path() -> PathBuf {
@@ -74,12 +82,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn write_options_change_layout() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+        let opts = write::WriteOptions {
+            indent: 4,
+            abs_pos: false,
+            version: Some("2.4".to_string()),
+            prefixes: vec![("https://www.railml.org/schemas/2021".to_string(), "rail".to_string())],
+            attribute_order: write::AttributeOrder::Alphabetical,
+        };
+        let written = write::write_railml_with_options(&railml, &opts);
+        assert!(written.contains("\n    <rail:metadata>"), "should indent by four spaces and prefix elements");
+        assert!(written.contains("xmlns:rail=\"https://www.railml.org/schemas/2021\""));
+        assert!(written.contains(" version=\"2.4\""));
+        assert!(!written.contains("absPos="), "absPos should not be written");
+        assert!(written.contains("<rail:track id=\"tr01\" mainDir=\"none\" name=\"track a02\""));
+
+        let roundtrip = xml::parse_railml(&written).expect("prefixed document should parse");
+        assert_eq!(
+            roundtrip.infrastructure.unwrap().tracks.len(),
+            railml.infrastructure.as_ref().unwrap().tracks.len()
+        );
+        assert_eq!(
+            write::write_railml_with_options(&railml, &write::WriteOptions::default()),
+            write::write_railml(&railml)
+        );
+    }
+
     #[test]
     fn write_roundtrip_preserves_counts() {
         let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
         let railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
         let xml = write::write_railml(&railml);
         let roundtrip = xml::parse_railml(&xml).expect("roundtrip parse failed");
+        let diffs = diff::diff(&railml, &roundtrip);
+        assert!(diffs.is_empty(), "roundtrip should not change the document: {:#?}", diffs);
 
         let infra1 = railml.infrastructure.unwrap();
         let infra2 = roundtrip.infrastructure.unwrap();
@@ -107,4 +146,355 @@ mod tests {
 
         assert!(roundtrip.metadata.is_some(), "metadata should be written and parsed");
     }
+
+    #[test]
+    fn write_roundtrip_preserves_unknown_elements() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let data = data
+            .replacen("<track id=\"tr01\"", "<track xmlns:v=\"urn:vendor\" v:flag=\"x\" id=\"tr01\"", 1)
+            .replacen("</trackElements>", "<tunnels><tunnel id=\"tu01\" pos=\"10\"/></tunnels></trackElements>", 1)
+            .replacen("</railml>", "<timetable id=\"tt01\"><train id=\"t01\">a &amp; b</train></timetable></railml>", 1);
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let written = write::write_railml(&railml);
+            for kept in &["v:flag=\"x\"", "<tunnel id=\"tu01\" pos=\"10\"/>", "<train id=\"t01\">a &amp; b</train>"] {
+                assert!(written.contains(kept), "{} should be written", kept);
+            }
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(write::write_railml(&roundtrip), written, "writing again should give the same document");
+            assert_eq!(diff::diff(&railml, &roundtrip), vec![], "unknown elements should survive roundtrip");
+        }
+    }
+
+    #[test]
+    fn diff_ignores_order_and_reports_changes() {
+        use crate::diff::{Change, Difference};
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+
+        let mut other = railml.clone();
+        let infra = other.infrastructure.as_mut().unwrap();
+        infra.tracks.reverse();
+        infra.ocps.reverse();
+        assert_eq!(diff::diff(&railml, &other), vec![], "order of elements should not matter");
+
+        let infra = other.infrastructure.as_mut().unwrap();
+        let track = infra.tracks.iter_mut().find(|t| t.objects.signals.len() > 0).unwrap();
+        let signal = &mut track.objects.signals[0];
+        let (signal_id, old_name) = (signal.id.clone(), signal.name.clone());
+        signal.name = Some("renamed".to_string());
+        let detector = infra.tracks.iter_mut().find_map(|t| t.objects.train_detectors.pop()).unwrap();
+        let mut ocp = infra.ocps[0].clone();
+        ocp.id = "ocp_new".to_string();
+        infra.ocps.push(ocp);
+
+        let diffs = diff::diff(&railml, &other);
+        assert_eq!(diffs.len(), 3, "{:#?}", diffs);
+        assert!(diffs.contains(&Difference { element: "ocp", id: "ocp_new".to_string(), change: Change::Added }));
+        assert!(diffs.contains(&Difference { element: "trainDetector", id: detector.id, change: Change::Removed }));
+        let renamed = Change::Changed(vec![("name", format!("{:?}", old_name), "Some(\"renamed\")".to_string())]);
+        assert!(diffs.contains(&Difference { element: "signal", id: signal_id, change: renamed }));
+    }
+
+    #[test]
+    fn parse_gradient_and_radius_changes() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let data = data.replacen(
+            "</trackElements>",
+            "<gradientChanges><gradientChange id=\"gc01\" pos=\"10\" dir=\"up\" slope=\"5.5\"/></gradientChanges>\
+             <radiusChanges><radiusChange id=\"rc01\" pos=\"20\" dir=\"down\" radius=\"-800\"/></radiusChanges></trackElements>",
+            1,
+        );
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let track = &railml.infrastructure.as_ref().unwrap().tracks[0];
+            let gc = &track.track_elements.gradient_changes[0];
+            assert_eq!((gc.id.as_str(), gc.pos.offset, gc.slope), ("gc01", 10.0, 5.5));
+            assert_eq!(track.track_elements.radius_changes[0].radius, -800.0);
+
+            let written = write::write_railml(&railml);
+            assert_eq!(written.matches("id=\"gc01\"").count(), 1, "gradient change should be written once");
+            assert!(written.contains("slope=\"5.5\""), "slope should be written");
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(write::write_railml(&roundtrip), written, "writing again should give the same document");
+
+            let topo = topo::convert_railml_topo(railml).expect("topology conversion failed");
+            let slopes = topo.tracks.iter()
+                .flat_map(|t| t.track_elements.gradient_changes.iter().map(|g| g.slope))
+                .collect::<Vec<_>>();
+            assert!(slopes.contains(&5.5), "gradient changes should be carried to the topology");
+        }
+    }
+
+    #[test]
+    fn parse_electrification_and_track_conditions() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let data = data.replacen(
+            "</trackElements>",
+            "<electrificationChanges><electrificationChange id=\"ec01\" pos=\"0\" dir=\"both\" type=\"overhead\" \
+             voltage=\"15000\" frequency=\"16.7\"/><electrificationChange id=\"ec02\" pos=\"300\" type=\"none\"/>\
+             </electrificationChanges><trackConditions><trackCondition id=\"tc01\" pos=\"100\" length=\"50\" \
+             type=\"powerless\"/></trackConditions></trackElements>",
+            1,
+        );
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let elements = &railml.infrastructure.as_ref().unwrap().tracks[0].track_elements;
+            let ecs = &elements.electrification_changes;
+            assert_eq!(ecs.len(), 2);
+            assert_eq!((ecs[0].voltage, ecs[0].dir.as_deref()), (Some(15000.0), Some("both")));
+            assert!(ecs[0].is_electrified() && !ecs[1].is_electrified());
+            assert_eq!(elements.track_conditions[0].condition_type.as_deref(), Some("powerless"));
+
+            let written = write::write_railml(&railml);
+            assert_eq!(written.matches("id=\"ec01\"").count(), 1, "electrification change should be written once");
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(write::write_railml(&roundtrip), written, "writing again should give the same document");
+        }
+    }
+
+    #[test]
+    fn parse_balise_groups() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let data = data.replacen(
+            "<ocsElements>",
+            "<ocsElements><balises><balise id=\"bal01\" pos=\"50\"/>\
+             <baliseGroup id=\"bg01\" pos=\"100\" dir=\"up\" countryID=\"76\" groupID=\"1201\" ndx=\"false\" \
+             linkingAccuracy=\"5\"><balise id=\"bal02\" pos=\"100\"/><balise id=\"bal03\" pos=\"103\"/>\
+             </baliseGroup></balises>",
+            1,
+        );
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let objects = &railml.infrastructure.as_ref().unwrap().tracks[0].objects;
+            assert_eq!(objects.balises.len(), 3);
+            let group = &objects.balise_groups[0];
+            assert_eq!((group.country_id.as_deref(), group.group_id.as_deref()), (Some("76"), Some("1201")));
+            assert_eq!((group.ndx, group.linking_accuracy), (Some(false), Some(5.0)));
+            let ids = group.balises(objects).map(|b| b.id.as_str()).collect::<Vec<_>>();
+            assert_eq!(ids, vec!["bal02", "bal03"]);
+            let issues = validate::validate(&railml);
+            assert!(issues.iter().all(|i| !i.message.contains("balise group")), "balise groups should validate");
+
+            let written = write::write_railml(&railml);
+            assert_eq!(written.matches("id=\"bal02\"").count(), 1, "balise should be written once");
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(write::write_railml(&roundtrip), written, "writing again should give the same document");
+
+            let topo = topo::convert_railml_topo(railml).expect("topology conversion failed");
+            let track = topo.tracks.iter().find(|t| !t.objects.balise_groups.is_empty()).expect("group in topology");
+            assert_eq!(track.objects.balise_groups[0].balises(&track.objects).count(), 2);
+        }
+    }
+
+    #[test]
+    fn parse_connections_tolerantly() {
+        use crate::model::Switch;
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let data = data.replacen(
+            "</trackEnd>",
+            "</trackEnd><connections>\
+             <crossing id=\"cr01\" pos=\"20\"><connection id=\"cc01\" ref=\"cc01\" orientation=\"outgoing\"/></crossing>\
+             <switchGroup id=\"swg01\"><switch id=\"sw91\" pos=\"40\">\
+             <connection id=\"sc91\" ref=\"sc91\" orientation=\"outgoing\" course=\"left\"/></switch></switchGroup>\
+             <vendor:marker xmlns:vendor=\"urn:vendor\" kind=\"x\"/>\
+             <switch id=\"sw92\" pos=\"not a number\"/>\
+             <switch id=\"sw93\" pos=\"60\"><connection id=\"sc93\" ref=\"sc93\" orientation=\"incoming\" course=\"right\"/></switch>\
+             </connections>",
+            1,
+        );
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let track = &railml.infrastructure.as_ref().unwrap().tracks[0];
+            let ids = track
+                .switches
+                .iter()
+                .map(|sw| match sw {
+                    Switch::Switch { id, .. } | Switch::Crossing { id, .. } => id.as_str(),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(ids, vec!["cr01", "sw91", "sw93"], "switches in document order");
+
+            let report = &railml.parse_report;
+            assert_eq!(report.warnings.len(), 2, "wrapper and unreadable switch: {:?}", report.warnings);
+            assert_eq!(report.warnings[0].id.as_deref(), Some("swg01"));
+            assert_eq!(report.warnings[1].id.as_deref(), Some("sw92"));
+            assert_eq!(&data[report.warnings[1].byte_offset..][..11], "<switch id=");
+            assert_eq!(report.skipped_elements.get("vendor:marker"), Some(&1));
+            assert_eq!(report.skipped_elements.get("switch"), Some(&1));
+
+            let issues = validate::validate(&railml);
+            assert!(issues.iter().any(|i| i.id.as_deref() == Some("sw92")), "parse warnings are validation issues");
+
+            let written = write::write_railml(&railml);
+            assert!(written.contains("<vendor:marker"), "unknown elements in connections are kept");
+            assert!(written.contains("id=\"sw92\""), "unreadable switches are kept as written");
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(roundtrip.infrastructure.unwrap().tracks[0].switches.len(), 3);
+        }
+    }
+
+    #[test]
+    fn parse_interlocking_routes() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let il = "<interlocking><assetsForIL id=\"il01\">\
+            <tvdSections><tvdSection id=\"tvd01\"><hasDemarcatingTraindetector ref=\"td01\"/></tvdSection></tvdSections>\
+            <signalsIL><signalIL id=\"sil01\"><refersTo ref=\"sig01\"/></signalIL>\
+            <signalIL id=\"sil02\"><refersTo ref=\"sig02\"/></signalIL></signalsIL>\
+            <routes><route id=\"rt01\"><routeEntry><refersTo ref=\"sil01\"/></routeEntry>\
+            <routeExit><refersTo ref=\"sil02\"/></routeExit><hasTvdSection ref=\"tvd01\"/>\
+            <facingSwitchInPosition inPosition=\"left\"><refersToSwitch ref=\"sw01\"/></facingSwitchInPosition>\
+            </route></routes></assetsForIL></interlocking></railml>";
+        let data = data.replacen("</railml>", il, 1);
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let il = railml.interlocking.as_ref().expect("interlocking should be parsed");
+            assert_eq!(il.tvd_sections.len(), 1);
+            assert_eq!(il.signals.len(), 2);
+            assert_eq!(il.routes.len(), 1);
+            let route = &il.routes[0];
+            assert_eq!(il.signal_ref(route.entry.as_deref().unwrap()), "sig01");
+            assert_eq!(il.signal_ref(route.exit.as_deref().unwrap()), "sig02");
+            assert_eq!(route.tvd_sections, vec!["tvd01".to_string()]);
+            assert_eq!(route.switch_positions[0].in_position.as_deref(), Some("left"));
+
+            let written = write::write_railml(&railml);
+            let roundtrip = xml::parse_railml(&written).expect("roundtrip parse failed");
+            assert_eq!(format!("{:?}", roundtrip.interlocking), format!("{:?}", railml.interlocking));
+        }
+    }
+
+    #[test]
+    fn parse_timetable_train_parts() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let tt = "<timetable id=\"tt01\"><trainParts><trainPart id=\"tp01\" trainNumber=\"101\">\
+            <formationTT formationRef=\"veh01\"/><ocpsTT>\
+            <ocpTT ocpRef=\"ocp02\" sequence=\"2\" ocpType=\"stop\">\
+            <times scope=\"scheduled\" arrival=\"08:10:00\" departure=\"08:11:30\"/></ocpTT>\
+            <ocpTT ocpRef=\"ocp01\" sequence=\"1\" ocpType=\"begin\">\
+            <times scope=\"published\" departure=\"08:00:00\"/>\
+            <times scope=\"scheduled\" departure=\"23:59:00\" departureDay=\"1\"/></ocpTT>\
+            </ocpsTT></trainPart></trainParts>\
+            <trains><train id=\"t01\" type=\"operational\"><trainPartSequence sequence=\"1\">\
+            <trainPartRef ref=\"tp01\"/></trainPartSequence></train></trains></timetable></railml>";
+        let data = data.replacen("</railml>", tt, 1);
+
+        for railml in vec![
+            xml::parse_railml(&data).expect("railml 2.5 parse failed"),
+            stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed"),
+        ] {
+            let tt = railml.timetable.as_ref().expect("timetable should be parsed");
+            assert_eq!(tt.trains[0].train_parts, vec!["tp01".to_string()]);
+            let part = &tt.train_parts[0];
+            assert_eq!(part.formation_ref.as_deref(), Some("veh01"));
+            assert_eq!(part.ocps.len(), 2);
+            let stop = &part.ocps[0];
+            assert!(stop.is_stop());
+            let times = stop.scheduled_times().unwrap();
+            assert_eq!((times.arrival, times.departure), (Some(29400.0), Some(29490.0)));
+            let begin = part.ocps[1].scheduled_times().unwrap();
+            assert_eq!(begin.departure, Some(2.0 * 86400.0 - 60.0));
+
+            let written = write::write_railml(&railml);
+            assert!(written.contains("<trainPartRef ref=\"tp01\"/>"), "timetable should be written as read");
+        }
+    }
+
+    #[test]
+    fn write_railml3_references_net_elements() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+        let xml = write::write_railml3(&railml);
+        let doc = roxmltree::Document::parse(&xml).expect("railML 3 output should be well-formed");
+        let named = |tag: &'static str| doc.descendants().filter(move |n| n.tag_name().name() == tag);
+
+        let elements = named("netElement").filter_map(|n| n.attribute("id")).collect::<std::collections::HashSet<_>>();
+        let infra = railml.infrastructure.unwrap();
+        assert!(elements.len() >= infra.tracks.len(), "every track should have a net element");
+        assert!(named("netRelation").count() > 0, "tracks should be connected");
+
+        for n in named("elementA").chain(named("elementB")) {
+            assert!(elements.contains(n.attribute("ref").unwrap()), "relation to unknown net element");
+        }
+        for n in named("spotLocation").chain(named("associatedNetElement")) {
+            assert!(elements.contains(n.attribute("netElementRef").unwrap()), "location on unknown net element");
+        }
+
+        let signal_count: usize = infra.tracks.iter().map(|t| t.objects.signals.len()).sum();
+        assert_eq!(named("signalIS").count(), signal_count, "all signals should be written");
+    }
+
+    #[test]
+    fn stream_parser_matches_document_parser() {
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let document = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+        let streamed = stream::parse_railml_reader(data.as_bytes()).expect("railml 2.5 stream parse failed");
+        assert_eq!(format!("{:?}", document), format!("{:?}", streamed));
+    }
+
+    #[test]
+    fn validate_sample_and_broken_connection() {
+        use crate::model::TrackEndConnection;
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let mut railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+        let issues = validate::validate(&railml);
+        assert!(
+            issues.iter().all(|i| i.severity != validate::Severity::Error),
+            "sample should have no errors: {:?}",
+            issues
+        );
+
+        let track = &mut railml.infrastructure.as_mut().unwrap().tracks[0];
+        let id = match &mut track.end.connection {
+            TrackEndConnection::Connection(id, r) => {
+                *r = "missing".to_string();
+                id.clone()
+            }
+            _ => panic!("first track should end in a connection"),
+        };
+        let mut issues = validate::validate(&railml);
+        validate::locate(&mut issues, &data);
+        let issue = issues
+            .iter()
+            .find(|i| i.id.as_ref() == Some(&id) && i.severity == validate::Severity::Error)
+            .expect("dangling connection should be reported");
+        assert!(data[issue.byte_offset.unwrap()..].starts_with("<connection"));
+    }
+
+    #[test]
+    fn project_geographic_coords() {
+        assert_eq!(geo::Crs::from_epsg_code("urn:ogc:def:crs:EPSG::4326"), Some(geo::Crs::Geographic));
+        assert_eq!(geo::Crs::from_epsg_code("EPSG:25832"), Some(geo::Crs::Planar));
+        let data = std::fs::read_to_string(sample_railml_path()).expect("sample railml 2.5 not found");
+        let railml = xml::parse_railml(&data).expect("railml 2.5 parse failed");
+        let inf = railml.infrastructure.as_ref().unwrap();
+        assert_eq!(geo::Crs::of_infrastructure(inf), geo::Crs::Geographic);
+
+        // 0.01 degrees north and east of 60N 10E are about 1114 m and 558 m.
+        let proj = geo::Projection::new(geo::Crs::Geographic, vec![(60.0, 10.0)]);
+        let (x0, y0) = proj.project((60.0, 10.0));
+        assert!(x0.abs() < 1e-6 && y0.abs() < 1e-6);
+        let (x, y) = proj.project((60.01, 10.0));
+        assert!(x.abs() < 1e-6 && (y - 1114.0).abs() < 2.0, "{} {}", x, y);
+        let (x, y) = proj.project((60.0, 10.01));
+        assert!((x - 558.0).abs() < 2.0 && y.abs() < 1.0, "{} {}", x, y);
+    }
 }
@@ -2,6 +2,7 @@
 
 use crate::topo::Side;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 //
 // original railml model (simplified)
@@ -15,6 +16,78 @@ pub struct RailML {
     pub metadata: Option<Metadata>,
     pub infrastructure: Option<Infrastructure>,
     pub rollingstock: Option<Rollingstock>,
+    #[serde(default)]
+    pub interlocking: Option<Interlocking>,
+    #[serde(default)]
+    pub timetable: Option<Timetable>,
+    #[serde(default)]
+    pub extras: Extras,
+    #[serde(default)]
+    pub parse_report: ParseReport,
+}
+
+/// Content that the parser could not read as expected, but skipped or read
+/// differently instead of failing the whole document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParseReport {
+    pub warnings: Vec<ParseWarning>,
+    /// Number of elements not read into the model, by tag name. They are
+    /// kept in the extras of their parent.
+    pub skipped_elements: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseWarning {
+    /// Id of the enclosing element, such as the track.
+    pub id: Option<Id>,
+    pub byte_offset: usize,
+    pub message: String,
+}
+
+impl ParseReport {
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty() && self.skipped_elements.is_empty()
+    }
+
+    pub fn warn(&mut self, id: Option<&str>, byte_offset: usize, message: String) {
+        let id = id.map(|x| x.to_string());
+        self.warnings.push(ParseWarning { id, byte_offset, message });
+    }
+
+    /// Count the elements kept in `extras` as skipped.
+    pub fn count_skipped(&mut self, extras: &Extras) {
+        for (_, xml) in &extras.elements {
+            let name = xml
+                .trim_start_matches('<')
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default();
+            *self.skipped_elements.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Content of an element that is not read into the model, such as
+/// timetables or vendor extensions. It is kept so that writing the
+/// document again does not lose it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Extras {
+    /// Attributes as (qualified name, value).
+    pub attributes: Vec<(String, String)>,
+    /// Child elements as (parent, XML text). The parent is the tag name of
+    /// the container element they were found in, or empty for children of
+    /// the element itself.
+    pub elements: Vec<(String, String)>,
+}
+
+impl Extras {
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.elements.is_empty()
+    }
+
+    pub fn elements_in<'a>(&'a self, parent: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.elements.iter().filter(move |(p, _)| p == parent).map(|(_, xml)| xml.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +118,128 @@ pub struct Infrastructure {
     pub track_groups: Vec<TrackGroup>,
     pub ocps: Vec<Ocp>,
     pub states: Vec<State>,
+    #[serde(default)]
+    pub extras: Extras,
+}
+
+/// Interlocking data in the layout of the railML 3 IL sub-schema, where
+/// `assetsForIL` lists the TVD sections, interlocking signals and routes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interlocking {
+    pub assets_id: Option<Id>,
+    pub tvd_sections: Vec<TvdSection>,
+    pub signals: Vec<SignalIL>,
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub extras: Extras,
+}
+
+/// Train vacancy detection section, bounded by detectors or buffer stops.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TvdSection {
+    pub id: Id,
+    pub name: Option<String>,
+    pub demarcations: Vec<IdRef>,
+}
+
+/// Interlocking view of a signal, referring to the signal in the
+/// infrastructure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalIL {
+    pub id: Id,
+    pub refers_to: Option<IdRef>,
+    pub is_virtual: Option<bool>,
+    pub aspects: Vec<IdRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    pub id: Id,
+    pub name: Option<String>,
+    /// Interlocking signal where the route starts.
+    pub entry: Option<IdRef>,
+    /// Interlocking signal where the route ends.
+    pub exit: Option<IdRef>,
+    pub tvd_sections: Vec<IdRef>,
+    pub switch_positions: Vec<SwitchInPosition>,
+}
+
+/// A switch the route runs over, and the position it is locked in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchInPosition {
+    pub switch: IdRef,
+    pub in_position: Option<String>,
+}
+
+impl Interlocking {
+    /// The infrastructure signal a route entry or exit refers to. The
+    /// reference is to an interlocking signal, but references directly to
+    /// a signal are also accepted.
+    pub fn signal_ref<'a>(&'a self, r: &'a str) -> &'a str {
+        self.signals
+            .iter()
+            .find(|s| s.id == r)
+            .and_then(|s| s.refers_to.as_deref())
+            .unwrap_or(r)
+    }
+}
+
+/// Timetable of the railML TT sub-schema, reduced to the trains and their
+/// times at OCPs. The `<timetable>` element is also kept as written in the
+/// extras of the document, so that it is written back unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timetable {
+    pub id: Option<Id>,
+    pub name: Option<String>,
+    pub train_parts: Vec<TrainPart>,
+    pub trains: Vec<Train>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainPart {
+    pub id: Id,
+    pub name: Option<String>,
+    pub train_number: Option<String>,
+    pub formation_ref: Option<IdRef>,
+    pub ocps: Vec<OcpTT>,
+}
+
+/// A train part's passing or stop at an OCP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcpTT {
+    pub ocp_ref: IdRef,
+    pub sequence: Option<usize>,
+    /// `stop`, `pass`, `begin`, `end` or another value from the schema.
+    pub ocp_type: Option<String>,
+    pub times: Vec<ArrivalDeparture>,
+}
+
+/// Times in seconds after midnight of the train's first day of operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrivalDeparture {
+    pub scope: String,
+    pub arrival: Option<f64>,
+    pub departure: Option<f64>,
+}
+
+/// A train, as a sequence of train parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Train {
+    pub id: Id,
+    pub name: Option<String>,
+    pub train_number: Option<String>,
+    pub train_parts: Vec<IdRef>,
+}
+
+impl OcpTT {
+    /// The scheduled times, or the first times given in another scope.
+    pub fn scheduled_times(&self) -> Option<&ArrivalDeparture> {
+        self.times.iter().find(|t| t.scope == "scheduled").or_else(|| self.times.first())
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.ocp_type.as_deref() == Some("stop")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,6 +272,8 @@ pub struct TrackGroup {
     pub line_type: Option<String>,
     pub track_refs: Vec<TrackRef>,
     pub additional_names: Vec<AdditionalName>,
+    #[serde(default)]
+    pub extras: Extras,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +294,8 @@ pub struct Ocp {
     pub prop_equipment: Option<PropEquipment>,
     pub prop_service: Option<PropService>,
     pub designator: Option<Designator>,
+    #[serde(default)]
+    pub extras: Extras,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -167,12 +366,22 @@ pub struct Track {
     pub switches: Vec<Switch>,
     pub track_elements: TrackElements,
     pub objects: Objects,
+    #[serde(default)]
+    pub extras: Extras,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrackElements {
     pub platform_edges: Vec<PlatformEdge>,
     pub speed_changes: Vec<SpeedChange>,
+    #[serde(default)]
+    pub gradient_changes: Vec<GradientChange>,
+    #[serde(default)]
+    pub radius_changes: Vec<RadiusChange>,
+    #[serde(default)]
+    pub electrification_changes: Vec<ElectrificationChange>,
+    #[serde(default)]
+    pub track_conditions: Vec<TrackCondition>,
     pub level_crossings: Vec<LevelCrossing>,
     pub cross_sections: Vec<CrossSection>,
     pub geo_mappings: Vec<GeoMapping>,
@@ -183,6 +392,10 @@ impl TrackElements {
         Self {
             platform_edges: Vec::new(),
             speed_changes: Vec::new(),
+            gradient_changes: Vec::new(),
+            radius_changes: Vec::new(),
+            electrification_changes: Vec::new(),
+            track_conditions: Vec::new(),
             level_crossings: Vec::new(),
             cross_sections: Vec::new(),
             geo_mappings: Vec::new(),
@@ -210,6 +423,64 @@ pub struct SpeedChange {
     pub signalised: Option<bool>,
 }
 
+/// Gradient from the position on, until the next change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradientChange {
+    pub id: Id,
+    pub pos: Position,
+    pub dir: TrackDirection,
+    /// Slope in per mille, rising in the direction `dir`.
+    pub slope: f64,
+    pub transition_length: Option<f64>,
+    pub transition_radius: Option<f64>,
+}
+
+/// Curve radius from the position on, until the next change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadiusChange {
+    pub id: Id,
+    pub pos: Position,
+    pub dir: TrackDirection,
+    /// Radius in metres, positive for right curves, negative for left
+    /// curves and zero for straight track.
+    pub radius: f64,
+    /// Superelevation (cant) in millimetres.
+    pub superelevation: Option<f64>,
+}
+
+/// Electrification from the position on, until the next change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElectrificationChange {
+    pub id: Id,
+    pub pos: Position,
+    /// Direction as written, which is often "both" for electrification.
+    pub dir: Option<String>,
+    /// Type of electrification, like "overhead", "3rdRail" or "none".
+    pub electrification_type: Option<String>,
+    /// Voltage in volts.
+    pub voltage: Option<f64>,
+    /// Frequency in hertz, zero for direct current.
+    pub frequency: Option<f64>,
+    pub vmax: Option<String>,
+    pub isolated_section: Option<bool>,
+}
+
+impl ElectrificationChange {
+    pub fn is_electrified(&self) -> bool {
+        self.electrification_type.as_deref() != Some("none")
+    }
+}
+
+/// A condition on a stretch of track, like "noStopping" or "powerless".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackCondition {
+    pub id: Id,
+    pub pos: Position,
+    pub dir: Option<String>,
+    pub length: Option<f64>,
+    pub condition_type: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LevelCrossing {
     pub id: Id,
@@ -248,7 +519,9 @@ pub enum TrackEndConnection {
     Connection(Id, IdRef),
     BufferStop,
     OpenEnd,
-    MacroscopicNode(String),
+    /// Boundary towards infrastructure outside the document, as the node
+    /// id and the OCP it belongs to.
+    MacroscopicNode(Id, Option<IdRef>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -266,6 +539,9 @@ pub enum Switch {
     Crossing {
         id: Id,
         pos: Position,
+        /// `simpleCrossing`, `simpleSwitchCrossing` (single slip) or
+        /// `doubleSwitchCrossing` (double slip).
+        crossing_type: Option<String>,
 
         track_continue_course: Option<SwitchConnectionCourse>,
         track_continue_radius: Option<f64>,
@@ -332,6 +608,8 @@ pub struct Position {
 pub struct Objects {
     pub signals: Vec<Signal>,
     pub balises: Vec<Balise>,
+    #[serde(default)]
+    pub balise_groups: Vec<BaliseGroup>,
     pub train_detectors: Vec<TrainDetector>,
     pub track_circuit_borders: Vec<TrackCircuitBorder>,
     pub derailers: Vec<Derailer>,
@@ -344,6 +622,7 @@ impl Objects {
         Objects {
             signals: Vec::new(),
             balises: Vec::new(),
+            balise_groups: Vec::new(),
             train_detectors: Vec::new(),
             track_circuit_borders: Vec::new(),
             derailers: Vec::new(),
@@ -413,6 +692,33 @@ pub struct Balise {
     pub id: Id,
     pub pos: Position,
     pub name: Option<String>,
+    /// The balise group this balise belongs to, written as a child of the
+    /// `<baliseGroup>` element.
+    #[serde(default)]
+    pub group_ref: Option<IdRef>,
+}
+
+/// An ETCS balise group (datapoint). Its balises refer to it by `group_ref`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaliseGroup {
+    pub id: Id,
+    pub pos: Position,
+    pub name: Option<String>,
+    pub dir: Option<TrackDirection>,
+    /// NID_C, the ETCS country or region identifier.
+    pub country_id: Option<String>,
+    /// NID_BG, the ETCS identity of the group within the country.
+    pub group_id: Option<String>,
+    /// Whether the group carries national (non-ETCS) data.
+    pub ndx: Option<bool>,
+    /// Linking accuracy in meters (Q_LOCACC).
+    pub linking_accuracy: Option<f64>,
+}
+
+impl BaliseGroup {
+    pub fn balises<'a>(&'a self, objects: &'a Objects) -> impl Iterator<Item = &'a Balise> {
+        objects.balises.iter().filter(move |b| b.group_ref.as_deref() == Some(self.id.as_str()))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,289 @@
+//! Streaming railML parser for files too large to hold as a document tree.
+//! The file is read as a sequence of events, and only one track, OCP or
+//! other item is kept as an element tree at a time. The items are read
+//! with the same code as in `xml`, so both parsers give the same model.
+
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::model::*;
+use crate::xml::{self, BoxResult, ByteOffset, Content, XmlNode};
+
+/// An element read from the stream, with its content.
+pub(crate) struct Element {
+    /// Tag name without namespace prefix.
+    name: String,
+    qualified_name: String,
+    /// Attributes by their name as written, including any prefix.
+    attributes: Vec<(String, String)>,
+    content: Vec<Content<Element>>,
+    start: ByteOffset,
+}
+
+impl XmlNode for &Element {
+    fn tag_name(&self) -> &str {
+        &self.name
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn children(&self) -> Box<dyn Iterator<Item = Self> + '_> {
+        Box::new(self.content.iter().filter_map(|c| match c {
+            Content::Element(e) => Some(e),
+            Content::Text(_) => None,
+        }))
+    }
+
+    /// Like the document parser, the text of an element is the text before
+    /// its first child element, kept as written.
+    fn text(&self) -> Option<&str> {
+        match self.content.first() {
+            Some(Content::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn start(&self) -> ByteOffset {
+        self.start
+    }
+
+    fn qualified_name(&self) -> String {
+        self.qualified_name.clone()
+    }
+
+    fn all_attributes(&self) -> Vec<(String, String)> {
+        let is_declaration = |k: &str| k == "xmlns" || k.starts_with("xmlns:");
+        let declarations = self.attributes.iter().filter(|(k, _)| is_declaration(k));
+        let others = self.attributes.iter().filter(|(k, _)| !is_declaration(k));
+        declarations.chain(others).cloned().collect()
+    }
+
+    fn content(&self) -> Vec<Content<Self>> {
+        self.content
+            .iter()
+            .map(|c| match c {
+                Content::Text(text) => Content::Text(text.clone()),
+                Content::Element(e) => Content::Element(e),
+            })
+            .collect()
+    }
+}
+
+fn local_name(name: &[u8]) -> String {
+    let local = match name.iter().rposition(|b| *b == b':') {
+        Some(i) => &name[i + 1..],
+        None => name,
+    };
+    String::from_utf8_lossy(local).into_owned()
+}
+
+fn read_start<R: BufRead>(reader: &Reader<R>, e: &BytesStart, start: ByteOffset) -> BoxResult<Element> {
+    let mut attributes = Vec::new();
+    for a in e.attributes() {
+        let a = a?;
+        let value = a.unescape_and_decode_value(reader)?;
+        attributes.push((String::from_utf8_lossy(a.key).into_owned(), value));
+    }
+    Ok(Element {
+        name: local_name(e.name()),
+        qualified_name: String::from_utf8_lossy(e.name()).into_owned(),
+        attributes,
+        content: Vec::new(),
+        start,
+    })
+}
+
+/// Read the contents of an element whose start tag has been read.
+fn read_element<R: BufRead>(reader: &mut Reader<R>, buf: &mut Vec<u8>, element: Element) -> BoxResult<Element> {
+    let mut ancestors: Vec<Element> = Vec::new();
+    let mut current = element;
+    loop {
+        let pos = reader.buffer_position();
+        match reader.read_event(buf)? {
+            Event::Start(e) => {
+                let child = read_start(reader, &e, pos)?;
+                ancestors.push(std::mem::replace(&mut current, child));
+            }
+            Event::Empty(e) => {
+                let child = read_start(reader, &e, pos)?;
+                current.content.push(Content::Element(child));
+            }
+            // Adjacent text and CDATA are one text node, as in the document parser.
+            Event::Text(e) | Event::CData(e) => {
+                let text = e.unescape_and_decode(reader)?;
+                if !text.is_empty() {
+                    match current.content.last_mut() {
+                        Some(Content::Text(prev)) => prev.push_str(&text),
+                        _ => current.content.push(Content::Text(text)),
+                    }
+                }
+            }
+            Event::End(_) => match ancestors.pop() {
+                Some(parent) => {
+                    let child = std::mem::replace(&mut current, parent);
+                    current.content.push(Content::Element(child));
+                }
+                None => return Ok(current),
+            },
+            Event::Eof => return Err(format!("unexpected end of file in <{}>", current.name).into()),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+enum Visit {
+    /// Container of items, whose children are visited.
+    Descend,
+    /// Item read as a whole and converted to the model.
+    Item,
+    /// Element not in the model, kept in the extras of its parent.
+    Extra,
+    Skip,
+}
+
+fn visit(path: &[String], name: &str) -> Visit {
+    match (path.len(), path.last().map(|p| p.as_str()), name) {
+        (0, _, _) => Visit::Descend,
+        (1, _, "metadata") | (1, _, "interlocking") | (1, _, "timetable") => Visit::Item,
+        (1, _, "infrastructure") | (1, _, "rollingstock") => Visit::Descend,
+        (1, _, _) => Visit::Extra,
+        (2, Some("infrastructure"), "tracks")
+        | (2, Some("infrastructure"), "trackGroups")
+        | (2, Some("infrastructure"), "operationControlPoints")
+        | (2, Some("infrastructure"), "states") => Visit::Descend,
+        (2, Some("infrastructure"), _) => Visit::Extra,
+        (2, Some("rollingstock"), "vehicles") => Visit::Descend,
+        (3, Some("tracks"), "track")
+        | (3, Some("trackGroups"), "line")
+        | (3, Some("operationControlPoints"), "ocp")
+        | (3, Some("states"), "state")
+        | (3, Some("vehicles"), "vehicle") => Visit::Item,
+        _ => Visit::Skip,
+    }
+}
+
+fn empty_infrastructure() -> Infrastructure {
+    Infrastructure {
+        tracks: Vec::new(),
+        track_groups: Vec::new(),
+        ocps: Vec::new(),
+        states: Vec::new(),
+        extras: Extras::default(),
+    }
+}
+
+/// Parse a railML document from a reader, without holding the whole
+/// document in memory.
+pub fn parse_railml_reader<R: BufRead>(input: R) -> BoxResult<RailML> {
+    let mut reader = Reader::from_reader(input);
+    let mut buf = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+
+    let mut metadata = None;
+    let mut infrastructure = None;
+    let mut rollingstock = None;
+    let mut rollingstock_ok = true;
+    let mut interlocking = None;
+    let mut timetable = None;
+    let mut extras = Extras::default();
+    let mut parse_report = ParseReport::default();
+
+    loop {
+        let pos = reader.buffer_position();
+        let (start, empty) = match reader.read_event(&mut buf)? {
+            Event::Start(e) => (e.into_owned(), false),
+            Event::Empty(e) => (e.into_owned(), true),
+            Event::End(_) => {
+                path.pop();
+                buf.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        buf.clear();
+
+        let name = local_name(start.name());
+        match visit(&path, &name) {
+            Visit::Descend => {
+                if path.is_empty() {
+                    let root = read_start(&reader, &start, pos)?;
+                    xml::keep_attributes(&&root, xml::ROOT_ATTRIBUTES, &mut extras);
+                }
+                if path.len() == 1 && name == "infrastructure" {
+                    let mut inf = empty_infrastructure();
+                    let element = read_start(&reader, &start, pos)?;
+                    xml::keep_attributes(&&element, &[], &mut inf.extras);
+                    infrastructure = Some(inf);
+                }
+                if path.len() == 1 && name == "rollingstock" {
+                    rollingstock = Some(Rollingstock::empty());
+                }
+                if !empty {
+                    path.push(name);
+                }
+            }
+            Visit::Item => {
+                let mut element = read_start(&reader, &start, pos)?;
+                if !empty {
+                    element = read_element(&mut reader, &mut buf, element)?;
+                }
+                let element = &element;
+                let doc_err = |e: xml::DocErr| format!("{:?}", e);
+                match (name.as_str(), infrastructure.as_mut(), rollingstock.as_mut()) {
+                    ("metadata", _, _) => metadata = Some(xml::parse_metadata_element(&element)),
+                    ("interlocking", _, _) => interlocking = Some(xml::parse_interlocking(&element)),
+                    ("timetable", _, _) => {
+                        // Also kept as written, see `Timetable`.
+                        timetable = Some(xml::parse_timetable(&element));
+                        extras.elements.push((String::new(), xml::to_xml(&element)));
+                    }
+                    ("track", Some(inf), _) => inf.tracks.push(xml::parse_track(&element, &mut parse_report).map_err(doc_err)?),
+                    ("line", Some(inf), _) => inf.track_groups.push(xml::parse_track_group(&element).map_err(doc_err)?),
+                    ("ocp", Some(inf), _) => inf.ocps.push(xml::parse_ocp(&element).map_err(doc_err)?),
+                    ("state", Some(inf), _) => inf.states.push(xml::parse_state(&element).map_err(doc_err)?),
+                    ("vehicle", _, Some(rs)) => match xml::parse_vehicle(&element) {
+                        Ok(v) => rs.vehicles.push(v),
+                        Err(_) => rollingstock_ok = false,
+                    },
+                    _ => {}
+                }
+            }
+            Visit::Extra => {
+                let mut element = read_start(&reader, &start, pos)?;
+                if !empty {
+                    element = read_element(&mut reader, &mut buf, element)?;
+                }
+                let fragment = (String::new(), xml::to_xml(&&element));
+                match infrastructure.as_mut() {
+                    Some(inf) if path.len() == 2 => inf.extras.elements.push(fragment),
+                    _ => extras.elements.push(fragment),
+                }
+            }
+            Visit::Skip => {
+                if !empty {
+                    reader.read_to_end(start.name(), &mut buf)?;
+                    buf.clear();
+                }
+            }
+        }
+    }
+
+    Ok(RailML {
+        metadata,
+        infrastructure,
+        rollingstock: if rollingstock_ok { rollingstock } else { None },
+        interlocking,
+        timetable,
+        extras,
+        parse_report,
+    })
+}
@@ -2,7 +2,7 @@
 
 use ordered_float::OrderedFloat;
 use crate::model::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use log::*;
 
 
@@ -126,7 +126,8 @@ impl Side {
 pub enum TopoNode {
     BufferStop,
     OpenEnd,
-    MacroscopicNode, // TODO preserve names for boundaries?
+    /// Boundary with its railML node id and OCP reference.
+    MacroscopicNode(String, Option<String>),
     Switch(Side),
     Crossing,
     Continuation,
@@ -149,12 +150,13 @@ pub fn topo_node_type(n :TrackEndConnection) -> TopoNode {
     match n {
         TrackEndConnection::BufferStop => TopoNode::BufferStop,
         TrackEndConnection::OpenEnd => TopoNode::OpenEnd,
-        TrackEndConnection::MacroscopicNode(_) => TopoNode::MacroscopicNode,
+        TrackEndConnection::MacroscopicNode(id, ocp_ref) => TopoNode::MacroscopicNode(id, ocp_ref),
         _ => panic!(),
     }
 }
 
-fn parse_geo_coord(value: &str) -> Option<(f64, f64)> {
+/// The x and y of a geoCoord value, separated by whitespace or a comma.
+pub fn parse_geo_coord(value: &str) -> Option<(f64, f64)> {
     let cleaned = value.replace(',', " ");
     let mut it = cleaned.split_whitespace();
     let x: f64 = it.next()?.parse().ok()?;
@@ -290,6 +292,12 @@ pub fn switch_info(sw :Switch) -> Result<TopoSwitchInfo,TopoConvErr> {
     }
 }
 
+/// Sort track objects by their position on the track.
+fn by_offset<T>(mut xs :Vec<T>, offset :impl Fn(&T) -> f64) -> VecDeque<T> {
+    xs.sort_by_key(|x| OrderedFloat(offset(x)));
+    xs.into()
+}
+
 pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
     let mut topo = Topological {
         tracks: Vec::new(),
@@ -298,8 +306,14 @@ pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
         node_coords: Vec::new(),
     };
 
-    let mut named_track_ports :HashMap<(String,String), (usize, AB)> = HashMap::new();
-    let mut named_node_ports  :HashMap<(String,String), (usize, Port)> = HashMap::new();
+    let n_tracks = doc.infrastructure.as_ref().map(|i| i.tracks.len()).unwrap_or(0);
+    topo.tracks.reserve(n_tracks);
+    topo.nodes.reserve(2*n_tracks);
+    topo.connections.reserve(2*n_tracks);
+    // Ordered by connection id, so that the connections and continuation
+    // nodes come out in the same order every time.
+    let mut named_track_ports :BTreeMap<(String,String), (usize, AB)> = BTreeMap::new();
+    let mut named_node_ports  :BTreeMap<(String,String), (usize, Port)> = BTreeMap::new();
 
     if let Some(inf) = doc.infrastructure {
         for mut track in inf.tracks {
@@ -354,108 +368,164 @@ pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
             });
             let first_track_idx = track_idx;
 
-            // prepare sorted objects for this track
-            let mut sigs = track.objects.signals.clone();
-            sigs.sort_by_key(|s| OrderedFloat(s.pos.offset));
-            let mut bals = track.objects.balises.clone();
-            bals.sort_by_key(|b| OrderedFloat(b.pos.offset));
-            let mut dets = track.objects.train_detectors.clone();
-            dets.sort_by_key(|d| OrderedFloat(d.pos.offset));
-            let mut tcbs = track.objects.track_circuit_borders.clone();
-            tcbs.sort_by_key(|d| OrderedFloat(d.pos.offset));
-            let mut ders = track.objects.derailers.clone();
-            ders.sort_by_key(|d| OrderedFloat(d.pos.offset));
-            let mut tpes = track.objects.train_protection_elements.clone();
-            tpes.sort_by_key(|e| OrderedFloat(e.pos.offset));
-            let mut pes = track.track_elements.platform_edges.clone();
-            pes.sort_by_key(|p| OrderedFloat(p.pos.offset));
-            let mut scs = track.track_elements.speed_changes.clone();
-            scs.sort_by_key(|s| OrderedFloat(s.pos.offset));
-            let mut lcs = track.track_elements.level_crossings.clone();
-            lcs.sort_by_key(|l| OrderedFloat(l.pos.offset));
-            let mut css = track.track_elements.cross_sections.clone();
-            css.sort_by_key(|c| OrderedFloat(c.pos.offset));
-            let mut gms = track.track_elements.geo_mappings.clone();
-            gms.sort_by_key(|g| OrderedFloat(g.pos.offset));
+            // prepare sorted objects for this track, moved out of the parsed
+            // document instead of copied, and consumed from the front.
+            let mut sigs = by_offset(std::mem::take(&mut track.objects.signals), |s| s.pos.offset);
+            let mut bals = by_offset(std::mem::take(&mut track.objects.balises), |b| b.pos.offset);
+            let mut bgs = by_offset(std::mem::take(&mut track.objects.balise_groups), |g| g.pos.offset);
+            let mut dets = by_offset(std::mem::take(&mut track.objects.train_detectors), |d| d.pos.offset);
+            let mut tcbs = by_offset(std::mem::take(&mut track.objects.track_circuit_borders), |d| d.pos.offset);
+            let mut ders = by_offset(std::mem::take(&mut track.objects.derailers), |d| d.pos.offset);
+            let mut tpes = by_offset(std::mem::take(&mut track.objects.train_protection_elements), |e| e.pos.offset);
+            let mut pes = by_offset(std::mem::take(&mut track.track_elements.platform_edges), |p| p.pos.offset);
+            let mut scs = by_offset(std::mem::take(&mut track.track_elements.speed_changes), |s| s.pos.offset);
+            let mut lcs = by_offset(std::mem::take(&mut track.track_elements.level_crossings), |l| l.pos.offset);
+            let mut css = by_offset(std::mem::take(&mut track.track_elements.cross_sections), |c| c.pos.offset);
+            let mut gms = by_offset(std::mem::take(&mut track.track_elements.geo_mappings), |g| g.pos.offset);
+            let mut grcs = by_offset(std::mem::take(&mut track.track_elements.gradient_changes), |g| g.pos.offset);
+            let mut rads = by_offset(std::mem::take(&mut track.track_elements.radius_changes), |r| r.pos.offset);
+            let mut elcs = by_offset(std::mem::take(&mut track.track_elements.electrification_changes), |e| e.pos.offset);
+            let mut tcos = by_offset(std::mem::take(&mut track.track_elements.track_conditions), |t| t.pos.offset);
+            // Gradient, radius and electrification hold until the next change,
+            // so a segment starts with a copy of the change in effect at its start.
+            let mut last_grc: Option<GradientChange> = None;
+            let mut last_rad: Option<RadiusChange> = None;
+            let mut last_elc: Option<ElectrificationChange> = None;
 
             let mut push_segment_objects = |seg: &mut TopoTrack, start: f64, end: f64| {
-                while let Some(s) = sigs.first() {
+                while let Some(s) = sigs.front() {
                     if s.pos.offset <= end {
-                        let mut s = sigs.remove(0);
+                        let mut s = sigs.pop_front().unwrap();
                         s.pos.offset -= start;
                         seg.objects.signals.push(s);
                     } else { break; }
                 }
-                while let Some(b) = bals.first() {
+                while let Some(b) = bals.front() {
                     if b.pos.offset <= end {
-                        let mut b = bals.remove(0);
+                        let mut b = bals.pop_front().unwrap();
                         b.pos.offset -= start;
                         seg.objects.balises.push(b);
                     } else { break; }
                 }
-                while let Some(d) = dets.first() {
+                while let Some(g) = bgs.front() {
+                    if g.pos.offset <= end {
+                        let mut g = bgs.pop_front().unwrap();
+                        g.pos.offset -= start;
+                        seg.objects.balise_groups.push(g);
+                    } else { break; }
+                }
+                while let Some(d) = dets.front() {
                     if d.pos.offset <= end {
-                        let mut d = dets.remove(0);
+                        let mut d = dets.pop_front().unwrap();
                         d.pos.offset -= start;
                         seg.objects.train_detectors.push(d);
                     } else { break; }
                 }
-                while let Some(t) = tcbs.first() {
+                while let Some(t) = tcbs.front() {
                     if t.pos.offset <= end {
-                        let mut t = tcbs.remove(0);
+                        let mut t = tcbs.pop_front().unwrap();
                         t.pos.offset -= start;
                         seg.objects.track_circuit_borders.push(t);
                     } else { break; }
                 }
-                while let Some(d) = ders.first() {
+                while let Some(d) = ders.front() {
                     if d.pos.offset <= end {
-                        let mut d = ders.remove(0);
+                        let mut d = ders.pop_front().unwrap();
                         d.pos.offset -= start;
                         seg.objects.derailers.push(d);
                     } else { break; }
                 }
-                while let Some(e) = tpes.first() {
+                while let Some(e) = tpes.front() {
                     if e.pos.offset <= end {
-                        let mut e = tpes.remove(0);
+                        let mut e = tpes.pop_front().unwrap();
                         e.pos.offset -= start;
                         seg.objects.train_protection_elements.push(e);
                     } else { break; }
                 }
-                while let Some(p) = pes.first() {
+                while let Some(p) = pes.front() {
                     if p.pos.offset <= end {
-                        let mut p = pes.remove(0);
+                        let mut p = pes.pop_front().unwrap();
                         p.pos.offset -= start;
                         seg.track_elements.platform_edges.push(p);
                     } else { break; }
                 }
-                while let Some(s) = scs.first() {
+                while let Some(s) = scs.front() {
                     if s.pos.offset <= end {
-                        let mut s = scs.remove(0);
+                        let mut s = scs.pop_front().unwrap();
                         s.pos.offset -= start;
                         seg.track_elements.speed_changes.push(s);
                     } else { break; }
                 }
-                while let Some(l) = lcs.first() {
+                while let Some(l) = lcs.front() {
                     if l.pos.offset <= end {
-                        let mut l = lcs.remove(0);
+                        let mut l = lcs.pop_front().unwrap();
                         l.pos.offset -= start;
                         seg.track_elements.level_crossings.push(l);
                     } else { break; }
                 }
-                while let Some(c) = css.first() {
+                while let Some(c) = css.front() {
                     if c.pos.offset <= end {
-                        let mut c = css.remove(0);
+                        let mut c = css.pop_front().unwrap();
                         c.pos.offset -= start;
                         seg.track_elements.cross_sections.push(c);
                     } else { break; }
                 }
-                while let Some(g) = gms.first() {
+                while let Some(g) = gms.front() {
                     if g.pos.offset <= end {
-                        let mut g = gms.remove(0);
+                        let mut g = gms.pop_front().unwrap();
                         g.pos.offset -= start;
                         seg.track_elements.geo_mappings.push(g);
                     } else { break; }
                 }
+                if let Some(mut g) = last_grc.clone() {
+                    if grcs.front().map(|n| n.pos.offset > start).unwrap_or(true) {
+                        g.pos.offset = 0.0;
+                        seg.track_elements.gradient_changes.push(g);
+                    }
+                }
+                while let Some(g) = grcs.front() {
+                    if g.pos.offset <= end {
+                        let mut g = grcs.pop_front().unwrap();
+                        last_grc = Some(g.clone());
+                        g.pos.offset -= start;
+                        seg.track_elements.gradient_changes.push(g);
+                    } else { break; }
+                }
+                if let Some(mut r) = last_rad.clone() {
+                    if rads.front().map(|n| n.pos.offset > start).unwrap_or(true) {
+                        r.pos.offset = 0.0;
+                        seg.track_elements.radius_changes.push(r);
+                    }
+                }
+                while let Some(r) = rads.front() {
+                    if r.pos.offset <= end {
+                        let mut r = rads.pop_front().unwrap();
+                        last_rad = Some(r.clone());
+                        r.pos.offset -= start;
+                        seg.track_elements.radius_changes.push(r);
+                    } else { break; }
+                }
+                if let Some(mut e) = last_elc.clone() {
+                    if elcs.front().map(|n| n.pos.offset > start).unwrap_or(true) {
+                        e.pos.offset = 0.0;
+                        seg.track_elements.electrification_changes.push(e);
+                    }
+                }
+                while let Some(e) = elcs.front() {
+                    if e.pos.offset <= end {
+                        let mut e = elcs.pop_front().unwrap();
+                        last_elc = Some(e.clone());
+                        e.pos.offset -= start;
+                        seg.track_elements.electrification_changes.push(e);
+                    } else { break; }
+                }
+                while let Some(t) = tcos.front() {
+                    if t.pos.offset <= end {
+                        let mut t = tcos.pop_front().unwrap();
+                        t.pos.offset -= start;
+                        seg.track_elements.track_conditions.push(t);
+                    } else { break; }
+                }
             };
 
             track_end(
@@ -637,12 +707,12 @@ pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
 pub fn track_end(conn :TrackEndConnection, 
                  (track_idx,side) :(usize,AB),
                  topo :&mut Topological,
-                 named_track_ports :&mut HashMap<(String,String),(usize,AB)>,
+                 named_track_ports :&mut BTreeMap<(String,String),(usize,AB)>,
                  geo_coord: Option<String>) {
     match conn {
         n @ TrackEndConnection::BufferStop | 
         n @ TrackEndConnection::OpenEnd |
-        n @ TrackEndConnection::MacroscopicNode(_) => {
+        n @ TrackEndConnection::MacroscopicNode(_, _) => {
             let nd = new_node(topo, topo_node_type(n));
             if let Some(gc) = geo_coord.as_ref().and_then(|v| parse_geo_coord(v)) {
                 topo.node_coords[nd] = Some(gc);
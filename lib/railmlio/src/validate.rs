@@ -0,0 +1,292 @@
+//! Semantic checks of a parsed railML 2.5 document, beyond what the parser
+//! requires: references to missing elements, duplicate ids, absPos values
+//! that do not follow the track, and switches with unexpected connections.
+//! All issues are collected, so that they can be shown together.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::model::*;
+use crate::xml::ByteOffset;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The document cannot be imported.
+    Error,
+    /// The document can be imported, but may not be what was intended.
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Id of the element the issue is about.
+    pub id: Option<Id>,
+    /// Position of the element in the file, when located with `locate`.
+    pub byte_offset: Option<ByteOffset>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn new(severity: Severity, id: &str, message: String) -> Self {
+        ValidationIssue {
+            severity,
+            id: Some(id.to_string()),
+            byte_offset: None,
+            message,
+        }
+    }
+}
+
+/// An element with an id and a position on a track.
+struct Located<'a> {
+    id: &'a str,
+    pos: &'a Position,
+}
+
+fn located_on_track(track: &Track) -> Vec<Located<'_>> {
+    let mut xs = vec![Located { id: &track.begin.id, pos: &track.begin.pos }];
+    for sw in &track.switches {
+        let (id, pos) = match sw {
+            Switch::Switch { id, pos, .. } | Switch::Crossing { id, pos, .. } => (id, pos),
+        };
+        xs.push(Located { id, pos });
+    }
+    let e = &track.track_elements;
+    xs.extend(e.platform_edges.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.speed_changes.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.gradient_changes.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.radius_changes.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.electrification_changes.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.track_conditions.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.level_crossings.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.cross_sections.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(e.geo_mappings.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    let o = &track.objects;
+    xs.extend(o.signals.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.balises.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.balise_groups.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.train_detectors.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.track_circuit_borders.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.derailers.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.extend(o.train_protection_elements.iter().map(|x| Located { id: &x.id, pos: &x.pos }));
+    xs.push(Located { id: &track.end.id, pos: &track.end.pos });
+    xs
+}
+
+fn switch_connections(sw: &Switch) -> &[SwitchConnection] {
+    match sw {
+        Switch::Switch { connections, .. } | Switch::Crossing { connections, .. } => connections,
+    }
+}
+
+/// All ids defined in the document, in document order.
+fn all_ids(inf: &Infrastructure) -> Vec<&str> {
+    let mut ids = Vec::new();
+    for track in &inf.tracks {
+        ids.push(track.id.as_str());
+        for node in &[&track.begin, &track.end] {
+            if let TrackEndConnection::Connection(id, _) = &node.connection {
+                ids.push(id.as_str());
+            }
+        }
+        for sw in &track.switches {
+            ids.extend(switch_connections(sw).iter().map(|c| c.id.as_str()));
+        }
+        ids.extend(located_on_track(track).iter().map(|x| x.id));
+        ids.extend(track.objects.train_protection_element_groups.iter().map(|g| g.id.as_str()));
+    }
+    ids.extend(inf.track_groups.iter().map(|g| g.id.as_str()));
+    ids.extend(inf.ocps.iter().map(|o| o.id.as_str()));
+    ids.extend(inf.states.iter().map(|s| s.id.as_str()));
+    ids
+}
+
+fn check_duplicate_ids(inf: &Infrastructure, issues: &mut Vec<ValidationIssue>) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for id in all_ids(inf) {
+        let count = counts.entry(id).or_insert(0);
+        if *count == 1 {
+            order.push(id);
+        }
+        *count += 1;
+    }
+    for id in order {
+        let message = format!("Id \"{}\" is used by {} elements.", id, counts[id]);
+        issues.push(ValidationIssue::new(Severity::Error, id, message));
+    }
+}
+
+fn check_connections(inf: &Infrastructure, issues: &mut Vec<ValidationIssue>) {
+    // Connection id to the connection it refers to.
+    let mut refs: HashMap<&str, &str> = HashMap::new();
+    for track in &inf.tracks {
+        for node in &[&track.begin, &track.end] {
+            if let TrackEndConnection::Connection(id, r) = &node.connection {
+                refs.insert(id, r);
+            }
+        }
+        for sw in &track.switches {
+            for c in switch_connections(sw) {
+                refs.insert(&c.id, &c.r#ref);
+            }
+        }
+    }
+
+    let mut ids = refs.keys().cloned().collect::<Vec<_>>();
+    ids.sort();
+    for id in ids {
+        let r = refs[id];
+        match refs.get(r) {
+            None => {
+                let message = format!("Connection \"{}\" refers to unknown connection \"{}\".", id, r);
+                issues.push(ValidationIssue::new(Severity::Error, id, message));
+            }
+            Some(back) if *back != id => {
+                let message = format!(
+                    "Connection \"{}\" refers to \"{}\", which refers to \"{}\".",
+                    id, r, back
+                );
+                issues.push(ValidationIssue::new(Severity::Warning, id, message));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_references(inf: &Infrastructure, issues: &mut Vec<ValidationIssue>) {
+    let ids = all_ids(inf).into_iter().collect::<HashSet<_>>();
+    let track_ids = inf.tracks.iter().map(|t| t.id.as_str()).collect::<HashSet<_>>();
+    let mut missing = |id: &str, r: &str, kind: &str, known: &HashSet<&str>| {
+        if !known.contains(r) {
+            let message = format!("\"{}\" refers to unknown {} \"{}\".", id, kind, r);
+            issues.push(ValidationIssue::new(Severity::Warning, id, message));
+        }
+    };
+
+    for group in &inf.track_groups {
+        for r in &group.track_refs {
+            missing(&group.id, &r.r#ref, "track", &track_ids);
+        }
+    }
+    for ocp in &inf.ocps {
+        for r in ocp.prop_equipment.iter().flat_map(|e| e.track_refs.iter()) {
+            missing(&ocp.id, r, "track", &track_ids);
+        }
+    }
+    for track in &inf.tracks {
+        for group in &track.objects.train_protection_element_groups {
+            for r in &group.element_refs {
+                missing(&group.id, r, "element", &ids);
+            }
+        }
+        for balise in &track.objects.balises {
+            if let Some(r) = &balise.group_ref {
+                missing(&balise.id, r, "balise group", &ids);
+            }
+        }
+    }
+}
+
+/// The absPos (mileage) values along a track should all increase or all
+/// decrease with the position on the track.
+fn check_mileage(track: &Track, issues: &mut Vec<ValidationIssue>) {
+    let mut xs = located_on_track(track)
+        .into_iter()
+        .filter_map(|x| x.pos.mileage.map(|m| (x.pos.offset, m, x.id)))
+        .collect::<Vec<_>>();
+    xs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let increasing = match (track.begin.pos.mileage, track.end.pos.mileage) {
+        (Some(a), Some(b)) if a != b => a < b,
+        _ => match xs.windows(2).find(|w| w[0].1 != w[1].1) {
+            Some(w) => w[0].1 < w[1].1,
+            None => return,
+        },
+    };
+
+    for w in xs.windows(2) {
+        let ((_, a, _), (_, b, id)) = (w[0], w[1]);
+        if (increasing && b < a) || (!increasing && b > a) {
+            let message = format!(
+                "absPos {} of \"{}\" does not follow the direction of track \"{}\".",
+                b, id, track.id
+            );
+            issues.push(ValidationIssue::new(Severity::Warning, id, message));
+        }
+    }
+}
+
+fn check_switch(sw: &Switch, issues: &mut Vec<ValidationIssue>) {
+    let (id, n, max) = match sw {
+        Switch::Switch { id, connections, .. } => (id, connections.len(), 2),
+        Switch::Crossing { id, connections, .. } => (id, connections.len(), 1),
+    };
+    if n == 0 {
+        let message = format!("Switch \"{}\" has no connections.", id);
+        issues.push(ValidationIssue::new(Severity::Error, id, message));
+    } else if n > max {
+        let message = format!("Switch \"{}\" has {} connections, but at most {} are supported.", id, n, max);
+        issues.push(ValidationIssue::new(Severity::Error, id, message));
+    }
+    for c in switch_connections(sw) {
+        if !matches!(c.orientation, ConnectionOrientation::Incoming | ConnectionOrientation::Outgoing) {
+            let message = format!("Switch connection \"{}\" has no incoming or outgoing orientation.", c.id);
+            issues.push(ValidationIssue::new(Severity::Error, &c.id, message));
+        }
+    }
+}
+
+/// Check a parsed document. The issues are ordered by severity, and
+/// otherwise by the kind of check. Warnings from parsing come first
+/// among the warnings, already located.
+pub fn validate(railml: &RailML) -> Vec<ValidationIssue> {
+    let mut issues = railml
+        .parse_report
+        .warnings
+        .iter()
+        .map(|w| ValidationIssue {
+            severity: Severity::Warning,
+            id: w.id.clone(),
+            byte_offset: Some(w.byte_offset),
+            message: w.message.clone(),
+        })
+        .collect::<Vec<_>>();
+    if let Some(inf) = &railml.infrastructure {
+        check_duplicate_ids(inf, &mut issues);
+        check_connections(inf, &mut issues);
+        check_references(inf, &mut issues);
+        for track in &inf.tracks {
+            for sw in &track.switches {
+                check_switch(sw, &mut issues);
+            }
+            check_mileage(track, &mut issues);
+        }
+    }
+    issues.sort_by_key(|i| i.severity);
+    issues
+}
+
+/// Find the byte offsets of the issues in the source text of the document,
+/// as the start of the first element with the issue's id. Issues that
+/// are already located are kept as they are.
+pub fn locate(issues: &mut [ValidationIssue], source: &str) {
+    for issue in issues.iter_mut().filter(|i| i.byte_offset.is_none()) {
+        if let Some(id) = &issue.id {
+            issue.byte_offset = ["\"", "'"].iter().find_map(|q| {
+                let pattern = format!(" id={}{}{}", q, id, q);
+                let i = source.find(&pattern)?;
+                Some(source[..i].rfind('<').unwrap_or(i))
+            });
+        }
+    }
+}
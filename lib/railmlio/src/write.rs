@@ -1,6 +1,62 @@
 use crate::model::*;
+use serde::{Deserialize, Serialize};
 
-fn escape_attr(value: &str) -> String {
+const RAILML2_NAMESPACE: &str = "https://www.railml.org/schemas/2021";
+const RAILML3_NAMESPACE: &str = "https://www.railml.org/schemas/3.2";
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Layout of the written document. The defaults give the layout this
+/// library has always written; other tools' layouts can be matched to keep
+/// diffs against their files small.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteOptions {
+    /// Spaces per nesting level.
+    pub indent: usize,
+    /// Write the absPos (mileage) of positions that have one.
+    pub abs_pos: bool,
+    /// The version attribute of the root element, instead of the version
+    /// of the written format.
+    pub version: Option<String>,
+    /// Prefixes by namespace URI, for the railML, Dublin Core and XML
+    /// Schema instance namespaces. Without an entry, railML is the default
+    /// namespace and the others use "dc" and "xsi".
+    pub prefixes: Vec<(String, String)>,
+    pub attribute_order: AttributeOrder,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttributeOrder {
+    /// The order of the schema, with the id first.
+    Schema,
+    /// Namespace declarations first, then sorted by name.
+    Alphabetical,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            indent: 2,
+            abs_pos: true,
+            version: None,
+            prefixes: Vec::new(),
+            attribute_order: AttributeOrder::Schema,
+        }
+    }
+}
+
+impl WriteOptions {
+    fn prefix<'a>(&'a self, namespace: &str, default: &'a str) -> &'a str {
+        self.prefixes
+            .iter()
+            .find(|(uri, _)| uri == namespace)
+            .map(|(_, prefix)| prefix.as_str())
+            .unwrap_or(default)
+    }
+}
+
+pub(crate) fn escape_attr(value: &str) -> String {
     let mut out = String::new();
     for ch in value.chars() {
         match ch {
@@ -15,9 +71,9 @@ fn escape_attr(value: &str) -> String {
     out
 }
 
-fn push_indent(out: &mut String, level: usize) {
-    for _ in 0..level {
-        out.push_str("  ");
+fn push_indent(out: &mut String, opts: &WriteOptions, level: usize) {
+    for _ in 0..level * opts.indent {
+        out.push(' ');
     }
 }
 
@@ -29,6 +85,22 @@ fn push_attr(out: &mut String, key: &str, value: &str) {
     out.push('"');
 }
 
+fn write_extra_attributes(out: &mut String, extras: &Extras) {
+    for (key, value) in &extras.attributes {
+        push_attr(out, key, value);
+    }
+}
+
+/// Write the kept child elements found in `parent`, at the end of the
+/// corresponding element.
+fn write_extra_elements(out: &mut String, opts: &WriteOptions, extras: &Extras, parent: &str, level: usize) {
+    for xml in extras.elements_in(parent) {
+        push_indent(out, opts, level);
+        out.push_str(xml);
+        out.push('\n');
+    }
+}
+
 fn fmt_f64(v: f64) -> String {
     if v.fract() == 0.0 {
         format!("{:.1}", v)
@@ -37,22 +109,22 @@ fn fmt_f64(v: f64) -> String {
     }
 }
 
-fn write_position_attrs(out: &mut String, pos: &Position) {
+fn write_position_attrs(out: &mut String, opts: &WriteOptions, pos: &Position) {
     push_attr(out, "pos", &fmt_f64(pos.offset));
-    if let Some(abs) = pos.mileage {
+    if let Some(abs) = pos.mileage.filter(|_| opts.abs_pos) {
         push_attr(out, "absPos", &fmt_f64(abs));
     }
 }
 
-fn write_geo_coord(out: &mut String, coord: &str, level: usize) {
-    push_indent(out, level);
+fn write_geo_coord(out: &mut String, opts: &WriteOptions, coord: &str, level: usize) {
+    push_indent(out, opts, level);
     out.push_str("<geoCoord");
     push_attr(out, "coord", coord);
     out.push_str("/>\n");
 }
 
-fn write_text_element(out: &mut String, tag: &str, value: &str, level: usize) {
-    push_indent(out, level);
+fn write_text_element(out: &mut String, opts: &WriteOptions, tag: &str, value: &str, level: usize) {
+    push_indent(out, opts, level);
     out.push('<');
     out.push_str(tag);
     out.push('>');
@@ -112,33 +184,36 @@ fn write_course(out: &mut String, course: SwitchConnectionCourse) {
     push_attr(out, "course", s);
 }
 
-fn write_track_end_connection(out: &mut String, conn: &TrackEndConnection, level: usize) {
+fn write_track_end_connection(out: &mut String, opts: &WriteOptions, conn: &TrackEndConnection, level: usize) {
     match conn {
         TrackEndConnection::Connection(id, idref) => {
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("<connection");
             push_attr(out, "id", id);
             push_attr(out, "ref", idref);
             out.push_str("/>\n");
         }
         TrackEndConnection::BufferStop => {
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("<bufferStop/>\n");
         }
         TrackEndConnection::OpenEnd => {
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("<openEnd/>\n");
         }
-        TrackEndConnection::MacroscopicNode(id) => {
-            push_indent(out, level);
+        TrackEndConnection::MacroscopicNode(id, ocp_ref) => {
+            push_indent(out, opts, level);
             out.push_str("<macroscopicNode");
             push_attr(out, "id", id);
+            if let Some(ocp_ref) = ocp_ref {
+                push_attr(out, "ocpRef", ocp_ref);
+            }
             out.push_str("/>\n");
         }
     }
 }
 
-fn write_switch(out: &mut String, sw: &Switch, level: usize) {
+fn write_switch(out: &mut String, opts: &WriteOptions, sw: &Switch, level: usize) {
     match sw {
         Switch::Switch {
             id,
@@ -150,10 +225,10 @@ fn write_switch(out: &mut String, sw: &Switch, level: usize) {
             track_continue_course,
             track_continue_radius,
         } => {
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("<switch");
             push_attr(out, "id", id);
-            write_position_attrs(out, pos);
+            write_position_attrs(out, opts, pos);
             if let Some(name) = name {
                 push_attr(out, "name", name);
             }
@@ -171,10 +246,10 @@ fn write_switch(out: &mut String, sw: &Switch, level: usize) {
             }
             out.push_str(">\n");
             if let Some(gc) = &pos.geo_coord {
-                write_geo_coord(out, gc, level + 1);
+                write_geo_coord(out, opts, gc, level + 1);
             }
             for conn in connections {
-                push_indent(out, level + 1);
+                push_indent(out, opts, level + 1);
                 out.push_str("<connection");
                 push_attr(out, "id", &conn.id);
                 push_attr(out, "ref", &conn.r#ref);
@@ -193,22 +268,26 @@ fn write_switch(out: &mut String, sw: &Switch, level: usize) {
                 }
                 out.push_str("/>\n");
             }
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("</switch>\n");
         }
         Switch::Crossing {
             id,
             pos,
+            crossing_type,
             track_continue_course,
             track_continue_radius,
             normal_position,
             length,
             connections,
         } => {
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("<crossing");
             push_attr(out, "id", id);
-            write_position_attrs(out, pos);
+            write_position_attrs(out, opts, pos);
+            if let Some(t) = crossing_type {
+                push_attr(out, "type", t);
+            }
             if let Some(course) = track_continue_course {
                 write_course(out, *course);
             }
@@ -223,10 +302,10 @@ fn write_switch(out: &mut String, sw: &Switch, level: usize) {
             }
             out.push_str(">\n");
             if let Some(gc) = &pos.geo_coord {
-                write_geo_coord(out, gc, level + 1);
+                write_geo_coord(out, opts, gc, level + 1);
             }
             for conn in connections {
-                push_indent(out, level + 1);
+                push_indent(out, opts, level + 1);
                 out.push_str("<connection");
                 push_attr(out, "id", &conn.id);
                 push_attr(out, "ref", &conn.r#ref);
@@ -236,32 +315,37 @@ fn write_switch(out: &mut String, sw: &Switch, level: usize) {
                 }
                 out.push_str("/>\n");
             }
-            push_indent(out, level);
+            push_indent(out, opts, level);
             out.push_str("</crossing>\n");
         }
     }
 }
 
-fn write_track_elements(out: &mut String, track: &Track, level: usize) {
+fn write_track_elements(out: &mut String, opts: &WriteOptions, track: &Track, level: usize) {
     if track.track_elements.platform_edges.is_empty()
         && track.track_elements.speed_changes.is_empty()
+        && track.track_elements.gradient_changes.is_empty()
+        && track.track_elements.radius_changes.is_empty()
+        && track.track_elements.electrification_changes.is_empty()
+        && track.track_elements.track_conditions.is_empty()
         && track.track_elements.level_crossings.is_empty()
         && track.track_elements.geo_mappings.is_empty()
+        && track.extras.elements_in("trackElements").next().is_none()
     {
         return;
     }
 
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<trackElements>\n");
 
     if !track.track_elements.speed_changes.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<speedChanges>\n");
         for sc in &track.track_elements.speed_changes {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<speedChange");
             push_attr(out, "id", &sc.id);
-            write_position_attrs(out, &sc.pos);
+            write_position_attrs(out, opts, &sc.pos);
             write_track_direction(out, sc.dir);
             if let Some(vmax) = &sc.vmax {
                 push_attr(out, "vMax", vmax);
@@ -271,18 +355,114 @@ fn write_track_elements(out: &mut String, track: &Track, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</speedChanges>\n");
     }
 
+    if !track.track_elements.gradient_changes.is_empty() {
+        push_indent(out, opts, level + 1);
+        out.push_str("<gradientChanges>\n");
+        for gc in &track.track_elements.gradient_changes {
+            push_indent(out, opts, level + 2);
+            out.push_str("<gradientChange");
+            push_attr(out, "id", &gc.id);
+            write_position_attrs(out, opts, &gc.pos);
+            write_track_direction(out, gc.dir);
+            push_attr(out, "slope", &fmt_f64(gc.slope));
+            if let Some(length) = gc.transition_length {
+                push_attr(out, "transitionLenght", &fmt_f64(length));
+            }
+            if let Some(radius) = gc.transition_radius {
+                push_attr(out, "transitionRadius", &fmt_f64(radius));
+            }
+            out.push_str("/>\n");
+        }
+        push_indent(out, opts, level + 1);
+        out.push_str("</gradientChanges>\n");
+    }
+
+    if !track.track_elements.radius_changes.is_empty() {
+        push_indent(out, opts, level + 1);
+        out.push_str("<radiusChanges>\n");
+        for rc in &track.track_elements.radius_changes {
+            push_indent(out, opts, level + 2);
+            out.push_str("<radiusChange");
+            push_attr(out, "id", &rc.id);
+            write_position_attrs(out, opts, &rc.pos);
+            write_track_direction(out, rc.dir);
+            push_attr(out, "radius", &fmt_f64(rc.radius));
+            if let Some(superelevation) = rc.superelevation {
+                push_attr(out, "superelevation", &fmt_f64(superelevation));
+            }
+            out.push_str("/>\n");
+        }
+        push_indent(out, opts, level + 1);
+        out.push_str("</radiusChanges>\n");
+    }
+
+    if !track.track_elements.electrification_changes.is_empty() {
+        push_indent(out, opts, level + 1);
+        out.push_str("<electrificationChanges>\n");
+        for ec in &track.track_elements.electrification_changes {
+            push_indent(out, opts, level + 2);
+            out.push_str("<electrificationChange");
+            push_attr(out, "id", &ec.id);
+            write_position_attrs(out, opts, &ec.pos);
+            if let Some(dir) = &ec.dir {
+                push_attr(out, "dir", dir);
+            }
+            if let Some(t) = &ec.electrification_type {
+                push_attr(out, "type", t);
+            }
+            if let Some(voltage) = ec.voltage {
+                push_attr(out, "voltage", &fmt_f64(voltage));
+            }
+            if let Some(frequency) = ec.frequency {
+                push_attr(out, "frequency", &fmt_f64(frequency));
+            }
+            if let Some(vmax) = &ec.vmax {
+                push_attr(out, "vMax", vmax);
+            }
+            if let Some(isolated) = ec.isolated_section {
+                push_attr(out, "isolatedSection", if isolated { "true" } else { "false" });
+            }
+            out.push_str("/>\n");
+        }
+        push_indent(out, opts, level + 1);
+        out.push_str("</electrificationChanges>\n");
+    }
+
+    if !track.track_elements.track_conditions.is_empty() {
+        push_indent(out, opts, level + 1);
+        out.push_str("<trackConditions>\n");
+        for tc in &track.track_elements.track_conditions {
+            push_indent(out, opts, level + 2);
+            out.push_str("<trackCondition");
+            push_attr(out, "id", &tc.id);
+            write_position_attrs(out, opts, &tc.pos);
+            if let Some(dir) = &tc.dir {
+                push_attr(out, "dir", dir);
+            }
+            if let Some(length) = tc.length {
+                push_attr(out, "length", &fmt_f64(length));
+            }
+            if let Some(t) = &tc.condition_type {
+                push_attr(out, "type", t);
+            }
+            out.push_str("/>\n");
+        }
+        push_indent(out, opts, level + 1);
+        out.push_str("</trackConditions>\n");
+    }
+
     if !track.track_elements.level_crossings.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<levelCrossings>\n");
         for lc in &track.track_elements.level_crossings {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<levelCrossing");
             push_attr(out, "id", &lc.id);
-            write_position_attrs(out, &lc.pos);
+            write_position_attrs(out, opts, &lc.pos);
             if let Some(protection) = &lc.protection {
                 push_attr(out, "protection", protection);
             }
@@ -291,18 +471,18 @@ fn write_track_elements(out: &mut String, track: &Track, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</levelCrossings>\n");
     }
 
     if !track.track_elements.geo_mappings.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<geoMappings>\n");
         for gm in &track.track_elements.geo_mappings {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<geoMapping");
             push_attr(out, "id", &gm.id);
-            write_position_attrs(out, &gm.pos);
+            write_position_attrs(out, opts, &gm.pos);
             if let Some(name) = &gm.name {
                 push_attr(out, "name", name);
             }
@@ -314,25 +494,25 @@ fn write_track_elements(out: &mut String, track: &Track, level: usize) {
             }
             if let Some(gc) = &gm.pos.geo_coord {
                 out.push_str(">\n");
-                write_geo_coord(out, gc, level + 3);
-                push_indent(out, level + 2);
+                write_geo_coord(out, opts, gc, level + 3);
+                push_indent(out, opts, level + 2);
                 out.push_str("</geoMapping>\n");
             } else {
                 out.push_str("/>\n");
             }
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</geoMappings>\n");
     }
 
     if !track.track_elements.platform_edges.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<platformEdges>\n");
         for pe in &track.track_elements.platform_edges {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<platformEdge");
             push_attr(out, "id", &pe.id);
-            write_position_attrs(out, &pe.pos);
+            write_position_attrs(out, opts, &pe.pos);
             write_track_direction(out, pe.dir);
             if let Some(name) = &pe.name {
                 push_attr(out, "name", name);
@@ -348,25 +528,26 @@ fn write_track_elements(out: &mut String, track: &Track, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</platformEdges>\n");
     }
 
-    push_indent(out, level);
+    write_extra_elements(out, opts, &track.extras, "trackElements", level + 1);
+    push_indent(out, opts, level);
     out.push_str("</trackElements>\n");
 }
 
-fn write_cross_sections(out: &mut String, track: &Track, level: usize) {
+fn write_cross_sections(out: &mut String, opts: &WriteOptions, track: &Track, level: usize) {
     if track.track_elements.cross_sections.is_empty() {
         return;
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<crossSections>\n");
     for cs in &track.track_elements.cross_sections {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<crossSection");
         push_attr(out, "id", &cs.id);
-        write_position_attrs(out, &cs.pos);
+        write_position_attrs(out, opts, &cs.pos);
         if let Some(name) = &cs.name {
             push_attr(out, "name", name);
         }
@@ -378,33 +559,47 @@ fn write_cross_sections(out: &mut String, track: &Track, level: usize) {
         }
         out.push_str("/>\n");
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</crossSections>\n");
 }
 
-fn write_objects(out: &mut String, objs: &Objects, level: usize) {
+fn write_balise(out: &mut String, opts: &WriteOptions, b: &Balise, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("<balise");
+    push_attr(out, "id", &b.id);
+    write_position_attrs(out, opts, &b.pos);
+    if let Some(name) = &b.name {
+        push_attr(out, "name", name);
+    }
+    out.push_str("/>\n");
+}
+
+fn write_objects(out: &mut String, opts: &WriteOptions, track: &Track, level: usize) {
+    let objs = &track.objects;
     if objs.signals.is_empty()
         && objs.balises.is_empty()
+        && objs.balise_groups.is_empty()
         && objs.train_detectors.is_empty()
         && objs.track_circuit_borders.is_empty()
         && objs.derailers.is_empty()
         && objs.train_protection_elements.is_empty()
         && objs.train_protection_element_groups.is_empty()
+        && track.extras.elements_in("ocsElements").next().is_none()
     {
         return;
     }
 
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<ocsElements>\n");
 
     if !objs.signals.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<signals>\n");
         for sig in &objs.signals {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<signal");
             push_attr(out, "id", &sig.id);
-            write_position_attrs(out, &sig.pos);
+            write_position_attrs(out, opts, &sig.pos);
             if let Some(name) = &sig.name {
                 push_attr(out, "name", name);
             }
@@ -427,7 +622,7 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
             } else {
                 out.push_str(">\n");
                 if let Some(etcs) = &sig.etcs {
-                    push_indent(out, level + 3);
+                    push_indent(out, opts, level + 3);
                     out.push_str("<etcs");
                     if let Some(v) = etcs.level_1 {
                         push_attr(out, "level_1", if v { "true" } else { "false" });
@@ -441,7 +636,7 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
                     out.push_str("/>\n");
                 }
                 for sp in &sig.speeds {
-                    push_indent(out, level + 3);
+                    push_indent(out, opts, level + 3);
                     out.push_str("<speed");
                     if let Some(kind) = &sp.kind {
                         push_attr(out, "kind", kind);
@@ -454,32 +649,32 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
                     }
                     if let Some(r) = &sp.speed_change_ref {
                         out.push_str(">\n");
-                        push_indent(out, level + 4);
+                        push_indent(out, opts, level + 4);
                         out.push_str("<speedChangeRef");
                         push_attr(out, "ref", r);
                         out.push_str("/>\n");
-                        push_indent(out, level + 3);
+                        push_indent(out, opts, level + 3);
                         out.push_str("</speed>\n");
                     } else {
                         out.push_str("/>\n");
                     }
                 }
-                push_indent(out, level + 2);
+                push_indent(out, opts, level + 2);
                 out.push_str("</signal>\n");
             }
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</signals>\n");
     }
 
     if !objs.train_detectors.is_empty() || !objs.track_circuit_borders.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<trainDetectionElements>\n");
         for det in &objs.train_detectors {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<trainDetector");
             push_attr(out, "id", &det.id);
-            write_position_attrs(out, &det.pos);
+            write_position_attrs(out, opts, &det.pos);
             if let Some(axle) = det.axle_counting {
                 push_attr(out, "axleCounting", if axle { "true" } else { "false" });
             }
@@ -492,44 +687,77 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
             out.push_str("/>\n");
         }
         for tcb in &objs.track_circuit_borders {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<trackCircuitBorder");
             push_attr(out, "id", &tcb.id);
-            write_position_attrs(out, &tcb.pos);
+            write_position_attrs(out, opts, &tcb.pos);
             if let Some(rail) = &tcb.insulated_rail {
                 push_attr(out, "insulatedRail", rail);
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</trainDetectionElements>\n");
     }
 
-    if !objs.balises.is_empty() {
-        push_indent(out, level + 1);
+    if !objs.balises.is_empty() || !objs.balise_groups.is_empty() {
+        push_indent(out, opts, level + 1);
         out.push_str("<balises>\n");
-        for b in &objs.balises {
-            push_indent(out, level + 2);
-            out.push_str("<balise");
-            push_attr(out, "id", &b.id);
-            write_position_attrs(out, &b.pos);
-            if let Some(name) = &b.name {
+        // Balises of a group on this track are written inside the group.
+        let in_group = |b: &Balise| match &b.group_ref {
+            Some(r) => objs.balise_groups.iter().any(|g| &g.id == r),
+            None => false,
+        };
+        for b in objs.balises.iter().filter(|b| !in_group(b)) {
+            write_balise(out, opts, b, level + 2);
+        }
+        for g in &objs.balise_groups {
+            push_indent(out, opts, level + 2);
+            out.push_str("<baliseGroup");
+            push_attr(out, "id", &g.id);
+            write_position_attrs(out, opts, &g.pos);
+            if let Some(name) = &g.name {
                 push_attr(out, "name", name);
             }
-            out.push_str("/>\n");
+            if let Some(dir) = g.dir {
+                write_track_direction(out, dir);
+            }
+            if let Some(country_id) = &g.country_id {
+                push_attr(out, "countryID", country_id);
+            }
+            if let Some(group_id) = &g.group_id {
+                push_attr(out, "groupID", group_id);
+            }
+            if let Some(ndx) = g.ndx {
+                push_attr(out, "ndx", if ndx { "true" } else { "false" });
+            }
+            if let Some(accuracy) = g.linking_accuracy {
+                push_attr(out, "linkingAccuracy", &accuracy.to_string());
+            }
+            let mut balises = g.balises(objs).peekable();
+            if balises.peek().is_none() {
+                out.push_str("/>\n");
+                continue;
+            }
+            out.push_str(">\n");
+            for b in balises {
+                write_balise(out, opts, b, level + 3);
+            }
+            push_indent(out, opts, level + 2);
+            out.push_str("</baliseGroup>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</balises>\n");
     }
 
     if !objs.derailers.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<derailers>\n");
         for d in &objs.derailers {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<derailer");
             push_attr(out, "id", &d.id);
-            write_position_attrs(out, &d.pos);
+            write_position_attrs(out, opts, &d.pos);
             if let Some(dir) = d.dir {
                 write_track_direction(out, dir);
             }
@@ -541,18 +769,18 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</derailers>\n");
     }
 
     if !objs.train_protection_elements.is_empty() || !objs.train_protection_element_groups.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<trainProtectionElements>\n");
         for tpe in &objs.train_protection_elements {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<trainProtectionElement");
             push_attr(out, "id", &tpe.id);
-            write_position_attrs(out, &tpe.pos);
+            write_position_attrs(out, opts, &tpe.pos);
             if let Some(dir) = tpe.dir {
                 write_track_direction(out, dir);
             }
@@ -565,7 +793,7 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
             out.push_str("/>\n");
         }
         for group in &objs.train_protection_element_groups {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<trainProtectionElementGroup");
             push_attr(out, "id", &group.id);
             if group.element_refs.is_empty() {
@@ -573,25 +801,26 @@ fn write_objects(out: &mut String, objs: &Objects, level: usize) {
             } else {
                 out.push_str(">\n");
                 for r in &group.element_refs {
-                    push_indent(out, level + 3);
+                    push_indent(out, opts, level + 3);
                     out.push_str("<trainProtectionElementRef");
                     push_attr(out, "ref", r);
                     out.push_str("/>\n");
                 }
-                push_indent(out, level + 2);
+                push_indent(out, opts, level + 2);
                 out.push_str("</trainProtectionElementGroup>\n");
             }
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</trainProtectionElements>\n");
     }
 
-    push_indent(out, level);
+    write_extra_elements(out, opts, &track.extras, "ocsElements", level + 1);
+    push_indent(out, opts, level);
     out.push_str("</ocsElements>\n");
 }
 
-fn write_metadata(out: &mut String, md: &Metadata, level: usize) {
-    push_indent(out, level);
+fn write_metadata(out: &mut String, opts: &WriteOptions, md: &Metadata, level: usize) {
+    push_indent(out, opts, level);
     out.push_str("<metadata");
     if let Some(v) = &md.version {
         push_attr(out, "version", v);
@@ -599,35 +828,35 @@ fn write_metadata(out: &mut String, md: &Metadata, level: usize) {
     out.push_str(">\n");
 
     if let Some(v) = &md.dc_format {
-        write_text_element(out, "format", v, level + 1);
+        write_text_element(out, opts, "format", v, level + 1);
     }
     if let Some(v) = &md.dc_identifier {
-        write_text_element(out, "identifier", v, level + 1);
+        write_text_element(out, opts, "identifier", v, level + 1);
     }
     if let Some(v) = &md.dc_source {
-        write_text_element(out, "source", v, level + 1);
+        write_text_element(out, opts, "source", v, level + 1);
     }
     if let Some(v) = &md.dc_title {
-        write_text_element(out, "title", v, level + 1);
+        write_text_element(out, opts, "title", v, level + 1);
     }
     if let Some(v) = &md.dc_language {
-        write_text_element(out, "language", v, level + 1);
+        write_text_element(out, opts, "language", v, level + 1);
     }
     if let Some(v) = &md.dc_creator {
-        write_text_element(out, "creator", v, level + 1);
+        write_text_element(out, opts, "creator", v, level + 1);
     }
     if let Some(v) = &md.dc_description {
-        write_text_element(out, "description", v, level + 1);
+        write_text_element(out, opts, "description", v, level + 1);
     }
     if let Some(v) = &md.dc_rights {
-        write_text_element(out, "rights", v, level + 1);
+        write_text_element(out, opts, "rights", v, level + 1);
     }
 
     if !md.organizational_units.is_empty() {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<organizationalUnits>\n");
         for ou in &md.organizational_units {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<infrastructureManager");
             push_attr(out, "id", &ou.id);
             if let Some(code) = &ou.code {
@@ -641,22 +870,22 @@ fn write_metadata(out: &mut String, md: &Metadata, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("</organizationalUnits>\n");
     }
 
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</metadata>\n");
 }
 
-fn write_track_groups(out: &mut String, infra: &Infrastructure, level: usize) {
+fn write_track_groups(out: &mut String, opts: &WriteOptions, infra: &Infrastructure, level: usize) {
     if infra.track_groups.is_empty() {
         return;
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<trackGroups>\n");
     for line in &infra.track_groups {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<line");
         push_attr(out, "id", &line.id);
         if let Some(code) = &line.code {
@@ -674,13 +903,14 @@ fn write_track_groups(out: &mut String, infra: &Infrastructure, level: usize) {
         if let Some(ty) = &line.line_type {
             push_attr(out, "type", ty);
         }
-        if line.track_refs.is_empty() && line.additional_names.is_empty() {
+        write_extra_attributes(out, &line.extras);
+        if line.track_refs.is_empty() && line.additional_names.is_empty() && line.extras.elements.is_empty() {
             out.push_str("/>\n");
             continue;
         }
         out.push_str(">\n");
         for an in &line.additional_names {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<additionalName");
             push_attr(out, "name", &an.name);
             if let Some(lang) = &an.lang {
@@ -692,7 +922,7 @@ fn write_track_groups(out: &mut String, infra: &Infrastructure, level: usize) {
             out.push_str("/>\n");
         }
         for tr in &line.track_refs {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<trackRef");
             push_attr(out, "ref", &tr.r#ref);
             if let Some(seq) = tr.sequence {
@@ -700,21 +930,22 @@ fn write_track_groups(out: &mut String, infra: &Infrastructure, level: usize) {
             }
             out.push_str("/>\n");
         }
-        push_indent(out, level + 1);
+        write_extra_elements(out, opts, &line.extras, "", level + 2);
+        push_indent(out, opts, level + 1);
         out.push_str("</line>\n");
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</trackGroups>\n");
 }
 
-fn write_operation_control_points(out: &mut String, infra: &Infrastructure, level: usize) {
+fn write_operation_control_points(out: &mut String, opts: &WriteOptions, infra: &Infrastructure, level: usize) {
     if infra.ocps.is_empty() {
         return;
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<operationControlPoints>\n");
     for ocp in &infra.ocps {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<ocp");
         push_attr(out, "id", &ocp.id);
         if let Some(name) = &ocp.name {
@@ -726,12 +957,14 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         if let Some(t) = &ocp.r#type {
             push_attr(out, "type", t);
         }
+        write_extra_attributes(out, &ocp.extras);
         if ocp.additional_names.is_empty()
             && ocp.prop_operational.is_none()
             && ocp.prop_equipment.is_none()
             && ocp.prop_service.is_none()
             && ocp.designator.is_none()
             && ocp.geo_coord.is_none()
+            && ocp.extras.elements.is_empty()
         {
             out.push_str("/>\n");
             continue;
@@ -739,7 +972,7 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         out.push_str(">\n");
 
         for an in &ocp.additional_names {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<additionalName");
             push_attr(out, "name", &an.name);
             if let Some(lang) = &an.lang {
@@ -752,7 +985,7 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         }
 
         if let Some(prop) = &ocp.prop_operational {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<propOperational");
             if let Some(v) = prop.ensures_train_sequence {
                 push_attr(out, "ensuresTrainSequence", if v { "true" } else { "false" });
@@ -770,7 +1003,7 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         }
 
         if let Some(prop) = &ocp.prop_service {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<propService");
             if let Some(v) = prop.passenger {
                 push_attr(out, "passenger", if v { "true" } else { "false" });
@@ -785,14 +1018,14 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         }
 
         if let Some(prop) = &ocp.prop_equipment {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<propEquipment");
             if prop.summary.is_none() && prop.track_refs.is_empty() {
                 out.push_str("/>\n");
             } else {
                 out.push_str(">\n");
                 if let Some(summary) = &prop.summary {
-                    push_indent(out, level + 3);
+                    push_indent(out, opts, level + 3);
                     out.push_str("<summary");
                     if let Some(v) = summary.has_home_signals {
                         push_attr(out, "hasHomeSignals", if v { "true" } else { "false" });
@@ -809,18 +1042,18 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
                     out.push_str("/>\n");
                 }
                 for tr in &prop.track_refs {
-                    push_indent(out, level + 3);
+                    push_indent(out, opts, level + 3);
                     out.push_str("<trackRef");
                     push_attr(out, "ref", tr);
                     out.push_str("/>\n");
                 }
-                push_indent(out, level + 2);
+                push_indent(out, opts, level + 2);
                 out.push_str("</propEquipment>\n");
             }
         }
 
         if let Some(gc) = &ocp.geo_coord {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<geoCoord");
             push_attr(out, "coord", &gc.coord);
             if let Some(code) = &gc.epsg_code {
@@ -830,7 +1063,7 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
         }
 
         if let Some(des) = &ocp.designator {
-            push_indent(out, level + 2);
+            push_indent(out, opts, level + 2);
             out.push_str("<designator");
             if let Some(reg) = &des.register {
                 push_attr(out, "register", reg);
@@ -841,21 +1074,22 @@ fn write_operation_control_points(out: &mut String, infra: &Infrastructure, leve
             out.push_str("/>\n");
         }
 
-        push_indent(out, level + 1);
+        write_extra_elements(out, opts, &ocp.extras, "", level + 2);
+        push_indent(out, opts, level + 1);
         out.push_str("</ocp>\n");
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</operationControlPoints>\n");
 }
 
-fn write_states(out: &mut String, infra: &Infrastructure, level: usize) {
+fn write_states(out: &mut String, opts: &WriteOptions, infra: &Infrastructure, level: usize) {
     if infra.states.is_empty() {
         return;
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<states>\n");
     for state in &infra.states {
-        push_indent(out, level + 1);
+        push_indent(out, opts, level + 1);
         out.push_str("<state");
         push_attr(out, "id", &state.id);
         if let Some(disabled) = state.disabled {
@@ -866,21 +1100,21 @@ fn write_states(out: &mut String, infra: &Infrastructure, level: usize) {
         }
         out.push_str("/>\n");
     }
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</states>\n");
 }
 
-fn write_rollingstock(out: &mut String, rs: &Rollingstock, level: usize) {
+fn write_rollingstock(out: &mut String, opts: &WriteOptions, rs: &Rollingstock, level: usize) {
     if rs.vehicles.is_empty() {
         return;
     }
 
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("<rollingstock>\n");
-    push_indent(out, level + 1);
+    push_indent(out, opts, level + 1);
     out.push_str("<vehicles>\n");
     for vehicle in &rs.vehicles {
-        push_indent(out, level + 2);
+        push_indent(out, opts, level + 2);
         out.push_str("<vehicle");
         push_attr(out, "id", &vehicle.id);
         if let Some(name) = &vehicle.name {
@@ -897,35 +1131,254 @@ fn write_rollingstock(out: &mut String, rs: &Rollingstock, level: usize) {
         }
         out.push_str("/>\n");
     }
-    push_indent(out, level + 1);
+    push_indent(out, opts, level + 1);
     out.push_str("</vehicles>\n");
-    push_indent(out, level);
+    push_indent(out, opts, level);
     out.push_str("</rollingstock>\n");
 }
 
+fn write_ref(out: &mut String, opts: &WriteOptions, tag: &str, r: &str, level: usize) {
+    push_indent(out, opts, level);
+    out.push('<');
+    out.push_str(tag);
+    push_attr(out, "ref", r);
+    out.push_str("/>\n");
+}
+
+fn write_interlocking(out: &mut String, opts: &WriteOptions, il: &Interlocking, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("<interlocking");
+    write_extra_attributes(out, &il.extras);
+    out.push_str(">\n");
+    push_indent(out, opts, level + 1);
+    out.push_str("<assetsForIL");
+    push_attr(out, "id", il.assets_id.as_deref().unwrap_or("il01"));
+    out.push_str(">\n");
+
+    if !il.tvd_sections.is_empty() {
+        push_indent(out, opts, level + 2);
+        out.push_str("<tvdSections>\n");
+        for tvd in &il.tvd_sections {
+            push_indent(out, opts, level + 3);
+            out.push_str("<tvdSection");
+            push_attr(out, "id", &tvd.id);
+            if let Some(name) = &tvd.name {
+                push_attr(out, "name", name);
+            }
+            out.push_str(">\n");
+            for r in &tvd.demarcations {
+                write_ref(out, opts, "hasDemarcatingTraindetector", r, level + 4);
+            }
+            push_indent(out, opts, level + 3);
+            out.push_str("</tvdSection>\n");
+        }
+        push_indent(out, opts, level + 2);
+        out.push_str("</tvdSections>\n");
+    }
+
+    if !il.signals.is_empty() {
+        push_indent(out, opts, level + 2);
+        out.push_str("<signalsIL>\n");
+        for sig in &il.signals {
+            push_indent(out, opts, level + 3);
+            out.push_str("<signalIL");
+            push_attr(out, "id", &sig.id);
+            if let Some(v) = sig.is_virtual {
+                push_attr(out, "isVirtual", if v { "true" } else { "false" });
+            }
+            out.push_str(">\n");
+            if let Some(r) = &sig.refers_to {
+                write_ref(out, opts, "refersTo", r, level + 4);
+            }
+            for r in &sig.aspects {
+                write_ref(out, opts, "hasAspect", r, level + 4);
+            }
+            push_indent(out, opts, level + 3);
+            out.push_str("</signalIL>\n");
+        }
+        push_indent(out, opts, level + 2);
+        out.push_str("</signalsIL>\n");
+    }
+
+    if !il.routes.is_empty() {
+        push_indent(out, opts, level + 2);
+        out.push_str("<routes>\n");
+        for route in &il.routes {
+            push_indent(out, opts, level + 3);
+            out.push_str("<route");
+            push_attr(out, "id", &route.id);
+            if let Some(name) = &route.name {
+                push_attr(out, "name", name);
+            }
+            out.push_str(">\n");
+            for (tag, r) in &[("routeEntry", &route.entry), ("routeExit", &route.exit)] {
+                if let Some(r) = r {
+                    push_indent(out, opts, level + 4);
+                    out.push_str(&format!("<{}>\n", tag));
+                    write_ref(out, opts, "refersTo", r, level + 5);
+                    push_indent(out, opts, level + 4);
+                    out.push_str(&format!("</{}>\n", tag));
+                }
+            }
+            for r in &route.tvd_sections {
+                write_ref(out, opts, "hasTvdSection", r, level + 4);
+            }
+            for sw in &route.switch_positions {
+                push_indent(out, opts, level + 4);
+                out.push_str("<facingSwitchInPosition");
+                if let Some(p) = &sw.in_position {
+                    push_attr(out, "inPosition", p);
+                }
+                out.push_str(">\n");
+                write_ref(out, opts, "refersToSwitch", &sw.switch, level + 5);
+                push_indent(out, opts, level + 4);
+                out.push_str("</facingSwitchInPosition>\n");
+            }
+            push_indent(out, opts, level + 3);
+            out.push_str("</route>\n");
+        }
+        push_indent(out, opts, level + 2);
+        out.push_str("</routes>\n");
+    }
+
+    write_extra_elements(out, opts, &il.extras, "assetsForIL", level + 2);
+    push_indent(out, opts, level + 1);
+    out.push_str("</assetsForIL>\n");
+    write_extra_elements(out, opts, &il.extras, "", level + 1);
+    push_indent(out, opts, level);
+    out.push_str("</interlocking>\n");
+}
+
+/// Start of the root element, declaring the namespaces with the prefixes
+/// of the options.
+fn write_root_start(out: &mut String, opts: &WriteOptions, tag: &str, namespace: &str, schema: &str, version: &str) {
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push('<');
+    out.push_str(tag);
+    for (uri, default) in &[(namespace, ""), (DC_NAMESPACE, "dc"), (XSI_NAMESPACE, "xsi")] {
+        match opts.prefix(uri, default) {
+            "" => push_attr(out, "xmlns", uri),
+            prefix => push_attr(out, &format!("xmlns:{}", prefix), uri),
+        }
+    }
+    push_attr(out, "xsi:schemaLocation", &format!("{} {}", namespace, schema));
+    push_attr(out, "version", opts.version.as_deref().unwrap_or(version));
+}
+
+fn rename_prefix(name: &str, opts: &WriteOptions, namespace: &str) -> String {
+    match name.split_once(':') {
+        None => match opts.prefix(namespace, "") {
+            "" => name.to_string(),
+            prefix => format!("{}:{}", prefix, name),
+        },
+        Some(("dc", local)) => format!("{}:{}", opts.prefix(DC_NAMESPACE, "dc"), local),
+        Some(("xsi", local)) => format!("{}:{}", opts.prefix(XSI_NAMESPACE, "xsi"), local),
+        Some(_) => name.to_string(),
+    }
+}
+
+/// Apply the namespace prefixes and attribute order of the options to a
+/// written document. Element names without a prefix are in the railML
+/// namespace. The writer escapes all text, so every `<` starts a tag.
+fn apply_names_and_order(xml: String, opts: &WriteOptions, namespace: &str) -> String {
+    let renamed = [(namespace, ""), (DC_NAMESPACE, "dc"), (XSI_NAMESPACE, "xsi")]
+        .iter()
+        .any(|(uri, default)| opts.prefix(uri, default) != *default);
+    if !renamed && opts.attribute_order == AttributeOrder::Schema {
+        return xml;
+    }
+
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml.as_str();
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let end = start + rest[start..].find('>').map(|i| i + 1).unwrap_or(rest.len() - start);
+        let tag = &rest[start..end];
+        rest = &rest[end..];
+        if tag.starts_with("<?") || tag.starts_with("<!") {
+            out.push_str(tag);
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix("</") {
+            out.push_str("</");
+            out.push_str(&rename_prefix(name.trim_end_matches('>'), opts, namespace));
+            out.push('>');
+            continue;
+        }
+
+        let (body, close) = match tag.strip_suffix("/>") {
+            Some(body) => (&body[1..], "/>"),
+            None => (&tag[1..tag.len() - 1], ">"),
+        };
+        let name_end = body.find(char::is_whitespace).unwrap_or(body.len());
+        let mut attrs = Vec::new();
+        let mut attr_text = &body[name_end..];
+        while let Some(eq) = attr_text.find("=\"") {
+            let key = attr_text[..eq].trim();
+            let value_end = eq + 2 + attr_text[eq + 2..].find('"').unwrap_or(attr_text.len() - eq - 2);
+            attrs.push((key, &attr_text[eq + 2..value_end]));
+            attr_text = &attr_text[(value_end + 1).min(attr_text.len())..];
+        }
+        if opts.attribute_order == AttributeOrder::Alphabetical {
+            attrs.sort_by_key(|(key, _)| (!key.starts_with("xmlns"), *key));
+        }
+
+        out.push('<');
+        out.push_str(&rename_prefix(&body[..name_end], opts, namespace));
+        for (key, value) in attrs {
+            out.push(' ');
+            // Attributes without a prefix are in no namespace.
+            match key.contains(':') {
+                true => out.push_str(&rename_prefix(key, opts, namespace)),
+                false => out.push_str(key),
+            }
+            out.push_str("=\"");
+            out.push_str(value);
+            out.push('"');
+        }
+        out.push_str(close);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Write a railML 2.5 document with the default options.
 pub fn write_railml(railml: &RailML) -> String {
+    write_railml_with_options(railml, &WriteOptions::default())
+}
+
+pub fn write_railml_with_options(railml: &RailML, opts: &WriteOptions) -> String {
     let mut out = String::new();
-    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
-    out.push_str("<railml xmlns=\"https://www.railml.org/schemas/2021\" ");
-    out.push_str("xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ");
-    out.push_str("xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ");
-    out.push_str("xsi:schemaLocation=\"https://www.railml.org/schemas/2021 https://schemas.railml.org/2021/railML-2.5/schema/railML.xsd\" ");
-    out.push_str("version=\"2.5\">\n");
+    write_root_start(
+        &mut out,
+        opts,
+        "railml",
+        RAILML2_NAMESPACE,
+        "https://schemas.railml.org/2021/railML-2.5/schema/railML.xsd",
+        "2.5",
+    );
+    write_extra_attributes(&mut out, &railml.extras);
+    out.push_str(">\n");
 
     if let Some(md) = &railml.metadata {
-        write_metadata(&mut out, md, 1);
+        write_metadata(&mut out, opts, md, 1);
     }
 
     if let Some(infra) = &railml.infrastructure {
-        push_indent(&mut out, 1);
-        out.push_str("<infrastructure id=\"inf01\">\n");
-        write_operation_control_points(&mut out, infra, 2);
-        write_track_groups(&mut out, infra, 2);
-        write_states(&mut out, infra, 2);
-        push_indent(&mut out, 2);
+        push_indent(&mut out, opts, 1);
+        out.push_str("<infrastructure");
+        if !infra.extras.attributes.iter().any(|(k, _)| k == "id") {
+            push_attr(&mut out, "id", "inf01");
+        }
+        write_extra_attributes(&mut out, &infra.extras);
+        out.push_str(">\n");
+        write_operation_control_points(&mut out, opts, infra, 2);
+        write_track_groups(&mut out, opts, infra, 2);
+        write_states(&mut out, opts, infra, 2);
+        push_indent(&mut out, opts, 2);
         out.push_str("<tracks>\n");
         for track in &infra.tracks {
-            push_indent(&mut out, 3);
+            push_indent(&mut out, opts, 3);
             out.push_str("<track");
             push_attr(&mut out, "id", &track.id);
             if let Some(name) = &track.name {
@@ -943,66 +1396,933 @@ pub fn write_railml(railml: &RailML) -> String {
             if let Some(dir) = &track.main_dir {
                 push_attr(&mut out, "mainDir", dir);
             }
+            write_extra_attributes(&mut out, &track.extras);
             out.push_str(">\n");
 
-            push_indent(&mut out, 4);
+            push_indent(&mut out, opts, 4);
             out.push_str("<trackTopology>\n");
 
-            push_indent(&mut out, 5);
+            push_indent(&mut out, opts, 5);
             out.push_str("<trackBegin");
             push_attr(&mut out, "id", &track.begin.id);
-            write_position_attrs(&mut out, &track.begin.pos);
+            write_position_attrs(&mut out, opts, &track.begin.pos);
             out.push_str(">\n");
             if let Some(gc) = &track.begin.pos.geo_coord {
-                write_geo_coord(&mut out, gc, 6);
+                write_geo_coord(&mut out, opts, gc, 6);
             }
-            write_track_end_connection(&mut out, &track.begin.connection, 6);
-            push_indent(&mut out, 5);
+            write_track_end_connection(&mut out, opts, &track.begin.connection, 6);
+            push_indent(&mut out, opts, 5);
             out.push_str("</trackBegin>\n");
 
-            push_indent(&mut out, 5);
+            push_indent(&mut out, opts, 5);
             out.push_str("<trackEnd");
             push_attr(&mut out, "id", &track.end.id);
-            write_position_attrs(&mut out, &track.end.pos);
+            write_position_attrs(&mut out, opts, &track.end.pos);
             out.push_str(">\n");
             if let Some(gc) = &track.end.pos.geo_coord {
-                write_geo_coord(&mut out, gc, 6);
+                write_geo_coord(&mut out, opts, gc, 6);
             }
-            write_track_end_connection(&mut out, &track.end.connection, 6);
-            push_indent(&mut out, 5);
+            write_track_end_connection(&mut out, opts, &track.end.connection, 6);
+            push_indent(&mut out, opts, 5);
             out.push_str("</trackEnd>\n");
 
-            if !track.switches.is_empty() {
-                push_indent(&mut out, 5);
+            if !track.switches.is_empty() || track.extras.elements_in("connections").next().is_some() {
+                push_indent(&mut out, opts, 5);
                 out.push_str("<connections>\n");
                 for sw in &track.switches {
-                    write_switch(&mut out, sw, 6);
+                    write_switch(&mut out, opts, sw, 6);
                 }
-                push_indent(&mut out, 5);
+                write_extra_elements(&mut out, opts, &track.extras, "connections", 6);
+                push_indent(&mut out, opts, 5);
                 out.push_str("</connections>\n");
             }
 
-            write_cross_sections(&mut out, track, 5);
+            write_cross_sections(&mut out, opts, track, 5);
+            write_extra_elements(&mut out, opts, &track.extras, "trackTopology", 5);
 
-            push_indent(&mut out, 4);
+            push_indent(&mut out, opts, 4);
             out.push_str("</trackTopology>\n");
 
-            write_track_elements(&mut out, track, 4);
-            write_objects(&mut out, &track.objects, 4);
+            write_track_elements(&mut out, opts, track, 4);
+            write_objects(&mut out, opts, track, 4);
+            write_extra_elements(&mut out, opts, &track.extras, "", 4);
 
-            push_indent(&mut out, 3);
+            push_indent(&mut out, opts, 3);
             out.push_str("</track>\n");
         }
-        push_indent(&mut out, 2);
+        push_indent(&mut out, opts, 2);
         out.push_str("</tracks>\n");
-        push_indent(&mut out, 1);
+        write_extra_elements(&mut out, opts, &infra.extras, "", 2);
+        push_indent(&mut out, opts, 1);
         out.push_str("</infrastructure>\n");
     }
 
     if let Some(rs) = &railml.rollingstock {
-        write_rollingstock(&mut out, rs, 1);
+        write_rollingstock(&mut out, opts, rs, 1);
+    }
+
+    if let Some(il) = &railml.interlocking {
+        write_interlocking(&mut out, opts, il, 1);
     }
 
+    write_extra_elements(&mut out, opts, &railml.extras, "", 1);
     out.push_str("</railml>\n");
-    out
+    apply_names_and_order(out, opts, RAILML2_NAMESPACE)
+}
+
+//
+// railML 3
+//
+
+/// Switches closer than this (in metres) to a track end are placed at the
+/// end instead of splitting the track.
+const NET_END_TOLERANCE: f64 = 1e-3;
+
+/// An end of a net element, as the track index, the section of the track
+/// and whether it is the end (intrinsic coordinate 1) or the begin (0).
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct NetEnd {
+    track: usize,
+    section: usize,
+    at_end: bool,
+}
+
+/// A railML 2.5 track, split into net elements at the switches and
+/// crossings placed inside it.
+struct NetTrack<'a> {
+    track: &'a Track,
+    /// Offsets where the sections begin and end, from the track begin to
+    /// the track end.
+    bounds: Vec<f64>,
+}
+
+impl<'a> NetTrack<'a> {
+    fn new(track: &'a Track) -> Self {
+        let begin = track.begin.pos.offset;
+        let end = track.end.pos.offset.max(begin);
+        let mut inner = track
+            .switches
+            .iter()
+            .map(|sw| switch_parts(sw).1.offset)
+            .filter(|p| *p > begin + NET_END_TOLERANCE && *p < end - NET_END_TOLERANCE)
+            .collect::<Vec<_>>();
+        inner.sort_by_key(|p| ordered_float::OrderedFloat(*p));
+        inner.dedup_by(|a, b| (*a - *b).abs() < NET_END_TOLERANCE);
+        let mut bounds = vec![begin];
+        bounds.extend(inner);
+        bounds.push(end);
+        NetTrack { track, bounds }
+    }
+
+    fn sections(&self) -> usize {
+        self.bounds.len() - 1
+    }
+
+    fn element_id(&self, section: usize) -> String {
+        if self.sections() == 1 {
+            format!("ne_{}", self.track.id)
+        } else {
+            format!("ne_{}_{}", self.track.id, section + 1)
+        }
+    }
+
+    fn section_length(&self, section: usize) -> f64 {
+        self.bounds[section + 1] - self.bounds[section]
+    }
+
+    /// Section and intrinsic coordinate of an offset on the track. Offsets
+    /// on a section boundary are placed at the begin of the next section,
+    /// or at the end of the previous one if `prefer_end` is set.
+    fn locate_with(&self, offset: f64, prefer_end: bool) -> (usize, f64) {
+        let n = self.sections();
+        let section = (0..n)
+            .find(|i| {
+                let b = self.bounds[i + 1];
+                if prefer_end { offset <= b } else { offset < b }
+            })
+            .unwrap_or(n - 1);
+        let (a, b) = (self.bounds[section], self.bounds[section + 1]);
+        let coord = if b > a { ((offset - a) / (b - a)).clamp(0.0, 1.0) } else { 0.0 };
+        (section, coord)
+    }
+
+    fn locate(&self, offset: f64) -> (usize, f64) {
+        self.locate_with(offset, false)
+    }
+}
+
+fn switch_parts(
+    sw: &Switch,
+) -> (&Id, &Position, &Vec<SwitchConnection>, Option<SwitchConnectionCourse>, bool) {
+    match sw {
+        Switch::Switch { id, pos, connections, track_continue_course, .. } => {
+            (id, pos, connections, *track_continue_course, false)
+        }
+        Switch::Crossing { id, pos, connections, track_continue_course, .. } => {
+            (id, pos, connections, *track_continue_course, true)
+        }
+    }
+}
+
+/// A point on a net element, with the direction it applies to.
+struct Spot {
+    element: String,
+    coord: f64,
+    dir: &'static str,
+}
+
+/// A switch or crossing with its location, and the net relations of its
+/// left and right branches.
+struct NetSwitch<'a> {
+    switch: &'a Switch,
+    track: usize,
+    section: usize,
+    coord: f64,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+/// The RTM topology of a railML 2.5 infrastructure.
+struct NetTopology<'a> {
+    tracks: Vec<NetTrack<'a>>,
+    relations: Vec<(NetEnd, NetEnd)>,
+    switches: Vec<NetSwitch<'a>>,
+}
+
+fn relate(relations: &mut Vec<(NetEnd, NetEnd)>, a: NetEnd, b: NetEnd) -> usize {
+    if let Some(idx) = relations.iter().position(|r| *r == (a, b) || *r == (b, a)) {
+        return idx;
+    }
+    relations.push((a, b));
+    relations.len() - 1
+}
+
+impl<'a> NetTopology<'a> {
+    /// Track ends are related through their connections. Switches relate
+    /// the trunk to each branch; crossings relate consecutive pairs of
+    /// connections, which pass straight through each other, and the slips
+    /// of switch crossings relate the two connections with the same left
+    /// or right course.
+    fn new(infra: &'a Infrastructure) -> Self {
+        let tracks = infra.tracks.iter().map(NetTrack::new).collect::<Vec<_>>();
+        let mut relations = Vec::new();
+        let mut switches = Vec::new();
+
+        let mut conn_ends = std::collections::HashMap::new();
+        let mut track_ends = Vec::new();
+        for (i, t) in tracks.iter().enumerate() {
+            let track: &'a Track = t.track;
+            let begin = NetEnd { track: i, section: 0, at_end: false };
+            let end = NetEnd { track: i, section: t.sections() - 1, at_end: true };
+            for &(node, net_end) in [(&track.begin, begin), (&track.end, end)].iter() {
+                if let TrackEndConnection::Connection(id, r) = &node.connection {
+                    conn_ends.insert(id.as_str(), net_end);
+                    track_ends.push((net_end, r.as_str()));
+                }
+            }
+            for k in 1..t.sections() {
+                relate(
+                    &mut relations,
+                    NetEnd { track: i, section: k - 1, at_end: true },
+                    NetEnd { track: i, section: k, at_end: false },
+                );
+            }
+        }
+
+        for (end, r) in track_ends {
+            if let Some(other) = conn_ends.get(r) {
+                relate(&mut relations, end, *other);
+            }
+        }
+
+        for (i, t) in tracks.iter().enumerate() {
+            let track: &'a Track = t.track;
+            for sw in &track.switches {
+                let (_, pos, connections, continue_course, crossing) = switch_parts(sw);
+                let (section, coord) = t.locate(pos.offset);
+                let branches = connections
+                    .iter()
+                    .filter_map(|c| conn_ends.get(c.r#ref.as_str()).map(|e| (c, *e)))
+                    .collect::<Vec<_>>();
+                let mut left = None;
+                let mut right = None;
+
+                if crossing {
+                    for pair in branches.chunks(2) {
+                        if let [(_, a), (_, b)] = pair {
+                            relate(&mut relations, *a, *b);
+                        }
+                    }
+                    for left in [true, false].iter() {
+                        let slip = branches
+                            .iter()
+                            .filter(|(c, _)| match c.course {
+                                Some(SwitchConnectionCourse::Left) => *left,
+                                Some(SwitchConnectionCourse::Right) => !*left,
+                                _ => false,
+                            })
+                            .collect::<Vec<_>>();
+                        if let [(_, a), (_, b)] = slip.as_slice() {
+                            relate(&mut relations, *a, *b);
+                        }
+                    }
+                } else {
+                    let last = t.sections() - 1;
+                    let at_begin = pos.offset <= t.bounds[0] + NET_END_TOLERANCE;
+                    let at_end = pos.offset >= t.bounds[last + 1] - NET_END_TOLERANCE;
+                    let host = if at_begin {
+                        NetEnd { track: i, section: 0, at_end: false }
+                    } else {
+                        NetEnd { track: i, section: last, at_end: true }
+                    };
+                    // At a track end, the trunk is the connection going
+                    // straight, or else the host track itself.
+                    let trunk = branches
+                        .iter()
+                        .find(|(c, _)| matches!(c.course, Some(SwitchConnectionCourse::Straight)))
+                        .map(|(_, e)| *e)
+                        .unwrap_or(host);
+                    for (c, end) in &branches {
+                        let from = if at_begin || at_end {
+                            trunk
+                        } else {
+                            // Outgoing branches leave in the track direction,
+                            // so they are reached from the section before.
+                            let k = section.max(1);
+                            match c.orientation {
+                                ConnectionOrientation::Outgoing => NetEnd { track: i, section: k - 1, at_end: true },
+                                _ => NetEnd { track: i, section: k, at_end: false },
+                            }
+                        };
+                        if *end == from {
+                            continue;
+                        }
+                        let r = relate(&mut relations, from, *end);
+                        match c.course.or(continue_course.and_then(|cc| cc.opposite())) {
+                            Some(SwitchConnectionCourse::Left) => left = Some(r),
+                            Some(SwitchConnectionCourse::Right) => right = Some(r),
+                            _ => {}
+                        }
+                    }
+                }
+
+                switches.push(NetSwitch { switch: sw, track: i, section, coord, left, right });
+            }
+        }
+
+        NetTopology { tracks, relations, switches }
+    }
+
+    fn end_element(&self, end: &NetEnd) -> String {
+        self.tracks[end.track].element_id(end.section)
+    }
+
+    fn spot(&self, track: usize, offset: f64, dir: &'static str) -> Spot {
+        let (section, coord) = self.tracks[track].locate(offset);
+        Spot { element: self.tracks[track].element_id(section), coord, dir }
+    }
+}
+
+fn relation_id(idx: usize) -> String {
+    format!("nr_{}", idx + 1)
+}
+
+fn fmt_intrinsic(v: f64) -> String {
+    fmt_f64((v * 1e6).round() / 1e6)
+}
+
+fn application_direction(dir: Option<TrackDirection>) -> &'static str {
+    match dir {
+        Some(TrackDirection::Up) => "normal",
+        Some(TrackDirection::Down) => "reverse",
+        None => "both",
+    }
+}
+
+fn write_name3(out: &mut String, opts: &WriteOptions, name: &str, lang: Option<&str>, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("<name");
+    push_attr(out, "name", name);
+    push_attr(out, "language", lang.unwrap_or("en"));
+    out.push_str("/>\n");
+}
+
+fn write_spot_location3(out: &mut String, opts: &WriteOptions, id: &str, spot: &Spot, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("<spotLocation");
+    push_attr(out, "id", &format!("{}_sloc", id));
+    push_attr(out, "netElementRef", &spot.element);
+    push_attr(out, "applicationDirection", spot.dir);
+    push_attr(out, "intrinsicCoord", &fmt_intrinsic(spot.coord));
+    out.push_str("/>\n");
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_linear_location3(
+    out: &mut String,
+    opts: &WriteOptions,
+    t: &NetTrack,
+    id: &str,
+    from: f64,
+    to: f64,
+    dir: &str,
+    level: usize,
+) {
+    let (first, first_coord) = t.locate_with(from.min(to), false);
+    let (last, last_coord) = t.locate_with(from.max(to), true);
+    push_indent(out, opts, level);
+    out.push_str("<linearLocation");
+    push_attr(out, "id", &format!("{}_lloc", id));
+    push_attr(out, "applicationDirection", dir);
+    out.push_str(">\n");
+    for (seq, section) in (first..=last.max(first)).enumerate() {
+        push_indent(out, opts, level + 1);
+        out.push_str("<associatedNetElement");
+        push_attr(out, "netElementRef", &t.element_id(section));
+        push_attr(out, "keepsOrientation", "true");
+        push_attr(out, "sequence", &(seq + 1).to_string());
+        let begin = if section == first { first_coord } else { 0.0 };
+        let end = if section == last { last_coord } else { 1.0 };
+        push_attr(out, "intrinsicCoordBegin", &fmt_intrinsic(begin));
+        push_attr(out, "intrinsicCoordEnd", &fmt_intrinsic(end));
+        out.push_str("/>\n");
+    }
+    push_indent(out, opts, level);
+    out.push_str("</linearLocation>\n");
+}
+
+/// Write an element placed at a single point, such as a balise.
+#[allow(clippy::too_many_arguments)]
+fn write_spot_element3(
+    out: &mut String,
+    opts: &WriteOptions,
+    tag: &str,
+    id: &str,
+    attrs: &[(&str, String)],
+    name: Option<&str>,
+    spot: &Spot,
+    level: usize,
+) {
+    push_indent(out, opts, level);
+    out.push('<');
+    out.push_str(tag);
+    push_attr(out, "id", id);
+    for (key, value) in attrs {
+        push_attr(out, key, value);
+    }
+    out.push_str(">\n");
+    if let Some(name) = name {
+        write_name3(out, opts, name, None, level + 1);
+    }
+    write_spot_location3(out, opts, id, spot, level + 1);
+    push_indent(out, opts, level);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push_str(">\n");
+}
+
+fn open_list(out: &mut String, opts: &WriteOptions, tag: &str, level: usize) {
+    push_indent(out, opts, level);
+    out.push('<');
+    out.push_str(tag);
+    out.push_str(">\n");
+}
+
+fn close_list(out: &mut String, opts: &WriteOptions, tag: &str, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push_str(">\n");
+}
+
+fn write_metadata3(out: &mut String, opts: &WriteOptions, md: &Metadata, level: usize) {
+    push_indent(out, opts, level);
+    out.push_str("<metadata>\n");
+    let fields = [
+        ("dc:format", &md.dc_format),
+        ("dc:identifier", &md.dc_identifier),
+        ("dc:source", &md.dc_source),
+        ("dc:title", &md.dc_title),
+        ("dc:language", &md.dc_language),
+        ("dc:creator", &md.dc_creator),
+        ("dc:description", &md.dc_description),
+        ("dc:rights", &md.dc_rights),
+    ];
+    for (tag, value) in fields.iter() {
+        if let Some(v) = value {
+            write_text_element(out, opts, tag, v, level + 1);
+        }
+    }
+    push_indent(out, opts, level);
+    out.push_str("</metadata>\n");
+}
+
+fn write_topology3(out: &mut String, opts: &WriteOptions, net: &NetTopology, level: usize) {
+    let mut element_relations = net
+        .tracks
+        .iter()
+        .map(|t| vec![Vec::new(); t.sections()])
+        .collect::<Vec<_>>();
+    for (idx, (a, b)) in net.relations.iter().enumerate() {
+        element_relations[a.track][a.section].push(idx);
+        if (b.track, b.section) != (a.track, a.section) {
+            element_relations[b.track][b.section].push(idx);
+        }
+    }
+
+    open_list(out, opts, "topology", level);
+    open_list(out, opts, "netElements", level + 1);
+    for (t, rels) in net.tracks.iter().zip(element_relations.iter()) {
+        for (section, section_rels) in rels.iter().enumerate() {
+            push_indent(out, opts, level + 2);
+            out.push_str("<netElement");
+            push_attr(out, "id", &t.element_id(section));
+            push_attr(out, "length", &fmt_f64(t.section_length(section)));
+            if section_rels.is_empty() {
+                out.push_str("/>\n");
+                continue;
+            }
+            out.push_str(">\n");
+            for idx in section_rels {
+                push_indent(out, opts, level + 3);
+                out.push_str("<relation");
+                push_attr(out, "ref", &relation_id(*idx));
+                out.push_str("/>\n");
+            }
+            push_indent(out, opts, level + 2);
+            out.push_str("</netElement>\n");
+        }
+    }
+    close_list(out, opts, "netElements", level + 1);
+
+    if !net.relations.is_empty() {
+        open_list(out, opts, "netRelations", level + 1);
+        for (idx, (a, b)) in net.relations.iter().enumerate() {
+            push_indent(out, opts, level + 2);
+            out.push_str("<netRelation");
+            push_attr(out, "id", &relation_id(idx));
+            push_attr(out, "positionOnA", if a.at_end { "1" } else { "0" });
+            push_attr(out, "positionOnB", if b.at_end { "1" } else { "0" });
+            push_attr(out, "navigability", "Both");
+            out.push_str(">\n");
+            for (tag, end) in &[("elementA", a), ("elementB", b)] {
+                push_indent(out, opts, level + 3);
+                out.push('<');
+                out.push_str(tag);
+                push_attr(out, "ref", &net.end_element(end));
+                out.push_str("/>\n");
+            }
+            push_indent(out, opts, level + 2);
+            out.push_str("</netRelation>\n");
+        }
+        close_list(out, opts, "netRelations", level + 1);
+    }
+
+    open_list(out, opts, "networks", level + 1);
+    push_indent(out, opts, level + 2);
+    out.push_str("<network id=\"nw01\">\n");
+    push_indent(out, opts, level + 3);
+    out.push_str("<level id=\"nw01_lv0\" descriptionLevel=\"Micro\">\n");
+    for t in &net.tracks {
+        for section in 0..t.sections() {
+            push_indent(out, opts, level + 4);
+            out.push_str("<networkResource");
+            push_attr(out, "ref", &t.element_id(section));
+            out.push_str("/>\n");
+        }
+    }
+    for idx in 0..net.relations.len() {
+        push_indent(out, opts, level + 4);
+        out.push_str("<networkResource");
+        push_attr(out, "ref", &relation_id(idx));
+        out.push_str("/>\n");
+    }
+    push_indent(out, opts, level + 3);
+    out.push_str("</level>\n");
+    push_indent(out, opts, level + 2);
+    out.push_str("</network>\n");
+    close_list(out, opts, "networks", level + 1);
+    close_list(out, opts, "topology", level);
+}
+
+fn signal_type3(t: SignalType) -> &'static str {
+    match t {
+        SignalType::Main => "main",
+        SignalType::Distant => "distant",
+        SignalType::Repeater => "repeater",
+        SignalType::Combined => "combined",
+        SignalType::Shunting => "shunting",
+    }
+}
+
+fn signal_function3(f: SignalFunction) -> Option<&'static str> {
+    match f {
+        SignalFunction::Exit => Some("exit"),
+        SignalFunction::Home => Some("entry"),
+        SignalFunction::Blocking => Some("block"),
+        SignalFunction::Intermediate => Some("intermediate"),
+        SignalFunction::Other => None,
+    }
+}
+
+fn course3(course: SwitchConnectionCourse) -> &'static str {
+    match course {
+        SwitchConnectionCourse::Straight => "straight",
+        SwitchConnectionCourse::Left => "left",
+        SwitchConnectionCourse::Right => "right",
+    }
+}
+
+/// Speed sections from the speed changes of a track. Each speed change
+/// holds until the next one in the same direction, or the track end.
+fn speed_sections(track: &Track) -> Vec<(&SpeedChange, f64, f64)> {
+    let (begin, end) = (track.begin.pos.offset, track.end.pos.offset);
+    let changes = &track.track_elements.speed_changes;
+    changes
+        .iter()
+        .map(|sc| {
+            let same_dir = changes.iter().filter(|o| std::mem::discriminant(&o.dir) == std::mem::discriminant(&sc.dir));
+            let limit = match sc.dir {
+                TrackDirection::Up => same_dir
+                    .map(|o| o.pos.offset)
+                    .filter(|p| *p > sc.pos.offset)
+                    .fold(end, f64::min),
+                TrackDirection::Down => same_dir
+                    .map(|o| o.pos.offset)
+                    .filter(|p| *p < sc.pos.offset)
+                    .fold(begin, f64::max),
+            };
+            (sc, sc.pos.offset, limit)
+        })
+        .collect()
+}
+
+fn write_functional_infrastructure3(out: &mut String, opts: &WriteOptions, infra: &Infrastructure, net: &NetTopology, level: usize) {
+    let tracks = || infra.tracks.iter().enumerate();
+    open_list(out, opts, "functionalInfrastructure", level);
+    let l = level + 2;
+
+    if tracks().any(|(_, t)| !t.objects.balises.is_empty()) {
+        open_list(out, opts, "balises", level + 1);
+        for (i, t) in tracks() {
+            for b in &t.objects.balises {
+                let spot = net.spot(i, b.pos.offset, "both");
+                write_spot_element3(out, opts, "balise", &b.id, &[], b.name.as_deref(), &spot, l);
+            }
+        }
+        close_list(out, opts, "balises", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.objects.balise_groups.is_empty()) {
+        open_list(out, opts, "baliseGroups", level + 1);
+        for (i, t) in tracks() {
+            for g in &t.objects.balise_groups {
+                let spot = net.spot(i, g.pos.offset, application_direction(g.dir));
+                let mut attrs = Vec::new();
+                if let Some(country_id) = &g.country_id {
+                    attrs.push(("countryID", country_id.clone()));
+                }
+                if let Some(group_id) = &g.group_id {
+                    attrs.push(("groupID", group_id.clone()));
+                }
+                if let Some(ndx) = g.ndx {
+                    attrs.push(("ndx", ndx.to_string()));
+                }
+                if let Some(accuracy) = g.linking_accuracy {
+                    attrs.push(("linkingAccuracy", accuracy.to_string()));
+                }
+                write_spot_element3(out, opts, "baliseGroup", &g.id, &attrs, g.name.as_deref(), &spot, l);
+            }
+        }
+        close_list(out, opts, "baliseGroups", level + 1);
+    }
+
+    let mut buffer_stops = Vec::new();
+    for t in &net.tracks {
+        let track = t.track;
+        if let TrackEndConnection::BufferStop = track.begin.connection {
+            let spot = Spot { element: t.element_id(0), coord: 0.0, dir: "both" };
+            buffer_stops.push((format!("bus_{}", track.begin.id), spot));
+        }
+        if let TrackEndConnection::BufferStop = track.end.connection {
+            let spot = Spot { element: t.element_id(t.sections() - 1), coord: 1.0, dir: "both" };
+            buffer_stops.push((format!("bus_{}", track.end.id), spot));
+        }
+    }
+    if !buffer_stops.is_empty() {
+        open_list(out, opts, "bufferStops", level + 1);
+        for (id, spot) in &buffer_stops {
+            write_spot_element3(out, opts, "bufferStop", id, &[], None, spot, l);
+        }
+        close_list(out, opts, "bufferStops", level + 1);
+    }
+
+    let crossings = net
+        .switches
+        .iter()
+        .filter(|s| matches!(s.switch, Switch::Crossing { .. }))
+        .collect::<Vec<_>>();
+    if !crossings.is_empty() {
+        open_list(out, opts, "crossings", level + 1);
+        for c in crossings {
+            let (id, _, _, _, _) = switch_parts(c.switch);
+            let spot = Spot { element: net.tracks[c.track].element_id(c.section), coord: c.coord, dir: "both" };
+            write_spot_element3(out, opts, "crossing", id, &[], None, &spot, l);
+        }
+        close_list(out, opts, "crossings", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.objects.derailers.is_empty()) {
+        open_list(out, opts, "derailersIS", level + 1);
+        for (i, t) in tracks() {
+            for d in &t.objects.derailers {
+                let spot = net.spot(i, d.pos.offset, application_direction(d.dir));
+                let attrs = d.derail_side.iter().map(|s| ("derailSide", s.clone())).collect::<Vec<_>>();
+                write_spot_element3(out, opts, "derailerIS", &d.id, &attrs, None, &spot, l);
+            }
+        }
+        close_list(out, opts, "derailersIS", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.track_elements.level_crossings.is_empty()) {
+        open_list(out, opts, "levelCrossingsIS", level + 1);
+        for (i, t) in tracks() {
+            for lc in &t.track_elements.level_crossings {
+                let spot = net.spot(i, lc.pos.offset, "both");
+                write_spot_element3(out, opts, "levelCrossingIS", &lc.id, &[], None, &spot, l);
+            }
+        }
+        close_list(out, opts, "levelCrossingsIS", level + 1);
+    }
+
+    if !infra.ocps.is_empty() {
+        open_list(out, opts, "operationalPoints", level + 1);
+        for ocp in &infra.ocps {
+            push_indent(out, opts, l);
+            out.push_str("<operationalPoint");
+            push_attr(out, "id", &ocp.id);
+            out.push_str(">\n");
+            if let Some(name) = &ocp.name {
+                write_name3(out, opts, name, ocp.lang.as_deref(), l + 1);
+            }
+            if let Some(Designator { register: Some(register), entry: Some(entry) }) = &ocp.designator {
+                push_indent(out, opts, l + 1);
+                out.push_str("<designator");
+                push_attr(out, "register", register);
+                push_attr(out, "entry", entry);
+                out.push_str("/>\n");
+            }
+            push_indent(out, opts, l);
+            out.push_str("</operationalPoint>\n");
+        }
+        close_list(out, opts, "operationalPoints", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.track_elements.platform_edges.is_empty()) {
+        open_list(out, opts, "platformEdges", level + 1);
+        for (i, t) in tracks() {
+            for pe in &t.track_elements.platform_edges {
+                push_indent(out, opts, l);
+                out.push_str("<platformEdge");
+                push_attr(out, "id", &pe.id);
+                if let Some(height) = pe.height {
+                    push_attr(out, "height", &fmt_f64(height));
+                }
+                if let Some(length) = pe.length {
+                    push_attr(out, "length", &fmt_f64(length));
+                }
+                out.push_str(">\n");
+                if let Some(name) = &pe.name {
+                    write_name3(out, opts, name, None, l + 1);
+                }
+                let from = pe.pos.offset;
+                let to = from + pe.length.unwrap_or(0.0);
+                write_linear_location3(out, opts, &net.tracks[i], &pe.id, from, to, application_direction(Some(pe.dir)), l + 1);
+                push_indent(out, opts, l);
+                out.push_str("</platformEdge>\n");
+            }
+        }
+        close_list(out, opts, "platformEdges", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.objects.signals.is_empty()) {
+        open_list(out, opts, "signalsIS", level + 1);
+        for (i, t) in tracks() {
+            for sig in &t.objects.signals {
+                push_indent(out, opts, l);
+                out.push_str("<signalIS");
+                push_attr(out, "id", &sig.id);
+                if let Some(sw) = sig.switchable {
+                    push_attr(out, "isSwitchable", if sw { "true" } else { "false" });
+                }
+                out.push_str(">\n");
+                if let Some(name) = &sig.name {
+                    write_name3(out, opts, name, None, l + 1);
+                }
+                let spot = net.spot(i, sig.pos.offset, application_direction(Some(sig.dir)));
+                write_spot_location3(out, opts, &sig.id, &spot, l + 1);
+                push_indent(out, opts, l + 1);
+                out.push_str("<isTrainMovementSignal");
+                push_attr(out, "type", signal_type3(sig.r#type));
+                if let Some(f) = sig.function.and_then(signal_function3) {
+                    push_attr(out, "function", f);
+                }
+                out.push_str("/>\n");
+                push_indent(out, opts, l);
+                out.push_str("</signalIS>\n");
+            }
+        }
+        close_list(out, opts, "signalsIS", level + 1);
+    }
+
+    let speeds = tracks()
+        .flat_map(|(i, t)| speed_sections(t).into_iter().map(move |s| (i, s)))
+        .filter_map(|(i, (sc, from, to))| {
+            let vmax = sc.vmax.as_ref()?.trim().parse::<f64>().ok()?;
+            Some((i, sc, vmax, from, to))
+        })
+        .collect::<Vec<_>>();
+    if !speeds.is_empty() {
+        open_list(out, opts, "speedSections", level + 1);
+        for (i, sc, vmax, from, to) in speeds {
+            push_indent(out, opts, l);
+            out.push_str("<speedSection");
+            push_attr(out, "id", &sc.id);
+            push_attr(out, "maxSpeed", &fmt_f64(vmax));
+            out.push_str(">\n");
+            write_linear_location3(out, opts, &net.tracks[i], &sc.id, from, to, application_direction(Some(sc.dir)), l + 1);
+            push_indent(out, opts, l);
+            out.push_str("</speedSection>\n");
+        }
+        close_list(out, opts, "speedSections", level + 1);
+    }
+
+    let switches = net
+        .switches
+        .iter()
+        .filter(|s| matches!(s.switch, Switch::Switch { .. }))
+        .collect::<Vec<_>>();
+    if !switches.is_empty() {
+        open_list(out, opts, "switchesIS", level + 1);
+        for s in switches {
+            let (id, _, _, continue_course, _) = switch_parts(s.switch);
+            push_indent(out, opts, l);
+            out.push_str("<switchIS");
+            push_attr(out, "id", id);
+            push_attr(out, "type", "ordinarySwitch");
+            if let Some(course) = continue_course {
+                push_attr(out, "continueCourse", course3(course));
+                if let Some(branch) = course.opposite() {
+                    push_attr(out, "branchCourse", course3(branch));
+                }
+            }
+            out.push_str(">\n");
+            if let Switch::Switch { name: Some(name), .. } = s.switch {
+                write_name3(out, opts, name, None, l + 1);
+            }
+            let spot = Spot { element: net.tracks[s.track].element_id(s.section), coord: s.coord, dir: "both" };
+            write_spot_location3(out, opts, id, &spot, l + 1);
+            for (tag, relation) in &[("leftBranch", s.left), ("rightBranch", s.right)] {
+                if let Some(r) = relation {
+                    push_indent(out, opts, l + 1);
+                    out.push('<');
+                    out.push_str(tag);
+                    push_attr(out, "netRelationRef", &relation_id(*r));
+                    out.push_str("/>\n");
+                }
+            }
+            push_indent(out, opts, l);
+            out.push_str("</switchIS>\n");
+        }
+        close_list(out, opts, "switchesIS", level + 1);
+    }
+
+    if !infra.tracks.is_empty() {
+        open_list(out, opts, "tracks", level + 1);
+        for (t, net_track) in infra.tracks.iter().zip(net.tracks.iter()) {
+            push_indent(out, opts, l);
+            out.push_str("<track");
+            push_attr(out, "id", &t.id);
+            if let Some(tt) = &t.track_type {
+                push_attr(out, "type", tt);
+            }
+            let main_direction = match t.main_dir.as_deref() {
+                Some("up") => Some("normal"),
+                Some("down") => Some("reverse"),
+                Some("both") => Some("both"),
+                Some("none") => Some("none"),
+                _ => None,
+            };
+            if let Some(dir) = main_direction {
+                push_attr(out, "mainDirection", dir);
+            }
+            out.push_str(">\n");
+            if let Some(name) = &t.name {
+                write_name3(out, opts, name, None, l + 1);
+            }
+            let (begin, end) = (t.begin.pos.offset, t.end.pos.offset);
+            write_linear_location3(out, opts, net_track, &t.id, begin, end, "both", l + 1);
+            push_indent(out, opts, l);
+            out.push_str("</track>\n");
+        }
+        close_list(out, opts, "tracks", level + 1);
+    }
+
+    if tracks().any(|(_, t)| !t.objects.train_detectors.is_empty() || !t.objects.track_circuit_borders.is_empty()) {
+        open_list(out, opts, "trainDetectionElements", level + 1);
+        for (i, t) in tracks() {
+            for d in &t.objects.train_detectors {
+                let spot = net.spot(i, d.pos.offset, "both");
+                let attrs = [("type", "axleCounter".to_string())];
+                write_spot_element3(out, opts, "trainDetectionElement", &d.id, &attrs, None, &spot, l);
+            }
+            for tcb in &t.objects.track_circuit_borders {
+                let spot = net.spot(i, tcb.pos.offset, "both");
+                let attrs = [("type", "insulatedRailJoint".to_string())];
+                write_spot_element3(out, opts, "trainDetectionElement", &tcb.id, &attrs, None, &spot, l);
+            }
+        }
+        close_list(out, opts, "trainDetectionElements", level + 1);
+    }
+
+    close_list(out, opts, "functionalInfrastructure", level);
+}
+
+/// Write a railML 3.2 document. The tracks become RTM net elements, split
+/// at switches and crossings placed inside them, and are connected by net
+/// relations. Signals, switches, detectors and the other track objects are
+/// written as functional infrastructure located on the net elements.
+/// Rolling stock is not written.
+pub fn write_railml3(railml: &RailML) -> String {
+    write_railml3_with_options(railml, &WriteOptions::default())
+}
+
+pub fn write_railml3_with_options(railml: &RailML, opts: &WriteOptions) -> String {
+    let mut out = String::new();
+    write_root_start(
+        &mut out,
+        opts,
+        "railML",
+        RAILML3_NAMESPACE,
+        "https://schemas.railml.org/3.2/railml3.xsd",
+        "3.2",
+    );
+    out.push_str(">\n");
+
+    if let Some(md) = &railml.metadata {
+        write_metadata3(&mut out, opts, md, 1);
+    }
+
+    if let Some(infra) = &railml.infrastructure {
+        let net = NetTopology::new(infra);
+        push_indent(&mut out, opts, 1);
+        out.push_str("<infrastructure id=\"is01\">\n");
+        write_topology3(&mut out, opts, &net, 2);
+        write_functional_infrastructure3(&mut out, opts, infra, &net, 2);
+        push_indent(&mut out, opts, 1);
+        out.push_str("</infrastructure>\n");
+    }
+
+    out.push_str("</railML>\n");
+    apply_names_and_order(out, opts, RAILML3_NAMESPACE)
 }
@@ -1,13 +1,181 @@
 use crate::model::*;
+use crate::write::escape_attr;
 use roxmltree as xml;
-type BoxResult<T> = Result<T, Box<dyn std::error::Error>>;
+pub(crate) type BoxResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+/// Parse a railML document held in memory as a whole. For very large
+/// files, use `stream::parse_railml_reader` instead.
 pub fn parse_railml(data: &str) -> BoxResult<RailML> {
     let doc = roxmltree::Document::parse(data)?;
     parse_railml_xml(&doc.root_element())
 }
 
 pub type ByteOffset = usize;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// The parts of an XML element used to read railML, so that the same code
+/// reads a roxmltree document and the elements of the streaming parser.
+/// Tag names are compared without namespace, and only element children
+/// are listed.
+pub(crate) trait XmlNode: Sized {
+    fn tag_name(&self) -> &str;
+    fn has_tag_name(&self, name: &str) -> bool {
+        self.tag_name() == name
+    }
+    /// Attribute value by unprefixed name, or by `xml:` prefixed name.
+    fn attribute(&self, name: &str) -> Option<&str>;
+    fn children(&self) -> Box<dyn Iterator<Item = Self> + '_>;
+    fn text(&self) -> Option<&str>;
+    /// Byte offset of the element in the file, for error messages.
+    fn start(&self) -> ByteOffset;
+    /// Tag name with its namespace prefix, as written.
+    fn qualified_name(&self) -> String;
+    /// Namespace declarations made on the element, followed by its
+    /// attributes, as (qualified name, value).
+    fn all_attributes(&self) -> Vec<(String, String)>;
+    /// Text and child elements in document order.
+    fn content(&self) -> Vec<Content<Self>>;
+}
+
+pub(crate) enum Content<N> {
+    Text(String),
+    Element(N),
+}
+
+impl<'a, 'input> XmlNode for xml::Node<'a, 'input> {
+    fn tag_name(&self) -> &str {
+        xml::Node::tag_name(self).name()
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        match name.strip_prefix("xml:") {
+            Some(local) => xml::Node::attributes(self)
+                .iter()
+                .find(|a| a.name() == local && a.namespace() == Some(XML_NAMESPACE))
+                .map(|a| a.value()),
+            None => xml::Node::attribute(self, name),
+        }
+    }
+
+    fn children(&self) -> Box<dyn Iterator<Item = Self> + '_> {
+        Box::new(xml::Node::children(self).filter(|c| c.is_element()))
+    }
+
+    fn text(&self) -> Option<&str> {
+        xml::Node::text(self)
+    }
+
+    fn start(&self) -> ByteOffset {
+        self.range().start
+    }
+
+    fn qualified_name(&self) -> String {
+        let name = xml::Node::tag_name(self);
+        match name.namespace().and_then(|ns| self.lookup_prefix(ns)) {
+            Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name.name()),
+            _ => name.name().to_string(),
+        }
+    }
+
+    fn all_attributes(&self) -> Vec<(String, String)> {
+        // Namespaces are listed with those in scope from the ancestors.
+        let parent = self.parent_element();
+        let inherited = parent.as_ref().map(|p| p.namespaces()).unwrap_or(&[]);
+        let mut attributes = Vec::new();
+        for ns in self.namespaces() {
+            if ns.name() == Some("xml") || inherited.iter().any(|i| i.name() == ns.name() && i.uri() == ns.uri()) {
+                continue;
+            }
+            let name = match ns.name() {
+                Some(prefix) => format!("xmlns:{}", prefix),
+                None => "xmlns".to_string(),
+            };
+            attributes.push((name, ns.uri().to_string()));
+        }
+        for a in xml::Node::attributes(self) {
+            let prefix = match a.namespace() {
+                Some(XML_NAMESPACE) => Some("xml"),
+                Some(ns) => self.lookup_prefix(ns),
+                None => None,
+            };
+            let name = match prefix {
+                Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, a.name()),
+                _ => a.name().to_string(),
+            };
+            attributes.push((name, a.value().to_string()));
+        }
+        attributes
+    }
+
+    fn content(&self) -> Vec<Content<Self>> {
+        xml::Node::children(self)
+            .filter_map(|c| {
+                if c.is_element() {
+                    Some(Content::Element(c))
+                } else if c.is_text() {
+                    c.text().map(|t| Content::Text(t.to_string()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn write_node<N: XmlNode>(node: &N, out: &mut String) {
+    let name = node.qualified_name();
+    out.push('<');
+    out.push_str(&name);
+    for (key, value) in node.all_attributes() {
+        out.push_str(&format!(" {}=\"{}\"", key, escape_attr(&value)));
+    }
+    let content = node.content();
+    if content.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for c in &content {
+        match c {
+            Content::Text(text) => out.push_str(&escape_attr(text)),
+            Content::Element(e) => write_node(e, out),
+        }
+    }
+    out.push_str("</");
+    out.push_str(&name);
+    out.push('>');
+}
+
+/// An element and its content as XML text.
+pub(crate) fn to_xml<N: XmlNode>(node: &N) -> String {
+    let mut out = String::new();
+    write_node(node, &mut out);
+    out
+}
+
+/// Keep the attributes of `node` that are not in `known`.
+pub(crate) fn keep_attributes<N: XmlNode>(node: &N, known: &[&str], extras: &mut Extras) {
+    for (name, value) in node.all_attributes() {
+        if !known.contains(&name.as_str()) {
+            extras.attributes.push((name, value));
+        }
+    }
+}
+
+/// Keep the child elements of `node` that are not in `known`, under `parent`.
+pub(crate) fn keep_elements<N: XmlNode>(node: &N, parent: &str, known: &[&str], extras: &mut Extras) {
+    for c in node.children() {
+        if !known.contains(&c.tag_name()) {
+            extras.elements.push((parent.to_string(), to_xml(&c)));
+        }
+    }
+}
+
+/// Attributes of the root element that are written by `write::write_railml`.
+pub(crate) const ROOT_ATTRIBUTES: &[&str] = &["xmlns", "xmlns:dc", "xmlns:xsi", "xsi:schemaLocation", "version"];
+pub(crate) const ROOT_ELEMENTS: &[&str] = &["metadata", "infrastructure", "rollingstock", "interlocking"];
+pub(crate) const INFRASTRUCTURE_ELEMENTS: &[&str] = &["tracks", "trackGroups", "operationControlPoints", "states"];
 #[derive(Debug)]
 pub enum DocErr {
     ElementMissing(&'static str, ByteOffset),
@@ -18,34 +186,45 @@ pub enum DocErr {
     EnumErr(&'static str, ByteOffset),
 }
 
-fn parse_railml_xml(root: &xml::Node) -> BoxResult<RailML> {
+fn parse_railml_xml<N: XmlNode>(root: &N) -> BoxResult<RailML> {
+    let mut extras = Extras::default();
+    keep_attributes(root, ROOT_ATTRIBUTES, &mut extras);
+    keep_elements(root, "", ROOT_ELEMENTS, &mut extras);
+    let mut parse_report = ParseReport::default();
+    let infrastructure = match root.children().find(|c| c.has_tag_name("infrastructure")) {
+        Some(inf) => Some(parse_infrastructure(&inf, &mut parse_report).map_err(|e| format!("{:?}", e))?),
+        None => None,
+    };
     Ok(RailML {
         metadata: parse_metadata(root).ok(),
-        infrastructure: match root.children().find(|c| c.has_tag_name("infrastructure")) {
-            Some(inf) => Some(parse_infrastructure(&inf).map_err(|e| format!("{:?}", e))?),
-            None => None,
-        },
+        infrastructure,
         rollingstock: parse_rollingstock(root).ok(),
+        interlocking: root.children().find(|c| c.has_tag_name("interlocking")).map(|il| parse_interlocking(&il)),
+        timetable: root.children().find(|c| c.has_tag_name("timetable")).map(|tt| parse_timetable(&tt)),
+        extras,
+        parse_report,
     })
 }
 
-fn parse_metadata(root: &xml::Node) -> Result<Metadata, DocErr> {
+fn parse_metadata<N: XmlNode>(root: &N) -> Result<Metadata, DocErr> {
     let md = root
         .children()
         .find(|c| c.has_tag_name("metadata"))
-        .ok_or(DocErr::ElementMissing("metadata", root.range().start))?;
+        .ok_or(DocErr::ElementMissing("metadata", root.start()))?;
+    Ok(parse_metadata_element(&md))
+}
 
+pub(crate) fn parse_metadata_element<N: XmlNode>(md: &N) -> Metadata {
     let text_of = |name: &str| -> Option<String> {
         md.children()
-            .find(|c| c.tag_name().name() == name)
-            .and_then(|n| n.text())
-            .map(|t| t.trim().to_string())
+            .find(|c| c.tag_name() == name)
+            .and_then(|n| n.text().map(|t| t.trim().to_string()))
     };
 
     let mut organizational_units = Vec::new();
     if let Some(orgs) = md
         .children()
-        .find(|c| c.tag_name().name() == "organizationalUnits")
+        .find(|c| c.tag_name() == "organizationalUnits")
     {
         for iu in orgs
             .children()
@@ -60,7 +239,7 @@ fn parse_metadata(root: &xml::Node) -> Result<Metadata, DocErr> {
         }
     }
 
-    Ok(Metadata {
+    Metadata {
         dc_format: text_of("format"),
         dc_identifier: text_of("identifier"),
         dc_source: text_of("source"),
@@ -71,14 +250,14 @@ fn parse_metadata(root: &xml::Node) -> Result<Metadata, DocErr> {
         dc_rights: text_of("rights"),
         organizational_units,
         version: md.attribute("version").map(|x| x.to_string()),
-    })
+    }
 }
 
-fn parse_infrastructure(inf: &xml::Node) -> Result<Infrastructure, DocErr> {
+fn parse_infrastructure<N: XmlNode>(inf: &N, report: &mut ParseReport) -> Result<Infrastructure, DocErr> {
     let mut tracks = Vec::new();
     if let Some(ts) = inf.children().find(|c| c.has_tag_name("tracks")) {
         for t in ts.children().filter(|c| c.has_tag_name("track")) {
-            tracks.push(parse_track(&t)?);
+            tracks.push(parse_track(&t, report)?);
         }
     }
 
@@ -106,19 +285,24 @@ fn parse_infrastructure(inf: &xml::Node) -> Result<Infrastructure, DocErr> {
         }
     }
 
+    let mut extras = Extras::default();
+    keep_attributes(inf, &[], &mut extras);
+    keep_elements(inf, "", INFRASTRUCTURE_ELEMENTS, &mut extras);
+
     Ok(Infrastructure {
         tracks,
         track_groups,
         ocps,
         states,
+        extras,
     })
 }
 
-fn parse_rollingstock(root: &xml::Node) -> Result<Rollingstock, DocErr> {
+fn parse_rollingstock<N: XmlNode>(root: &N) -> Result<Rollingstock, DocErr> {
     let rs = root
         .children()
         .find(|c| c.has_tag_name("rollingstock"))
-        .ok_or(DocErr::ElementMissing("rollingstock", root.range().start))?;
+        .ok_or(DocErr::ElementMissing("rollingstock", root.start()))?;
 
     let mut vehicles = Vec::new();
     if let Some(vehicles_root) = rs.children().find(|c| c.has_tag_name("vehicles")) {
@@ -130,11 +314,195 @@ fn parse_rollingstock(root: &xml::Node) -> Result<Rollingstock, DocErr> {
     Ok(Rollingstock { vehicles })
 }
 
-fn parse_vehicle(node: &xml::Node) -> Result<Vehicle, DocErr> {
+/// The `name` attribute, or the first railML 3 `<name name=".."/>` child.
+fn name_of<N: XmlNode>(node: &N) -> Option<String> {
+    match node.attribute("name") {
+        Some(name) => Some(name.to_string()),
+        None => node
+            .children()
+            .find(|c| c.has_tag_name("name"))
+            .and_then(|n| n.attribute("name").map(|x| x.to_string())),
+    }
+}
+
+/// Reference of the first child element with the given tag, as in
+/// `<refersTo ref=".."/>`.
+fn child_ref<N: XmlNode>(node: &N, tag: &str) -> Option<String> {
+    node.children()
+        .find(|c| c.has_tag_name(tag))
+        .and_then(|c| c.attribute("ref").map(|x| x.to_string()))
+}
+
+fn parse_route<N: XmlNode>(node: &N) -> Route {
+    let entry = node.children().find(|c| c.has_tag_name("routeEntry"));
+    let exit = node.children().find(|c| c.has_tag_name("routeExit"));
+    Route {
+        id: node.attribute("id").unwrap_or_default().to_string(),
+        name: name_of(node),
+        entry: entry.and_then(|e| child_ref(&e, "refersTo")),
+        exit: exit.and_then(|e| child_ref(&e, "refersTo")),
+        tvd_sections: node
+            .children()
+            .filter(|c| c.has_tag_name("hasTvdSection"))
+            .filter_map(|c| c.attribute("ref").map(|x| x.to_string()))
+            .collect(),
+        switch_positions: node
+            .children()
+            .filter(|c| c.has_tag_name("facingSwitchInPosition"))
+            .filter_map(|c| {
+                let switch = child_ref(&c, "refersToSwitch")?;
+                let in_position = c.attribute("inPosition").map(|x| x.to_string());
+                Some(SwitchInPosition { switch, in_position })
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn parse_interlocking<N: XmlNode>(il: &N) -> Interlocking {
+    let mut extras = Extras::default();
+    keep_attributes(il, &[], &mut extras);
+    keep_elements(il, "", &["assetsForIL"], &mut extras);
+
+    let mut interlocking = Interlocking {
+        assets_id: None,
+        tvd_sections: Vec::new(),
+        signals: Vec::new(),
+        routes: Vec::new(),
+        extras,
+    };
+    for assets in il.children().filter(|c| c.has_tag_name("assetsForIL")) {
+        if interlocking.assets_id.is_none() {
+            interlocking.assets_id = assets.attribute("id").map(|x| x.to_string());
+        }
+        keep_elements(&assets, "assetsForIL", &["tvdSections", "signalsIL", "routes"], &mut interlocking.extras);
+        for list in assets.children() {
+            match list.tag_name() {
+                "tvdSections" => {
+                    for t in list.children().filter(|c| c.has_tag_name("tvdSection")) {
+                        interlocking.tvd_sections.push(TvdSection {
+                            id: t.attribute("id").unwrap_or_default().to_string(),
+                            name: name_of(&t),
+                            demarcations: t
+                                .children()
+                                .filter(|c| c.tag_name().starts_with("hasDemarcating"))
+                                .filter_map(|c| c.attribute("ref").map(|x| x.to_string()))
+                                .collect(),
+                        });
+                    }
+                }
+                "signalsIL" => {
+                    for sig in list.children().filter(|c| c.has_tag_name("signalIL")) {
+                        interlocking.signals.push(SignalIL {
+                            id: sig.attribute("id").unwrap_or_default().to_string(),
+                            refers_to: child_ref(&sig, "refersTo"),
+                            is_virtual: sig.attribute("isVirtual").and_then(|v| v.parse::<bool>().ok()),
+                            aspects: sig
+                                .children()
+                                .filter(|c| c.has_tag_name("hasAspect"))
+                                .filter_map(|c| c.attribute("ref").map(|x| x.to_string()))
+                                .collect(),
+                        });
+                    }
+                }
+                "routes" => {
+                    for r in list.children().filter(|c| c.has_tag_name("route")) {
+                        interlocking.routes.push(parse_route(&r));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    interlocking
+}
+
+/// Seconds after midnight of an `xs:time` such as `08:15:30` or
+/// `08:15:30.5+01:00`, on the given day of operation. The time zone is
+/// ignored.
+fn parse_time(time: &str, day: Option<&str>) -> Option<f64> {
+    let time = time.split(&['Z', '+'][..]).next()?;
+    let mut parts = time.splitn(3, ':');
+    let h = parts.next()?.parse::<f64>().ok()?;
+    let m = parts.next()?.parse::<f64>().ok()?;
+    let s = parts.next()?.parse::<f64>().ok()?;
+    let day = day.and_then(|d| d.parse::<f64>().ok()).unwrap_or(0.0);
+    Some(((day * 24.0 + h) * 60.0 + m) * 60.0 + s)
+}
+
+fn parse_ocp_tt<N: XmlNode>(node: &N) -> OcpTT {
+    OcpTT {
+        ocp_ref: node.attribute("ocpRef").unwrap_or_default().to_string(),
+        sequence: node.attribute("sequence").and_then(|s| s.parse().ok()),
+        ocp_type: node.attribute("ocpType").map(|x| x.to_string()),
+        times: node
+            .children()
+            .filter(|c| c.has_tag_name("times"))
+            .map(|t| ArrivalDeparture {
+                scope: t.attribute("scope").unwrap_or_default().to_string(),
+                arrival: t.attribute("arrival").and_then(|a| parse_time(a, t.attribute("arrivalDay"))),
+                departure: t.attribute("departure").and_then(|d| parse_time(d, t.attribute("departureDay"))),
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn parse_timetable<N: XmlNode>(tt: &N) -> Timetable {
+    let mut timetable = Timetable {
+        id: tt.attribute("id").map(|x| x.to_string()),
+        name: name_of(tt),
+        train_parts: Vec::new(),
+        trains: Vec::new(),
+    };
+    for list in tt.children() {
+        match list.tag_name() {
+            "trainParts" => {
+                for tp in list.children().filter(|c| c.has_tag_name("trainPart")) {
+                    let formation = tp.children().find(|c| c.has_tag_name("formationTT"));
+                    let ocps = tp.children().find(|c| c.has_tag_name("ocpsTT"));
+                    timetable.train_parts.push(TrainPart {
+                        id: tp.attribute("id").unwrap_or_default().to_string(),
+                        name: name_of(&tp),
+                        train_number: tp.attribute("trainNumber").map(|x| x.to_string()),
+                        formation_ref: formation.and_then(|f| f.attribute("formationRef").map(|x| x.to_string())),
+                        ocps: ocps
+                            .iter()
+                            .flat_map(|o| o.children())
+                            .filter(|c| c.has_tag_name("ocpTT"))
+                            .map(|o| parse_ocp_tt(&o))
+                            .collect(),
+                    });
+                }
+            }
+            "trains" => {
+                for train in list.children().filter(|c| c.has_tag_name("train")) {
+                    timetable.trains.push(Train {
+                        id: train.attribute("id").unwrap_or_default().to_string(),
+                        name: name_of(&train),
+                        train_number: train.attribute("trainNumber").map(|x| x.to_string()),
+                        train_parts: train
+                            .children()
+                            .filter(|c| c.has_tag_name("trainPartSequence"))
+                            .flat_map(|s| {
+                                s.children()
+                                    .filter(|c| c.has_tag_name("trainPartRef"))
+                                    .filter_map(|r| r.attribute("ref").map(|x| x.to_string()))
+                                    .collect::<Vec<_>>()
+                            })
+                            .collect(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    timetable
+}
+
+pub(crate) fn parse_vehicle<N: XmlNode>(node: &N) -> Result<Vehicle, DocErr> {
     Ok(Vehicle {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         name: node.attribute("name").map(|x| x.to_string()),
         description: node.attribute("description").map(|x| x.to_string()),
@@ -143,13 +511,13 @@ fn parse_vehicle(node: &xml::Node) -> Result<Vehicle, DocErr> {
     })
 }
 
-fn parse_track_group(node: &xml::Node) -> Result<TrackGroup, DocErr> {
+pub(crate) fn parse_track_group<N: XmlNode>(node: &N) -> Result<TrackGroup, DocErr> {
     let mut track_refs = Vec::new();
     for tr in node.children().filter(|c| c.has_tag_name("trackRef")) {
         track_refs.push(TrackRef {
             r#ref: tr
                 .attribute("ref")
-                .ok_or(DocErr::AttributeMissing("ref", tr.range().start))?
+                .ok_or(DocErr::AttributeMissing("ref", tr.start()))?
                 .to_string(),
             sequence: tr.attribute("sequence").and_then(|s| s.parse().ok()),
         });
@@ -165,10 +533,18 @@ fn parse_track_group(node: &xml::Node) -> Result<TrackGroup, DocErr> {
         }
     }
 
+    let mut extras = Extras::default();
+    keep_attributes(
+        node,
+        &["id", "code", "name", "infrastructureManagerRef", "lineCategory", "type"],
+        &mut extras,
+    );
+    keep_elements(node, "", &["trackRef", "additionalName"], &mut extras);
+
     Ok(TrackGroup {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         code: node.attribute("code").map(|x| x.to_string()),
         name: node.attribute("name").map(|x| x.to_string()),
@@ -177,10 +553,11 @@ fn parse_track_group(node: &xml::Node) -> Result<TrackGroup, DocErr> {
         line_type: node.attribute("type").map(|x| x.to_string()),
         track_refs,
         additional_names,
+        extras,
     })
 }
 
-fn parse_ocp(node: &xml::Node) -> Result<Ocp, DocErr> {
+pub(crate) fn parse_ocp<N: XmlNode>(node: &N) -> Result<Ocp, DocErr> {
     let mut additional_names = Vec::new();
     for an in node.children().filter(|c| c.has_tag_name("additionalName")) {
         if let Some(name) = an.attribute("name") {
@@ -241,10 +618,19 @@ fn parse_ocp(node: &xml::Node) -> Result<Ocp, DocErr> {
         })
     });
 
+    let mut extras = Extras::default();
+    keep_attributes(node, &["id", "name", "xml:lang", "lang", "type"], &mut extras);
+    keep_elements(
+        node,
+        "",
+        &["additionalName", "propOperational", "propService", "propEquipment", "designator", "geoCoord"],
+        &mut extras,
+    );
+
     Ok(Ocp {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         name: node.attribute("name").map(|x| x.to_string()),
         lang: node.attribute("xml:lang").or_else(|| node.attribute("lang")).map(|x| x.to_string()),
@@ -255,14 +641,15 @@ fn parse_ocp(node: &xml::Node) -> Result<Ocp, DocErr> {
         prop_equipment,
         prop_service,
         designator,
+        extras,
     })
 }
 
-fn parse_state(node: &xml::Node) -> Result<State, DocErr> {
+pub(crate) fn parse_state<N: XmlNode>(node: &N) -> Result<State, DocErr> {
     Ok(State {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         disabled: node
             .attribute("disabled")
@@ -272,44 +659,82 @@ fn parse_state(node: &xml::Node) -> Result<State, DocErr> {
     })
 }
 
-fn parse_track(track: &xml::Node) -> Result<Track, DocErr> {
+pub(crate) fn parse_track<N: XmlNode>(track: &N, report: &mut ParseReport) -> Result<Track, DocErr> {
     let topo = track
         .children()
         .find(|c| c.has_tag_name("trackTopology"))
         .ok_or(DocErr::ElementMissing(
             "trackTopology",
-            track.range().start,
+            track.start(),
         ))?;
+    let begin = topo
+        .children()
+        .find(|c| c.has_tag_name("trackBegin"))
+        .ok_or(DocErr::ElementMissing("trackBegin", topo.start()))?;
+    let end = topo
+        .children()
+        .find(|c| c.has_tag_name("trackEnd"))
+        .ok_or(DocErr::ElementMissing("trackEnd", topo.start()))?;
+
+    let mut extras = Extras::default();
+    keep_attributes(track, &["id", "name", "code", "description", "type", "mainDir"], &mut extras);
+    keep_elements(track, "", &["trackTopology", "trackElements", "ocsElements"], &mut extras);
+    keep_elements(
+        &topo,
+        "trackTopology",
+        &["trackBegin", "trackEnd", "connections", "crossSections"],
+        &mut extras,
+    );
+    if let Some(te) = track.children().find(|c| c.has_tag_name("trackElements")) {
+        keep_elements(
+            &te,
+            "trackElements",
+            &[
+                "platformEdges",
+                "speedChanges",
+                "gradientChanges",
+                "radiusChanges",
+                "electrificationChanges",
+                "trackConditions",
+                "levelCrossings",
+                "geoMappings",
+            ],
+            &mut extras,
+        );
+    }
+    if let Some(ocs) = track.children().find(|c| c.has_tag_name("ocsElements")) {
+        keep_elements(
+            &ocs,
+            "ocsElements",
+            &["signals", "trainDetectionElements", "balises", "derailers", "trainProtectionElements"],
+            &mut extras,
+        );
+    }
+
+    let id = track
+        .attribute("id")
+        .ok_or(DocErr::AttributeMissing("id", track.start()))?
+        .to_string();
+    let switches = parse_switches(&id, &topo, &mut extras, report);
+    report.count_skipped(&extras);
 
     Ok(Track {
-        id: track
-            .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", track.range().start))?
-            .to_string(),
+        id,
         name: track.attribute("name").map(|x| x.to_string()),
         code: track.attribute("code").map(|x| x.to_string()),
         description: track.attribute("description").map(|x| x.to_string()),
         track_type: track.attribute("type").map(|x| x.to_string()),
         main_dir: track.attribute("mainDir").map(|x| x.to_string()),
-        begin: parse_track_node(
-            &topo
-                .children()
-                .find(|c| c.has_tag_name("trackBegin"))
-                .ok_or(DocErr::ElementMissing("trackBegin", topo.range().start))?,
-        )?,
-        end: parse_track_node(
-            &topo
-                .children()
-                .find(|c| c.has_tag_name("trackEnd"))
-                .ok_or(DocErr::ElementMissing("trackEnd", topo.range().start))?,
-        )?,
-        switches: parse_switches(&topo)?,
+        begin: parse_track_node(&begin)?,
+        end: parse_track_node(&end)?,
+        switches,
         track_elements: parse_track_elements(track, &topo)?,
         objects: parse_objects(track)?,
+        extras,
     })
 }
 
-fn parse_track_elements(track: &xml::Node, topo: &xml::Node) -> Result<TrackElements, DocErr> {
+fn parse_track_elements<N: XmlNode>(track: &N, topo: &N) -> Result<TrackElements, DocErr> {
     let mut res = TrackElements::empty();
     if let Some(cs) = topo.children().find(|c| c.has_tag_name("crossSections")) {
         for c in cs.children().filter(|c| c.has_tag_name("crossSection")) {
@@ -327,6 +752,26 @@ fn parse_track_elements(track: &xml::Node, topo: &xml::Node) -> Result<TrackElem
                 res.speed_changes.push(parse_speed_change(&s)?);
             }
         }
+        if let Some(gcs) = te.children().find(|c| c.has_tag_name("gradientChanges")) {
+            for g in gcs.children().filter(|c| c.has_tag_name("gradientChange")) {
+                res.gradient_changes.push(parse_gradient_change(&g)?);
+            }
+        }
+        if let Some(rcs) = te.children().find(|c| c.has_tag_name("radiusChanges")) {
+            for r in rcs.children().filter(|c| c.has_tag_name("radiusChange")) {
+                res.radius_changes.push(parse_radius_change(&r)?);
+            }
+        }
+        if let Some(ecs) = te.children().find(|c| c.has_tag_name("electrificationChanges")) {
+            for e in ecs.children().filter(|c| c.has_tag_name("electrificationChange")) {
+                res.electrification_changes.push(parse_electrification_change(&e)?);
+            }
+        }
+        if let Some(tcs) = te.children().find(|c| c.has_tag_name("trackConditions")) {
+            for t in tcs.children().filter(|c| c.has_tag_name("trackCondition")) {
+                res.track_conditions.push(parse_track_condition(&t)?);
+            }
+        }
         if let Some(lcs) = te.children().find(|c| c.has_tag_name("levelCrossings")) {
             for l in lcs.children().filter(|c| c.has_tag_name("levelCrossing")) {
                 res.level_crossings.push(parse_level_crossing(&l)?);
@@ -341,9 +786,9 @@ fn parse_track_elements(track: &xml::Node, topo: &xml::Node) -> Result<TrackElem
     Ok(res)
 }
 
-fn parse_cross_section(node: &xml::Node) -> Result<CrossSection, DocErr> {
+fn parse_cross_section<N: XmlNode>(node: &N) -> Result<CrossSection, DocErr> {
     Ok(CrossSection {
-        id: node.attribute("id").ok_or(DocErr::AttributeMissing("id", node.range().start))?.to_string(),
+        id: node.attribute("id").ok_or(DocErr::AttributeMissing("id", node.start()))?.to_string(),
         name: node.attribute("name").map(|x| x.to_string()),
         ocp_ref: node.attribute("ocpRef").map(|x| x.to_string()),
         pos: parse_position(node)?,
@@ -351,29 +796,29 @@ fn parse_cross_section(node: &xml::Node) -> Result<CrossSection, DocErr> {
     })
 }
 
-fn parse_platform_edge(node: &xml::Node) -> Result<PlatformEdge, DocErr> {
+fn parse_platform_edge<N: XmlNode>(node: &N) -> Result<PlatformEdge, DocErr> {
     Ok(PlatformEdge {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         name: node.attribute("name").map(|x| x.to_string()),
         pos: parse_position(node)?,
-        dir: parse_direction(node.attribute("dir"), node.range().start)?,
+        dir: parse_direction(node.attribute("dir"), node.start())?,
         side: node.attribute("side").map(|x| x.to_string()),
         height: node.attribute("height").and_then(|v| v.parse::<f64>().ok()),
         length: node.attribute("length").and_then(|v| v.parse::<f64>().ok()),
     })
 }
 
-fn parse_speed_change(node: &xml::Node) -> Result<SpeedChange, DocErr> {
+fn parse_speed_change<N: XmlNode>(node: &N) -> Result<SpeedChange, DocErr> {
     Ok(SpeedChange {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
-        dir: parse_direction(node.attribute("dir"), node.range().start)?,
+        dir: parse_direction(node.attribute("dir"), node.start())?,
         vmax: node.attribute("vMax").map(|s| s.to_string()),
         signalised: node
             .attribute("signalised")
@@ -382,11 +827,76 @@ fn parse_speed_change(node: &xml::Node) -> Result<SpeedChange, DocErr> {
     })
 }
 
-fn parse_level_crossing(node: &xml::Node) -> Result<LevelCrossing, DocErr> {
+fn parse_gradient_change<N: XmlNode>(node: &N) -> Result<GradientChange, DocErr> {
+    Ok(GradientChange {
+        id: node
+            .attribute("id")
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
+            .to_string(),
+        pos: parse_position(node)?,
+        dir: parse_direction(node.attribute("dir"), node.start())?,
+        slope: node
+            .attribute("slope")
+            .ok_or(DocErr::AttributeMissing("slope", node.start()))?
+            .parse()
+            .map_err(|_| DocErr::NumberError(node.start()))?,
+        // The attribute name is misspelled in the railML 2 schema.
+        transition_length: node.attribute("transitionLenght").and_then(|v| v.parse().ok()),
+        transition_radius: node.attribute("transitionRadius").and_then(|v| v.parse().ok()),
+    })
+}
+
+fn parse_radius_change<N: XmlNode>(node: &N) -> Result<RadiusChange, DocErr> {
+    Ok(RadiusChange {
+        id: node
+            .attribute("id")
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
+            .to_string(),
+        pos: parse_position(node)?,
+        dir: parse_direction(node.attribute("dir"), node.start())?,
+        radius: node
+            .attribute("radius")
+            .ok_or(DocErr::AttributeMissing("radius", node.start()))?
+            .parse()
+            .map_err(|_| DocErr::NumberError(node.start()))?,
+        superelevation: node.attribute("superelevation").and_then(|v| v.parse().ok()),
+    })
+}
+
+fn parse_electrification_change<N: XmlNode>(node: &N) -> Result<ElectrificationChange, DocErr> {
+    Ok(ElectrificationChange {
+        id: node
+            .attribute("id")
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
+            .to_string(),
+        pos: parse_position(node)?,
+        dir: node.attribute("dir").map(|s| s.to_string()),
+        electrification_type: node.attribute("type").map(|s| s.to_string()),
+        voltage: node.attribute("voltage").and_then(|v| v.parse().ok()),
+        frequency: node.attribute("frequency").and_then(|v| v.parse().ok()),
+        vmax: node.attribute("vMax").map(|s| s.to_string()),
+        isolated_section: node.attribute("isolatedSection").and_then(|v| v.parse::<bool>().ok()),
+    })
+}
+
+fn parse_track_condition<N: XmlNode>(node: &N) -> Result<TrackCondition, DocErr> {
+    Ok(TrackCondition {
+        id: node
+            .attribute("id")
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
+            .to_string(),
+        pos: parse_position(node)?,
+        dir: node.attribute("dir").map(|s| s.to_string()),
+        length: node.attribute("length").and_then(|v| v.parse().ok()),
+        condition_type: node.attribute("type").map(|s| s.to_string()),
+    })
+}
+
+fn parse_level_crossing<N: XmlNode>(node: &N) -> Result<LevelCrossing, DocErr> {
     Ok(LevelCrossing {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         protection: node.attribute("protection").map(|s| s.to_string()),
@@ -394,11 +904,11 @@ fn parse_level_crossing(node: &xml::Node) -> Result<LevelCrossing, DocErr> {
     })
 }
 
-fn parse_geo_mapping(node: &xml::Node) -> Result<GeoMapping, DocErr> {
+fn parse_geo_mapping<N: XmlNode>(node: &N) -> Result<GeoMapping, DocErr> {
     Ok(GeoMapping {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         name: node.attribute("name").map(|x| x.to_string()),
@@ -407,9 +917,10 @@ fn parse_geo_mapping(node: &xml::Node) -> Result<GeoMapping, DocErr> {
     })
 }
 
-fn parse_objects(track: &xml::Node) -> Result<Objects, DocErr> {
+fn parse_objects<N: XmlNode>(track: &N) -> Result<Objects, DocErr> {
     let mut signals = Vec::new();
     let mut balises = Vec::new();
+    let mut balise_groups = Vec::new();
     let mut train_detectors = Vec::new();
     let mut track_circuit_borders = Vec::new();
     let mut derailers = Vec::new();
@@ -440,6 +951,15 @@ fn parse_objects(track: &xml::Node) -> Result<Objects, DocErr> {
             for b in bs.children().filter(|c| c.has_tag_name("balise")) {
                 balises.push(parse_balise(&b)?);
             }
+            for g in bs.children().filter(|c| c.has_tag_name("baliseGroup")) {
+                let group = parse_balise_group(&g)?;
+                for b in g.children().filter(|c| c.has_tag_name("balise")) {
+                    let mut balise = parse_balise(&b)?;
+                    balise.group_ref = Some(group.id.clone());
+                    balises.push(balise);
+                }
+                balise_groups.push(group);
+            }
         }
         if let Some(der) = ocs.children().find(|c| c.has_tag_name("derailers")) {
             for d in der.children().filter(|c| c.has_tag_name("derailer")) {
@@ -467,6 +987,7 @@ fn parse_objects(track: &xml::Node) -> Result<Objects, DocErr> {
     Ok(Objects {
         signals,
         balises,
+        balise_groups,
         train_detectors,
         track_circuit_borders,
         derailers,
@@ -475,14 +996,13 @@ fn parse_objects(track: &xml::Node) -> Result<Objects, DocErr> {
     })
 }
 
-fn parse_signal(s: &xml::Node) -> Result<Signal, DocErr> {
+fn parse_signal<N: XmlNode>(s: &N) -> Result<Signal, DocErr> {
     let mut speeds = Vec::new();
     for sp in s.children().filter(|c| c.has_tag_name("speed")) {
         let speed_change_ref = sp
             .children()
             .find(|c| c.has_tag_name("speedChangeRef"))
-            .and_then(|c| c.attribute("ref"))
-            .map(|x| x.to_string());
+            .and_then(|c| c.attribute("ref").map(|x| x.to_string()));
         speeds.push(SignalSpeed {
             kind: sp.attribute("kind").map(|x| x.to_string()),
             train_relation: sp.attribute("trainRelation").map(|x| x.to_string()),
@@ -500,11 +1020,11 @@ fn parse_signal(s: &xml::Node) -> Result<Signal, DocErr> {
     Ok(Signal {
         id: s
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", s.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", s.start()))?
             .to_string(),
         pos: parse_position(s)?,
         name: s.attribute("name").map(|x| x.to_string()),
-        dir: parse_direction(s.attribute("dir"), s.range().start)?,
+        dir: parse_direction(s.attribute("dir"), s.start())?,
         sight: s.attribute("sight").and_then(|x| x.parse().ok()),
         r#type: match s.attribute("type") {
             Some("distant") => SignalType::Distant,
@@ -529,11 +1049,11 @@ fn parse_signal(s: &xml::Node) -> Result<Signal, DocErr> {
     })
 }
 
-fn parse_train_detector(node: &xml::Node) -> Result<TrainDetector, DocErr> {
+fn parse_train_detector<N: XmlNode>(node: &N) -> Result<TrainDetector, DocErr> {
     Ok(TrainDetector {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         axle_counting: node.attribute("axleCounting").and_then(|v| v.parse::<bool>().ok()),
@@ -544,43 +1064,43 @@ fn parse_train_detector(node: &xml::Node) -> Result<TrainDetector, DocErr> {
     })
 }
 
-fn parse_track_circuit_border(node: &xml::Node) -> Result<TrackCircuitBorder, DocErr> {
+fn parse_track_circuit_border<N: XmlNode>(node: &N) -> Result<TrackCircuitBorder, DocErr> {
     Ok(TrackCircuitBorder {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         insulated_rail: node.attribute("insulatedRail").map(|v| v.to_string()),
     })
 }
 
-fn parse_derailer(node: &xml::Node) -> Result<Derailer, DocErr> {
+fn parse_derailer<N: XmlNode>(node: &N) -> Result<Derailer, DocErr> {
     Ok(Derailer {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         dir: node
             .attribute("dir")
-            .map(|d| parse_direction(Some(d), node.range().start))
+            .map(|d| parse_direction(Some(d), node.start()))
             .transpose()?,
         derail_side: node.attribute("derailSide").map(|v| v.to_string()),
         code: node.attribute("code").map(|v| v.to_string()),
     })
 }
 
-fn parse_train_protection_element(node: &xml::Node) -> Result<TrainProtectionElement, DocErr> {
+fn parse_train_protection_element<N: XmlNode>(node: &N) -> Result<TrainProtectionElement, DocErr> {
     Ok(TrainProtectionElement {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         dir: node
             .attribute("dir")
-            .map(|d| parse_direction(Some(d), node.range().start))
+            .map(|d| parse_direction(Some(d), node.start()))
             .transpose()?,
         medium: node.attribute("medium").map(|v| v.to_string()),
         system: node
@@ -589,18 +1109,38 @@ fn parse_train_protection_element(node: &xml::Node) -> Result<TrainProtectionEle
     })
 }
 
-fn parse_balise(node: &xml::Node) -> Result<Balise, DocErr> {
+fn parse_balise<N: XmlNode>(node: &N) -> Result<Balise, DocErr> {
     Ok(Balise {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         name: node.attribute("name").map(|x| x.to_string()),
+        group_ref: None,
     })
 }
 
-fn parse_train_protection_group(node: &xml::Node) -> Result<TrainProtectionElementGroup, DocErr> {
+fn parse_balise_group<N: XmlNode>(node: &N) -> Result<BaliseGroup, DocErr> {
+    Ok(BaliseGroup {
+        id: node
+            .attribute("id")
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
+            .to_string(),
+        pos: parse_position(node)?,
+        name: node.attribute("name").map(|x| x.to_string()),
+        dir: node
+            .attribute("dir")
+            .map(|d| parse_direction(Some(d), node.start()))
+            .transpose()?,
+        country_id: node.attribute("countryID").map(|x| x.to_string()),
+        group_id: node.attribute("groupID").map(|x| x.to_string()),
+        ndx: node.attribute("ndx").and_then(|v| v.parse::<bool>().ok()),
+        linking_accuracy: node.attribute("linkingAccuracy").and_then(|v| v.parse().ok()),
+    })
+}
+
+fn parse_train_protection_group<N: XmlNode>(node: &N) -> Result<TrainProtectionElementGroup, DocErr> {
     let mut refs = Vec::new();
     for r in node
         .children()
@@ -613,36 +1153,61 @@ fn parse_train_protection_group(node: &xml::Node) -> Result<TrainProtectionEleme
     Ok(TrainProtectionElementGroup {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         element_refs: refs,
     })
 }
 
-fn parse_switches(topo: &xml::Node) -> Result<Vec<Switch>, DocErr> {
+fn parse_switches<N: XmlNode>(track_id: &str, topo: &N, extras: &mut Extras, report: &mut ParseReport) -> Vec<Switch> {
     let mut result = Vec::new();
     if let Some(connections) = topo.children().find(|c| c.has_tag_name("connections")) {
-        for conn_obj in connections.children().filter(|c| c.is_element()) {
-            if conn_obj.has_tag_name("switch") {
-                result.push(parse_switch(&conn_obj)?);
-            } else if conn_obj.has_tag_name("crossing") {
-                result.push(parse_crossing(&conn_obj)?);
-            } else {
-                return Err(DocErr::UnexpectedElement(
-                    format!("{:?}", conn_obj.tag_name()),
-                    conn_obj.range().start,
-                ));
+        parse_connections(track_id, &connections, &mut result, extras, report);
+    }
+    result
+}
+
+/// Switches and crossings in any order, also inside wrappers such as
+/// `<switchGroup>`. Other elements, and switches that cannot be read, are
+/// kept as extras of `<connections>` instead of failing the track.
+fn parse_connections<N: XmlNode>(
+    track_id: &str,
+    node: &N,
+    result: &mut Vec<Switch>,
+    extras: &mut Extras,
+    report: &mut ParseReport,
+) {
+    let is_switch = |c: &N| c.has_tag_name("switch") || c.has_tag_name("crossing");
+    for c in node.children() {
+        if is_switch(&c) {
+            let sw = if c.has_tag_name("switch") { parse_switch(&c) } else { parse_crossing(&c) };
+            match sw {
+                Ok(sw) => result.push(sw),
+                Err(e) => {
+                    let message = format!("<{}> in track \"{}\" was skipped: {:?}", c.tag_name(), track_id, e);
+                    report.warn(c.attribute("id").or(Some(track_id)), c.start(), message);
+                    extras.elements.push(("connections".to_string(), to_xml(&c)));
+                }
             }
+        } else if c.children().any(|x| is_switch(&x)) {
+            let message = format!(
+                "Switches in <{}> of track \"{}\" are read as if directly in <connections>.",
+                c.tag_name(),
+                track_id
+            );
+            report.warn(c.attribute("id").or(Some(track_id)), c.start(), message);
+            parse_connections(track_id, &c, result, extras, report);
+        } else {
+            extras.elements.push(("connections".to_string(), to_xml(&c)));
         }
     }
-    Ok(result)
 }
 
-fn parse_switch(sw: &xml::Node) -> Result<Switch, DocErr> {
+fn parse_switch<N: XmlNode>(sw: &N) -> Result<Switch, DocErr> {
     Ok(Switch::Switch {
         id: sw
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", sw.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", sw.start()))?
             .to_string(),
         pos: parse_position(sw)?,
         name: sw.attribute("name").map(|x| x.to_string()),
@@ -651,66 +1216,66 @@ fn parse_switch(sw: &xml::Node) -> Result<Switch, DocErr> {
             Some(length) => Some(
                 length
                     .parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(sw.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(sw.start()))?,
             ),
             None => None,
         },
         connections: parse_switch_connections(sw)?,
         track_continue_course: match sw.attribute("trackContinueCourse") {
-            Some(course) => Some(parse_course(course, sw.range().start)?),
+            Some(course) => Some(parse_course(course, sw.start())?),
             None => None,
         },
         track_continue_radius: match sw.attribute("trackContinueRadius") {
             Some(rad) => Some(
                 rad.parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(sw.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(sw.start()))?,
             ),
             None => None,
         },
     })
 }
 
-fn parse_switch_connections(sw: &xml::Node) -> Result<Vec<SwitchConnection>, DocErr> {
+fn parse_switch_connections<N: XmlNode>(sw: &N) -> Result<Vec<SwitchConnection>, DocErr> {
     let mut result = Vec::new();
     for c in sw
         .children()
-        .filter(|x| x.is_element() && x.has_tag_name("connection"))
+        .filter(|x| x.has_tag_name("connection"))
     {
         result.push(parse_switch_connection(&c)?);
     }
     Ok(result)
 }
 
-fn parse_switch_connection(c: &xml::Node) -> Result<SwitchConnection, DocErr> {
+fn parse_switch_connection<N: XmlNode>(c: &N) -> Result<SwitchConnection, DocErr> {
     Ok(SwitchConnection {
         id: c
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", c.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", c.start()))?
             .to_string(),
         r#ref: c
             .attribute("ref")
-            .ok_or(DocErr::AttributeMissing("ref", c.range().start))?
+            .ok_or(DocErr::AttributeMissing("ref", c.start()))?
             .to_string(),
         orientation: parse_orientation(
             c.attribute("orientation")
-                .ok_or(DocErr::AttributeMissing("orientation", c.range().start))?,
-            c.range().start,
+                .ok_or(DocErr::AttributeMissing("orientation", c.start()))?,
+            c.start(),
         )?,
         course: match c.attribute("course") {
-            Some(course) => Some(parse_course(course, c.range().start)?),
+            Some(course) => Some(parse_course(course, c.start())?),
             None => None,
         },
         radius: match c.attribute("radius") {
             Some(rad) => Some(
                 rad.parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(c.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(c.start()))?,
             ),
             None => None,
         },
         max_speed: match c.attribute("maxSpeed") {
             Some(rad) => Some(
                 rad.parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(c.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(c.start()))?,
             ),
             None => None,
         },
@@ -718,7 +1283,7 @@ fn parse_switch_connection(c: &xml::Node) -> Result<SwitchConnection, DocErr> {
             Some(passable) => Some(
                 passable
                     .parse::<bool>()
-                    .map_err(|_e| DocErr::BoolError(c.range().start))?,
+                    .map_err(|_e| DocErr::BoolError(c.start()))?,
             ),
             None => None,
         },
@@ -742,33 +1307,34 @@ fn parse_orientation(x: &str, pos: usize) -> Result<ConnectionOrientation, DocEr
     }
 }
 
-fn parse_crossing(sw: &xml::Node) -> Result<Switch, DocErr> {
+fn parse_crossing<N: XmlNode>(sw: &N) -> Result<Switch, DocErr> {
     Ok(Switch::Crossing {
         id: sw
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", sw.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", sw.start()))?
             .to_string(),
         pos: parse_position(sw)?,
+        crossing_type: sw.attribute("type").map(|t| t.to_string()),
         track_continue_course: match sw.attribute("trackContinueCourse") {
-            Some(course) => Some(parse_course(course, sw.range().start)?),
+            Some(course) => Some(parse_course(course, sw.start())?),
             None => None,
         },
         track_continue_radius: match sw.attribute("trackContinueRadius") {
             Some(rad) => Some(
                 rad.parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(sw.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(sw.start()))?,
             ),
             None => None,
         },
         normal_position: match sw.attribute("normalPosition") {
-            Some(course) => Some(parse_course(course, sw.range().start)?),
+            Some(course) => Some(parse_course(course, sw.start())?),
             None => None,
         },
         length: match sw.attribute("length") {
             Some(length) => Some(
                 length
                     .parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(sw.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(sw.start()))?,
             ),
             None => None,
         },
@@ -776,25 +1342,25 @@ fn parse_crossing(sw: &xml::Node) -> Result<Switch, DocErr> {
     })
 }
 
-fn parse_track_node(node: &xml::Node) -> Result<Node, DocErr> {
+fn parse_track_node<N: XmlNode>(node: &N) -> Result<Node, DocErr> {
     Ok(Node {
         id: node
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("id", node.start()))?
             .to_string(),
         pos: parse_position(node)?,
         connection: parse_track_connection(node)?,
     })
 }
 
-fn parse_track_connection(node: &xml::Node) -> Result<TrackEndConnection, DocErr> {
+fn parse_track_connection<N: XmlNode>(node: &N) -> Result<TrackEndConnection, DocErr> {
     if let Some(e) = node.children().find(|c| c.has_tag_name("connection")) {
         let id = e
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", e.range().start))?;
+            .ok_or(DocErr::AttributeMissing("id", e.start()))?;
         let idref = e
             .attribute("ref")
-            .ok_or(DocErr::AttributeMissing("ref", e.range().start))?;
+            .ok_or(DocErr::AttributeMissing("ref", e.start()))?;
         return Ok(TrackEndConnection::Connection(id.to_string(), idref.to_string()));
     }
     if let Some(_e) = node.children().find(|c| c.has_tag_name("bufferStop")) {
@@ -806,35 +1372,35 @@ fn parse_track_connection(node: &xml::Node) -> Result<TrackEndConnection, DocErr
     if let Some(e) = node.children().find(|c| c.has_tag_name("macroscopicNode")) {
         let id = e
             .attribute("id")
-            .ok_or(DocErr::AttributeMissing("id", e.range().start))?;
-        return Ok(TrackEndConnection::MacroscopicNode(id.to_string()));
+            .ok_or(DocErr::AttributeMissing("id", e.start()))?;
+        let ocp_ref = e.attribute("ocpRef").map(|x| x.to_string());
+        return Ok(TrackEndConnection::MacroscopicNode(id.to_string(), ocp_ref));
     }
     Err(DocErr::ElementMissing(
         "connection or bufferStop or openEnd or macroscopicNode",
-        node.range().start,
+        node.start(),
     ))
 }
 
-fn parse_position(node: &xml::Node) -> Result<Position, DocErr> {
+fn parse_position<N: XmlNode>(node: &N) -> Result<Position, DocErr> {
     Ok(Position {
         offset: node
             .attribute("pos")
-            .ok_or(DocErr::AttributeMissing("pos", node.range().start))?
+            .ok_or(DocErr::AttributeMissing("pos", node.start()))?
             .parse::<f64>()
-            .map_err(|_e| DocErr::NumberError(node.range().start))?,
+            .map_err(|_e| DocErr::NumberError(node.start()))?,
         mileage: match node.attribute("absPos") {
             Some(abs_pos) => Some(
                 abs_pos
                     .parse::<f64>()
-                    .map_err(|_e| DocErr::NumberError(node.range().start))?,
+                    .map_err(|_e| DocErr::NumberError(node.start()))?,
             ),
             None => None,
         },
         geo_coord: node
             .children()
             .find(|c| c.has_tag_name("geoCoord"))
-            .and_then(|c| c.attribute("coord"))
-            .map(|x| x.to_string())
+            .and_then(|c| c.attribute("coord").map(|x| x.to_string()))
             .or_else(|| node.attribute("geoCoord").map(|x| x.to_string())),
     })
 }
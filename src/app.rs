@@ -1,37 +1,213 @@
 use crate::document::Document;
+use crate::document::model::Model;
 use crate::config::Config;
 use crate::gui::windows::logview::LogStore;
 use crate::import;
 use crate::gui;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PendingAction {
     New,
+    NewFromTemplate(usize),
     Load,
+    LoadFile(String),
+    OpenUrl(String),
     Import,
+    ImportFile(String),
+    ImportUrl(String),
+    ReloadSource,
+    /// Import a file with a plugin, as (plugin index, importer index).
+    PluginImport(usize, usize),
+    CloseTab,
     Quit,
 }
 
 pub struct App {
+    /// The document in the active tab.
     pub document :Document,
+    pub tabs :DocumentTabs,
+    /// Copied part of a model, which can be pasted into any open document.
+    pub clipboard :Model,
     pub config :Config,
     pub log :LogStore,
     pub windows: Windows,
     pub background_jobs :BackgroundJobs,
+    /// Project opened as the workspace, if any.
+    pub project :Option<crate::project::OpenProject>,
+    pub plugins :crate::plugins::Plugins,
+    /// HTTP/WebSocket API server, when enabled.
+    pub server :Option<crate::server::ApiServer>,
+    pub feed :Option<crate::feed::Feed>,
     //    - TODO set window name
     //    - TODO font / font size?
 }
 
+impl App {
+    pub fn start_server(&mut self) {
+        let port = match std::convert::TryFrom::try_from(self.config.api_port) {
+            Ok(port) => port,
+            Err(_) => { log::error!("Invalid API server port {}", self.config.api_port); return; },
+        };
+        match crate::server::ApiServer::start(port, self.config.api_origins.clone()) {
+            Ok(s) => { self.server = Some(s); },
+            Err(e) => { log::error!("Could not start API server on port {}: {}", self.config.api_port, e); },
+        }
+    }
+
+    pub fn start_feed(&mut self) {
+        self.feed = Some(crate::feed::Feed::start(&self.config.feed));
+    }
+}
+
+/// The documents open in other tabs than the active one, which is
+/// `App::document`. Switching tabs swaps the documents, so the rest of the
+/// program only sees the active document.
+pub struct DocumentTabs {
+    /// Inactive documents in tab order.
+    pub others :Vec<Document>,
+    /// Index of the active tab among all tabs.
+    pub active :usize,
+    /// Set when the active tab is changed by the program instead of by
+    /// clicking the tab bar, which then needs to select it.
+    pub changed :bool,
+}
+
+impl DocumentTabs {
+    pub fn new() -> Self {
+        DocumentTabs { others: Vec::new(), active: 0, changed: false }
+    }
+
+    pub fn len(&self) -> usize { self.others.len() + 1 }
+
+    /// The document in the given tab, where `current` is the active document.
+    pub fn get<'a>(&'a self, current :&'a Document, tab :usize) -> &'a Document {
+        if tab == self.active { current }
+        else if tab < self.active { &self.others[tab] }
+        else { &self.others[tab - 1] }
+    }
+
+    /// Open a document in a new tab after the active one, and make it active.
+    pub fn open(&mut self, current :&mut Document, doc :Document) {
+        let old = std::mem::replace(current, doc);
+        self.others.insert(self.active, old);
+        self.active += 1;
+        self.changed = true;
+    }
+
+    /// Make the given tab active.
+    pub fn select(&mut self, current :&mut Document, tab :usize) {
+        if tab == self.active || tab >= self.len() { return; }
+        let doc = self.others.remove(if tab > self.active { tab - 1 } else { tab });
+        let old = std::mem::replace(current, doc);
+        self.others.insert(if tab > self.active { self.active } else { self.active - 1 }, old);
+        self.active = tab;
+        self.changed = true;
+    }
+
+    /// Close the active tab, making the next one active. Closing the only
+    /// tab leaves an empty document.
+    pub fn close_active(&mut self, current :&mut Document, bg :BackgroundJobs) {
+        if self.others.is_empty() {
+            *current = Document::empty(bg);
+        } else {
+            let tab = self.active.min(self.others.len() - 1);
+            *current = self.others.remove(tab);
+            self.active = tab;
+        }
+        self.changed = true;
+    }
+
+    /// An inactive tab with unsaved changes.
+    pub fn unsaved_tab(&self) -> Option<usize> {
+        let i = self.others.iter().position(|d| d.fileinfo.unsaved)?;
+        Some(if i >= self.active { i + 1 } else { i })
+    }
+}
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::collections::BinaryHeap;
+
+/// Priority of a background job. Queued jobs with higher priority are
+/// started first, jobs with equal priority in the order they were added.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// Shared flag for canceling a background job. A canceled job is skipped
+/// if it has not started yet, and running jobs can poll `is_canceled` to
+/// stop early.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self { Default::default() }
+    pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst); }
+    pub fn is_canceled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+}
+
+struct Job {
+    priority :Priority,
+    seq :usize,
+    cancel :CancelToken,
+    job :Box<dyn FnOnce() + Send + 'static>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other :&Job) -> bool { self.priority == other.priority && self.seq == other.seq }
+}
+impl Eq for Job {}
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other :&Job) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) }
+}
+impl Ord for Job {
+    fn cmp(&self, other :&Job) -> std::cmp::Ordering {
+        // Max-heap: higher priority first, then lower sequence number.
+        self.priority.cmp(&other.priority).then(other.seq.cmp(&self.seq))
+    }
+}
+
 #[derive(Clone)]
-/// Wrapper for thread pool.
-pub struct BackgroundJobs(threadpool::ThreadPool);
+/// Wrapper for thread pool, with a priority queue in front of it.
+pub struct BackgroundJobs {
+    pool :threadpool::ThreadPool,
+    queue :Arc<Mutex<BinaryHeap<Job>>>,
+    seq :Arc<AtomicUsize>,
+}
 
 impl BackgroundJobs {
-    pub fn new() -> Self { BackgroundJobs(threadpool::ThreadPool::new(2)) }
+    pub fn new() -> Self { 
+        BackgroundJobs {
+            pool: threadpool::ThreadPool::new(2),
+            queue: Arc::new(Mutex::new(BinaryHeap::new())),
+            seq: Arc::new(AtomicUsize::new(0)),
+        }
+    }
 
     /// Run the given function as a background job.
     pub fn execute(&mut self, job: impl FnOnce() + Send + 'static) {
-        self.0.execute(job)
+        self.execute_with(Priority::Normal, CancelToken::new(), job);
+    }
+
+    /// Run the given function as a background job with the given priority.
+    /// The job is dropped without running if `cancel` is triggered before
+    /// a worker thread picks it up.
+    pub fn execute_with(&mut self, priority :Priority, cancel :CancelToken, 
+                        job: impl FnOnce() + Send + 'static) {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        self.queue.lock().unwrap().push(Job { priority, seq, cancel, job: Box::new(job) });
+        // Each pool task runs the most important queued job, which is not
+        // necessarily the one pushed above.
+        let queue = self.queue.clone();
+        self.pool.execute(move || {
+            let next = queue.lock().unwrap().pop();
+            if let Some(Job { cancel, job, .. }) = next {
+                if !cancel.is_canceled() { job(); }
+            }
+        });
     }
 }
 
@@ -41,9 +217,30 @@ pub struct Windows {
     pub log: bool,
     pub pending_action: Option<PendingAction>,
     pub vehicles: bool,
+    pub stats: bool,
+    pub detector_placement: bool,
+    pub project: bool,
     pub diagram_split :Option<f32>,
     pub import_window :import::ImportWindow,
     pub synthesis_window :Option<gui::windows::synthesis::SynthesisWindow>,
+    pub lint :gui::windows::lint::LintWindow,
+    pub path_query :gui::windows::paths::PathQueryWindow,
+    pub file_change :gui::windows::filechange::FileChangeWindow,
+    pub download :gui::windows::download::DownloadWindow,
+    pub scripts :gui::windows::scripts::ScriptWindow,
+    pub object_tree :gui::windows::objecttree::ObjectTreeWindow,
+    pub merge :gui::windows::merge::MergeWindow,
+    pub export_report :gui::windows::exportreport::ExportReportWindow,
+    pub print :gui::windows::print::PrintWindow,
+    pub export_scope :gui::windows::exportscope::ExportScopeWindow,
+    pub groups :gui::windows::groups::GroupsWindow,
+    pub search :gui::windows::search::SearchWindow,
+    pub track_properties :gui::windows::trackprops::TrackPropertiesWindow,
+    pub palette :gui::palette::CommandPalette,
+    /// Name entered for a new view bookmark.
+    pub bookmark_name :String,
+    /// Folder of the crash report from the previous run, not yet shown.
+    pub crash_report :Option<String>,
 }
 
 impl Windows {
@@ -54,11 +251,30 @@ impl Windows {
             log: false,
             pending_action: None,
             vehicles: false,
+            stats: false,
+            detector_placement: false,
+            project: false,
 
             diagram_split: None,
 
             import_window: import::ImportWindow::new(bg),
             synthesis_window: None,
+            lint: gui::windows::lint::LintWindow::new(),
+            path_query: gui::windows::paths::PathQueryWindow::new(),
+            file_change: gui::windows::filechange::FileChangeWindow::new(),
+            download: gui::windows::download::DownloadWindow::new(),
+            scripts: gui::windows::scripts::ScriptWindow::new(),
+            object_tree: gui::windows::objecttree::ObjectTreeWindow::new(),
+            merge: gui::windows::merge::MergeWindow::new(),
+            export_report: gui::windows::exportreport::ExportReportWindow::new(),
+            print: gui::windows::print::PrintWindow::new(),
+            export_scope: gui::windows::exportscope::ExportScopeWindow::new(),
+            groups: gui::windows::groups::GroupsWindow::new(),
+            search: gui::windows::search::SearchWindow::new(),
+            track_properties: gui::windows::trackprops::TrackPropertiesWindow::new(),
+            palette: gui::palette::CommandPalette::new(),
+            bookmark_name: String::new(),
+            crash_report: None,
         }
     }
 }
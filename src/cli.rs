@@ -0,0 +1,337 @@
+//! Commands that run without the user interface, so that infrastructure
+//! files can be processed in scripts and CI pipelines. They are given as
+//! the first argument, as in `junction convert station.railml station.junc`,
+//! and return the exit code of the process.
+
+use std::sync::mpsc;
+
+use crate::document;
+use crate::document::model::Model;
+use crate::export::{self, RailMLVersion};
+use crate::file;
+use crate::import::{self, ImportJob, ImportState, SolverGoal, SolverSettings};
+
+pub const USAGE :&str = "usage:
+  junction [<document>]
+  junction convert <input.railml|.osm.pbf|.json|.geojson> <output.junc> [layout options]
+  junction layout <model.junc> <output.junc> [layout options]
+  junction export <model.junc> <output.railml|.xml|.dxf|.geojson> [--railml3]
+  junction validate <input.railml|model.junc>
+  junction diff <a.railml> <b.railml>
+  junction merge <base.junc> <ours.junc> <theirs.junc> <output.junc>
+  junction dump-json <model.junc> <output.json>
+layout options:
+  --solver=<goals>   solver goals in order of priority, separated by commas:
+                     bends, height, width, localx, localy
+  --time-limit=<s>   seconds before the solver is given up for a simpler layout
+  --distinct-nodes   place nodes at distinct horizontal positions
+  --no-auto-scale    keep the size of small layouts
+  --embed-source     keep a copy of the railML file in the document";
+
+/// Run the command given on the command line. Returns None if the
+/// arguments are not a command, and the GUI should be started instead.
+pub fn run(args :&[String]) -> Option<i32> {
+    let command = args.get(1)?;
+    if command == "help" {
+        println!("{}", USAGE);
+        return Some(0);
+    }
+    if !["convert", "layout", "export", "validate", "diff", "merge", "dump-json"].contains(&command.as_str()) {
+        return None;
+    }
+    let (files, options) :(Vec<&String>, Vec<&String>) = args[2..].iter().partition(|x| !x.starts_with("--"));
+    let result = match (command.as_str(), files.as_slice()) {
+        ("convert", [input, output]) => Options::parse(&options).and_then(|o| convert(input, output, &o)),
+        ("layout", [input, output]) => Options::parse(&options).and_then(|o| layout(input, output, &o)),
+        ("export", [input, output]) => export(input, output, options.iter().any(|x| *x == "--railml3")),
+        ("validate", [input]) => return Some(validate(input)),
+        ("diff", [a, b]) => return Some(diff_railml(a, b)),
+        ("merge", [base, ours, theirs, output]) => return Some(merge(base, ours, theirs, output)),
+        ("dump-json", [input, output]) => dump_json(input, output),
+        _ => Err(Error::Usage(format!("wrong number of files for {}", command))),
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(Error::Usage(e)) => {
+            eprintln!("junction {}: {}", command, e);
+            eprintln!("{}", USAGE);
+            Some(2)
+        },
+        Err(Error::Failed(e)) => {
+            eprintln!("junction {}: {}", command, e);
+            Some(1)
+        },
+    }
+}
+
+enum Error {
+    /// The arguments are wrong, and the usage is printed.
+    Usage(String),
+    Failed(String),
+}
+
+impl From<String> for Error {
+    fn from(e :String) -> Error { Error::Failed(e) }
+}
+
+/// Options of the import and layout.
+struct Options {
+    solver :SolverSettings,
+    auto_scale :bool,
+    embed_source :bool,
+}
+
+impl Options {
+    fn parse(args :&[&String]) -> Result<Options, Error> {
+        let mut options = Options { solver: SolverSettings::default(), auto_scale: true, embed_source: false };
+        for arg in args {
+            let (name, value) = match arg.find('=') {
+                Some(i) => (&arg[..i], Some(&arg[i+1..])),
+                None => (arg.as_str(), None),
+            };
+            match (name, value) {
+                ("--solver", Some(goals)) => {
+                    let mut used = Vec::new();
+                    for name in goals.split(',').map(|g| g.trim().to_lowercase()) {
+                        used.push(match name.as_str() {
+                            "bends" => SolverGoal::Bends,
+                            "height" => SolverGoal::Height,
+                            "width" => SolverGoal::Width,
+                            "localx" => SolverGoal::LocalX,
+                            "localy" => SolverGoal::LocalY,
+                            _ => return Err(Error::Usage(format!("unknown solver goal {:?}", name))),
+                        });
+                    }
+                    // Goals that are not given are kept after the others, unused.
+                    let unused = options.solver.goals.iter().map(|(g,_)| *g).filter(|g| !used.contains(g)).collect::<Vec<_>>();
+                    options.solver.goals = used.into_iter().map(|g| (g, true))
+                        .chain(unused.into_iter().map(|g| (g, false))).collect();
+                },
+                ("--time-limit", Some(s)) => {
+                    options.solver.time_limit = s.parse()
+                        .map_err(|_| Error::Usage(format!("time limit {:?} is not a number", s)))?;
+                },
+                ("--distinct-nodes", None) => options.solver.nodes_distinct = true,
+                ("--no-auto-scale", None) => options.auto_scale = false,
+                ("--embed-source", None) => options.embed_source = true,
+                _ => return Err(Error::Usage(format!("unknown option {:?}", arg))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Run an import in a thread of its own, printing its progress, as the
+/// import window does in the GUI.
+fn run_import(import :impl FnOnce(ImportJob) + Send + 'static, solver :SolverSettings) -> Result<Model, String> {
+    let (tx, rx) = mpsc::channel();
+    let job = ImportJob::new(tx).with_solver(solver);
+    std::thread::spawn(move || import(job));
+    let mut stage = None;
+    while let Ok(state) = rx.recv() {
+        match state {
+            ImportState::Progress(s, _, _) => {
+                if stage != Some(s) { eprintln!("{}", s.description()); }
+                stage = Some(s);
+            },
+            ImportState::Available(model) => return Ok(model),
+            ImportState::SourceFileError(e) | ImportState::PlotError(e) => return Err(e),
+            ImportState::ValidationFailed(issues) => {
+                for issue in &issues {
+                    eprintln!("{}: {}: {}", issue.severity.name(), issue.id.as_deref().unwrap_or("-"), issue.message);
+                }
+                return Err("the railML document has errors".to_string());
+            },
+            _ => {},
+        }
+    }
+    Err("the import stopped".to_string())
+}
+
+fn save(filename :&str, model :Model) -> Result<(), String> {
+    file::save(filename, model, 0).map_err(|e| format!("could not write {:?}: {}", filename, e))?;
+    eprintln!("Saved {:?}", filename);
+    Ok(())
+}
+
+fn load(filename :&str) -> Result<Model, String> {
+    file::load(filename).map_err(|e| format!("could not read {:?}: {}", filename, e))
+}
+
+fn is_railml_file(filename :&str) -> bool {
+    let lower = filename.to_lowercase();
+    [".railml", ".xml", ".gz", ".zip"].iter().any(|ext| lower.ends_with(ext))
+}
+
+/// Import a railML, OpenStreetMap or GeoJSON file and save it as a document.
+fn convert(input :&str, output :&str, options :&Options) -> Result<(), Error> {
+    let (filename, auto_scale, embed) = (input.to_string(), options.auto_scale, options.embed_source);
+    let model = if input.to_lowercase().ends_with(".geojson") {
+        let text = std::fs::read_to_string(input).map_err(|e| format!("could not read {:?}: {}", input, e))?;
+        crate::geojson::import_geojson(&text)?
+    } else if import::is_osm_file(input) {
+        run_import(move |job| import::load_osm_file(filename, job, auto_scale), options.solver.clone())?
+    } else {
+        run_import(move |job| import::load_railml_file(filename, job, auto_scale, embed, None),
+                   options.solver.clone())?
+    };
+    Ok(save(output, model)?)
+}
+
+/// Lay out a document again from the railML file it was imported from and
+/// keeps a copy of, for example with other solver goals.
+fn layout(input :&str, output :&str, options :&Options) -> Result<(), Error> {
+    let model = load(input)?;
+    let source = model.railml_source.clone()
+        .ok_or_else(|| format!("{:?} has no copy of its railML file, import it with --embed-source", input))?;
+    let auto_scale = options.auto_scale;
+    let model = run_import(move |job| import::load_railml_bundle(source, job, auto_scale, true),
+                           options.solver.clone())?;
+    Ok(save(output, model)?)
+}
+
+/// Export a document to railML, DXF or GeoJSON, chosen by the extension of
+/// the output file.
+fn export(input :&str, output :&str, railml3 :bool) -> Result<(), Error> {
+    let model = load(input)?;
+    let lower = output.to_lowercase();
+    let result = if lower.ends_with(".dxf") {
+        crate::dxf::export_dxf_to_file(output, &model)
+    } else if lower.ends_with(".geojson") {
+        crate::geojson::export_geojson_to_file(output, &model)
+    } else {
+        let version = if railml3 { RailMLVersion::V3 } else { RailMLVersion::V25 };
+        export::export_railml_to_file(output, &model, version).map(|report| {
+            if report.changed().next().is_some() || !report.lost.is_empty() {
+                eprintln!("{} imported ids were changed and {} lost", report.changed().count(), report.lost.len());
+            }
+        })
+    };
+    result.map_err(|e| format!("could not write {:?}: {}", output, e))?;
+    eprintln!("Exported {:?}", output);
+    Ok(())
+}
+
+/// Write a document in the JSON dump format, for comparing documents with
+/// text tools.
+fn dump_json(input :&str, output :&str) -> Result<(), Error> {
+    let model = load(input)?;
+    file::dump_json(output, &model).map_err(|e| format!("could not write {:?}: {}", output, e))?;
+    Ok(())
+}
+
+/// Validate a railML file, or load a document, derive the interlocking and
+/// print the lint issues. Returns the exit code, nonzero if the file could
+/// not be read or has errors.
+fn validate(filename :&str) -> i32 {
+    if is_railml_file(filename) { validate_railml(filename) } else { validate_model(filename) }
+}
+
+fn validate_railml(filename :&str) -> i32 {
+    let source = match import::RailMLSource::open(filename) {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("Failed to load {:?}: {}", filename, err);
+            return 1;
+        }
+    };
+    let text = match source.as_str() {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("Failed to load {:?}: {}", filename, err);
+            return 1;
+        }
+    };
+    let railml = match railmlio::stream::parse_railml_reader(text.as_bytes()) {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            return 1;
+        }
+    };
+    let mut issues = railmlio::validate::validate(&railml);
+    railmlio::validate::locate(&mut issues, text);
+    let line = |offset :usize| text[..offset.min(text.len())].matches('\n').count() + 1;
+    for issue in &issues {
+        let location = issue.byte_offset.map(|o| format!(":{}", line(o))).unwrap_or_default();
+        println!("{}{}: {}: {}", filename, location, issue.severity.name(), issue.message);
+    }
+    let errors = issues.iter().filter(|i| i.severity == railmlio::validate::Severity::Error).count();
+    println!("{}: {} issues, {} errors", filename, issues.len(), errors);
+    if errors > 0 { 1 } else { 0 }
+}
+
+fn validate_model(filename :&str) -> i32 {
+    let model = match file::load(filename) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("Failed to load {:?}: {}", filename, err);
+            return 1;
+        }
+    };
+    let issues = match document::lint::validate(&model) {
+        Ok(issues) => issues,
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            return 1;
+        }
+    };
+    for issue in &issues {
+        let pos = issue.pos();
+        println!("{}: {}: {} at ({:.1}, {:.1})", filename, issue.severity.name(),
+                 issue.message, pos.x, pos.y);
+    }
+    let errors = issues.iter().filter(|i| i.severity == document::lint::Severity::Error).count();
+    println!("{}: {} issues, {} errors", filename, issues.len(), errors);
+    if errors > 0 { 1 } else { 0 }
+}
+
+/// Three-way merge of documents, e.g. as a git merge driver. Conflicts keep
+/// our version and are listed. Returns the exit code, nonzero if there were
+/// conflicts or a file could not be read or written.
+fn merge(base :&str, ours :&str, theirs :&str, output :&str) -> i32 {
+    let mut models = Vec::new();
+    for filename in &[base, ours, theirs] {
+        match file::load(filename) {
+            Ok(m) => models.push(m),
+            Err(err) => {
+                eprintln!("Failed to load {:?}: {}", filename, err);
+                return 1;
+            }
+        }
+    }
+    let result = document::merge::merge(&models[0], &models[1], &models[2]);
+    for conflict in &result.conflicts {
+        println!("Conflict: {} (ours: {}, theirs: {})", conflict.describe(),
+                 conflict.describe_version(document::merge::Choice::Ours),
+                 conflict.describe_version(document::merge::Choice::Theirs));
+    }
+    if let Err(err) = file::save(output, result.model, 0) {
+        eprintln!("Failed to write {:?}: {}", output, err);
+        return 1;
+    }
+    if result.conflicts.is_empty() { 0 } else { 1 }
+}
+
+/// Compare two railML documents element by element and print the
+/// differences. Returns the exit code, as for diff: zero if the documents
+/// are the same, 1 if they differ and 2 if a file could not be read.
+fn diff_railml(a :&str, b :&str) -> i32 {
+    let mut docs = Vec::new();
+    for filename in &[a, b] {
+        let parsed = import::RailMLSource::open(filename)
+            .and_then(|s| railmlio::stream::parse_railml_reader(s.as_str()?.as_bytes()).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(doc) => docs.push(doc),
+            Err(err) => {
+                eprintln!("Failed to load {:?}: {}", filename, err);
+                return 2;
+            }
+        }
+    }
+    let diffs = railmlio::diff::diff(&docs[0], &docs[1]);
+    for d in &diffs {
+        println!("{}", d);
+    }
+    if diffs.is_empty() { 0 } else { 1 }
+}
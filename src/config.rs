@@ -8,6 +8,10 @@ use num_derive::FromPrimitive;
 use log::*;
 use enum_map::{enum_map, Enum, EnumMap};
 use serde::{Serialize, Deserialize};
+use crate::units::*;
+use crate::feed::FeedSettings;
+use crate::import::SolverSettings;
+use crate::print::PrintSettings;
 
 type Color = palette::rgb::Rgba;
 
@@ -76,9 +80,11 @@ lazy_static! {
                 RailUIColorName::CanvasTVDFree => const_cstr!("Canvas TVD free"),
                 RailUIColorName::CanvasTVDOccupied => const_cstr!("Canvas TVD occupied"),
                 RailUIColorName::CanvasTVDReserved => const_cstr!("Canvas TVD reserved"),
+                RailUIColorName::CanvasTVDReleased => const_cstr!("Canvas TVD released"),
                 RailUIColorName::CanvasRoutePath => const_cstr!("Canvas route path"),
                 RailUIColorName::CanvasRouteSection => const_cstr!("Canvas route section"),
                 RailUIColorName::CanvasSelectionWindow => const_cstr!("Canvas selection window"),
+                RailUIColorName::CanvasCatenary => const_cstr!("Canvas catenary"),
                 RailUIColorName::GraphBackground => const_cstr!("Graph background"),
                 RailUIColorName::GraphTimeSlider => const_cstr!("Graph time slider"),
                 RailUIColorName::GraphTimeSliderText => const_cstr!("Graph time slider text"),
@@ -98,16 +104,148 @@ lazy_static! {
 #[derive(Debug)]
 pub struct Config {
     pub colors :EnumMap<RailUIColorName,Color>,
+    /// Design speed (km/h) used for checking signal spacing.
+    pub design_speed :f32,
+    /// Service brake deceleration (m/s^2) used for checking signal spacing.
+    pub design_deceleration :f32,
+    pub show_signal_spacing :bool,
+    /// Draw the catenary along electrified tracks.
+    pub show_catenary :bool,
+    /// Draw the 45° corners of tracks as curves, on screen and in prints.
+    pub smooth_curves :bool,
+    /// Units for lengths and speeds shown in the user interface and reports.
+    pub units :Units,
+    /// Number of undo steps kept in memory, 0 for unlimited.
+    pub undo_depth :i32,
+    /// Number of previous versions kept as backups when saving a document.
+    pub backup_count :i32,
+    /// Number of undo and redo steps saved in the document file, 0 for none.
+    pub saved_undo_steps :i32,
+    /// TCP port of the HTTP API server. WebSocket clients connect to the next port.
+    pub api_port :i32,
+    /// Origins of web pages allowed to use the API server, like
+    /// "http://localhost:8080". Programs outside a browser are always allowed.
+    pub api_origins :Vec<String>,
+    /// Where the live train feed is sent during dispatch playback.
+    pub feed :FeedSettings,
+    /// Language code of the user interface, see `i18n::languages`.
+    pub language :String,
+    /// Keyboard shortcuts, None for commands without a key.
+    pub keys :EnumMap<KeyCommand, Option<KeyBinding>>,
+    /// Recently opened/saved documents and imported railML files, most recent first.
+    pub recent_files :Vec<RecentFile>,
+    /// Goals and time limit of the layout solver used when importing railML.
+    pub solver :SolverSettings,
+    /// Page and title block of printed schematics.
+    pub print :PrintSettings,
+    /// The canvas grid, and snapping of inserted objects to it.
+    pub grid :GridSettings,
 }
 
+/// Number of unpinned entries kept in the recent files list.
+pub const MAX_RECENT_FILES :usize = 10;
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecentFileKind {
+    Document,
+    RailML,
+}
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentFile {
+    pub path :String,
+    pub kind :RecentFileKind,
+    /// Pinned entries are never pushed out of the list.
+    #[serde(default)]
+    pub pinned :bool,
+}
+
+
+/// A color theme file, containing only the colors of a config file.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug)]
+pub struct ThemeFile {
+    pub colors :Vec<(String,String)>,  // name -> hex color
+}
 
 /// serde-friendly representation of the config struct
 #[derive(Serialize,Deserialize)]
 #[derive(Debug)]
 pub struct ConfigString {
     pub colors :Vec<(String,String)>,  // name -> hex color
+    #[serde(default = "default_design_speed")]
+    pub design_speed :f32,
+    #[serde(default = "default_design_deceleration")]
+    pub design_deceleration :f32,
+    #[serde(default)]
+    pub show_signal_spacing :bool,
+    #[serde(default)]
+    pub show_catenary :bool,
+    #[serde(default)]
+    pub smooth_curves :bool,
+    #[serde(default)]
+    pub length_unit :LengthUnit,
+    #[serde(default)]
+    pub speed_unit :SpeedUnit,
+    #[serde(default = "default_undo_depth")]
+    pub undo_depth :i32,
+    #[serde(default = "default_backup_count")]
+    pub backup_count :i32,
+    #[serde(default = "default_saved_undo_steps")]
+    pub saved_undo_steps :i32,
+    #[serde(default = "default_api_port")]
+    pub api_port :i32,
+    #[serde(default)]
+    pub api_origins :Vec<String>,
+    #[serde(default)]
+    pub feed :FeedSettings,
+    #[serde(default = "default_language")]
+    pub language :String,
+    #[serde(default)]
+    pub keys :Vec<(String,String)>,  // command -> key, e.g. "Ctrl+Shift+Z"
+    #[serde(default)]
+    pub recent_files :Vec<RecentFile>,
+    #[serde(default)]
+    pub solver :SolverSettings,
+    #[serde(default)]
+    pub print :PrintSettings,
+    #[serde(default)]
+    pub grid :GridSettings,
 }
 
+/// The grid drawn on the canvas. The tracks are always drawn between grid
+/// points; the divisions of each grid unit are only marks between them,
+/// which inserted objects can be snapped to.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct GridSettings {
+    /// Draw the grid points.
+    pub visible :bool,
+    /// Grid units between the major grid lines, 0 for none.
+    pub major :i32,
+    /// Number of divisions of each grid unit.
+    pub minor :i32,
+    /// Place inserted objects at the nearest division along the track.
+    pub snap_objects :bool,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        GridSettings { visible: true, major: 0, minor: 1, snap_objects: false }
+    }
+}
+
+fn default_design_speed() -> f32 { 80.0 }
+fn default_design_deceleration() -> f32 { 0.5 }
+fn default_undo_depth() -> i32 { 200 }
+fn default_backup_count() -> i32 { 3 }
+fn default_saved_undo_steps() -> i32 { 20 }
+fn default_api_port() -> i32 { 7070 }
+fn default_language() -> String { crate::i18n::DEFAULT_LANGUAGE.to_string() }
+
 fn to_hex(c :Color) -> String {
     use palette::encoding::pixel::Pixel;
     let px  :[u8;4] = c.into_format().into_raw();
@@ -158,8 +296,16 @@ impl Config {
         }
     }
 
+    /// A folder next to the config file, such as the scripts or plugins folder.
+    pub fn subdir(name :&str) -> Option<std::path::PathBuf> {
+        // Same location as confy uses for the config file.
+        directories::ProjectDirs::from("rs", "", env!("CARGO_PKG_NAME"))
+            .map(|d| d.config_dir().join(name))
+    }
+
 
-    pub fn to_config_string(&self) ->  ConfigString {
+    /// Colors as (name, hex color) pairs, as stored in the config file.
+    pub fn color_strings(&self) -> Vec<(String,String)> {
         let mut colors = Vec::new();
         unsafe {
             for (c,val) in self.colors.iter() {
@@ -167,28 +313,158 @@ impl Config {
                              to_hex(*val)));
             }
         }
-
-        ConfigString {
-            colors: colors,
-        }
+        colors
     }
 
-    pub fn from_config_string(cs :&ConfigString) -> Self {
-        let mut colors = default_colors();
-        for (name,col_hex) in cs.colors.iter() {
+    /// Set the colors given as (name, hex color) pairs, leaving other colors unchanged.
+    pub fn set_color_strings(&mut self, colors :&[(String,String)]) {
+        for (name,col_hex) in colors.iter() {
             for (col_choice, name_cstr) in COLORNAMES.iter() {
                 unsafe {
                     if std::str::from_utf8_unchecked(name_cstr.as_cstr().to_bytes()) == name {
                         if let Ok(c) = from_hex(col_hex) {
-                            colors[col_choice] = c;
+                            self.colors[col_choice] = c;
                         }
                     }
                 }
             }
         }
+    }
 
-        Config {
-            colors: colors,
+    /// The colors as a theme file.
+    pub fn theme_string(&self) -> Result<String,String> {
+        toml::to_string(&ThemeFile { colors: self.color_strings() }).map_err(|e| format!("{}", e))
+    }
+
+    /// Apply the colors of a theme file. Colors missing from the theme get
+    /// their default value. Config files can also be used as themes.
+    pub fn set_theme_string(&mut self, s :&str) -> Result<(),String> {
+        let theme :ThemeFile = toml::from_str(s).map_err(|e| format!("{}", e))?;
+        self.colors = default_colors();
+        self.set_color_strings(&theme.colors);
+        Ok(())
+    }
+
+    /// Key bindings as (command, key) pairs, as stored in the config file.
+    /// Unbound commands have an empty key.
+    pub fn key_strings(&self) -> Vec<(String,String)> {
+        self.keys.iter().map(|(c,k)| (c.name().to_string(),
+                             k.map(|k| k.to_string()).unwrap_or_default())).collect()
+    }
+
+    /// Set the key bindings given as (command, key) pairs, leaving other commands unchanged.
+    pub fn set_key_strings(&mut self, keys :&[(String,String)]) {
+        for (name, key) in keys.iter() {
+            if let Some((cmd,_)) = self.keys.iter().find(|(c,_)| c.name() == name) {
+                if key.is_empty() {
+                    self.keys[cmd] = None;
+                } else if let Some(k) = KeyBinding::parse(key) {
+                    self.keys[cmd] = Some(k);
+                } else {
+                    warn!("Invalid key {:?} for {:?} in config file", key, name);
+                }
+            }
+        }
+    }
+
+    /// Shortcut text for menus, empty if the command has no key.
+    pub fn key_text(&self, cmd :KeyCommand) -> String {
+        self.keys[cmd].map(|k| k.to_string()).unwrap_or_default()
+    }
+
+    /// Pairs of commands bound to the same key.
+    pub fn key_conflicts(&self) -> Vec<(KeyCommand, KeyCommand)> {
+        let mut conflicts = Vec::new();
+        let keys = self.keys.iter().filter_map(|(c,k)| k.map(|k| (c,k))).collect::<Vec<_>>();
+        for (i,(c1,k1)) in keys.iter().enumerate() {
+            for (c2,k2) in keys.iter().skip(i+1) {
+                if k1 == k2 { conflicts.push((*c1,*c2)); }
+            }
+        }
+        conflicts
+    }
+
+    pub fn to_config_string(&self) ->  ConfigString {
+        ConfigString {
+            colors: self.color_strings(),
+            design_speed: self.design_speed,
+            design_deceleration: self.design_deceleration,
+            show_signal_spacing: self.show_signal_spacing,
+            show_catenary: self.show_catenary,
+            smooth_curves: self.smooth_curves,
+            length_unit: self.units.length,
+            speed_unit: self.units.speed,
+            undo_depth: self.undo_depth,
+            backup_count: self.backup_count,
+            saved_undo_steps: self.saved_undo_steps,
+            api_port: self.api_port,
+            api_origins: self.api_origins.clone(),
+            feed: self.feed.clone(),
+            language: self.language.clone(),
+            keys: self.key_strings(),
+            recent_files: self.recent_files.clone(),
+            solver: self.solver.clone(),
+            grid: self.grid.clone(),
+            print: self.print.clone(),
+        }
+    }
+
+    pub fn from_config_string(cs :&ConfigString) -> Self {
+        let mut config = Config {
+            colors: default_colors(),
+            design_speed: cs.design_speed,
+            design_deceleration: cs.design_deceleration,
+            show_signal_spacing: cs.show_signal_spacing,
+            show_catenary: cs.show_catenary,
+            smooth_curves: cs.smooth_curves,
+            units: Units { length: cs.length_unit, speed: cs.speed_unit },
+            undo_depth: cs.undo_depth,
+            backup_count: cs.backup_count,
+            saved_undo_steps: cs.saved_undo_steps,
+            api_port: cs.api_port,
+            api_origins: cs.api_origins.clone(),
+            feed: cs.feed.clone(),
+            language: cs.language.clone(),
+            keys: default_keys(),
+            recent_files: cs.recent_files.clone(),
+            solver: cs.solver.clone(),
+            grid: cs.grid.clone(),
+            print: cs.print.clone(),
+        };
+        config.set_color_strings(&cs.colors);
+        config.set_key_strings(&cs.keys);
+        config
+    }
+
+    /// Move a file to the top of the recent files list, keeping its pinned
+    /// state, and drop the oldest unpinned entries. The config is saved.
+    pub fn add_recent_file(&mut self, path :String, kind :RecentFileKind) {
+        let mut pinned = false;
+        self.recent_files.retain(|f| {
+            if f.path == path && f.kind == kind { pinned = f.pinned; false } else { true }
+        });
+        self.recent_files.insert(0, RecentFile { path, kind, pinned });
+
+        let mut unpinned = 0;
+        self.recent_files.retain(|f| {
+            if f.pinned { return true; }
+            unpinned += 1;
+            unpinned <= MAX_RECENT_FILES
+        });
+        self.save();
+    }
+
+    pub fn set_recent_file_pinned(&mut self, idx :usize, pinned :bool) {
+        if let Some(f) = self.recent_files.get_mut(idx) {
+            f.pinned = pinned;
+            self.save();
+        }
+    }
+
+    pub fn remove_recent_file(&mut self, idx :usize) {
+        if idx < self.recent_files.len() {
+            self.recent_files.remove(idx);
+            self.save();
         }
     }
 
@@ -226,6 +502,24 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             colors: default_colors(),
+            design_speed: default_design_speed(),
+            design_deceleration: default_design_deceleration(),
+            show_signal_spacing: false,
+            show_catenary: false,
+            smooth_curves: false,
+            units: Default::default(),
+            undo_depth: default_undo_depth(),
+            backup_count: default_backup_count(),
+            saved_undo_steps: default_saved_undo_steps(),
+            api_port: default_api_port(),
+            api_origins: Vec::new(),
+            feed: Default::default(),
+            language: default_language(),
+            keys: default_keys(),
+            recent_files: Vec::new(),
+            solver: Default::default(),
+            grid: Default::default(),
+            print: Default::default(),
         }
     }
 }
@@ -256,9 +550,11 @@ pub fn default_colors() -> EnumMap<RailUIColorName, Color> {
         RailUIColorName::CanvasTVDFree => c(named::BLACK),
         RailUIColorName::CanvasTVDOccupied => c(named::SALMON),
         RailUIColorName::CanvasTVDReserved => c(named::SLATEBLUE),
+        RailUIColorName::CanvasTVDReleased => c(named::LIGHTSTEELBLUE),
         RailUIColorName::CanvasRoutePath => c(named::DARKSLATEBLUE),
         RailUIColorName::CanvasRouteSection => c(named::SLATEBLUE),
         RailUIColorName::CanvasSelectionWindow => c(named::NAVY),
+        RailUIColorName::CanvasCatenary => c(named::DARKORANGE),
         RailUIColorName::GraphBackground => c(named::HONEYDEW),
         RailUIColorName::GraphTimeSlider => c(named::LIGHTSALMON),
         RailUIColorName::GraphTimeSliderText => c(named::DARKGREY),
@@ -274,6 +570,155 @@ pub fn default_colors() -> EnumMap<RailUIColorName, Color> {
     }
 }
 
+/// Commands which can be bound to a key in the configuration.
+#[derive(Enum, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KeyCommand {
+    Undo,
+    Redo,
+    RedoAlternative,
+    Save,
+    SaveAs,
+    Open,
+    SelectAll,
+    Copy,
+    Paste,
+    Delete,
+    SelectTool,
+    DrawTool,
+    InsertObjectTool,
+    EraserTool,
+    PlayPause,
+    CommandPalette,
+    Find,
+    FitView,
+    ZoomToSelection,
+    MirrorHorizontal,
+    MirrorVertical,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Rotate45Clockwise,
+    Rotate45CounterClockwise,
+}
+
+impl KeyCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyCommand::Undo => "Undo",
+            KeyCommand::Redo => "Redo",
+            KeyCommand::RedoAlternative => "Redo (alternative)",
+            KeyCommand::Save => "Save",
+            KeyCommand::SaveAs => "Save as",
+            KeyCommand::Open => "Load file",
+            KeyCommand::SelectAll => "Select all",
+            KeyCommand::Copy => "Copy",
+            KeyCommand::Paste => "Paste",
+            KeyCommand::Delete => "Delete selection",
+            KeyCommand::SelectTool => "Select and move tool",
+            KeyCommand::DrawTool => "Draw tracks tool",
+            KeyCommand::InsertObjectTool => "Insert object tool",
+            KeyCommand::EraserTool => "Eraser tool",
+            KeyCommand::PlayPause => "Play/pause dispatch",
+            KeyCommand::CommandPalette => "Command palette",
+            KeyCommand::Find => "Find",
+            KeyCommand::FitView => "Fit to view",
+            KeyCommand::ZoomToSelection => "Zoom to selection",
+            KeyCommand::MirrorHorizontal => "Mirror selection horizontally",
+            KeyCommand::MirrorVertical => "Mirror selection vertically",
+            KeyCommand::RotateClockwise => "Rotate selection clockwise",
+            KeyCommand::RotateCounterClockwise => "Rotate selection counterclockwise",
+            KeyCommand::Rotate45Clockwise => "Rotate selection 45° clockwise",
+            KeyCommand::Rotate45CounterClockwise => "Rotate selection 45° counterclockwise",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Key {
+    /// Upper case letter or digit.
+    Char(char),
+    Space,
+    Delete,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct KeyBinding {
+    pub ctrl :bool,
+    pub shift :bool,
+    pub key :Key,
+}
+
+impl KeyBinding {
+    pub fn new(ctrl :bool, shift :bool, key :Key) -> Self {
+        KeyBinding { ctrl, shift, key }
+    }
+
+    /// Parse a key such as `Ctrl+Shift+Z`, `Space` or `Del`.
+    pub fn parse(s :&str) -> Option<KeyBinding> {
+        let mut binding = KeyBinding::new(false, false, Key::Space);
+        let mut key = None;
+        for part in s.split('+').map(|p| p.trim()) {
+            match part.to_lowercase().as_str() {
+                "ctrl" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "space" => key = Some(Key::Space),
+                "del" | "delete" => key = Some(Key::Delete),
+                p if p.chars().count() == 1 => {
+                    let c = p.chars().next().unwrap().to_ascii_uppercase();
+                    if !c.is_ascii_alphanumeric() { return None; }
+                    key = Some(Key::Char(c));
+                },
+                _ => return None,
+            }
+        }
+        binding.key = key?;
+        Some(binding)
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f :&mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.ctrl { write!(f, "Ctrl+")?; }
+        if self.shift { write!(f, "Shift+")?; }
+        match self.key {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Space => write!(f, "Space"),
+            Key::Delete => write!(f, "Del"),
+        }
+    }
+}
+
+pub fn default_keys() -> EnumMap<KeyCommand, Option<KeyBinding>> {
+    let ctrl = |c| Some(KeyBinding::new(true, false, Key::Char(c)));
+    let plain = |k| Some(KeyBinding::new(false, false, k));
+    enum_map! {
+        KeyCommand::Undo => ctrl('Z'),
+        KeyCommand::Redo => ctrl('Y'),
+        KeyCommand::RedoAlternative => Some(KeyBinding::new(true, true, Key::Char('Z'))),
+        KeyCommand::Save => ctrl('S'),
+        KeyCommand::SaveAs => Some(KeyBinding::new(true, true, Key::Char('S'))),
+        KeyCommand::Open => ctrl('O'),
+        KeyCommand::SelectAll => ctrl('A'),
+        KeyCommand::Copy => ctrl('C'),
+        KeyCommand::Paste => ctrl('V'),
+        KeyCommand::Delete => plain(Key::Delete),
+        KeyCommand::SelectTool => plain(Key::Char('A')),
+        KeyCommand::DrawTool => plain(Key::Char('D')),
+        KeyCommand::InsertObjectTool => plain(Key::Char('S')),
+        KeyCommand::EraserTool => plain(Key::Char('X')),
+        KeyCommand::PlayPause => plain(Key::Space),
+        KeyCommand::CommandPalette => ctrl('P'),
+        KeyCommand::Find => ctrl('F'),
+        KeyCommand::FitView => plain(Key::Char('F')),
+        KeyCommand::ZoomToSelection => plain(Key::Char('Z')),
+        KeyCommand::MirrorHorizontal => plain(Key::Char('H')),
+        KeyCommand::MirrorVertical => plain(Key::Char('V')),
+        KeyCommand::RotateClockwise => plain(Key::Char('R')),
+        KeyCommand::RotateCounterClockwise => Some(KeyBinding::new(false, true, Key::Char('R'))),
+        KeyCommand::Rotate45Clockwise => plain(Key::Char('E')),
+        KeyCommand::Rotate45CounterClockwise => Some(KeyBinding::new(false, true, Key::Char('E'))),
+    }
+}
+
 #[derive(Enum, Debug, PartialEq, Eq, Copy, Clone)]
 #[derive(Serialize,Deserialize)]
 pub enum RailUIColorName {
@@ -295,9 +740,11 @@ pub enum RailUIColorName {
     CanvasTVDFree,
     CanvasTVDOccupied,
     CanvasTVDReserved,
+    CanvasTVDReleased,
     CanvasRoutePath,
     CanvasRouteSection,
     CanvasSelectionWindow,
+    CanvasCatenary,
     GraphBackground,
     GraphTimeSlider,
     GraphTimeSliderText,
@@ -320,4 +767,28 @@ pub fn colr_no() {
     //assert_eq!(x, RailUIColorName::CanvasSymbol);
 }
 
+#[test]
+pub fn theme_roundtrip() {
+    let mut config = Config::default();
+    config.colors[RailUIColorName::CanvasBackground] = Color::new(0.0, 0.0, 0.0, 1.0);
+    let theme = config.theme_string().unwrap();
+    let mut other = Config::default();
+    other.set_theme_string(&theme).unwrap();
+    assert_eq!(other.color_strings(), config.color_strings());
+    assert!(other.set_theme_string("colors = 3").is_err());
+}
 
+#[test]
+pub fn key_bindings() {
+    let mut config = Config::default();
+    assert!(config.key_conflicts().is_empty());
+    assert_eq!(KeyBinding::parse("ctrl + shift + z"), config.keys[KeyCommand::RedoAlternative]);
+    assert_eq!(KeyBinding::parse("Ctrl+"), None);
+
+    config.set_key_strings(&[("Draw tracks tool".to_string(), "A".to_string()),
+                             ("Undo".to_string(), String::new())]);
+    assert_eq!(config.keys[KeyCommand::Undo], None);
+    assert_eq!(config.key_conflicts(), vec![(KeyCommand::SelectTool, KeyCommand::DrawTool)]);
+    let reloaded = Config::from_config_string(&config.to_config_string());
+    assert_eq!(reloaded.keys, config.keys);
+}
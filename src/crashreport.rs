@@ -0,0 +1,117 @@
+//! Crash report bundles. A panic hook writes the panic message with a
+//! backtrace, the end of the log, the last model and the config to a new
+//! folder in the `crashes` folder next to the config file. The path of the
+//! bundle is shown the next time the program starts.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use lazy_static::*;
+
+use crate::app::App;
+use crate::config::Config;
+use crate::document::analysis::Generation;
+use crate::document::model::Model;
+use crate::gui::windows::logview::LogStore;
+use crate::file;
+
+/// Bytes of the log included in the report.
+const LOG_TAIL :usize = 20_000;
+/// File in the crashes folder naming the bundle that has not been shown yet.
+const LAST_CRASH :&str = "last_crash.txt";
+
+#[derive(Default)]
+struct Snapshot {
+    log :Option<LogStore>,
+    model :Option<(Generation, Model)>,
+    filename :Option<String>,
+    unsaved :bool,
+    config :Option<String>,
+}
+
+lazy_static! {
+    static ref SNAPSHOT :Mutex<Snapshot> = Mutex::new(Default::default());
+}
+
+/// Install the panic hook. The default hook still runs, so the panic is
+/// also printed to the terminal.
+pub fn install(log :LogStore) {
+    SNAPSHOT.lock().unwrap().log = Some(log);
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_bundle(info) {
+            Ok(dir) => eprintln!("Crash report written to {}", dir.display()),
+            Err(e) => eprintln!("Could not write crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Keep a copy of the current model and config for the crash report. The
+/// model is only copied when it has changed.
+pub fn update(app :&App) {
+    let mut snapshot = match SNAPSHOT.try_lock() { Ok(s) => s, Err(_) => return };
+    let generation = *app.document.analysis.generation();
+    if snapshot.model.as_ref().map(|(g,_)| *g == generation).unwrap_or(false) { return; }
+    snapshot.model = Some((generation, app.document.analysis.model().clone()));
+    snapshot.filename = app.document.fileinfo.filename.clone();
+    snapshot.unsaved = app.document.fileinfo.unsaved;
+    snapshot.config = toml::to_string(&app.config.to_config_string()).ok();
+}
+
+fn write_bundle(info :&std::panic::PanicInfo) -> Result<PathBuf, String> {
+    let crashes = Config::subdir("crashes").ok_or_else(|| "No config folder".to_string())?;
+    let secs = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs()).unwrap_or(0);
+    let dir = crashes.join(format!("crash-{}", secs));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}", e))?;
+
+    let message = info.payload().downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "(no message)".to_string());
+    let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_default();
+    let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+    let mut report = format!("{} v{}\nPlatform: {} {}\nThread: {}\nPanic: {}\nLocation: {}\n",
+                             env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+                             std::env::consts::OS, std::env::consts::ARCH,
+                             thread, message, location);
+
+    // The panic may have happened while the snapshot or the log was locked,
+    // so the bundle is written without them rather than waiting.
+    match SNAPSHOT.try_lock() {
+        Ok(snapshot) => {
+            if let Some(filename) = &snapshot.filename {
+                report.push_str(&format!("Document: {}{}\n", filename,
+                                         if snapshot.unsaved { " (unsaved changes)" } else { "" }));
+            }
+            if let Some(log) = snapshot.log.as_ref().and_then(|l| l.try_lock().ok()) {
+                let skip = log.len().saturating_sub(LOG_TAIL);
+                let tail = log.iter().skip(skip).cloned().collect::<Vec<u8>>();
+                let _ = std::fs::write(dir.join("log.txt"), tail);
+            }
+            if let Some((_,model)) = &snapshot.model {
+                let path = dir.join("model.junc");
+                if let Err(e) = file::save(&path.to_string_lossy(), model.clone(), 0) {
+                    report.push_str(&format!("Could not save model: {}\n", e));
+                }
+            }
+            if let Some(config) = &snapshot.config {
+                let _ = std::fs::write(dir.join("config.toml"), config);
+            }
+        },
+        Err(_) => report.push_str("Model, log and config not available.\n"),
+    }
+
+    report.push_str(&format!("\nBacktrace:\n{}\n", std::backtrace::Backtrace::force_capture()));
+    std::fs::write(dir.join("report.txt"), report).map_err(|e| format!("{}", e))?;
+    std::fs::write(crashes.join(LAST_CRASH), dir.to_string_lossy().as_bytes()).map_err(|e| format!("{}", e))?;
+    Ok(dir)
+}
+
+/// The crash bundle from the previous run, if it has not been shown yet.
+pub fn take_last_crash() -> Option<String> {
+    let marker = Config::subdir("crashes")?.join(LAST_CRASH);
+    let dir = std::fs::read_to_string(&marker).ok()?;
+    let _ = std::fs::remove_file(&marker);
+    Some(dir.trim().to_string())
+}
@@ -6,19 +6,26 @@ use crate::document::model::*;
 use crate::document::dgraph::*;
 use crate::document::topology;
 use crate::document::interlocking;
+use crate::document::lint;
 use crate::document::infview::unround_coord;
 
 use crate::document::history;
 use crate::app;
+use crate::file;
 use crate::util;
 use crate::util::VecMap;
 use crate::document::dispatch;
 use crate::document::plan;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use nalgebra_glm as glm;
 
 pub type Generation = usize;
 
+/// Minimum time between recomputations for continuous edits, see
+/// `Analysis::set_model_deferred`.
+const DEFERRED_UPDATE_INTERVAL :std::time::Duration = std::time::Duration::from_millis(250);
+
 #[derive(Default)]
 pub struct AnalysisOutput {
     pub topology: Option<(Generation, Arc<topology::Topology>)>,
@@ -27,6 +34,8 @@ pub struct AnalysisOutput {
     pub dispatch :Vec<Option<(Generation, dispatch::DispatchOutput)>>,
     //pub plandispatches :HashMap<usize, Vec<Option<(Generation, dispatch::DispatchOutput)>>>,
     pub plandispatches :Vec<Option<(Generation, Vec<dispatch::DispatchOutput>)>>,
+    pub lint :Option<(Generation, Arc<Vec<lint::Issue>>)>,
+    pub plan_feasibility :Vec<Option<(Generation, Vec<plan::VisitIssue>)>>,
 }
 
 pub struct Analysis {
@@ -35,22 +44,37 @@ pub struct Analysis {
     output: AnalysisOutput,
     chan :Option<Receiver<SetData>>,
     bg :app::BackgroundJobs,
+    /// The model that the current topology was computed from, used for
+    /// updating the derived data incrementally after an edit.
+    computed :Option<(Generation, Model)>,
+    /// Cancels the background job computing data for an outdated model.
+    cancel :app::CancelToken,
+    /// The model has changed since the last call to `update`.
+    pending_update :bool,
+    last_update :std::time::Instant,
 }
 
 #[derive(Debug)]
 pub enum SetData {
     DGraph(Generation, Arc<DGraph>),
     Interlocking(Generation, Arc<interlocking::Interlocking>),
+    Lint(Generation, Arc<Vec<lint::Issue>>),
     Dispatch(Generation, usize,dispatch::DispatchOutput),
     PlanDispatch(Generation, usize,Vec<dispatch::DispatchOutput>),
+    PlanDispatchAdd(Generation, usize, dispatch::DispatchOutput),
+    PlanFeasibility(Generation, usize, Vec<plan::VisitIssue>),
 }
 
 impl app::BackgroundUpdates for Analysis {
     fn check(&mut self) {
+        if self.pending_update && self.last_update.elapsed() >= DEFERRED_UPDATE_INTERVAL {
+            self.update();
+        }
         while let Some(Ok(data)) = self.chan.as_mut().map(|r| r.try_recv()) {
             match data {
                 SetData::DGraph(g, dgraph) => { self.output.dgraph = Some((g, dgraph)); },
                 SetData::Interlocking(g, il) => { self.output.interlocking = Some((g, il)); },
+                SetData::Lint(g, issues) => { self.output.lint = Some((g, issues)); },
                 SetData::Dispatch(g, idx,h) => { 
                     self.output.dispatch.vecmap_insert(idx, (g, h));
                     //cache.clear_dispatch(idx);
@@ -61,6 +85,15 @@ impl app::BackgroundUpdates for Analysis {
                         //.vecmap_insert(dispatch_idx, (g, h));
                     self.output.plandispatches.vecmap_insert(plan_idx, (g,hs));
                 },
+                SetData::PlanDispatchAdd(g, plan_idx, d) => {
+                    match self.output.plandispatches.get_mut(plan_idx) {
+                        Some(Some((current, ds))) if *current == g => { ds.push(d); },
+                        _ => { self.output.plandispatches.vecmap_insert(plan_idx, (g, vec![d])); },
+                    }
+                },
+                SetData::PlanFeasibility(g, plan_idx, issues) => {
+                    self.output.plan_feasibility.vecmap_insert(plan_idx, (g,issues));
+                },
             }
         }
     }
@@ -70,6 +103,7 @@ impl Analysis {
     pub fn model(&self) -> &Model { &self.model.get() }
     pub fn data(&self) -> &AnalysisOutput { &self.output }
     pub fn generation(&self) -> &Generation { &self.model_generation }
+    pub fn background_jobs(&self) -> app::BackgroundJobs { self.bg.clone() }
 
     pub fn from_model(model :Model, bg: app::BackgroundJobs) -> Self {
         let mut a = Analysis {
@@ -78,29 +112,71 @@ impl Analysis {
             output: Default::default(),
             chan: None,
             bg: bg,
+            computed: None,
+            cancel: app::CancelToken::new(),
+            pending_update: false,
+            last_update: std::time::Instant::now(),
         };
         a.update();
         a
     }
 
     fn update(&mut self) {
+        self.pending_update = false;
+        self.last_update = std::time::Instant::now();
         let model = self.model.get().clone(); // persistent structs
         let gen = self.model_generation;
 
-        let topology = Arc::new(topology::convert(&model, 50.0).unwrap());
-        self.output.topology = Some((gen,topology.clone()));
+        let topology = match (&self.computed, &self.output.topology) {
+            (Some((_,prev_model)), Some((_,prev))) => 
+                topology::convert_incremental(prev, prev_model, &model, 50.0),
+            _ => topology::convert(&model, 50.0),
+        };
+        let topology = Arc::new(topology.unwrap());
+        let prev_topology = self.output.topology.replace((gen,topology.clone()));
+
+        // If the edit did not touch the infrastructure, the dgraph and
+        // interlocking from the previous model can be reused as long as
+        // they were finished for that model.
+        let reuse = match (&self.computed, &prev_topology, &self.output.dgraph, &self.output.interlocking) {
+            (Some((prev_gen, prev_model)), Some((_,prev_topology)), Some((g1,dgraph)), Some((g2,il))) 
+                if g1 == prev_gen && g2 == prev_gen && topology::same_infrastructure(prev_model, &model) &&
+                    prev_topology.gradients == topology.gradients => 
+                Some((dgraph.clone(), il.clone())),
+            _ => None,
+        };
+        if let Some((dgraph, il)) = &reuse {
+            self.output.dgraph = Some((gen, dgraph.clone()));
+            self.output.interlocking = Some((gen, il.clone()));
+        }
+        self.computed = Some((gen, model.clone()));
+
+        // Quick lint pass without the interlocking, replaced by the full
+        // pass from the background thread.
+        self.output.lint = Some((gen, Arc::new(lint::lint(&model, &topology, None))));
 
         let (tx,rx) = channel();
         self.chan = Some(rx);
 
-        self.bg.execute(move || {
+        // A newer model makes any queued or running analysis obsolete.
+        self.cancel.cancel();
+        self.cancel = app::CancelToken::new();
+        let cancel = self.cancel.clone();
+
+        let mut bg = self.bg.clone();
+        self.bg.execute_with(app::Priority::High, cancel.clone(), move || {
             info!("Background thread starting");
             let model = model;  // move model into thread
             let tx = tx;        // move sender into thread
 
-            //let dgraph = dgraph::calc(&model); // calc dgraph from model.
-            let dgraph = DGraphBuilder::convert(&topology).expect("dgraph conversion failed");
-            let dgraph = Arc::new(dgraph);
+            let (dgraph, interlocking) = match reuse {
+                Some((dgraph, il)) => (dgraph, Some(il)),
+                None => {
+                    //let dgraph = dgraph::calc(&model); // calc dgraph from model.
+                    let dgraph = DGraphBuilder::convert(&topology).expect("dgraph conversion failed");
+                    (Arc::new(dgraph), None)
+                },
+            };
 
             info!("Dgraph successful with {:?} nodes", dgraph.rolling_inf.nodes.len());
 
@@ -113,17 +189,27 @@ impl Analysis {
             // receiver end of the channel, so it will anyway not
             // be placed into the struct.
 
-            let interlocking = interlocking::calc(&dgraph); 
-            let interlocking = Arc::new(interlocking);
+            if cancel.is_canceled() { println!("job canceled after dgraph"); return; }
+            let interlocking = interlocking.unwrap_or_else(|| {
                 // calc interlocking from dgraph
+                let mut il = interlocking::calc(&dgraph);
+                interlocking::add_railml_routes(&mut il, &model);
+                Arc::new(il)
+            });
             let send_ok = tx.send(SetData::Interlocking(gen, interlocking.clone()));
             if !send_ok.is_ok() { println!("job canceled after interlocking"); return; }
             info!("Interlocking successful with {:?} routes", interlocking.routes.len());
 
+            if cancel.is_canceled() { println!("job canceled after interlocking"); return; }
+            let issues = lint::lint(&model, &topology, Some(&interlocking));
+            let send_ok = tx.send(SetData::Lint(gen, Arc::new(issues)));
+            if !send_ok.is_ok() { println!("job canceled after lint"); return; }
+
             for (i,dispatch) in model.dispatches.iter() {
+                if cancel.is_canceled() { println!("job canceled before dispatch {}", i); return; }
                 //let history = dispatch::run(&dgraph, &interlocking, &dispatch);
                 let (history,route_refs) = history::get_history(model.vehicles.data(),
-                                                   &dgraph.rolling_inf,
+                                                   &dgraph,
                                                    &interlocking,
                                                    &(dispatch.commands)).unwrap();
                 info!("Simulation successful {:?}", &dispatch.commands);
@@ -133,18 +219,56 @@ impl Analysis {
             }
 
             for (plan_idx,plan) in model.plans.iter() {
-                let planresults = plan::get_dispatches(&dgraph, &interlocking,
-                                             model.vehicles.data(),
-                                             plan).unwrap();
+                if cancel.is_canceled() { println!("job canceled before plan {}", plan_idx); return; }
+                let issues = plan::check_feasibility(&dgraph, &interlocking, plan);
+                let feasible = issues.is_empty();
+                for issue in issues.iter() {
+                    warn!("Plan {}: train {} visit {} is unreachable: {}", plan_idx,
+                          issue.train, issue.visit, issue.reason.description());
+                }
+                let send_ok = tx.send(SetData::PlanFeasibility(gen, *plan_idx, issues));
+                if !send_ok.is_ok() { println!("job cancelled after plan feasibility {}", plan_idx); return; }
+                if !feasible {
+                    // Don't run the planner on a plan that cannot be satisfied.
+                    let send_ok = tx.send(SetData::PlanDispatch(gen, *plan_idx, Vec::new()));
+                    if !send_ok.is_ok() { println!("job cancelled after plan dispatch {}", plan_idx); return; }
+                    continue;
+                }
 
-                info!("Planning successful. {:?}", planresults);
+                let candidates = plan::plan_candidates(&interlocking, model.vehicles.data(), plan).unwrap();
+                info!("Planning successful with {} candidates", candidates.len());
 
-                let dispatches = planresults.into_iter().map(|(d,h)| {
-                    dispatch::DispatchOutput::from_history(d, &dgraph, h)
-                }).collect();
+                if candidates.is_empty() {
+                    let send_ok = tx.send(SetData::PlanDispatch(gen, *plan_idx, Vec::new()));
+                    if !send_ok.is_ok() { println!("job cancelled after plan dispatch {}", plan_idx); return; }
+                    continue;
+                }
 
-                let send_ok = tx.send(SetData::PlanDispatch(gen, *plan_idx, dispatches));
-                if !send_ok.is_ok() { println!("job cancelled after plan dispatch {}", plan_idx); }
+                // Simulate the candidates in parallel. Each successful one is
+                // added to the plan's dispatches when it finishes, and if none
+                // succeeds, the last one to finish reports the empty result.
+                let remaining = Arc::new(AtomicUsize::new(candidates.len()));
+                let vehicles = Arc::new(model.vehicles.data().to_vec());
+                let plan = Arc::new(plan.clone());
+                let found = Arc::new(AtomicUsize::new(0));
+                for candidate in candidates {
+                    let (dgraph, interlocking, vehicles, plan, found, remaining, tx, plan_idx) = 
+                        (dgraph.clone(), interlocking.clone(), vehicles.clone(), plan.clone(), 
+                         found.clone(), remaining.clone(), tx.clone(), *plan_idx);
+                    bg.execute_with(app::Priority::Normal, cancel.clone(), move || {
+                        let result = plan::simulate_candidate(&dgraph, &interlocking, &vehicles, 
+                                                              &plan, &candidate);
+                        if let Some((commands, history)) = result {
+                            let n = found.fetch_add(1, Ordering::SeqCst) + 1;
+                            let d = Dispatch::from_vec(format!("Dispatch {}", n), commands);
+                            let view = dispatch::DispatchOutput::from_history(d, &dgraph, history);
+                            let _ = tx.send(SetData::PlanDispatchAdd(gen, plan_idx, view));
+                        }
+                        if remaining.fetch_sub(1, Ordering::SeqCst) == 1 && found.load(Ordering::SeqCst) == 0 {
+                            let _ = tx.send(SetData::PlanDispatch(gen, plan_idx, Vec::new()));
+                        }
+                    });
+                }
             }
 
         });
@@ -162,12 +286,45 @@ impl Analysis {
         self.on_changed();
     }
 
+    /// Set the model without recomputing the derived data more often than
+    /// every `DEFERRED_UPDATE_INTERVAL`. Used for continuous edits such as
+    /// dragging; call `flush` when the edit is finished.
+    pub fn set_model_deferred(&mut self, m :Model, cl :Option<EditClass>) {
+        self.model.set(m, cl);
+        self.model_generation += 1;
+        if self.last_update.elapsed() >= DEFERRED_UPDATE_INTERVAL {
+            self.update();
+        } else {
+            self.pending_update = true;
+        }
+    }
+
+    /// Recompute derived data now if a deferred update is waiting.
+    pub fn flush(&mut self) {
+        if self.pending_update { self.update(); }
+    }
+
     pub fn override_edit_class(&mut self, cl :EditClass) {
         self.model.override_edit_class(cl);
     }
 
     pub fn undo(&mut self) { if self.model.undo() { self.on_changed(); } }
     pub fn redo(&mut self) { if self.model.redo() { self.on_changed(); } }
+    /// Set the maximum number of undo steps, 0 meaning unlimited.
+    pub fn set_undo_depth(&mut self, depth :usize) {
+        self.model.set_max_depth(if depth > 0 { Some(depth) } else { None });
+    }
+    /// The undo steps to save with the document, at most `depth` on each
+    /// side of the current model.
+    pub fn undo_history(&self, depth :usize) -> file::UndoHistory {
+        let (steps, pointer, class) = self.model.steps(depth);
+        file::UndoHistory { steps, pointer, class: class.cloned() }
+    }
+    /// Replace the undo steps with ones saved with the document. The current
+    /// model of the history is the one being edited.
+    pub fn restore_undo_history(&mut self, history :file::UndoHistory) {
+        self.model = Undoable::from_steps(history.steps, history.pointer, history.class);
+    }
     pub fn can_undo(&self) -> bool { self.model.can_undo() }
     pub fn can_redo(&self) -> bool { self.model.can_redo() }
 
@@ -241,3 +398,10 @@ impl Analysis {
 
 }
 
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        // Don't keep computing for a document that was closed.
+        self.cancel.cancel();
+    }
+}
+
@@ -0,0 +1,196 @@
+//! Copying and pasting parts of the infrastructure. A copied part is a
+//! model with its coordinates relative to the middle of the part, so that
+//! it can be pasted anywhere. On the system clipboard it is kept in the
+//! text save format, which lets other running instances paste it.
+
+use std::collections::{HashMap, HashSet};
+use nalgebra_glm as glm;
+
+use crate::document::model::*;
+use crate::document::objects::Object;
+use crate::document::infview::{round_coord, unround_coord};
+use crate::file;
+use crate::util;
+
+/// A model containing only the selected elements and the nodes they need.
+pub fn selected_part(model :&Model, selection :&HashSet<Ref>) -> Model {
+    let mut part = Model::empty();
+    let mut node_set = HashSet::new();
+    for r in selection {
+        match r {
+            Ref::Node(p) => {
+                if let Some(data) = model.node_data.get(p) {
+                    part.node_data.insert(*p, data.clone());
+                    node_set.insert(*p);
+                }
+            }
+            Ref::LineSeg(p1, p2) => {
+                part.linesegs.insert((*p1, *p2));
+                if let Some(attrs) = model.lineseg_attributes.get(&(*p1, *p2)) {
+                    part.lineseg_attributes.insert((*p1, *p2), attrs.clone());
+                }
+                node_set.insert(*p1);
+                node_set.insert(*p2);
+            }
+            Ref::Object(pta) => {
+                if let Some(obj) = model.objects.get(pta) {
+                    part.objects.insert(*pta, obj.clone());
+                }
+                if let Some(info) = model.railml_objects.get(pta) {
+                    part.railml_objects.insert(*pta, info.clone());
+                }
+            }
+        }
+    }
+    // Ensure all required nodes for linesegs/objects are in node_data
+    for p in node_set {
+        if !part.node_data.contains_key(&p) {
+            if let Some(data) = model.node_data.get(&p) {
+                part.node_data.insert(p, data.clone());
+            }
+        }
+        if let Some(info) = model.railml_nodes.get(&p) {
+            part.railml_nodes.insert(p, info.clone());
+        }
+        if model.trap_points.contains(&p) {
+            part.trap_points.insert(p);
+        }
+    }
+    part
+}
+
+/// Copy the selection, moved so that the grid point nearest its middle
+/// is the origin.
+pub fn copy(model :&Model, selection :&HashSet<Ref>) -> Model {
+    let part = selected_part(model, selection);
+    let mut pts = Vec::new();
+    for (p1, p2) in part.linesegs.iter() {
+        pts.push(glm::vec2(p1.x as f32, p1.y as f32));
+        pts.push(glm::vec2(p2.x as f32, p2.y as f32));
+    }
+    for p in part.node_data.keys() { pts.push(glm::vec2(p.x as f32, p.y as f32)); }
+    for obj in part.objects.values() { pts.push(obj.loc); }
+    if pts.is_empty() { return part; }
+
+    let mut middle = glm::vec2(0.0, 0.0);
+    for p in &pts { middle += *p; }
+    middle /= pts.len() as f32;
+    let mut relative = Model::empty();
+    translate(&part, &mut relative, -glm::vec2(middle.x.round() as i32, middle.y.round() as i32));
+    relative
+}
+
+/// Insert a copied part into the model with its origin at `at`, and return
+/// the inserted elements. Objects that would replace existing ones are
+/// moved a little along their track instead, and imported railML ids which
+/// are already used in the model are renamed.
+pub fn paste(model :&mut Model, clip :&Model, at :Pt) -> HashSet<Ref> {
+    translate(clip, model, at)
+}
+
+fn translate(part :&Model, model :&mut Model, delta :Pt) -> HashSet<Ref> {
+    let renamed :HashMap<String,String> = model.railml_id_renames(part).into_iter().collect();
+    let mut inserted = HashSet::new();
+    for (p, data) in part.node_data.iter() {
+        model.node_data.insert(p + delta, data.clone());
+        inserted.insert(Ref::Node(p + delta));
+    }
+    for p in part.trap_points.iter() { model.trap_points.insert(p + delta); }
+    for (p, info) in part.railml_nodes.iter() {
+        let mut info = info.clone();
+        if let Some(new) = renamed.get(&info.id) { info.id = new.clone(); }
+        model.railml_nodes.insert(p + delta, info);
+    }
+    for (p1, p2) in part.linesegs.iter() {
+        let attrs = part.lineseg_attrs(*p1, *p2).cloned();
+        let (p1, p2) = util::order_ivec(p1 + delta, p2 + delta);
+        model.linesegs.insert((p1, p2));
+        model.remove_lineseg_attrs(p1, p2);
+        if let Some(attrs) = attrs { model.lineseg_attributes.insert((p1, p2), attrs); }
+        inserted.insert(Ref::LineSeg(p1, p2));
+    }
+    for (old_pta, obj) in part.objects.iter() {
+        let mut obj = obj.clone();
+        obj.loc += glm::vec2(delta.x as f32, delta.y as f32);
+        let pta = move_to_free_key(model, &mut obj);
+        model.objects.insert(pta, obj);
+        if let Some(infos) = part.railml_objects.get(old_pta) {
+            let mut infos = infos.clone();
+            infos.iter_mut().for_each(|i| i.rename_ids(&renamed));
+            model.railml_objects.insert(pta, infos);
+        }
+        inserted.insert(Ref::Object(pta));
+    }
+    inserted
+}
+
+/// Move the object along its track until its key is not used by another
+/// object in the model, and return the key.
+fn move_to_free_key(model :&Model, obj :&mut Object) -> PtA {
+    let tangent = if obj.tangent == glm::zero() { glm::vec2(1, 0) } else { obj.tangent };
+    let step = unround_coord(tangent);
+    let mut pta = round_coord(obj.loc);
+    while model.objects.contains_key(&pta) {
+        obj.loc += step;
+        pta = round_coord(obj.loc);
+    }
+    pta
+}
+
+/// The part in the text save format, for the system clipboard.
+pub fn to_text(clip :&Model) -> Result<String, String> {
+    file::to_text_document(clip)
+}
+
+/// A part copied to the system clipboard, possibly by another instance.
+/// Other clipboard contents give `None`.
+pub fn from_text(text :&str) -> Option<Model> {
+    if !text.trim_start().starts_with('{') { return None; }
+    file::from_bytes(text.as_bytes()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::objects::Function;
+
+    #[test]
+    fn paste_is_relative_and_keeps_objects() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(10,0), glm::vec2(12,0)));
+        let obj = Object { loc: glm::vec2(11.0, 0.0), tangent: glm::vec2(1,0), functions: vec![Function::Detector] };
+        model.objects.insert(round_coord(obj.loc), obj.clone());
+        let attrs = LineSegAttributes { max_speed: Some(80.0), ..Default::default() };
+        model.lineseg_attributes.insert((glm::vec2(10,0), glm::vec2(12,0)), attrs.clone());
+        model.railml_objects.insert(round_coord(obj.loc), vec![RailMLObjectInfo::TrainDetector {
+            id: "det1".to_string(), axle_counting: None, direction_detection: None, medium: None }]);
+        model.railml_nodes.insert(glm::vec2(12,0), RailMLNodeInfo { id: "end1".to_string(), ocp_ref: None });
+        let selection = model.linesegs.iter().map(|(a,b)| Ref::LineSeg(*a,*b))
+            .chain(model.objects.keys().map(|p| Ref::Object(*p))).collect();
+
+        let clip = copy(&model, &selection);
+        assert!(clip.linesegs.contains(&(glm::vec2(-1,0), glm::vec2(1,0))));
+        let clip = from_text(&to_text(&clip).unwrap()).unwrap();
+
+        // Pasting on top of the original gives a second, separate object.
+        let inserted = paste(&mut model, &clip, glm::vec2(11,0));
+        assert_eq!(model.linesegs.len(), 1);
+        assert_eq!(model.objects.len(), 2);
+        assert_eq!(inserted.len(), 2);
+        assert_eq!(model.lineseg_attrs(glm::vec2(10,0), glm::vec2(12,0)), Some(&attrs));
+        let inserted = paste(&mut model, &clip, glm::vec2(11,3));
+        assert!(inserted.contains(&Ref::LineSeg(glm::vec2(10,3), glm::vec2(12,3))));
+        assert_eq!(model.lineseg_attrs(glm::vec2(10,3), glm::vec2(12,3)), Some(&attrs));
+        for (pta, obj) in model.objects.iter() {
+            assert_eq!(*pta, round_coord(obj.loc));
+        }
+
+        // The railML data is kept, with new ids where the old ones are used.
+        let ids = model.railml_objects.values().flatten().map(|i| i.id().to_string()).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 3);
+        assert!(model.railml_objects.keys().all(|pta| model.objects.contains_key(pta)));
+        assert!(model.railml_nodes.contains_key(&glm::vec2(12,3)));
+        let node_ids = model.railml_nodes.values().map(|n| n.id.clone()).collect::<HashSet<_>>();
+        assert_eq!(node_ids.len(), model.railml_nodes.len());
+    }
+}
@@ -0,0 +1,86 @@
+//! Smooth drawing of tracks. Where two line segments meet at a 45° corner,
+//! the corner can be drawn as a curve instead, as in traditional schematic
+//! plans. Only the drawing changes, the model stays on the grid.
+
+use std::collections::HashMap;
+use nalgebra_glm as glm;
+
+use crate::document::model::*;
+
+/// Distance from the corner, in grid units, where a curve starts.
+const CURVE_REACH :f32 = 0.5;
+/// Number of straight pieces drawn for each half of a curve.
+const CURVE_STEPS :usize = 4;
+
+/// Grid points where exactly two line segments meet at a 45° corner,
+/// with the other ends of the two segments.
+pub fn corners(linesegs :&im::HashSet<(Pt,Pt)>) -> HashMap<Pt,(Pt,Pt)> {
+    let mut neighbors :HashMap<Pt,Vec<Pt>> = HashMap::new();
+    for (a,b) in linesegs.iter() {
+        neighbors.entry(*a).or_insert_with(Vec::new).push(*b);
+        neighbors.entry(*b).or_insert_with(Vec::new).push(*a);
+    }
+    neighbors.into_iter().filter_map(|(p,qs)| match qs.as_slice() {
+        [q1,q2] => {
+            let u = glm::normalize(&to_ptc(q1 - p));
+            let v = glm::normalize(&to_ptc(q2 - p));
+            let turn = (glm::dot(&u,&v) + std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3;
+            if turn { Some((p,(*q1,*q2))) } else { None }
+        },
+        _ => None,
+    }).collect()
+}
+
+/// The points to draw for the line segment from `a` to `b`. At the corners
+/// found by `corners`, the segment is shortened and gets the half of the
+/// curve closest to it.
+pub fn polyline(a :Pt, b :Pt, corners :&HashMap<Pt,(Pt,Pt)>) -> Vec<PtC> {
+    let mut pts = match corners.get(&a) {
+        Some(qs) => half_curve(a, b, other(qs, b)),
+        None => vec![to_ptc(a)],
+    };
+    match corners.get(&b) {
+        Some(qs) => pts.extend(half_curve(b, a, other(qs, a)).into_iter().rev()),
+        None => pts.push(to_ptc(b)),
+    }
+    pts
+}
+
+fn other((q1,q2) :&(Pt,Pt), q :Pt) -> Pt { if *q1 == q { *q2 } else { *q1 } }
+
+fn to_ptc(p :Pt) -> PtC { glm::vec2(p.x as f32, p.y as f32) }
+
+/// The half of the curve around corner `p` lying along the segment towards
+/// `q`, from the middle of the curve to where it leaves the segment. The
+/// curve is a quadratic Bézier curve with the corner as control point.
+fn half_curve(p :Pt, q :Pt, r :Pt) -> Vec<PtC> {
+    let c = to_ptc(p);
+    let t0 = c + CURVE_REACH * glm::normalize(&(to_ptc(q) - c));
+    let t1 = c + CURVE_REACH * glm::normalize(&(to_ptc(r) - c));
+    (0..=CURVE_STEPS).map(|i| {
+        let s = 0.5 - 0.5 * i as f32 / CURVE_STEPS as f32;
+        (1.0-s)*(1.0-s)*t0 + 2.0*s*(1.0-s)*c + s*s*t1
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_halves_meet() {
+        let mut linesegs = im::HashSet::new();
+        linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        linesegs.insert((glm::vec2(2,1), glm::vec2(3,1)));
+        let corners = corners(&linesegs);
+        assert_eq!(corners.len(), 2);
+
+        let first = polyline(glm::vec2(0,0), glm::vec2(1,0), &corners);
+        let second = polyline(glm::vec2(1,0), glm::vec2(2,1), &corners);
+        assert_eq!(first[0], glm::vec2(0.0, 0.0));
+        assert_eq!(first[1], glm::vec2(0.5, 0.0));
+        assert!(glm::distance(first.last().unwrap(), &second[0]) < 1e-6);
+        assert_eq!(second.len(), 2 * (CURVE_STEPS + 1));
+    }
+}
@@ -0,0 +1,73 @@
+use matches::matches;
+
+use crate::document::model::*;
+use crate::document::objects::*;
+use crate::document::topology::Topology;
+use crate::document::infview::round_coord;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The track connects two switches or crossings, which would otherwise
+    /// end up in the same (undetectable) section.
+    BetweenSwitches,
+    /// Routes start and end at signals, so the section boundary should be there.
+    AtSignal,
+}
+
+#[derive(Clone, Debug)]
+pub struct Proposal {
+    pub loc :PtC,
+    pub track_idx :usize,
+    pub reason :Reason,
+}
+
+/// Distance (m) along the track within which an existing detector is
+/// considered to already provide the boundary at a signal.
+const SIGNAL_TOLERANCE :f64 = 10.0;
+
+/// Propose detector locations so that every switch gets its own section
+/// and every main signal is at a section boundary.
+pub fn propose(topo :&Topology) -> Vec<Proposal> {
+    let mut proposals = Vec::new();
+    let is_switch = |pt :&Pt| matches!(topo.locations.get(pt),
+                                       Some((NDType::Sw(_),_)) | Some((NDType::Crossing(_),_)));
+
+    for (track_idx,(length,(a,_),(b,_))) in topo.tracks.iter().enumerate() {
+        let objs = &topo.trackobjects[track_idx];
+        let detectors = objs.iter()
+            .filter(|(_,_,f,_)| matches!(f, Function::Detector | Function::TrackCircuitBorder))
+            .map(|(pos,_,_,_)| *pos).collect::<Vec<_>>();
+
+        if is_switch(a) && is_switch(b) && detectors.is_empty() {
+            if let Some(loc) = point_on_track(topo, track_idx, 0.5*length) {
+                proposals.push(Proposal { loc, track_idx, reason: Reason::BetweenSwitches });
+            }
+        }
+
+        for (pos,_,f,_) in objs.iter() {
+            if !matches!(f, Function::MainSignal { .. }) { continue; }
+            if detectors.iter().any(|d| (d - pos).abs() < SIGNAL_TOLERANCE) { continue; }
+            if let Some(loc) = point_on_track(topo, track_idx, *pos) {
+                proposals.push(Proposal { loc, track_idx, reason: Reason::AtSignal });
+            }
+        }
+    }
+    proposals
+}
+
+fn point_on_track(topo :&Topology, track_idx :usize, pos :f64) -> Option<PtC> {
+    topo.interval_map(track_idx, pos, pos).first().cloned()
+}
+
+/// Insert detectors at the proposed locations.
+pub fn apply(model :&mut Model, proposals :&[Proposal]) {
+    for p in proposals {
+        let mut obj = Object { loc: p.loc, tangent: nalgebra_glm::vec2(1,0), 
+                               functions: vec![Function::Detector] };
+        obj.move_to(model, p.loc);
+        let key = round_coord(obj.loc);
+        if !model.objects.contains_key(&key) {
+            model.objects.insert(key, obj);
+        }
+    }
+}
@@ -24,8 +24,12 @@ pub struct DGraph {
     pub tvd_edges :HashMap<rolling_inf::ObjectId, Vec<(rolling_inf::NodeId, rolling_inf::NodeId)>>,
     pub tvd_entry_nodes :HashMap<rolling_inf::ObjectId, Vec<rolling_inf::NodeId>>,
     pub edge_lines :HashMap<(rolling_inf::NodeId, rolling_inf::NodeId), Vec<PtC>>,
+    /// Gradient (‰, rising in the edge direction) of the edges on a slope.
+    pub edge_gradients :HashMap<(rolling_inf::NodeId, rolling_inf::NodeId), f64>,
     pub mileage :HashMap<rolling_inf::NodeId, f64>,
     pub all_paths :(f64, Vec<allpaths::Path>),
+    /// Edges (in both directions) from a trap point switch into its trap branch.
+    pub trap_edges :HashSet<(rolling_inf::NodeId, rolling_inf::NodeId)>,
 }
 
 
@@ -86,6 +90,9 @@ impl DGraphBuilder {
                 let mut objs :Vec<(f64,PtA,Function,Option<AB>)> = trackobjects[track_idx].clone();
                 objs.sort_by_key(|(pos,_,_,_)| OrderedFloat(*pos));
                 for (pos, id, func, dir) in objs {
+                    // Other objects are left out so that editing them does
+                    // not change the dgraph (see topology::same_infrastructure).
+                    if !func.in_dgraph() { continue; }
 
                     // TODO stack overflow here
                     cursor = cursor.advance_single(&dg.dgraph, pos - last_pos).unwrap();
@@ -105,20 +112,8 @@ impl DGraphBuilder {
                             detector_ids.insert(a,id);
                             detector_ids.insert(b,id);
                         },
-                        Function::Derailer => {
-                            // Derailer does not affect rolling infra; ignore for now.
-                        },
-                        Function::TrainProtectionElement => {
-                            // Train protection element does not affect rolling infra; ignore for now.
-                        },
-                        Function::TrainProtectionGroup => {
-                            // Train protection group does not affect rolling infra; ignore for now.
-                        },
-                        Function::PlatformEdge
-                        | Function::SpeedChange
-                        | Function::LevelCrossing
-                        | Function::CrossSection => {
-                            // Track elements not part of rolling infra; ignore.
+                        Function::GradientChange => {
+                            // Only splits the edges; see edge_gradients below.
                         },
                         Function::MainSignal { has_distant, .. }=> { 
                             let c = if matches!(dir,Some(AB::B)) { cursor.reverse(&dg.dgraph) } else { cursor };
@@ -129,9 +124,7 @@ impl DGraphBuilder {
                             static_signals.insert(id, obj);
                             object_ids.insert(obj, id);
                         },
-                        Function::Balise => {
-                            // Balise does not affect rolling infra; ignore for now.
-                        },
+                        _ => {},
                     }
                     last_pos = pos;
                 }
@@ -160,6 +153,15 @@ impl DGraphBuilder {
                                                                               &crossing_edges)
             .expect("could not calc tvd sections.");
 
+        let mut edge_gradients = HashMap::new();
+        for ((a,b),Interval { track_idx, start, end }) in m.edge_tracks.iter() {
+            let gradient = topology.gradient_at(*track_idx, 0.5*(start+end));
+            if gradient != 0.0 {
+                edge_gradients.insert((*a,*b), gradient);
+                edge_gradients.insert((*b,*a), -gradient);
+            }
+        }
+
         let mut edge_lines :HashMap<(rolling_inf::NodeId, rolling_inf::NodeId), Vec<PtC>>
             = m.edge_tracks.into_iter()
             .map(|(edge,Interval { track_idx, start, end })| 
@@ -173,6 +175,22 @@ impl DGraphBuilder {
         //mileage::test_lsq();
         //let mileage = std::iter::empty().collect();
 
+        let mut trap_edges = HashSet::new();
+        for pt in topology.trap_points.iter() {
+            let (side, sw_obj) = match (locs.get(pt), switch_ids.get_by_right(pt)) {
+                (Some((NDType::Sw(side),_)), Some(sw_obj)) => (*side, *sw_obj),
+                _ => continue,
+            };
+            let trunk = m.dgraph.nodes.iter().position(|n| 
+                    matches!(n.edges, rolling_inf::Edges::Switchable(x) if x == sw_obj));
+            if let (Some(trunk), rolling_inf::StaticObject::Switch { left_link, right_link, .. }) 
+                    = (trunk, &m.dgraph.objects[sw_obj]) {
+                let trap = if side.as_port() == Port::Left { left_link.0 } else { right_link.0 };
+                trap_edges.insert((trunk, trap));
+                trap_edges.insert((trap, trunk));
+            }
+        }
+
         let all_paths_length = 100.0;
         let all_paths = (all_paths_length, allpaths::paths(&m.dgraph, all_paths_length));
         Ok(DGraph {
@@ -184,8 +202,10 @@ impl DGraphBuilder {
             tvd_edges: tvd_edges,
             tvd_entry_nodes: tvd_entry_nodes,
             edge_lines: edge_lines,
+            edge_gradients: edge_gradients,
             mileage: mileage,
             all_paths: all_paths,
+            trap_edges: trap_edges,
         })
 
     }
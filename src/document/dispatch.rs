@@ -8,6 +8,9 @@ use crate::document::history::*;
 use crate::document::analysis::*;
 
 use crate::util::VecMap;
+use crate::app;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver};
 
 use rolling::input::staticinfrastructure as rolling_inf;
 use rolling_inf::{ObjectId};
@@ -16,7 +19,7 @@ use nalgebra_glm as glm;
 #[derive(Debug)]
 pub struct DispatchOutput {
     pub dispatch :Dispatch,
-    pub history :History,
+    pub history :Arc<History>,
     pub time_interval :(f32,f32),
     pub max_t :f32,
     pub pos_interval :(f32,f32),
@@ -49,7 +52,7 @@ impl DispatchOutput {
         let (pos1,pos2) = pos_range(&diagram);
         DispatchOutput {
             dispatch,
-            history: history,
+            history: Arc::new(history),
             time_interval: (0.0, t),
             max_t: t,
             pos_interval: (pos1, pos2),
@@ -60,13 +63,40 @@ impl DispatchOutput {
 }
 
 pub type DispatchRef = (Result<usize, (usize,usize)>, f32);
+
+/// Maximum number of instants kept in the cache.
+const INSTANT_CACHE_SIZE :usize = 512;
+/// Instants are computed at multiples of this time step (seconds).
+const INSTANT_STEP :f32 = 0.1;
+/// Number of time steps on each side of the current time that are
+/// computed in the background, so that scrubbing finds them ready.
+const INSTANT_PREFETCH :i64 = 50;
+
+/// Least-recently-used cache of dispatch instants for the current dispatch.
 #[derive(Debug)]
 pub struct InstantCache {
-    cached : Option<(Generation, DispatchRef, Instant)>,
+    /// Dgraph generation, dispatch generation and dispatch for the cached instants.
+    key :Option<(Generation, Generation, Result<usize,(usize,usize)>)>,
+    entries :HashMap<i64, (u64, Instant)>,
+    clock :u64,
+    current :Option<(DispatchRef, i64)>,
+    pending :HashSet<i64>,
+    chan :Option<Receiver<(i64, Instant)>>,
+    cancel :app::CancelToken,
 }
 
 impl InstantCache {
-    pub fn new() -> Self { InstantCache { cached: None } }
+    pub fn new() -> Self { 
+        InstantCache { 
+            key: None,
+            entries: HashMap::new(),
+            clock: 0,
+            current: None,
+            pending: HashSet::new(),
+            chan: None,
+            cancel: app::CancelToken::new(),
+        }
+    }
     
     pub fn update(&mut self, analysis :&Analysis, r:DispatchRef) -> Option<()> {
         let (dgraph_gen,dgraph) = analysis.data().dgraph.as_ref()?;
@@ -81,26 +111,97 @@ impl InstantCache {
                 (gen,ds.get(d)?)
             }
         };
-        let cached_gen = self.cached.as_ref().map(|x| &x.0);
-        let cached_ref = self.cached.as_ref().map(|x| &x.1);
-        if Some(dgraph_gen) != cached_gen || Some(dispatch_gen) != cached_gen || Some(&r) != cached_ref {
-            self.cached = Some((*dispatch_gen, (d, time), Instant::from(time, &dispatch.history, dgraph)));
+
+        let key = (*dgraph_gen, *dispatch_gen, d);
+        if self.key != Some(key) {
+            self.clear();
+            self.key = Some(key);
+        }
+
+        if let Some(rx) = &self.chan {
+            while let Ok((step, instant)) = rx.try_recv() {
+                if self.pending.remove(&step) {
+                    self.entries.insert(step, (self.clock, instant));
+                }
+            }
         }
+
+        let step = (time / INSTANT_STEP).round() as i64;
+        if !self.entries.contains_key(&step) {
+            let instant = Instant::from(step as f32 * INSTANT_STEP, &dispatch.history, dgraph);
+            self.pending.remove(&step);
+            self.entries.insert(step, (0, instant));
+        }
+        self.clock += 1;
+        self.entries.get_mut(&step).unwrap().0 = self.clock;
+        self.current = Some((r, step));
+
+        self.prefetch(analysis, step, dispatch, dgraph);
+        self.evict(step);
         Some(())
     }
 
     pub fn get<'a>(&'a self, q :DispatchRef) -> Option<&'a Instant> {
-        match &self.cached {
-            Some((_g, d,v)) if d == &q => Some(&v),
+        match &self.current {
+            Some((d, step)) if d == &q => self.entries.get(step).map(|(_,v)| v),
             _ => None,
         }
     }
+
+    fn clear(&mut self) {
+        self.cancel.cancel();
+        self.cancel = app::CancelToken::new();
+        self.entries.clear();
+        self.pending.clear();
+        self.chan = None;
+        self.current = None;
+    }
+
+    /// Compute the missing instants around the current time in the
+    /// background, nearest first. Only one batch is outstanding at a time.
+    fn prefetch(&mut self, analysis :&Analysis, step :i64, dispatch :&DispatchOutput, dgraph :&Arc<DGraph>) {
+        if !self.pending.is_empty() { return; }
+        let last_step = (dispatch.max_t / INSTANT_STEP).ceil() as i64;
+        let mut steps = (1..=INSTANT_PREFETCH)
+            .flat_map(|i| vec![step + i, step - i])
+            .filter(|s| *s >= 0 && *s <= last_step && !self.entries.contains_key(s))
+            .collect::<Vec<_>>();
+        if steps.is_empty() { return; }
+        steps.truncate(INSTANT_CACHE_SIZE / 2);
+
+        let (tx,rx) = channel();
+        self.chan = Some(rx);
+        self.pending.extend(steps.iter().cloned());
+        let history = dispatch.history.clone();
+        let dgraph = dgraph.clone();
+        let cancel = self.cancel.clone();
+        analysis.background_jobs().execute_with(app::Priority::Low, cancel.clone(), move || {
+            for s in steps {
+                if cancel.is_canceled() { return; }
+                let instant = Instant::from(s as f32 * INSTANT_STEP, &history, &dgraph);
+                if tx.send((s, instant)).is_err() { return; }
+            }
+        });
+    }
+
+    fn evict(&mut self, current :i64) {
+        while self.entries.len() > INSTANT_CACHE_SIZE {
+            let oldest = self.entries.iter()
+                .filter(|(s,_)| **s != current)
+                .min_by_key(|(_,(used,_))| *used)
+                .map(|(s,_)| *s);
+            match oldest {
+                Some(s) => { self.entries.remove(&s); },
+                None => break,
+            }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum SignalAspect { Stop, Proceed }
 #[derive(Debug, Copy, Clone)]
-pub enum SectionStatus { Free, Reserved, Occupied }
+pub enum SectionStatus { Free, Reserved, Occupied, Released }
 #[derive(Debug, Copy, Clone)]
 pub enum SwitchStatus { Left, Right, Unknown }
 
@@ -368,6 +469,10 @@ fn plot_trains(history :&History, dgraph :&DGraph) -> Vec<TrainGraph> {
     output
 }
 
+/// Seconds that a section is shown as released after a route freed it,
+/// before it is free again.
+const RELEASED_SHOW_TIME :f64 = 5.0;
+
 pub fn draw_infrastructure(time :f64, history :&History, dgraph :&DGraph) -> InfrastructureState  {
     let mut object_state = HashMap::new();
     //let mut signals :HashMap<PtA, SignalAspect> = HashMap::new();
@@ -385,6 +490,9 @@ pub fn draw_infrastructure(time :f64, history :&History, dgraph :&DGraph) -> Inf
 
     let mut reserved : HashSet<ObjectId> = HashSet::new();
     let mut occupied : HashSet<ObjectId> = HashSet::new();
+    // Sections which have been used by a route and then freed again, with
+    // the time they were freed.
+    let mut released : HashMap<ObjectId, f64> = HashMap::new();
 
     let mut t = 0.0;
     for infevent in &history.inf {
@@ -401,15 +509,20 @@ pub fn draw_infrastructure(time :f64, history :&History, dgraph :&DGraph) -> Inf
                 }
             },
             InfrastructureLogEvent::Reserved(tvd,b) => {
-                if *b { reserved.insert(*tvd); } else { reserved.remove(tvd); }
+                if *b { reserved.insert(*tvd); released.remove(tvd); }
+                else { reserved.remove(tvd); released.insert(*tvd, t); }
             },
             InfrastructureLogEvent::Occupied(tvd,b,_,_) => {
-                if *b { occupied.insert(*tvd); } else { occupied.remove(tvd); }
+                if *b { occupied.insert(*tvd); released.remove(tvd); } 
+                else { occupied.remove(tvd); released.insert(*tvd, t); }
             },
             _ => {}, // TODO switches
         }
     }
 
+    for (tvd, t_released) in released {
+        if time - t_released < RELEASED_SHOW_TIME { sections.insert(tvd, SectionStatus::Released); }
+    }
     for tvd in reserved { sections.insert(tvd, SectionStatus::Reserved); }
     for tvd in occupied { sections.insert(tvd, SectionStatus::Occupied); }
 
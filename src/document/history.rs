@@ -1,8 +1,8 @@
-use rolling::input::staticinfrastructure as rolling_inf;
 pub use rolling::output::history::History;
 
 use crate::document::model::*;
 use crate::document::interlocking::*;
+use crate::document::dgraph::DGraph;
 
 pub fn convert_vehicle(vehicle :&Vehicle) -> rolling::railway::dynamics::TrainParams {
     rolling::railway::dynamics::TrainParams {
@@ -14,8 +14,13 @@ pub fn convert_vehicle(vehicle :&Vehicle) -> rolling::railway::dynamics::TrainPa
 }
 
 pub type RouteRefs = Vec<(f32,usize)>;
+
+/// The steepest climb (‰) along a route, from the gradients of its edges.
+fn max_climb(dgraph :&DGraph, route :&RouteInfo) -> f64 {
+    route.path.iter().filter_map(|e| dgraph.edge_gradients.get(e)).cloned().fold(0.0, f64::max)
+}
 pub fn get_history<'a>(vehicles :&[(usize,Vehicle)], 
-                   inf :&rolling_inf::StaticInfrastructure, 
+                   dgraph :&DGraph, 
                    il :&Interlocking,
                    commands :&[(usize, (f64, Command))]) -> Result<(History, RouteRefs) , String> {
 
@@ -45,7 +50,7 @@ pub fn get_history<'a>(vehicles :&[(usize,Vehicle)],
             Command::Train(vehicle, routespec) => {
                 if let Some(route_idx) = il.find_route(routespec) {
                     // get train params
-                    let vehicle = vehicles.iter().find(|(i,v)| i == vehicle).map(|(i,v)| v).cloned().unwrap_or(Vehicle {
+                    let mut vehicle = vehicles.iter().find(|(i,v)| i == vehicle).map(|(i,v)| v).cloned().unwrap_or(Vehicle {
                         name :format!("Default train"),
                         length: 210.0,
                         max_acc: 0.95,
@@ -53,6 +58,12 @@ pub fn get_history<'a>(vehicles :&[(usize,Vehicle)],
                         max_vel: 180.0 / 3.6, // 180 km/h in m/s
                     });
 
+                    // The train dynamics have no gradients, so the grade resistance on the
+                    // steepest climb of the first route is taken off the acceleration.
+                    let climb = max_climb(dgraph, &il.routes[*route_idx]);
+                    let grade_acc = (9.81 * climb / 1000.0) as f32;
+                    vehicle.max_acc = (vehicle.max_acc - grade_acc).max(0.1 * vehicle.max_acc);
+
                     let train_params = convert_vehicle(&vehicle);
 
                     // just make some name for now
@@ -73,7 +84,7 @@ pub fn get_history<'a>(vehicles :&[(usize,Vehicle)],
 
     // TODO don't convert on the fly?
     //println!("Starting rolling");
-    let history = rolling::evaluate_plan(inf,
+    let history = rolling::evaluate_plan(&dgraph.rolling_inf,
                                          &il.routes.iter().map(|r| r.route.clone()).enumerate().collect(),
                                          &rolling::input::dispatch::Dispatch { actions: dispatch }, None);
 
@@ -13,8 +13,28 @@ pub struct InfView {
     pub view :View,
     pub instant_cache: dispatch::InstantCache,
     pub drag_ghost :Option<DragState>,
-    pub clipboard :crate::document::model::Model,
     pub pending_fit_view: bool,
+    /// Size of the canvas in the last frame.
+    pub canvas_size: ImVec2,
+    pub pending_center: Option<PtC>,
+    /// Zoom to show this rectangle in the next frame.
+    pub pending_fit_bounds: Option<(PtC, PtC)>,
+    /// Rectangle marked on the canvas until the time (from `igGetTime`),
+    /// to show where a search result is.
+    pub highlight: Option<(PtC, PtC, f64)>,
+    /// Selected OCP station marker, as index into `Model::railml_ocps`.
+    pub selected_ocp: Option<usize>,
+    /// Selected annotation, as id in `Model::annotations`.
+    pub selected_annotation: Option<usize>,
+    /// World position where the context menu was opened.
+    pub context_pos: PtC,
+    /// Number of copies and grid offset between them for the duplicate
+    /// command in the context menu.
+    pub duplicate_count: i32,
+    pub duplicate_offset: [i32; 2],
+    /// Object type last chosen in the insert object tool, listed first
+    /// the next time the tool is opened.
+    pub last_object_type: Function,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +48,12 @@ pub struct DragState {
 pub enum Action {
     Normal(NormalState),
     DrawingLine(Option<Pt>),
+    /// Drawing a track through the clicked points, finished by
+    /// double-clicking or pressing Esc.
+    DrawingPolyline(Vec<Pt>),
+    /// Erasing the line segments dragged over, deleted when the mouse
+    /// button is released.
+    Erasing(HashSet<(Pt,Pt)>),
     SelectObjectType,
     InsertObject(Option<Object>),
 }
@@ -38,6 +64,14 @@ pub enum NormalState {
     Default,
     SelectWindow(ImVec2),
     DragMove(MoveType),
+    /// Moving an OCP station marker, by index into `Model::railml_ocps`.
+    DragOcp(usize),
+    /// Moving an annotation by id, at its anchor point if true, otherwise
+    /// at its text.
+    DragAnnotation(usize, bool),
+    /// Changing the length of a platform edge by dragging the end of its
+    /// bar, or the start if false.
+    DragPlatformEdge(PtA, bool),
 }
 
 
@@ -52,8 +86,17 @@ impl InfView {
             view: View::default(),
             instant_cache: dispatch::InstantCache::new(),
             drag_ghost: None,
-            clipboard: crate::document::model::Model::empty(),
             pending_fit_view: false,
+            canvas_size: ImVec2 { x: 0.0, y: 0.0 },
+            pending_center: None,
+            pending_fit_bounds: None,
+            highlight: None,
+            selected_ocp: None,
+            selected_annotation: None,
+            context_pos: glm::zero(),
+            duplicate_count: 1,
+            duplicate_offset: [0, -2],
+            last_object_type: OBJECT_TYPES[0],
         }
     }
 }
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use log::*;
 use rolling::input::staticinfrastructure as rolling_inf;
 use crate::document::model::*;
 use crate::document::dgraph::*;
@@ -10,6 +11,9 @@ pub struct Interlocking {
     pub boundary_out_routes: HashMap<Pt, Vec<usize>>,
     pub signal_routes: HashMap<PtA, Vec<usize>>,
     pub alternatives :HashMap<(Ref,Ref), Vec<usize>>,
+    /// Imported railML routes from each signal that match none of the
+    /// routes found in the layout.
+    pub unmatched_railml_routes :HashMap<PtA, Vec<String>>,
 }
 
 impl Interlocking {
@@ -33,6 +37,8 @@ pub struct RouteInfo {
     pub route :rolling_inf::Route,
     pub id :RouteSpec,
     pub path :Vec<(rolling_inf::NodeId, rolling_inf::NodeId)>,
+    /// Id of the imported railML route with the same entry and exit.
+    pub railml_id :Option<String>,
 }
 
 impl RouteInfo {
@@ -56,7 +62,10 @@ pub fn calc(dgraph :&DGraph) -> Interlocking {
     let mut signal_routes = HashMap::new();
     let mut route_info = Vec::new();
     let mut alternatives : HashMap<(Ref,Ref), Vec<usize>> = HashMap::new();
-    for (route_idx, (route,path)) in routes.into_iter().enumerate() {
+    // Routes running into the branch of a trap point would derail the train.
+    let routes = routes.into_iter()
+        .filter(|(_,path)| !path.iter().any(|e| dgraph.trap_edges.contains(e)));
+    for (route_idx, (mut route,path)) in routes.enumerate() {
         let from = match route.entry {
             rolling_inf::RouteEntryExit::Boundary(Some(boundary)) => {
                 // Boundary is a NodeId, which should be tied to a Pt in the Dgraph
@@ -96,12 +105,91 @@ pub fn calc(dgraph :&DGraph) -> Interlocking {
         alternative_vec.push(route_idx);
         let alternative = alternative_vec.len()-1;
 
-        route_info.push(RouteInfo { route, id: RouteSpec { from, to, alternative }, path });
+        add_flank_protection(dgraph, &mut route, &path);
+        route_info.push(RouteInfo { route, id: RouteSpec { from, to, alternative }, path, railml_id: None });
     }
 
 
     let interlocking = Interlocking { routes: route_info, 
-        boundary_routes, boundary_out_routes, signal_routes, alternatives };
+        boundary_routes, boundary_out_routes, signal_routes, alternatives,
+        unmatched_railml_routes: HashMap::new() };
 
     interlocking
 }
+
+/// How far from a trap point a route can pass and still be protected by it.
+const FLANK_PROTECTION_DISTANCE :f64 = 200.0;
+
+/// Trap points beside a route are its flank protection. The route sets
+/// them to the trap branch, so that vehicles running away from the siding
+/// behind a trap point are derailed before they reach the route. A trap
+/// point protects the route if its through branch leads to the route
+/// without passing another facing switch.
+fn add_flank_protection(dgraph :&DGraph, route :&mut rolling_inf::Route,
+                        path :&[(rolling_inf::NodeId, rolling_inf::NodeId)]) {
+    let inf = &dgraph.rolling_inf;
+    let on_path = path.iter().flat_map(|(a,b)| vec![*a, *b, inf.nodes[*a].other_node, inf.nodes[*b].other_node])
+        .collect::<HashSet<_>>();
+    for (trunk, trap) in dgraph.trap_edges.iter() {
+        let (sw, left_link, right_link) = match inf.nodes[*trunk].edges {
+            rolling_inf::Edges::Switchable(sw) => match &inf.objects[sw] {
+                rolling_inf::StaticObject::Switch { left_link, right_link, .. } => (sw, *left_link, *right_link),
+                _ => continue,
+            },
+            // The other direction of the trap edge.
+            _ => continue,
+        };
+        if route.resources.switch_positions.iter().any(|(x,_)| *x == sw) { continue; }
+        let (trap_pos, through) = if left_link.0 == *trap { (rolling_inf::SwitchPosition::Left, right_link) }
+                                  else { (rolling_inf::SwitchPosition::Right, left_link) };
+
+        let mut node = through.0;
+        let mut dist = through.1;
+        let protects = loop {
+            if on_path.contains(&node) { break true; }
+            if dist > FLANK_PROTECTION_DISTANCE { break false; }
+            match inf.nodes[inf.nodes[node].other_node].edges {
+                rolling_inf::Edges::Single(other, d) => { node = other; dist += d; },
+                _ => break false,
+            }
+        };
+        if protects {
+            route.resources.switch_positions.push((sw, trap_pos));
+            if let Some(release) = route.resources.releases.last_mut() {
+                release.resources.push(sw);
+            }
+        }
+    }
+}
+
+/// Match the routes of the imported railML interlocking to the routes found
+/// in the layout, by their entry and exit signals. Routes between the same
+/// signals are matched in order.
+pub fn add_railml_routes(il :&mut Interlocking, model :&Model) {
+    let railml_il = match &model.railml_interlocking { Some(x) => x, None => return };
+    let signals :HashMap<&str, PtA> = model.railml_objects.iter()
+        .flat_map(|(pta, infos)| infos.iter().filter_map(move |info| match info {
+            RailMLObjectInfo::Signal { id, .. } => Some((id.as_str(), *pta)),
+            _ => None,
+        })).collect();
+
+    for route in &railml_il.routes {
+        let signal = |r :&Option<String>| r.as_ref().and_then(|r| signals.get(railml_il.signal_ref(r)).cloned());
+        let (entry, exit) = match (signal(&route.entry), signal(&route.exit)) {
+            (Some(entry), Some(exit)) => (entry, exit),
+            _ => {
+                warn!("Imported route {:?} does not start and end at known signals", route.id);
+                continue;
+            },
+        };
+        let found = il.alternatives.get(&(Ref::Object(entry), Ref::Object(exit)))
+            .and_then(|alts| alts.iter().find(|idx| il.routes[**idx].railml_id.is_none()).cloned());
+        match found {
+            Some(idx) => { il.routes[idx].railml_id = Some(route.id.clone()); },
+            None => {
+                warn!("Imported route {:?} was not found in the layout", route.id);
+                il.unmatched_railml_routes.entry(entry).or_insert(Vec::new()).push(route.id.clone());
+            },
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{HashMap, HashSet};
+use matches::matches;
+use nalgebra_glm as glm;
+
+use crate::document::model::*;
+use crate::document::objects::*;
+use crate::document::topology::{self, Topology};
+use crate::document::interlocking::{self, Interlocking};
+use crate::document::dgraph::DGraphBuilder;
+use crate::document::infview::unround_coord;
+use crate::util;
+use crate::units::Units;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+            Severity::Info => "Info",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    DanglingOpenEnd,
+    MissingDetector,
+    SignalWithoutRoute,
+    UnconnectedObject,
+    OverlappingLineSegs,
+    SignalSpacing,
+    /// Reported by a plugin check.
+    Plugin,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity :Severity,
+    pub kind :IssueKind,
+    pub location :Ref,
+    pub message :String,
+}
+
+impl Issue {
+    /// World coordinate to center the view on when jumping to the issue.
+    pub fn pos(&self) -> PtC {
+        ref_pos(&self.location)
+    }
+}
+
+/// World coordinate of a model element, for centering the view on it.
+pub fn ref_pos(location :&Ref) -> PtC {
+    match *location {
+        Ref::Node(p) => glm::vec2(p.x as f32, p.y as f32),
+        Ref::LineSeg(a,b) => glm::vec2((a.x + b.x) as f32 * 0.5, (a.y + b.y) as f32 * 0.5),
+        Ref::Object(p) => unround_coord(p),
+    }
+}
+
+/// Derive the topology and interlocking and check the model, without the
+/// background jobs of `Analysis`. Used for validating files from the command line.
+pub fn validate(model :&Model) -> Result<Vec<Issue>, String> {
+    let topo = topology::convert(model, 50.0).map_err(|_| "Topology conversion failed".to_string())?;
+    let dgraph = DGraphBuilder::convert(&topo).map_err(|_| "Dgraph conversion failed".to_string())?;
+    let il = interlocking::calc(&dgraph);
+    Ok(lint(model, &topo, Some(&il)))
+}
+
+/// Check the model for common design mistakes. The interlocking is optional
+/// because it is computed in the background, and the route check is
+/// skipped until it is available.
+pub fn lint(model :&Model, topo :&Topology, il :Option<&Interlocking>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    dangling_open_ends(model, topo, &mut issues);
+    missing_detectors(topo, &mut issues);
+    if let Some(il) = il { signals_without_routes(model, il, &mut issues); }
+    unconnected_objects(model, &mut issues);
+    overlapping_linesegs(model, &mut issues);
+    issues.sort_by_key(|i| i.severity);
+    issues
+}
+
+/// Open ends are expected at the model boundary. An open end which has
+/// other track on both sides of it (horizontally) is probably a track
+/// that was not finished.
+fn dangling_open_ends(model :&Model, topo :&Topology, issues :&mut Vec<Issue>) {
+    let xs = model.linesegs.iter().flat_map(|(a,b)| vec![a.x, b.x]);
+    let (min_x, max_x) = match (xs.clone().min(), xs.max()) {
+        (Some(a),Some(b)) => (a,b),
+        _ => return,
+    };
+
+    for (pt,(nd,_)) in topo.locations.iter() {
+        if let NDType::OpenEnd = nd {
+            if pt.x > min_x && pt.x < max_x {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    kind: IssueKind::DanglingOpenEnd,
+                    location: Ref::Node(*pt),
+                    message: format!("Open end at ({},{}) is inside the station area.", pt.x, pt.y),
+                });
+            }
+        }
+    }
+}
+
+/// Two switches connected directly by a track without a detector in
+/// between will end up in the same train detection section.
+fn missing_detectors(topo :&Topology, issues :&mut Vec<Issue>) {
+    let is_switch = |pt :&Pt| matches!(topo.locations.get(pt),
+                                       Some((NDType::Sw(_),_)) | Some((NDType::Crossing(_),_)));
+    for (track_idx,(_,(a,_),(b,_))) in topo.tracks.iter().enumerate() {
+        if !(is_switch(a) && is_switch(b)) { continue; }
+        let has_detector = topo.trackobjects[track_idx].iter()
+            .any(|(_,_,f,_)| matches!(f, Function::Detector | Function::TrackCircuitBorder));
+        if has_detector { continue; }
+
+        let segs = &topo.track_segments[track_idx];
+        let location = segs.get(segs.len()/2).map(|(p1,p2)| Ref::LineSeg(*p1,*p2))
+            .unwrap_or(Ref::Node(*a));
+        issues.push(Issue {
+            severity: Severity::Warning,
+            kind: IssueKind::MissingDetector,
+            location,
+            message: format!("No detector between switches at ({},{}) and ({},{}).", a.x, a.y, b.x, b.y),
+        });
+    }
+}
+
+fn signals_without_routes(model :&Model, il :&Interlocking, issues :&mut Vec<Issue>) {
+    for (pta,obj) in model.objects.iter() {
+        let is_main = obj.functions.iter().any(|f| matches!(f,
+                Function::MainSignal { kind: SignalKind::Main, .. } |
+                Function::MainSignal { kind: SignalKind::Combined, .. } |
+                Function::MainSignal { kind: SignalKind::Shunting, .. }));
+        if !is_main { continue; }
+        if il.signal_routes.get(pta).map(|r| r.is_empty()).unwrap_or(true) {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                kind: IssueKind::SignalWithoutRoute,
+                location: Ref::Object(*pta),
+                message: format!("Signal at ({:.1},{:.1}) does not start any route.", obj.loc.x, obj.loc.y),
+            });
+        }
+    }
+}
+
+fn unconnected_objects(model :&Model, issues :&mut Vec<Issue>) {
+    for (pta,obj) in model.objects.iter() {
+        if model.get_closest_lineseg(obj.loc).is_none() {
+            issues.push(Issue {
+                severity: Severity::Error,
+                kind: IssueKind::UnconnectedObject,
+                location: Ref::Object(*pta),
+                message: format!("Object at ({:.1},{:.1}) is not placed on a track.", obj.loc.x, obj.loc.y),
+            });
+        }
+    }
+}
+
+/// Line segments are stored with arbitrary length, so two segments can
+/// cover the same stretch of track without being equal.
+fn overlapping_linesegs(model :&Model, issues :&mut Vec<Issue>) {
+    let mut unit_steps :HashMap<(Pt,Pt), Vec<(Pt,Pt)>> = HashMap::new();
+    for (a,b) in model.linesegs.iter() {
+        let pts = util::unit_step_diag_line(*a,*b);
+        for (p1,p2) in pts.iter().zip(pts.iter().skip(1)) {
+            unit_steps.entry(util::order_ivec(*p1,*p2)).or_insert(Vec::new()).push((*a,*b));
+        }
+    }
+
+    let mut reported = HashSet::new();
+    for (_,segs) in unit_steps.iter() {
+        if segs.len() < 2 { continue; }
+        for (a,b) in segs.iter() {
+            if reported.insert((*a,*b)) {
+                issues.push(Issue {
+                    severity: Severity::Error,
+                    kind: IssueKind::OverlappingLineSegs,
+                    location: Ref::LineSeg(*a,*b),
+                    message: format!("Line segment ({},{})-({},{}) overlaps another segment.",
+                                     a.x, a.y, b.x, b.y),
+                });
+            }
+        }
+    }
+}
+
+/// Braking distance (m) from the given speed (m/s) with constant deceleration (m/s^2).
+pub fn braking_distance(speed :f64, deceleration :f64) -> f64 {
+    speed*speed / (2.0*deceleration)
+}
+
+/// Find signals where the shortest route to the next signal or boundary
+/// is shorter than the braking distance from the design speed (km/h).
+/// This depends on the user configuration, so it is not part of the
+/// background lint pass. Distances in the messages are given in `units`.
+pub fn signal_spacing(model :&Model, il :&Interlocking, design_speed :f64, deceleration :f64,
+                      units :&Units) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if !(deceleration > 0.0) { return issues; }
+    let required = braking_distance(design_speed / 3.6, deceleration);
+    for (pta,routes) in il.signal_routes.iter() {
+        let shortest = routes.iter().map(|r| il.routes[*r].route.length)
+            .fold(std::f64::INFINITY, f64::min);
+        if shortest < required {
+            let loc = model.objects.get(pta).map(|o| o.loc).unwrap_or(unround_coord(*pta));
+            issues.push(Issue {
+                severity: Severity::Warning,
+                kind: IssueKind::SignalSpacing,
+                location: Ref::Object(*pta),
+                message: format!("Signal at ({:.1},{:.1}) is {} from the next signal, braking distance is {}.",
+                                 loc.x, loc.y, units.length(shortest), units.length(required)),
+            });
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_linesegs_reported() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(2,0), glm::vec2(4,0)));
+        let mut issues = Vec::new();
+        overlapping_linesegs(&model, &mut issues);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|i| i.kind == IssueKind::OverlappingLineSegs));
+    }
+
+    #[test]
+    fn braking_distance_from_speed() {
+        // 80 km/h with 0.5 m/s^2
+        let d = braking_distance(80.0/3.6, 0.5);
+        assert!((d - 493.8).abs() < 0.1);
+    }
+}
@@ -0,0 +1,367 @@
+//! Three-way merge of documents. Changes made in two versions of a document
+//! since their common ancestor are combined. Track segments are merged as
+//! sets and never conflict; nodes, objects, line segment attributes,
+//! vehicles, dispatches, selection groups, annotations and view bookmarks
+//! conflict when both versions changed the same element differently. Plans
+//! and the imported railML data are taken from our version.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::document::model::*;
+use crate::document::objects::Object;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Choice { Ours, Theirs }
+
+/// An element changed differently in both versions, as (our, their) values.
+/// `None` means the element was removed.
+#[derive(Debug, Clone)]
+pub enum Conflict {
+    Node(Pt, Option<NDType>, Option<NDType>),
+    Object(PtA, Option<Object>, Option<Object>),
+    Vehicle(usize, Option<Vehicle>, Option<Vehicle>),
+    Dispatch(usize, Option<Dispatch>, Option<Dispatch>),
+    LineSeg((Pt,Pt), Option<LineSegAttributes>, Option<LineSegAttributes>),
+    SelectionGroup(usize, Option<SelectionGroup>, Option<SelectionGroup>),
+    Annotation(usize, Option<Annotation>, Option<Annotation>),
+    ViewBookmark(usize, Option<ViewBookmark>, Option<ViewBookmark>),
+}
+
+pub struct MergeResult {
+    /// The merged model, using our version of the conflicting elements.
+    pub model :Model,
+    pub conflicts :Vec<Conflict>,
+}
+
+fn merge_set<T :Hash + Eq + Clone>(base :&im::HashSet<T>, ours :&im::HashSet<T>,
+                                   theirs :&im::HashSet<T>) -> im::HashSet<T> {
+    let mut result = ours.clone();
+    for x in theirs.iter() { if !base.contains(x) { result.insert(x.clone()); } }
+    for x in base.iter() { if !theirs.contains(x) { result.remove(x); } }
+    result
+}
+
+fn merge_map<K :Hash + Eq + Clone, V :PartialEq + Clone>(
+    base :&im::HashMap<K,V>, ours :&im::HashMap<K,V>, theirs :&im::HashMap<K,V>,
+    mut conflict :impl FnMut(K, Option<V>, Option<V>)) -> im::HashMap<K,V> {
+    let mut result = ours.clone();
+    let keys = base.keys().chain(ours.keys()).chain(theirs.keys()).cloned()
+        .collect::<std::collections::HashSet<K>>();
+    for k in keys {
+        let (b,o,t) = (base.get(&k), ours.get(&k), theirs.get(&k));
+        if o == t || t == b { continue; }
+        if o == b {
+            match t {
+                Some(t) => { result.insert(k, t.clone()); },
+                None => { result.remove(&k); },
+            }
+        } else {
+            conflict(k, o.cloned(), t.cloned());
+        }
+    }
+    result
+}
+
+/// Merge lists with generation ids. Elements added in their version get
+/// new ids, which are returned as a map from their id to the new id.
+fn merge_list<T :PartialEq + Clone>(base :&ImShortGenList<T>, ours :&ImShortGenList<T>,
+                                    theirs :&ImShortGenList<T>,
+                                    mut conflict :impl FnMut(usize, Option<T>, Option<T>))
+    -> (ImShortGenList<T>, HashMap<usize,usize>) {
+    let mut result = ours.clone();
+    let mut new_ids = HashMap::new();
+    for (id,t) in theirs.iter() {
+        match (base.get(*id), ours.get(*id)) {
+            (None, o) => {
+                if o != Some(t) { new_ids.insert(*id, result.insert(t.clone())); }
+            },
+            (Some(b), _) if b == t => {},
+            (Some(b), Some(o)) if o == b => { *result.get_mut(*id).unwrap() = t.clone(); },
+            (Some(_), Some(o)) if o == t => {},
+            (Some(_), o) => conflict(*id, o.cloned(), Some(t.clone())),
+        }
+    }
+    for (id,b) in base.iter() {
+        if theirs.get(*id).is_some() { continue; }
+        match ours.get(*id) {
+            Some(o) if o == b => { result.remove(*id); },
+            Some(o) => conflict(*id, Some(o.clone()), None),
+            None => {},
+        }
+    }
+    (result, new_ids)
+}
+
+pub fn merge(base :&Model, ours :&Model, theirs :&Model) -> MergeResult {
+    let mut conflicts = Vec::new();
+    let mut model = ours.clone();
+    model.linesegs = merge_set(&base.linesegs, &ours.linesegs, &theirs.linesegs);
+    model.trap_points = merge_set(&base.trap_points, &ours.trap_points, &theirs.trap_points);
+    model.node_data = merge_map(&base.node_data, &ours.node_data, &theirs.node_data,
+                                |k,o,t| conflicts.push(Conflict::Node(k,o,t)));
+    model.objects = merge_map(&base.objects, &ours.objects, &theirs.objects,
+                              |k,o,t| conflicts.push(Conflict::Object(k,o,t)));
+    model.lineseg_attributes = merge_map(&base.lineseg_attributes, &ours.lineseg_attributes,
+                                         &theirs.lineseg_attributes,
+                                         |k,o,t| conflicts.push(Conflict::LineSeg(k,o,t)));
+    let (vehicles, new_vehicle_ids) = merge_list(&base.vehicles, &ours.vehicles, &theirs.vehicles,
+                                                 |k,o,t| conflicts.push(Conflict::Vehicle(k,o,t)));
+    model.vehicles = vehicles;
+
+    // Their dispatches refer to their vehicle ids.
+    let mut their_dispatches = theirs.dispatches.clone();
+    if !new_vehicle_ids.is_empty() {
+        for (_,d) in their_dispatches.iter_mut() {
+            for (_,(_,cmd)) in d.commands.iter_mut() {
+                if let Command::Train(v,_) = cmd {
+                    if let Some(new_id) = new_vehicle_ids.get(v) { *v = *new_id; }
+                }
+            }
+        }
+    }
+    let (dispatches, _) = merge_list(&base.dispatches, &ours.dispatches, &their_dispatches,
+                                     |k,o,t| conflicts.push(Conflict::Dispatch(k,o,t)));
+    model.dispatches = dispatches;
+
+    let (groups, _) = merge_list(&base.selection_groups, &ours.selection_groups, &theirs.selection_groups,
+                                 |k,o,t| conflicts.push(Conflict::SelectionGroup(k,o,t)));
+    model.selection_groups = groups;
+    let (annotations, _) = merge_list(&base.annotations, &ours.annotations, &theirs.annotations,
+                                      |k,o,t| conflicts.push(Conflict::Annotation(k,o,t)));
+    model.annotations = annotations;
+    let (bookmarks, _) = merge_list(&base.view_bookmarks, &ours.view_bookmarks, &theirs.view_bookmarks,
+                                    |k,o,t| conflicts.push(Conflict::ViewBookmark(k,o,t)));
+    model.view_bookmarks = bookmarks;
+
+    MergeResult { model, conflicts }
+}
+
+fn set_list_item<T :Clone>(list :&mut ImShortGenList<T>, id :usize, value :&Option<T>) {
+    match value {
+        Some(v) => match list.get_mut(id) {
+            Some(x) => { *x = v.clone(); },
+            None => { list.insert(v.clone()); },
+        },
+        None => { list.remove(id); },
+    }
+}
+
+fn pick<'a,T>(choice :Choice, ours :&'a T, theirs :&'a T) -> &'a T {
+    if choice == Choice::Ours { ours } else { theirs }
+}
+
+impl Conflict {
+    /// Use their version of the element in the model.
+    fn apply_theirs(&self, model :&mut Model) {
+        match self {
+            Conflict::Node(pt,_,t) => match t {
+                Some(t) => { model.node_data.insert(*pt, *t); },
+                None => { model.node_data.remove(pt); },
+            },
+            Conflict::Object(pta,_,t) => match t {
+                Some(t) => { model.objects.insert(*pta, t.clone()); },
+                None => { model.objects.remove(pta); },
+            },
+            Conflict::Vehicle(id,_,t) => set_list_item(&mut model.vehicles, *id, t),
+            Conflict::Dispatch(id,_,t) => set_list_item(&mut model.dispatches, *id, t),
+            Conflict::LineSeg(seg,_,t) => match t {
+                Some(t) => { model.lineseg_attributes.insert(*seg, t.clone()); },
+                None => { model.lineseg_attributes.remove(seg); },
+            },
+            Conflict::SelectionGroup(id,_,t) => set_list_item(&mut model.selection_groups, *id, t),
+            Conflict::Annotation(id,_,t) => set_list_item(&mut model.annotations, *id, t),
+            Conflict::ViewBookmark(id,_,t) => set_list_item(&mut model.view_bookmarks, *id, t),
+        }
+    }
+
+    /// The conflicting element, e.g. `Object at (10.0, 2.5)`.
+    pub fn describe(&self) -> String {
+        let name = |v :&Option<Vehicle>| v.as_ref().map(|v| v.name.clone());
+        let dname = |d :&Option<Dispatch>| d.as_ref().map(|d| d.name.clone());
+        match self {
+            Conflict::Node(pt,_,_) => format!("Node at ({}, {})", pt.x, pt.y),
+            Conflict::Object(pta,_,_) => format!("Object at ({:.1}, {:.1})",
+                                                 pta.x as f32 / 10.0, pta.y as f32 / 10.0),
+            Conflict::Vehicle(_,o,t) => format!("Vehicle \"{}\"", name(o).or(name(t)).unwrap_or_default()),
+            Conflict::Dispatch(_,o,t) => format!("Dispatch \"{}\"", dname(o).or(dname(t)).unwrap_or_default()),
+            Conflict::LineSeg((a,b),_,_) => format!("Track from ({}, {}) to ({}, {})", a.x, a.y, b.x, b.y),
+            Conflict::SelectionGroup(_,o,t) => {
+                let gname = |g :&Option<SelectionGroup>| g.as_ref().map(|g| g.name.clone());
+                format!("Selection group \"{}\"", gname(o).or(gname(t)).unwrap_or_default())
+            },
+            Conflict::Annotation(_,o,t) => {
+                let text = |a :&Option<Annotation>| a.as_ref().map(|a| a.text.clone());
+                format!("Annotation \"{}\"", text(o).or(text(t)).unwrap_or_default())
+            },
+            Conflict::ViewBookmark(_,o,t) => {
+                let bname = |b :&Option<ViewBookmark>| b.as_ref().map(|b| b.name.clone());
+                format!("Bookmark \"{}\"", bname(o).or(bname(t)).unwrap_or_default())
+            },
+        }
+    }
+
+    /// One version of the element, in a few words.
+    pub fn describe_version(&self, choice :Choice) -> String {
+        let removed = || "removed".to_string();
+        match self {
+            Conflict::Node(_,o,t) => pick(choice,o,t).map(|n| format!("{:?}", n)).unwrap_or_else(removed),
+            Conflict::Object(_,o,t) => pick(choice,o,t).as_ref().map(|obj| {
+                let functions = obj.functions.iter().map(|f| crate::scripting::function_name(f))
+                    .collect::<Vec<_>>().join(", ");
+                format!("{} at ({:.1}, {:.1})", functions, obj.loc.x, obj.loc.y)
+            }).unwrap_or_else(removed),
+            Conflict::Vehicle(_,o,t) => pick(choice,o,t).as_ref().map(|v| {
+                format!("{}, {:.0} m, {:.1} m/s", v.name, v.length, v.max_vel)
+            }).unwrap_or_else(removed),
+            Conflict::Dispatch(_,o,t) => pick(choice,o,t).as_ref().map(|d| {
+                format!("{}, {} commands", d.name, d.commands.len())
+            }).unwrap_or_else(removed),
+            Conflict::LineSeg(_,o,t) => pick(choice,o,t).as_ref().map(|a| format!("{:?}", a))
+                .unwrap_or_else(removed),
+            Conflict::SelectionGroup(_,o,t) => pick(choice,o,t).as_ref().map(|g| {
+                format!("{}, {} elements", g.name, g.refs.len())
+            }).unwrap_or_else(removed),
+            Conflict::Annotation(_,o,t) => pick(choice,o,t).as_ref().map(|a| {
+                format!("{} at ({:.1}, {:.1})", a.text, a.pos.x, a.pos.y)
+            }).unwrap_or_else(removed),
+            Conflict::ViewBookmark(_,o,t) => pick(choice,o,t).as_ref().map(|b| {
+                format!("{} at ({:.1}, {:.1})", b.name, b.center.x, b.center.y)
+            }).unwrap_or_else(removed),
+        }
+    }
+}
+
+impl MergeResult {
+    /// The merged model with a choice for each conflict.
+    pub fn resolve(&self, choices :&[Choice]) -> Model {
+        let mut model = self.model.clone();
+        for (conflict, choice) in self.conflicts.iter().zip(choices.iter()) {
+            if *choice == Choice::Theirs { conflict.apply_theirs(&mut model); }
+        }
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::objects::Function;
+    use nalgebra_glm as glm;
+
+    #[test]
+    fn merge_linesegs_and_objects() {
+        let obj = |x| Object { loc: glm::vec2(x, 0.0), tangent: glm::vec2(1, 0),
+                               functions: vec![Function::Detector] };
+        let mut base = Model::empty();
+        base.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        base.objects.insert(glm::vec2(5,0), obj(0.5));
+        base.vehicles.insert(Vehicle::default());
+
+        let mut ours = base.clone();
+        ours.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        ours.objects.insert(glm::vec2(5,0), obj(0.6));
+
+        let mut theirs = base.clone();
+        theirs.linesegs.remove(&(glm::vec2(0,0), glm::vec2(1,0)));
+        theirs.objects.insert(glm::vec2(5,0), obj(0.7));
+        theirs.vehicles.insert(Vehicle { name: "Freight".to_string(), ..Vehicle::default() });
+
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.model.linesegs.len(), 1);
+        assert!(result.model.linesegs.contains(&(glm::vec2(1,0), glm::vec2(2,0))));
+        assert_eq!(result.model.vehicles.iter().count(), 2);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.model.objects[&glm::vec2(5,0)], obj(0.6));
+        let resolved = result.resolve(&[Choice::Theirs]);
+        assert_eq!(resolved.objects[&glm::vec2(5,0)], obj(0.7));
+    }
+
+    #[test]
+    fn merge_lineseg_attributes() {
+        let seg = (glm::vec2(0,0), glm::vec2(1,0));
+        let attrs = |voltage| LineSegAttributes { voltage: Some(voltage), ..Default::default() };
+        let mut base = Model::empty();
+        base.linesegs.insert(seg);
+        base.lineseg_attributes.insert(seg, attrs(750.0));
+
+        let mut ours = base.clone();
+        ours.lineseg_attributes.insert(seg, attrs(1500.0));
+        let mut theirs = base.clone();
+        theirs.lineseg_attributes.insert((glm::vec2(1,0), glm::vec2(2,0)), attrs(15000.0));
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.model.lineseg_attributes.len(), 2);
+        assert_eq!(result.model.lineseg_attributes[&seg], attrs(1500.0));
+
+        theirs.lineseg_attributes.remove(&seg);
+        let result = merge(&base, &ours, &theirs);
+        assert!(matches!(result.conflicts.as_slice(), [Conflict::LineSeg(_, Some(_), None)]));
+        assert!(!result.resolve(&[Choice::Theirs]).lineseg_attributes.contains_key(&seg));
+    }
+
+    #[test]
+    fn merge_selection_groups() {
+        let group = |name :&str, n| SelectionGroup { name: name.to_string(),
+            refs: (0..n).map(|x| Ref::Node(glm::vec2(x, 0))).collect() };
+        let mut base = Model::empty();
+        let id = base.selection_groups.insert(group("Station", 1));
+
+        let mut ours = base.clone();
+        ours.selection_groups.insert(group("Yard", 2));
+        let mut theirs = base.clone();
+        *theirs.selection_groups.get_mut(id).unwrap() = group("Station", 3);
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.model.selection_groups.iter().count(), 2);
+        assert_eq!(result.model.selection_groups.get(id), Some(&group("Station", 3)));
+
+        *ours.selection_groups.get_mut(id).unwrap() = group("Station", 2);
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.resolve(&[Choice::Theirs]).selection_groups.get(id), Some(&group("Station", 3)));
+    }
+
+    #[test]
+    fn merge_annotations() {
+        let note = |text :&str| Annotation { text: text.to_string(), pos: glm::vec2(1.0, 1.0),
+                                             anchor: None, dimension: false };
+        let mut base = Model::empty();
+        let id = base.annotations.insert(note("Depot"));
+
+        let mut ours = base.clone();
+        ours.annotations.remove(id);
+        let mut theirs = base.clone();
+        theirs.annotations.insert(note("Bridge"));
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        let texts = result.model.annotations.iter().map(|(_,a)| a.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Bridge".to_string()]);
+
+        *theirs.annotations.get_mut(id).unwrap() = note("Old depot");
+        let result = merge(&base, &ours, &theirs);
+        assert!(matches!(result.conflicts.as_slice(), [Conflict::Annotation(_, None, Some(_))]));
+        assert_eq!(result.resolve(&[Choice::Theirs]).annotations.get(id), Some(&note("Old depot")));
+    }
+
+    #[test]
+    fn merge_view_bookmarks() {
+        let bookmark = |name :&str, x| ViewBookmark { name: name.to_string(), center: glm::vec2(x, 0.0), scale: 10 };
+        let mut base = Model::empty();
+        let id = base.view_bookmarks.insert(bookmark("North", 0.0));
+
+        let mut ours = base.clone();
+        *ours.view_bookmarks.get_mut(id).unwrap() = bookmark("North", 5.0);
+        let mut theirs = base.clone();
+        theirs.view_bookmarks.insert(bookmark("South", 50.0));
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.model.view_bookmarks.iter().count(), 2);
+        assert_eq!(result.model.view_bookmarks.get(id), Some(&bookmark("North", 5.0)));
+
+        *theirs.view_bookmarks.get_mut(id).unwrap() = bookmark("North", 7.0);
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.resolve(&[Choice::Ours]).view_bookmarks.get(id), Some(&bookmark("North", 5.0)));
+    }
+}
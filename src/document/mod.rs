@@ -13,13 +13,23 @@ pub mod history;
 pub mod dispatch;
 pub mod mileage;
 pub mod plan;
+pub mod lint;
+pub mod paths;
+pub mod stats;
+pub mod detector_placement;
+pub mod objecttree;
+pub mod merge;
+pub mod clipboard;
+pub mod search;
 
 // graphical view representation
 pub mod infview;
 pub mod view;
+pub mod curves;
 //pub mod diagram;
 
 use crate::file;
+use crate::config::Config;
 use crate::app::*;
 use model::*;
 use infview::*;
@@ -65,6 +75,19 @@ impl Document {
         }
     }
 
+    /// A loaded document, with the undo history saved in its file.
+    pub fn from_saved(model :model::Model, history :Option<file::UndoHistory>, bg :BackgroundJobs) -> Self {
+        let mut doc = Self::from_model(model, bg);
+        if let Some(history) = history { doc.analysis.restore_undo_history(history); }
+        doc
+    }
+
+    /// The undo history to save with the document, if enabled.
+    pub fn saved_history(&self, config :&Config) -> Option<file::UndoHistory> {
+        let depth = config.saved_undo_steps;
+        if depth > 0 { Some(self.analysis.undo_history(depth as usize)) } else { None }
+    }
+
     pub fn set_saved_file(&mut self, filename :String) {
         self.saved_model = *self.analysis.generation();
         self.fileinfo.set_saved_file(filename);
@@ -78,6 +101,22 @@ pub enum DispatchView {
     Auto(AutoDispatchView),
 }
 
+impl DispatchView {
+    /// The dispatch and time shown, if any.
+    pub fn dispatch_ref(&self) -> Option<dispatch::DispatchRef> {
+        match self {
+            DispatchView::Manual(ManualDispatchView { dispatch_idx, time, .. }) => {
+               Some((Ok(*dispatch_idx),*time as _))
+            },
+            DispatchView::Auto(AutoDispatchView { plan_idx,
+                dispatch: Some(ManualDispatchView { dispatch_idx, time, .. }), .. }) => {
+               Some((Err((*plan_idx, *dispatch_idx)), *time as _))
+            },
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone,Copy)]
 pub struct ManualDispatchView {
     pub dispatch_idx :usize,
@@ -3,9 +3,11 @@ use crate::document::objects::*;
 use crate::document::infview::*;
 use crate::util::*;
 use ordered_float::OrderedFloat;
+use matches::matches;
 use serde::{Serialize,Deserialize};
 
 use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize,Deserialize)]
@@ -42,7 +44,7 @@ pub type PtC = glm::Vec2;
 pub type Vc = Pt;
 
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 #[derive(Debug)]
 #[derive(Serialize,Deserialize)]
 pub struct Vehicle {
@@ -126,7 +128,7 @@ pub struct RouteSpec {
     pub alternative: usize,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 #[derive(Debug)]
 #[derive(Serialize,Deserialize)]
 pub enum Command {
@@ -137,7 +139,7 @@ pub enum Command {
 pub type Commands = Vec<(usize,(f64,Command))>;
 
 #[derive(Serialize,Deserialize)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Dispatch {
     pub name :String,
     generation :usize,
@@ -291,16 +293,193 @@ pub struct Model {
     pub plans :ImShortGenList<PlanSpec>,
     #[serde(default)]
     pub railml_metadata: Option<railmlio::model::Metadata>,
+    // The imported railML data is never edited, so it is kept behind Arcs
+    // to be shared between the undo snapshots instead of copied into each.
+    #[serde(default)]
+    pub railml_track_groups: Arc<Vec<railmlio::model::TrackGroup>>,
     #[serde(default)]
-    pub railml_track_groups: Vec<railmlio::model::TrackGroup>,
+    pub railml_ocps: Arc<Vec<railmlio::model::Ocp>>,
+    /// Positions of the station markers of `railml_ocps` by OCP id. They
+    /// are moved in the infrastructure view and exported as geoCoord.
     #[serde(default)]
-    pub railml_ocps: Vec<railmlio::model::Ocp>,
+    pub ocp_positions: im::HashMap<String, PtC>,
     #[serde(default)]
-    pub railml_states: Vec<railmlio::model::State>,
+    pub railml_states: Arc<Vec<railmlio::model::State>>,
     #[serde(default)]
-    pub railml_tracks: Vec<RailMLTrackInfo>,
+    pub railml_interlocking: Option<Arc<railmlio::model::Interlocking>>,
+    #[serde(default)]
+    pub railml_tracks: Arc<Vec<RailMLTrackInfo>>,
     #[serde(default)]
     pub railml_objects: im::HashMap<PtA, Vec<RailMLObjectInfo>>,
+    /// Names of the track ends in `node_data` that were imported from
+    /// railML macroscopic nodes.
+    #[serde(default)]
+    pub railml_nodes: im::HashMap<Pt, RailMLNodeInfo>,
+    /// Attributes of line segments, from the imported tracks they are part
+    /// of or edited in the track properties panel.
+    #[serde(default)]
+    pub lineseg_attributes: im::HashMap<(Pt,Pt), LineSegAttributes>,
+    /// The imported railML file, if it was embedded at import.
+    #[serde(default)]
+    pub railml_source: Option<Arc<RailMLSourceFile>>,
+    #[serde(default)]
+    pub file_settings: FileSettings,
+    /// Switches whose deviating branch is a trap point (catch point).
+    /// The trap branch is only used for flank protection, never as a
+    /// running path in routes.
+    #[serde(default)]
+    pub trap_points: im::HashSet<Pt>,
+    #[serde(default)]
+    pub selection_groups: ImShortGenList<SelectionGroup>,
+    #[serde(default)]
+    pub annotations: ImShortGenList<Annotation>,
+    #[serde(default)]
+    pub view_bookmarks: ImShortGenList<ViewBookmark>,
+}
+
+/// A named position and zoom level of the infrastructure view, for going
+/// back to a part of the document such as a station.
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ViewBookmark {
+    pub name :String,
+    /// World position at the middle of the view.
+    pub center :PtC,
+    pub scale :usize,
+}
+
+/// A named set of elements, saved from the selection so that it can be
+/// selected again, for example to move, delete or export the same part of
+/// the infrastructure.
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct SelectionGroup {
+    pub name :String,
+    pub refs :Vec<Ref>,
+}
+
+/// A note left on the infrastructure view. The text is drawn at `pos`,
+/// with a leader line to the anchor if there is one. A dimension is
+/// instead drawn as a measurement line from `pos` to the anchor, with the
+/// text in the middle.
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct Annotation {
+    pub text :String,
+    pub pos :PtC,
+    pub anchor :Option<AnnotationAnchor>,
+    pub dimension :bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum AnnotationAnchor {
+    Point(PtC),
+    /// Anchored to an element, following it when it is moved.
+    Element(Ref),
+}
+
+impl Annotation {
+    pub fn anchor_pos(&self) -> Option<PtC> {
+        match self.anchor? {
+            AnnotationAnchor::Point(p) => Some(p),
+            AnnotationAnchor::Element(r) => Some(crate::document::lint::ref_pos(&r)),
+        }
+    }
+}
+
+/// Import and export choices stored with the document, so they are reused
+/// the next time instead of entered again.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct FileSettings {
+    #[serde(default)]
+    pub railml_export_path :Option<String>,
+    #[serde(default)]
+    pub import :Option<ImportSettings>,
+    /// Layout of exported railML files, to match the files of other tools.
+    #[serde(default)]
+    pub export :Option<railmlio::write::WriteOptions>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ImportSettings {
+    pub filename :String,
+    pub auto_scale :bool,
+    pub bundle_source :bool,
+}
+
+/// A gzip-compressed copy of the railML file a model was imported from.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct RailMLSourceFile {
+    /// Path of the file at the time of import.
+    pub filename :String,
+    #[serde(with = "serde_bytes")]
+    pub gzip_data :Vec<u8>,
+}
+
+impl RailMLSourceFile {
+    pub fn compress(filename :&str, text :&str) -> Result<Self, std::io::Error> {
+        use std::io::Write;
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(text.as_bytes())?;
+        Ok(RailMLSourceFile { filename: filename.to_string(), gzip_data: enc.finish()? })
+    }
+
+    pub fn decompress(&self) -> Result<String, std::io::Error> {
+        use std::io::Read;
+        let mut s = String::new();
+        flate2::read::GzDecoder::new(&self.gzip_data[..]).read_to_string(&mut s)?;
+        Ok(s)
+    }
+}
+
+impl std::fmt::Debug for RailMLSourceFile {
+    fn fmt(&self, f :&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RailMLSourceFile({:?}, {} bytes)", self.filename, self.gzip_data.len())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct LineSegAttributes {
+    /// Electrification type, like "overhead", or "none" for track known
+    /// not to be electrified.
+    pub electrification :Option<String>,
+    pub voltage :Option<f64>,
+    pub frequency :Option<f64>,
+    /// Types of the track conditions on the line segment.
+    pub track_conditions :Vec<String>,
+    /// Real length (m), used instead of the default length of a line
+    /// segment in the topology and in exported track lengths.
+    #[serde(default)]
+    pub length :Option<f64>,
+    /// Maximum speed (km/h), exported as speed changes.
+    #[serde(default)]
+    pub max_speed :Option<f64>,
+    /// Name and code of the track, for tracks not imported from railML or
+    /// to rename imported ones.
+    #[serde(default)]
+    pub track_name :Option<String>,
+    #[serde(default)]
+    pub track_code :Option<String>,
+}
+
+impl LineSegAttributes {
+    pub fn is_electrified(&self) -> Option<bool> {
+        self.electrification.as_ref().map(|t| t != "none")
+    }
+}
+
+/// A track end that was a railML macroscopic node, a boundary towards
+/// infrastructure outside the document.
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct RailMLNodeInfo {
+    pub id: String,
+    pub ocp_ref: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -331,6 +510,11 @@ pub enum RailMLObjectInfo {
         switchable: Option<bool>,
         ocp_station_ref: Option<String>,
         dir: railmlio::model::TrackDirection,
+        /// Speed announcements, referring to speed changes by id.
+        #[serde(default)]
+        speeds: Vec<railmlio::model::SignalSpeed>,
+        #[serde(default)]
+        etcs: Option<railmlio::model::Etcs>,
     },
     TrainDetector {
         id: String,
@@ -361,6 +545,17 @@ pub enum RailMLObjectInfo {
     Balise {
         id: String,
         name: Option<String>,
+        #[serde(default)]
+        group_ref: Option<String>,
+    },
+    BaliseGroup {
+        id: String,
+        name: Option<String>,
+        dir: Option<railmlio::model::TrackDirection>,
+        country_id: Option<String>,
+        group_id: Option<String>,
+        ndx: Option<bool>,
+        linking_accuracy: Option<f64>,
     },
     PlatformEdge {
         id: String,
@@ -376,6 +571,19 @@ pub enum RailMLObjectInfo {
         vmax: Option<String>,
         signalised: Option<bool>,
     },
+    GradientChange {
+        id: String,
+        dir: railmlio::model::TrackDirection,
+        slope: f64,
+        transition_length: Option<f64>,
+        transition_radius: Option<f64>,
+    },
+    RadiusChange {
+        id: String,
+        dir: railmlio::model::TrackDirection,
+        radius: f64,
+        superelevation: Option<f64>,
+    },
     LevelCrossing {
         id: String,
         protection: Option<String>,
@@ -389,6 +597,51 @@ pub enum RailMLObjectInfo {
     },
 }
 
+impl RailMLObjectInfo {
+    /// The id of the element in the imported document.
+    pub fn id(&self) -> &str {
+        use RailMLObjectInfo::*;
+        match self {
+            Signal { id, .. } | TrainDetector { id, .. } | TrackCircuitBorder { id, .. } | Derailer { id, .. }
+            | TrainProtectionElement { id, .. } | TrainProtectionElementGroup { id, .. } | Balise { id, .. }
+            | BaliseGroup { id, .. } | PlatformEdge { id, .. } | SpeedChange { id, .. } | GradientChange { id, .. }
+            | RadiusChange { id, .. } | LevelCrossing { id, .. } | CrossSection { id, .. } => id,
+        }
+    }
+
+    /// Use the new names of renamed ids, for the id of the element and the
+    /// ids it refers to.
+    pub fn rename_ids(&mut self, renamed :&HashMap<String,String>) {
+        use RailMLObjectInfo::*;
+        let rename = |id :&mut String| {
+            if let Some(new) = renamed.get(id.as_str()) { *id = new.clone(); }
+        };
+        match self {
+            Signal { id, ocp_station_ref, speeds, .. } => {
+                rename(id);
+                ocp_station_ref.iter_mut().for_each(rename);
+                speeds.iter_mut().filter_map(|s| s.speed_change_ref.as_mut()).for_each(rename);
+            },
+            TrainProtectionElementGroup { id, element_refs } => {
+                rename(id);
+                element_refs.iter_mut().for_each(rename);
+            },
+            Balise { id, group_ref, .. } => {
+                rename(id);
+                group_ref.iter_mut().for_each(rename);
+            },
+            CrossSection { id, ocp_ref, .. } => {
+                rename(id);
+                ocp_ref.iter_mut().for_each(rename);
+            },
+            TrainDetector { id, .. } | TrackCircuitBorder { id, .. } | Derailer { id, .. }
+            | TrainProtectionElement { id, .. } | BaliseGroup { id, .. } | PlatformEdge { id, .. }
+            | SpeedChange { id, .. } | GradientChange { id, .. } | RadiusChange { id, .. }
+            | LevelCrossing { id, .. } => rename(id),
+        }
+    }
+}
+
 
 #[derive(Hash,PartialEq,Eq)]
 #[derive(Copy,Clone)]
@@ -481,19 +734,346 @@ impl Model {
 
     pub fn delete(&mut self, x :Ref) {
         match x {
-            Ref::LineSeg(a,b) => { self.linesegs.remove(&(a,b)); },
+            Ref::LineSeg(a,b) => {
+                self.linesegs.remove(&(a,b));
+                self.remove_lineseg_attrs(a,b);
+            },
             Ref::Node(a) => { self.node_data.remove(&a); },
             Ref::Object(p) => { self.objects.remove(&p); },
         }
     }
 
+    /// Delete line segments, with the data which was only there for them:
+    /// the objects closest to a deleted segment, and node data, trap points
+    /// and track end names which no longer fit the tracks meeting at their
+    /// point. Returns the deleted elements.
+    pub fn erase_linesegs(&mut self, segs :&HashSet<(Pt,Pt)>) -> HashSet<Ref> {
+        let mut erased :HashSet<Ref> = segs.iter().filter(|l| self.linesegs.contains(*l))
+            .map(|(a,b)| Ref::LineSeg(*a,*b)).collect();
+        let objects = self.objects.iter()
+            .filter(|(_,o)| self.get_closest_lineseg(o.loc).map(|(l,_,_)| segs.contains(&l)).unwrap_or(false))
+            .map(|(p,_)| *p).collect::<Vec<_>>();
+        for (a,b) in segs {
+            self.linesegs.remove(&(*a,*b));
+            self.remove_lineseg_attrs(*a,*b);
+        }
+        for p in objects {
+            self.objects.remove(&p);
+            self.railml_objects.remove(&p);
+            erased.insert(Ref::Object(p));
+        }
+
+        let mut degree :HashMap<Pt,usize> = HashMap::new();
+        for (a,b) in self.linesegs.iter() {
+            *degree.entry(*a).or_insert(0) += 1;
+            *degree.entry(*b).or_insert(0) += 1;
+        }
+        let ends = segs.iter().flat_map(|(a,b)| vec![*a,*b]).collect::<HashSet<_>>();
+        for p in ends {
+            let n = degree.get(&p).cloned().unwrap_or(0);
+            let fits = match self.node_data.get(&p) {
+                Some(NDType::OpenEnd) | Some(NDType::BufferStop) => n == 1,
+                Some(NDType::Sw(_)) => n == 3,
+                Some(NDType::Crossing(_)) => n == 4,
+                _ => false,
+            };
+            if !fits && self.node_data.remove(&p).is_some() { erased.insert(Ref::Node(p)); }
+            if n != 3 { self.trap_points.remove(&p); }
+            if n == 0 { self.railml_nodes.remove(&p); }
+        }
+        erased
+    }
+
+    /// The elements of a selection group which are still in the model.
+    pub fn group_selection(&self, group :&SelectionGroup) -> HashSet<Ref> {
+        group.refs.iter().filter(|r| self.contains_ref(r)).cloned().collect()
+    }
+
+    /// Replace the references in selection groups and annotation anchors
+    /// for which `f` gives a new reference, for elements which were moved.
+    pub fn rename_element_refs(&mut self, f :&dyn Fn(Ref) -> Option<Ref>) {
+        for (_,group) in self.selection_groups.iter_mut() {
+            for r in group.refs.iter_mut() {
+                if let Some(x) = f(*r) { *r = x; }
+            }
+        }
+        for (_,a) in self.annotations.iter_mut() {
+            if let Some(AnnotationAnchor::Element(r)) = &mut a.anchor {
+                if let Some(x) = f(*r) { *r = x; }
+            }
+        }
+    }
+
+    /// Remove deleted elements from the selection groups, and keep the
+    /// annotations anchored to them where the elements were.
+    pub fn remove_element_refs(&mut self, refs :&HashSet<Ref>) {
+        for (_,group) in self.selection_groups.iter_mut() {
+            group.refs.retain(|r| !refs.contains(r));
+        }
+        for (_,a) in self.annotations.iter_mut() {
+            if let Some(AnnotationAnchor::Element(r)) = a.anchor {
+                if refs.contains(&r) { a.anchor = a.anchor_pos().map(AnnotationAnchor::Point); }
+            }
+        }
+    }
+
+    /// An id for a new railML object, made from the prefix and the first
+    /// number not used by another object in the model.
+    pub fn new_object_id(&self, prefix :&str) -> String {
+        let used :HashSet<&str> = self.railml_objects.values().flatten().map(|i| i.id()).collect();
+        (1..).map(|n| format!("{}{}", prefix, n)).find(|id| !used.contains(id.as_str())).unwrap()
+    }
+
+    /// The railML data of the object at `pta` accepted by `is_kind`. When
+    /// the object has none, it is created by `new` from a new id with the
+    /// given prefix, so that properties edited on objects drawn in the
+    /// editor are also exported.
+    pub fn object_info_mut(&mut self, pta :PtA, prefix :&str,
+                           is_kind :impl Fn(&RailMLObjectInfo) -> bool,
+                           new :impl FnOnce(String) -> RailMLObjectInfo) -> &mut RailMLObjectInfo {
+        if !self.railml_objects.get(&pta).map(|infos| infos.iter().any(&is_kind)).unwrap_or(false) {
+            let info = new(self.new_object_id(prefix));
+            self.railml_objects.entry(pta).or_insert_with(Vec::new).push(info);
+        }
+        self.railml_objects.get_mut(&pta).unwrap().iter_mut().find(|i| is_kind(i)).unwrap()
+    }
+
+    /// The railML data of the platform edge at `pta`, see `object_info_mut`.
+    pub fn platform_edge_info_mut(&mut self, pta :PtA) -> &mut RailMLObjectInfo {
+        self.object_info_mut(pta, "pe", |i| matches!(i, RailMLObjectInfo::PlatformEdge { .. }),
+            |id| RailMLObjectInfo::PlatformEdge {
+                id,
+                name: None,
+                dir: railmlio::model::TrackDirection::Up,
+                side: None,
+                height: None,
+                length: None,
+            })
+    }
+
+    /// The railML data of the level crossing at `pta`, see `object_info_mut`.
+    pub fn level_crossing_info_mut(&mut self, pta :PtA) -> &mut RailMLObjectInfo {
+        self.object_info_mut(pta, "lc", |i| matches!(i, RailMLObjectInfo::LevelCrossing { .. }),
+            |id| RailMLObjectInfo::LevelCrossing { id, protection: None, angle: None })
+    }
+
+    /// The attributes of the line segment between `a` and `b`, in either
+    /// direction.
+    pub fn lineseg_attrs(&self, a :Pt, b :Pt) -> Option<&LineSegAttributes> {
+        self.lineseg_attributes.get(&(a,b)).or_else(|| self.lineseg_attributes.get(&(b,a)))
+    }
+
+    /// Remove the attributes of the line segment between `a` and `b`, in
+    /// either direction.
+    pub fn remove_lineseg_attrs(&mut self, a :Pt, b :Pt) -> Option<LineSegAttributes> {
+        let ba = self.lineseg_attributes.remove(&(b,a));
+        self.lineseg_attributes.remove(&(a,b)).or(ba)
+    }
+
+    /// Move the attributes of line segments which were moved from the first
+    /// to the second key. All are removed before inserting, as line segments
+    /// may swap places.
+    pub fn move_lineseg_data(&mut self, moves :&[((Pt,Pt), (Pt,Pt))]) {
+        let data = moves.iter()
+            .filter_map(|((a,b),to)| self.remove_lineseg_attrs(*a,*b).map(|attrs| (*to, attrs)))
+            .collect::<Vec<_>>();
+        for (to, attrs) in data { self.lineseg_attributes.insert(to, attrs); }
+    }
+
+    /// Move the railML data of objects which were moved from the first to
+    /// the second key. All are removed before inserting, as objects may
+    /// swap places.
+    pub fn move_object_data(&mut self, moves :&[(PtA, PtA)]) {
+        let data = moves.iter()
+            .filter_map(|(a,b)| self.railml_objects.remove(a).map(|infos| (*b, infos)))
+            .collect::<Vec<_>>();
+        for (b, infos) in data { self.railml_objects.insert(b, infos); }
+    }
+
+    /// Move the trap point flags of switches which were moved from the
+    /// first to the second point. All are removed before inserting, as
+    /// nodes may swap places.
+    pub fn move_trap_points(&mut self, moves :&[(Pt, Pt)]) {
+        let moved = moves.iter().filter(|(a,_)| self.trap_points.remove(a).is_some())
+            .map(|(_,b)| *b).collect::<Vec<_>>();
+        for p in moved { self.trap_points.insert(p); }
+    }
+
+    fn contains_ref(&self, x :&Ref) -> bool {
+        match x {
+            Ref::LineSeg(a,b) => self.linesegs.contains(&(*a,*b)),
+            Ref::Node(a) => self.node_data.contains_key(a) || 
+                self.linesegs.iter().any(|(p1,p2)| p1 == a || p2 == a),
+            Ref::Object(p) => self.objects.contains_key(p),
+        }
+    }
+
+    /// Remove what refers to infrastructure no longer in the model, for a
+    /// part of a model: dispatch commands with routes outside it, plans
+    /// visiting elements outside it, annotations not anchored to its
+    /// elements, and elements outside it from the selection groups.
+    pub fn remove_dangling_refs(&mut self) {
+        let m = self.clone();
+        let route_kept = |r :&RouteSpec| m.contains_ref(&r.from) && m.contains_ref(&r.to);
+        let mut dispatches = ImShortGenList::default();
+        for (_,d) in m.dispatches.iter() {
+            let mut d = d.clone();
+            d.commands.retain(|(_,(_,cmd))| match cmd {
+                Command::Train(_,r) | Command::Route(r) => route_kept(r),
+            });
+            if !d.commands.is_empty() { dispatches.insert(d); }
+        }
+        let mut plans = ImShortGenList::default();
+        for (_,p) in m.plans.iter() {
+            let kept = p.trains.iter().all(|(_,(_,visits))| visits.iter().all(|(_,v)| 
+                v.locs.iter().all(|l| match l { Ok(r) => m.contains_ref(r), Err(_) => true })));
+            if kept { plans.insert(p.clone()); }
+        }
+        let mut annotations = ImShortGenList::default();
+        for (_,a) in m.annotations.iter() {
+            if let Some(AnnotationAnchor::Element(r)) = a.anchor {
+                if m.contains_ref(&r) { annotations.insert(a.clone()); }
+            }
+        }
+        let mut groups = ImShortGenList::default();
+        for (_,g) in m.selection_groups.iter() {
+            let refs = g.refs.iter().filter(|r| m.contains_ref(r)).cloned().collect::<Vec<_>>();
+            if !refs.is_empty() { groups.insert(SelectionGroup { name: g.name.clone(), refs }); }
+        }
+        self.dispatches = dispatches;
+        self.plans = plans;
+        self.annotations = annotations;
+        self.selection_groups = groups;
+    }
+
+    /// Ids of the imported railML elements, in a fixed order.
+    fn railml_ids(&self) -> Vec<String> {
+        let mut ids = Vec::new();
+        for t in self.railml_tracks.iter() {
+            ids.extend(vec![t.id.clone(), t.begin_id.clone(), t.end_id.clone()]);
+        }
+        let mut objects = self.railml_objects.values().flatten().map(|o| o.id().to_string()).collect::<Vec<_>>();
+        let mut nodes = self.railml_nodes.values().map(|n| n.id.clone()).collect::<Vec<_>>();
+        objects.sort();
+        nodes.sort();
+        ids.extend(objects);
+        ids.extend(nodes);
+        ids.extend(self.railml_ocps.iter().map(|o| o.id.clone()));
+        ids.extend(self.railml_track_groups.iter().map(|g| g.id.clone()));
+        ids.extend(self.railml_states.iter().map(|s| s.id.clone()));
+        ids
+    }
+
+    /// New ids for the imported railML elements of another model whose ids
+    /// are already used in this model, as (old, new). A number is appended,
+    /// like `sig1_2`.
+    pub fn railml_id_renames(&self, other :&Model) -> Vec<(String,String)> {
+        let existing = self.railml_ids().into_iter().collect::<HashSet<_>>();
+        let other_ids = other.railml_ids();
+        let mut used = existing.iter().chain(other_ids.iter()).cloned().collect::<HashSet<_>>();
+        let mut renames :Vec<(String,String)> = Vec::new();
+        for id in other_ids {
+            if !existing.contains(&id) || renames.iter().any(|(old,_)| *old == id) { continue; }
+            let new = (2..).map(|n| format!("{}_{}", id, n)).find(|x| !used.contains(x)).unwrap();
+            used.insert(new.clone());
+            renames.push((id, new));
+        }
+        renames
+    }
+
+    /// Add the infrastructure of another model, moved by `offset`. Imported
+    /// railML ids which are already used in this model are renamed, and the
+    /// renamed ids are returned as (old, new). Vehicles are added unless
+    /// there is one with the same name, and the dispatches, plans and
+    /// document settings of this model are kept.
+    pub fn add_infrastructure(&mut self, other :Model, offset :Pt) -> Vec<(String,String)> {
+        let renames = self.railml_id_renames(&other);
+        let renamed :HashMap<String,String> = renames.iter().cloned().collect();
+        let rename = |id :&mut String| {
+            if let Some(new) = renamed.get(id.as_str()) { *id = new.clone(); }
+        };
+
+        let offset_c = glm::vec2(offset.x as f32, offset.y as f32);
+        let offset_a = round_coord(offset_c);
+        for (a,b) in other.linesegs.iter() { self.linesegs.insert((a + offset, b + offset)); }
+        for ((a,b),attrs) in other.lineseg_attributes.iter() {
+            self.lineseg_attributes.insert((a + offset, b + offset), attrs.clone());
+        }
+        for (p,nd) in other.node_data.iter() { self.node_data.insert(p + offset, *nd); }
+        for p in other.trap_points.iter() { self.trap_points.insert(p + offset); }
+        for (p,obj) in other.objects.iter() {
+            let mut obj = obj.clone();
+            obj.loc += offset_c;
+            self.objects.insert(p + offset_a, obj);
+        }
+        for (p,infos) in other.railml_objects.iter() {
+            let mut infos = infos.clone();
+            infos.iter_mut().for_each(|i| i.rename_ids(&renamed));
+            self.railml_objects.insert(p + offset_a, infos);
+        }
+        for (p,info) in other.railml_nodes.iter() {
+            let mut info = info.clone();
+            rename(&mut info.id);
+            info.ocp_ref.iter_mut().for_each(rename);
+            self.railml_nodes.insert(p + offset, info);
+        }
+
+        let mut tracks = (*self.railml_tracks).clone();
+        for t in other.railml_tracks.iter() {
+            let mut t = t.clone();
+            for id in vec![&mut t.id, &mut t.begin_id, &mut t.end_id] { rename(id); }
+            t.segments.iter_mut().for_each(|(a,b)| { *a += offset; *b += offset; });
+            tracks.push(t);
+        }
+        self.railml_tracks = Arc::new(tracks);
+
+        let mut ocps = (*self.railml_ocps).clone();
+        for ocp in other.railml_ocps.iter() {
+            let mut ocp = ocp.clone();
+            if let Some(p) = other.ocp_positions.get(&ocp.id) {
+                let mut id = ocp.id.clone();
+                rename(&mut id);
+                self.ocp_positions.insert(id, p + offset_c);
+            }
+            rename(&mut ocp.id);
+            ocp.prop_equipment.iter_mut().flat_map(|e| e.track_refs.iter_mut()).for_each(rename);
+            ocps.push(ocp);
+        }
+        self.railml_ocps = Arc::new(ocps);
+
+        let mut groups = (*self.railml_track_groups).clone();
+        for group in other.railml_track_groups.iter() {
+            let mut group = group.clone();
+            rename(&mut group.id);
+            group.track_refs.iter_mut().for_each(|r| rename(&mut r.r#ref));
+            groups.push(group);
+        }
+        self.railml_track_groups = Arc::new(groups);
+
+        let mut states = (*self.railml_states).clone();
+        for state in other.railml_states.iter() {
+            let mut state = state.clone();
+            rename(&mut state.id);
+            states.push(state);
+        }
+        self.railml_states = Arc::new(states);
+
+        for (_,v) in other.vehicles.iter() {
+            if !self.vehicles.iter().any(|(_,x)| x.name == v.name) {
+                self.vehicles.insert(v.clone());
+            }
+        }
+        renames
+    }
+
 
 }
 
-use std::collections::HashSet;
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
 pub enum EditClass {
     MoveObjects(HashSet<Ref>),
+    MoveOcp(usize),
     CommandTime(usize,usize),
     VehicleName(usize),
     VehicleLen(usize),
@@ -503,6 +1083,14 @@ pub enum EditClass {
 
     DispatchName(usize),
     PlanName(usize),
+    GroupName(usize),
+    MoveAnnotation(usize),
+    AnnotationText(usize),
+    SignalCode(PtA),
+    LevelCrossingProtection(PtA),
+    Electrification(Vec<(Pt,Pt)>),
+    TrackName(Vec<(Pt,Pt)>),
+    TrackCode(Vec<(Pt,Pt)>),
 }
 
 
@@ -511,6 +1099,8 @@ pub struct Undoable<T, C> {
     stack :Vec<T>,
     pointer: usize,
     class :Option<C>,
+    /// Maximum number of undo steps kept, or `None` for no limit.
+    max_depth :Option<usize>,
 }
 
 impl<T : Clone + Default, C : Eq> Undoable<T,C> {
@@ -527,6 +1117,38 @@ impl<T : Clone + Default, C : Eq> Undoable<T,C> {
             stack: vec![x],
             pointer: 0,
             class: None,
+            max_depth: None,
+        }
+    }
+
+    /// Restore saved values, see `steps`.
+    pub fn from_steps(stack :Vec<T>, pointer :usize, class :Option<C>) -> Undoable<T,C> {
+        assert!(pointer < stack.len());
+        Undoable { stack, pointer, class, max_depth: None }
+    }
+
+    /// The values at most `depth` steps before and after the current one,
+    /// the index of the current one among them, and the class of the last
+    /// change.
+    pub fn steps(&self, depth :usize) -> (Vec<T>, usize, Option<&C>) {
+        let first = self.pointer.saturating_sub(depth);
+        let last = (self.pointer + depth + 1).min(self.stack.len());
+        (self.stack[first..last].to_vec(), self.pointer - first, self.class.as_ref())
+    }
+
+    /// Limit the number of undo steps, discarding the oldest ones.
+    pub fn set_max_depth(&mut self, depth :Option<usize>) {
+        self.max_depth = depth;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if let Some(depth) = self.max_depth {
+            let excess = self.pointer.saturating_sub(depth);
+            if excess > 0 {
+                self.stack.drain(..excess);
+                self.pointer -= excess;
+            }
         }
     }
 
@@ -542,6 +1164,7 @@ impl<T : Clone + Default, C : Eq> Undoable<T,C> {
             self.pointer += 1;
             self.stack.truncate(self.pointer);
             self.stack.push(v);
+            self.trim();
         }
         self.class = cl;
     }
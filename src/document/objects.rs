@@ -10,7 +10,7 @@ use backend_glfw::imgui::*;
 use nalgebra_glm as glm;
 
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 #[derive(Debug)]
 #[derive(Serialize,Deserialize)]
 pub struct Object {
@@ -19,7 +19,7 @@ pub struct Object {
     pub functions :Vec<Function>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize,Deserialize)]
 pub enum SignalKind {
     Main,
@@ -29,7 +29,7 @@ pub enum SignalKind {
     Shunting,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize,Deserialize)]
 pub enum Function {
     MainSignal { has_distant: bool, kind: SignalKind },
@@ -39,22 +39,165 @@ pub enum Function {
     TrainProtectionElement,
     TrainProtectionGroup,
     Balise,
+    BaliseGroup,
     PlatformEdge,
     SpeedChange,
+    GradientChange,
+    RadiusChange,
     LevelCrossing,
     CrossSection,
 }
 
+/// Object types offered by the insert object tool, in the order they are
+/// listed. Each is the function a new object of the type starts with.
+pub const OBJECT_TYPES :&[Function] = &[
+    Function::MainSignal { has_distant: false, kind: SignalKind::Main },
+    Function::MainSignal { has_distant: true, kind: SignalKind::Combined },
+    Function::MainSignal { has_distant: false, kind: SignalKind::Distant },
+    Function::MainSignal { has_distant: false, kind: SignalKind::Repeater },
+    Function::MainSignal { has_distant: false, kind: SignalKind::Shunting },
+    Function::Detector,
+    Function::TrackCircuitBorder,
+    Function::Derailer,
+    Function::TrainProtectionElement,
+    Function::TrainProtectionGroup,
+    Function::Balise,
+    Function::BaliseGroup,
+    Function::PlatformEdge,
+    Function::SpeedChange,
+    Function::GradientChange,
+    Function::RadiusChange,
+    Function::LevelCrossing,
+    Function::CrossSection,
+];
+
+impl Function {
+    /// True if objects with this function split the tracks in the dgraph:
+    /// signals and train detection, which are part of the rolling
+    /// infrastructure, and gradient changes, which end the edges of one
+    /// gradient. Other objects have no effect on the dgraph or interlocking.
+    pub fn in_dgraph(&self) -> bool {
+        match self {
+            Function::MainSignal { .. } | Function::Detector | Function::TrackCircuitBorder
+                | Function::GradientChange => true,
+            Function::Derailer | Function::TrainProtectionElement | Function::TrainProtectionGroup
+                | Function::Balise | Function::BaliseGroup | Function::PlatformEdge
+                | Function::SpeedChange | Function::RadiusChange | Function::LevelCrossing
+                | Function::CrossSection => false,
+        }
+    }
+}
+
+/// Track length in meters of one grid step, as in the topology used for
+/// simulation.
+pub const GRID_STEP_LENGTH :f64 = 50.0;
+
+/// Length in meters of platform edges without a railML length.
+pub const DEFAULT_PLATFORM_LENGTH :f64 = 200.0;
+
+/// Distance from the track to the bar of a platform edge, in grid units.
+const PLATFORM_OFFSET :f32 = 0.4;
+
+/// Platform edges are drawn as a bar beside the track, from the object's
+/// location along its tangent for the length of the platform, on the left
+/// of the track unless its railML side is "right". Gives the ends of the
+/// bar.
+pub fn platform_edge_bar(model :&Model, pta :PtA, obj :&Object) -> (PtC, PtC) {
+    let (length, side) = platform_edge_data(model, pta);
+    let tangent = platform_tangent(obj);
+    let right = side.map(|s| s.eq_ignore_ascii_case("right")).unwrap_or(false);
+    let left = glm::normalize(&glm::vec2(-tangent.y, tangent.x));
+    let offset = (if right { -PLATFORM_OFFSET } else { PLATFORM_OFFSET }) * left;
+    let steps = (length / GRID_STEP_LENGTH) as f32;
+    (obj.loc + offset, obj.loc + steps * tangent + offset)
+}
+
+/// Length in meters and railML side of the platform edge at `pta`.
+pub fn platform_edge_data(model :&Model, pta :PtA) -> (f64, Option<String>) {
+    let (length, side) = model.railml_objects.get(&pta).into_iter().flatten()
+        .find_map(|info| match info {
+            RailMLObjectInfo::PlatformEdge { length, side, .. } => Some((*length, side.clone())),
+            _ => None,
+        })
+        .unwrap_or((None, None));
+    (length.unwrap_or(DEFAULT_PLATFORM_LENGTH), side)
+}
+
+/// The point on the track at a distance in meters along a platform edge
+/// from its start.
+pub fn platform_edge_point(obj :&Object, dist :f64) -> PtC {
+    obj.loc + (dist / GRID_STEP_LENGTH) as f32 * platform_tangent(obj)
+}
+
+/// Distance in meters along a platform edge from its start to the point
+/// beside the track nearest to `p`.
+pub fn platform_edge_param(obj :&Object, p :PtC) -> f64 {
+    let tangent = platform_tangent(obj);
+    (glm::dot(&(p - obj.loc), &tangent) / glm::dot(&tangent, &tangent)) as f64 * GRID_STEP_LENGTH
+}
+
+fn platform_tangent(obj :&Object) -> PtC {
+    if obj.tangent == glm::zero() { glm::vec2(1.0, 0.0) }
+    else { glm::vec2(obj.tangent.x as f32, obj.tangent.y as f32) }
+}
+
+/// Railway protection and angle in degrees between road and track of the
+/// level crossing at `pta`, from its railML data.
+pub fn level_crossing_data(model :&Model, pta :PtA) -> (Option<String>, Option<f64>) {
+    model.railml_objects.get(&pta).into_iter().flatten()
+        .find_map(|info| match info {
+            RailMLObjectInfo::LevelCrossing { protection, angle, .. } => Some((protection.clone(), *angle)),
+            _ => None,
+        })
+        .unwrap_or((None, None))
+}
+
+/// Level crossings are drawn as a road crossing the track: two lines at
+/// the angle in degrees between the road and the track.
+pub fn level_crossing_road(tangent :Vc, angle :f64) -> Vec<SymbolPrim> {
+    let (_, t, _) = symbol_axes(tangent);
+    let len = (t.x*t.x + t.y*t.y).sqrt().max(1e-6);
+    let along = ImVec2 { x: t.x / len, y: t.y / len };
+    // Counterclockwise on the screen, where y points down.
+    let (sin, cos) = (angle as f32).to_radians().sin_cos();
+    let across = ImVec2 { x: along.x*cos + along.y*sin, y: along.y*cos - along.x*sin };
+    let (half_length, half_width) = (12.0, 3.5);
+    [-half_width, half_width].iter()
+        .map(|w| SymbolPrim::Line(*w*along - half_length*across, *w*along + half_length*across, 1.5))
+        .collect()
+}
+
+/// The point `pt` moved along its closest line segment to the nearest of
+/// `divisions` equal parts of the segment, keeping its distance from the
+/// track. Points away from the tracks are not moved.
+pub fn snap_to_track(model :&Model, pt :PtC, divisions :i32) -> PtC {
+    match model.get_closest_lineseg(pt) {
+        Some(((a,b),param,_)) => {
+            let (a, b) = (glm::vec2(a.x as f32, a.y as f32), glm::vec2(b.x as f32, b.y as f32));
+            let n = divisions.max(1) as f32;
+            let snapped = (param * n).round() / n;
+            pt + (snapped - param) * (b - a)
+        },
+        None => pt,
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ObjectState { SignalStop, SignalProceed, DistantStop, DistantProceed }
 
 impl Object {
+    /// A new object with a single function, to be placed with `move_to`.
+    pub fn with_function(function :Function) -> Object {
+        Object { loc: glm::vec2(0.0, 0.0), tangent: glm::vec2(1,0), functions: vec![function] }
+    }
+
     pub fn move_to(&mut self, model :&Model, pt :PtC) -> Option<()> {
         if let Some((l,_param,(d1,d2))) = model.get_closest_lineseg(pt) {
             let (pt_on_line,_param) = project_to_line(pt, glm::vec2(l.0.x as _ ,l.0.y as _ ),
                                                  glm::vec2(l.1.x as _ ,l.1.y as _ ));
             let tangent : PtC = glm::vec2(l.1.x as f32 -l.0.x as f32 ,l.1.y as f32 -l.0.y as f32);
             let normal : PtC   = glm::vec2(-tangent.y,tangent.x);
+            let old_tangent = self.tangent;
             self.tangent = glm::vec2(tangent.x.round() as _, tangent.y.round() as _);
 
             if self.functions.iter().find(|c| matches!(c, Function::MainSignal { .. })).is_some() {
@@ -65,9 +208,15 @@ impl Object {
                     self.loc = pt_on_line + offset;
             } else if self.functions.iter().find(|c| matches!(c,
                 Function::Detector | Function::TrackCircuitBorder | Function::Derailer |
-                Function::TrainProtectionElement | Function::TrainProtectionGroup | Function::Balise |
-                Function::PlatformEdge | Function::SpeedChange | Function::LevelCrossing | Function::CrossSection)).is_some() {
+                Function::TrainProtectionElement | Function::TrainProtectionGroup | Function::Balise | Function::BaliseGroup |
+                Function::PlatformEdge | Function::SpeedChange | Function::GradientChange | Function::RadiusChange |
+                Function::LevelCrossing | Function::CrossSection)).is_some() {
                 self.loc = pt_on_line;
+                // Keep pointing the same way along the track, as the tangent
+                // gives the direction of gradient changes and platform edges.
+                if old_tangent.x*self.tangent.x + old_tangent.y*self.tangent.y < 0 {
+                    self.tangent *= -1;
+                }
             }
 
             None
@@ -78,137 +227,198 @@ impl Object {
     }
 
     pub fn draw(&self, pos :ImVec2, view :&View, draw_list :*mut ImDrawList, c :u32, state :&[ObjectState], config :&Config) {
-        unsafe {
-            let p = pos + view.world_ptc_to_screen(self.loc);
-            let scale = 5.0;
-            // TODO can this be simplified?
-            let tangent = ImVec2 { x: scale * self.tangent.x as f32,
-                                   y: scale * -self.tangent.y as f32 };
-            let normal  = ImVec2 { x: scale * -self.tangent.y as f32,
-                                   y: scale * -self.tangent.x as f32 };
-
-            for f in self.functions.iter() {
-                match f {
-                    Function::Detector => {
-                        ImDrawList_AddLine(draw_list, p - normal, p + normal, c, 2.0);
-                    },
-                    Function::TrackCircuitBorder => {
-                        let s = scale * 0.8;
-                        ImDrawList_AddRect(draw_list, p - ImVec2 { x: s, y: s },
-                                           p + ImVec2 { x: s, y: s }, c, 0.0, 0, 2.0);
-                    },
-                    Function::Derailer => {
-                        let s = scale * 0.7;
-                        ImDrawList_AddLine(draw_list, p - ImVec2 { x: s, y: s },
-                                           p + ImVec2 { x: s, y: s }, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p - ImVec2 { x: s, y: -s },
-                                           p + ImVec2 { x: s, y: -s }, c, 2.0);
-                    },
-                    Function::TrainProtectionElement => {
-                        let s = scale * 0.7;
-                        let p1 = p + ImVec2 { x: 0.0, y: -s };
-                        let p2 = p + ImVec2 { x: -s, y: s };
-                        let p3 = p + ImVec2 { x: s, y: s };
-                        ImDrawList_AddLine(draw_list, p1, p2, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p2, p3, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p3, p1, c, 2.0);
-                    },
-                    Function::TrainProtectionGroup => {
-                        let s = scale * 0.9;
-                        let p1 = p + ImVec2 { x: 0.0, y: -s };
-                        let p2 = p + ImVec2 { x: -s, y: s };
-                        let p3 = p + ImVec2 { x: s, y: s };
-                        ImDrawList_AddTriangle(draw_list, p1, p2, p3, c, 2.0);
-                    },
-                    Function::Balise => {
-                        ImDrawList_AddCircleFilled(draw_list, p, scale * 0.6, c, 8);
-                    },
-                    Function::PlatformEdge => {
-                        let s = scale * 1.2;
-                        ImDrawList_AddLine(draw_list, p - ImVec2 { x: s, y: 0.0 },
-                                           p + ImVec2 { x: s, y: 0.0 }, c, 2.5);
-                    },
-                    Function::SpeedChange => {
-                        let s = scale * 0.8;
-                        let p1 = p + ImVec2 { x: 0.0, y: -s };
-                        let p2 = p + ImVec2 { x: -s, y: s };
-                        let p3 = p + ImVec2 { x: s, y: s };
-                        ImDrawList_AddLine(draw_list, p1, p2, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p2, p3, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p3, p1, c, 2.0);
-                    },
-                    Function::LevelCrossing => {
-                        let s = scale * 0.8;
-                        ImDrawList_AddLine(draw_list, p - ImVec2 { x: s, y: 0.0 },
-                                           p + ImVec2 { x: s, y: 0.0 }, c, 2.0);
-                        ImDrawList_AddLine(draw_list, p - ImVec2 { x: 0.0, y: s },
-                                           p + ImVec2 { x: 0.0, y: s }, c, 2.0);
-                    },
-                    Function::CrossSection => {
-                        let s = scale * 0.6;
-                        ImDrawList_AddRect(draw_list, p - ImVec2 { x: s, y: s },
-                                           p + ImVec2 { x: s, y: s }, c, 0.0, 0, 1.5);
-                    },
-                    Function::MainSignal { has_distant, kind } => {
-                        // base
-                        ImDrawList_AddLine(draw_list, p + normal, p - normal, c, 2.0);
-
-                        let draw_main = matches!(kind, SignalKind::Main | SignalKind::Combined | SignalKind::Repeater | SignalKind::Shunting);
-                        let draw_distant = matches!(kind, SignalKind::Distant | SignalKind::Combined) || *has_distant;
-                        let stem = if draw_distant { 2.0 } else { 1.0 };
-                        ImDrawList_AddLine(draw_list, p, p + stem*tangent, c, 2.0);
-
-                        for s in state.iter() {
-                            match s {
-                                ObjectState::SignalStop if draw_main => {
-                                    let c = config.color_u32(RailUIColorName::CanvasSignalStop);
-                                    ImDrawList_AddCircleFilled(draw_list, p + stem*tangent + tangent, scale, c, 8);
-                                },
-                                ObjectState::SignalProceed if draw_main => {
-                                    let c = config.color_u32(RailUIColorName::CanvasSignalProceed);
-                                    ImDrawList_AddCircleFilled(draw_list, p + stem*tangent + tangent, scale, c, 8);
-                                },
-                                ObjectState::DistantStop if draw_distant => {
-                                    let c = config.color_u32(RailUIColorName::CanvasSignalStop);
-                                    ImDrawList_AddCircleFilled(draw_list, p + 1.5*tangent + normal, scale*0.8, c, 8);
-                                },
-                                ObjectState::DistantProceed if draw_distant => {
-                                    let c = config.color_u32(RailUIColorName::CanvasSignalProceed);
-                                    ImDrawList_AddCircleFilled(draw_list, p + 1.5*tangent + normal, scale*0.8, c, 8);
-                                },
-                                _ => {},
-                            };
-                        }
+        let p = pos + view.world_ptc_to_screen(self.loc);
+        if !state.is_empty() {
+            self.draw_state(p, draw_list, state, config);
+        }
+        let symbol = symbol_geometry(&self.functions, self.tangent);
+        for prim in symbol.iter() {
+            prim.draw(draw_list, p, c);
+        }
+    }
 
-                        // distant
-                        if draw_distant {
-                            ImDrawList_AddCircle(draw_list, p + 1.5*tangent + normal, scale*0.8, c, 8, 2.0);
-                        }
-                        // main signal
-                        if draw_main {
-                            match kind {
-                                SignalKind::Shunting => {
-                                    let s = scale * 0.8;
-                                    ImDrawList_AddRect(draw_list,
-                                        p + stem*tangent + tangent - ImVec2 { x: s, y: s },
-                                        p + stem*tangent + tangent + ImVec2 { x: s, y: s },
-                                        c, 0.0, 0, 2.0);
-                                }
-                                SignalKind::Repeater => {
-                                    ImDrawList_AddCircle(draw_list, p + stem*tangent + tangent, scale*0.7, c, 8, 2.0);
-                                }
-                                _ => {
-                                    ImDrawList_AddCircle(draw_list, p + stem*tangent + tangent, scale, c, 8, 2.0);
-                                }
-                            }
-                        }
-                    },
+    fn draw_state(&self, p :ImVec2, draw_list :*mut ImDrawList, state :&[ObjectState], config :&Config) {
+        let (scale, tangent, normal) = symbol_axes(self.tangent);
+        for f in self.functions.iter() {
+            if let Function::MainSignal { has_distant, kind } = f {
+                let (draw_main, draw_distant, stem) = signal_parts(*has_distant, *kind);
+                for s in state.iter() {
+                    let (color, center, r) = match s {
+                        ObjectState::SignalStop if draw_main => 
+                            (RailUIColorName::CanvasSignalStop, p + stem*tangent + tangent, scale),
+                        ObjectState::SignalProceed if draw_main => 
+                            (RailUIColorName::CanvasSignalProceed, p + stem*tangent + tangent, scale),
+                        ObjectState::DistantStop if draw_distant => 
+                            (RailUIColorName::CanvasSignalStop, p + 1.5*tangent + normal, scale*0.8),
+                        ObjectState::DistantProceed if draw_distant => 
+                            (RailUIColorName::CanvasSignalProceed, p + 1.5*tangent + normal, scale*0.8),
+                        _ => continue,
+                    };
+                    unsafe { ImDrawList_AddCircleFilled(draw_list, center, r, config.color_u32(color), 8); }
                 }
+            }
+        }
+    }
+}
 
+/// Drawing primitive of an object symbol, relative to the object's
+/// screen position.
+#[derive(Copy, Clone, Debug)]
+pub enum SymbolPrim {
+    Line(ImVec2, ImVec2, f32),
+    Rect(ImVec2, ImVec2, f32),
+    Triangle(ImVec2, ImVec2, ImVec2, f32),
+    Circle(ImVec2, f32, f32),
+    CircleFilled(ImVec2, f32),
+}
+
+impl SymbolPrim {
+    pub fn draw(&self, draw_list :*mut ImDrawList, p :ImVec2, c :u32) {
+        unsafe {
+            match *self {
+                SymbolPrim::Line(a,b,w) => ImDrawList_AddLine(draw_list, p + a, p + b, c, w),
+                SymbolPrim::Rect(a,b,w) => ImDrawList_AddRect(draw_list, p + a, p + b, c, 0.0, 0, w),
+                SymbolPrim::Triangle(a,b,d,w) => ImDrawList_AddTriangle(draw_list, p + a, p + b, p + d, c, w),
+                SymbolPrim::Circle(a,r,w) => ImDrawList_AddCircle(draw_list, p + a, r, c, 8, w),
+                SymbolPrim::CircleFilled(a,r) => ImDrawList_AddCircleFilled(draw_list, p + a, r, c, 8),
             }
         }
     }
 }
 
+/// Symbols are drawn in screen space with a fixed size, so their geometry
+/// only depends on the object's functions and tangent.
+type SymbolKey = (Vec<Function>, (i32,i32));
+
+thread_local! {
+    static SYMBOL_CACHE :std::cell::RefCell<std::collections::HashMap<SymbolKey, std::rc::Rc<Vec<SymbolPrim>>>> =
+        std::cell::RefCell::new(std::collections::HashMap::new());
+}
+
+/// Get the symbol geometry for the given functions and tangent, computing
+/// it only the first time each combination is drawn.
+pub fn symbol_geometry(functions :&[Function], tangent :Vc) -> std::rc::Rc<Vec<SymbolPrim>> {
+    let key = (functions.to_vec(), (tangent.x, tangent.y));
+    SYMBOL_CACHE.with(|cache| {
+        cache.borrow_mut().entry(key)
+            .or_insert_with(|| std::rc::Rc::new(compute_symbol(functions, tangent)))
+            .clone()
+    })
+}
+
+fn symbol_axes(tangent :Vc) -> (f32, ImVec2, ImVec2) {
+    let scale = 5.0;
+    // TODO can this be simplified?
+    let t = ImVec2 { x: scale * tangent.x as f32,
+                     y: scale * -tangent.y as f32 };
+    let n = ImVec2 { x: scale * -tangent.y as f32,
+                     y: scale * -tangent.x as f32 };
+    (scale, t, n)
+}
+
+fn signal_parts(has_distant :bool, kind :SignalKind) -> (bool, bool, f32) {
+    let draw_main = matches!(kind, SignalKind::Main | SignalKind::Combined | SignalKind::Repeater | SignalKind::Shunting);
+    let draw_distant = matches!(kind, SignalKind::Distant | SignalKind::Combined) || has_distant;
+    let stem = if draw_distant { 2.0 } else { 1.0 };
+    (draw_main, draw_distant, stem)
+}
 
+fn compute_symbol(functions :&[Function], tangent :Vc) -> Vec<SymbolPrim> {
+    use SymbolPrim::*;
+    let tangent_vc = tangent;
+    let (scale, tangent, normal) = symbol_axes(tangent);
+    let zero = ImVec2 { x: 0.0, y: 0.0 };
+    let mut prims = Vec::new();
+    for f in functions.iter() {
+        match f {
+            Function::Detector => {
+                prims.push(Line(zero - normal, normal, 2.0));
+            },
+            Function::TrackCircuitBorder => {
+                let s = scale * 0.8;
+                prims.push(Rect(ImVec2 { x: -s, y: -s }, ImVec2 { x: s, y: s }, 2.0));
+            },
+            Function::Derailer => {
+                let s = scale * 0.7;
+                prims.push(Line(ImVec2 { x: -s, y: -s }, ImVec2 { x: s, y: s }, 2.0));
+                prims.push(Line(ImVec2 { x: -s, y: s }, ImVec2 { x: s, y: -s }, 2.0));
+            },
+            Function::TrainProtectionElement | Function::SpeedChange => {
+                let s = scale * if matches!(f, Function::SpeedChange) { 0.8 } else { 0.7 };
+                let p1 = ImVec2 { x: 0.0, y: -s };
+                let p2 = ImVec2 { x: -s, y: s };
+                let p3 = ImVec2 { x: s, y: s };
+                prims.push(Line(p1, p2, 2.0));
+                prims.push(Line(p2, p3, 2.0));
+                prims.push(Line(p3, p1, 2.0));
+            },
+            Function::TrainProtectionGroup => {
+                let s = scale * 0.9;
+                prims.push(Triangle(ImVec2 { x: 0.0, y: -s }, ImVec2 { x: -s, y: s },
+                                    ImVec2 { x: s, y: s }, 2.0));
+            },
+            Function::Balise => {
+                prims.push(CircleFilled(zero, scale * 0.6));
+            },
+            Function::BaliseGroup => {
+                // Two balises in a row.
+                prims.push(CircleFilled(zero - 0.5*tangent, scale * 0.4));
+                prims.push(CircleFilled(0.5*tangent, scale * 0.4));
+                prims.push(Line(zero - tangent, tangent, 1.5));
+            },
+            Function::PlatformEdge => {
+                // The platform itself is drawn as a bar by `platform_edge_bar`,
+                // this marks its start on the track.
+                prims.push(Line(zero - 0.6*normal, 0.6*normal, 2.0));
+            },
+            Function::GradientChange => {
+                // A ramp rising in the tangent direction, which points uphill.
+                let s = 0.8;
+                prims.push(Line(zero - s*tangent - 0.5*normal, s*tangent + 0.5*normal, 2.0));
+                prims.push(Line(s*tangent + 0.5*normal, s*tangent - 0.5*normal, 2.0));
+            },
+            Function::RadiusChange => {
+                let s = 0.6;
+                prims.push(Line(zero - s*normal - 0.5*tangent, zero, 2.0));
+                prims.push(Line(zero, s*normal - 0.5*tangent, 2.0));
+            },
+            Function::LevelCrossing => {
+                // Crossing at a right angle. Other angles from the railML
+                // data are drawn with `level_crossing_road`.
+                prims.extend(level_crossing_road(tangent_vc, 90.0));
+            },
+            Function::CrossSection => {
+                let s = scale * 0.6;
+                prims.push(Rect(ImVec2 { x: -s, y: -s }, ImVec2 { x: s, y: s }, 1.5));
+            },
+            Function::MainSignal { has_distant, kind } => {
+                let (draw_main, draw_distant, stem) = signal_parts(*has_distant, *kind);
+                // base
+                prims.push(Line(normal, zero - normal, 2.0));
+                prims.push(Line(zero, stem*tangent, 2.0));
 
+                // distant
+                if draw_distant {
+                    prims.push(Circle(1.5*tangent + normal, scale*0.8, 2.0));
+                }
+                // main signal
+                if draw_main {
+                    let head = stem*tangent + tangent;
+                    match kind {
+                        SignalKind::Shunting => {
+                            let s = scale * 0.8;
+                            prims.push(Rect(head - ImVec2 { x: s, y: s }, head + ImVec2 { x: s, y: s }, 2.0));
+                        }
+                        SignalKind::Repeater => {
+                            prims.push(Circle(head, scale*0.7, 2.0));
+                        }
+                        _ => {
+                            prims.push(Circle(head, scale, 2.0));
+                        }
+                    }
+                }
+            },
+        }
+    }
+    prims
+}
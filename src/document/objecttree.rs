@@ -0,0 +1,181 @@
+//! Hierarchical listing of the model for the object tree panel: stations
+//! with their tracks and objects, and dispatches with their commands.
+
+use std::collections::HashMap;
+
+use crate::document::model::*;
+use crate::document::topology::Topology;
+use crate::units::Units;
+use crate::i18n;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TreeKey {
+    Tracks,
+    /// Index into the imported railML OCPs.
+    Station(usize),
+    /// Tracks not belonging to any station.
+    OtherTracks,
+    /// Index into the topology tracks.
+    Track(usize),
+    Object(PtA),
+    Dispatches,
+    /// Dispatch id.
+    Dispatch(usize),
+    /// Dispatch id and command id.
+    Command(usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub key :TreeKey,
+    pub label :String,
+    /// Model elements selected on the canvas together with this node.
+    pub refs :Vec<Ref>,
+    pub children :Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn new(key :TreeKey, label :String, refs :Vec<Ref>, children :Vec<TreeNode>) -> Self {
+        TreeNode { key, label, refs, children }
+    }
+
+    /// Keys from the root to the node containing `r`, preferring the
+    /// deepest node that refers to exactly this element.
+    pub fn path_to(nodes :&[TreeNode], r :&Ref) -> Option<Vec<TreeKey>> {
+        for n in nodes {
+            if let Some(mut path) = TreeNode::path_to(&n.children, r) {
+                path.insert(0, n.key);
+                return Some(path);
+            }
+            if n.refs.len() == 1 && n.refs[0] == *r { return Some(vec![n.key]); }
+            // Tracks are found from any of their line segments.
+            if let TreeKey::Track(_) = n.key {
+                if n.refs.contains(r) { return Some(vec![n.key]); }
+            }
+        }
+        None
+    }
+}
+
+fn function_names(obj :&Object) -> String {
+    let names = obj.functions.iter().map(|f| crate::scripting::function_name(f)).collect::<Vec<_>>();
+    if names.is_empty() { "object".to_string() } else { names.join(", ") }
+}
+
+fn ref_name(r :&Ref) -> String {
+    match r {
+        Ref::Node(p) => format!("({},{})", p.x, p.y),
+        Ref::Object(p) => format!("signal ({:.1},{:.1})", p.x as f32 / 10.0, p.y as f32 / 10.0),
+        Ref::LineSeg(a,b) => format!("({},{})-({},{})", a.x, a.y, b.x, b.y),
+    }
+}
+
+/// The imported name of a track, found from its line segments.
+fn track_name(model :&Model, segments :&[(Pt,Pt)]) -> Option<String> {
+    let t = model.railml_tracks.iter().find(|t| {
+        segments.iter().any(|(a,b)| t.segments.contains(&(*a,*b)) || t.segments.contains(&(*b,*a)))
+    })?;
+    t.name.clone().or(t.code.clone()).or(Some(t.id.clone()))
+}
+
+fn tracks(model :&Model, topo :&Topology, units :&Units) -> Vec<TreeNode> {
+    topo.tracks.iter().enumerate().map(|(i,(length,_,_))| {
+        let segments = &topo.track_segments[i];
+        let name = track_name(model, segments).unwrap_or_else(|| i18n::tr_fmt("Track {}", &[&(i+1).to_string()]));
+        let objects = topo.trackobjects[i].iter().filter_map(|(_,pta,_,_)| {
+            let obj = model.objects.get(pta)?;
+            Some(TreeNode::new(TreeKey::Object(*pta),
+                               format!("{} at ({:.1},{:.1})", function_names(obj), obj.loc.x, obj.loc.y),
+                               vec![Ref::Object(*pta)], Vec::new()))
+        });
+        // An object can be listed more than once for different functions.
+        let mut seen = std::collections::HashSet::new();
+        let objects = objects.filter(|n| seen.insert(n.key)).collect();
+        TreeNode::new(TreeKey::Track(i), format!("{} ({})", name, units.length(*length)),
+                      segments.iter().map(|(a,b)| Ref::LineSeg(*a,*b)).collect(), objects)
+    }).collect()
+}
+
+/// The station of each track, from the station references of the imported
+/// signals on the track.
+pub fn track_stations(model :&Model, topo :&Topology) -> Vec<Option<usize>> {
+    let ocp_idx = model.railml_ocps.iter().enumerate()
+        .map(|(i,o)| (o.id.as_str(), i)).collect::<HashMap<_,_>>();
+    topo.trackobjects.iter().map(|objs| {
+        objs.iter().filter_map(|(_,pta,_,_)| model.railml_objects.get(pta)).flatten()
+            .filter_map(|info| match info {
+                RailMLObjectInfo::Signal { ocp_station_ref: Some(r), .. } => ocp_idx.get(r.as_str()).cloned(),
+                _ => None,
+            }).next()
+    }).collect()
+}
+
+fn dispatches(model :&Model) -> Vec<TreeNode> {
+    model.dispatches.iter().map(|(id,d)| {
+        let commands = d.commands.iter().map(|(cmd_id,(t,cmd))| {
+            let (label, spec) = match cmd {
+                Command::Route(spec) => (format!("t={:.1} route", t), spec),
+                Command::Train(v, spec) => {
+                    let vehicle = model.vehicles.get(*v).map(|v| v.name.as_str()).unwrap_or("unknown vehicle");
+                    (format!("t={:.1} train {}", t, vehicle), spec)
+                },
+            };
+            TreeNode::new(TreeKey::Command(*id, *cmd_id),
+                          format!("{} from {} to {}", label, ref_name(&spec.from), ref_name(&spec.to)),
+                          vec![spec.from, spec.to], Vec::new())
+        }).collect();
+        TreeNode::new(TreeKey::Dispatch(*id), d.name.clone(), Vec::new(), commands)
+    }).collect()
+}
+
+pub fn build(model :&Model, topo :Option<&Topology>, units :&Units) -> Vec<TreeNode> {
+    let mut roots = Vec::new();
+    if let Some(topo) = topo {
+        let mut tracks = tracks(model, topo, units).into_iter().map(Some).collect::<Vec<_>>();
+        let stations = track_stations(model, topo);
+        if stations.iter().all(|s| s.is_none()) {
+            roots.push(TreeNode::new(TreeKey::Tracks, i18n::tr("Tracks").to_string(), Vec::new(),
+                                     tracks.into_iter().flatten().collect()));
+        } else {
+            for (i,ocp) in model.railml_ocps.iter().enumerate() {
+                let children = (0..tracks.len()).filter(|t| stations[*t] == Some(i))
+                    .filter_map(|t| tracks[t].take()).collect::<Vec<_>>();
+                if children.is_empty() { continue; }
+                let name = ocp.name.clone().unwrap_or_else(|| ocp.id.clone());
+                let refs = children.iter().flat_map(|c| c.refs.iter().cloned()).collect();
+                roots.push(TreeNode::new(TreeKey::Station(i), name, refs, children));
+            }
+            let other = tracks.into_iter().flatten().collect::<Vec<_>>();
+            if !other.is_empty() {
+                roots.push(TreeNode::new(TreeKey::OtherTracks, i18n::tr("Other tracks").to_string(), Vec::new(), other));
+            }
+        }
+    }
+    roots.push(TreeNode::new(TreeKey::Dispatches, i18n::tr("Dispatches").to_string(), Vec::new(), dispatches(model)));
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::topology;
+    use nalgebra_glm as glm;
+
+    #[test]
+    fn tracks_and_objects_listed() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(10,0)));
+        let pta = crate::document::infview::round_coord(glm::vec2(5.0, 0.0));
+        model.objects.insert(pta, Object { loc: glm::vec2(5.0, 0.0), tangent: glm::vec2(1, 0),
+                                           functions: vec![crate::document::objects::Function::Detector] });
+        let topo = topology::convert(&model, 50.0).unwrap();
+        let tree = build(&model, Some(&topo), &Default::default());
+
+        assert_eq!(tree[0].key, TreeKey::Tracks);
+        assert_eq!(tree[0].children.len(), 1);
+        assert_eq!(tree[0].children[0].children.len(), 1);
+        assert_eq!(TreeNode::path_to(&tree, &Ref::Object(pta)),
+                   Some(vec![TreeKey::Tracks, TreeKey::Track(0), TreeKey::Object(pta)]));
+        assert_eq!(tree.last().unwrap().key, TreeKey::Dispatches);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::document::model::*;
+use crate::document::topology::Topology;
+
+/// A simple path through the track network, as found by `find_paths`.
+#[derive(Clone, Debug)]
+pub struct TopoPath {
+    pub nodes :Vec<Pt>,
+    pub tracks :Vec<usize>,
+    pub length :f64,
+    /// Switches passed by the path, with the position they need to be in.
+    pub switches :Vec<(Pt, Side)>,
+}
+
+/// Find all simple paths (no node visited twice) between two nodes in the
+/// topology. Trains cannot reverse, so a path entering a switch through a
+/// branch must leave through the trunk and vice versa. Crossings are
+/// passed straight over only. The search stops after `max_paths` paths.
+pub fn find_paths(topo :&Topology, from :Pt, to :Pt, max_paths :usize) -> Vec<TopoPath> {
+    let mut ends :HashMap<Pt, Vec<(usize, Port)>> = HashMap::new();
+    for (track_idx,(_,(pa,porta),(pb,portb))) in topo.tracks.iter().enumerate() {
+        ends.entry(*pa).or_insert(Vec::new()).push((track_idx, *porta));
+        ends.entry(*pb).or_insert(Vec::new()).push((track_idx, *portb));
+    }
+
+    let mut output = Vec::new();
+    if from == to { return output; }
+    let mut state = Search {
+        topo, ends: &ends, to, max_paths,
+        visited: HashSet::new(),
+        current: TopoPath { nodes: vec![from], tracks: Vec::new(), length: 0.0, switches: Vec::new() },
+        output: &mut output,
+    };
+    state.visited.insert(from);
+    state.go(from, None);
+    output.sort_by(|a,b| a.length.partial_cmp(&b.length).unwrap_or(std::cmp::Ordering::Equal));
+    output
+}
+
+struct Search<'a> {
+    topo :&'a Topology,
+    ends :&'a HashMap<Pt, Vec<(usize, Port)>>,
+    to :Pt,
+    max_paths :usize,
+    visited :HashSet<Pt>,
+    current :TopoPath,
+    output :&'a mut Vec<TopoPath>,
+}
+
+impl<'a> Search<'a> {
+    fn go(&mut self, node :Pt, entered :Option<Port>) {
+        if self.output.len() >= self.max_paths { return; }
+        let exits = match self.ends.get(&node) { Some(e) => e.clone(), None => return };
+        for (track_idx, port) in exits {
+            if let Some(entered) = entered {
+                if !entered.is_opposite(&port) { continue; }
+            }
+            let (length,(pa,porta),(pb,portb)) = self.topo.tracks[track_idx];
+            let (next, next_port) = if pa == node && porta == port { (pb,portb) } else { (pa,porta) };
+            if self.visited.contains(&next) { continue; }
+
+            let switch = switch_position(entered, port).map(|side| (node, side));
+            if let Some(s) = switch { self.current.switches.push(s); }
+            self.current.nodes.push(next);
+            self.current.tracks.push(track_idx);
+            self.current.length += length;
+
+            if next == self.to {
+                self.output.push(self.current.clone());
+            } else {
+                self.visited.insert(next);
+                self.go(next, Some(next_port));
+                self.visited.remove(&next);
+            }
+
+            self.current.length -= length;
+            self.current.tracks.pop();
+            self.current.nodes.pop();
+            if switch.is_some() { self.current.switches.pop(); }
+        }
+    }
+}
+
+fn switch_position(entered :Option<Port>, exit :Port) -> Option<Side> {
+    let side_of = |p :Port| match p {
+        Port::Left => Some(Side::Left),
+        Port::Right => Some(Side::Right),
+        _ => None,
+    };
+    match entered {
+        Some(Port::Trunk) => side_of(exit),
+        Some(p) if exit == Port::Trunk => side_of(p),
+        _ => None,
+    }
+}
+
+/// Format the paths as CSV, one line per path.
+pub fn to_csv(paths :&[TopoPath]) -> String {
+    let mut s = String::from("path,length,nodes,switches\n");
+    for (i,p) in paths.iter().enumerate() {
+        let nodes = p.nodes.iter().map(|n| format!("({};{})", n.x, n.y)).collect::<Vec<_>>().join(" ");
+        let switches = p.switches.iter().map(|(n,side)| format!("({};{}):{:?}", n.x, n.y, side))
+            .collect::<Vec<_>>().join(" ");
+        s.push_str(&format!("{},{:.1},{},{}\n", i+1, p.length, nodes, switches));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::topology;
+    use nalgebra_glm as glm;
+
+    #[test]
+    fn paths_through_switch() {
+        let mut model = Model::empty();
+        for (a,b) in &[((0,0),(1,0)),((1,0),(2,0)),((2,0),(3,0)),((3,0),(4,0)),
+                       ((2,0),(3,1)),((3,1),(4,1))] {
+            model.linesegs.insert((glm::vec2(a.0,a.1), glm::vec2(b.0,b.1)));
+        }
+        let topo = topology::convert(&model, 50.0).unwrap();
+
+        let paths = find_paths(&topo, glm::vec2(0,0), glm::vec2(4,1), 10);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].switches.len(), 1);
+        assert_eq!(paths[0].switches[0].0, glm::vec2(2,0));
+
+        // Going from one branch to the other requires reversing.
+        let paths = find_paths(&topo, glm::vec2(4,0), glm::vec2(4,1), 10);
+        assert!(paths.is_empty());
+    }
+}
@@ -83,7 +83,7 @@ pub fn test_plan(dgraph :&DGraph,
 
     // simulate the dispatch
     let (history,route_refs) =
-         history::get_history(vehicles, &dgraph.rolling_inf, il, &commands)?;
+         history::get_history(vehicles, dgraph, il, &commands)?;
 
     // then check that the plan is satisfied
     if let Err(e) = eval_plan(dgraph, plan_spec, &history) { return Ok(Err(e)); }
@@ -106,12 +106,144 @@ fn event_matches_spec(dgraph :&DGraph, visit :&Visit, event :&TrainLogEvent) ->
     false
 }
 
+/// Why a plan visit cannot be reached using the interlocking's routes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unreachable {
+    /// The location is not a node in the infrastructure.
+    NotOnNetwork,
+    /// No route passes through the location.
+    NoRoute,
+    /// The first visit cannot be reached from any entry boundary.
+    NotFromEntry,
+    /// The visit cannot be reached by continuing from the previous visit.
+    NotFromPreviousVisit,
+}
+
+impl Unreachable {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Unreachable::NotOnNetwork => "location is not a node in the infrastructure",
+            Unreachable::NoRoute => "no route passes through the location",
+            Unreachable::NotFromEntry => "no route sequence leads here from an entry boundary",
+            Unreachable::NotFromPreviousVisit => "no route sequence leads here from the previous visit",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VisitIssue {
+    pub train :ListId,
+    pub visit :ListId,
+    pub reason :Unreachable,
+}
+
+/// Check, before running the planner, that each train's visits can be
+/// reached in sequence by following routes, starting from the routes
+/// entering the network at a boundary. Returns one issue for each
+/// unreachable visit; an empty list means the plan passes the check.
+pub fn check_feasibility(dgraph :&DGraph, il :&Interlocking, plan :&PlanSpec) -> Vec<VisitIssue> {
+    // Routes that can directly follow each route.
+    let mut next_routes :HashMap<Ref, Vec<usize>> = HashMap::new();
+    for (i,r) in il.routes.iter().enumerate() {
+        next_routes.entry(r.id.from).or_insert(Vec::new()).push(i);
+    }
+    let reachable_from = |start :&HashSet<usize>| {
+        let mut visited = start.clone();
+        let mut stack :Vec<usize> = start.iter().cloned().collect();
+        while let Some(r) = stack.pop() {
+            for n in next_routes.get(&il.routes[r].id.to).into_iter().flat_map(|v| v.iter()) {
+                if visited.insert(*n) { stack.push(*n); }
+            }
+        }
+        visited
+    };
+    let entry_routes = il.boundary_routes.values().flat_map(|v| v.iter()).cloned().collect();
+    let from_entry = reachable_from(&entry_routes);
+
+    let mut issues = Vec::new();
+    for (train_id, (_, visits)) in plan.trains.iter() {
+        let mut previous :Option<HashSet<usize>> = None;
+        for (visit_id, visit) in visits.iter() {
+            let covering = visit_routes(dgraph, il, visit);
+            let reason = if covering.is_none() {
+                Some(Unreachable::NotOnNetwork)
+            } else if covering.as_ref().unwrap().is_empty() {
+                Some(Unreachable::NoRoute)
+            } else {
+                None
+            };
+            let covering = covering.unwrap_or_default();
+
+            let reachable = match &previous {
+                None => covering.intersection(&from_entry).cloned().collect::<HashSet<_>>(),
+                Some(prev) => covering.intersection(&reachable_from(prev)).cloned().collect(),
+            };
+            let reason = reason.or_else(|| if reachable.is_empty() {
+                Some(if previous.is_none() { Unreachable::NotFromEntry }
+                     else { Unreachable::NotFromPreviousVisit })
+            } else { None });
+
+            if let Some(reason) = reason {
+                issues.push(VisitIssue { train: *train_id, visit: *visit_id, reason });
+                // Continue from the visit's own routes to report later
+                // visits independently of this one.
+                previous = Some(covering);
+            } else {
+                previous = Some(reachable);
+            }
+        }
+    }
+    issues
+}
+
+/// Routes passing through any of the visit's locations, or `None` if none
+/// of the locations is a node in the infrastructure.
+fn visit_routes(dgraph :&DGraph, il :&Interlocking, visit :&Visit) -> Option<HashSet<usize>> {
+    let pts = visit.locs.iter().filter_map(|l| match l {
+        Ok(Ref::Node(pt)) => Some(*pt),
+        _ => None,
+    }).filter(|pt| dgraph.node_ids.get_by_right(pt).is_some() ||
+                   dgraph.switch_ids.get_by_right(pt).is_some())
+      .collect::<Vec<_>>();
+    if pts.is_empty() { return None; }
+
+    let mut routes = HashSet::new();
+    for (i,r) in il.routes.iter().enumerate() {
+        let passes = pts.iter().any(|pt| {
+            r.id.from == Ref::Node(*pt) || r.id.to == Ref::Node(*pt) ||
+            r.path.iter().any(|(a,b)| dgraph.node_ids.get_by_left(a) == Some(pt) ||
+                                      dgraph.node_ids.get_by_left(b) == Some(pt)) ||
+            r.route.resources.switch_positions.iter()
+                .any(|(sw,_)| dgraph.switch_ids.get_by_left(sw) == Some(pt))
+        });
+        if passes { routes.insert(i); }
+    }
+    Some(routes)
+}
+
 pub fn get_dispatches(
       dgraph :&DGraph,
       il :&Interlocking, 
       vehicles :&[(usize,Vehicle)],
       plan :&PlanSpec,
       ) -> Result<Vec<(Dispatch, History)>, String> {
+    let mut output = Vec::new();
+    for candidate in plan_candidates(il, vehicles, plan)? {
+        if let Some((cmds,p)) = simulate_candidate(dgraph, il, vehicles, plan, &candidate) {
+            let name = format!("Dispatch {}", output.len()+1);
+            output.push((Dispatch::from_vec(name,cmds),p));
+        }
+    }
+    Ok(output)
+}
+
+/// Run the planner and collect its candidate route plans. Each candidate
+/// must be checked by `simulate_candidate` before it can be used.
+pub fn plan_candidates(
+      il :&Interlocking, 
+      vehicles :&[(usize,Vehicle)],
+      plan :&PlanSpec,
+      ) -> Result<Vec<planner::input::RoutePlan>, String> {
 
     let routes : HashMap<usize,rolling_inf::Route> = 
         il.routes.iter().map(|r| r.route.clone()).enumerate().collect();
@@ -132,16 +264,30 @@ pub fn get_dispatches(
     let mut output = Vec::new();
     planner::solver::plan(&config, &plan_inf, &plan_usage, |candidate| {
         //println!("got one plan");
-        if let Ok((cmds,p)) = test_plan(dgraph, il, vehicles, plan, candidate).unwrap() {
-            let name = format!("Dispatch {}", output.len()+1);
-            output.push((Dispatch::from_vec(name,cmds),p));
-        }
+        output.push(candidate.clone());
         false
     });
     //println!("planner finished");
     Ok(output)
 }
 
+/// Simulate a candidate route plan, returning the dispatch commands and
+/// the resulting history if the simulation satisfies the plan.
+pub fn simulate_candidate(dgraph :&DGraph,
+                          il :&Interlocking, 
+                          vehicles :&[(usize,Vehicle)],
+                          plan :&PlanSpec,
+                          candidate :&planner::input::RoutePlan) -> Option<(Commands, History)> {
+    match test_plan(dgraph, il, vehicles, plan, candidate) {
+        Ok(Ok(result)) => Some(result),
+        Ok(Err(_)) => None,
+        Err(e) => { 
+            println!("WARNING: plan candidate simulation failed: {}", e);
+            None
+        },
+    }
+}
+
 
 fn convert_dispatch_commands(routeplan :&planner::input::RoutePlan, il :&Interlocking,
                           plan :&PlanSpec) -> Result<Commands,String> {
@@ -0,0 +1,115 @@
+//! Finding elements of the model by text: the ids of imported railML
+//! elements, track and OCP names, signal codes and object functions.
+//! Matching ignores case and finds the query anywhere in the text.
+
+use nalgebra_glm as glm;
+
+use crate::document::infview::unround_coord;
+use crate::document::lint::ref_pos;
+use crate::document::model::*;
+use crate::scripting::function_name;
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// What matched and what it belongs to, for listing the result.
+    pub label :String,
+    /// Elements selected when the result is chosen.
+    pub refs :Vec<Ref>,
+    /// Index of the OCP station marker, for OCP results.
+    pub ocp :Option<usize>,
+    /// Bounding box of the result, for zooming to it.
+    pub bounds :(PtC, PtC),
+}
+
+fn bounds(pts :impl Iterator<Item = PtC>) -> Option<(PtC, PtC)> {
+    pts.fold(None, |b, p| Some(match b {
+        None => (p, p),
+        Some((lo, hi)) => (glm::vec2(lo.x.min(p.x), lo.y.min(p.y)), glm::vec2(hi.x.max(p.x), hi.y.max(p.y))),
+    }))
+}
+
+fn result(label :String, refs :Vec<Ref>) -> Option<SearchResult> {
+    let bounds = bounds(refs.iter().map(ref_pos))?;
+    Some(SearchResult { label, refs, ocp: None, bounds })
+}
+
+/// Results matching the query, in the order tracks, OCPs, objects and
+/// track ends. An empty query matches nothing.
+pub fn search(model :&Model, query :&str) -> Vec<SearchResult> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() { return Vec::new(); }
+    let matches = |s :&str| s.to_lowercase().contains(&query);
+    let mut results = Vec::new();
+
+    for t in model.railml_tracks.iter() {
+        let mut names = std::iter::once(&t.id).chain(t.name.iter()).chain(t.code.iter());
+        if let Some(name) = names.find(|n| matches(n.as_str())) {
+            let label = if *name == t.id { format!("track {}", t.id) } else { format!("track {} ({})", name, t.id) };
+            let refs = t.segments.iter().filter(|(a,b)| model.linesegs.contains(&(*a,*b)))
+                .map(|(a,b)| Ref::LineSeg(*a,*b)).collect();
+            results.extend(result(label, refs));
+        }
+    }
+
+    for (idx, ocp) in model.railml_ocps.iter().enumerate() {
+        let mut names = std::iter::once(&ocp.id).chain(ocp.name.iter());
+        if let Some(name) = names.find(|n| matches(n.as_str())) {
+            let pos = match model.ocp_positions.get(&ocp.id) { Some(p) => *p, None => continue };
+            let label = if *name == ocp.id { format!("station {}", ocp.id) } else { format!("station {} ({})", name, ocp.id) };
+            results.push(SearchResult { label, refs: Vec::new(), ocp: Some(idx), bounds: (pos, pos) });
+        }
+    }
+
+    let mut objects = model.objects.iter().collect::<Vec<_>>();
+    objects.sort_by_key(|(pta, _)| (pta.x, pta.y));
+    for (pta, obj) in objects {
+        let functions = obj.functions.iter().map(function_name).collect::<Vec<_>>().join(", ");
+        let at = unround_coord(*pta);
+        let infos = model.railml_objects.get(pta).map(|i| i.as_slice()).unwrap_or(&[]);
+        let mut texts = infos.iter().map(|i| i.id().to_string()).collect::<Vec<_>>();
+        texts.extend(infos.iter().filter_map(|i| match i {
+            RailMLObjectInfo::Signal { code: Some(c), .. } | RailMLObjectInfo::Derailer { code: Some(c), .. } =>
+                Some(c.clone()),
+            _ => None,
+        }));
+        texts.push(functions.clone());
+        if let Some(text) = texts.iter().find(|t| matches(t.as_str())) {
+            let label = if *text == functions { format!("{} at ({:.1},{:.1})", functions, at.x, at.y) }
+                        else { format!("{} {} at ({:.1},{:.1})", functions, text, at.x, at.y) };
+            results.extend(result(label, vec![Ref::Object(*pta)]));
+        }
+    }
+
+    let mut nodes = model.railml_nodes.iter().collect::<Vec<_>>();
+    nodes.sort_by_key(|(p, _)| (p.x, p.y));
+    for (p, node) in nodes {
+        if matches(&node.id) {
+            results.extend(result(format!("track end {}", node.id), vec![Ref::Node(*p)]));
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::infview::round_coord;
+    use crate::document::objects::*;
+
+    #[test]
+    fn finds_functions_and_ids() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(4,0)));
+        let obj = Object { loc: glm::vec2(1.0, 0.0), tangent: glm::vec2(1,0), functions: vec![Function::Detector] };
+        model.objects.insert(round_coord(obj.loc), obj);
+        model.railml_nodes.insert(glm::vec2(4,0), RailMLNodeInfo { id: "mn_east".to_string(), ocp_ref: None });
+
+        let found = search(&model, "DETECT");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].refs, vec![Ref::Object(round_coord(glm::vec2(1.0, 0.0)))]);
+        let found = search(&model, "east");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].bounds, (glm::vec2(4.0, 0.0), glm::vec2(4.0, 0.0)));
+        assert!(search(&model, " ").is_empty());
+    }
+}
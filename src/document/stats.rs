@@ -0,0 +1,70 @@
+use crate::document::model::*;
+use crate::document::objects::*;
+use crate::document::topology::Topology;
+use crate::document::dgraph::DGraph;
+use crate::document::interlocking::Interlocking;
+
+#[derive(Clone, Debug, Default)]
+pub struct TopologyStats {
+    pub tracks :usize,
+    pub total_length :f64,
+    pub switches_left :usize,
+    pub switches_right :usize,
+    pub crossovers :usize,
+    pub single_slips :usize,
+    pub double_slips :usize,
+    pub open_ends :usize,
+    pub buffer_stops :usize,
+    pub signals :usize,
+    pub detectors :usize,
+    /// Longest distance along a single track without a main signal, and the track index.
+    pub longest_signal_free :Option<(f64, usize)>,
+    /// Number of train detection sections, if the dgraph is available.
+    pub tvds :Option<usize>,
+    /// Number of routes, if the interlocking is available.
+    pub routes :Option<usize>,
+}
+
+pub fn calc(topo :&Topology, dgraph :Option<&DGraph>, il :Option<&Interlocking>) -> TopologyStats {
+    let mut stats = TopologyStats::default();
+    stats.tracks = topo.tracks.len();
+    stats.total_length = topo.tracks.iter().map(|(l,_,_)| *l).sum();
+
+    for (_,(nd,_)) in topo.locations.iter() {
+        match nd {
+            NDType::Sw(Side::Left) => stats.switches_left += 1,
+            NDType::Sw(Side::Right) => stats.switches_right += 1,
+            NDType::Crossing(CrossingType::Crossover) => stats.crossovers += 1,
+            NDType::Crossing(CrossingType::SingleSlip(_)) => stats.single_slips += 1,
+            NDType::Crossing(CrossingType::DoubleSlip) => stats.double_slips += 1,
+            NDType::OpenEnd => stats.open_ends += 1,
+            NDType::BufferStop => stats.buffer_stops += 1,
+            NDType::Cont | NDType::Err => {},
+        }
+    }
+
+    for (track_idx,objs) in topo.trackobjects.iter().enumerate() {
+        let mut signal_pos = Vec::new();
+        for (pos,_,f,_) in objs.iter() {
+            match f {
+                Function::MainSignal { .. } => { stats.signals += 1; signal_pos.push(*pos); },
+                Function::Detector | Function::TrackCircuitBorder => stats.detectors += 1,
+                _ => {},
+            }
+        }
+        signal_pos.sort_by(|a,b| a.partial_cmp(b).unwrap());
+        let mut prev = 0.0;
+        let length = topo.tracks[track_idx].0;
+        for pos in signal_pos.into_iter().chain(std::iter::once(length)) {
+            let gap = pos - prev;
+            if stats.longest_signal_free.map(|(l,_)| gap > l).unwrap_or(true) {
+                stats.longest_signal_free = Some((gap, track_idx));
+            }
+            prev = pos;
+        }
+    }
+
+    stats.tvds = dgraph.map(|d| d.tvd_edges.len());
+    stats.routes = il.map(|il| il.routes.len());
+    stats
+}
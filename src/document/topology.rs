@@ -1,4 +1,4 @@
-use std::collections::{BTreeSet, BTreeMap,HashMap, VecDeque};
+use std::collections::{BTreeSet, BTreeMap, HashMap, HashSet, VecDeque};
 use nalgebra_glm as glm;
 
 use crate::document::model::*;
@@ -6,6 +6,7 @@ use crate::document::objects::*;
 
 use crate::util::*;
 use ordered_float::OrderedFloat;
+use matches::matches;
 
 
 #[derive(Clone)]
@@ -16,8 +17,16 @@ pub struct Topology {
     pub trackobjects : Vec<Vec<(f64,PtA, Function,Option<AB>)>>,
     pub interval_lines :Vec<Vec<(OrderedFloat<f64>,PtC)>>,
     pub track_segments: Vec<Vec<(Pt,Pt)>>,
+    pub trap_points: HashSet<Pt>,
+    /// Gradient (‰, rising in the track direction) from each position of
+    /// a track, sorted by position.
+    pub gradients: Vec<Vec<(f64,f64)>>,
+    piece_map: PieceMap,
 }
 
+/// Line segments mapped to their track index, start position and length.
+type PieceMap = HashMap<((i32,i32),(i32,i32)), (usize, f64, f64)>;
+
 impl Topology {
     pub fn interval_map(&self, track_idx: usize, start :f64, end :f64) -> Vec<PtC> {
         let lines = &self.interval_lines[track_idx];
@@ -52,6 +61,12 @@ impl Topology {
 
         output
     }
+
+    /// The gradient (‰, rising in the track direction) at a position on a track.
+    pub fn gradient_at(&self, track_idx :usize, pos :f64) -> f64 {
+        self.gradients[track_idx].iter().take_while(|(p,_)| *p <= pos)
+            .last().map(|(_,g)| *g).unwrap_or(0.0)
+    }
 }
 
 
@@ -69,14 +84,13 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
         pieces.insert(((a.x,a.y),(b.x,b.y)));
     }
 
-    let mut piece_map : HashMap<((i32,i32),(i32,i32)), (usize, f64, f64)> = HashMap::new();
+    let mut piece_map : PieceMap = HashMap::new();
     let mut trackobjects = Vec::new();
     let mut track_segments = Vec::new();
     while let Some((p1,p2)) = pieces.remove_any() {
         let mut list = VecDeque::new();
         list.push_back((p1,p2));
 
-        let mut length = def_len;
         let (mut a, mut b) = ((p1,p2),(p2,p1));
         drop(p1);drop(p2);
 
@@ -95,7 +109,6 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
                     } else { panic!(); }
 
                     *p = (n,p.0);
-                    length += def_len;
 
                 } else {
                     break;
@@ -106,7 +119,6 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
         extend(&mut a, b.0);
         extend(&mut b, a.0);
         let track_idx = tracks.len();
-        tracks.push((to_vec(a.0),to_vec(b.0),length));
         locs.entry(a.0).or_insert(Vec::new()).push(((track_idx, AB::A), to_vec(a.1)));
         locs.entry(b.0).or_insert(Vec::new()).push(((track_idx, AB::B), to_vec(b.1)));
 
@@ -116,121 +128,24 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
         let mut interval_map = Vec::new();
         let mut segments = Vec::new();
         for (a,b) in list.iter().cloned() {
-            piece_map.insert((a,b), (tracks.len()-1, l, def_len));
+            let length = segment_length(model, to_vec(a), to_vec(b), def_len);
+            piece_map.insert((a,b), (track_idx, l, length));
             interval_map.push((OrderedFloat(l),glm::vec2(a.0 as f32 ,a.1 as f32)));
-            l += def_len;
+            l += length;
             let (mut pa, mut pb) = (to_vec(a), to_vec(b));
             if pa > pb { std::mem::swap(&mut pa, &mut pb); }
             segments.push((pa, pb));
         }
         let last_pt = list[list.len()-1].1;
         interval_map.push((OrderedFloat(l),glm::vec2(last_pt.0 as f32, last_pt.1 as f32)));
+        tracks.push((to_vec(a.0),to_vec(b.0),l));
         interval_lines.push(interval_map);
         trackobjects.push(Vec::new());
         track_segments.push(segments);
     }
 
-    fn get_dir_from_side((a,b) :&(Pt,Pt), pt :PtC) -> AB {
-        let (pt_on_line,_param) = project_to_line(pt, glm::vec2(a.x as _, a.y as _),
-                                                      glm::vec2(b.x as _, b.y as _));
-        let tangent = glm::vec2(b.x as f32 - a.x as f32, b.y as f32 - a.y as f32);
-        let normal = glm::vec2(-tangent.y, tangent.x);
-        let a = glm::angle(&(pt_on_line - pt), &normal);
-        if glm::angle(&(pt_on_line - pt), &normal) > glm::half_pi() {
-            AB::B
-        } else { AB::A }
-    }
-
-    let get_from_piece_map = |a :(i32,i32), b :(i32,i32)| -> Option<(usize,f64,f64,isize)> {
-        if let Some((track_idx, pos_start, length)) = piece_map.get(&(a,b)) {
-            return Some((*track_idx, *pos_start, pos_start + length, 1));
-        }
-        if let Some((track_idx, pos_start, length)) = piece_map.get(&(b,a)) {
-            return Some((*track_idx, pos_start + length, *pos_start, -1));
-        }
-        None
-    };
-
-    fn find_closest_lineseg_global(model: &Model, pt: PtC) -> Option<((Pt, Pt), f32, (f32, f32))> {
-        let mut best = None;
-        let mut best_dist = std::f32::INFINITY;
-        let mut next_dist = std::f32::INFINITY;
-        let mut best_param = 0.0;
-        for (a, b) in model.linesegs.iter() {
-            let (d, param) = dist_to_line_sqr(
-                pt,
-                glm::vec2(a.x as f32, a.y as f32),
-                glm::vec2(b.x as f32, b.y as f32),
-            );
-            if d < best_dist {
-                next_dist = best_dist;
-                best_dist = d;
-                best_param = param;
-                best = Some((*a, *b));
-            } else if d < next_dist {
-                next_dist = d;
-            }
-        }
-        best.map(|seg| (seg, best_param, (best_dist, next_dist)))
-    }
-
-    for (id,Object { loc, functions, .. }) in model.objects.iter() {
-        let closest = model.get_closest_lineseg(*loc)
-            .or_else(|| find_closest_lineseg_global(model, *loc));
-        if let Some((pt,param,_)) = closest {
-            if let Some((track_idx,pos_a, pos_b, dir)) = get_from_piece_map((pt.0.x,pt.0.y), (pt.1.x,pt.1.y)) {
-                let pos = glm::lerp_scalar(pos_a, pos_b, param as f64);
-                let pt = if dir > 0 { pt } else { (pt.1,pt.0) }; // reverse line if track direction is not
-                // the default left-to-right.
-                let track_objs = &mut trackobjects[track_idx];
-
-                for f in functions.iter() {
-                    match f {
-                        Function::Detector => {
-                            track_objs.push((pos,*id,Function::Detector,None));
-                        },
-                        Function::MainSignal { has_distant, kind } => {
-                            
-                            // TODO this seems unnecessary when we can simply copy the `Function`s.
-                            track_objs.push((pos,*id, Function::MainSignal { has_distant: *has_distant, kind: *kind },
-                                             Some(get_dir_from_side(&pt, *loc))));
-                        },
-                        Function::TrackCircuitBorder => {
-                            track_objs.push((pos,*id,Function::TrackCircuitBorder,None));
-                        },
-                        Function::Derailer => {
-                            track_objs.push((pos,*id,Function::Derailer,None));
-                        },
-                        Function::TrainProtectionElement => {
-                            track_objs.push((pos,*id,Function::TrainProtectionElement,None));
-                        },
-                        Function::TrainProtectionGroup => {
-                            track_objs.push((pos,*id,Function::TrainProtectionGroup,None));
-                        },
-                        Function::Balise => {
-                            track_objs.push((pos,*id,Function::Balise,None));
-                        }
-                        Function::PlatformEdge => {
-                            track_objs.push((pos,*id,Function::PlatformEdge,None));
-                        },
-                        Function::SpeedChange => {
-                            track_objs.push((pos,*id,Function::SpeedChange,None));
-                        },
-                        Function::LevelCrossing => {
-                            track_objs.push((pos,*id,Function::LevelCrossing,None));
-                        },
-                        Function::CrossSection => {
-                            track_objs.push((pos,*id,Function::CrossSection,None));
-                        }
-                    }
-                }
-            } else {
-                println!("WARNING: object trackside position error.");
-            }
-        } else {
-            println!("WARNING: object outside track network.");
-        }
-    }
+    let trackobjects = place_objects(model, &piece_map, trackobjects);
+    let gradients = gradient_profiles(model, &trackobjects);
 
     let mut tp : Vec<(Option<(Pt,Port)>, Option<(Pt,Port)>, f64)> =
         tracks.into_iter().map(|(_,_,l)| (None,None,l)).collect();
@@ -365,10 +280,20 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
 
     for (pt,x) in model.node_data.iter() {
         if let Some((ndtype,tangent)) = locx.get_mut(pt) {
+            // A switch set to the other hand than its geometry deviates to
+            // the geometrically straight leg, and goes straight to the other.
+            if let (NDType::Sw(geometry), NDType::Sw(side)) = (*ndtype, *x) {
+                if geometry != side {
+                    let turn = if geometry == Side::Left { 1 } else { -1 };
+                    *tangent = angle_v(v_angle(*tangent) + turn);
+                }
+            }
             *ndtype = *x;
         }
     }
 
+    let trap_points = find_trap_points(model, &locx);
+
     Ok(
         Topology {
             tracks: tp.into_iter().map(|(a,b,l)| (l, a.unwrap(), b.unwrap())).collect(),
@@ -376,10 +301,211 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
             trackobjects: trackobjects,
             interval_lines: interval_lines,
             track_segments: track_segments,
+            trap_points: trap_points,
+            gradients: gradients,
+            piece_map: piece_map,
         }
     )
 }
 
+/// Update the topology after an edit, reusing the parts of `prev` (which
+/// was converted from `prev_model`) that the edit could not have changed.
+/// Editing objects only re-places the objects on the existing tracks;
+/// changes to the track layout, node types or line segment lengths fall
+/// back to a full `convert`.
+pub fn convert_incremental(prev :&Topology, prev_model :&Model, model :&Model, def_len :f64) -> Result<Topology, ()> {
+    if prev_model.linesegs != model.linesegs || prev_model.node_data != model.node_data ||
+        !same_lengths(prev_model, model) {
+        return convert(model, def_len);
+    }
+
+    let mut topo = prev.clone();
+    if prev_model.objects != model.objects {
+        let empty = topo.tracks.iter().map(|_| Vec::new()).collect();
+        topo.trackobjects = place_objects(model, &topo.piece_map, empty);
+    }
+    // The slopes are kept in the railML info, which may have been edited
+    // without touching the objects.
+    topo.gradients = gradient_profiles(model, &topo.trackobjects);
+    if prev_model.trap_points != model.trap_points {
+        topo.trap_points = find_trap_points(model, &topo.locations);
+    }
+    Ok(topo)
+}
+
+/// True if the edit from `a` to `b` cannot have changed the dgraph or
+/// interlocking. Objects which are not in the dgraph (see
+/// `Function::in_dgraph`) may have been added, moved or removed; they are
+/// only re-placed in the topology by `convert_incremental`. The gradients
+/// of the edges also depend on the slopes of the gradient changes, which
+/// the caller compares on the topologies.
+pub fn same_infrastructure(a :&Model, b :&Model) -> bool {
+    a.linesegs == b.linesegs && a.node_data == b.node_data &&
+        same_dgraph_objects(a, b) && a.trap_points == b.trap_points && same_lengths(a, b)
+}
+
+fn same_dgraph_objects(a :&Model, b :&Model) -> bool {
+    let objects = |m :&Model| m.objects.iter()
+        .filter(|(_,o)| o.functions.iter().any(Function::in_dgraph))
+        .map(|(p,o)| (*p, o.clone())).collect::<im::HashMap<_,_>>();
+    objects(a) == objects(b)
+}
+
+/// Length of the line segment between `a` and `b`: its real length if set
+/// in its attributes, otherwise `def_len`.
+fn segment_length(model :&Model, a :Pt, b :Pt, def_len :f64) -> f64 {
+    model.lineseg_attrs(a, b).and_then(|x| x.length).unwrap_or(def_len)
+}
+
+/// True if all line segments have the same real lengths in `a` and `b`.
+fn same_lengths(a :&Model, b :&Model) -> bool {
+    let lengths = |m :&Model| m.lineseg_attributes.iter()
+        .filter_map(|((p,q),x)| x.length.map(|l| ((p.x,p.y,q.x,q.y), OrderedFloat(l))))
+        .collect::<BTreeMap<_,_>>();
+    lengths(a) == lengths(b)
+}
+
+fn find_trap_points(model :&Model, locations :&HashMap<Pt,(NDType,Vc)>) -> HashSet<Pt> {
+    model.trap_points.iter()
+        .filter(|pt| matches!(locations.get(pt), Some((NDType::Sw(_),_))))
+        .cloned().collect()
+}
+
+/// The gradient profile of each track, from the imported slopes of its
+/// gradient change objects. The objects point uphill, see `place_objects`.
+fn gradient_profiles(model :&Model, trackobjects :&[Vec<(f64,PtA,Function,Option<AB>)>]) -> Vec<Vec<(f64,f64)>> {
+    trackobjects.iter().map(|objs| {
+        let mut profile = objs.iter().filter_map(|(pos,pta,func,dir)| {
+            if !matches!(func, Function::GradientChange) { return None; }
+            let slope = model.railml_objects.get(pta)?.iter().find_map(|info| match info {
+                RailMLObjectInfo::GradientChange { slope, .. } => Some(slope.abs()),
+                _ => None,
+            })?;
+            Some((*pos, if *dir == Some(AB::B) { -slope } else { slope }))
+        }).collect::<Vec<_>>();
+        profile.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        profile
+    }).collect()
+}
+
+fn place_objects(model :&Model, piece_map :&PieceMap,
+                 mut trackobjects :Vec<Vec<(f64,PtA,Function,Option<AB>)>>) -> Vec<Vec<(f64,PtA,Function,Option<AB>)>> {
+    fn get_dir_from_side((a,b) :&(Pt,Pt), pt :PtC) -> AB {
+        let (pt_on_line,_param) = project_to_line(pt, glm::vec2(a.x as _, a.y as _),
+                                                      glm::vec2(b.x as _, b.y as _));
+        let tangent = glm::vec2(b.x as f32 - a.x as f32, b.y as f32 - a.y as f32);
+        let normal = glm::vec2(-tangent.y, tangent.x);
+        let a = glm::angle(&(pt_on_line - pt), &normal);
+        if glm::angle(&(pt_on_line - pt), &normal) > glm::half_pi() {
+            AB::B
+        } else { AB::A }
+    }
+
+    let get_from_piece_map = |a :(i32,i32), b :(i32,i32)| -> Option<(usize,f64,f64,isize)> {
+        if let Some((track_idx, pos_start, length)) = piece_map.get(&(a,b)) {
+            return Some((*track_idx, *pos_start, pos_start + length, 1));
+        }
+        if let Some((track_idx, pos_start, length)) = piece_map.get(&(b,a)) {
+            return Some((*track_idx, pos_start + length, *pos_start, -1));
+        }
+        None
+    };
+
+    fn find_closest_lineseg_global(model: &Model, pt: PtC) -> Option<((Pt, Pt), f32, (f32, f32))> {
+        let mut best = None;
+        let mut best_dist = std::f32::INFINITY;
+        let mut next_dist = std::f32::INFINITY;
+        let mut best_param = 0.0;
+        for (a, b) in model.linesegs.iter() {
+            let (d, param) = dist_to_line_sqr(
+                pt,
+                glm::vec2(a.x as f32, a.y as f32),
+                glm::vec2(b.x as f32, b.y as f32),
+            );
+            if d < best_dist {
+                next_dist = best_dist;
+                best_dist = d;
+                best_param = param;
+                best = Some((*a, *b));
+            } else if d < next_dist {
+                next_dist = d;
+            }
+        }
+        best.map(|seg| (seg, best_param, (best_dist, next_dist)))
+    }
+
+    for (id,Object { loc, tangent, functions }) in model.objects.iter() {
+        let closest = model.get_closest_lineseg(*loc)
+            .or_else(|| find_closest_lineseg_global(model, *loc));
+        if let Some((pt,param,_)) = closest {
+            if let Some((track_idx,pos_a, pos_b, dir)) = get_from_piece_map((pt.0.x,pt.0.y), (pt.1.x,pt.1.y)) {
+                let pos = glm::lerp_scalar(pos_a, pos_b, param as f64);
+                let pt = if dir > 0 { pt } else { (pt.1,pt.0) }; // reverse line if track direction is not
+                // the default left-to-right.
+                let track_objs = &mut trackobjects[track_idx];
+
+                for f in functions.iter() {
+                    match f {
+                        Function::Detector => {
+                            track_objs.push((pos,*id,Function::Detector,None));
+                        },
+                        Function::MainSignal { has_distant, kind } => {
+                        
+                            // TODO this seems unnecessary when we can simply copy the `Function`s.
+                            track_objs.push((pos,*id, Function::MainSignal { has_distant: *has_distant, kind: *kind },
+                                             Some(get_dir_from_side(&pt, *loc))));
+                        },
+                        Function::TrackCircuitBorder => {
+                            track_objs.push((pos,*id,Function::TrackCircuitBorder,None));
+                        },
+                        Function::Derailer => {
+                            track_objs.push((pos,*id,Function::Derailer,None));
+                        },
+                        Function::TrainProtectionElement => {
+                            track_objs.push((pos,*id,Function::TrainProtectionElement,None));
+                        },
+                        Function::TrainProtectionGroup => {
+                            track_objs.push((pos,*id,Function::TrainProtectionGroup,None));
+                        },
+                        Function::Balise => {
+                            track_objs.push((pos,*id,Function::Balise,None));
+                        }
+                        Function::BaliseGroup => {
+                            track_objs.push((pos,*id,Function::BaliseGroup,None));
+                        }
+                        Function::PlatformEdge => {
+                            track_objs.push((pos,*id,Function::PlatformEdge,None));
+                        },
+                        Function::SpeedChange => {
+                            track_objs.push((pos,*id,Function::SpeedChange,None));
+                        },
+                        Function::GradientChange => {
+                            // Rising in the track direction if the object points along it.
+                            let d = pt.1 - pt.0;
+                            let dir = if d.x*tangent.x + d.y*tangent.y >= 0 { AB::A } else { AB::B };
+                            track_objs.push((pos,*id,Function::GradientChange,Some(dir)));
+                        },
+                        Function::RadiusChange => {
+                            track_objs.push((pos,*id,Function::RadiusChange,None));
+                        },
+                        Function::LevelCrossing => {
+                            track_objs.push((pos,*id,Function::LevelCrossing,None));
+                        },
+                        Function::CrossSection => {
+                            track_objs.push((pos,*id,Function::CrossSection,None));
+                        }
+                    }
+                }
+            } else {
+                println!("WARNING: object trackside position error.");
+            }
+        } else {
+            println!("WARNING: object outside track network.");
+        }
+    }
+    trackobjects
+}
+
 fn modu(a :i8, b:i8) -> i8 { (a % b + b ) % b }
 
 fn v_angle(v :Vc) -> i8 {
@@ -396,6 +522,20 @@ fn v_angle(v :Vc) -> i8 {
     }
 }
 
+/// Unit grid vector in the direction given by `v_angle`.
+fn angle_v(a :i8) -> Vc {
+    match modu(a, 8) {
+        0 => glm::vec2( 1, 0),
+        1 => glm::vec2( 1, 1),
+        2 => glm::vec2( 0, 1),
+        3 => glm::vec2(-1, 1),
+        4 => glm::vec2(-1, 0),
+        5 => glm::vec2(-1,-1),
+        6 => glm::vec2( 0,-1),
+        _ => glm::vec2( 1,-1),
+    }
+}
+
 
 #[derive(Debug,Clone)]
 pub struct SymSet<T:Ord+Copy> {
@@ -466,3 +606,73 @@ impl<T:Ord+Copy> SymSet<T> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_object_move() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        let obj = |x| Object { loc: glm::vec2(x, 0.0), tangent: glm::vec2(1,0),
+                               functions: vec![Function::Detector] };
+        model.objects.insert(glm::vec2(1,0), obj(0.5));
+        let prev = convert(&model, 50.0).unwrap();
+
+        let mut edited = model.clone();
+        edited.objects.insert(glm::vec2(1,0), obj(1.5));
+        let incremental = convert_incremental(&prev, &model, &edited, 50.0).unwrap();
+        let full = convert(&edited, 50.0).unwrap();
+        assert_eq!(incremental.trackobjects.len(), full.trackobjects.len());
+        let positions = |t :&Topology| t.trackobjects.iter()
+            .flat_map(|objs| objs.iter().map(|(pos,_,_,_)| *pos)).collect::<Vec<_>>();
+        assert_eq!(positions(&incremental), positions(&full));
+        assert!(same_infrastructure(&model, &model.clone()));
+        assert!(!same_infrastructure(&model, &edited));
+
+        // Objects which are not in the dgraph can be edited freely.
+        let mut platform = edited.clone();
+        platform.objects.insert(glm::vec2(3,0), Object { loc: glm::vec2(0.5, 0.0), tangent: glm::vec2(1,0),
+                                                        functions: vec![Function::PlatformEdge] });
+        assert!(same_infrastructure(&edited, &platform));
+        let incremental = convert_incremental(&full, &edited, &platform, 50.0).unwrap();
+        assert_eq!(incremental.trackobjects.iter().map(|o| o.len()).sum::<usize>(), 2);
+        assert_eq!(incremental.gradients, full.gradients);
+    }
+
+    #[test]
+    fn switch_hand_from_node_data() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        let topo = convert(&model, 50.0).unwrap();
+        assert_eq!(topo.locations[&glm::vec2(1,0)], (NDType::Sw(Side::Left), glm::vec2(1,0)));
+
+        // The other hand makes the diagonal leg the straight one.
+        model.node_data.insert(glm::vec2(1,0), NDType::Sw(Side::Right));
+        let topo = convert(&model, 50.0).unwrap();
+        assert_eq!(topo.locations[&glm::vec2(1,0)], (NDType::Sw(Side::Right), glm::vec2(1,1)));
+    }
+
+    #[test]
+    fn real_segment_length() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        let obj = Object { loc: glm::vec2(1.5, 0.0), tangent: glm::vec2(1,0), functions: vec![Function::Detector] };
+        model.objects.insert(glm::vec2(3,0), obj);
+        let prev = convert(&model, 50.0).unwrap();
+
+        let mut edited = model.clone();
+        let attrs = LineSegAttributes { length: Some(120.0), ..Default::default() };
+        edited.lineseg_attributes.insert((glm::vec2(0,0), glm::vec2(1,0)), attrs);
+        assert!(!same_infrastructure(&model, &edited));
+        let topo = convert_incremental(&prev, &model, &edited, 50.0).unwrap();
+        assert_eq!(topo.tracks[0].0, 170.0);
+        let pos = topo.trackobjects[0][0].0;
+        assert!(pos == 145.0 || pos == 25.0);
+    }
+}
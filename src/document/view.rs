@@ -17,6 +17,16 @@ impl View {
         }
     }
 
+    /// Scale and translation, for storing the camera between sessions.
+    pub fn camera(&self) -> (usize, f32, f32) {
+        (self.scale, self.translation.x, self.translation.y)
+    }
+
+    pub fn set_camera(&mut self, (scale, x, y) :(usize, f32, f32)) {
+        self.scale = scale.max(20).min(150);
+        self.translation = ImVec2 { x, y };
+    }
+
     pub fn zoom(&mut self, amount :f32) {
         self.scale = (self.scale as f32 + 3.0*amount).max(20.0).min(150.0).round() as _;
     }
@@ -78,4 +88,10 @@ impl View {
         let s = self.scale as f32;
         self.translation = ImVec2 { x: s * cx - size.x * 0.5, y: s * -cy - size.y * 0.5 };
     }
+
+    /// Move the view so that the given point is in the center, keeping the zoom level.
+    pub fn center_on(&mut self, pt :PtC, size :ImVec2) {
+        let s = self.scale as f32;
+        self.translation = ImVec2 { x: s * pt.x - size.x * 0.5, y: s * -pt.y - size.y * 0.5 };
+    }
 }
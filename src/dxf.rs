@@ -0,0 +1,287 @@
+//! Export of the schematic to DXF, for handing the layout over to CAD tools.
+//!
+//! The file is an AutoCAD R12 ASCII drawing, which all CAD tools read.
+//! Tracks, signals, detectors, other objects and labels are drawn on
+//! separate layers. Coordinates are in meters, with one grid unit as one
+//! default segment length, and object symbols are drawn as on screen.
+
+use std::fmt::Write;
+use std::io;
+use log::*;
+use nalgebra_glm as glm;
+
+use crate::document::model::*;
+use crate::document::objects::{symbol_geometry, Function, SymbolPrim};
+use crate::document::topology;
+use crate::gui::ImVec2;
+
+/// Length of a line segment between grid points, in meters.
+const SEGMENT_LENGTH :f64 = 50.0;
+/// Size of a pixel of the object symbols, in meters.
+const SYMBOL_SCALE :f64 = 0.5;
+/// Height of label text, in meters.
+const TEXT_HEIGHT :f64 = 3.0;
+
+/// Layer names and their AutoCAD color index.
+const TRACKS :(&str, i32) = ("TRACKS", 7);
+const SIGNALS :(&str, i32) = ("SIGNALS", 1);
+const DETECTORS :(&str, i32) = ("DETECTORS", 3);
+const OBJECTS :(&str, i32) = ("OBJECTS", 4);
+const LABELS :(&str, i32) = ("LABELS", 5);
+const LAYERS :&[(&str, i32)] = &[TRACKS, SIGNALS, DETECTORS, OBJECTS, LABELS];
+
+type P = (f64, f64);
+
+fn coords(p :PtC) -> P { (p.x as f64 * SEGMENT_LENGTH, p.y as f64 * SEGMENT_LENGTH) }
+
+/// The layer of the symbol of an object function.
+fn function_layer(f :&Function) -> &'static str {
+    match f {
+        Function::MainSignal { .. } => SIGNALS.0,
+        Function::Detector | Function::TrackCircuitBorder => DETECTORS.0,
+        _ => OBJECTS.0,
+    }
+}
+
+/// The imported name of an object, for its label.
+fn object_label(model :&Model, pta :&PtA) -> Option<String> {
+    let info = model.railml_objects.get(pta)?.first()?;
+    Some(match info {
+        RailMLObjectInfo::Signal { code: Some(code), .. } => code.clone(),
+        RailMLObjectInfo::Balise { name: Some(name), .. } => name.clone(),
+        RailMLObjectInfo::BaliseGroup { name: Some(name), .. } => name.clone(),
+        _ => info.id().to_string(),
+    })
+}
+
+/// Entities of a DXF drawing, written as pairs of group code and value.
+struct Dxf { out :String }
+
+impl Dxf {
+    fn pair(&mut self, code :i32, value :impl std::fmt::Display) {
+        let _ = write!(self.out, "{:>3}\n{}\n", code, value);
+    }
+
+    fn point(&mut self, code :i32, p :P) {
+        self.pair(code, format!("{:.4}", p.0));
+        self.pair(code + 10, format!("{:.4}", p.1));
+        self.pair(code + 20, "0.0");
+    }
+
+    fn entity(&mut self, kind :&str, layer :&str) {
+        self.pair(0, kind);
+        self.pair(8, layer);
+    }
+
+    fn line(&mut self, layer :&str, a :P, b :P) {
+        self.entity("LINE", layer);
+        self.point(10, a);
+        self.point(11, b);
+    }
+
+    /// A polyline with constant width. A bulge of one makes a vertex the
+    /// start of a half circle.
+    fn polyline(&mut self, layer :&str, pts :&[P], closed :bool, width :f64, bulge :f64) {
+        self.entity("POLYLINE", layer);
+        self.pair(66, 1);
+        self.point(10, (0.0, 0.0));
+        self.pair(70, if closed { 1 } else { 0 });
+        if width > 0.0 {
+            self.pair(40, format!("{:.4}", width));
+            self.pair(41, format!("{:.4}", width));
+        }
+        for p in pts {
+            self.entity("VERTEX", layer);
+            self.point(10, *p);
+            if bulge != 0.0 { self.pair(42, format!("{:.4}", bulge)); }
+        }
+        self.entity("SEQEND", layer);
+    }
+
+    fn circle(&mut self, layer :&str, c :P, r :f64) {
+        self.entity("CIRCLE", layer);
+        self.point(10, c);
+        self.pair(40, format!("{:.4}", r));
+    }
+
+    /// A filled disk, as a closed polyline of two half circles as wide as
+    /// the radius.
+    fn disk(&mut self, layer :&str, c :P, r :f64) {
+        let pts = [(c.0 - 0.5 * r, c.1), (c.0 + 0.5 * r, c.1)];
+        self.polyline(layer, &pts, true, r, 1.0);
+    }
+
+    fn text(&mut self, layer :&str, p :P, height :f64, rotation :f64, text :&str) {
+        self.entity("TEXT", layer);
+        self.point(10, p);
+        self.pair(40, format!("{:.4}", height));
+        self.pair(1, text.replace('\n', " "));
+        if rotation != 0.0 { self.pair(50, format!("{:.2}", rotation)); }
+    }
+
+    /// An object symbol at `c`, drawn in screen pixels with y downwards.
+    fn symbol(&mut self, layer :&str, c :P, prims :&[SymbolPrim]) {
+        let at = |v :ImVec2| (c.0 + SYMBOL_SCALE * v.x as f64, c.1 - SYMBOL_SCALE * v.y as f64);
+        for prim in prims {
+            match *prim {
+                SymbolPrim::Line(a, b, _) => self.line(layer, at(a), at(b)),
+                SymbolPrim::Rect(a, b, _) => {
+                    let (a, b) = (at(a), at(b));
+                    self.polyline(layer, &[a, (b.0, a.1), b, (a.0, b.1)], true, 0.0, 0.0);
+                },
+                SymbolPrim::Triangle(a, b, d, _) => self.polyline(layer, &[at(a), at(b), at(d)], true, 0.0, 0.0),
+                SymbolPrim::Circle(a, r, _) => self.circle(layer, at(a), SYMBOL_SCALE * r as f64),
+                SymbolPrim::CircleFilled(a, r) => self.disk(layer, at(a), SYMBOL_SCALE * r as f64),
+            }
+        }
+    }
+}
+
+fn header(out :&mut Dxf) {
+    out.pair(0, "SECTION");
+    out.pair(2, "HEADER");
+    out.pair(9, "$ACADVER");
+    out.pair(1, "AC1009");
+    // Drawing units are meters.
+    out.pair(9, "$INSUNITS");
+    out.pair(70, 6);
+    out.pair(0, "ENDSEC");
+
+    out.pair(0, "SECTION");
+    out.pair(2, "TABLES");
+    out.pair(0, "TABLE");
+    out.pair(2, "LTYPE");
+    out.pair(70, 1);
+    out.pair(0, "LTYPE");
+    out.pair(2, "CONTINUOUS");
+    out.pair(70, 0);
+    out.pair(3, "Solid line");
+    out.pair(72, 65);
+    out.pair(73, 0);
+    out.pair(40, "0.0");
+    out.pair(0, "ENDTAB");
+    out.pair(0, "TABLE");
+    out.pair(2, "LAYER");
+    out.pair(70, LAYERS.len());
+    for (name, color) in LAYERS {
+        out.pair(0, "LAYER");
+        out.pair(2, name);
+        out.pair(70, 0);
+        out.pair(62, color);
+        out.pair(6, "CONTINUOUS");
+    }
+    out.pair(0, "ENDTAB");
+    out.pair(0, "ENDSEC");
+}
+
+/// The schematic of the model as a DXF drawing.
+pub fn export_dxf(model :&Model) -> Result<String, io::Error> {
+    let topo = topology::convert(model, SEGMENT_LENGTH).map_err(|_| {
+        io::Error::new(io::ErrorKind::Other, "topology conversion failed")
+    })?;
+    let mut out = Dxf { out: String::new() };
+    header(&mut out);
+    out.pair(0, "SECTION");
+    out.pair(2, "ENTITIES");
+
+    for (idx, line) in topo.interval_lines.iter().enumerate() {
+        let pts = line.iter().map(|(_, p)| coords(*p)).collect::<Vec<_>>();
+        if pts.len() < 2 { continue; }
+        out.polyline(TRACKS.0, &pts, false, 0.0, 0.0);
+
+        // The imported track name is written along the middle of the track.
+        let segments = &topo.track_segments[idx];
+        let info = model.railml_tracks.iter().find(|t| {
+            segments.iter().any(|(a,b)| t.segments.contains(&(*a,*b)) || t.segments.contains(&(*b,*a)))
+        });
+        if let Some(name) = info.map(|t| t.name.clone().or(t.code.clone()).unwrap_or(t.id.clone())) {
+            let mid = pts.len() / 2;
+            let (a, b) = (pts[mid - 1], pts[mid]);
+            let mut angle = (b.1 - a.1).atan2(b.0 - a.0).to_degrees();
+            if angle > 90.0 { angle -= 180.0; } else if angle < -90.0 { angle += 180.0; }
+            let p = (0.5 * (a.0 + b.0), 0.5 * (a.1 + b.1) + 0.5 * TEXT_HEIGHT);
+            out.text(LABELS.0, p, TEXT_HEIGHT, angle, &name);
+        }
+    }
+
+    let mut nodes = topo.locations.iter().collect::<Vec<_>>();
+    nodes.sort_by_key(|(p, _)| (p.x, p.y));
+    for (pt, (nd, tangent)) in nodes {
+        let p = coords(glm::vec2(pt.x as f32, pt.y as f32));
+        if let NDType::BufferStop = nd {
+            let s = 0.5 * TEXT_HEIGHT;
+            let n = (-tangent.y as f64, tangent.x as f64);
+            let len = (n.0 * n.0 + n.1 * n.1).sqrt().max(1.0);
+            out.line(TRACKS.0, (p.0 - s * n.0 / len, p.1 - s * n.1 / len), (p.0 + s * n.0 / len, p.1 + s * n.1 / len));
+        }
+        if let Some(info) = model.railml_nodes.get(pt) {
+            out.text(LABELS.0, (p.0 + 1.0, p.1 + 1.0), TEXT_HEIGHT, 0.0, &info.id);
+        }
+    }
+
+    let mut objects = model.objects.iter().collect::<Vec<_>>();
+    objects.sort_by_key(|(p, _)| (p.x, p.y));
+    for (pta, obj) in objects {
+        let c = coords(obj.loc);
+        for f in &obj.functions {
+            let prims = symbol_geometry(std::slice::from_ref(f), obj.tangent);
+            out.symbol(function_layer(f), c, &prims);
+        }
+        if let Some(label) = object_label(model, pta) {
+            out.text(LABELS.0, (c.0 + 3.0, c.1 + 3.0), TEXT_HEIGHT, 0.0, &label);
+        }
+    }
+
+    out.pair(0, "ENDSEC");
+    out.pair(0, "EOF");
+    Ok(out.out)
+}
+
+pub fn export_dxf_to_file(filename :&str, model :&Model) -> Result<(), io::Error> {
+    std::fs::write(filename, export_dxf(model)?)
+}
+
+pub fn export_dxf_interactive(model :&Model) -> Result<Option<String>, io::Error> {
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export DXF", "schematic.dxf") {
+        if !filename.to_lowercase().ends_with(".dxf") { filename.push_str(".dxf"); }
+        info!("Exporting DXF to {:?}", filename);
+        export_dxf_to_file(&filename, model)?;
+        Ok(Some(filename))
+    } else {
+        info!("User cancelled DXF export");
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::infview::round_coord;
+    use crate::document::objects::{Object, SignalKind};
+
+    #[test]
+    fn dxf_layers() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.node_data.insert(glm::vec2(0,0), NDType::BufferStop);
+        for (loc, functions) in vec![
+            (glm::vec2(0.5, 0.1), vec![Function::MainSignal { has_distant: false, kind: SignalKind::Main }]),
+            (glm::vec2(1.5, 0.0), vec![Function::Detector]),
+        ] {
+            model.objects.insert(round_coord(loc), Object { loc, tangent: glm::vec2(1,0), functions });
+        }
+
+        let dxf = export_dxf(&model).unwrap();
+        let lines = dxf.lines().map(|l| l.trim()).collect::<Vec<_>>();
+        assert_eq!(lines.len() % 2, 0);
+        assert_eq!(&lines[lines.len() - 2..], &["0", "EOF"]);
+        let layers = lines.chunks(2).filter(|p| p[0] == "8").map(|p| p[1]).collect::<Vec<_>>();
+        for layer in &["TRACKS", "SIGNALS", "DETECTORS"] {
+            assert!(layers.contains(layer), "no entities on layer {}", layer);
+        }
+        assert!(!layers.contains(&"OBJECTS"));
+        // The detector is drawn across the track, 75 m from its start.
+        assert!(dxf.contains("  8\nDETECTORS\n 10\n75.0000\n 20\n-2.5000\n"));
+    }
+}
@@ -0,0 +1,132 @@
+use log::*;
+use std::io;
+
+use crate::document::dgraph::DGraph;
+use crate::document::dispatch::tvd_max_pos_interval;
+use crate::document::history::History;
+use crate::document::infview::unround_coord;
+use crate::units::Units;
+use rolling::input::staticinfrastructure as rolling_inf;
+use rolling::output::history::*;
+
+/// An event of a simulated dispatch, for the event table.
+struct Event {
+    time :f64,
+    train :String,
+    object :String,
+    event :&'static str,
+    /// Mileage of the event, in meters.
+    position :Option<f64>,
+}
+
+fn fmt_signal(dgraph :&DGraph, id :&rolling_inf::ObjectId) -> String {
+    match dgraph.object_ids.get_by_left(id) {
+        Some(pta) => {
+            let p = unround_coord(*pta);
+            format!("signal ({:.1},{:.1})", p.x, p.y)
+        },
+        None => format!("signal {}", id),
+    }
+}
+
+fn train_events(history :&History, dgraph :&DGraph, out :&mut Vec<Event>) {
+    for (name, _params, events) in history.trains.iter() {
+        let mut t = 0.0;
+        for e in events {
+            match e {
+                TrainLogEvent::Wait(dt) => { t += dt; },
+                TrainLogEvent::Move(dt, _, _) => { t += dt; },
+                TrainLogEvent::Node(n) => {
+                    let object = if let Some(pt) = dgraph.node_ids.get_by_left(n) {
+                        format!("boundary ({},{})", pt.x, pt.y)
+                    } else if let Some(pta) = dgraph.detector_ids.get_by_left(n) {
+                        let p = unround_coord(*pta);
+                        format!("detector ({:.1},{:.1})", p.x, p.y)
+                    } else { continue; };
+                    out.push(Event { time: t, train: name.to_string(), object, event: "passed",
+                                     position: dgraph.mileage.get(n).cloned() });
+                },
+                TrainLogEvent::Sight(id, value) => {
+                    out.push(Event { time: t, train: name.to_string(), object: fmt_signal(dgraph, id),
+                                     event: if *value { "in sight" } else { "out of sight" }, position: None });
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+fn infrastructure_events(history :&History, dgraph :&DGraph, out :&mut Vec<Event>) {
+    let section = |tvd :&rolling_inf::ObjectId| format!("section {}", tvd);
+    let section_middle = |tvd :&rolling_inf::ObjectId| tvd_max_pos_interval(dgraph, *tvd)
+        .filter(|(a,b)| a <= b).map(|(a,b)| 0.5*(a+b));
+    let mut t = 0.0;
+    for e in &history.inf {
+        let event = match e {
+            InfrastructureLogEvent::Wait(dt) => { t += dt; continue; },
+            InfrastructureLogEvent::Authority(sig, (main, _)) => {
+                Event { time: t, train: String::new(), object: fmt_signal(dgraph, sig),
+                        event: if main.is_some() { "proceed" } else { "stop" }, position: None }
+            },
+            InfrastructureLogEvent::Reserved(tvd, on) => {
+                Event { time: t, train: String::new(), object: section(tvd),
+                        event: if *on { "reserved" } else { "released" }, position: section_middle(tvd) }
+            },
+            InfrastructureLogEvent::Occupied(tvd, on, node, _) => {
+                Event { time: t, train: String::new(), object: section(tvd),
+                        event: if *on { "occupied" } else { "vacant" },
+                        position: dgraph.mileage.get(node).cloned().or_else(|| section_middle(tvd)) }
+            },
+            InfrastructureLogEvent::Position(sw, pos) => {
+                let object = match dgraph.switch_ids.get_by_left(sw) {
+                    Some(pt) => format!("switch ({},{})", pt.x, pt.y),
+                    None => format!("switch {}", sw),
+                };
+                let event = match pos {
+                    rolling_inf::SwitchPosition::Left => "left",
+                    rolling_inf::SwitchPosition::Right => "right",
+                };
+                Event { time: t, train: String::new(), object, event, position: None }
+            },
+            _ => continue,
+        };
+        out.push(event);
+    }
+}
+
+/// The events of a simulated dispatch as CSV, ordered by time: trains
+/// passing boundaries and detectors and sighting signals, signal aspects,
+/// section reservation and occupation, and switch movements. Positions
+/// are mileages in `units`.
+pub fn format_events(history :&History, dgraph :&DGraph, units :&Units) -> String {
+    let mut events = Vec::new();
+    train_events(history, dgraph, &mut events);
+    infrastructure_events(history, dgraph, &mut events);
+    events.sort_by(|a,b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut out = format!("Time (s),Train,Object,Event,Position ({})\n", units.length.symbol());
+    for e in events {
+        let position = e.position.map(|x| format!("{:.1}", units.length.from_meters(x))).unwrap_or_default();
+        out.push_str(&format!("{:.2},{},{},{},{}\n", e.time, csv_field(&e.train), csv_field(&e.object),
+                              e.event, position));
+    }
+    out
+}
+
+/// Quote a CSV field containing separators or quotes.
+fn csv_field(s :&str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn export_interactive(history :&History, dgraph :&DGraph, units :&Units) -> Result<(), io::Error> {
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export event times", "events.csv") {
+        if !filename.to_lowercase().ends_with(".csv") { filename.push_str(".csv"); }
+        std::fs::write(&filename, format_events(history, dgraph, units))?;
+        info!("Exported event times to {:?}", filename);
+    }
+    Ok(())
+}
@@ -1,15 +1,73 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 
 use log::*;
 
-use crate::document::model::{AB, NDType, Port};
+use crate::document::model::{AnnotationAnchor, AB, CrossingType, NDType, Port, Pt, PtA, Ref, Side};
 use crate::document::objects::{Function, SignalKind};
 use crate::document::topology::{self, Topology};
 use crate::document::model::Model;
 
 use railmlio::model::*;
-use railmlio::write::write_railml;
+use railmlio::write::{write_railml_with_options, write_railml3_with_options};
+
+/// The railML version to export to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RailMLVersion {
+    V25,
+    V3,
+}
+
+impl RailMLVersion {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RailMLVersion::V25 => "railML 2.5",
+            RailMLVersion::V3 => "railML 3.2",
+        }
+    }
+}
+
+/// What became of the ids of the imported document in an export. Switch
+/// and connection ids are not kept at import, and OCPs, lines and states
+/// are written as imported, so they are not part of the report.
+#[derive(Debug, Clone, Default)]
+pub struct IdReport {
+    /// Imported ids of tracks and objects, and the id written for the
+    /// same element.
+    pub mapping: Vec<(String, String)>,
+    /// Imported ids whose element was not written, such as deleted or
+    /// moved objects.
+    pub lost: Vec<String>,
+    /// Ids of written tracks and objects that were not imported.
+    pub generated: Vec<String>,
+}
+
+impl IdReport {
+    /// Imported ids written with another id.
+    pub fn changed(&self) -> impl Iterator<Item = &(String, String)> {
+        self.mapping.iter().filter(|(a, b)| a != b)
+    }
+
+    pub fn kept(&self) -> usize {
+        self.mapping.iter().filter(|(a, b)| a == b).count()
+    }
+
+    /// One line per id, with the imported and the written id. Lost ids
+    /// have no written id, and generated ids no imported id.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("original,exported\n");
+        for (a, b) in &self.mapping {
+            out.push_str(&format!("{},{}\n", a, b));
+        }
+        for a in &self.lost {
+            out.push_str(&format!("{},\n", a));
+        }
+        for b in &self.generated {
+            out.push_str(&format!(",{}\n", b));
+        }
+        out
+    }
+}
 
 #[derive(Default)]
 struct IdCounters {
@@ -20,8 +78,13 @@ struct IdCounters {
     tpe: usize,
     tpg: usize,
     balise: usize,
+    balise_group: usize,
     platform_edge: usize,
     speed_change: usize,
+    gradient_change: usize,
+    radius_change: usize,
+    electrification_change: usize,
+    track_condition: usize,
     level_crossing: usize,
     cross_section: usize,
 }
@@ -31,6 +94,99 @@ fn next_id(prefix: &str, track_id: &str, counter: &mut usize) -> String {
     format!("{}{}{:02}", track_id, prefix, *counter)
 }
 
+/// Electrification changes where the electrification of the line segments
+/// along a track changes, a track condition for each run of line segments
+/// with the same condition, and speed changes in both directions where the
+/// maximum speed of the line segments changes. The segments are in track
+/// order, and `offsets` are the positions of their starts and of the end.
+fn lineseg_track_elements(
+    model: &Model,
+    segments: &[(Pt, Pt)],
+    offsets: &[f64],
+    abs_begin: Option<f64>,
+    tr_id: &str,
+    ids: &mut IdCounters,
+    elements: &mut TrackElements,
+) {
+    let n = segments.len();
+    let position = |i: usize| {
+        let offset = offsets.get(i).cloned().unwrap_or(0.0);
+        Position { offset, mileage: abs_begin.map(|v| v + offset), geo_coord: None }
+    };
+    let known = |t: &String| if t == "unknown" { None } else { Some(t.clone()) };
+    let attrs = segments
+        .iter()
+        .map(|(a, b)| model.lineseg_attrs(*a, *b))
+        .collect::<Vec<_>>();
+
+    let mut current = None;
+    for (i, a) in attrs.iter().enumerate() {
+        let electrification = a.and_then(|a| a.electrification.as_ref().map(|t| (t, a.voltage, a.frequency)));
+        if let Some((t, voltage, frequency)) = electrification {
+            if electrification != current {
+                elements.electrification_changes.push(ElectrificationChange {
+                    id: next_id("ec", tr_id, &mut ids.electrification_change),
+                    pos: position(i),
+                    dir: None,
+                    electrification_type: known(t),
+                    voltage,
+                    frequency,
+                    vmax: None,
+                    isolated_section: None,
+                });
+                current = electrification;
+            }
+        }
+    }
+
+    // Conditions on the line segments before the current one, with the
+    // index of the first line segment of the run.
+    let mut runs: Vec<(&String, usize)> = Vec::new();
+    for i in 0..=n {
+        let conditions = attrs.get(i).and_then(|a| a.map(|a| a.track_conditions.as_slice())).unwrap_or(&[]);
+        for (t, first) in runs.iter().filter(|(t, _)| !conditions.contains(*t)) {
+            let pos = position(*first);
+            elements.track_conditions.push(TrackCondition {
+                id: next_id("tc", tr_id, &mut ids.track_condition),
+                length: Some(position(i).offset - pos.offset),
+                pos,
+                dir: None,
+                condition_type: known(*t),
+            });
+        }
+        runs.retain(|(t, _)| conditions.contains(*t));
+        for t in conditions {
+            if !runs.iter().any(|(r, _)| *r == t) {
+                runs.push((t, i));
+            }
+        }
+    }
+
+    // A speed change up the track where a line segment starts with another
+    // maximum speed than the one before it, and down the track where it ends
+    // with another one than the one after it.
+    let speed = |i: usize| attrs.get(i).and_then(|a| a.and_then(|a| a.max_speed));
+    let mut speed_change = |pos: Position, dir: TrackDirection, vmax: f64| {
+        elements.speed_changes.push(SpeedChange {
+            id: next_id("sc", tr_id, &mut ids.speed_change),
+            pos,
+            dir,
+            vmax: Some(format!("{}", vmax)),
+            signalised: None,
+        });
+    };
+    for i in 0..n {
+        if let Some(v) = speed(i).filter(|v| i == 0 || speed(i - 1) != Some(*v)) {
+            speed_change(position(i), TrackDirection::Up, v);
+        }
+    }
+    for i in (0..n).rev() {
+        if let Some(v) = speed(i).filter(|v| speed(i + 1) != Some(*v)) {
+            speed_change(position(i + 1), TrackDirection::Down, v);
+        }
+    }
+}
+
 fn track_id(idx: usize) -> String {
     format!("tr{}", idx + 1)
 }
@@ -99,6 +255,27 @@ fn course_from_port(port: Port) -> Option<SwitchConnectionCourse> {
     }
 }
 
+/// railML crossing type for the kind of crossing.
+fn crossing_type_name(type_: CrossingType) -> &'static str {
+    match type_ {
+        CrossingType::Crossover => "simpleCrossing",
+        CrossingType::SingleSlip(_) => "simpleSwitchCrossing",
+        CrossingType::DoubleSlip => "doubleSwitchCrossing",
+    }
+}
+
+/// Course of a crossing connection. The two connections joined by a slip
+/// get the side of the slip, see the switches made for slips in `dgraph`.
+fn crossing_course(type_: CrossingType, port: Port) -> Option<SwitchConnectionCourse> {
+    let left = matches!(type_, CrossingType::DoubleSlip | CrossingType::SingleSlip(Side::Left));
+    let right = matches!(type_, CrossingType::DoubleSlip | CrossingType::SingleSlip(Side::Right));
+    match port {
+        Port::Cross(AB::A, 0) | Port::Cross(AB::B, 1) if left => Some(SwitchConnectionCourse::Left),
+        Port::Cross(AB::B, 0) | Port::Cross(AB::A, 1) if right => Some(SwitchConnectionCourse::Right),
+        _ => None,
+    }
+}
+
 fn direction_from_ab(dir: Option<AB>) -> TrackDirection {
     match dir {
         Some(AB::A) => TrackDirection::Up,
@@ -107,7 +284,7 @@ fn direction_from_ab(dir: Option<AB>) -> TrackDirection {
     }
 }
 
-fn signal_type_from_kind(kind: SignalKind, has_distant: bool) -> SignalType {
+pub fn signal_type_from_kind(kind: SignalKind, has_distant: bool) -> SignalType {
     match kind {
         SignalKind::Main => {
             if has_distant {
@@ -140,6 +317,21 @@ fn segment_key(segments: &[(crate::document::model::Pt, crate::document::model::
     out
 }
 
+/// The description of an element with the texts of the annotations
+/// anchored to it appended, each on its own line.
+fn with_annotations(description: Option<String>, model: &Model, anchored: impl Fn(&Ref) -> bool) -> Option<String> {
+    let notes = model.annotations.iter().filter_map(|(_, a)| match a.anchor {
+        Some(AnnotationAnchor::Element(r)) if !a.text.is_empty() && anchored(&r) => Some(a.text.clone()),
+        _ => None,
+    });
+    let lines = description.into_iter().chain(notes).collect::<Vec<_>>();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
 fn info_matches_function(
     info: &crate::document::model::RailMLObjectInfo,
     func: &Function,
@@ -153,15 +345,118 @@ fn info_matches_function(
         (TrainProtectionElement { .. }, Function::TrainProtectionElement) => true,
         (TrainProtectionElementGroup { .. }, Function::TrainProtectionGroup) => true,
         (Balise { .. }, Function::Balise) => true,
+        (BaliseGroup { .. }, Function::BaliseGroup) => true,
         (PlatformEdge { .. }, Function::PlatformEdge) => true,
         (SpeedChange { .. }, Function::SpeedChange) => true,
+        (GradientChange { .. }, Function::GradientChange) => true,
+        (RadiusChange { .. }, Function::RadiusChange) => true,
         (LevelCrossing { .. }, Function::LevelCrossing) => true,
         (CrossSection { .. }, Function::CrossSection) => true,
         _ => false,
     }
 }
 
-fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
+/// The id of the element last written for an object with the function.
+fn last_object_id(objects: &Objects, elements: &TrackElements, func: &Function) -> Option<String> {
+    match func {
+        Function::MainSignal { .. } => objects.signals.last().map(|x| x.id.clone()),
+        Function::Detector => objects.train_detectors.last().map(|x| x.id.clone()),
+        Function::TrackCircuitBorder => objects.track_circuit_borders.last().map(|x| x.id.clone()),
+        Function::Derailer => objects.derailers.last().map(|x| x.id.clone()),
+        Function::TrainProtectionElement => objects.train_protection_elements.last().map(|x| x.id.clone()),
+        Function::TrainProtectionGroup => objects.train_protection_element_groups.last().map(|x| x.id.clone()),
+        Function::Balise => objects.balises.last().map(|x| x.id.clone()),
+        Function::BaliseGroup => objects.balise_groups.last().map(|x| x.id.clone()),
+        Function::PlatformEdge => elements.platform_edges.last().map(|x| x.id.clone()),
+        Function::SpeedChange => elements.speed_changes.last().map(|x| x.id.clone()),
+        Function::GradientChange => elements.gradient_changes.last().map(|x| x.id.clone()),
+        Function::RadiusChange => elements.radius_changes.last().map(|x| x.id.clone()),
+        Function::LevelCrossing => elements.level_crossings.last().map(|x| x.id.clone()),
+        Function::CrossSection => elements.cross_sections.last().map(|x| x.id.clone()),
+    }
+}
+
+/// Compare the written track and object ids with the imported ones.
+/// An object written with a new id takes over an unused imported id at the
+/// same place, as when its function was changed, and a track that was split
+/// or joined takes over the id of the imported track its first segment
+/// belonged to.
+fn id_report(model: &Model, topo: &Topology, track_ids: &[String], written: &[(PtA, String)]) -> IdReport {
+    let mut report = IdReport::default();
+
+    let imported_tracks = model.railml_tracks.iter().map(|t| t.id.as_str()).collect::<HashSet<_>>();
+    let mut taken_tracks = HashSet::new();
+    for info in model.railml_tracks.iter() {
+        if track_ids.contains(&info.id) {
+            report.mapping.push((info.id.clone(), info.id.clone()));
+            report.mapping.push((info.begin_id.clone(), info.begin_id.clone()));
+            report.mapping.push((info.end_id.clone(), info.end_id.clone()));
+            continue;
+        }
+        let idx = info.segments.first().and_then(|(a, b)| {
+            topo.track_segments.iter().position(|segs| segs.contains(&(*a, *b)) || segs.contains(&(*b, *a)))
+        });
+        match idx {
+            Some(idx) if !imported_tracks.contains(track_ids[idx].as_str()) => {
+                report.mapping.push((info.id.clone(), track_ids[idx].clone()));
+                taken_tracks.insert(idx);
+            }
+            _ => report.lost.push(info.id.clone()),
+        }
+        report.lost.push(info.begin_id.clone());
+        report.lost.push(info.end_id.clone());
+    }
+    for (idx, id) in track_ids.iter().enumerate() {
+        if !imported_tracks.contains(id.as_str()) && !taken_tracks.contains(&idx) {
+            report.generated.push(id.clone());
+        }
+    }
+
+    let imported = model.railml_objects.values().flatten().map(|i| i.id()).collect::<HashSet<_>>();
+    let written_ids = written.iter().map(|(_, id)| id.as_str()).collect::<HashSet<_>>();
+    let mut taken = HashSet::new();
+    for (pt, id) in written {
+        if imported.contains(id.as_str()) {
+            report.mapping.push((id.clone(), id.clone()));
+            continue;
+        }
+        let original = model.railml_objects.get(pt).into_iter().flatten()
+            .map(|i| i.id())
+            .find(|i| !written_ids.contains(i) && !taken.contains(i));
+        match original {
+            Some(original) => {
+                taken.insert(original);
+                report.mapping.push((original.to_string(), id.clone()));
+            }
+            None => report.generated.push(id.clone()),
+        }
+    }
+    let mut lost = imported.iter()
+        .filter(|i| !written_ids.contains(*i) && !taken.contains(*i))
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>();
+    lost.sort();
+    report.lost.extend(lost);
+    report
+}
+
+/// The OCP with the position of its station marker as geoCoord. The
+/// imported geoCoord is kept while the marker is where it says.
+fn export_ocp(ocp: &Ocp, model: &Model) -> Ocp {
+    let mut ocp = ocp.clone();
+    if let Some(p) = model.ocp_positions.get(&ocp.id) {
+        let imported = ocp.geo_coord.as_ref().and_then(|g| railmlio::topo::parse_geo_coord(&g.coord));
+        let unchanged = imported
+            .map(|(x, y)| (x as f32 - p.x).abs() < 1e-3 && (y as f32 - p.y).abs() < 1e-3)
+            .unwrap_or(false);
+        if !unchanged {
+            ocp.geo_coord = Some(GeoCoord { coord: geo_coord_from_xy(p.x as f64, p.y as f64), epsg_code: None });
+        }
+    }
+    ocp
+}
+
+fn convert_topology_to_railml(topo: &Topology, model: &Model) -> (RailML, IdReport) {
     let mut node_map: HashMap<crate::document::model::Pt, Vec<(usize, AB, Port)>> = HashMap::new();
     let mut track_lengths = Vec::new();
     for (idx, (len, (pta, porta), (ptb, portb))) in topo.tracks.iter().enumerate() {
@@ -177,7 +472,7 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
     }
 
     let mut track_info_by_segments = HashMap::new();
-    for info in &model.railml_tracks {
+    for info in model.railml_tracks.iter() {
         let key = segment_key(&info.segments);
         track_info_by_segments.insert(key, info);
     }
@@ -204,6 +499,15 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
             .map(|(nd, _)| *nd)
             .unwrap_or(NDType::OpenEnd);
 
+        // Imported boundaries keep their macroscopic node names.
+        if let Some(node) = model.railml_nodes.get(pt).filter(|_| matches!(nd, NDType::OpenEnd | NDType::BufferStop)) {
+            for (track_idx, end, _) in ends {
+                let conn = TrackEndConnection::MacroscopicNode(node.id.clone(), node.ocp_ref.clone());
+                track_connections.insert((*track_idx, *end), conn);
+            }
+            continue;
+        }
+
         match nd {
             NDType::OpenEnd => {
                 for (track_idx, end, _) in ends {
@@ -277,17 +581,22 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                     id: switch_id,
                     pos: sw_pos,
                     name: None,
-                    description: None,
+                    description: with_annotations(None, model, |r| *r == Ref::Node(*pt)),
                     length: None,
                     connections,
                     track_continue_course: Some(SwitchConnectionCourse::Straight),
                     track_continue_radius: None,
                 });
             }
-            NDType::Crossing(_) => {
+            NDType::Crossing(type_) => {
                 let switch_id = node_id("crs", *pt);
                 let mut ordered = ends.clone();
-                ordered.sort_by_key(|(_, _, port)| port_order(*port));
+                // Opposite ports next to each other, so that each pair of
+                // connections passes straight through the crossing.
+                ordered.sort_by_key(|(_, _, port)| match port {
+                    Port::Cross(ab, n) => (*n, *ab == AB::B),
+                    _ => (usize::MAX, false),
+                });
 
                 let host_track = ordered[0].0;
                 let host_end = ordered[0].1;
@@ -311,7 +620,7 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                         id: switch_conn,
                         r#ref: track_conn,
                         orientation: ConnectionOrientation::Incoming,
-                        course: course_from_port(*port),
+                        course: crossing_course(type_, *port),
                         radius: None,
                         max_speed: None,
                         passable: None,
@@ -321,6 +630,7 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                 track_switches[host_track].push(Switch::Crossing {
                     id: switch_id,
                     pos: sw_pos,
+                    crossing_type: Some(crossing_type_name(type_).to_string()),
                     track_continue_course: None,
                     track_continue_radius: None,
                     normal_position: None,
@@ -337,6 +647,7 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
     }
 
     let mut tracks = Vec::new();
+    let mut written = Vec::new();
 
     for (idx, (len, _a, _b)) in topo.tracks.iter().enumerate() {
         let segments = topo.track_segments.get(idx).cloned().unwrap_or_default();
@@ -370,7 +681,16 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                 )
             };
 
-        let scale = if let (Some(a), Some(b)) = (abs_begin, abs_end) {
+        // Names and real lengths set on the line segments replace the
+        // imported ones. Real lengths are not scaled to the imported mileage.
+        let seg_attrs = segments.iter().filter_map(|(a, b)| model.lineseg_attrs(*a, *b)).collect::<Vec<_>>();
+        let track_name = seg_attrs.iter().find_map(|a| a.track_name.clone()).or(track_name);
+        let track_code = seg_attrs.iter().find_map(|a| a.track_code.clone()).or(track_code);
+        let real_lengths = seg_attrs.iter().any(|a| a.length.is_some());
+
+        let scale = if real_lengths {
+            1.0
+        } else if let (Some(a), Some(b)) = (abs_begin, abs_end) {
             let abs_len = (b - a).abs();
             if *len > 0.0 { abs_len / *len } else { 1.0 }
         } else {
@@ -404,12 +724,10 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                         id,
                         pos,
                         name: None,
-                        dir: info
-                            .and_then(|i| match i {
-                                crate::document::model::RailMLObjectInfo::Signal { dir, .. } => Some(*dir),
-                                _ => None,
-                            })
-                            .unwrap_or_else(|| direction_from_ab(*dir)),
+                        // The signal faces along its tangent, which may have been
+                        // turned since import, and signals added in the editor have
+                        // no imported direction.
+                        dir: direction_from_ab(*dir),
                         sight: info
                             .and_then(|i| match i {
                                 crate::document::model::RailMLObjectInfo::Signal { sight, .. } => *sight,
@@ -436,8 +754,17 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                                 crate::document::model::RailMLObjectInfo::Signal { ocp_station_ref, .. } => ocp_station_ref.clone(),
                                 _ => None,
                             }),
-                        speeds: Vec::new(),
-                        etcs: None,
+                        speeds: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::Signal { speeds, .. } => Some(speeds.clone()),
+                                _ => None,
+                            })
+                            .unwrap_or_default(),
+                        etcs: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::Signal { etcs, .. } => etcs.clone(),
+                                _ => None,
+                            }),
                     });
                 }
                 Function::Detector => {
@@ -573,7 +900,39 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                             crate::document::model::RailMLObjectInfo::Balise { name, .. } => name.clone(),
                             _ => None,
                         });
-                    objects.balises.push(Balise { id, pos, name });
+                    let group_ref = info
+                        .and_then(|i| match i {
+                            crate::document::model::RailMLObjectInfo::Balise { group_ref, .. } => group_ref.clone(),
+                            _ => None,
+                        });
+                    objects.balises.push(Balise { id, pos, name, group_ref });
+                }
+                Function::BaliseGroup => {
+                    let group = match info {
+                        Some(crate::document::model::RailMLObjectInfo::BaliseGroup {
+                            id, name, dir, country_id, group_id, ndx, linking_accuracy,
+                        }) => BaliseGroup {
+                            id: id.clone(),
+                            pos,
+                            name: name.clone(),
+                            dir: *dir,
+                            country_id: country_id.clone(),
+                            group_id: group_id.clone(),
+                            ndx: *ndx,
+                            linking_accuracy: *linking_accuracy,
+                        },
+                        _ => BaliseGroup {
+                            id: next_id("bg", &tr_id, &mut ids.balise_group),
+                            pos,
+                            name: None,
+                            dir: None,
+                            country_id: None,
+                            group_id: None,
+                            ndx: None,
+                            linking_accuracy: None,
+                        },
+                    };
+                    objects.balise_groups.push(group);
                 }
                 Function::PlatformEdge => {
                     let id = info
@@ -641,6 +1000,65 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                             }),
                     });
                 }
+                Function::GradientChange => {
+                    let id = info
+                        .and_then(|i| match i {
+                            crate::document::model::RailMLObjectInfo::GradientChange { id, .. } => Some(id.clone()),
+                            _ => None,
+                        })
+                        .unwrap_or_else(|| next_id("gc", &tr_id, &mut ids.gradient_change));
+                    elements.gradient_changes.push(GradientChange {
+                        id,
+                        pos,
+                        // The object points uphill, so the direction is that of the track here.
+                        dir: direction_from_ab(*dir),
+                        slope: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::GradientChange { slope, .. } => Some(slope.abs()),
+                                _ => None,
+                            })
+                            .unwrap_or(0.0),
+                        transition_length: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::GradientChange { transition_length, .. } => *transition_length,
+                                _ => None,
+                            }),
+                        transition_radius: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::GradientChange { transition_radius, .. } => *transition_radius,
+                                _ => None,
+                            }),
+                    });
+                }
+                Function::RadiusChange => {
+                    let id = info
+                        .and_then(|i| match i {
+                            crate::document::model::RailMLObjectInfo::RadiusChange { id, .. } => Some(id.clone()),
+                            _ => None,
+                        })
+                        .unwrap_or_else(|| next_id("rc", &tr_id, &mut ids.radius_change));
+                    elements.radius_changes.push(RadiusChange {
+                        id,
+                        pos,
+                        dir: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::RadiusChange { dir, .. } => Some(*dir),
+                                _ => None,
+                            })
+                            .unwrap_or(TrackDirection::Down),
+                        radius: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::RadiusChange { radius, .. } => Some(*radius),
+                                _ => None,
+                            })
+                            .unwrap_or(0.0),
+                        superelevation: info
+                            .and_then(|i| match i {
+                                crate::document::model::RailMLObjectInfo::RadiusChange { superelevation, .. } => *superelevation,
+                                _ => None,
+                            }),
+                    });
+                }
                 Function::LevelCrossing => {
                     let id = info
                         .and_then(|i| match i {
@@ -691,6 +1109,17 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
                     });
                 }
             }
+            if let Some(id) = last_object_id(&objects, &elements, func) {
+                written.push((*pt, id));
+            }
+        }
+
+        // A balise stays in its group only while the group is on the same track.
+        let group_ids = objects.balise_groups.iter().map(|g| g.id.clone()).collect::<HashSet<_>>();
+        for b in &mut objects.balises {
+            if b.group_ref.as_ref().map(|r| !group_ids.contains(r)).unwrap_or(false) {
+                b.group_ref = None;
+            }
         }
 
         if let Some(lines) = topo.interval_lines.get(idx) {
@@ -712,6 +1141,13 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
             }
         }
 
+        let offsets = topo
+            .interval_lines
+            .get(idx)
+            .map(|lines| lines.iter().map(|(pos, _)| pos.0 * scale).collect::<Vec<_>>())
+            .unwrap_or_default();
+        lineseg_track_elements(model, &segments, &offsets, abs_begin, &tr_id, &mut ids, &mut elements);
+
         let begin_conn = track_connections
             .remove(&(idx, AB::A))
             .unwrap_or(TrackEndConnection::OpenEnd);
@@ -747,7 +1183,10 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
             id: tr_id,
             code: track_code,
             name: track_name,
-            description: track_desc,
+            description: with_annotations(track_desc, model, |r| match r {
+                Ref::LineSeg(a, b) => segments.contains(&(*a, *b)) || segments.contains(&(*b, *a)),
+                _ => false,
+            }),
             track_type: track_type,
             main_dir: track_main_dir,
             begin,
@@ -755,19 +1194,44 @@ fn convert_topology_to_railml(topo: &Topology, model: &Model) -> RailML {
             switches: track_switches[idx].clone(),
             track_elements: elements,
             objects,
+            extras: Extras::default(),
         });
     }
 
-    RailML {
+    // Speed announcements of signals are kept while their speed change is.
+    let speed_change_ids = tracks.iter()
+        .flat_map(|t| t.track_elements.speed_changes.iter().map(|s| s.id.clone()))
+        .collect::<HashSet<_>>();
+    for track in &mut tracks {
+        for signal in &mut track.objects.signals {
+            let id = &signal.id;
+            signal.speeds.retain(|sp| match &sp.speed_change_ref {
+                Some(r) if !speed_change_ids.contains(r) => {
+                    warn!("Signal {} refers to removed speed change {}, speed not exported", id, r);
+                    false
+                }
+                _ => true,
+            });
+        }
+    }
+
+    let report = id_report(model, topo, &track_ids, &written);
+    let railml = RailML {
         metadata: model.railml_metadata.clone(),
         infrastructure: Some(Infrastructure {
             tracks,
-            track_groups: model.railml_track_groups.clone(),
-            ocps: model.railml_ocps.clone(),
-            states: model.railml_states.clone(),
+            track_groups: (*model.railml_track_groups).clone(),
+            ocps: model.railml_ocps.iter().map(|ocp| export_ocp(ocp, model)).collect(),
+            states: (*model.railml_states).clone(),
+            extras: Extras::default(),
         }),
         rollingstock: build_rollingstock(model),
-    }
+        interlocking: model.railml_interlocking.as_ref().map(|il| (**il).clone()),
+        timetable: None,
+        extras: Extras::default(),
+        parse_report: ParseReport::default(),
+    };
+    (railml, report)
 }
 
 fn build_rollingstock(model: &Model) -> Option<Rollingstock> {
@@ -791,22 +1255,162 @@ fn build_rollingstock(model: &Model) -> Option<Rollingstock> {
     Some(Rollingstock { vehicles })
 }
 
-pub fn export_railml_to_file(filename: &str, model: &Model) -> Result<(), io::Error> {
+/// The model as a railML document.
+pub fn railml_string(model: &Model, version: RailMLVersion) -> Result<String, io::Error> {
+    railml_string_with_report(model, version).map(|(xml, _)| xml)
+}
+
+/// The model as a railML document, and what became of the imported ids.
+/// The document is written with the export options saved in the model.
+pub fn railml_string_with_report(model: &Model, version: RailMLVersion) -> Result<(String, IdReport), io::Error> {
     let topo = topology::convert(model, 50.0).map_err(|_| {
         io::Error::new(io::ErrorKind::Other, "topology conversion failed")
     })?;
-    let railml = convert_topology_to_railml(&topo, model);
-    let xml = write_railml(&railml);
+    let (railml, report) = convert_topology_to_railml(&topo, model);
+    let opts = model.file_settings.export.clone().unwrap_or_default();
+    let xml = match version {
+        RailMLVersion::V25 => write_railml_with_options(&railml, &opts),
+        RailMLVersion::V3 => write_railml3_with_options(&railml, &opts),
+    };
+    Ok((xml, report))
+}
+
+/// The part of the model to export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportScope {
+    Model,
+    /// The selected line segments, and the selected objects on them.
+    Selection(HashSet<Ref>),
+    /// The tracks of the stations with these OCP ids, from the station
+    /// references of their signals and the OCPs of their end nodes.
+    Ocps(Vec<String>),
+    /// The tracks of the lines (track groups) with these ids.
+    Lines(Vec<String>),
+}
+
+/// The line segments of the tracks in the scope.
+fn scope_linesegs(model: &Model, scope: &ExportScope) -> Result<HashSet<(Pt, Pt)>, io::Error> {
+    let ordered = |(a, b): (Pt, Pt)| if model.linesegs.contains(&(a, b)) { (a, b) } else { (b, a) };
+    Ok(match scope {
+        ExportScope::Model => model.linesegs.iter().cloned().collect(),
+        ExportScope::Selection(selection) => selection.iter().filter_map(|r| match r {
+            Ref::LineSeg(a, b) => Some(ordered((*a, *b))),
+            _ => None,
+        }).collect(),
+        ExportScope::Ocps(ids) => {
+            let topo = topology::convert(model, 50.0).map_err(|_| {
+                io::Error::new(io::ErrorKind::Other, "topology conversion failed")
+            })?;
+            let stations = crate::document::objecttree::track_stations(model, &topo);
+            let in_scope = |idx: &Option<usize>| idx.and_then(|i| model.railml_ocps.get(i))
+                .map(|o| ids.contains(&o.id)).unwrap_or(false);
+            let node_in_scope = |pt: &Pt| model.railml_nodes.get(pt).and_then(|n| n.ocp_ref.as_ref())
+                .map(|r| ids.contains(r)).unwrap_or(false);
+            let mut linesegs = HashSet::new();
+            for (i, (_, (a, _), (b, _))) in topo.tracks.iter().enumerate() {
+                if in_scope(&stations[i]) || node_in_scope(a) || node_in_scope(b) {
+                    linesegs.extend(topo.track_segments[i].iter().map(|s| ordered(*s)));
+                }
+            }
+            linesegs
+        },
+        ExportScope::Lines(ids) => {
+            let tracks = model.railml_track_groups.iter().filter(|g| ids.contains(&g.id))
+                .flat_map(|g| g.track_refs.iter().map(|r| r.r#ref.clone()))
+                .collect::<HashSet<_>>();
+            let mut linesegs = HashSet::new();
+            for t in model.railml_tracks.iter().filter(|t| tracks.contains(&t.id)) {
+                linesegs.extend(t.segments.iter().map(|s| ordered(*s)));
+            }
+            linesegs
+        },
+    })
+}
+
+/// A model with only the tracks in the scope and the objects on them. The
+/// types of nodes where tracks were cut off are found again from the
+/// remaining tracks, and the cut ends become open ends. Dispatches, plans
+/// and annotations referring to the rest of the model are left out, see
+/// `Model::remove_dangling_refs`. This is used for both exporting and
+/// saving parts of a model.
+pub fn scoped_model(model: &Model, scope: &ExportScope) -> Result<Model, io::Error> {
+    if let ExportScope::Model = scope { return Ok(model.clone()); }
+    let linesegs = scope_linesegs(model, scope)?;
+    let kept = |s: &(Pt, Pt)| linesegs.contains(s) || linesegs.contains(&(s.1, s.0));
+    let mut part = model.clone();
+    part.linesegs = model.linesegs.iter().filter(|s| kept(*s)).cloned().collect();
+
+    part.objects = model.objects.iter().filter(|(pta, obj)| {
+        let selected = match scope {
+            ExportScope::Selection(selection) => selection.contains(&Ref::Object(**pta)),
+            _ => true,
+        };
+        selected && model.get_closest_lineseg(obj.loc).map(|(s, _, _)| kept(&s)).unwrap_or(false)
+    }).map(|(pta, obj)| (*pta, obj.clone())).collect();
+    part.railml_objects = model.railml_objects.iter().filter(|(pta, _)| part.objects.contains_key(*pta))
+        .map(|(pta, info)| (*pta, info.clone())).collect();
+    part.lineseg_attributes = model.lineseg_attributes.iter().filter(|(s, _)| kept(*s))
+        .map(|(s, attr)| (*s, attr.clone())).collect();
+
+    let degree = |linesegs: &im::HashSet<(Pt, Pt)>| {
+        let mut degree: HashMap<Pt, usize> = HashMap::new();
+        for (a, b) in linesegs.iter() {
+            *degree.entry(*a).or_insert(0) += 1;
+            *degree.entry(*b).or_insert(0) += 1;
+        }
+        degree
+    };
+    let (before, after) = (degree(&model.linesegs), degree(&part.linesegs));
+    part.node_data = im::HashMap::new();
+    for (pt, n) in after.iter() {
+        if before.get(pt) == Some(n) {
+            if let Some(nd) = model.node_data.get(pt) { part.node_data.insert(*pt, *nd); }
+        } else if *n == 1 {
+            part.node_data.insert(*pt, NDType::OpenEnd);
+        }
+    }
+    part.railml_nodes = model.railml_nodes.iter().filter(|(pt, _)| part.node_data.contains_key(*pt))
+        .map(|(pt, node)| (*pt, node.clone())).collect();
+    part.trap_points = model.trap_points.iter().filter(|pt| before.get(*pt) == after.get(*pt))
+        .cloned().collect();
+
+    // Imported tracks and lines are kept where some of their tracks are exported.
+    let tracks = model.railml_tracks.iter().filter(|t| t.segments.iter().any(|s| kept(s)))
+        .cloned().collect::<Vec<_>>();
+    let track_ids = tracks.iter().map(|t| t.id.as_str()).collect::<HashSet<_>>();
+    let groups = model.railml_track_groups.iter().cloned().filter_map(|mut g| {
+        g.track_refs.retain(|r| track_ids.contains(r.r#ref.as_str()));
+        if g.track_refs.is_empty() { None } else { Some(g) }
+    }).collect::<Vec<_>>();
+    part.railml_track_groups = std::sync::Arc::new(groups);
+    part.railml_tracks = std::sync::Arc::new(tracks);
+    part.remove_dangling_refs();
+    info!("Export scope has {} of {} line segments and {} of {} objects", part.linesegs.len(),
+          model.linesegs.len(), part.objects.len(), model.objects.len());
+    Ok(part)
+}
+
+pub fn export_railml_to_file(filename: &str, model: &Model, version: RailMLVersion) -> Result<IdReport, io::Error> {
+    let (xml, report) = railml_string_with_report(model, version)?;
     std::fs::write(filename, xml)?;
-    Ok(())
+    if report.changed().next().is_some() || !report.lost.is_empty() {
+        warn!("{} imported ids were changed and {} lost in the export",
+              report.changed().count(), report.lost.len());
+    }
+    Ok(report)
 }
 
-pub fn export_railml_interactive(model: &Model) -> Result<(), io::Error> {
-    if let Some(filename) = tinyfiledialogs::save_file_dialog("Export railML to file", "") {
-        info!("Exporting railML to {:?}", filename);
-        export_railml_to_file(&filename, model)?;
+/// Export to a file chosen by the user, starting from the document's previous
+/// export path. Returns the chosen path and the id report.
+pub fn export_railml_interactive(model: &Model, version: RailMLVersion) -> Result<Option<(String, IdReport)>, io::Error> {
+    let default_path = model.file_settings.railml_export_path.as_ref().map(|s| s.as_str()).unwrap_or("");
+    let title = format!("Export {} to file", version.name());
+    if let Some(filename) = tinyfiledialogs::save_file_dialog(&title, default_path) {
+        info!("Exporting {} to {:?}", version.name(), filename);
+        let report = export_railml_to_file(&filename, model, version)?;
+        Ok(Some((filename, report)))
     } else {
         info!("User cancelled railML export");
+        Ok(None)
     }
-    Ok(())
 }
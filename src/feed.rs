@@ -0,0 +1,264 @@
+//! Live feed of the dispatch playback for external displays, such as train
+//! describers. Train positions, signal aspects, section states and switch
+//! positions are sent as JSON messages, either as lines on a TCP connection
+//! or published to a topic on an MQTT broker.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use log::*;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use crate::document::{Document, DispatchView};
+use crate::document::dispatch::{SectionStatus, SwitchStatus};
+use crate::document::objects::ObjectState;
+
+/// Time to wait before connecting again after the connection failed.
+const RECONNECT_INTERVAL :Duration = Duration::from_secs(5);
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FeedProtocol {
+    /// Newline-separated JSON messages on a TCP connection.
+    Tcp,
+    /// JSON messages published with QoS 0 to an MQTT 3.1.1 broker.
+    Mqtt,
+}
+
+impl FeedProtocol {
+    pub const ALL :&'static [FeedProtocol] = &[FeedProtocol::Tcp, FeedProtocol::Mqtt];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeedProtocol::Tcp => "TCP",
+            FeedProtocol::Mqtt => "MQTT",
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FeedSettings {
+    pub protocol :FeedProtocol,
+    /// Host and port to connect to, e.g. `localhost:1883`.
+    pub address :String,
+    /// MQTT topic the messages are published to.
+    pub topic :String,
+    /// Maximum number of messages per second.
+    pub rate :f32,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        FeedSettings {
+            protocol: FeedProtocol::Mqtt,
+            address: "localhost:1883".to_string(),
+            topic: "junction/state".to_string(),
+            rate: 5.0,
+        }
+    }
+}
+
+pub struct Feed {
+    settings :FeedSettings,
+    send :mpsc::Sender<String>,
+    last_message :Option<String>,
+    last_sent :Option<Instant>,
+}
+
+impl Feed {
+    /// Start sending to the address in the settings. The connection is made
+    /// in the background and made again if it is lost.
+    pub fn start(settings :&FeedSettings) -> Feed {
+        let (send, recv) = mpsc::channel();
+        let thread_settings = settings.clone();
+        std::thread::spawn(move || run(&thread_settings, recv));
+        info!("Sending train feed to {} {}", settings.protocol.name(), settings.address);
+        Feed { settings: settings.clone(), send, last_message: None, last_sent: None }
+    }
+
+    pub fn settings(&self) -> &FeedSettings { &self.settings }
+
+    /// Send the state of the dispatch playback, if it has changed and the
+    /// rate allows it. Called every frame.
+    pub fn update(&mut self, doc :&Document) {
+        let interval = Duration::from_secs_f32(1.0 / self.settings.rate.max(0.1));
+        if self.last_sent.map(|t| t.elapsed() < interval).unwrap_or(false) { return; }
+        let message = match current_message(doc) { Some(m) => m, None => return };
+        if self.last_message.as_ref() == Some(&message) { return; }
+        let _ = self.send.send(message.clone());
+        self.last_message = Some(message);
+        self.last_sent = Some(Instant::now());
+    }
+}
+
+fn current_message(doc :&Document) -> Option<String> {
+    let model = doc.analysis.model();
+    let (name, time) = match &doc.dispatch_view {
+        Some(DispatchView::Manual(m)) =>
+            (model.dispatches.get(m.dispatch_idx).map(|d| d.name.clone()).unwrap_or_default(), m.time),
+        Some(DispatchView::Auto(a)) => {
+            let m = a.dispatch.as_ref()?;
+            (format!("Dispatch {}", m.dispatch_idx + 1), m.time)
+        },
+        None => return None,
+    };
+    let instant = doc.dispatch_view.as_ref().and_then(|d| d.dispatch_ref())
+        .and_then(|r| doc.inf_view.instant_cache.get(r))?;
+
+    let trains = instant.trains.iter().map(|t| json!({
+        "front": t.get_front().map(|p| (p.x, p.y)),
+        "lines": t.lines.iter().map(|(a,b)| ((a.x, a.y), (b.x, b.y))).collect::<Vec<_>>(),
+    })).collect::<Vec<_>>();
+
+    let mut signals = instant.infrastructure.object_state.iter().filter_map(|(pta,states)| {
+        let aspect = states.iter().find_map(|s| match s {
+            ObjectState::SignalStop => Some("stop"),
+            ObjectState::SignalProceed => Some("proceed"),
+            _ => None,
+        });
+        let distant = states.iter().find_map(|s| match s {
+            ObjectState::DistantStop => Some("stop"),
+            ObjectState::DistantProceed => Some("proceed"),
+            _ => None,
+        });
+        if aspect.is_none() && distant.is_none() { return None; }
+        let loc = model.objects.get(pta)?.loc;
+        Some(json!({ "pos": (loc.x, loc.y), "aspect": aspect, "distant": distant }))
+    }).collect::<Vec<_>>();
+    // Sorted, so that an unchanged state gives the same message.
+    signals.sort_by_key(|s| s.to_string());
+
+    let sections = instant.infrastructure.sections.iter().map(|(id,status,_)| json!({
+        "id": id,
+        "status": match status {
+            SectionStatus::Free => "free",
+            SectionStatus::Reserved => "reserved",
+            SectionStatus::Occupied => "occupied",
+            SectionStatus::Released => "released",
+        },
+    })).collect::<Vec<_>>();
+
+    let mut switches = instant.infrastructure.switches.iter().map(|(pt,status)| json!({
+        "pos": (pt.x, pt.y),
+        "position": match status {
+            SwitchStatus::Left => "left",
+            SwitchStatus::Right => "right",
+            SwitchStatus::Unknown => "unknown",
+        },
+    })).collect::<Vec<_>>();
+    switches.sort_by_key(|s| s.to_string());
+
+    Some(json!({
+        "dispatch": name,
+        "time": time,
+        "trains": trains,
+        "signals": signals,
+        "sections": sections,
+        "switches": switches,
+    }).to_string())
+}
+
+/// Send messages until the feed is dropped. Messages arriving while there
+/// is no connection are dropped, as only the latest state is of interest.
+fn run(settings :&FeedSettings, recv :mpsc::Receiver<String>) {
+    let mut stream :Option<TcpStream> = None;
+    let mut last_attempt :Option<Instant> = None;
+    for message in recv {
+        if stream.is_none() && last_attempt.map(|t| t.elapsed() >= RECONNECT_INTERVAL).unwrap_or(true) {
+            last_attempt = Some(Instant::now());
+            match connect(settings) {
+                Ok(s) => {
+                    info!("Train feed connected to {}", settings.address);
+                    stream = Some(s);
+                },
+                Err(e) => { warn!("Train feed could not connect to {}: {}", settings.address, e); },
+            }
+        }
+        if let Some(s) = &mut stream {
+            let data = match settings.protocol {
+                FeedProtocol::Tcp => format!("{}\n", message).into_bytes(),
+                FeedProtocol::Mqtt => mqtt_publish(&settings.topic, message.as_bytes()),
+            };
+            if let Err(e) = s.write_all(&data) {
+                warn!("Train feed connection to {} lost: {}", settings.address, e);
+                stream = None;
+            }
+        }
+    }
+}
+
+fn connect(settings :&FeedSettings) -> Result<TcpStream, String> {
+    let mut stream = TcpStream::connect(&settings.address).map_err(|e| format!("{}", e))?;
+    let _ = stream.set_nodelay(true);
+    if settings.protocol == FeedProtocol::Mqtt {
+        let client_id = format!("junction-{}", std::process::id());
+        stream.write_all(&mqtt_connect(&client_id)).map_err(|e| format!("{}", e))?;
+        stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| format!("{}", e))?;
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack).map_err(|e| format!("{}", e))?;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(format!("broker refused the connection (code {})", connack[3]));
+        }
+    }
+    Ok(stream)
+}
+
+fn mqtt_remaining_length(mut len :usize, out :&mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 { byte |= 0x80; }
+        out.push(byte);
+        if len == 0 { break; }
+    }
+}
+
+fn mqtt_string(s :&[u8], out :&mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s);
+}
+
+fn mqtt_packet(header :u8, body :&[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    mqtt_remaining_length(body.len(), &mut packet);
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// CONNECT with a clean session and no keep alive, as the feed only publishes.
+fn mqtt_connect(client_id :&str) -> Vec<u8> {
+    let mut body = Vec::new();
+    mqtt_string(b"MQTT", &mut body);
+    body.push(4); // protocol level 3.1.1
+    body.push(0x02); // clean session
+    body.extend_from_slice(&0u16.to_be_bytes());
+    mqtt_string(client_id.as_bytes(), &mut body);
+    mqtt_packet(0x10, &body)
+}
+
+/// PUBLISH with QoS 0.
+fn mqtt_publish(topic :&str, payload :&[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    mqtt_string(topic.as_bytes(), &mut body);
+    body.extend_from_slice(payload);
+    mqtt_packet(0x30, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mqtt_packets() {
+        assert_eq!(mqtt_publish("a/b", b"hi"), vec![0x30, 7, 0, 3, b'a', b'/', b'b', b'h', b'i']);
+        let long = mqtt_publish("t", &[0u8; 200]);
+        assert_eq!(&long[..3], &[0x30, 0xcb, 0x01]);
+        assert_eq!(long.len(), 3 + 203);
+        let connect = mqtt_connect("x");
+        assert_eq!(connect, vec![0x10, 13, 0, 4, b'M', b'Q', b'T', b'T', 4, 2, 0, 0, 0, 1, b'x']);
+    }
+}
@@ -1,22 +1,418 @@
-use crate::document::model::Model;
+use crate::document::model::{EditClass, Model};
 use crate::util::order_ivec;
+use std::collections::BTreeMap;
 use std::fs::File;
 use log::*;
-use serde::Serialize;
+use matches::matches;
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+
+/// Version of the save file format. Increase this and add a step to
+/// `MIGRATIONS` when a change to the model would make older files fail to
+/// deserialize.
+pub const FORMAT_VERSION :u32 = 5;
+
+/// Upgrade steps for saved documents, `MIGRATIONS[i]` converts a file of
+/// version `i` to version `i+1`.
+const MIGRATIONS :&[fn(Value) -> Result<Value, String>] = &[
+    // 0 -> 1: files saved before the format was versioned contain the bare
+    // model, which is still readable as is.
+    |model| Ok(model),
+    // 1 -> 2: selection groups were added, and are empty when missing.
+    |model| Ok(model),
+    // 2 -> 3: annotations were added, and are empty when missing.
+    |model| Ok(model),
+    // 3 -> 4: view bookmarks were added, and are empty when missing.
+    |model| Ok(model),
+    // 4 -> 5: line segment attributes got a length, speed and track name,
+    // which are unset when missing.
+    |model| Ok(model),
+];
+
+/// Saved documents are wrapped in a header identifying the format version.
+#[derive(Serialize)]
+struct SavedFile<'a> {
+    format :&'static str,
+    version :u32,
+    model :&'a Model,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history :Option<SavedHistory>,
+}
+
+/// Undo and redo steps saved with a document, so that the edits made before
+/// it was saved can still be undone after it is opened again.
+#[derive(Clone, Debug)]
+pub struct UndoHistory {
+    /// Models from the oldest undo step to the newest redo step.
+    pub steps :Vec<Model>,
+    /// Index of the saved model in `steps`.
+    pub pointer :usize,
+    /// Kind of the last edit, which a following edit of the same kind replaces.
+    pub class :Option<EditClass>,
+}
+
+/// The undo history as written in the file. Each step is kept as the model
+/// fields that differ from the saved model, which are usually few.
+#[derive(Serialize, Deserialize)]
+struct SavedHistory {
+    steps :Vec<Value>,
+    pointer :usize,
+    class :Option<EditClass>,
+}
+
+const FORMAT_NAME :&str = "junction";
+
+/// Model fields which are serialized as sequences in hash order, and are
+/// sorted when writing the text format.
+const UNORDERED_FIELDS :&[&str] = &["linesegs", "trap_points"];
+
+/// Documents saved with this extension use the text format.
+pub const TEXT_EXTENSION :&str = ".json";
+
+pub fn is_text_filename(filename :&str) -> bool {
+    filename.to_lowercase().ends_with(TEXT_EXTENSION)
+}
+
+/// Built-in documents offered when creating a new file, as (name, document
+/// in the text format).
+pub const DOCUMENT_TEMPLATES :&[(&str, &str)] = &[
+    ("Single-track station", 
+     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/documents/single_track_station.json"))),
+    ("Double-track junction", 
+     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/documents/double_track_junction.json"))),
+    ("Test layout with vehicles and plan", 
+     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/documents/test_layout.json"))),
+];
 
 pub fn load(filename :&str) -> Result<Model, std::io::Error> {
-    let m = serde_cbor::from_reader(File::open(&filename)?)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    Ok(m)
+    load_document(filename).map(|(m, _)| m)
+}
+
+/// Read a model and the undo history saved with it.
+pub fn load_document(filename :&str) -> Result<(Model, Option<UndoHistory>), std::io::Error> {
+    let data = std::fs::read(&filename)?;
+    document_from_bytes(&data).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+}
+
+/// Read a model from the contents of a save file in either format.
+pub fn from_bytes(data :&[u8]) -> Result<Model, String> {
+    document_from_bytes(data).map(|(m, _)| m)
+}
+
+fn document_from_bytes(data :&[u8]) -> Result<(Model, Option<UndoHistory>), String> {
+    let value :Value = if data.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'{') {
+        let json :serde_json::Value = serde_json::from_slice(data).map_err(|e| format!("{}", e))?;
+        from_text_value(json)?
+    } else {
+        serde_cbor::from_slice(data).map_err(|e| format!("{}", e))?
+    };
+    document_from_value(value)
+}
+
+/// Read a model from a deserialized save file, upgrading it from older
+/// format versions if necessary.
+pub fn from_value(value :Value) -> Result<Model, String> {
+    document_from_value(value).map(|(m, _)| m)
+}
+
+/// Read a model and its undo history from a deserialized save file. A
+/// history that cannot be read is left out, as the model is still usable.
+fn document_from_value(value :Value) -> Result<(Model, Option<UndoHistory>), String> {
+    let (version, model_value, history) = split_header(value);
+    let model = model_from_value(version, model_value.clone())?;
+    let history = history.and_then(|h| match read_history(version, &model_value, &model, h) {
+        Ok(h) => Some(h),
+        Err(e) => { warn!("Could not read the undo history of the file: {}", e); None },
+    });
+    Ok((model, history))
+}
+
+fn model_from_value(version :u32, model :Value) -> Result<Model, String> {
+    let model = migrate(version, model)?;
+    let (unknown, missing) = compare_fields(&model);
+    if !unknown.is_empty() {
+        return Err(format!("The file has fields that this version does not know: {}.", unknown.join(", ")));
+    }
+    serde_cbor::value::from_value(model).map_err(|e| {
+        if missing.is_empty() {
+            format!("Could not read the model: {}", e)
+        } else {
+            format!("Could not read the model: {}. The file is missing the fields: {}.", e, missing.join(", "))
+        }
+    })
+}
+
+/// Upgrade the model data of a save file from format `version` to the
+/// current format version.
+pub fn migrate(version :u32, model :Value) -> Result<Value, String> {
+    if version > FORMAT_VERSION {
+        return Err(format!("File format version {} is newer than supported version {}.", 
+                           version, FORMAT_VERSION));
+    }
+    let mut model = model;
+    for (v, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Upgrading file from format version {} to {}", v, v+1);
+        model = migration(model)?;
+    }
+    Ok(model)
+}
+
+/// Fields of the model data that the model does not have, and fields of
+/// the model that are not in the data, sorted by name. Unknown fields
+/// would be dropped when reading, so they must be handled by a migration.
+fn compare_fields(model :&Value) -> (Vec<String>, Vec<String>) {
+    let names = |v :&Value| match v {
+        Value::Map(m) => m.keys().filter_map(|k| match k {
+            Value::Text(t) => Some(t.clone()),
+            _ => None,
+        }).collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+    let known = serde_cbor::value::to_value(Model::empty()).map(|v| names(&v)).unwrap_or_default();
+    let present = names(model);
+    if known.is_empty() || present.is_empty() { return (Vec::new(), Vec::new()); }
+    let mut unknown = present.iter().filter(|f| !known.contains(f)).cloned().collect::<Vec<_>>();
+    let mut missing = known.iter().filter(|f| !present.contains(f)).cloned().collect::<Vec<_>>();
+    unknown.sort();
+    missing.sort();
+    (unknown, missing)
+}
+
+/// Split a save file into its format version, the model data and the
+/// undo history. Files without the header are version 0.
+fn split_header(value :Value) -> (u32, Value, Option<Value>) {
+    if let Value::Map(map) = &value {
+        let get = |k :&str| map.get(&Value::Text(k.to_string()));
+        if let (Some(Value::Text(format)), Some(Value::Integer(version)), Some(model)) = 
+            (get("format"), get("version"), get("model")) {
+            if format == FORMAT_NAME {
+                return (*version as u32, model.clone(), get("history").cloned());
+            }
+        }
+    }
+    (0, value, None)
+}
+
+fn model_fields(m :&Model) -> Result<BTreeMap<Value, Value>, String> {
+    match serde_cbor::value::to_value(m).map_err(|e| format!("{}", e))? {
+        Value::Map(fields) => Ok(fields),
+        _ => Err("Model is not a map".to_string()),
+    }
+}
+
+fn write_history(model :&Model, history :&UndoHistory) -> Result<SavedHistory, String> {
+    let current = model_fields(model)?;
+    let mut steps = Vec::new();
+    for (i, step) in history.steps.iter().enumerate() {
+        let changed = if i == history.pointer { BTreeMap::new() } else {
+            model_fields(step)?.into_iter().filter(|(k, v)| current.get(k) != Some(v)).collect()
+        };
+        steps.push(Value::Map(changed));
+    }
+    Ok(SavedHistory { steps, pointer: history.pointer, class: history.class.clone() })
+}
+
+/// Read the undo steps of a file of format `version`, where `model_value`
+/// is the saved model before migration and `model` the model read from it.
+fn read_history(version :u32, model_value :&Value, model :&Model, history :Value) -> Result<UndoHistory, String> {
+    let saved :SavedHistory = serde_cbor::value::from_value(history).map_err(|e| format!("{}", e))?;
+    let current = match model_value {
+        Value::Map(fields) => fields,
+        _ => return Err("Model is not a map".to_string()),
+    };
+    if saved.pointer >= saved.steps.len() {
+        return Err("The saved model is not in the history".to_string());
+    }
+    let mut steps = Vec::new();
+    for (i, step) in saved.steps.into_iter().enumerate() {
+        let changed = match step {
+            Value::Map(changed) => changed,
+            _ => return Err("Undo step is not a map".to_string()),
+        };
+        if i == saved.pointer {
+            steps.push(model.clone());
+            continue;
+        }
+        let unchanged = |field :&str| !changed.contains_key(&Value::Text(field.to_string()));
+        let shared = ["railml_source", "railml_tracks", "railml_ocps", "railml_track_groups",
+                      "railml_states", "railml_interlocking"].iter().map(|f| unchanged(f)).collect::<Vec<_>>();
+        let mut fields = current.clone();
+        fields.extend(changed);
+        let mut step = model_from_value(version, Value::Map(fields))?;
+        // Share the imported railML data with the model, as in the undo
+        // stack before saving, instead of keeping a copy for each step.
+        if shared[0] { step.railml_source = model.railml_source.clone(); }
+        if shared[1] { step.railml_tracks = model.railml_tracks.clone(); }
+        if shared[2] { step.railml_ocps = model.railml_ocps.clone(); }
+        if shared[3] { step.railml_track_groups = model.railml_track_groups.clone(); }
+        if shared[4] { step.railml_states = model.railml_states.clone(); }
+        if shared[5] { step.railml_interlocking = model.railml_interlocking.clone(); }
+        steps.push(step);
+    }
+    Ok(UndoHistory { steps, pointer: saved.pointer, class: saved.class })
+}
+
+/// Save a model, first keeping up to `backups` previous versions of the
+/// file as `<filename>.bak1` (newest) to `<filename>.bak<backups>`.
+pub fn save(filename :&str, m :Model, backups :usize) -> Result<(),std::io::Error> {
+    save_document(filename, m, None, backups)
 }
 
-pub fn save(filename :&str, m :Model) -> Result<(),std::io::Error> {
+/// Save a model with its undo history, see `save`.
+pub fn save_document(filename :&str, m :Model, history :Option<&UndoHistory>, 
+                     backups :usize) -> Result<(),std::io::Error> {
     info!("Will save file to file name {:?}", filename);
-    serde_cbor::to_writer(&File::create(filename)?, &m)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let history = history.map(|h| write_history(&m, h))
+        .transpose().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    if backups > 0 && std::path::Path::new(filename).exists() {
+        rotate_backups(filename, backups)?;
+    }
+    let file = SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &m, history };
+    if is_text_filename(filename) {
+        let text = to_text(&file).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        std::fs::write(filename, text)?;
+    } else {
+        serde_cbor::to_writer(&File::create(filename)?, &file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    }
+    Ok(())
+}
+
+fn backup_filename(filename :&str, i :usize) -> String {
+    format!("{}.bak{}", filename, i)
+}
+
+fn rotate_backups(filename :&str, backups :usize) -> Result<(), std::io::Error> {
+    let oldest = backup_filename(filename, backups);
+    if std::path::Path::new(&oldest).exists() {
+        std::fs::remove_file(&oldest)?;
+    }
+    for i in (1..backups).rev() {
+        let from = backup_filename(filename, i);
+        if std::path::Path::new(&from).exists() {
+            std::fs::rename(&from, backup_filename(filename, i+1))?;
+        }
+    }
+    // Copy instead of rename, so the file is intact if writing the new version fails.
+    std::fs::copy(filename, backup_filename(filename, 1))?;
     Ok(())
 }
 
+/// Write a save file in the canonical text format: pretty-printed JSON with
+/// all maps and sets sorted, so that the same model always gives the same
+/// text and small edits give small diffs.
+fn to_text(file :&SavedFile) -> Result<String, String> {
+    let sort_fields = |model :&mut BTreeMap<Value, Value>| {
+        for field in UNORDERED_FIELDS {
+            if let Some(Value::Array(items)) = model.get_mut(&Value::Text(field.to_string())) {
+                items.sort();
+            }
+        }
+    };
+    let mut value = serde_cbor::value::to_value(file).map_err(|e| format!("{}", e))?;
+    if let Value::Map(header) = &mut value {
+        if let Some(Value::Map(model)) = header.get_mut(&Value::Text("model".to_string())) {
+            sort_fields(model);
+        }
+        let history = header.get_mut(&Value::Text("history".to_string()));
+        if let Some(Value::Map(history)) = history {
+            if let Some(Value::Array(steps)) = history.get_mut(&Value::Text("steps".to_string())) {
+                for step in steps {
+                    if let Value::Map(step) = step { sort_fields(step); }
+                }
+            }
+        }
+    }
+    let mut text = serde_json::to_string_pretty(&to_text_value(value)?)
+        .map_err(|e| format!("{}", e))?;
+    text.push('\n');
+    Ok(text)
+}
+
+/// A model in the text save format, for passing documents to other programs.
+pub fn to_text_document(m :&Model) -> Result<String, String> {
+    to_text(&SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: m, history: None })
+}
+
+// JSON objects only have string keys, so maps with other keys are written as
+// `{"$map": [[key, value], ..]}`. Byte strings are written as `{"$bytes": "<hex>"}`.
+
+fn to_text_value(value :Value) -> Result<serde_json::Value, String> {
+    use serde_json::Value as J;
+    Ok(match value {
+        Value::Null => J::Null,
+        Value::Bool(b) => J::Bool(b),
+        Value::Integer(i) => {
+            if i >= 0 { J::from(i as u64) } else { J::from(i as i64) }
+        },
+        Value::Float(f) => serde_json::Number::from_f64(f).map(J::Number)
+            .ok_or_else(|| format!("Cannot write non-finite number {} as text", f))?,
+        Value::Text(s) => J::String(s),
+        Value::Bytes(b) => {
+            let hex = b.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+            J::Object(vec![("$bytes".to_string(), J::String(hex))].into_iter().collect())
+        },
+        Value::Array(a) => J::Array(a.into_iter().map(to_text_value).collect::<Result<_,_>>()?),
+        Value::Map(m) => {
+            if m.keys().all(|k| matches!(k, Value::Text(_))) {
+                let mut obj = serde_json::Map::new();
+                for (k,v) in m {
+                    if let Value::Text(k) = k { obj.insert(k, to_text_value(v)?); }
+                }
+                J::Object(obj)
+            } else {
+                let pairs = m.into_iter().map(|(k,v)| Ok(J::Array(vec![to_text_value(k)?, to_text_value(v)?])))
+                    .collect::<Result<Vec<_>,String>>()?;
+                J::Object(vec![("$map".to_string(), J::Array(pairs))].into_iter().collect())
+            }
+        },
+        other => return Err(format!("Cannot write {:?} as text", other)),
+    })
+}
+
+fn from_text_value(value :serde_json::Value) -> Result<Value, String> {
+    use serde_json::Value as J;
+    Ok(match value {
+        J::Null => Value::Null,
+        J::Bool(b) => Value::Bool(b),
+        J::Number(n) => {
+            if let Some(i) = n.as_u64() { Value::Integer(i as i128) }
+            else if let Some(i) = n.as_i64() { Value::Integer(i as i128) }
+            else { Value::Float(n.as_f64().unwrap()) }
+        },
+        J::String(s) => Value::Text(s),
+        J::Array(a) => Value::Array(a.into_iter().map(from_text_value).collect::<Result<_,_>>()?),
+        J::Object(mut obj) => {
+            if obj.len() == 1 && obj.contains_key("$bytes") {
+                let hex = obj.remove("$bytes").unwrap();
+                let hex = hex.as_str().ok_or("Invalid byte string")?;
+                let bytes = (0..hex.len()).step_by(2)
+                    .map(|i| hex.get(i..i+2).and_then(|x| u8::from_str_radix(x, 16).ok()))
+                    .collect::<Option<Vec<u8>>>().ok_or("Invalid byte string")?;
+                Value::Bytes(bytes)
+            } else if obj.len() == 1 && obj.contains_key("$map") {
+                let mut map = std::collections::BTreeMap::new();
+                if let J::Array(pairs) = obj.remove("$map").unwrap() {
+                    for pair in pairs {
+                        match pair {
+                            J::Array(mut kv) if kv.len() == 2 => {
+                                let v = kv.pop().unwrap();
+                                let k = kv.pop().unwrap();
+                                map.insert(from_text_value(k)?, from_text_value(v)?);
+                            },
+                            _ => return Err("Invalid map entry".to_string()),
+                        }
+                    }
+                }
+                Value::Map(map)
+            } else {
+                Value::Map(obj.into_iter().map(|(k,v)| Ok((Value::Text(k), from_text_value(v)?)))
+                           .collect::<Result<_,String>>()?)
+            }
+        },
+    })
+}
+
 pub fn dump_json(filename: &str, m: &Model) -> Result<(), std::io::Error> {
     let dump = DumpModel::from_model(m);
     serde_json::to_writer_pretty(&File::create(filename)?, &dump)
@@ -24,20 +420,36 @@ pub fn dump_json(filename: &str, m: &Model) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-pub fn save_interactive(m :Model) -> Result<Option<String>,std::io::Error> {
-    if let Some(filename) = tinyfiledialogs::save_file_dialog("Save model to file", "") {
-        save(&filename, m).map(|_| Some(filename))
+/// Download a file over http(s). This blocks, so it should run in a background job.
+pub fn download(url :&str) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    info!("Downloading {:?}", url);
+    let response = ureq::get(url).call().map_err(|e| format!("{}", e))?;
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data).map_err(|e| format!("{}", e))?;
+    info!("Downloaded {} bytes from {:?}", data.len(), url);
+    Ok(data)
+}
+
+pub fn save_interactive(m :Model, backups :usize) -> Result<Option<String>,std::io::Error> {
+    save_document_interactive(m, None, backups)
+}
+
+pub fn save_document_interactive(m :Model, history :Option<&UndoHistory>, 
+                                 backups :usize) -> Result<Option<String>,std::io::Error> {
+    if let Some(filename) = tinyfiledialogs::save_file_dialog("Save model to file (*.json for text format)", "") {
+        save_document(&filename, m, history, backups).map(|_| Some(filename))
     } else {
         info!("User cancelled save");
         Ok(None) // user cancelled, this is not an error
     }
 }
 
-pub fn load_interactive() -> Result<Option<(Model,String)>, std::io::Error> {
+pub fn load_interactive() -> Result<Option<(Model,Option<UndoHistory>,String)>, std::io::Error> {
     if let Some(filename) = tinyfiledialogs::open_file_dialog("Open model from file", "", None) {
         info!("Loading file from {:?}", filename);
-        let m = load(&filename)?;
-        Ok(Some((m,filename)))
+        let (m, history) = load_document(&filename)?;
+        Ok(Some((m,history,filename)))
     } else {
         Ok(None)
     }
@@ -54,9 +466,16 @@ struct DumpModel {
     railml_metadata: Option<railmlio::model::Metadata>,
     railml_track_groups: Vec<railmlio::model::TrackGroup>,
     railml_ocps: Vec<railmlio::model::Ocp>,
+    ocp_positions: Vec<(String, crate::document::model::PtC)>,
     railml_states: Vec<railmlio::model::State>,
+    railml_interlocking: Option<railmlio::model::Interlocking>,
     railml_tracks: Vec<crate::document::model::RailMLTrackInfo>,
     railml_objects: Vec<DumpRailMLObjectEntry>,
+    railml_nodes: Vec<DumpRailMLNode>,
+    lineseg_attributes: Vec<DumpLineSegAttributes>,
+    railml_source: Option<String>,
+    file_settings: crate::document::model::FileSettings,
+    trap_points: Vec<DumpPt>,
 }
 
 #[derive(Serialize, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -95,6 +514,19 @@ struct DumpRailMLObjectEntry {
     value: Vec<crate::document::model::RailMLObjectInfo>,
 }
 
+#[derive(Serialize)]
+struct DumpRailMLNode {
+    key: DumpPt,
+    value: crate::document::model::RailMLNodeInfo,
+}
+
+#[derive(Serialize)]
+struct DumpLineSegAttributes {
+    a: DumpPt,
+    b: DumpPt,
+    value: crate::document::model::LineSegAttributes,
+}
+
 impl DumpModel {
     fn from_model(m: &Model) -> Self {
         let mut linesegs: Vec<DumpLineSeg> = m
@@ -140,6 +572,37 @@ impl DumpModel {
             .collect();
         railml_objects.sort_by(|l, r| l.key.cmp(&r.key));
 
+        let mut railml_nodes: Vec<DumpRailMLNode> = m
+            .railml_nodes
+            .iter()
+            .map(|(k, v)| DumpRailMLNode {
+                key: DumpPt::from(*k),
+                value: v.clone(),
+            })
+            .collect();
+        railml_nodes.sort_by(|l, r| l.key.cmp(&r.key));
+
+        let mut lineseg_attributes: Vec<DumpLineSegAttributes> = m
+            .lineseg_attributes
+            .iter()
+            .map(|((a, b), v)| DumpLineSegAttributes {
+                a: DumpPt::from(*a),
+                b: DumpPt::from(*b),
+                value: v.clone(),
+            })
+            .collect();
+        lineseg_attributes.sort_by(|l, r| (l.a, l.b).cmp(&(r.a, r.b)));
+
+        let mut ocp_positions: Vec<(String, crate::document::model::PtC)> = m
+            .ocp_positions
+            .iter()
+            .map(|(id, p)| (id.clone(), *p))
+            .collect();
+        ocp_positions.sort_by(|l, r| l.0.cmp(&r.0));
+
+        let mut trap_points: Vec<DumpPt> = m.trap_points.iter().map(|p| DumpPt::from(*p)).collect();
+        trap_points.sort();
+
         DumpModel {
             linesegs,
             objects,
@@ -148,11 +611,18 @@ impl DumpModel {
             dispatches: m.dispatches.data().iter().cloned().collect(),
             plans: m.plans.data().iter().cloned().collect(),
             railml_metadata: m.railml_metadata.clone(),
-            railml_track_groups: m.railml_track_groups.clone(),
-            railml_ocps: m.railml_ocps.clone(),
-            railml_states: m.railml_states.clone(),
-            railml_tracks: m.railml_tracks.clone(),
+            railml_track_groups: (*m.railml_track_groups).clone(),
+            railml_ocps: (*m.railml_ocps).clone(),
+            ocp_positions,
+            railml_states: (*m.railml_states).clone(),
+            railml_interlocking: m.railml_interlocking.as_ref().map(|il| (**il).clone()),
+            railml_tracks: (*m.railml_tracks).clone(),
             railml_objects,
+            railml_nodes,
+            lineseg_attributes,
+            railml_source: m.railml_source.as_ref().map(|s| s.filename.clone()),
+            file_settings: m.file_settings.clone(),
+            trap_points,
         }
     }
 }
@@ -163,6 +633,12 @@ impl DumpModel {
 pub struct FileInfo {
     pub filename :Option<String>,
     pub unsaved :bool,
+    /// Modification time of the file when it was last loaded or saved.
+    pub modified :Option<std::time::SystemTime>,
+}
+
+pub fn modified_time(filename :&str) -> Option<std::time::SystemTime> {
+    std::fs::metadata(filename).and_then(|m| m.modified()).ok()
 }
 
 impl FileInfo {
@@ -170,11 +646,13 @@ impl FileInfo {
         FileInfo {
             filename :None,
             unsaved :false,
+            modified :None,
         }
     }
 
     pub fn set_saved_file(&mut self, filename :String) {
         self.unsaved = false;
+        self.modified = modified_time(&filename);
         self.filename = Some(filename);
         self.update_window_title();
     }
@@ -195,8 +673,132 @@ impl FileInfo {
         backend_glfw::set_window_title(&self.window_title());
     }
 
+    /// The file name without its folder, as shown in the document tabs.
+    pub fn name(&self) -> String {
+        self.filename.as_ref().and_then(|f| std::path::Path::new(f).file_name())
+            .map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "Untitled".to_string())
+    }
+
     pub fn window_title(&self) -> String {
         format!("{}{} - Junction", if self.unsaved {"*"}  else { "" },
                                    self.filename.as_ref().map(|x| x.as_str()).unwrap_or("Untitled"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_unversioned_and_versioned() {
+        let mut model = Model::empty();
+        model.linesegs.insert((nalgebra_glm::vec2(0,0), nalgebra_glm::vec2(1,0)));
+
+        let legacy = serde_cbor::to_vec(&model).unwrap();
+        let loaded = from_value(serde_cbor::from_slice(&legacy).unwrap()).unwrap();
+        assert_eq!(loaded.linesegs, model.linesegs);
+
+        let file = SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &model, history: None };
+        let current = serde_cbor::to_vec(&file).unwrap();
+        let loaded = from_value(serde_cbor::from_slice(&current).unwrap()).unwrap();
+        assert_eq!(loaded.linesegs, model.linesegs);
+
+        let newer = SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION + 1, model: &model, history: None };
+        let newer = serde_cbor::to_vec(&newer).unwrap();
+        assert!(from_value(serde_cbor::from_slice(&newer).unwrap()).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let model = Model::empty();
+        let file = SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &model, history: None };
+        let edited = |f :&dyn Fn(&mut std::collections::BTreeMap<Value, Value>)| {
+            let mut value = serde_cbor::value::to_value(&file).unwrap();
+            if let Value::Map(header) = &mut value {
+                if let Some(Value::Map(model)) = header.get_mut(&Value::Text("model".to_string())) {
+                    f(model);
+                }
+            }
+            value
+        };
+
+        let added = edited(&|m| { m.insert(Value::Text("signal_groups".to_string()), Value::Array(Vec::new())); });
+        let err = from_value(added).unwrap_err();
+        assert!(err.contains("signal_groups"), "{}", err);
+
+        let removed = edited(&|m| { m.remove(&Value::Text("linesegs".to_string())); });
+        let err = from_value(removed).unwrap_err();
+        assert!(err.contains("missing the fields: linesegs"), "{}", err);
+
+        assert!(migrate(FORMAT_VERSION + 1, Value::Null).is_err());
+    }
+
+    #[test]
+    fn load_version_1_without_added_fields() {
+        let model = Model::empty();
+        let file = SavedFile { format: FORMAT_NAME, version: 1, model: &model, history: None };
+        let mut value = serde_cbor::value::to_value(&file).unwrap();
+        if let Value::Map(header) = &mut value {
+            if let Some(Value::Map(model)) = header.get_mut(&Value::Text("model".to_string())) {
+                for field in &["selection_groups", "annotations", "view_bookmarks"] {
+                    model.remove(&Value::Text(field.to_string()));
+                }
+            }
+        }
+        let loaded = from_value(value).unwrap();
+        assert_eq!(loaded.selection_groups.iter().count(), 0);
+        assert_eq!(loaded.view_bookmarks.iter().count(), 0);
+    }
+
+    #[test]
+    fn undo_history_roundtrip() {
+        let mut steps = vec![Model::empty()];
+        for x in 0..2 {
+            let mut m = steps.last().unwrap().clone();
+            m.linesegs.insert((nalgebra_glm::vec2(x,0), nalgebra_glm::vec2(x+1,0)));
+            steps.push(m);
+        }
+        let history = UndoHistory { steps: steps.clone(), pointer: 1, class: Some(EditClass::MoveOcp(0)) };
+        let saved = write_history(&steps[1], &history).unwrap();
+        assert_eq!(saved.steps[1], Value::Map(BTreeMap::new()));
+        let file = SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &steps[1], history: Some(saved) };
+
+        let json :serde_json::Value = serde_json::from_str(&to_text(&file).unwrap()).unwrap();
+        let (model, loaded) = document_from_value(from_text_value(json).unwrap()).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(model.linesegs, steps[1].linesegs);
+        assert_eq!(loaded.pointer, 1);
+        assert_eq!(loaded.class, history.class);
+        assert_eq!(loaded.steps.iter().map(|m| m.linesegs.clone()).collect::<Vec<_>>(),
+                   steps.iter().map(|m| m.linesegs.clone()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn document_templates_load() {
+        for (name, data) in DOCUMENT_TEMPLATES {
+            let model = from_bytes(data.as_bytes()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(!model.linesegs.is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn text_format_is_canonical() {
+        let mut a = Model::empty();
+        let mut b = Model::empty();
+        let segs = [((0,0),(1,0)), ((1,0),(2,1)), ((1,0),(2,0)), ((2,0),(3,0))];
+        for ((x1,y1),(x2,y2)) in segs.iter() {
+            a.linesegs.insert((nalgebra_glm::vec2(*x1,*y1), nalgebra_glm::vec2(*x2,*y2)));
+        }
+        for ((x1,y1),(x2,y2)) in segs.iter().rev() {
+            b.linesegs.insert((nalgebra_glm::vec2(*x1,*y1), nalgebra_glm::vec2(*x2,*y2)));
+        }
+
+        let text_a = to_text(&SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &a, history: None }).unwrap();
+        let text_b = to_text(&SavedFile { format: FORMAT_NAME, version: FORMAT_VERSION, model: &b, history: None }).unwrap();
+        assert_eq!(text_a, text_b);
+
+        let json :serde_json::Value = serde_json::from_str(&text_a).unwrap();
+        let loaded = from_value(from_text_value(json).unwrap()).unwrap();
+        assert_eq!(loaded.linesegs, a.linesegs);
+    }
+}
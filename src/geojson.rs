@@ -0,0 +1,215 @@
+//! GeoJSON FeatureCollections of the infrastructure, for inspecting layouts
+//! in GIS tools like QGIS.
+//!
+//! Each track becomes a `LineString` through its grid points, and each
+//! object and each node with a chosen type becomes a `Point`. Coordinates
+//! are model coordinates (one unit per grid step, y upwards), not longitude
+//! and latitude, so the layer should be given a local projected CRS.
+//! Object functions, tangents and node types are kept in the properties in
+//! the document's serde encoding, which lets the collection be imported
+//! again. Imported lines are routed through the grid like drawn tracks, and
+//! points without a tangent are placed on the closest line segment.
+
+use std::io;
+use log::*;
+use nalgebra_glm as glm;
+use serde_json::{json, Value};
+
+use crate::document::infview::round_coord;
+use crate::document::model::*;
+use crate::document::objects::{Function, Object};
+use crate::document::topology;
+use crate::util;
+
+fn point(p :PtC) -> Value { json!([p.x, p.y]) }
+
+/// The imported id and name of the track a line segment belongs to.
+fn track_info(model :&Model, seg :(Pt,Pt)) -> Option<&RailMLTrackInfo> {
+    model.railml_tracks.iter().find(|t| t.segments.iter().any(|&(a,b)| (a,b) == seg || (b,a) == seg))
+}
+
+/// The infrastructure of the model as a GeoJSON FeatureCollection.
+pub fn export_geojson(model :&Model) -> Result<String, io::Error> {
+    let topo = topology::convert(model, 50.0).map_err(|_| {
+        io::Error::new(io::ErrorKind::Other, "topology conversion failed")
+    })?;
+    let mut features = Vec::new();
+
+    for (idx, line) in topo.interval_lines.iter().enumerate() {
+        let coordinates = line.iter().map(|(_, p)| point(*p)).collect::<Vec<_>>();
+        let mut properties = json!({ "kind": "track", "track": idx });
+        if let Some(info) = topo.track_segments[idx].first().and_then(|s| track_info(model, *s)) {
+            properties["id"] = json!(info.id);
+            if let Some(name) = &info.name { properties["name"] = json!(name); }
+        }
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": properties,
+        }));
+    }
+
+    let mut objects = model.objects.iter().collect::<Vec<_>>();
+    objects.sort_by_key(|(p, _)| (p.x, p.y));
+    for (_, obj) in objects {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": point(obj.loc) },
+            "properties": {
+                "kind": "object",
+                "functions": obj.functions,
+                "tangent": [obj.tangent.x, obj.tangent.y],
+            },
+        }));
+    }
+
+    let mut nodes = model.node_data.iter().collect::<Vec<_>>();
+    nodes.sort_by_key(|(p, _)| (p.x, p.y));
+    for (pt, nd) in nodes {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": [pt.x, pt.y] },
+            "properties": { "kind": "node", "node": nd },
+        }));
+    }
+
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+    serde_json::to_string_pretty(&collection).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+fn parse_point(value :&Value) -> Option<PtC> {
+    let xs = value.as_array()?;
+    Some(glm::vec2(xs.get(0)?.as_f64()? as f32, xs.get(1)?.as_f64()? as f32))
+}
+
+fn grid_point(p :PtC) -> Pt { glm::vec2(p.x.round() as i32, p.y.round() as i32) }
+
+fn add_line(model :&mut Model, coordinates :&Value) {
+    let pts = coordinates.as_array().map(|c| c.iter().filter_map(parse_point).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for w in pts.windows(2) {
+        for (a,b) in util::route_line(grid_point(w[0]), grid_point(w[1])) {
+            let unit = util::unit_step_diag_line(a, b);
+            for (pa,pb) in unit.iter().zip(unit.iter().skip(1)) {
+                model.linesegs.insert(util::order_ivec(*pa, *pb));
+            }
+        }
+    }
+}
+
+/// A model from a GeoJSON FeatureCollection, as written by `export_geojson`
+/// or edited in a GIS tool. Features that are not understood are skipped.
+pub fn import_geojson(text :&str) -> Result<Model, String> {
+    let value :Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let features = value.get("features").and_then(|f| f.as_array())
+        .ok_or_else(|| "Not a GeoJSON FeatureCollection".to_string())?;
+    let mut model = Model::empty();
+    let mut points = Vec::new();
+    for feature in features {
+        let geometry = match feature.get("geometry") { Some(g) => g, None => continue };
+        let coordinates = &geometry["coordinates"];
+        match geometry.get("type").and_then(|t| t.as_str()) {
+            Some("LineString") => add_line(&mut model, coordinates),
+            Some("MultiLineString") => {
+                for line in coordinates.as_array().into_iter().flatten() { add_line(&mut model, line); }
+            },
+            Some("Point") => match parse_point(coordinates) {
+                Some(p) => points.push((p, &feature["properties"])),
+                None => warn!("GeoJSON point without coordinates"),
+            },
+            other => warn!("Skipping GeoJSON geometry {:?}", other),
+        }
+    }
+
+    // Objects are placed after all the tracks are known.
+    for (p, properties) in points {
+        if let Some(node) = properties.get("node") {
+            match serde_json::from_value::<NDType>(node.clone()) {
+                Ok(nd) => { model.node_data.insert(grid_point(p), nd); },
+                Err(e) => warn!("Skipping GeoJSON node {:?}: {}", node, e),
+            }
+        } else if let Some(functions) = properties.get("functions") {
+            let functions = match serde_json::from_value::<Vec<Function>>(functions.clone()) {
+                Ok(f) => f,
+                Err(e) => { warn!("Skipping GeoJSON object {:?}: {}", functions, e); continue; },
+            };
+            let tangent = properties.get("tangent").and_then(|t| serde_json::from_value::<(i32,i32)>(t.clone()).ok());
+            let mut obj = Object { loc: p, tangent: glm::vec2(1, 0), functions };
+            match tangent {
+                Some((x, y)) => obj.tangent = glm::vec2(x, y),
+                None => { obj.move_to(&model, p); },
+            }
+            model.objects.insert(round_coord(obj.loc), obj);
+        }
+    }
+    info!("Imported {} line segments and {} objects from GeoJSON", model.linesegs.len(), model.objects.len());
+    Ok(model)
+}
+
+pub fn export_geojson_to_file(filename :&str, model :&Model) -> Result<(), io::Error> {
+    std::fs::write(filename, export_geojson(model)?)
+}
+
+pub fn export_geojson_interactive(model :&Model) -> Result<Option<String>, io::Error> {
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export GeoJSON", "infrastructure.geojson") {
+        if !filename.ends_with(".geojson") && !filename.ends_with(".json") { filename.push_str(".geojson"); }
+        info!("Exporting GeoJSON to {:?}", filename);
+        export_geojson_to_file(&filename, model)?;
+        Ok(Some(filename))
+    } else {
+        info!("User cancelled GeoJSON export");
+        Ok(None)
+    }
+}
+
+pub fn import_geojson_interactive() -> Result<Option<Model>, String> {
+    let filename = match tinyfiledialogs::open_file_dialog("Import GeoJSON", "", Some((&["*.geojson", "*.json"][..], "GeoJSON"))) {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    info!("Importing GeoJSON from {:?}", filename);
+    let text = std::fs::read_to_string(&filename).map_err(|e| e.to_string())?;
+    import_geojson(&text).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::objects::SignalKind;
+
+    #[test]
+    fn geojson_roundtrip() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        model.node_data.insert(glm::vec2(0,0), NDType::BufferStop);
+        let signal = Object { loc: glm::vec2(0.5, 0.25), tangent: glm::vec2(-1,0),
+            functions: vec![Function::MainSignal { has_distant: true, kind: SignalKind::Combined }] };
+        model.objects.insert(round_coord(signal.loc), signal);
+
+        let text = export_geojson(&model).unwrap();
+        assert_eq!(text.matches("\"LineString\"").count(), 3);
+        let imported = import_geojson(&text).unwrap();
+        assert_eq!(imported.linesegs, model.linesegs);
+        assert_eq!(imported.node_data, model.node_data);
+        let obj = &imported.objects[&round_coord(glm::vec2(0.5, 0.25))];
+        assert_eq!(obj.tangent, glm::vec2(-1,0));
+        assert_eq!(obj.functions, model.objects.values().next().unwrap().functions);
+    }
+
+    #[test]
+    fn geojson_lines_follow_grid() {
+        let text = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0.1, 0.0], [3.0, 1.2]]},
+             "properties": {}},
+            {"type": "Feature", "geometry": {"type": "Point", "coordinates": [2.5, 0.9]},
+             "properties": {"functions": ["Detector"]}}
+        ]}"#;
+        let model = import_geojson(text).unwrap();
+        assert_eq!(model.linesegs.len(), 3);
+        let obj = model.objects.values().next().unwrap();
+        assert_eq!(obj.tangent, glm::vec2(1,0));
+        assert_eq!(obj.loc, glm::vec2(2.5, 1.0));
+    }
+}
@@ -55,7 +55,8 @@ pub fn diagram(config :&Config, graphics :&DispatchOutput, draw :&Draw, view :&D
             if igIsItemHovered(0) {
                 if ra.x <= draw.mouse.x && draw.mouse.x <= rb.x && ra.y <= draw.mouse.y && draw.mouse.y <= rb.y {
                     igBeginTooltip();
-                    widgets::show_text(&format!("TVD section reserved t={:.1} -> t={:.1}", 
+                    widgets::show_text(&format!("TVD section {} - {} reserved t={:.1} -> t={:.1}", 
+                                                config.units.length(block.pos.0), config.units.length(block.pos.1),
                                                 block.reserved.0, block.reserved.1));
                     igEndTooltip();
                 }
@@ -209,12 +210,12 @@ pub fn command_icons(config :&Config,
                         widgets::show_text(&format!("Invalid route start/end points."));
                     }
                     (Command::Route(_),_) => {
-                        widgets::show_text(&format!("Route request t={:.1}", cmd_t));
+                        widgets::show_text(&format!("Route request t={:.1} at {}", cmd_t, config.units.length(km)));
                     },
                     (Command::Train(v,_),_) => {
                         let v = analysis.model().vehicles.get(*v).map(|v| v.name.as_str())
                             .unwrap_or("Unknown vehicle");
-                        widgets::show_text(&format!("{} entering t={:.1}", v, cmd_t));
+                        widgets::show_text(&format!("{} entering t={:.1} at {}", v, cmd_t, config.units.length(km)));
                     },
                 }
                 igEndTooltip();
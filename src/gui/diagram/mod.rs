@@ -11,6 +11,11 @@ use crate::app::*;
 use crate::document::*;
 use crate::gui::widgets::Draw;
 use crate::document::infview::InfView;
+use crate::event_table;
+use crate::i18n;
+use crate::print::{self, PrintFormat};
+use log::*;
+use std::ffi::CString;
 
 mod draw;
 
@@ -34,7 +39,7 @@ pub fn diagram_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfVi
                     analysis :&Analysis, dv :&mut ManualDispatchView, graph :&DispatchOutput) -> Option<DiagramViewAction> {
     let mut action = None;
     unsafe {
-        diagram_toolbar(dv, graph);
+        diagram_toolbar(config, analysis, dv, graph);
         let size = igGetContentRegionAvail_nonUDT2().into();
         let draw = widgets::canvas(size,
                     config.color_u32(RailUIColorName::GraphBackground),
@@ -76,7 +81,7 @@ pub fn diagram_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfVi
 
         if igBeginPopup(const_cstr!("cmded").as_ptr(), 0 as _) {
             if let Some(selection) = dv.selected_command {
-                if igSelectable(const_cstr!("Delete").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                if igSelectable(tr!("Delete"), false, 0 as _, ImVec2::zero()) {
                     action = Some(DiagramViewAction::DeleteCommand { id: selection });
                 }
             }
@@ -121,7 +126,7 @@ fn scroll(draw :&Draw, viewport :&mut DiagramViewport) {
 }
 
 
-fn diagram_toolbar(dv :&mut ManualDispatchView, graph :&DispatchOutput) {
+fn diagram_toolbar(config :&Config, analysis :&Analysis, dv :&mut ManualDispatchView, graph :&DispatchOutput) {
     unsafe {
     let label = if dv.play { const_cstr!("\u{f04c}") }
                 else { const_cstr!("\u{f04b}") };
@@ -132,5 +137,39 @@ fn diagram_toolbar(dv :&mut ManualDispatchView, graph :&DispatchOutput) {
     if igButton(const_cstr!("\u{f0b2}").as_ptr(), ImVec2::zero()) {
         dv.viewport = Some(default_viewport(graph));
     }
+    igSameLine(0.0,-1.0);
+    if igButton(const_cstr!("\u{f56e}").as_ptr(), ImVec2::zero()) {
+        igOpenPopup(const_cstr!("diagexp").as_ptr());
+    }
+    if igBeginPopup(const_cstr!("diagexp").as_ptr(), 0 as _) {
+        for format in PrintFormat::all() {
+            let label = CString::new(i18n::tr_fmt("Train graph as {}...", &[format.name()])).unwrap();
+            if igSelectable(label.as_ptr(), false, 0 as _, ImVec2::zero()) {
+                export_train_graph(config, graph, *format);
+            }
+        }
+        if igSelectable(tr!("Event times as CSV..."), false, 0 as _, ImVec2::zero()) {
+            if let Some((_, dgraph)) = analysis.data().dgraph.as_ref() {
+                if let Err(e) = event_table::export_interactive(&graph.history, dgraph, &config.units) {
+                    error!("Error exporting event times: {}", e);
+                }
+            }
+        }
+        igEndPopup();
+    }
+    }
+}
+
+fn export_train_graph(config :&Config, graph :&DispatchOutput, format :PrintFormat) {
+    let default = format!("train_graph.{}", format.extension());
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export train graph", &default) {
+        if !filename.to_lowercase().ends_with(&format!(".{}", format.extension())) {
+            filename.push_str(&format!(".{}", format.extension()));
+        }
+        let sheet = print::train_graph(graph, &graph.dispatch.name, &config.print, &config.units);
+        info!("Exporting train graph to {:?}", filename);
+        if let Err(e) = print::print_to_file(&filename, format, &sheet, config.print.dpi) {
+            error!("Error exporting train graph: {}", e);
+        }
     }
 }
@@ -15,6 +15,7 @@ use crate::gui::diagram::*;
 use crate::gui::plan::planning_icon;
 use crate::gui::widgets::Draw;
 use crate::document::infview::InfView;
+use crate::i18n;
 
 pub fn dispatch_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfView,
                      analysis :&mut Analysis, dv :&mut DispatchView) -> Option<Option<DispatchView>> {
@@ -118,7 +119,7 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
         let comboflag = ImGuiComboFlags__ImGuiComboFlags_HeightLarge;
         if igBeginCombo(const_cstr!("##sel").as_ptr(), current_name.as_ptr(), comboflag as _) {
 
-            if igSelectable(const_cstr!("None").as_ptr(), 
+            if igSelectable(tr!("None"), 
                             dispatch_view.is_none(), 0 as _, ImVec2::zero()) {
                 retval = Some(None);
             }
@@ -150,11 +151,11 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
 
                 igPopID();
             }
-            if !any { widgets::show_text("No dispatches."); }
+            if !any { widgets::show_text(i18n::tr("No dispatches.")); }
             igPopID();
 
             igSpacing();
-            if igButton(const_cstr!("\u{f0fe}\u{f4fd} Manual").as_ptr(), ImVec2::zero()) {
+            if igButton(tr!("\u{f0fe}\u{f4fd} Manual"), ImVec2::zero()) {
                 new_dispatch_auto = Some(false);
             }
             igSpacing();
@@ -197,12 +198,12 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
 
                 igPopID();
             }
-            if !any { widgets::show_text("No plans."); }
+            if !any { widgets::show_text(i18n::tr("No plans.")); }
             igPopID();
 
 
             igSpacing();
-            if igButton(const_cstr!("\u{f0fe}\u{f0d0} Auto").as_ptr(), ImVec2::zero()) {
+            if igButton(tr!("\u{f0fe}\u{f0d0} Auto"), ImVec2::zero()) {
                 new_dispatch_auto = Some(true);
             }
             igSpacing();
@@ -214,7 +215,7 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
 
         if igIsItemHovered(0) {
             igBeginTooltip();
-            widgets::show_text("Add automatic or manual dispatching.");
+            widgets::show_text(i18n::tr("Add automatic or manual dispatching."));
             igEndTooltip();
         }
 
@@ -43,6 +43,101 @@ pub fn box_around(config :&Config, draw :&Draw, inf_view :&InfView, p :PtC) {
     }
 }
 
+/// Mark a world-space rectangle, with some space around it so that single
+/// points are also visible.
+pub fn highlight_rect(config :&Config, inf_view :&InfView, draw :&Draw, min :PtC, max :PtC) {
+    let a = draw.pos + inf_view.view.world_ptc_to_screen(glm::vec2(min.x, max.y));
+    let b = draw.pos + inf_view.view.world_ptc_to_screen(glm::vec2(max.x, min.y));
+    let pad = ImVec2 { x: 12.0, y: 12.0 };
+    unsafe {
+    ImDrawList_AddRect(draw.draw_list, a - pad, b + pad,
+                       config.color_u32(RailUIColorName::CanvasTrackDrawing),
+                       4.0,0,3.0);
+    }
+}
+
+/// World-space rectangle shown on the canvas, used for skipping geometry
+/// that is outside the view.
+pub struct Viewport { lo :PtC, hi :PtC }
+
+impl Viewport {
+    pub fn new(inf_view :&InfView, draw :&Draw) -> Self {
+        let (a,b) = inf_view.view.points_in_view(draw.size);
+        // One grid unit of margin keeps symbols at the edges from popping.
+        let margin = 1.0;
+        Viewport {
+            lo: glm::vec2(a.x.min(b.x) as f32 - margin, a.y.min(b.y) as f32 - margin),
+            hi: glm::vec2(a.x.max(b.x) as f32 + margin, a.y.max(b.y) as f32 + margin),
+        }
+    }
+
+    pub fn contains(&self, p :PtC) -> bool {
+        p.x >= self.lo.x && p.x <= self.hi.x && p.y >= self.lo.y && p.y <= self.hi.y
+    }
+
+    /// Conservative test using the line's bounding box.
+    pub fn line(&self, a :PtC, b :PtC) -> bool {
+        !(a.x.max(b.x) < self.lo.x || a.x.min(b.x) > self.hi.x ||
+          a.y.max(b.y) < self.lo.y || a.y.min(b.y) > self.hi.y)
+    }
+}
+
+/// Draw many lines of the same color and thickness as quads written into
+/// a single reserved block of the draw list, instead of one
+/// `ImDrawList_AddLine` call per line.
+pub fn line_batch(draw_list :*mut ImDrawList, lines :&[(ImVec2,ImVec2)], col :u32, thickness :f32) {
+    // Keep each reservation well below the 16-bit vertex index limit.
+    const CHUNK :usize = 4096;
+    unsafe {
+        let uv :ImVec2 = igGetFontTexUvWhitePixel_nonUDT2().into();
+        for chunk in lines.chunks(CHUNK) {
+            ImDrawList_PrimReserve(draw_list, 6*chunk.len() as i32, 4*chunk.len() as i32);
+            for (a,b) in chunk.iter() {
+                let d = *b - *a;
+                let len = (d.x*d.x + d.y*d.y).sqrt().max(1e-6);
+                let n = (0.5*thickness) * ImVec2 { x: -d.y / len, y: d.x / len };
+                ImDrawList_PrimQuadUV(draw_list, *a + n, *b + n, *b - n, *a - n, uv, uv, uv, uv, col);
+            }
+        }
+    }
+}
+
+/// Smallest distance in pixels between the marks of the grid divisions.
+const MIN_DIVISION_SPACING :f32 = 8.0;
+
+/// The grid points, the major grid lines and the marks dividing each grid
+/// unit along the rows, between the grid points `lo` and `hi`.
+fn grid(config :&Config, inf_view :&InfView, draw :&Draw, lo :Pt, hi :Pt) {
+    let color_grid = config.color_u32(RailUIColorName::CanvasGridPoint);
+    let screen = |x :f32, y :f32| draw.pos + inf_view.view.world_ptc_to_screen(glm::vec2(x,y));
+    unsafe {
+        let major = config.grid.major;
+        if major > 0 {
+            let color_major = (color_grid & 0x00FFFFFF) | 0x60000000;
+            for x in (lo.x..=hi.x).filter(|x| x.rem_euclid(major) == 0) {
+                ImDrawList_AddLine(draw.draw_list, screen(x as _, lo.y as _), screen(x as _, hi.y as _), color_major, 1.0);
+            }
+            for y in (lo.y..=hi.y).filter(|y| y.rem_euclid(major) == 0) {
+                ImDrawList_AddLine(draw.draw_list, screen(lo.x as _, y as _), screen(hi.x as _, y as _), color_major, 1.0);
+            }
+        }
+
+        let minor = config.grid.minor.max(1);
+        let spacing = (screen(1.0, 0.0).x - screen(0.0, 0.0).x) / minor as f32;
+        for x in lo.x..=hi.x {
+            for y in lo.y..=hi.y {
+                ImDrawList_AddCircleFilled(draw.draw_list, screen(x as _, y as _), 3.0, color_grid, 4);
+                if minor > 1 && spacing >= MIN_DIVISION_SPACING {
+                    for i in 1..minor {
+                        let p = screen(x as f32 + i as f32 / minor as f32, y as _);
+                        ImDrawList_AddCircleFilled(draw.draw_list, p, 1.5, color_grid, 4);
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub fn base(config :&Config, analysis :&Analysis, inf_view :&InfView, 
             instant :Option<&Instant>,
             dispatch_view :&Option<DispatchView>, draw :&Draw) {
@@ -58,42 +153,45 @@ pub fn base(config :&Config, analysis :&Analysis, inf_view :&InfView,
             Some((*a, *a + igGetMouseDragDelta_nonUDT2(0,-1.0).into()))
         } else { None };
 
+        let viewport = Viewport::new(inf_view, draw);
         let (lo,hi) = inf_view.view.points_in_view(draw.size);
-        let color_grid = config.color_u32(RailUIColorName::CanvasGridPoint);
-        for x in lo.x..=hi.x {
-            for y in lo.y..=hi.y {
-                let pt = inf_view.view.world_pt_to_screen(glm::vec2(x,y));
-                ImDrawList_AddCircleFilled(draw.draw_list, draw.pos+pt, 3.0, color_grid, 4);
-            }
-        }
+        if config.grid.visible { grid(config, inf_view, draw, lo, hi); }
 
         let color_line = config.color_u32(RailUIColorName::CanvasTrack);
         let color_line_selected = config.color_u32(RailUIColorName::CanvasTrackSelected);
         let color_glow = (config.color_u32(RailUIColorName::CanvasSelectionWindow) & 0x00FFFFFF) | 0x80000000; // Semi-transparent glow
+        let mut track_lines = Vec::new();
+        let corners = if config.smooth_curves { curves::corners(&m.linesegs) } else { HashMap::new() };
         for l in &m.linesegs {
+            if !viewport.line(glm::vec2(l.0.x as _, l.0.y as _), glm::vec2(l.1.x as _, l.1.y as _)) { continue; }
             let selected = inf_view.selection.contains(&Ref::LineSeg(l.0,l.1));
             let preview = sel_window
                 .map(|(a,b)| util::point_in_rect(inf_view.view.world_pt_to_screen(l.0),a,b) || 
                              util::point_in_rect(inf_view.view.world_pt_to_screen(l.1),a,b))
                 .unwrap_or(false) ;
 
-            if selected {
-                let p1 = inf_view.view.world_pt_to_screen(l.0);
-                let p2 = inf_view.view.world_pt_to_screen(l.1);
-                ImDrawList_AddLine(draw.draw_list, draw.pos + p1, draw.pos + p2, color_glow, 6.0);
-            }
+            let pts = curves::polyline(l.0, l.1, &corners).into_iter()
+                .map(|p| draw.pos + inf_view.view.world_ptc_to_screen(p)).collect::<Vec<_>>();
+            for (p1,p2) in pts.iter().zip(pts.iter().skip(1)) {
+                if selected {
+                    ImDrawList_AddLine(draw.draw_list, *p1, *p2, color_glow, 6.0);
+                }
 
-            let p1 = inf_view.view.world_pt_to_screen(l.0);
-            let p2 = inf_view.view.world_pt_to_screen(l.1);
-            let col = if selected || preview { color_line_selected } else { color_line };
-            ImDrawList_AddLine(draw.draw_list, draw.pos + p1, draw.pos + p2, col, 2.5);
+                if selected || preview {
+                    ImDrawList_AddLine(draw.draw_list, *p1, *p2, color_line_selected, 2.5);
+                } else {
+                    track_lines.push((*p1,*p2));
+                }
+            }
         }
+        line_batch(draw.draw_list, &track_lines, color_line, 2.5);
 
         let color_node = config.color_u32(RailUIColorName::CanvasNode);
         let color_node_selected = config.color_u32(RailUIColorName::CanvasNodeSelected);
         if let Some((_gen,topo)) = d.topology.as_ref() {
             use nalgebra_glm::{vec2, rotate_vec2, radians, vec1, normalize};
             for (pt0,(t,vc)) in &topo.locations {
+                if !viewport.contains(glm::vec2(pt0.x as _, pt0.y as _)) { continue; }
                 let selected = inf_view.selection.contains(&Ref::Node(*pt0));
                 let preview = sel_window.map(|(a,b)| 
                          util::point_in_rect(inf_view.view.world_pt_to_screen(*pt0),a,b)).unwrap_or(false);
@@ -192,6 +290,13 @@ pub fn base(config :&Config, analysis :&Analysis, inf_view :&InfView,
             let preview = sel_window.map(|(a,b)| 
                      util::point_in_rect(inf_view.view.
                              world_ptc_to_screen(unround_coord(*pta)),a,b)).unwrap_or(false);
+            if obj.functions.contains(&Function::PlatformEdge) {
+                let (a,b) = platform_edge_bar(m, *pta, obj);
+                if viewport.line(a,b) {
+                    platform_edge(draw, inf_view, a, b, if selected || preview { color_obj_selected } else { color_obj }, selected);
+                }
+            }
+            if !viewport.contains(obj.loc) { continue; }
             
             if selected {
                 let p = draw.pos + inf_view.view.world_ptc_to_screen(unround_coord(*pta));
@@ -201,11 +306,53 @@ pub fn base(config :&Config, analysis :&Analysis, inf_view :&InfView,
             let col = if selected || preview { color_obj_selected } else { color_obj };
             let empty = vec![];
             let state = object_states.get(pta).unwrap_or(&empty);
-            obj.draw(draw.pos, &inf_view.view, draw.draw_list, col, state, config);
+            let crossing_angle = if obj.functions.contains(&Function::LevelCrossing) {
+                level_crossing_data(m, *pta).1.filter(|a| *a != 90.0)
+            } else { None };
+            if let Some(angle) = crossing_angle {
+                let mut other = obj.clone();
+                other.functions.retain(|f| *f != Function::LevelCrossing);
+                other.draw(draw.pos, &inf_view.view, draw.draw_list, col, state, config);
+                let p = draw.pos + inf_view.view.world_ptc_to_screen(obj.loc);
+                for prim in level_crossing_road(obj.tangent, angle) {
+                    prim.draw(draw.draw_list, p, col);
+                }
+            } else {
+                obj.draw(draw.pos, &inf_view.view, draw.draw_list, col, state, config);
+            }
+        }
+    }
+}
+
+/// The bar of a platform edge, with handles for changing its length when
+/// it is selected.
+fn platform_edge(draw :&Draw, inf_view :&InfView, a :PtC, b :PtC, col :u32, selected :bool) {
+    let a = draw.pos + inf_view.view.world_ptc_to_screen(a);
+    let b = draw.pos + inf_view.view.world_ptc_to_screen(b);
+    unsafe {
+        ImDrawList_AddLine(draw.draw_list, a, b, col, 4.0);
+        if selected {
+            let half_size = ImVec2 { x: 4.0, y: 4.0 };
+            for p in &[a, b] {
+                ImDrawList_AddRectFilled(draw.draw_list, *p - half_size, *p + half_size, col, 0.0, 0);
+            }
         }
     }
 }
 
+/// The end of a selected platform edge's bar at a point on the canvas,
+/// relative to its origin: the start if false, otherwise the end.
+pub fn platform_edge_handle_at(analysis :&Analysis, inf_view :&InfView, p :ImVec2) -> Option<(PtA, bool)> {
+    let m = analysis.model();
+    let near = |q :PtC| (inf_view.view.world_ptc_to_screen(q) - p).length_sq() < 6.0*6.0;
+    inf_view.selection.iter().find_map(|r| {
+        let pta = match r { Ref::Object(pta) => *pta, _ => return None };
+        let obj = m.objects.get(&pta).filter(|o| o.functions.contains(&Function::PlatformEdge))?;
+        let (a,b) = platform_edge_bar(m, pta, obj);
+        if near(b) { Some((pta, true)) } else if near(a) { Some((pta, false)) } else { None }
+    })
+}
+
 pub fn route(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Draw, route_idx :usize) -> Option<()> { 
     unsafe {
         let il = &analysis.data().interlocking.as_ref()?.1;
@@ -245,18 +392,142 @@ pub fn route(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Dr
     }
 }
 
+/// Draw a catenary line beside the electrified line segments, with a mast
+/// at the middle of each. Track known not to be electrified gets no line.
+pub fn catenary(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Draw) {
+    let color = config.color_u32(RailUIColorName::CanvasCatenary);
+    let viewport = Viewport::new(inf_view, draw);
+    let mut lines = Vec::new();
+    let m = analysis.model();
+    for ((a,b),attrs) in m.lineseg_attributes.iter() {
+        if attrs.is_electrified() != Some(true) || !m.linesegs.contains(&(*a,*b)) { continue; }
+        if !viewport.line(glm::vec2(a.x as _, a.y as _), glm::vec2(b.x as _, b.y as _)) { continue; }
+        let p1 = draw.pos + inf_view.view.world_pt_to_screen(*a);
+        let p2 = draw.pos + inf_view.view.world_pt_to_screen(*b);
+        let d = p2 - p1;
+        let len = (d.x*d.x + d.y*d.y).sqrt().max(1e-6);
+        let mut n = 6.0 * ImVec2 { x: -d.y / len, y: d.x / len };
+        if n.y > 0.0 || (n.y == 0.0 && n.x < 0.0) {
+            // Keep the catenary above the track.
+            n = ImVec2 { x: -n.x, y: -n.y };
+        }
+        let mid = p1 + 0.5*d;
+        lines.push((p1 + n, p2 + n));
+        lines.push((mid, mid + n));
+    }
+    line_batch(draw.draw_list, &lines, color, 1.5);
+}
+
+/// Draw the imported OCPs as station markers with their names.
+pub fn ocps(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Draw) {
+    let m = analysis.model();
+    let viewport = Viewport::new(inf_view, draw);
+    let color = config.color_u32(RailUIColorName::CanvasSymbol);
+    let color_selected = config.color_u32(RailUIColorName::CanvasSymbolSelected);
+    let half_size = ImVec2 { x: 6.0, y: 6.0 };
+    for (idx,ocp) in m.railml_ocps.iter().enumerate() {
+        let pos = match m.ocp_positions.get(&ocp.id) { Some(p) => *p, None => continue };
+        if !viewport.contains(pos) { continue; }
+        let col = if inf_view.selected_ocp == Some(idx) { color_selected } else { color };
+        let p = draw.pos + inf_view.view.world_ptc_to_screen(pos);
+        let name = ocp.name.as_ref().unwrap_or(&ocp.id);
+        unsafe {
+            ImDrawList_AddRectFilled(draw.draw_list, p - half_size, p + half_size, col, 2.0, 0);
+            ImDrawList_AddText(draw.draw_list, p + ImVec2 { x: 10.0, y: -8.0 }, col,
+                               name.as_ptr() as _, name.as_ptr().add(name.len()) as _);
+        }
+    }
+}
+
+/// The OCP station marker at a point on the canvas, relative to its origin.
+pub fn ocp_at(analysis :&Analysis, inf_view :&InfView, p :ImVec2) -> Option<usize> {
+    let m = analysis.model();
+    m.railml_ocps.iter().position(|ocp| {
+        m.ocp_positions.get(&ocp.id)
+            .map(|pos| (inf_view.view.world_ptc_to_screen(*pos) - p).length_sq() < 8.0*8.0)
+            .unwrap_or(false)
+    })
+}
+
+/// Draw the annotations: notes with their leader lines, and dimension
+/// lines with end ticks and the text in the middle.
+pub fn annotations(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Draw) {
+    let viewport = Viewport::new(inf_view, draw);
+    let color = config.color_u32(RailUIColorName::CanvasSymbol);
+    let color_selected = config.color_u32(RailUIColorName::CanvasSymbolSelected);
+    let half_size = ImVec2 { x: 3.0, y: 3.0 };
+    for (id,a) in analysis.model().annotations.iter() {
+        let anchor = a.anchor_pos();
+        let visible = match anchor {
+            Some(q) => viewport.line(a.pos, q),
+            None => viewport.contains(a.pos),
+        };
+        if !visible { continue; }
+        let col = if inf_view.selected_annotation == Some(*id) { color_selected } else { color };
+        let p = draw.pos + inf_view.view.world_ptc_to_screen(a.pos);
+        let mut text_pos = p + ImVec2 { x: 6.0, y: -8.0 };
+        unsafe {
+            if let Some(q) = anchor {
+                let q = draw.pos + inf_view.view.world_ptc_to_screen(q);
+                ImDrawList_AddLine(draw.draw_list, p, q, col, 1.0);
+                if a.dimension {
+                    let d = q - p;
+                    let len = (d.x*d.x + d.y*d.y).sqrt().max(1e-6);
+                    let n = 6.0 * ImVec2 { x: -d.y / len, y: d.x / len };
+                    ImDrawList_AddLine(draw.draw_list, p - n, p + n, col, 1.0);
+                    ImDrawList_AddLine(draw.draw_list, q - n, q + n, col, 1.0);
+                    text_pos = p + 0.5*d + ImVec2 { x: 4.0, y: -16.0 };
+                } else {
+                    ImDrawList_AddCircleFilled(draw.draw_list, q, 3.0, col, 8);
+                }
+            }
+            ImDrawList_AddRectFilled(draw.draw_list, p - half_size, p + half_size, col, 0.0, 0);
+            ImDrawList_AddText(draw.draw_list, text_pos, col,
+                               a.text.as_ptr() as _, a.text.as_ptr().add(a.text.len()) as _);
+        }
+    }
+}
+
+/// The annotation at a point on the canvas, relative to its origin, and
+/// whether the point is at its anchor instead of its text. Only anchors
+/// which are points can be moved, so those are the only ones found.
+pub fn annotation_at(analysis :&Analysis, inf_view :&InfView, p :ImVec2) -> Option<(usize, bool)> {
+    let near = |q :PtC| (inf_view.view.world_ptc_to_screen(q) - p).length_sq() < 8.0*8.0;
+    analysis.model().annotations.iter().find_map(|(id,a)| {
+        if near(a.pos) { return Some((*id, false)); }
+        match a.anchor {
+            Some(AnnotationAnchor::Point(q)) if near(q) => Some((*id, true)),
+            _ => None,
+        }
+    })
+}
+
+/// Mark signals which are closer to the next signal than the braking
+/// distance from the configured design speed.
+pub fn signal_spacing(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Draw) -> Option<()> {
+    let il = &analysis.data().interlocking.as_ref()?.1;
+    let issues = crate::document::lint::signal_spacing(analysis.model(), il,
+                          config.design_speed as f64, config.design_deceleration as f64, &config.units);
+    let color = config.color_u32(RailUIColorName::CanvasSymbolLocError);
+    for issue in issues.iter() {
+        let p = draw.pos + inf_view.view.world_ptc_to_screen(issue.pos());
+        unsafe {
+            ImDrawList_AddCircle(draw.draw_list, p, 12.0, color, 16, 2.0);
+        }
+    }
+    Some(())
+}
+
 pub fn trains(config :&Config, instant :&Instant, inf_view :&InfView, draw :&Draw) -> Option<()> { 
     let color = config.color_u32(RailUIColorName::CanvasTrain);
     let sight_color = config.color_u32(RailUIColorName::CanvasTrainSight);
+    let viewport = Viewport::new(inf_view, draw);
     for t in instant.trains.iter() {
-        for (p1,p2) in t.lines.iter() {
-            unsafe {
-            ImDrawList_AddLine(draw.draw_list,
-                               draw.pos + inf_view.view.world_ptc_to_screen(*p1),
-                               draw.pos + inf_view.view.world_ptc_to_screen(*p2),
-                               color, 2.0*10.0);
-            }
-        }
+        let lines = t.lines.iter().filter(|(p1,p2)| viewport.line(*p1,*p2))
+            .map(|(p1,p2)| (draw.pos + inf_view.view.world_ptc_to_screen(*p1),
+                            draw.pos + inf_view.view.world_ptc_to_screen(*p2)))
+            .collect::<Vec<_>>();
+        line_batch(draw.draw_list, &lines, color, 2.0*10.0);
 
         if let Some(front) = t.get_front() {
             for pta in t.signals_sighted.iter() {
@@ -276,21 +547,28 @@ pub fn trains(config :&Config, instant :&Instant, inf_view :&InfView, draw :&Dra
 }
 
 pub fn state(config :&Config, instant :&Instant, inf_view :&InfView, draw :&Draw) {
-    for (_tvd, status, lines) in instant.infrastructure.sections.iter() {
+    // Draw released sections first, so that locked and occupied sections stay on top.
+    let mut sections = instant.infrastructure.sections.iter().collect::<Vec<_>>();
+    sections.sort_by_key(|(_,status,_)| match status {
+        SectionStatus::Free => 0,
+        SectionStatus::Released => 1,
+        SectionStatus::Reserved => 2,
+        SectionStatus::Occupied => 3,
+    });
+    let viewport = Viewport::new(inf_view, draw);
+    for (_tvd, status, lines) in sections {
         let color = match status {
             SectionStatus::Occupied => config.color_u32(RailUIColorName::CanvasTVDOccupied),
             SectionStatus::Reserved => config.color_u32(RailUIColorName::CanvasTVDReserved),
-            _ => config.color_u32(RailUIColorName::CanvasTVDFree),
+            SectionStatus::Released => config.color_u32(RailUIColorName::CanvasTVDReleased),
+            SectionStatus::Free => config.color_u32(RailUIColorName::CanvasTVDFree),
         };
 
-        for (p1,p2) in lines.iter() {
-            unsafe {
-                ImDrawList_AddLine(draw.draw_list,
-                                   draw.pos + inf_view.view.world_ptc_to_screen(*p1),
-                                   draw.pos + inf_view.view.world_ptc_to_screen(*p2),
-                                   color, 2.0*4.0);
-            }
-        }
+        let lines = lines.iter().filter(|(p1,p2)| viewport.line(*p1,*p2))
+            .map(|(p1,p2)| (draw.pos + inf_view.view.world_ptc_to_screen(*p1),
+                            draw.pos + inf_view.view.world_ptc_to_screen(*p2)))
+            .collect::<Vec<_>>();
+        line_batch(draw.draw_list, &lines, color, 2.0*4.0);
     }
 }
 
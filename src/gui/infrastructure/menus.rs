@@ -14,7 +14,9 @@ use crate::document::view::*;
 use crate::document::interlocking::*;
 use crate::gui::widgets;
 use crate::gui::plan;
-use crate::config::RailUIColorName;
+use crate::config::{Config, RailUIColorName};
+use crate::i18n;
+use crate::export::signal_type_from_kind;
 
 
 
@@ -23,9 +25,15 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
     unsafe {
     match nd {
         NDType::OpenEnd | NDType::BufferStop => {
+            if let Some(node) = analysis.model().railml_nodes.get(&pt) {
+                widgets::show_text(&i18n::tr_fmt("Boundary {}", &[&node.id]));
+                if let Some(ocp) = &node.ocp_ref {
+                    widgets::show_text(&i18n::tr_fmt("OCP: {}", &[ocp]));
+                }
+            }
             if let Some(new_value) =
-                widgets::radio_select(&[(const_cstr!("Open end").as_ptr(), *nd == NDType::OpenEnd, NDType::OpenEnd),
-                                   (const_cstr!("Buffer stop").as_ptr(), *nd == NDType::BufferStop, NDType::BufferStop)]) {
+                widgets::radio_select(&[(tr!("Open end"), *nd == NDType::OpenEnd, NDType::OpenEnd),
+                                   (tr!("Buffer stop"), *nd == NDType::BufferStop, NDType::BufferStop)]) {
 
                 analysis.edit_model(|m| {
                     m.node_data.insert(pt, *new_value);
@@ -35,19 +43,35 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
         },
         NDType::Sw(side) => {
             widgets::show_text(&format!("Switch ({:?})", side));
+            if let Some(new_side) =
+                widgets::radio_select(&[(tr!("Left-hand"), *side == Side::Left, Side::Left),
+                                        (tr!("Right-hand"), *side == Side::Right, Side::Right)]) {
+                analysis.edit_model(|m| {
+                    m.node_data.insert(pt, NDType::Sw(*new_side));
+                    None
+                });
+            }
+
+            let mut trap = analysis.model().trap_points.contains(&pt);
+            if igCheckbox(tr!("Trap point on deviating branch"), &mut trap) {
+                analysis.edit_model(|m| {
+                    if trap { m.trap_points.insert(pt); } else { m.trap_points.remove(&pt); }
+                    None
+                });
+            }
 
             // TODO 
             let mut speed = 60.0;
-            igInputFloat(const_cstr!("Deviating speed restr.").as_ptr(), &mut speed, 1.0, 10.0,
+            igInputFloat(tr!("Deviating speed restr."), &mut speed, 1.0, 10.0,
                          const_cstr!("%.1f").as_ptr(), 0 as _);
         },
         NDType::Crossing(type_) => {
             widgets::show_text(&format!("Crossing ({:?})", type_));
             if let Some(new_value) =
-                widgets::radio_select(&[(const_cstr!("Crossover").as_ptr(), *type_ == CrossingType::Crossover, CrossingType::Crossover),
-                                   (const_cstr!("Single slip (above)").as_ptr(), *type_ == CrossingType::SingleSlip(Side::Left), CrossingType::SingleSlip(Side::Left)),
-                                   (const_cstr!("Single slip (below)").as_ptr(), *type_ == CrossingType::SingleSlip(Side::Right), CrossingType::SingleSlip(Side::Right)),
-                                   (const_cstr!("Double slip").as_ptr(), *type_ == CrossingType::DoubleSlip, CrossingType::DoubleSlip)]) {
+                widgets::radio_select(&[(tr!("Crossover"), *type_ == CrossingType::Crossover, CrossingType::Crossover),
+                                   (tr!("Single slip (above)"), *type_ == CrossingType::SingleSlip(Side::Left), CrossingType::SingleSlip(Side::Left)),
+                                   (tr!("Single slip (below)"), *type_ == CrossingType::SingleSlip(Side::Right), CrossingType::SingleSlip(Side::Right)),
+                                   (tr!("Double slip"), *type_ == CrossingType::DoubleSlip, CrossingType::DoubleSlip)]) {
 
                 analysis.edit_model(|m| {
                     m.node_data.insert(pt, NDType::Crossing(*new_value));
@@ -57,7 +81,7 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
 
             // TODO 
             let mut speed = 60.0;
-            igInputFloat(const_cstr!("Deviating speed restr.").as_ptr(), &mut speed, 1.0, 10.0,
+            igInputFloat(tr!("Deviating speed restr."), &mut speed, 1.0, 10.0,
                          const_cstr!("%.1f").as_ptr(), 0 as _);
         }
         _ => {},
@@ -67,46 +91,301 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
 }
 
 
-pub fn object_menu(analysis :&mut Analysis, pta :PtA) -> Option<()> {
+pub fn object_menu(config :&Config, analysis :&mut Analysis, pta :PtA) -> Option<()> {
     let obj = analysis.model().objects.get(&pta)?;
 
-    let mut set_distant = None;
+    let mut set_signal = None;
     for f in obj.functions.iter() {
         match f {
-            Function::Detector => { widgets::show_text("Detector"); },
-            Function::TrackCircuitBorder => { widgets::show_text("Track circuit border"); },
-            Function::Derailer => { widgets::show_text("Derailer"); },
-            Function::TrainProtectionElement => { widgets::show_text("Train protection element"); },
-            Function::TrainProtectionGroup => { widgets::show_text("Train protection group"); },
-            Function::Balise => { widgets::show_text("Balise"); },
-            Function::PlatformEdge => { widgets::show_text("Platform edge"); },
-            Function::SpeedChange => { widgets::show_text("Speed change"); },
-            Function::LevelCrossing => { widgets::show_text("Level crossing"); },
-            Function::CrossSection => { widgets::show_text("Cross section"); },
-            Function::MainSignal { has_distant, kind } => {
-                widgets::show_text(&format!("Signal ({:?})", kind));
-                if matches!(kind, SignalKind::Main | SignalKind::Combined) {
-                    let mut has_distant = *has_distant;
-                    unsafe {
-                        igCheckbox(const_cstr!("Distant signal").as_ptr(), &mut has_distant);
-                        if igIsItemEdited() {
-                            set_distant = Some(has_distant);
-                        }
+            Function::Detector => { widgets::show_text(i18n::tr("Detector")); },
+            Function::TrackCircuitBorder => { widgets::show_text(i18n::tr("Track circuit border")); },
+            Function::Derailer => { widgets::show_text(i18n::tr("Derailer")); },
+            Function::TrainProtectionElement => { widgets::show_text(i18n::tr("Train protection element")); },
+            Function::TrainProtectionGroup => { widgets::show_text(i18n::tr("Train protection group")); },
+            Function::Balise => { widgets::show_text(i18n::tr("Balise")); },
+            Function::BaliseGroup => {
+                widgets::show_text(i18n::tr("Balise group"));
+                let model = analysis.model();
+                let group = model.railml_objects.get(&pta).into_iter().flatten()
+                    .find_map(|info| match info {
+                        RailMLObjectInfo::BaliseGroup { id, country_id, group_id, linking_accuracy, .. } =>
+                            Some((id, country_id, group_id, linking_accuracy)),
+                        _ => None,
+                    });
+                if let Some((id, country_id, group_id, linking_accuracy)) = group {
+                    if let (Some(c), Some(g)) = (country_id, group_id) {
+                        widgets::show_text(&format!("NID_C {} / NID_BG {}", c, g));
+                    }
+                    if let Some(acc) = linking_accuracy {
+                        widgets::show_text(&i18n::tr_fmt("Linking accuracy: {} m", &[&acc.to_string()]));
                     }
+                    let balises = model.railml_objects.values().flatten()
+                        .filter(|info| matches!(info, RailMLObjectInfo::Balise { group_ref: Some(r), .. } if r == id))
+                        .count();
+                    widgets::show_text(&i18n::tr_fmt("{} balises", &[&balises.to_string()]));
+                }
+            },
+            Function::PlatformEdge => {
+                widgets::show_text(i18n::tr("Platform edge"));
+                platform_edge_editor(config, analysis, pta);
+            },
+            Function::SpeedChange => { widgets::show_text(i18n::tr("Speed change")); },
+            Function::GradientChange => {
+                widgets::show_text(i18n::tr("Gradient change"));
+                let slope = analysis.model().railml_objects.get(&pta).into_iter().flatten()
+                    .find_map(|info| match info {
+                        RailMLObjectInfo::GradientChange { slope, .. } => Some(*slope),
+                        _ => None,
+                    });
+                if let Some(slope) = slope {
+                    widgets::show_text(&format!("{:.1} ‰", slope));
+                }
+            },
+            Function::RadiusChange => {
+                widgets::show_text(i18n::tr("Radius change"));
+                let radius = analysis.model().railml_objects.get(&pta).into_iter().flatten()
+                    .find_map(|info| match info {
+                        RailMLObjectInfo::RadiusChange { radius, .. } => Some(*radius),
+                        _ => None,
+                    });
+                if let Some(radius) = radius {
+                    widgets::show_text(&format!("R = {:.0} m", radius));
+                }
+            },
+            Function::LevelCrossing => {
+                widgets::show_text(i18n::tr("Level crossing"));
+                level_crossing_editor(analysis, pta);
+            },
+            Function::CrossSection => { widgets::show_text(i18n::tr("Cross section")); },
+            Function::MainSignal { has_distant, kind } => {
+                widgets::show_text(i18n::tr("Signal"));
+                if let Some(f) = signal_kind_editor(*kind, *has_distant) {
+                    set_signal = Some(f);
+                }
+                let speeds = analysis.model().railml_objects.get(&pta).into_iter().flatten()
+                    .find_map(|info| match info {
+                        RailMLObjectInfo::Signal { speeds, .. } => Some(speeds.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                for sp in &speeds {
+                    let target = sp.speed_change_ref.as_deref().unwrap_or("?");
+                    let text = if sp.switchable == Some(true) {
+                        i18n::tr_fmt("Switchable speed for {}", &[target])
+                    } else {
+                        i18n::tr_fmt("Speed for {}", &[target])
+                    };
+                    widgets::show_text(&text);
                 }
             }
         }
     }
-    if let Some(d) = set_distant {
+    if let Some(f) = set_signal {
         analysis.edit_model(|new| {
-            let kind = if d { SignalKind::Combined } else { SignalKind::Main };
-            new.objects.get_mut(&pta).unwrap().functions = vec![Function::MainSignal { has_distant: d, kind }];
+            new.objects.get_mut(&pta).unwrap().functions = vec![f];
+            if let Function::MainSignal { kind, has_distant } = f {
+                let type_ = signal_type_from_kind(kind, has_distant);
+                for info in new.railml_objects.get_mut(&pta).into_iter().flatten() {
+                    if let RailMLObjectInfo::Signal { r#type, .. } = info { *r#type = type_; }
+                }
+            }
             None
         });
     }
+    if let Some(Function::MainSignal { kind, has_distant }) = analysis.model().objects.get(&pta)
+            .and_then(|o| o.functions.iter().find(|f| matches!(f, Function::MainSignal { .. }))).cloned() {
+        signal_editor(analysis, pta, kind, has_distant);
+    }
     Some(())
 }
 
+/// Side and length of a platform edge, which are also changed by dragging
+/// the ends of its bar on the canvas. The length is edited in the
+/// configured unit.
+fn platform_edge_editor(config :&Config, analysis :&mut Analysis, pta :PtA) {
+    let (length, side) = platform_edge_data(analysis.model(), pta);
+    let right = side.map(|s| s.eq_ignore_ascii_case("right")).unwrap_or(false);
+    let mut new_side = None;
+    let mut new_length = None;
+    unsafe {
+        igIndent(14.0);
+        if let Some(s) = widgets::radio_select(&[(tr!("Left side"), !right, "left"),
+                                                 (tr!("Right side"), right, "right")]) {
+            new_side = Some(s.to_string());
+        }
+        let unit = config.units.length;
+        let mut value = unit.from_meters(length) as f32;
+        let label = CString::new(format!("{} ({})", i18n::tr("Length"), unit.symbol())).unwrap();
+        let format = CString::new(format!("%.{}f", unit.decimals())).unwrap();
+        let step = unit.from_meters(10.0) as f32;
+        // Typed lengths are applied with Enter, to keep one undo step.
+        if igInputFloat(label.as_ptr(), &mut value, step, 5.0 * step, format.as_ptr(),
+                        ImGuiInputTextFlags__ImGuiInputTextFlags_EnterReturnsTrue as _) && value > 0.0 {
+            new_length = Some(unit.to_meters(value as f64));
+        }
+        igUnindent(14.0);
+    }
+    if new_side.is_some() || new_length.is_some() {
+        analysis.edit_model(|m| {
+            if let RailMLObjectInfo::PlatformEdge { side, length, .. } = m.platform_edge_info_mut(pta) {
+                if new_side.is_some() { *side = new_side; }
+                if new_length.is_some() { *length = new_length; }
+            }
+            None
+        });
+    }
+}
+
+/// Railway protection and road angle of a level crossing.
+fn level_crossing_editor(analysis :&mut Analysis, pta :PtA) {
+    let (protection, angle) = level_crossing_data(analysis.model(), pta);
+    let mut new_protection = None;
+    let mut new_angle = None;
+    unsafe {
+        igIndent(14.0);
+        if let Some(p) = widgets::edit_text(tr!("Protection"), protection.unwrap_or_default()) {
+            new_protection = Some(Some(p).filter(|p| !p.trim().is_empty()));
+        }
+        let mut value = angle.unwrap_or(90.0) as f32;
+        // Typed angles are applied with Enter, to keep one undo step.
+        if igInputFloat(tr!("Angle (°)"), &mut value, 5.0, 15.0, const_cstr!("%.0f").as_ptr(),
+                        ImGuiInputTextFlags__ImGuiInputTextFlags_EnterReturnsTrue as _) && value > 0.0 && value < 180.0 {
+            new_angle = Some(value as f64);
+        }
+        igUnindent(14.0);
+    }
+    if new_protection.is_some() || new_angle.is_some() {
+        let class = if new_protection.is_some() { Some(EditClass::LevelCrossingProtection(pta)) } else { None };
+        analysis.edit_model(|m| {
+            if let RailMLObjectInfo::LevelCrossing { protection, angle, .. } = m.level_crossing_info_mut(pta) {
+                if let Some(p) = new_protection { *protection = p; }
+                if new_angle.is_some() { *angle = new_angle; }
+            }
+            class
+        });
+    }
+}
+
+/// Choice of signal kind, and of a distant signal on the same post for
+/// main signals. Gives the changed function.
+fn signal_kind_editor(kind :SignalKind, has_distant :bool) -> Option<Function> {
+    let mut changed = None;
+    unsafe {
+        let kinds = [(tr!("Main signal"), SignalKind::Main),
+                     (tr!("Combined signal"), SignalKind::Combined),
+                     (tr!("Distant signal"), SignalKind::Distant),
+                     (tr!("Repeater signal"), SignalKind::Repeater),
+                     (tr!("Shunting signal"), SignalKind::Shunting)];
+        let choices = kinds.iter().map(|(name, k)| (*name, *k == kind, *k)).collect::<Vec<_>>();
+        igIndent(14.0);
+        if let Some(k) = widgets::radio_select(&choices) {
+            changed = Some(Function::MainSignal { kind: *k, has_distant: *k == SignalKind::Combined });
+        }
+        if matches!(kind, SignalKind::Main | SignalKind::Combined) {
+            let mut has_distant = has_distant;
+            igCheckbox(tr!("With distant signal"), &mut has_distant);
+            if igIsItemEdited() {
+                let kind = if has_distant { SignalKind::Combined } else { SignalKind::Main };
+                changed = Some(Function::MainSignal { has_distant, kind });
+            }
+        }
+        igUnindent(14.0);
+    }
+    changed
+}
+
+/// Editor for the railML properties of a signal. They are kept with the
+/// signal in the model and written when exporting.
+fn signal_editor(analysis :&mut Analysis, pta :PtA, kind :SignalKind, has_distant :bool) {
+    let model = analysis.model();
+    let (mut code, mut switchable, mut ocp_ref, mut etcs) = model.railml_objects.get(&pta).into_iter().flatten()
+        .find_map(|info| match info {
+            RailMLObjectInfo::Signal { code, switchable, ocp_station_ref, etcs, .. } =>
+                Some((code.clone(), *switchable, ocp_station_ref.clone(), etcs.clone())),
+            _ => None,
+        })
+        .unwrap_or((None, None, None, None));
+    let mut changed = false;
+    let mut class = None;
+    unsafe {
+        if let Some(new_code) = widgets::edit_text(tr!("Code"), code.clone().unwrap_or_default()) {
+            code = Some(new_code).filter(|c| !c.trim().is_empty());
+            changed = true;
+            class = Some(EditClass::SignalCode(pta));
+        }
+
+        let mut value = switchable.unwrap_or(false);
+        if igCheckbox(tr!("Switchable"), &mut value) {
+            switchable = Some(value);
+            changed = true;
+        }
+
+        let ocp_name = |id :&str| model.railml_ocps.iter().find(|o| o.id == id)
+            .and_then(|o| o.name.clone()).map(|n| format!("{} ({})", n, id)).unwrap_or_else(|| id.to_string());
+        let current = CString::new(ocp_ref.as_deref().map(ocp_name)
+            .unwrap_or_else(|| i18n::tr("None").to_string())).unwrap();
+        if igBeginCombo(tr!("Station"), current.as_ptr(), 0 as _) {
+            if igSelectable(tr!("None"), ocp_ref.is_none(), 0 as _, ImVec2::zero()) {
+                ocp_ref = None;
+                changed = true;
+            }
+            for (i,ocp) in model.railml_ocps.iter().enumerate() {
+                igPushIDInt(i as _);
+                let label = CString::new(ocp_name(&ocp.id)).unwrap();
+                if igSelectable(label.as_ptr(), ocp_ref.as_deref() == Some(ocp.id.as_str()), 0 as _, ImVec2::zero()) {
+                    ocp_ref = Some(ocp.id.clone());
+                    changed = true;
+                }
+                igPopID();
+            }
+            igEndCombo();
+        }
+
+        let mut levels = match &etcs {
+            Some(e) => [e.level_1 == Some(true), e.level_2 == Some(true), e.level_3 == Some(true)],
+            None => [false; 3],
+        };
+        let mut levels_changed = false;
+        for (i,(name, level)) in [tr!("ETCS level 1"), tr!("ETCS level 2"), tr!("ETCS level 3")].iter()
+                .zip(levels.iter_mut()).enumerate() {
+            if i > 0 { igSameLine(0.0,-1.0); }
+            levels_changed |= igCheckbox(*name, level);
+        }
+        if levels_changed {
+            etcs = if levels.iter().any(|l| *l) {
+                Some(railmlio::model::Etcs { level_1: Some(levels[0]), level_2: Some(levels[1]), level_3: Some(levels[2]) })
+            } else { None };
+            changed = true;
+        }
+    }
+
+    if changed {
+        analysis.edit_model(|m| {
+            let info = m.object_info_mut(pta, "sig", |i| matches!(i, RailMLObjectInfo::Signal { .. }),
+                |id| RailMLObjectInfo::Signal {
+                    id,
+                    sight: None,
+                    r#type: signal_type_from_kind(kind, has_distant),
+                    function: None,
+                    code: None,
+                    switchable: None,
+                    ocp_station_ref: None,
+                    // Written from the signal's position when exporting.
+                    dir: railmlio::model::TrackDirection::Up,
+                    speeds: Vec::new(),
+                    etcs: None,
+                });
+            if let RailMLObjectInfo::Signal { code: c, switchable: s, ocp_station_ref: o, etcs: e, .. } = info {
+                *c = code;
+                *s = switchable;
+                *o = ocp_ref;
+                *e = etcs;
+            }
+            class
+        });
+    }
+}
+
 pub fn route_selector(analysis :&mut Analysis, dispatch_view :&Option<DispatchView>, 
                       thing :Ref, preview :&mut Option<usize>) -> Option<Command> {
 
@@ -120,9 +399,9 @@ pub fn route_selector(analysis :&mut Analysis, dispatch_view :&Option<DispatchVi
 
         let have_dispatch = matches!(&dispatch_view, Some(DispatchView::Manual(_)));
         if have_dispatch {
-            widgets::show_text("Add dispatch command:");
+            widgets::show_text(i18n::tr("Add dispatch command:"));
         } else {
-            widgets::show_text("New dispatch:");
+            widgets::show_text(i18n::tr("New dispatch:"));
         }
 
         let mut some = false;
@@ -133,7 +412,10 @@ pub fn route_selector(analysis :&mut Analysis, dispatch_view :&Option<DispatchVi
             igPushIDInt(*idx as _);
 
             let is_boundary = matches!(il.routes[*idx].route.entry, rolling_inf::RouteEntryExit::Boundary(_));
-            let text = CString::new(format!("Route to {:?}", (il.routes[*idx].route).exit)).unwrap();
+            let text = match &il.routes[*idx].railml_id {
+                Some(id) => CString::new(format!("Route {} to {:?}", id, (il.routes[*idx].route).exit)).unwrap(),
+                None => CString::new(format!("Route to {:?}", (il.routes[*idx].route).exit)).unwrap(),
+            };
 
             if is_boundary {
                 if igBeginMenu(text.as_ptr(), true) {
@@ -155,7 +437,13 @@ pub fn route_selector(analysis :&mut Analysis, dispatch_view :&Option<DispatchVi
 
         }
         if !some {
-            widgets::show_text("No routes.");
+            widgets::show_text(i18n::tr("No routes."));
+        }
+        if let Ref::Object(pta) = thing {
+            for id in il.unmatched_railml_routes.get(&pta).into_iter().flatten() {
+                let text = CString::new(i18n::tr_fmt("Imported route {} (not found in layout)", &[id.as_str()])).unwrap();
+                igTextDisabled(const_cstr!("%s").as_ptr(), text.as_ptr());
+            }
         }
         igUnindent(14.0);
         action
@@ -173,7 +461,7 @@ pub fn add_plan_visit(analysis :&mut Analysis,
 
     unsafe {
         if let Some(DispatchView::Auto(AutoDispatchView { plan_idx, .. })) = &dispatch_view {
-            widgets::show_text("Add visit to plan");
+            widgets::show_text(i18n::tr("Add visit to plan"));
 
             if let Some(plan) = analysis.model().plans.get(*plan_idx) {
                 igIndent(14.0);
@@ -189,7 +477,7 @@ pub fn add_plan_visit(analysis :&mut Analysis,
             }
 
         } else {
-            if igSelectable(const_cstr!("Create new plan").as_ptr(), false, 0 as _, ImVec2::zero()) {
+            if igSelectable(tr!("Create new plan"), false, 0 as _, ImVec2::zero()) {
                 action = Some(None);
             }
         }
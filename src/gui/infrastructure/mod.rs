@@ -5,6 +5,7 @@ use const_cstr::*;
 use matches::matches;
 use backend_glfw::imgui::*;
 use nalgebra_glm as glm;
+use std::collections::HashSet;
 
 use crate::util;
 use crate::app::App;
@@ -20,6 +21,8 @@ use crate::document::objects::*;
 use crate::gui::widgets;
 use crate::gui::widgets::Draw;
 use crate::config::RailUIColorName;
+use crate::plugins::Plugins;
+use crate::i18n;
 
 
 #[derive(Copy,Clone,Debug)]
@@ -31,24 +34,33 @@ pub enum Highlight {
 pub fn inf_view(config :&Config, 
                 analysis :&mut Analysis,
                 inf_view :&mut InfView,
-                dispatch_view :&mut Option<DispatchView>) -> Draw {
+                dispatch_view :&mut Option<DispatchView>,
+                plugins :&Plugins) -> Draw {
     unsafe {
         let pos_before : ImVec2 = igGetCursorPos_nonUDT2().into();
 
         let size = igGetContentRegionAvail_nonUDT2().into();
+        inf_view.canvas_size = size;
         if inf_view.pending_fit_view {
             if let Some((min, max)) = model_bounds(analysis.model()) {
                 inf_view.view.fit_to_bounds(min, max, size);
             }
             inf_view.pending_fit_view = false;
         }
+        if let Some(pt) = inf_view.pending_center.take() {
+            inf_view.view.center_on(pt, size);
+        }
+        if let Some((min, max)) = inf_view.pending_fit_bounds.take() {
+            inf_view.view.fit_to_bounds(min, max, size);
+        }
         let draw = widgets::canvas(size,
                         config.color_u32(RailUIColorName::CanvasBackground),
                         const_cstr!("railwaycanvas").as_ptr());
         draw.begin_draw();
         scroll(inf_view);
+        level_crossing_tooltip(analysis, inf_view, &draw);
         let mut preview_route = None;
-        context_menu(analysis, inf_view, dispatch_view, &draw, &mut preview_route);
+        context_menu(config, analysis, inf_view, dispatch_view, plugins, &draw, &mut preview_route);
         interact(config, analysis, inf_view, &draw);
         draw_inf(config, analysis, inf_view, dispatch_view, &draw, preview_route);
         draw.end_draw();
@@ -56,12 +68,77 @@ pub fn inf_view(config :&Config,
         let pos_after = igGetCursorPos_nonUDT2().into();
         let framespace = igGetFrameHeightWithSpacing() - igGetFrameHeight();
         igSetCursorPos(pos_before + ImVec2 { x: 2.0*framespace, y: 2.0*framespace });
-        inf_toolbar(analysis, inf_view);
+        inf_toolbar(config, analysis, inf_view);
         igSetCursorPos(pos_after);
         draw
     }
 }
 
+/// Show the protection and road angle of the level crossing under the
+/// mouse.
+fn level_crossing_tooltip(analysis :&Analysis, inf_view :&InfView, draw :&Draw) {
+    unsafe {
+        if !igIsItemHovered(0) || !matches!(inf_view.action, Action::Normal(NormalState::Default)) { return; }
+        let model = analysis.model();
+        let pt = inf_view.view.screen_to_world_ptc(draw.mouse);
+        let pta = match model.get_closest_object(pt) {
+            Some(((pta, obj), d)) if d < 0.5*0.5 && obj.functions.contains(&Function::LevelCrossing) => *pta,
+            _ => return,
+        };
+        let (protection, angle) = level_crossing_data(model, pta);
+        let protection = protection.unwrap_or_else(|| i18n::tr("unknown").to_string());
+        igBeginTooltip();
+        widgets::show_text(i18n::tr("Level crossing"));
+        widgets::show_text(&i18n::tr_fmt("Protection: {}", &[&protection]));
+        if let Some(angle) = angle {
+            widgets::show_text(&i18n::tr_fmt("Angle: {}°", &[&format!("{:.0}", angle)]));
+        }
+        igEndTooltip();
+    }
+}
+
+/// Zoom the view to show the selected elements.
+pub fn zoom_to_selection(inf_view :&mut InfView) {
+    let mut pts = Vec::new();
+    for r in inf_view.selection.iter() {
+        match r {
+            Ref::LineSeg(a,b) => {
+                pts.push(glm::vec2(a.x as f32, a.y as f32));
+                pts.push(glm::vec2(b.x as f32, b.y as f32));
+            },
+            Ref::Node(p) => pts.push(glm::vec2(p.x as f32, p.y as f32)),
+            Ref::Object(p) => pts.push(unround_coord(*p)),
+        }
+    }
+    if pts.is_empty() { return; }
+    let margin = glm::vec2(1.0, 1.0);
+    let min = pts.iter().fold(pts[0], |m, p| glm::vec2(m.x.min(p.x), m.y.min(p.y)));
+    let max = pts.iter().fold(pts[0], |m, p| glm::vec2(m.x.max(p.x), m.y.max(p.y)));
+    inf_view.pending_fit_bounds = Some((min - margin, max + margin));
+}
+
+/// Save the current view in the document. Without a name, the bookmark is
+/// named after the station nearest to the middle of the view.
+pub fn add_view_bookmark(analysis :&mut Analysis, inf_view :&InfView, name :&str) {
+    let center = inf_view.view.screen_to_world_ptc(0.5 * inf_view.canvas_size);
+    let model = analysis.model();
+    let name = if !name.trim().is_empty() { name.trim().to_string() } else {
+        model.railml_ocps.iter()
+            .filter_map(|o| model.ocp_positions.get(&o.id).map(|p| (glm::distance2(p, &center), o)))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(_,o)| o.name.clone().unwrap_or_else(|| o.id.clone()))
+            .unwrap_or_else(|| i18n::tr_fmt("View {}", &[&(model.view_bookmarks.iter().count() + 1).to_string()]))
+    };
+    let bookmark = ViewBookmark { name, center, scale: inf_view.view.camera().0 };
+    analysis.edit_model(|m| { m.view_bookmarks.insert(bookmark); None });
+}
+
+pub fn go_to_view_bookmark(inf_view :&mut InfView, bookmark :&ViewBookmark) {
+    let (_, x, y) = inf_view.view.camera();
+    inf_view.view.set_camera((bookmark.scale, x, y));
+    inf_view.pending_center = Some(bookmark.center);
+}
+
 fn model_bounds(model: &Model) -> Option<(PtC, PtC)> {
     let mut min = glm::vec2(f32::INFINITY, f32::INFINITY);
     let mut max = glm::vec2(f32::NEG_INFINITY, f32::NEG_INFINITY);
@@ -114,6 +191,10 @@ fn draw_inf(config :&Config, analysis :&Analysis, inf_view :&mut InfView,
     };
 
     draw::base(config, analysis, inf_view, instant, dispatch_view, draw);
+    draw::ocps(config, analysis, inf_view, draw);
+    draw::annotations(config, analysis, inf_view, draw);
+    if config.show_signal_spacing { draw::signal_spacing(config, analysis, inf_view, draw); }
+    if config.show_catenary { draw::catenary(config, analysis, inf_view, draw); }
 
     if let Some(instant) = instant {
         draw::state(config, instant, inf_view, draw);
@@ -121,6 +202,14 @@ fn draw_inf(config :&Config, analysis :&Analysis, inf_view :&mut InfView,
     }
 
     if let Some(r) = preview_route { draw::route(config, analysis, inf_view, draw, r); }
+
+    if let Some((min, max, until)) = inf_view.highlight {
+        if unsafe { igGetTime() } < until {
+            draw::highlight_rect(config, inf_view, draw, min, max);
+        } else {
+            inf_view.highlight = None;
+        }
+    }
 }
 
 fn scroll(inf_view :&mut InfView) { 
@@ -148,6 +237,14 @@ fn interact(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView, dr
             let from = *from;
             interact_drawing(config, analysis, inf_view, draw, from); 
         },
+        Action::DrawingPolyline(pts) => {
+            let pts = pts.clone();
+            interact_polyline(config, analysis, inf_view, draw, pts);
+        },
+        Action::Erasing(segs) => {
+            let segs = segs.clone();
+            interact_erasing(config, analysis, inf_view, draw, segs);
+        },
         Action::InsertObject(obj) => { 
             let obj = obj.clone();
             interact_insert(config, analysis, inf_view, draw, obj); 
@@ -207,20 +304,93 @@ fn interact_normal(config :&Config, analysis :&mut Analysis,
                         inf_view.action = Action::Normal(NormalState::DragMove(MoveType::Grid(final_offset)));
                     }
 
-                    analysis.set_model(new_model, Some(EditClass::MoveObjects(initial_selection)));
+                    analysis.set_model_deferred(new_model, Some(EditClass::MoveObjects(initial_selection)));
                     analysis.override_edit_class(EditClass::MoveObjects(new_selection.clone()));
                     inf_view.selection = new_selection;
 
                 } else {
                     // Finalize movement
                     if let Some(_ghost) = inf_view.drag_ghost.take() {
-                        // Already updated in the last dragging frame
+                        // The model was updated in the last dragging frame,
+                        // but the derived data may still be pending.
+                        analysis.flush();
+                    }
+                    inf_view.action = Action::Normal(NormalState::Default);
+                }
+            },
+            NormalState::DragOcp(idx) => {
+                if igIsMouseDragging(0,-1.0) {
+                    let delta = inf_view.view.screen_to_world_ptc((*io).MouseDelta) -
+                                inf_view.view.screen_to_world_ptc(ImVec2 { x:0.0, y: 0.0 });
+                    let mut model = analysis.model().clone();
+                    if let Some(ocp) = model.railml_ocps.get(idx).map(|o| o.id.clone()) {
+                        if let Some(p) = model.ocp_positions.get_mut(&ocp) { *p += delta; }
+                        analysis.set_model_deferred(model, Some(EditClass::MoveOcp(idx)));
                     }
+                } else {
+                    analysis.flush();
+                    inf_view.action = Action::Normal(NormalState::Default);
+                }
+            },
+            NormalState::DragAnnotation(id, at_anchor) => {
+                if igIsMouseDragging(0,-1.0) {
+                    let delta = inf_view.view.screen_to_world_ptc((*io).MouseDelta) -
+                                inf_view.view.screen_to_world_ptc(ImVec2 { x:0.0, y: 0.0 });
+                    let mut model = analysis.model().clone();
+                    if let Some(a) = model.annotations.get_mut(id) {
+                        match &mut a.anchor {
+                            Some(AnnotationAnchor::Point(p)) if at_anchor => { *p += delta; },
+                            _ => { a.pos += delta; },
+                        }
+                        analysis.set_model_deferred(model, Some(EditClass::MoveAnnotation(id)));
+                    }
+                } else {
+                    analysis.flush();
+                    inf_view.action = Action::Normal(NormalState::Default);
+                }
+            },
+            NormalState::DragPlatformEdge(pta, at_end) => {
+                if igIsMouseDragging(0,-1.0) {
+                    let p = inf_view.view.screen_to_world_ptc(draw.mouse);
+                    let (model, new_pta) = drag_platform_edge(analysis.model(), pta, at_end, p);
+                    let single = |pta| -> std::collections::HashSet<Ref> { std::iter::once(Ref::Object(pta)).collect() };
+                    analysis.set_model_deferred(model, Some(EditClass::MoveObjects(single(pta))));
+                    analysis.override_edit_class(EditClass::MoveObjects(single(new_pta)));
+                    if new_pta != pta {
+                        inf_view.selection = single(new_pta);
+                        inf_view.action = Action::Normal(NormalState::DragPlatformEdge(new_pta, at_end));
+                    }
+                } else {
+                    analysis.flush();
                     inf_view.action = Action::Normal(NormalState::Default);
                 }
             },
             NormalState::Default => {
-                if !(*io).KeyCtrl && igIsItemHovered(0) && igIsMouseDragging(0,-1.0) {
+                let dragging = !(*io).KeyCtrl && igIsItemHovered(0) && igIsMouseDragging(0,-1.0);
+                let dragged_platform = if dragging {
+                    draw::platform_edge_handle_at(analysis, inf_view, (*io).MouseClickedPos[0] - draw.pos)
+                } else { None };
+                let dragged_ocp = if dragging && dragged_platform.is_none() {
+                    draw::ocp_at(analysis, inf_view, (*io).MouseClickedPos[0] - draw.pos)
+                } else { None };
+                let dragged_annotation = if dragging && dragged_ocp.is_none() {
+                    draw::annotation_at(analysis, inf_view, (*io).MouseClickedPos[0] - draw.pos)
+                } else { None };
+                if let Some((pta, at_end)) = dragged_platform {
+                    inf_view.action = Action::Normal(NormalState::DragPlatformEdge(pta, at_end));
+                } else if let Some(idx) = dragged_ocp {
+                    inf_view.selected_ocp = Some(idx);
+                    inf_view.selected_annotation = None;
+                    inf_view.selection.clear();
+                    inf_view.action = Action::Normal(NormalState::DragOcp(idx));
+                } else if let Some((id, at_anchor)) = dragged_annotation {
+                    inf_view.selected_ocp = None;
+                    inf_view.selected_annotation = Some(id);
+                    inf_view.selection.clear();
+                    inf_view.action = Action::Normal(NormalState::DragAnnotation(id, at_anchor));
+                } else if dragging {
+                    inf_view.selected_ocp = None;
+                    inf_view.selected_annotation = None;
                     if let Some((r,_)) = analysis.get_closest(
                             inf_view.view.screen_to_world_ptc(draw.mouse)) {
                         if !inf_view.selection.contains(&r) {
@@ -239,7 +409,13 @@ fn interact_normal(config :&Config, analysis :&mut Analysis,
                     }
                 } else {
                     if igIsItemHovered(0) && igIsMouseReleased(0) && !igIsMouseDragging(0, -1.0) {
-                        if let Some((r,_)) = analysis.get_closest(
+                        inf_view.selected_ocp = draw::ocp_at(analysis, inf_view, draw.mouse);
+                        inf_view.selected_annotation = if inf_view.selected_ocp.is_none() {
+                            draw::annotation_at(analysis, inf_view, draw.mouse).map(|(id,_)| id)
+                        } else { None };
+                        if inf_view.selected_ocp.is_some() || inf_view.selected_annotation.is_some() {
+                            inf_view.selection.clear();
+                        } else if let Some((r,_)) = analysis.get_closest(
                                 inf_view.view.screen_to_world_ptc(draw.mouse)) {
                             if (*io).KeyShift || (*io).KeyCtrl {
                                 if inf_view.selection.contains(&r) {
@@ -316,18 +492,25 @@ pub fn apply_move_selection(base_model: &Model, base_selection: &std::collection
 
     // 3. Update linesegs (DETACHMENT LOGIC)
     let mut new_linesegs = im::HashSet::new();
+    let mut seg_moves = Vec::new();
     for &(p1, p2) in base_model.linesegs.iter() {
         if base_selection.contains(&Ref::LineSeg(p1, p2)) {
             // This line is selected, it moves to its new endpoints
             let np1 = point_map.get(&p1).cloned().unwrap_or(p1);
             let np2 = point_map.get(&p2).cloned().unwrap_or(p2);
-            if np1 != np2 { new_linesegs.insert(util::order_ivec(np1, np2)); }
+            if np1 != np2 {
+                new_linesegs.insert(util::order_ivec(np1, np2));
+                seg_moves.push(((p1, p2), util::order_ivec(np1, np2)));
+            } else {
+                model.remove_lineseg_attrs(p1, p2);
+            }
         } else {
             // This line is NOT selected, it stays at its original position
             new_linesegs.insert((p1, p2));
         }
     }
     model.linesegs = new_linesegs;
+    model.move_lineseg_data(&seg_moves);
 
     // 4. Update objects
     let mut new_objects = im::HashMap::new();
@@ -355,6 +538,7 @@ pub fn apply_move_selection(base_model: &Model, base_selection: &std::collection
     }
 
     // Move selected node data
+    let mut node_moves = Vec::new();
     for &r in base_selection {
         if let Ref::Node(p) = r {
             if let Some(data) = base_model.node_data.get(&p) {
@@ -369,11 +553,13 @@ pub fn apply_move_selection(base_model: &Model, base_selection: &std::collection
                     // the USER wants the "Node" entity to move.
                     new_node_data = new_node_data.without(&p);
                     new_node_data.insert(np, data.clone());
+                    node_moves.push((p, np));
                 }
             }
         }
     }
     model.node_data = new_node_data;
+    model.move_trap_points(&node_moves);
 
     // 6. Update references in dispatches and plans
     for &r in base_selection {
@@ -386,32 +572,274 @@ pub fn apply_move_selection(base_model: &Model, base_selection: &std::collection
     for (a, b) in &changed_ptas {
         model_rename_object(&mut model, *a, *b);
     }
+    model.move_object_data(&changed_ptas);
 
     // 7. Update selection to reflect new coordinates
     let mut new_selection = std::collections::HashSet::new();
+    let mut renamed = std::collections::HashMap::new();
     for &r in base_selection {
-        match r {
+        let new_r = match r {
             Ref::Node(p) => { 
-                new_selection.insert(Ref::Node(*point_map.get(&p).unwrap_or(&p))); 
+                Ref::Node(*point_map.get(&p).unwrap_or(&p))
             }
             Ref::LineSeg(p1, p2) => {
                 let np1 = point_map.get(&p1).unwrap_or(&p1);
                 let np2 = point_map.get(&p2).unwrap_or(&p2);
-                new_selection.insert(Ref::LineSeg(*np1, *np2));
+                Ref::LineSeg(*np1, *np2)
             }
             Ref::Object(p) => {
                 let mut final_p = p;
                 for (old_pta, new_pta) in &changed_ptas {
                     if p == *old_pta { final_p = *new_pta; break; }
                 }
-                new_selection.insert(Ref::Object(final_p));
+                Ref::Object(final_p)
             }
-        }
+        };
+        new_selection.insert(new_r);
+        renamed.insert(r, new_r);
     }
+    model.rename_element_refs(&|r| renamed.get(&r).cloned());
     
     (model, new_selection)
 }
 
+/// Shortest platform edge, in meters, when changing the length by dragging.
+const MIN_PLATFORM_LENGTH :f64 = 10.0;
+
+/// Move an end of a platform edge to the point along the track nearest to
+/// `p`. Moving the start moves the object and keeps the end in place.
+/// Gives the new model and the new key of the platform edge.
+pub fn drag_platform_edge(base_model :&Model, pta :PtA, at_end :bool, p :PtC) -> (Model, PtA) {
+    let mut model = base_model.clone();
+    let obj = match model.objects.get(&pta) { Some(o) => o.clone(), None => return (model, pta) };
+    let (length, _) = platform_edge_data(&model, pta);
+    if at_end {
+        let length = platform_edge_param(&obj, p).max(MIN_PLATFORM_LENGTH);
+        if let RailMLObjectInfo::PlatformEdge { length: l, .. } = model.platform_edge_info_mut(pta) {
+            *l = Some(length);
+        }
+        return (model, pta);
+    }
+
+    let end = platform_edge_point(&obj, length);
+    let start = platform_edge_param(&obj, p).min(length - MIN_PLATFORM_LENGTH);
+    let mut moved = obj.clone();
+    if moved.move_to(&model, platform_edge_point(&obj, start)).is_some() { return (model, pta); }
+    let new_pta = round_coord(moved.loc);
+    let length = platform_edge_param(&moved, end);
+    if length < MIN_PLATFORM_LENGTH || (new_pta != pta && model.objects.contains_key(&new_pta)) {
+        return (model, pta);
+    }
+    model.objects.remove(&pta);
+    model.objects.insert(new_pta, moved);
+    if new_pta != pta {
+        model_rename_object(&mut model, pta, new_pta);
+        model.move_object_data(&[(pta, new_pta)]);
+        model.rename_element_refs(&|r| if r == Ref::Object(pta) { Some(Ref::Object(new_pta)) } else { None });
+    }
+    if let RailMLObjectInfo::PlatformEdge { length: l, .. } = model.platform_edge_info_mut(new_pta) {
+        *l = Some(length);
+    }
+    (model, new_pta)
+}
+
+/// Mirroring and rotation of the selection about the middle of its
+/// bounding box. The 45° rotations keep points on the grid by stretching:
+/// horizontal and vertical tracks keep their length, while diagonal tracks
+/// become twice as long.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transform {
+    MirrorHorizontal,
+    MirrorVertical,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Rotate45Clockwise,
+    Rotate45CounterClockwise,
+}
+
+impl Transform {
+    /// The linear part of the transform as a matrix `[[a, b], [c, d]]`.
+    fn matrix(&self) -> [[i32; 2]; 2] {
+        match self {
+            Transform::MirrorHorizontal => [[-1, 0], [0, 1]],
+            Transform::MirrorVertical => [[1, 0], [0, -1]],
+            Transform::RotateClockwise => [[0, 1], [-1, 0]],
+            Transform::RotateCounterClockwise => [[0, -1], [1, 0]],
+            Transform::Rotate45Clockwise => [[1, 1], [-1, 1]],
+            Transform::Rotate45CounterClockwise => [[1, -1], [1, 1]],
+        }
+    }
+
+    fn is_mirror(&self) -> bool {
+        matches!(self, Transform::MirrorHorizontal | Transform::MirrorVertical)
+    }
+
+    fn apply_vec(&self, v :PtC) -> PtC {
+        let m = self.matrix();
+        glm::vec2(m[0][0] as f32 * v.x + m[0][1] as f32 * v.y,
+                  m[1][0] as f32 * v.x + m[1][1] as f32 * v.y)
+    }
+}
+
+pub fn transform_selection(analysis: &mut Analysis, inf_view: &mut InfView, transform: Transform) {
+    if inf_view.selection.is_empty() { return; }
+    let (model, selection) = apply_transform_selection(analysis.model(), &inf_view.selection, transform);
+    inf_view.selection = selection;
+    analysis.set_model(model, None);
+}
+
+/// Mirror the selection horizontally (left to right) or vertically.
+pub fn apply_mirror_selection(base_model: &Model, base_selection: &std::collections::HashSet<Ref>, horizontal :bool) -> (Model, std::collections::HashSet<Ref>) {
+    let transform = if horizontal { Transform::MirrorHorizontal } else { Transform::MirrorVertical };
+    apply_transform_selection(base_model, base_selection, transform)
+}
+
+/// Mirror or rotate the selected elements. Like when moving, selected line
+/// segments are detached from unselected ones. Switches change side when
+/// mirrored, and signals stay on the right side of the track seen in their
+/// direction.
+pub fn apply_transform_selection(base_model: &Model, base_selection: &std::collections::HashSet<Ref>, transform: Transform) -> (Model, std::collections::HashSet<Ref>) {
+    // 1. The center is the middle of the selected tracks, or of the
+    // objects if no tracks are selected.
+    let mut points = Vec::new();
+    let mut object_points = Vec::new();
+    for &r in base_selection {
+        match r {
+            Ref::Node(p) => { points.push(glm::vec2(p.x as f32, p.y as f32)); }
+            Ref::LineSeg(p1, p2) => {
+                points.push(glm::vec2(p1.x as f32, p1.y as f32));
+                points.push(glm::vec2(p2.x as f32, p2.y as f32));
+            }
+            Ref::Object(pta) => {
+                if let Some(obj) = base_model.objects.get(&pta) { object_points.push(obj.loc); }
+            }
+        }
+    }
+    if points.is_empty() { points = object_points; }
+    if points.is_empty() { return (base_model.clone(), base_selection.clone()); }
+    let mut min = points[0];
+    let mut max = points[0];
+    for p in &points {
+        min = glm::vec2(min.x.min(p.x), min.y.min(p.y));
+        max = glm::vec2(max.x.max(p.x), max.y.max(p.y));
+    }
+    // Rotating about a point between grid points would move the tracks off
+    // the grid, so rotations use the nearest grid point.
+    let center = if transform.is_mirror() { (min + max) / 2.0 }
+                 else { glm::vec2(((min.x + max.x) / 2.0).round(), ((min.y + max.y) / 2.0).round()) };
+    let map_ptc = |p :PtC| center + transform.apply_vec(p - center);
+    let map_pt = |p :Pt| {
+        let q = map_ptc(glm::vec2(p.x as f32, p.y as f32));
+        glm::vec2(q.x.round() as i32, q.y.round() as i32)
+    };
+
+    let mut model = base_model.clone();
+    let mut point_map = std::collections::HashMap::new();
+    for &r in base_selection {
+        match r {
+            Ref::Node(p) => { point_map.insert(p, map_pt(p)); }
+            Ref::LineSeg(p1, p2) => {
+                point_map.insert(p1, map_pt(p1));
+                point_map.insert(p2, map_pt(p2));
+            }
+            _ => {}
+        }
+    }
+
+    // 2. Line segments
+    for &r in base_selection {
+        if let Ref::LineSeg(p1, p2) = r {
+            model.linesegs.remove(&(p1, p2));
+        }
+    }
+    let mut seg_moves = Vec::new();
+    for &r in base_selection {
+        if let Ref::LineSeg(p1, p2) = r {
+            let seg = util::order_ivec(point_map[&p1], point_map[&p2]);
+            model.linesegs.insert(seg);
+            seg_moves.push(((p1, p2), seg));
+        }
+    }
+    model.move_lineseg_data(&seg_moves);
+
+    // 3. Node data, removed first as the transform may swap nodes
+    let mut moved_nodes = Vec::new();
+    for &r in base_selection {
+        if let Ref::Node(p) = r {
+            if let Some(data) = model.node_data.remove(&p) {
+                moved_nodes.push((point_map[&p], data));
+            }
+        }
+    }
+    model.move_trap_points(&base_selection.iter().filter_map(|r| match r {
+        Ref::Node(p) => Some((*p, point_map[p])),
+        _ => None,
+    }).collect::<Vec<_>>());
+    for (p, mut data) in moved_nodes {
+        if transform.is_mirror() {
+            data = match data {
+                NDType::Sw(side) => NDType::Sw(side.opposite()),
+                NDType::Crossing(CrossingType::SingleSlip(side)) => NDType::Crossing(CrossingType::SingleSlip(side.opposite())),
+                x => x,
+            };
+        }
+        model.node_data.insert(p, data);
+    }
+
+    // 4. Objects
+    let mut object_map = std::collections::HashMap::new();
+    let mut moved_objects = Vec::new();
+    for &r in base_selection {
+        if let Ref::Object(pta) = r {
+            if let Some(mut obj) = model.objects.remove(&pta) {
+                let dir = glm::vec2(obj.tangent.x as f32, obj.tangent.y as f32);
+                let new_dir = transform.apply_vec(dir);
+                // The stretched 45° directions are shortened to unit steps.
+                let sign = |x :f32| if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 };
+                let new_tangent = glm::vec2(sign(new_dir.x), sign(new_dir.y));
+                let is_signal = obj.functions.iter().any(|f| matches!(f, Function::MainSignal { .. }));
+                obj.loc = if is_signal {
+                    // Signals are placed to the right of the track in their
+                    // direction, which mirroring would turn into the left.
+                    let right = |d :PtC| 0.25 * glm::vec2(d.y, -d.x);
+                    let on_track = obj.loc - right(dir);
+                    map_ptc(on_track) + right(new_tangent)
+                } else {
+                    map_ptc(obj.loc)
+                };
+                obj.tangent = glm::vec2(new_tangent.x as i32, new_tangent.y as i32);
+                let new_pta = round_coord(obj.loc);
+                object_map.insert(pta, new_pta);
+                moved_objects.push((new_pta, obj));
+            }
+        }
+    }
+    for (pta, obj) in moved_objects { model.objects.insert(pta, obj); }
+    model.move_object_data(&object_map.iter().map(|(a,b)| (*a, *b)).collect::<Vec<_>>());
+
+    // 5. References from dispatches and plans, renamed in one pass since
+    // the transform may swap elements.
+    model_rename_refs(&mut model, &|r| match r {
+        Ref::Node(p) => point_map.get(&p).map(|q| Ref::Node(*q)),
+        Ref::Object(p) => object_map.get(&p).map(|q| Ref::Object(*q)),
+        _ => None,
+    });
+
+    // 6. Selection and selection groups
+    let map_ref = |r :Ref| match r {
+        Ref::Node(p) => Ref::Node(point_map[&p]),
+        Ref::LineSeg(p1, p2) => {
+            let (a, b) = util::order_ivec(point_map[&p1], point_map[&p2]);
+            Ref::LineSeg(a, b)
+        },
+        Ref::Object(p) => Ref::Object(*object_map.get(&p).unwrap_or(&p)),
+    };
+    model.rename_element_refs(&|r| if base_selection.contains(&r) { Some(map_ref(r)) } else { None });
+    let selection = base_selection.iter().map(|r| map_ref(*r)).collect();
+
+    (model, selection)
+}
+
 fn interact_drawing(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView, 
                     draw :&Draw, from :Option<Pt>) {
     unsafe {
@@ -427,29 +855,101 @@ fn interact_drawing(config :&Config, analysis :&mut Analysis, inf_view :&mut Inf
 
             if !igIsMouseDown(0) {
                 if pt != pt_end {
-                    let mut new_model = analysis.model().clone();
-                    if let Some((p1,p2)) = is_boundary_extension(analysis, pt, pt_end) {
-                        model_rename_node(&mut new_model, p1, p2);
-                    }
-                    for (p1,p2) in util::route_line(pt,pt_end) {
-                        let unit = util::unit_step_diag_line(p1,p2);
-                        for (pa,pb) in unit.iter().zip(unit.iter().skip(1)) {
-                            new_model.linesegs.insert(util::order_ivec(*pa,*pb));
-                        }
-                    }
-                    analysis.set_model(new_model, None);
-                    inf_view.selection = std::iter::empty().collect();
+                    add_track(analysis, inf_view, &[pt, pt_end]);
+                    inf_view.action = Action::DrawingLine(None);
+                } else {
+                    // A click without dragging starts a track with bends.
+                    inf_view.action = Action::DrawingPolyline(vec![pt]);
                 }
-                inf_view.action = Action::DrawingLine(None);
             }
         } else {
-            if igIsItemHovered(0) && igIsMouseDown(0) {
+            // Only a new press starts a line, not the mouse still held
+            // after finishing a track with bends.
+            if igIsItemHovered(0) && igIsMouseClicked(0, false) {
                 inf_view.action = Action::DrawingLine(Some(pt_end));
             }
         }
     }
 }
 
+fn interact_polyline(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView,
+                     draw :&Draw, mut pts :Vec<Pt>) {
+    unsafe {
+        let color = config.color_u32(RailUIColorName::CanvasTrackDrawing);
+        let pt_end = inf_view.view.screen_to_world_pt(draw.mouse);
+        let last = *pts.last().unwrap();
+        for (a,b) in pts.iter().zip(pts.iter().skip(1)).chain(std::iter::once((&last,&pt_end))) {
+            for (p1,p2) in util::route_line(*a, *b) {
+                ImDrawList_AddLine(draw.draw_list, draw.pos + inf_view.view.world_pt_to_screen(p1),
+                                                   draw.pos + inf_view.view.world_pt_to_screen(p2),
+                                              color, 2.0);
+            }
+        }
+
+        let escape = igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_Escape as _), false);
+        let clicked = igIsItemHovered(0) && igIsMouseClicked(0, false);
+        if escape || (clicked && (igIsMouseDoubleClicked(0) || pt_end == last)) {
+            // The whole track is one edit, undone in one step.
+            if pts.len() > 1 { add_track(analysis, inf_view, &pts); }
+            inf_view.action = Action::DrawingLine(None);
+        } else if clicked {
+            pts.push(pt_end);
+            inf_view.action = Action::DrawingPolyline(pts);
+        }
+    }
+}
+
+/// Distance from the pointer, in grid units, within which the eraser
+/// catches line segments.
+const ERASER_RADIUS :f32 = 0.3;
+
+fn interact_erasing(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView,
+                    draw :&Draw, mut segs :HashSet<(Pt,Pt)>) {
+    unsafe {
+        let color = config.color_u32(RailUIColorName::CanvasNodeError);
+        for (p1,p2) in segs.iter() {
+            ImDrawList_AddLine(draw.draw_list, draw.pos + inf_view.view.world_pt_to_screen(*p1),
+                                               draw.pos + inf_view.view.world_pt_to_screen(*p2),
+                                          color, 4.0);
+        }
+
+        if igIsMouseDown(0) && (igIsItemHovered(0) || !segs.is_empty()) {
+            let pt = inf_view.view.screen_to_world_ptc(draw.mouse);
+            if let Some((l,_,(d,_))) = analysis.model().get_closest_lineseg(pt) {
+                if d < ERASER_RADIUS*ERASER_RADIUS { segs.insert(l); }
+            }
+            inf_view.action = Action::Erasing(segs);
+        } else if !segs.is_empty() {
+            // Everything erased in one drag is undone in one step.
+            let mut new_model = analysis.model().clone();
+            let erased = new_model.erase_linesegs(&segs);
+            new_model.remove_element_refs(&erased);
+            analysis.set_model(new_model, None);
+            inf_view.selection.retain(|r| !erased.contains(r));
+            inf_view.action = Action::Erasing(HashSet::new());
+        }
+    }
+}
+
+/// Add tracks routed through the given points as one model edit. Starting
+/// or ending at an open end extends the track there.
+fn add_track(analysis :&mut Analysis, inf_view :&mut InfView, pts :&[Pt]) {
+    let mut new_model = analysis.model().clone();
+    if let Some((p1,p2)) = is_boundary_extension(analysis, pts[0], pts[pts.len()-1]) {
+        model_rename_node(&mut new_model, p1, p2);
+    }
+    for (a,b) in pts.iter().zip(pts.iter().skip(1)) {
+        for (p1,p2) in util::route_line(*a,*b) {
+            let unit = util::unit_step_diag_line(p1,p2);
+            for (pa,pb) in unit.iter().zip(unit.iter().skip(1)) {
+                new_model.linesegs.insert(util::order_ivec(*pa,*pb));
+            }
+        }
+    }
+    analysis.set_model(new_model, None);
+    inf_view.selection = std::iter::empty().collect();
+}
+
 fn is_boundary_extension(analysis :&Analysis, p1 :Pt, p2 :Pt) -> Option<(Pt,Pt)> {
     let locs = &analysis.data().topology.as_ref()?.1.locations;
     match (locs.get(&p1), locs.get(&p2)) {
@@ -492,6 +992,60 @@ fn model_rename_node(model :&mut Model, a :Pt, b :Pt) {
     }
 }
 
+pub fn duplicate_selection(analysis: &mut Analysis, inf_view: &mut InfView, count :usize, offset :Pt) {
+    if inf_view.selection.is_empty() || count == 0 || offset == glm::zero() { return; }
+    let (model, selection) = apply_duplicate_selection(analysis.model(), &inf_view.selection, count, offset);
+    inf_view.selection = selection;
+    analysis.set_model(model, None);
+}
+
+/// Add `count` copies of the selected elements, the `i`th copy moved by
+/// `i` times `offset`, and return the model with the copies selected.
+/// Copied railML ids get a number appended, like when importing.
+pub fn apply_duplicate_selection(base_model: &Model, base_selection: &std::collections::HashSet<Ref>, count :usize, offset :Pt) -> (Model, std::collections::HashSet<Ref>) {
+    let part = crate::document::clipboard::selected_part(base_model, base_selection);
+    let mut model = base_model.clone();
+    let mut selection = std::collections::HashSet::new();
+    for i in 1..=(count as i32) {
+        let delta = offset * i;
+        let delta_a = round_coord(glm::vec2(delta.x as f32, delta.y as f32));
+        model.add_infrastructure(part.clone(), delta);
+        for r in base_selection {
+            selection.insert(match *r {
+                Ref::Node(p) => Ref::Node(p + delta),
+                Ref::LineSeg(p1, p2) => Ref::LineSeg(p1 + delta, p2 + delta),
+                Ref::Object(p) => Ref::Object(p + delta_a),
+            });
+        }
+    }
+    (model, selection)
+}
+
+/// Replace the node and object references in dispatches and plans for
+/// which `f` gives a new reference.
+fn model_rename_refs(model :&mut Model, f :&dyn Fn(Ref) -> Option<Ref>) {
+    for (_,dispatch) in model.dispatches.iter_mut() {
+        for (_,(_,command)) in dispatch.commands.iter_mut() {
+            match command {
+                Command::Train(_,r) | Command::Route(r) => {
+                    if let Some(x) = f(r.from) { r.from = x; }
+                    if let Some(x) = f(r.to) { r.to = x; }
+                }
+            };
+        }
+    }
+
+    for (_,p) in model.plans.iter_mut() {
+        for (_,(_veh, visits)) in p.trains.iter_mut() {
+            for (_,v) in visits.iter_mut() {
+                for l in v.locs.iter_mut() {
+                    if let Some(x) = l.as_ref().ok().and_then(|r| f(*r)) { *l = Ok(x); }
+                }
+            }
+        }
+    }
+}
+
 fn model_rename_object(model :&mut Model, a :PtA, b :PtA) {
     for (_,dispatch) in model.dispatches.iter_mut() {
         for (_,(_,command)) in dispatch.commands.iter_mut() {
@@ -526,7 +1080,9 @@ fn interact_insert(config :&Config, analysis :&mut Analysis,
                    inf_view :&InfView, draw :&Draw, obj :Option<Object>) {
     unsafe {
         if let Some(mut obj) = obj {
-            let moved = obj.move_to(analysis.model(),inf_view.view.screen_to_world_ptc(draw.mouse));
+            let mut pt = inf_view.view.screen_to_world_ptc(draw.mouse);
+            if config.grid.snap_objects { pt = snap_to_track(analysis.model(), pt, config.grid.minor); }
+            let moved = obj.move_to(analysis.model(), pt);
             obj.draw(draw.pos,&inf_view.view,draw.draw_list,
                      config.color_u32(RailUIColorName::CanvasSymbol),&[],&config);
 
@@ -548,7 +1104,7 @@ fn interact_insert(config :&Config, analysis :&mut Analysis,
     }
 }
 
-fn inf_toolbar(analysis :&mut Analysis, inf_view :&mut InfView) {
+fn inf_toolbar(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView) {
     unsafe  {
     if toolbar_button(
         const_cstr!("\u{f245}").as_ptr(), 
@@ -557,7 +1113,7 @@ fn inf_toolbar(analysis :&mut Analysis, inf_view :&mut InfView) {
     }
     if igIsItemHovered(0) {
         igBeginTooltip();
-        widgets::show_text("\u{f245} select (A)\nSelect tracks, nodes and objects. Drag to move.");
+        tool_tooltip(config, "\u{f245}", i18n::tr("select"), KeyCommand::SelectTool, i18n::tr("Select tracks, nodes and objects. Drag to move."));
         igEndTooltip();
     }
 
@@ -572,18 +1128,29 @@ fn inf_toolbar(analysis :&mut Analysis, inf_view :&mut InfView) {
     }
     if igIsItemHovered(0) {
         igBeginTooltip();
-        widgets::show_text("\u{f637} insert object (S)\nOpens a drop-down menu for selecting an object type.\nInsert the object by clicking a position.");
+        tool_tooltip(config, "\u{f637}", i18n::tr("insert object"), KeyCommand::InsertObjectTool, i18n::tr("Opens a drop-down menu for selecting an object type.\nInsert the object by clicking a position."));
         igEndTooltip();
     }
     igSameLine(0.0,-1.0);
 
     if toolbar_button(const_cstr!("\u{f303}").as_ptr(), 
-                      matches!(inf_view.action, Action::DrawingLine(_)), true ) {
+                      matches!(inf_view.action, Action::DrawingLine(_) | Action::DrawingPolyline(_)), true ) {
         inf_view.action = Action::DrawingLine(None);
     }
     if igIsItemHovered(0) {
         igBeginTooltip();
-        widgets::show_text("\u{f303} draw tracks (D)\nClick and drag to create new tracks.");
+        tool_tooltip(config, "\u{f303}", i18n::tr("draw tracks"), KeyCommand::DrawTool, i18n::tr("Click and drag to create new tracks.\nClick points to draw a track with bends, and double-click or press Esc to finish."));
+        igEndTooltip();
+    }
+    igSameLine(0.0,-1.0);
+
+    if toolbar_button(const_cstr!("\u{f12d}").as_ptr(),
+                      matches!(inf_view.action, Action::Erasing(_)), true ) {
+        inf_view.action = Action::Erasing(HashSet::new());
+    }
+    if igIsItemHovered(0) {
+        igBeginTooltip();
+        tool_tooltip(config, "\u{f12d}", i18n::tr("erase tracks"), KeyCommand::EraserTool, i18n::tr("Drag over tracks to delete them, with the objects on them."));
         igEndTooltip();
     }
     igSameLine(0.0,-1.0);
@@ -592,7 +1159,7 @@ fn inf_toolbar(analysis :&mut Analysis, inf_view :&mut InfView) {
     }
     if igIsItemHovered(0) {
         igBeginTooltip();
-        widgets::show_text("\u{f0e2} undo (CTRL-Z)\nUndo the previous action.");
+        tool_tooltip(config, "\u{f0e2}", i18n::tr("undo"), KeyCommand::Undo, i18n::tr("Undo the previous action."));
         igEndTooltip();
     }
     igSameLine(0.0,-1.0);
@@ -601,12 +1168,19 @@ fn inf_toolbar(analysis :&mut Analysis, inf_view :&mut InfView) {
     }
     if igIsItemHovered(0) {
         igBeginTooltip();
-        widgets::show_text("\u{f01e} redo (CTRL-Y)\nRedo the previously undone action.");
+        tool_tooltip(config, "\u{f01e}", i18n::tr("redo"), KeyCommand::Redo, i18n::tr("Redo the previously undone action."));
         igEndTooltip();
     }
     }
 }
 
+/// Tooltip for a toolbar button, showing its current keyboard shortcut.
+fn tool_tooltip(config :&Config, icon :&str, name :&str, cmd :KeyCommand, description :&str) {
+    let key = config.key_text(cmd);
+    let title = if key.is_empty() { format!("{} {}", icon, name) } else { format!("{} {} ({})", icon, name, key) };
+    widgets::show_text(&format!("{}\n{}", title, description));
+}
+
 fn toolbar_button(name :*const i8, selected :bool, enabled :bool) -> bool {
         unsafe {
         if selected {
@@ -634,6 +1208,49 @@ fn toolbar_button(name :*const i8, selected :bool, enabled :bool) -> bool {
     }
 }
 
+/// Name of an object type in the insert object tool.
+fn object_type_name(f :&Function) -> &'static str {
+    match f {
+        Function::MainSignal { kind: SignalKind::Main, .. } => "Main signal",
+        Function::MainSignal { kind: SignalKind::Combined, .. } => "Combined signal",
+        Function::MainSignal { kind: SignalKind::Distant, .. } => "Distant signal",
+        Function::MainSignal { kind: SignalKind::Repeater, .. } => "Repeater signal",
+        Function::MainSignal { kind: SignalKind::Shunting, .. } => "Shunting signal",
+        Function::Detector => "Detector",
+        Function::TrackCircuitBorder => "Track circuit border",
+        Function::Derailer => "Derailer",
+        Function::TrainProtectionElement => "Train protection element",
+        Function::TrainProtectionGroup => "Train protection group",
+        Function::Balise => "Balise",
+        Function::BaliseGroup => "Balise group",
+        Function::PlatformEdge => "Platform edge",
+        Function::SpeedChange => "Speed change",
+        Function::GradientChange => "Gradient change",
+        Function::RadiusChange => "Radius change",
+        Function::LevelCrossing => "Level crossing",
+        Function::CrossSection => "Cross section",
+    }
+}
+
+/// A selectable palette entry with the object's symbol in front of the
+/// name.
+fn object_type_item(f :&Function, selected :bool) -> bool {
+    unsafe {
+        let icon_width = 30.0;
+        let pos :ImVec2 = igGetCursorScreenPos_nonUDT2().into();
+        let h = igGetTextLineHeight();
+        let symbol = symbol_geometry(&[*f], glm::vec2(1,0));
+        let c = igGetColorU32(ImGuiCol__ImGuiCol_Text as _, 1.0);
+        for prim in symbol.iter() {
+            prim.draw(igGetWindowDrawList(), pos + ImVec2 { x: 8.0, y: 0.5*h }, c);
+        }
+        igDummy(ImVec2 { x: icon_width, y: h });
+        igSameLine(0.0,-1.0);
+        let name = std::ffi::CString::new(i18n::tr(object_type_name(f))).unwrap();
+        igSelectable(name.as_ptr(), selected, 0 as _, ImVec2::zero())
+    }
+}
+
 fn object_select(inf_view :&mut InfView) {
     unsafe {
         if matches!(&inf_view.action, Action::SelectObjectType) {
@@ -642,44 +1259,44 @@ fn object_select(inf_view :&mut InfView) {
         }
 
         if igBeginPopup(const_cstr!("osel").as_ptr(), 0 as _) {
-
-
-            if igSelectable(const_cstr!("Signal").as_ptr(), false, 0 as _, ImVec2::zero()) {
-                inf_view.action = Action::InsertObject(Some( 
-                        Object {
-                            loc: glm::vec2(0.0, 0.0),
-                            tangent: glm::vec2(1,0),
-                            functions: vec![Function::MainSignal { has_distant: false, kind: SignalKind::Main }],
-                        }
-                        ));
-            } 
-            if igSelectable(const_cstr!("Detector").as_ptr(), false, 0 as _, ImVec2::zero()) {
-                inf_view.action = Action::InsertObject(Some( 
-                        Object {
-                            loc: glm::vec2(0.0, 0.0),
-                            tangent: glm::vec2(1,0),
-                            functions: vec![Function::Detector],
-                        }
-                        ));
-            } 
+            let last = inf_view.last_object_type;
+            let mut chosen = None;
+            if object_type_item(&last, true) { chosen = Some(last); }
+            widgets::sep();
+            for (i,f) in OBJECT_TYPES.iter().enumerate() {
+                if *f == last { continue; }
+                igPushIDInt(i as _);
+                if object_type_item(f, false) { chosen = Some(*f); }
+                igPopID();
+            }
+            if let Some(f) = chosen {
+                inf_view.last_object_type = f;
+                inf_view.action = Action::InsertObject(Some(Object::with_function(f)));
+            }
 
             igEndPopup();
         }
     }
 }
 
-fn context_menu(analysis :&mut Analysis, 
+fn context_menu(config :&Config,
+                analysis :&mut Analysis, 
                 inf_view :&mut InfView,
                 dispatch_view :&mut Option<DispatchView>,
+                plugins :&Plugins,
                 draw :&Draw, preview_route :&mut Option<usize>) {
     unsafe {
     if igBeginPopup(const_cstr!("ctx").as_ptr(), 0 as _) {
-        context_menu_contents(analysis, inf_view, dispatch_view, preview_route);
+        context_menu_contents(config, analysis, inf_view, dispatch_view, plugins, preview_route);
         igEndPopup();
     }
 
     if igIsItemHovered(0) && igIsMouseClicked(1, false) {
-        if let Some((r,_)) = analysis.get_closest(inf_view.view.screen_to_world_ptc(draw.mouse)) {
+        inf_view.context_pos = inf_view.view.screen_to_world_ptc(draw.mouse);
+        inf_view.selected_annotation = draw::annotation_at(analysis, inf_view, draw.mouse).map(|(id,_)| id);
+        if inf_view.selected_annotation.is_some() {
+            inf_view.selection.clear();
+        } else if let Some((r,_)) = analysis.get_closest(inf_view.context_pos) {
             if !inf_view.selection.contains(&r) {
                 inf_view.selection = std::iter::once(r).collect();
             }
@@ -719,27 +1336,148 @@ fn selection_title(inf_view :&InfView) -> String {
     }
 }
 
-fn context_menu_contents(analysis :&mut Analysis, inf_view :&mut InfView,
+fn context_menu_contents(config :&Config, analysis :&mut Analysis, inf_view :&mut InfView,
                          dispatch_view :&mut Option<DispatchView>,
+                         plugins :&Plugins,
                          preview_route :&mut Option<usize>) {
     unsafe {
+    if let Some(id) = inf_view.selected_annotation {
+        if analysis.model().annotations.get(id).is_some() {
+            annotation_menu(analysis, inf_view, id);
+            return;
+        }
+    }
     widgets::show_text(&selection_title(inf_view));
 
     widgets::sep();
     if !inf_view.selection.is_empty() {
-        if igSelectable(const_cstr!("Delete").as_ptr(), false, 0 as _, ImVec2::zero()) {
+        if igSelectable(tr!("Delete"), false, 0 as _, ImVec2::zero()) {
             delete_selection(analysis, inf_view);
         }
+        if igBeginMenu(tr!("Transform"), true) {
+            for (label, transform) in &[
+                    (tr!("Mirror horizontally"), Transform::MirrorHorizontal),
+                    (tr!("Mirror vertically"), Transform::MirrorVertical),
+                    (tr!("Rotate 90° clockwise"), Transform::RotateClockwise),
+                    (tr!("Rotate 90° counterclockwise"), Transform::RotateCounterClockwise),
+                    (tr!("Rotate 45° clockwise"), Transform::Rotate45Clockwise),
+                    (tr!("Rotate 45° counterclockwise"), Transform::Rotate45CounterClockwise)] {
+                if igMenuItemBool(*label, std::ptr::null(), false, true) {
+                    transform_selection(analysis, inf_view, *transform);
+                }
+            }
+            igEndMenu();
+        }
+        if igBeginMenu(tr!("Duplicate"), true) {
+            igInputInt(tr!("Copies"), &mut inf_view.duplicate_count, 1, 5, 0 as _);
+            inf_view.duplicate_count = inf_view.duplicate_count.max(1);
+            igInputInt2(tr!("Offset"), inf_view.duplicate_offset.as_mut_ptr(), 0 as _);
+            let offset = glm::vec2(inf_view.duplicate_offset[0], inf_view.duplicate_offset[1]);
+            if igMenuItemBool(tr!("Duplicate selection"), std::ptr::null(), false, offset != glm::zero()) {
+                let count = inf_view.duplicate_count as usize;
+                duplicate_selection(analysis, inf_view, count, offset);
+            }
+            igEndMenu();
+        }
+    }
+    if igSelectable(tr!("Add note"), false, 0 as _, ImVec2::zero()) {
+        add_annotation(analysis, inf_view, false);
     }
+    if igSelectable(tr!("Add dimension"), false, 0 as _, ImVec2::zero()) {
+        add_annotation(analysis, inf_view, true);
+    }
+    plugin_actions(analysis, inf_view, plugins);
     widgets::sep();
     if inf_view.selection.len() == 1 {
         let thing = inf_view.selection.iter().nth(0).cloned().unwrap();
-        context_menu_single(analysis, dispatch_view ,thing,preview_route);
+        context_menu_single(config, analysis, dispatch_view ,thing,preview_route);
+    }
+    }
+}
+
+/// Add a note or dimension where the context menu was opened. A note on a
+/// single selected element is anchored to it.
+fn add_annotation(analysis :&mut Analysis, inf_view :&mut InfView, dimension :bool) {
+    let single = if inf_view.selection.len() == 1 { inf_view.selection.iter().next().cloned() } else { None };
+    let annotation = match single {
+        Some(r) if !dimension => Annotation {
+            text: i18n::tr("Note").to_string(),
+            pos: crate::document::lint::ref_pos(&r) + glm::vec2(1.0, 1.0),
+            anchor: Some(AnnotationAnchor::Element(r)),
+            dimension,
+        },
+        _ => Annotation {
+            text: if dimension { String::new() } else { i18n::tr("Note").to_string() },
+            pos: inf_view.context_pos,
+            anchor: if dimension { Some(AnnotationAnchor::Point(inf_view.context_pos + glm::vec2(4.0, 0.0))) } else { None },
+            dimension,
+        },
+    };
+    let mut id = None;
+    analysis.edit_model(|m| { id = Some(m.annotations.insert(annotation)); None });
+    inf_view.selected_annotation = id;
+    inf_view.selection.clear();
+}
+
+/// Context menu for editing the selected annotation.
+fn annotation_menu(analysis :&mut Analysis, inf_view :&mut InfView, id :usize) {
+    unsafe {
+    let mut a = analysis.model().annotations.get(id).cloned().unwrap();
+    widgets::show_text(i18n::tr(if a.dimension { "Dimension" } else { "Note" }));
+    widgets::sep();
+    if widgets::edit_text_multiline(const_cstr!("##annotation").as_ptr(), &mut a.text, ImVec2 { x: 250.0, y: 60.0 }) {
+        let text = a.text.clone();
+        analysis.edit_model(|m| {
+            if let Some(x) = m.annotations.get_mut(id) { x.text = text; }
+            Some(EditClass::AnnotationText(id))
+        });
+    }
+    if igCheckbox(tr!("Dimension line"), &mut a.dimension) {
+        if a.dimension && a.anchor.is_none() {
+            a.anchor = Some(AnnotationAnchor::Point(a.pos + glm::vec2(4.0, 0.0)));
+        }
+        analysis.edit_model(|m| { if let Some(x) = m.annotations.get_mut(id) { *x = a.clone(); } None });
+    }
+    if let Some(AnnotationAnchor::Element(_)) = a.anchor {
+        if igSelectable(tr!("Detach from element"), false, 0 as _, ImVec2::zero()) {
+            let anchor = a.anchor_pos().map(AnnotationAnchor::Point);
+            analysis.edit_model(|m| { if let Some(x) = m.annotations.get_mut(id) { x.anchor = anchor; } None });
+        }
+    }
+    if a.anchor.is_some() && !a.dimension {
+        if igSelectable(tr!("Remove leader line"), false, 0 as _, ImVec2::zero()) {
+            analysis.edit_model(|m| { if let Some(x) = m.annotations.get_mut(id) { x.anchor = None; } None });
+        }
+    }
+    if igSelectable(tr!("Delete annotation"), false, 0 as _, ImVec2::zero()) {
+        analysis.edit_model(|m| { m.annotations.remove(id); None });
+        inf_view.selected_annotation = None;
     }
     }
 }
 
-fn context_menu_single(analysis :&mut Analysis, 
+fn plugin_actions(analysis :&mut Analysis, inf_view :&mut InfView, plugins :&Plugins) {
+    unsafe {
+    for (i,plugin) in plugins.plugins.iter().enumerate() {
+        for (j,action) in plugin.info.actions.iter().enumerate() {
+            igPushIDInt((1000*i + j) as _);
+            let label = std::ffi::CString::new(action.as_str()).unwrap();
+            if igSelectable(label.as_ptr(), false, 0 as _, ImVec2::zero()) {
+                match plugin.action(action, analysis.model(), &inf_view.selection) {
+                    Ok((model, selection)) => {
+                        if let Some(m) = model { analysis.set_model(m, None); }
+                        if let Some(s) = selection { inf_view.selection = s; }
+                    },
+                    Err(e) => { log::error!("Plugin action {:?} failed: {}", action, e); },
+                }
+            }
+            igPopID();
+        }
+    }
+    }
+}
+
+fn context_menu_single(config :&Config, analysis :&mut Analysis, 
                        dispatch_view :&mut Option<DispatchView>,
                        thing :Ref, preview_route :&mut Option<usize>) {
 
@@ -751,7 +1489,7 @@ fn context_menu_single(analysis :&mut Analysis,
 
     // Object editor
     if let Ref::Object(pta) = thing { 
-        menus::object_menu(analysis, pta);
+        menus::object_menu(config, analysis, pta);
         widgets::sep();
     }
 
@@ -769,6 +1507,7 @@ fn context_menu_single(analysis :&mut Analysis,
 
 pub fn delete_selection(analysis :&mut Analysis, inf_view :&mut InfView) {
     let mut new_model = analysis.model().clone();
+    new_model.remove_element_refs(&inf_view.selection);
     for x in inf_view.selection.drain() {
         new_model.delete(x);
     }
@@ -798,15 +1537,183 @@ fn start_route(analysis :&mut Analysis, dispatch_view :&mut Option<DispatchView>
 }
 
 fn dispatch_view_ref(dispatch_view :&Option<DispatchView>) -> Option<DispatchRef> {
-    match dispatch_view {
-        Some(DispatchView::Manual(ManualDispatchView { dispatch_idx, time, .. })) => {
-           Some((Ok(*dispatch_idx),*time as _))
-        },
-        Some(DispatchView::Auto(AutoDispatchView { plan_idx,
-            dispatch: Some(ManualDispatchView { dispatch_idx, time, .. }), .. })) => {
-           Some((Err((*plan_idx, *dispatch_idx)), *time as _))
-        },
-        _ => { return None; },
-    }
+    dispatch_view.as_ref().and_then(|d| d.dispatch_ref())
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_are_reversible() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(4,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        model.linesegs.insert((glm::vec2(2,1), glm::vec2(3,2)));
+        model.node_data.insert(glm::vec2(1,0), NDType::Sw(Side::Left));
+        let signal = Object { loc: glm::vec2(2.5, -0.25), tangent: glm::vec2(1,0),
+                              functions: vec![Function::MainSignal { has_distant: false, kind: SignalKind::Main }] };
+        model.objects.insert(round_coord(signal.loc), signal);
+        let selection = model.linesegs.iter().map(|(a,b)| Ref::LineSeg(*a,*b))
+            .chain(model.node_data.keys().map(|p| Ref::Node(*p)))
+            .chain(model.objects.keys().map(|p| Ref::Object(*p))).collect();
+
+        let (mirrored, mirrored_selection) = apply_mirror_selection(&model, &selection, true);
+        assert!(mirrored.linesegs.contains(&(glm::vec2(2,1), glm::vec2(3,0))));
+        assert!(mirrored.linesegs.contains(&(glm::vec2(1,2), glm::vec2(2,1))));
+        assert_eq!(mirrored.node_data.get(&glm::vec2(3,0)), Some(&NDType::Sw(Side::Right)));
+        let obj = mirrored.objects.values().next().unwrap();
+        assert_eq!(obj.tangent, glm::vec2(-1,0));
+        assert!(obj.loc.y > 0.0);
+        let (back, _) = apply_mirror_selection(&mirrored, &mirrored_selection, true);
+        assert_eq!(back.linesegs, model.linesegs);
+        assert_eq!(back.node_data, model.node_data);
+        assert_eq!(back.objects.keys().collect::<Vec<_>>(), model.objects.keys().collect::<Vec<_>>());
+
+        let mut rotated = (model.clone(), selection);
+        for _ in 0..4 {
+            rotated = apply_transform_selection(&rotated.0, &rotated.1, Transform::RotateClockwise);
+        }
+        assert_eq!(rotated.0.linesegs, model.linesegs);
+        assert_eq!(rotated.0.node_data, model.node_data);
+    }
+
+    #[test]
+    fn selection_groups_follow_moved_elements() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(2,0), glm::vec2(4,0)));
+        let moved = Ref::LineSeg(glm::vec2(2,0), glm::vec2(4,0));
+        let kept = Ref::LineSeg(glm::vec2(0,0), glm::vec2(2,0));
+        model.selection_groups.insert(SelectionGroup { name: "a".to_string(), refs: vec![kept, moved] });
+
+        let selection = vec![moved].into_iter().collect();
+        let (model, _) = apply_move_selection(&model, &selection, glm::vec2(0.0, 1.0));
+        let group = model.selection_groups.iter().next().unwrap().1;
+        assert_eq!(group.refs, vec![kept, Ref::LineSeg(glm::vec2(2,1), glm::vec2(4,1))]);
+        assert_eq!(model.group_selection(group).len(), 2);
+    }
+
+    #[test]
+    fn lineseg_attributes_follow_moved_segments() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(2,0), glm::vec2(4,0)));
+        let attrs = LineSegAttributes { length: Some(120.0), ..Default::default() };
+        model.lineseg_attributes.insert((glm::vec2(2,0), glm::vec2(4,0)), attrs.clone());
+
+        let selection = vec![Ref::LineSeg(glm::vec2(2,0), glm::vec2(4,0))].into_iter().collect();
+        let (model, selection) = apply_move_selection(&model, &selection, glm::vec2(0.0, 1.0));
+        assert_eq!(model.lineseg_attributes.len(), 1);
+        assert_eq!(model.lineseg_attrs(glm::vec2(2,1), glm::vec2(4,1)), Some(&attrs));
+
+        let (mut model, _) = apply_transform_selection(&model, &selection, Transform::MirrorHorizontal);
+        assert_eq!(model.lineseg_attributes.len(), 1);
+        assert_eq!(model.lineseg_attrs(glm::vec2(2,1), glm::vec2(4,1)), Some(&attrs));
+
+        model.erase_linesegs(&std::iter::once((glm::vec2(2,1), glm::vec2(4,1))).collect());
+        assert!(model.lineseg_attributes.is_empty());
+    }
+
+    #[test]
+    fn trap_points_follow_moved_switches() {
+        let mut model = Model::empty();
+        for (a,b) in vec![((0,0),(2,0)), ((2,0),(4,0)), ((2,0),(3,1))] {
+            model.linesegs.insert((glm::vec2(a.0,a.1), glm::vec2(b.0,b.1)));
+        }
+        model.node_data.insert(glm::vec2(2,0), NDType::Sw(Side::Left));
+        model.trap_points.insert(glm::vec2(2,0));
+        let selection = model.linesegs.iter().map(|(a,b)| Ref::LineSeg(*a,*b))
+            .chain(std::iter::once(Ref::Node(glm::vec2(2,0)))).collect();
+
+        let (model, selection) = apply_move_selection(&model, &selection, glm::vec2(0.0, 1.0));
+        assert_eq!(model.trap_points.iter().cloned().collect::<Vec<_>>(), vec![glm::vec2(2,1)]);
+
+        let (model, _) = apply_transform_selection(&model, &selection, Transform::MirrorHorizontal);
+        let sw = model.node_data.iter().find(|(_,nd)| matches!(nd, NDType::Sw(_))).map(|(p,_)| *p);
+        assert_eq!(model.trap_points.iter().cloned().collect::<Vec<_>>(), sw.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn annotations_follow_their_elements() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(2,0)));
+        let seg = Ref::LineSeg(glm::vec2(0,0), glm::vec2(2,0));
+        let note = Annotation { text: "x".to_string(), pos: glm::vec2(1.0, 1.0),
+                                anchor: Some(AnnotationAnchor::Element(seg)), dimension: false };
+        let id = model.annotations.insert(note);
+
+        let selection = vec![seg].into_iter().collect();
+        let (mut model, selection) = apply_move_selection(&model, &selection, glm::vec2(0.0, 2.0));
+        let moved = Ref::LineSeg(glm::vec2(0,2), glm::vec2(2,2));
+        assert_eq!(model.annotations.get(id).unwrap().anchor, Some(AnnotationAnchor::Element(moved)));
+
+        model.remove_element_refs(&selection);
+        assert_eq!(model.annotations.get(id).unwrap().anchor, Some(AnnotationAnchor::Point(glm::vec2(1.0, 2.0))));
+    }
+
+    #[test]
+    fn duplicates_are_offset_and_selected() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(2,0)));
+        let detector = Object { loc: glm::vec2(1.0, 0.0), tangent: glm::vec2(1,0), functions: vec![Function::Detector] };
+        model.objects.insert(round_coord(detector.loc), detector);
+        let selection = model.linesegs.iter().map(|(a,b)| Ref::LineSeg(*a,*b))
+            .chain(model.objects.keys().map(|p| Ref::Object(*p))).collect();
+
+        let (copied, copied_selection) = apply_duplicate_selection(&model, &selection, 2, glm::vec2(0,-2));
+        assert_eq!(copied.linesegs.len(), 3);
+        assert!(copied.linesegs.contains(&(glm::vec2(0,-4), glm::vec2(2,-4))));
+        assert_eq!(copied.objects.len(), 3);
+        assert_eq!(copied_selection.len(), 4);
+        assert!(copied_selection.contains(&Ref::Object(round_coord(glm::vec2(1.0, -2.0)))));
+        for (pta, obj) in copied.objects.iter() {
+            assert_eq!(*pta, round_coord(obj.loc));
+        }
+    }
+
+    #[test]
+    fn platform_edge_ends_change_length() {
+        let mut model = Model::empty();
+        for x in 0..10 { model.linesegs.insert((glm::vec2(x,0), glm::vec2(x+1,0))); }
+        let platform = Object { loc: glm::vec2(2.0, 0.0), tangent: glm::vec2(1,0), functions: vec![Function::PlatformEdge] };
+        let pta = round_coord(platform.loc);
+        model.objects.insert(pta, platform);
+
+        // Dragging the end keeps the start.
+        let (model, same) = drag_platform_edge(&model, pta, true, glm::vec2(7.0, 0.4));
+        assert_eq!(same, pta);
+        assert_eq!(platform_edge_data(&model, pta).0, 250.0);
+
+        // Dragging the start moves the object with its railML data.
+        let (model, moved) = drag_platform_edge(&model, pta, false, glm::vec2(4.0, 0.4));
+        assert_eq!(moved, round_coord(glm::vec2(4.0, 0.0)));
+        assert!(!model.objects.contains_key(&pta) && !model.railml_objects.contains_key(&pta));
+        assert_eq!(platform_edge_data(&model, moved).0, 150.0);
+        let (_, end) = platform_edge_bar(&model, moved, model.objects.get(&moved).unwrap());
+        assert!((end.x - 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn erasing_removes_orphaned_data() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        model.node_data.insert(glm::vec2(1,0), NDType::Sw(Side::Left));
+        model.node_data.insert(glm::vec2(2,1), NDType::BufferStop);
+        model.node_data.insert(glm::vec2(2,0), NDType::BufferStop);
+        model.trap_points.insert(glm::vec2(1,0));
+        let obj = Object { loc: glm::vec2(1.5, 0.5), tangent: glm::vec2(1,1), functions: vec![Function::Detector] };
+        model.objects.insert(round_coord(obj.loc), obj);
+
+        let erased = model.erase_linesegs(&std::iter::once((glm::vec2(1,0), glm::vec2(2,1))).collect());
+        assert_eq!(erased.len(), 4);
+        assert!(erased.contains(&Ref::Object(round_coord(glm::vec2(1.5, 0.5)))));
+        assert_eq!(model.linesegs.len(), 2);
+        assert!(model.objects.is_empty() && model.trap_points.is_empty());
+        // The buffer stop at the other end of the remaining track is kept.
+        assert_eq!(model.node_data.len(), 1);
+    }
+}
@@ -1,157 +1,138 @@
 use crate::app::{App, PendingAction};
-use crate::document::objects::*;
+use crate::config::{Config, Key, KeyCommand};
 use crate::document::infview::*;
 use crate::gui::infrastructure;
 use crate::gui::mainmenu;
-use crate::util;
-use crate::file;
-use crate::document::{Document, model::Ref, DispatchView, AutoDispatchView};
+use crate::document::{Document, clipboard, model::{Pt, Ref}, DispatchView, AutoDispatchView};
 
-use log::*;
 use backend_glfw::imgui::*;
+use log::*;
 use nalgebra_glm as glm;
 use std::collections::HashSet;
+use std::ffi::{CStr, CString};
 
-pub fn keys(app :&mut App) {
+pub fn select_all(doc :&mut Document) {
+    let mut selection = HashSet::new();
+    let model = doc.analysis.model();
+    for l in &model.linesegs { selection.insert(Ref::LineSeg(l.0, l.1)); }
+    for pt in model.node_data.keys() { selection.insert(Ref::Node(*pt)); }
+    for pta in model.objects.keys() { selection.insert(Ref::Object(*pta)); }
+    doc.inf_view.selection = selection;
+}
+
+/// Copy the selection to the clipboard, and to the system clipboard for
+/// pasting into other instances of the program.
+pub fn copy(app :&mut App) {
+    app.clipboard = clipboard::copy(app.document.analysis.model(), &app.document.inf_view.selection);
+    match clipboard::to_text(&app.clipboard).map(CString::new) {
+        Ok(Ok(text)) => unsafe { igSetClipboardText(text.as_ptr()); },
+        Ok(Err(e)) => { error!("Error copying selection: {}", e); },
+        Err(e) => { error!("Error copying selection: {}", e); },
+    }
+}
+
+/// Paste the clipboard with its middle at the grid point `at`, and select
+/// the pasted elements. A part copied in another instance of the program is
+/// taken from the system clipboard.
+pub fn paste(app :&mut App, at :Pt) {
+    let text = unsafe {
+        let ptr = igGetClipboardText();
+        if ptr.is_null() { None } else { Some(CStr::from_ptr(ptr).to_string_lossy().into_owned()) }
+    };
+    let clip = text.and_then(|t| clipboard::from_text(&t)).unwrap_or_else(|| app.clipboard.clone());
+    if clip.linesegs.is_empty() && clip.node_data.is_empty() && clip.objects.is_empty() { return; }
+    let mut selection = HashSet::new();
+    app.document.analysis.edit_model(|m| {
+        selection = clipboard::paste(m, &clip, at);
+        None
+    });
+    app.document.inf_view.selection = selection;
+}
+
+/// The key binding of the command was pressed this frame. Keys without
+/// Ctrl are ignored while a text field or other widget is active.
+pub fn pressed(config :&Config, cmd :KeyCommand) -> bool {
+    let binding = match config.keys[cmd] { Some(b) => b, None => return false };
     unsafe {
         let io = igGetIO();
+        if (*io).KeyCtrl != binding.ctrl || (*io).KeyShift != binding.shift { return false; }
+        if !binding.ctrl && igIsAnyItemActive() { return false; }
+        let idx = match binding.key {
+            Key::Char(c) => c as i32,
+            Key::Space => ' ' as i32,
+            Key::Delete => igGetKeyIndex(ImGuiKey__ImGuiKey_Delete as _),
+        };
+        igIsKeyPressed(idx, false)
+    }
+}
 
+pub fn keys(app :&mut App) {
+    unsafe {
+        // The command palette takes the keyboard while it is open.
+        if app.windows.palette.open { return; }
+        if pressed(&app.config, KeyCommand::CommandPalette) {
+            app.windows.palette.show();
+            return;
+        }
+        if pressed(&app.config, KeyCommand::Find) {
+            app.windows.search.show();
+        }
+        if pressed(&app.config, KeyCommand::FitView) {
+            app.document.inf_view.pending_fit_view = true;
+        }
+        if pressed(&app.config, KeyCommand::ZoomToSelection) {
+            infrastructure::zoom_to_selection(&mut app.document.inf_view);
+        }
 
-        if (*io).KeyCtrl && !(*io).KeyShift && igIsKeyPressed('Z' as _, false) {
+        if pressed(&app.config, KeyCommand::Undo) {
             app.document.analysis.undo();
         }
-        if (*io).KeyCtrl && (*io).KeyShift && igIsKeyPressed('Z' as _, false) {
-            app.document.analysis.redo();
-        }
-        if (*io).KeyCtrl && !(*io).KeyShift && igIsKeyPressed('Y' as _, false) {
+        if pressed(&app.config, KeyCommand::Redo) || pressed(&app.config, KeyCommand::RedoAlternative) {
             app.document.analysis.redo();
         }
 
-        if (*io).KeyCtrl && igIsKeyPressed('S' as _, false) {
-            match (&app.document.fileinfo.filename, (*io).KeyShift) {
-                (None,_) | (_,true) => {
-                    match file::save_interactive(app.document.analysis.model().clone()) {
-                        Err(e) => { error!("Error saving file: {}", e); },
-                        Ok(Some(filename)) => { app.document.set_saved_file(filename); },
-                        _ => {},
-                    }
-                }
-                (Some(filename),_) => {
-                    match file::save(filename, app.document.analysis.model().clone()) {
-                        Err(e) => { error!("Error saving file: {}", e); },
-                        Ok(()) => { app.document.set_saved_file(filename.clone()); },
-                        _ => {},
-                    }
-                },
-            }
+        if pressed(&app.config, KeyCommand::Save) {
+            mainmenu::save(app, false);
+        }
+        if pressed(&app.config, KeyCommand::SaveAs) {
+            mainmenu::save(app, true);
         }
 
-        if (*io).KeyCtrl && !(*io).KeyShift && igIsKeyPressed('O' as _, false) {
+        if pressed(&app.config, KeyCommand::Open) {
             app.windows.pending_action = Some(PendingAction::Load);
         }
 
-        if (*io).KeyCtrl && igIsKeyPressed('A' as _, false) {
-            let mut selection = HashSet::new();
-            let model = app.document.analysis.model();
-            for l in &model.linesegs { selection.insert(Ref::LineSeg(l.0, l.1)); }
-            for pt in model.node_data.keys() { selection.insert(Ref::Node(*pt)); }
-            for pta in model.objects.keys() { selection.insert(Ref::Object(*pta)); }
-            app.document.inf_view.selection = selection;
-        }
-
-        if (*io).KeyCtrl && igIsKeyPressed('C' as _, false) {
-            let inf_view = &mut app.document.inf_view;
-            let model = app.document.analysis.model();
-            inf_view.clipboard = crate::document::model::Model::empty();
-            let mut node_set = HashSet::new();
-            for r in &inf_view.selection {
-                match r {
-                    Ref::Node(p) => { 
-                        if let Some(data) = model.node_data.get(p) {
-                            inf_view.clipboard.node_data.insert(*p, data.clone());
-                            node_set.insert(*p);
-                        }
-                    }
-                    Ref::LineSeg(p1, p2) => {
-                        inf_view.clipboard.linesegs.insert((*p1, *p2));
-                        node_set.insert(*p1);
-                        node_set.insert(*p2);
-                    }
-                    Ref::Object(pta) => {
-                        if let Some(obj) = model.objects.get(pta) {
-                            inf_view.clipboard.objects.insert(*pta, obj.clone());
-                        }
-                    }
-                }
-            }
-            // Ensure all required nodes for linesegs/objects are in node_data
-            for p in node_set {
-                if !inf_view.clipboard.node_data.contains_key(&p) {
-                    if let Some(data) = model.node_data.get(&p) {
-                        inf_view.clipboard.node_data.insert(p, data.clone());
-                    }
-                }
-            }
+        if pressed(&app.config, KeyCommand::SelectAll) {
+            select_all(&mut app.document);
+        }
+
+        if pressed(&app.config, KeyCommand::Copy) {
+            copy(app);
         }
 
-        if (*io).KeyCtrl && igIsKeyPressed('V' as _, false) {
+        if pressed(&app.config, KeyCommand::Paste) {
             let mouse_world = app.document.inf_view.view.screen_to_world_ptc(igGetMousePos_nonUDT2().into());
-            let clipboard = app.document.inf_view.clipboard.clone();
-            
-            // Calculate center of clipboard
-            let mut pts = Vec::new();
-            for p in clipboard.node_data.keys() { pts.push(glm::vec2(p.x as f32, p.y as f32)); }
-            for obj in clipboard.objects.values() { pts.push(obj.loc); }
-            
-            if !pts.is_empty() {
-                let mut avg_loc = glm::vec2(0.0, 0.0);
-                for p in &pts { avg_loc += *p; }
-                avg_loc /= pts.len() as f32;
-                
-                let delta = mouse_world - avg_loc;
-                let grid_delta = glm::vec2(delta.x.round(), delta.y.round());
-                
-                let mut new_selection = HashSet::new();
-                app.document.analysis.edit_model(|m| {
-                    let mut node_map = std::collections::HashMap::new();
-                    
-                    // 1. Nodes
-                    for (p, data) in clipboard.node_data.iter() {
-                        let np = glm::vec2(p.x + grid_delta.x as i32, p.y + grid_delta.y as i32);
-                        m.node_data.insert(np, data.clone());
-                        node_map.insert(*p, np);
-                        new_selection.insert(Ref::Node(np));
-                    }
-                    
-                    // 2. Linesegs
-                    for (p1, p2) in clipboard.linesegs.iter() {
-                        let np1 = node_map.get(p1).cloned().unwrap_or(glm::vec2(p1.x + grid_delta.x as i32, p1.y + grid_delta.y as i32));
-                        let np2 = node_map.get(p2).cloned().unwrap_or(glm::vec2(p2.x + grid_delta.x as i32, p2.y + grid_delta.y as i32));
-                        m.linesegs.insert(util::order_ivec(np1, np2));
-                        new_selection.insert(Ref::LineSeg(np1, np2));
-                    }
-                    
-                    // 3. Objects
-                    for obj in clipboard.objects.values() {
-                        let mut obj = obj.clone();
-                        obj.loc += grid_delta;
-                        let npta = round_coord(obj.loc);
-                        m.objects.insert(npta, obj);
-                        new_selection.insert(Ref::Object(npta));
-                    }
-                    
-                    None
-                });
-                app.document.inf_view.selection = new_selection;
-            }
+            paste(app, glm::vec2(mouse_world.x.round() as i32, mouse_world.y.round() as i32));
         }
 
-        if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_Delete as _), false) {
+        if pressed(&app.config, KeyCommand::Delete) {
             infrastructure::delete_selection(&mut app.document.analysis, &mut app.document.inf_view);
         }
 
-        // Keyboard Movement (Arrow Keys)
-        if !app.document.inf_view.selection.is_empty() {
+        for (cmd, transform) in &[
+                (KeyCommand::MirrorHorizontal, infrastructure::Transform::MirrorHorizontal),
+                (KeyCommand::MirrorVertical, infrastructure::Transform::MirrorVertical),
+                (KeyCommand::RotateClockwise, infrastructure::Transform::RotateClockwise),
+                (KeyCommand::RotateCounterClockwise, infrastructure::Transform::RotateCounterClockwise),
+                (KeyCommand::Rotate45Clockwise, infrastructure::Transform::Rotate45Clockwise),
+                (KeyCommand::Rotate45CounterClockwise, infrastructure::Transform::Rotate45CounterClockwise)] {
+            if pressed(&app.config, *cmd) {
+                infrastructure::transform_selection(&mut app.document.analysis, &mut app.document.inf_view, *transform);
+            }
+        }
+
+        // Keyboard Movement (Arrow Keys), unless the object tree has the keyboard.
+        if !app.document.inf_view.selection.is_empty() && !app.windows.object_tree.focused {
             let mut delta = glm::vec2(0.0, 0.0);
             if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_LeftArrow as _), true) { delta.x -= 1.0; }
             if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_RightArrow as _), true) { delta.x += 1.0; }
@@ -163,26 +144,28 @@ pub fn keys(app :&mut App) {
             }
         }
 
-        if !igIsAnyItemActive() {
-            if igIsKeyPressed('A' as _, false) {
-                app.document.inf_view.action = Action::Normal(NormalState::Default);
-            }
+        if pressed(&app.config, KeyCommand::SelectTool) {
+            app.document.inf_view.action = Action::Normal(NormalState::Default);
+        }
 
-            if igIsKeyPressed(' ' as _, false) {
-                if let Some(DispatchView::Manual(m)) 
-                     | Some(DispatchView::Auto(AutoDispatchView { dispatch: Some(m), .. })) 
-                         = &mut app.document.dispatch_view {
-                    m.play = !m.play;
-                }
+        if pressed(&app.config, KeyCommand::PlayPause) && !app.windows.object_tree.focused {
+            if let Some(DispatchView::Manual(m)) 
+                 | Some(DispatchView::Auto(AutoDispatchView { dispatch: Some(m), .. })) 
+                     = &mut app.document.dispatch_view {
+                m.play = !m.play;
             }
+        }
 
-            if igIsKeyPressed('D' as _, false) {
-                app.document.inf_view.action = Action::DrawingLine(None);
-            }
+        if pressed(&app.config, KeyCommand::DrawTool) {
+            app.document.inf_view.action = Action::DrawingLine(None);
+        }
 
-            if igIsKeyPressed('S' as _, false) {
-                app.document.inf_view.action = Action::SelectObjectType;
-            }
+        if pressed(&app.config, KeyCommand::InsertObjectTool) {
+            app.document.inf_view.action = Action::SelectObjectType;
+        }
+
+        if pressed(&app.config, KeyCommand::EraserTool) {
+            app.document.inf_view.action = Action::Erasing(Default::default());
         }
     }
 }
@@ -7,13 +7,37 @@ use crate::document::Document;
 use crate::gui;
 use crate::file;
 use crate::export;
+use crate::route_table;
 use crate::gui::widgets;
+use crate::config::{KeyCommand, RecentFileKind};
+use std::ffi::CString;
+use crate::i18n;
 
 pub fn load(app :&mut App) {
     match file::load_interactive() {
-        Ok(Some((m, filename))) => {
+        Ok(Some((m, history, filename))) => {
             info!("Loading model from file succeeded.");
-            app.document = Document::from_model(m, app.background_jobs.clone());
+            app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+            app.document = Document::from_saved(m, history, app.background_jobs.clone());
+            app.document.fileinfo.set_saved_file(filename);
+        },
+        Ok(None) => {
+            info!("Load file cancelled by user.");
+        },
+        Err(e) => {
+            error!("Error loading file: {}", e);
+        },
+    };
+}
+
+/// Open a file in a new tab, keeping the current document open.
+pub fn load_in_new_tab(app :&mut App) {
+    match file::load_interactive() {
+        Ok(Some((m, history, filename))) => {
+            info!("Loading model from file succeeded.");
+            app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+            let doc = Document::from_saved(m, history, app.background_jobs.clone());
+            app.tabs.open(&mut app.document, doc);
             app.document.fileinfo.set_saved_file(filename);
         },
         Ok(None) => {
@@ -25,81 +49,522 @@ pub fn load(app :&mut App) {
     };
 }
 
+pub fn new_tab(app :&mut App) {
+    let doc = Document::empty(app.background_jobs.clone());
+    app.tabs.open(&mut app.document, doc);
+    app.document.fileinfo.update_window_title();
+}
+
+/// Open a copy of the current document in a new tab, for trying out
+/// alternatives to it.
+pub fn duplicate_tab(app :&mut App) {
+    let doc = Document::from_model(app.document.analysis.model().clone(), app.background_jobs.clone());
+    app.tabs.open(&mut app.document, doc);
+    app.document.fileinfo.set_unsaved();
+}
+
+pub fn load_file(app :&mut App, filename :String) {
+    info!("Loading file from {:?}", filename);
+    match file::load_document(&filename) {
+        Ok((m, history)) => {
+            info!("Loading model from file succeeded.");
+            app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+            app.document = Document::from_saved(m, history, app.background_jobs.clone());
+            app.document.fileinfo.set_saved_file(filename);
+        },
+        Err(e) => {
+            error!("Error loading file {:?}: {}", filename, e);
+        },
+    }
+}
+
+/// Save the document to its file, or ask for a file name if it has none
+/// or `save_as` is set.
+pub fn save(app :&mut App, save_as :bool) {
+    match (&app.document.fileinfo.filename, save_as) {
+        (None,_) | (_,true) => {
+            match file::save_document_interactive(app.document.analysis.model().clone(),
+                    app.document.saved_history(&app.config).as_ref(), app.config.backup_count as usize) {
+                Err(e) => { error!("Error saving file: {}", e); },
+                Ok(Some(filename)) => { 
+                    app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+                    app.document.set_saved_file(filename); 
+                },
+                _ => {},
+            }
+        }
+        (Some(filename),_) => {
+            match file::save_document(filename, app.document.analysis.model().clone(),
+                    app.document.saved_history(&app.config).as_ref(), app.config.backup_count as usize) {
+                Err(e) => { error!("Error saving file: {}", e); },
+                Ok(()) => { 
+                    app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+                    app.document.set_saved_file(filename.clone()); 
+                },
+            }
+        },
+    }
+}
+
+pub fn export_railml(app :&mut App, version :export::RailMLVersion) {
+    match export::export_railml_interactive(app.document.analysis.model(), version) {
+        Err(e) => { error!("Error exporting railML: {}", e); },
+        Ok(Some((filename, report))) => {
+            // Only shown when imported ids were not all kept.
+            if report.changed().next().is_some() || !report.lost.is_empty() {
+                app.windows.export_report.show(filename.clone(), report);
+            }
+            if app.document.analysis.model().file_settings.railml_export_path.as_ref() != Some(&filename) {
+                app.document.analysis.edit_model(|m| {
+                    m.file_settings.railml_export_path = Some(filename);
+                    None
+                });
+            }
+        },
+        Ok(None) => {},
+    }
+}
+
+/// The view bookmarks of the document, for going to one of them, and for
+/// adding or deleting them.
+fn bookmarks_menu(app :&mut App) {
+    unsafe {
+        let mut go_to = None;
+        let mut delete = None;
+        let bookmarks = &app.document.analysis.model().view_bookmarks;
+        for (id,b) in bookmarks.iter() {
+            igPushIDInt(*id as _);
+            let name = CString::new(b.name.as_str()).unwrap();
+            if igMenuItemBool(name.as_ptr(), std::ptr::null(), false, true) { go_to = Some(b.clone()); }
+            igPopID();
+        }
+        if bookmarks.iter().next().is_none() { widgets::show_text(i18n::tr("No bookmarks.")); }
+        widgets::sep();
+        if let Some(name) = widgets::edit_text(tr!("Name"), app.windows.bookmark_name.clone()) {
+            app.windows.bookmark_name = name;
+        }
+        if igMenuItemBool(tr!("Add bookmark"), std::ptr::null(), false, true) {
+            gui::infrastructure::add_view_bookmark(&mut app.document.analysis, &app.document.inf_view,
+                                                   &app.windows.bookmark_name);
+            app.windows.bookmark_name.clear();
+        }
+        let bookmarks = &app.document.analysis.model().view_bookmarks;
+        if igBeginMenu(tr!("Delete bookmark"), bookmarks.iter().next().is_some()) {
+            for (id,b) in bookmarks.iter() {
+                igPushIDInt(*id as _);
+                let name = CString::new(b.name.as_str()).unwrap();
+                if igMenuItemBool(name.as_ptr(), std::ptr::null(), false, true) { delete = Some(*id); }
+                igPopID();
+            }
+            igEndMenu();
+        }
+        if let Some(b) = go_to {
+            gui::infrastructure::go_to_view_bookmark(&mut app.document.inf_view, &b);
+        }
+        if let Some(id) = delete {
+            app.document.analysis.edit_model(|m| { m.view_bookmarks.remove(id); None });
+        }
+    }
+}
+
+/// Export the selected part of the infrastructure as railML and put the
+/// XML on the system clipboard.
+pub fn copy_as_railml(app :&mut App) {
+    let model = app.document.analysis.model();
+    if app.document.inf_view.selection.is_empty() {
+        info!("Nothing selected to copy as railML.");
+        return;
+    }
+    let scope = export::ExportScope::Selection(app.document.inf_view.selection.clone());
+    let xml = export::scoped_model(model, &scope)
+        .and_then(|part| export::railml_string(&part, export::RailMLVersion::V25));
+    match xml {
+        Ok(xml) => match CString::new(xml) {
+            Ok(xml) => {
+                unsafe { igSetClipboardText(xml.as_ptr()); }
+                info!("Copied selection as railML.");
+            },
+            Err(e) => { error!("Error copying railML: {}", e); },
+        },
+        Err(e) => { error!("Error exporting railML: {}", e); },
+    }
+}
+
+pub fn export_sumo(app :&mut App) {
+    let default_speed = app.config.design_speed as f64 / 3.6;
+    if let Err(e) = crate::sumo::export_sumo_interactive(app.document.analysis.model(), default_speed) {
+        error!("Error exporting SUMO network: {}", e);
+    }
+}
+
+pub fn export_geojson(app :&mut App) {
+    if let Err(e) = crate::geojson::export_geojson_interactive(app.document.analysis.model()) {
+        error!("Error exporting GeoJSON: {}", e);
+    }
+}
+
+pub fn export_dxf(app :&mut App) {
+    if let Err(e) = crate::dxf::export_dxf_interactive(app.document.analysis.model()) {
+        error!("Error exporting DXF: {}", e);
+    }
+}
+
+pub fn import_geojson(app :&mut App) {
+    match crate::geojson::import_geojson_interactive() {
+        Ok(Some(m)) => {
+            app.document = Document::from_model(m, app.background_jobs.clone());
+            app.document.fileinfo.set_unsaved();
+        },
+        Ok(None) => {},
+        Err(e) => { error!("Error importing GeoJSON: {}", e); },
+    }
+}
+
+fn file_patterns(format :&crate::plugins::FileFormat) -> Vec<&str> {
+    format.patterns.iter().map(|p| p.as_str()).collect()
+}
+
+pub fn plugin_import(app :&mut App, plugin :usize, format :usize) {
+    let plugin = &app.plugins.plugins[plugin];
+    let format = &plugin.info.importers[format];
+    let patterns = file_patterns(format);
+    let filter = if patterns.is_empty() { None } else { Some((&patterns[..], format.name.as_str())) };
+    let filename = match tinyfiledialogs::open_file_dialog(&format!("Import {}", format.name), "", filter) {
+        Some(f) => f,
+        None => return,
+    };
+    match plugin.import(&format.name, &filename) {
+        Ok(m) => {
+            info!("Imported {:?} with plugin {}", filename, plugin.info.name);
+            app.document = Document::from_model(m, app.background_jobs.clone());
+            app.document.fileinfo.set_unsaved();
+        },
+        Err(e) => { error!("Could not import {:?}: {}", filename, e); },
+    }
+}
+
+fn plugin_export(app :&App, plugin :usize, format :usize) {
+    let plugin = &app.plugins.plugins[plugin];
+    let format = &plugin.info.exporters[format];
+    if let Some(filename) = tinyfiledialogs::save_file_dialog(&format!("Export {}", format.name), "") {
+        match plugin.export(&format.name, &filename, app.document.analysis.model()) {
+            Ok(()) => { info!("Exported {:?} with plugin {}", filename, plugin.info.name); },
+            Err(e) => { error!("Could not export {:?}: {}", filename, e); },
+        }
+    }
+}
+
+fn recent_files_menu(app :&mut App) {
+    unsafe {
+        if app.config.recent_files.is_empty() {
+            widgets::show_text(i18n::tr("No recent files"));
+            return;
+        }
+
+        // Pinned entries are listed first.
+        let mut order = (0..app.config.recent_files.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| !app.config.recent_files[*i].pinned);
+
+        let mut pin = None;
+        let mut remove = None;
+        let mut last_pinned = app.config.recent_files[order[0]].pinned;
+        for i in order {
+            let file = &app.config.recent_files[i];
+            if last_pinned && !file.pinned { widgets::sep(); }
+            last_pinned = file.pinned;
+
+            igPushIDInt(i as _);
+            let kind = match file.kind {
+                RecentFileKind::Document => "",
+                RecentFileKind::RailML => " (railML)",
+            };
+            let label = CString::new(format!("{}{}", file.path, kind)).unwrap();
+            if igBeginMenu(label.as_ptr(), true) {
+                let open_label = match file.kind {
+                    RecentFileKind::Document => const_cstr!("Open"),
+                    RecentFileKind::RailML => const_cstr!("Import"),
+                };
+                if igMenuItemBool(open_label.as_ptr(), std::ptr::null(), false, true) {
+                    let is_url = file.path.starts_with("http://") || file.path.starts_with("https://");
+                    app.windows.pending_action = Some(match (file.kind, is_url) {
+                        (RecentFileKind::Document, false) => PendingAction::LoadFile(file.path.clone()),
+                        (RecentFileKind::Document, true) => PendingAction::OpenUrl(file.path.clone()),
+                        (RecentFileKind::RailML, false) => PendingAction::ImportFile(file.path.clone()),
+                        (RecentFileKind::RailML, true) => PendingAction::ImportUrl(file.path.clone()),
+                    });
+                }
+                if igMenuItemBool(tr!("Pinned"), std::ptr::null(), file.pinned, true) {
+                    pin = Some((i, !file.pinned));
+                }
+                if igMenuItemBool(tr!("Remove from list"), std::ptr::null(), false, true) {
+                    remove = Some(i);
+                }
+                igEndMenu();
+            }
+            igPopID();
+        }
+
+        if let Some((i,pinned)) = pin { app.config.set_recent_file_pinned(i, pinned); }
+        if let Some(i) = remove { app.config.remove_recent_file(i); }
+    }
+}
+
 pub fn main_menu(app :&mut App) {
     unsafe {
         if igBeginMenuBar() {
 
-            if igBeginMenu(const_cstr!("File").as_ptr(), true) {
+            if igBeginMenu(tr!("File"), true) {
 
                 // TODO warn about saving file when doing new file / load file
-                if igMenuItemBool(const_cstr!("New file").as_ptr(), std::ptr::null(), false, true) {
+                if igMenuItemBool(tr!("New file"), std::ptr::null(), false, true) {
                     app.windows.pending_action = Some(PendingAction::New);
                 }
 
-                if igMenuItemBool(const_cstr!("Load file...").as_ptr(), std::ptr::null(), false, true) {
+                if igBeginMenu(tr!("New from template"), true) {
+                    if igMenuItemBool(tr!("Empty"), std::ptr::null(), false, true) {
+                        app.windows.pending_action = Some(PendingAction::New);
+                    }
+                    for (i,(name,_)) in file::DOCUMENT_TEMPLATES.iter().enumerate() {
+                        let name = CString::new(*name).unwrap();
+                        if igMenuItemBool(name.as_ptr(), std::ptr::null(), false, true) {
+                            app.windows.pending_action = Some(PendingAction::NewFromTemplate(i));
+                        }
+                    }
+                    igEndMenu();
+                }
+
+                if igMenuItemBool(tr!("Load file..."), std::ptr::null(), false, true) {
                     app.windows.pending_action = Some(PendingAction::Load);
                 }
 
+                if igBeginMenu(tr!("Tabs"), true) {
+                    if igMenuItemBool(tr!("New tab"), std::ptr::null(), false, true) {
+                        new_tab(app);
+                    }
+                    if igMenuItemBool(tr!("Open in new tab..."), std::ptr::null(), false, true) {
+                        load_in_new_tab(app);
+                    }
+                    if igMenuItemBool(tr!("Duplicate in new tab"), std::ptr::null(), false, true) {
+                        duplicate_tab(app);
+                    }
+                    if igMenuItemBool(tr!("Close tab"), std::ptr::null(), false, true) {
+                        app.windows.pending_action = Some(PendingAction::CloseTab);
+                    }
+                    igEndMenu();
+                }
+
+                if igMenuItemBool(tr!("Open URL..."), std::ptr::null(), false, true) {
+                    if let Some(action) = gui::windows::download::ask_url(gui::windows::download::DownloadKind::Document) {
+                        app.windows.pending_action = Some(action);
+                    }
+                }
+
+                if igMenuItemBool(tr!("Open project..."), std::ptr::null(), false, true) {
+                    gui::windows::project::open_project_interactive(&mut app.project, &mut app.config);
+                    app.windows.project = app.project.is_some();
+                }
+
+                if igMenuItemBool(tr!("Project window"), std::ptr::null(), app.windows.project, true) {
+                    app.windows.project = !app.windows.project;
+                }
+
+                if igBeginMenu(tr!("Recent"), true) {
+                    recent_files_menu(app);
+                    igEndMenu();
+                }
+
                 match &app.document.fileinfo.filename  {
                     Some(filename) => {
-                        if igMenuItemBool(const_cstr!("Save").as_ptr(), 
+                        if igMenuItemBool(tr!("Save"), 
                                           std::ptr::null(), false, true) {
-                            match file::save(filename, app.document.analysis.model().clone()) {
+                            match file::save_document(filename, app.document.analysis.model().clone(),
+                                    app.document.saved_history(&app.config).as_ref(), app.config.backup_count as usize) {
                                 Err(e) => { error!("Error saving file: {}", e); },
                                 Ok(()) => { 
+                                    app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
                                     app.document.set_saved_file(filename.clone()); 
                                 },
                             };
                         }
                     },
                     None => {
-                        if igMenuItemBool(const_cstr!("Save...").as_ptr(), 
+                        if igMenuItemBool(tr!("Save..."), 
                                           std::ptr::null(), false, true) {
-                            match file::save_interactive(app.document.analysis.model().clone()) {
+                            match file::save_document_interactive(app.document.analysis.model().clone(),
+                                    app.document.saved_history(&app.config).as_ref(), app.config.backup_count as usize) {
                                 Err(e) => { error!("Error saving file: {}", e); },
-                                Ok(Some(filename)) => { app.document.set_saved_file(filename); },
+                                Ok(Some(filename)) => { 
+                                    app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
+                                    app.document.set_saved_file(filename); 
+                                },
                                 _ => {}, // cancelled
                             };
                         }
                     }
                 }
 
-                if igMenuItemBool(const_cstr!("Save as...").as_ptr(), std::ptr::null(), false, true) {
-                    match file::save_interactive(app.document.analysis.model().clone()) {
+                if igMenuItemBool(tr!("Save as..."), std::ptr::null(), false, true) {
+                    match file::save_document_interactive(app.document.analysis.model().clone(),
+                            app.document.saved_history(&app.config).as_ref(), app.config.backup_count as usize) {
                         Err(e) => { error!("Error saving file: {}", e); },
                         Ok(Some(filename)) => {
+                            app.config.add_recent_file(filename.clone(), RecentFileKind::Document);
                             app.document.set_saved_file(filename);
                         },
                         _ => {},
                     }
                 }
 
+                if igMenuItemBool(tr!("Merge documents..."), std::ptr::null(), app.windows.merge.open, true) {
+                    app.windows.merge.open = !app.windows.merge.open;
+                }
+
+                let has_selection = !app.document.inf_view.selection.is_empty();
+                if igMenuItemBool(tr!("Save selection as..."), std::ptr::null(), false, has_selection) {
+                    let scope = export::ExportScope::Selection(app.document.inf_view.selection.clone());
+                    let saved = export::scoped_model(app.document.analysis.model(), &scope)
+                        .and_then(|model| file::save_interactive(model, app.config.backup_count as usize));
+                    match saved {
+                        Err(e) => { error!("Error saving selection: {}", e); },
+                        Ok(Some(filename)) => {
+                            app.config.add_recent_file(filename, RecentFileKind::Document);
+                        },
+                        _ => {},
+                    }
+                }
+
                 widgets::sep();
 
-                if igMenuItemBool(const_cstr!("Import from railML...").as_ptr(), std::ptr::null(), false, true) {
+                if igMenuItemBool(tr!("Import from railML..."), std::ptr::null(), false, true) {
                     app.windows.pending_action = Some(PendingAction::Import);
                 }
 
-                if igMenuItemBool(const_cstr!("Export to railML...").as_ptr(), std::ptr::null(), false, true) {
-                    if let Err(e) = export::export_railml_interactive(app.document.analysis.model()) {
-                        error!("Error exporting railML: {}", e);
+                if igMenuItemBool(tr!("Import railML from URL..."), std::ptr::null(), false, true) {
+                    if let Some(action) = gui::windows::download::ask_url(gui::windows::download::DownloadKind::RailML) {
+                        app.windows.pending_action = Some(action);
+                    }
+                }
+
+                let model = app.document.analysis.model();
+                let has_source = model.railml_source.is_some() || model.file_settings.import.is_some();
+                if igMenuItemBool(tr!("Reload from railML source"), std::ptr::null(), false, has_source) {
+                    app.windows.pending_action = Some(PendingAction::ReloadSource);
+                }
+
+                if igBeginMenu(tr!("Export to railML"), true) {
+                    if igMenuItemBool(tr!("railML 2.5..."), std::ptr::null(), false, true) {
+                        export_railml(app, export::RailMLVersion::V25);
+                    }
+                    if igMenuItemBool(tr!("railML 3.2..."), std::ptr::null(), false, true) {
+                        export_railml(app, export::RailMLVersion::V3);
+                    }
+                    igSeparator();
+                    if igMenuItemBool(tr!("Part of the model..."), std::ptr::null(), false, true) {
+                        app.windows.export_scope.open = true;
+                    }
+                    igEndMenu();
+                }
+                if igMenuItemBool(tr!("Export to SUMO..."), std::ptr::null(), false, true) {
+                    export_sumo(app);
+                }
+                if igMenuItemBool(tr!("Import from GeoJSON..."), std::ptr::null(), false, true) {
+                    import_geojson(app);
+                }
+                if igMenuItemBool(tr!("Export to GeoJSON..."), std::ptr::null(), false, true) {
+                    export_geojson(app);
+                }
+                if igMenuItemBool(tr!("Export to DXF..."), std::ptr::null(), false, true) {
+                    export_dxf(app);
+                }
+                if igMenuItemBool(tr!("Print layout..."), std::ptr::null(), false, true) {
+                    app.windows.print.open = true;
+                }
+
+                if igBeginMenu(tr!("Import with plugin"), 
+                               app.plugins.plugins.iter().any(|p| !p.info.importers.is_empty())) {
+                    for (i,p) in app.plugins.plugins.iter().enumerate() {
+                        for (j,f) in p.info.importers.iter().enumerate() {
+                            igPushIDInt((1000*i + j) as _);
+                            let label = CString::new(format!("{} ({})", f.name, p.info.name)).unwrap();
+                            if igMenuItemBool(label.as_ptr(), std::ptr::null(), false, true) {
+                                app.windows.pending_action = Some(PendingAction::PluginImport(i, j));
+                            }
+                            igPopID();
+                        }
+                    }
+                    igEndMenu();
+                }
+
+                if igBeginMenu(tr!("Export with plugin"), 
+                               app.plugins.plugins.iter().any(|p| !p.info.exporters.is_empty())) {
+                    let mut export = None;
+                    for (i,p) in app.plugins.plugins.iter().enumerate() {
+                        for (j,f) in p.info.exporters.iter().enumerate() {
+                            igPushIDInt((1000*i + j) as _);
+                            let label = CString::new(format!("{} ({})", f.name, p.info.name)).unwrap();
+                            if igMenuItemBool(label.as_ptr(), std::ptr::null(), false, true) {
+                                export = Some((i,j));
+                            }
+                            igPopID();
+                        }
                     }
+                    if let Some((i,j)) = export { plugin_export(app, i, j); }
+                    igEndMenu();
+                }
+
+                let data = app.document.analysis.data();
+                let il_available = data.interlocking.is_some() && data.dgraph.is_some();
+                if igBeginMenu(tr!("Export route table"), il_available) {
+                    let mut template = None;
+                    for (i,t) in route_table::BUILTIN_TEMPLATES.iter().enumerate() {
+                        igPushIDInt(i as _);
+                        let name = std::ffi::CString::new(t.name.clone()).unwrap();
+                        if igMenuItemBool(name.as_ptr(), std::ptr::null(), false, true) {
+                            template = Some(t.clone());
+                        }
+                        igPopID();
+                    }
+                    widgets::sep();
+                    if igMenuItemBool(tr!("From template file..."), std::ptr::null(), false, true) {
+                        if let Some(filename) = tinyfiledialogs::open_file_dialog("Open route table template", "",
+                                                                Some((&["*.toml"], "TOML files"))) {
+                            match route_table::load_template(&filename) {
+                                Ok(t) => { template = Some(t); },
+                                Err(e) => { error!("Could not load route table template: {}", e); },
+                            }
+                        }
+                    }
+                    if let (Some(template), Some((_,il)), Some((_,dgraph))) = 
+                            (template, &data.interlocking, &data.dgraph) {
+                        if let Err(e) = route_table::export_interactive(&template, il, dgraph, &app.config.units) {
+                            error!("Error exporting route table: {}", e);
+                        }
+                    }
+                    igEndMenu();
                 }
 
                 widgets::sep();
-                if igMenuItemBool(const_cstr!("Quit").as_ptr(), 
+                if igMenuItemBool(tr!("Quit"), 
                                   std::ptr::null(), false, true) {
                     app.windows.pending_action = Some(PendingAction::Quit);
                 }
 
                 igEndMenu();
             }
-            if igBeginMenu(const_cstr!("Edit").as_ptr(), true) {
-                if igMenuItemBool(const_cstr!("Edit vehicles").as_ptr(), 
+            if igBeginMenu(tr!("Edit"), true) {
+                if igMenuItemBool(tr!("Copy as railML"), std::ptr::null(), false,
+                                  !app.document.inf_view.selection.is_empty()) {
+                    copy_as_railml(app);
+                }
+                let shortcut = CString::new(app.config.key_text(KeyCommand::Find)).unwrap();
+                if igMenuItemBool(tr!("Find..."), shortcut.as_ptr(), false, true) {
+                    app.windows.search.show();
+                }
+                if igMenuItemBool(tr!("Edit vehicles"), 
                                   std::ptr::null(), app.windows.vehicles, true) {
                     app.windows.vehicles = !app.windows.vehicles;
                 }
-                if igMenuItemBool(const_cstr!("Signal designer").as_ptr(), 
+                if igMenuItemBool(tr!("Signal designer"), 
                                   std::ptr::null(), app.windows.synthesis_window.is_some(), true) {
                     if app.windows.synthesis_window.is_none() {
                         let model = app.document.analysis.model().clone();
@@ -109,7 +574,7 @@ pub fn main_menu(app :&mut App) {
 
                     }
                 }
-                if igMenuItemBool(const_cstr!("Delete all objects").as_ptr(), std::ptr::null(), false, true) {
+                if igMenuItemBool(tr!("Delete all objects"), std::ptr::null(), false, true) {
                     app.document.analysis.edit_model(|m| {
                         m.objects.clear();
                         None
@@ -117,23 +582,93 @@ pub fn main_menu(app :&mut App) {
                 }
                 igEndMenu();
             }
-            if igBeginMenu(const_cstr!("View").as_ptr(), true) {
-                if igMenuItemBool(const_cstr!("Log window").as_ptr(), 
+            if igBeginMenu(tr!("View"), true) {
+                if igMenuItemBool(tr!("Log window"), 
                                   std::ptr::null(), app.windows.log, true) {
                     app.windows.log = !app.windows.log;
                 }
-                if igMenuItemBool(const_cstr!("Fit to view").as_ptr(),
-                                  std::ptr::null(), false, true) {
+                if igMenuItemBool(tr!("Object tree"),
+                                  std::ptr::null(), app.windows.object_tree.open, true) {
+                    app.windows.object_tree.open = !app.windows.object_tree.open;
+                }
+                if igMenuItemBool(tr!("Selection groups"),
+                                  std::ptr::null(), app.windows.groups.open, true) {
+                    app.windows.groups.open = !app.windows.groups.open;
+                }
+                if igMenuItemBool(tr!("Track properties"),
+                                  std::ptr::null(), app.windows.track_properties.open, true) {
+                    app.windows.track_properties.open = !app.windows.track_properties.open;
+                }
+                let shortcut = CString::new(app.config.key_text(KeyCommand::CommandPalette)).unwrap();
+                if igMenuItemBool(tr!("Command palette"),
+                                  shortcut.as_ptr(), false, true) {
+                    app.windows.palette.show();
+                }
+                let shortcut = CString::new(app.config.key_text(KeyCommand::FitView)).unwrap();
+                if igMenuItemBool(tr!("Fit to view"),
+                                  shortcut.as_ptr(), false, true) {
                     app.document.inf_view.pending_fit_view = true;
                 }
+                let shortcut = CString::new(app.config.key_text(KeyCommand::ZoomToSelection)).unwrap();
+                if igMenuItemBool(tr!("Zoom to selection"),
+                                  shortcut.as_ptr(), false, !app.document.inf_view.selection.is_empty()) {
+                    gui::infrastructure::zoom_to_selection(&mut app.document.inf_view);
+                }
+                if igBeginMenu(tr!("Bookmarks"), true) {
+                    bookmarks_menu(app);
+                    igEndMenu();
+                }
+                if igMenuItemBool(tr!("Catenary"),
+                                  std::ptr::null(), app.config.show_catenary, true) {
+                    app.config.show_catenary = !app.config.show_catenary;
+                    app.config.save();
+                }
+                if igMenuItemBool(tr!("Grid"),
+                                  std::ptr::null(), app.config.grid.visible, true) {
+                    app.config.grid.visible = !app.config.grid.visible;
+                    app.config.save();
+                }
+                if igMenuItemBool(tr!("Smooth curves"),
+                                  std::ptr::null(), app.config.smooth_curves, true) {
+                    app.config.smooth_curves = !app.config.smooth_curves;
+                    app.config.save();
+                }
                 igEndMenu();
             }
-            if igBeginMenu(const_cstr!("Tools").as_ptr(), true) {
-                if igMenuItemBool(const_cstr!("View data").as_ptr(), 
+            if igBeginMenu(tr!("Tools"), true) {
+                if igMenuItemBool(tr!("View data"), 
                                   std::ptr::null(), app.windows.debug, true) {
                     app.windows.debug = !app.windows.debug;
                 }
-                if igMenuItemBool(const_cstr!("Configure colors").as_ptr(), 
+                if igMenuItemBool(tr!("Model issues"), 
+                                  std::ptr::null(), app.windows.lint.open, true) {
+                    app.windows.lint.open = !app.windows.lint.open;
+                }
+                if igMenuItemBool(tr!("Path query"), 
+                                  std::ptr::null(), app.windows.path_query.open, true) {
+                    app.windows.path_query.open = !app.windows.path_query.open;
+                }
+                if igMenuItemBool(tr!("Script console"), 
+                                  std::ptr::null(), app.windows.scripts.open, true) {
+                    app.windows.scripts.toggle();
+                }
+                if igMenuItemBool(tr!("API server"), 
+                                  std::ptr::null(), app.server.is_some(), true) {
+                    if app.server.is_some() { app.server = None; } else { app.start_server(); }
+                }
+                if igMenuItemBool(tr!("Train position feed"), 
+                                  std::ptr::null(), app.feed.is_some(), true) {
+                    if app.feed.is_some() { app.feed = None; } else { app.start_feed(); }
+                }
+                if igMenuItemBool(tr!("Statistics"), 
+                                  std::ptr::null(), app.windows.stats, true) {
+                    app.windows.stats = !app.windows.stats;
+                }
+                if igMenuItemBool(tr!("Detector placement"), 
+                                  std::ptr::null(), app.windows.detector_placement, true) {
+                    app.windows.detector_placement = !app.windows.detector_placement;
+                }
+                if igMenuItemBool(tr!("Configure colors"), 
                                   std::ptr::null(), app.windows.config, true) {
                     app.windows.config = !app.windows.config;
                 }
@@ -1,6 +1,8 @@
 pub mod widgets;
 mod mainmenu;
 mod keys;
+mod tabs;
+pub mod palette;
 pub mod windows;
 
 mod infrastructure;
@@ -19,12 +21,14 @@ pub fn main(app :&mut App) -> bool {
 
     // keyboard commands (ctrl+s for save, etc. + a/s/d for tool selection)
     keys::keys(app);
+    app.document.analysis.set_undo_depth(app.config.undo_depth as usize);
 
     let mut inf_canvas = None;
     // Main window
     widgets::in_root_window(|| {
         // top menu bar
         mainmenu::main_menu(app);
+        tabs::tab_bar(app);
 
         // Three main window arrangements:
         // 1. Infrastructure only (diagram_view = None)
@@ -34,8 +38,9 @@ pub fn main(app :&mut App) -> bool {
         let analysis = &mut app.document.analysis;
         let inf_view = &mut app.document.inf_view;
         let dispatch_view = &mut app.document.dispatch_view;
+        let plugins = &app.plugins;
         if dispatch_view.is_none() {
-            let d = infrastructure::inf_view(config, analysis, inf_view, dispatch_view);
+            let d = infrastructure::inf_view(config, analysis, inf_view, dispatch_view, plugins);
             inf_canvas = Some(d);
 
             unsafe {
@@ -54,7 +59,7 @@ pub fn main(app :&mut App) -> bool {
 
             widgets::Splitter::vertical(app.windows.diagram_split.as_mut().unwrap())
                 .left(const_cstr!("inf_canv").as_ptr(), || {
-                    let d = infrastructure::inf_view(config, analysis, inf_view, dispatch_view); 
+                    let d = infrastructure::inf_view(config, analysis, inf_view, dispatch_view, plugins); 
                     inf_canvas = Some(d);
                 })
                 .right(const_cstr!("dia_dptch").as_ptr(), || {
@@ -70,17 +75,41 @@ pub fn main(app :&mut App) -> bool {
     windows::logview::view_log(&mut app.windows.log, &app.log);
     app.windows.debug = windows::debug::debug_window(app.windows.debug, &app, 
                                                      inf_canvas.as_ref(), &app.document.inf_view );
-    windows::vehicles::edit_vehicles_window(&mut app.windows.vehicles, &mut app.document);
+    windows::vehicles::edit_vehicles_window(&mut app.windows.vehicles, &mut app.document, &app.config.units);
     windows::config::edit_config_window(&mut app.windows.config, &mut app.config);
-
-    app.windows.import_window.draw(&mut app.document.analysis);
+    windows::project::project_window(&mut app.windows.project, &mut app.project, &mut app.document,
+                                     &mut app.config, &mut app.windows.pending_action);
+    windows::stats::stats_window(&mut app.windows.stats, &app.config, &app.document.analysis);
+    windows::detectors::detector_placement_window(&mut app.windows.detector_placement, &app.config,
+                                                  &mut app.document.analysis, inf_canvas.as_ref(),
+                                                  &app.document.inf_view);
+    app.windows.lint.draw(&app.config, &app.document.analysis, &mut app.document.inf_view, &app.plugins);
+    app.windows.path_query.draw(&app.config, &app.document.analysis, inf_canvas.as_ref(), 
+                                &app.document.inf_view);
+    app.windows.scripts.draw(&mut app.document.analysis, &mut app.document.inf_view);
+    app.windows.object_tree.draw(&app.config, &app.document.analysis, &mut app.document.inf_view,
+                                 &mut app.document.dispatch_view);
+    app.windows.merge.draw(&mut app.document.analysis);
+    app.windows.export_report.draw();
+    app.windows.print.draw(&app.document, &mut app.config);
+    app.windows.export_scope.draw(&app.document);
+    app.windows.groups.draw(&mut app.document.analysis, &mut app.document.inf_view);
+    app.windows.search.draw(&app.document.analysis, &mut app.document.inf_view);
+    app.windows.track_properties.draw(&app.config, &mut app.document.analysis, &app.document.inf_view);
+
+    app.windows.import_window.draw(&mut app.document.analysis, &mut app.config);
     if let Some(win) = &mut app.windows.synthesis_window { if !win.draw(&mut app.document.analysis) {
         app.windows.synthesis_window = None; }}
 
+    windows::download::download_window(app);
+    palette::command_palette(app);
+    app.windows.file_change.draw(&mut app.document, &mut app.windows.pending_action);
+    windows::crashreport::crash_report_window(&mut app.windows.crash_report);
+
     // Pending action dialog (Unsaved changes)
-    let really_quit = if let Some(action) = app.windows.pending_action {
+    let really_quit = if let Some(action) = app.windows.pending_action.clone() {
         if app.document.fileinfo.unsaved {
-            match windows::unsaved::unsaved_changes_window(&mut app.document, &mut app.windows) {
+            match windows::unsaved::unsaved_changes_window(&mut app.document, &mut app.config, &mut app.windows) {
                 Some(true) => {
                     // Confirmed (either saved or discarded)
                     execute_action(app, action)
@@ -102,18 +131,77 @@ pub fn main(app :&mut App) -> bool {
 
 fn execute_action(app :&mut App, action :PendingAction) -> bool {
     app.windows.pending_action = None;
+    if let Some(settings) = &app.document.analysis.model().file_settings.import {
+        app.windows.import_window.use_settings(settings);
+    }
     match action {
         PendingAction::New => {
             app.document = Document::empty(app.background_jobs.clone());
             app.document.fileinfo.update_window_title();
         },
+        PendingAction::NewFromTemplate(idx) => {
+            let (name, data) = crate::file::DOCUMENT_TEMPLATES[idx];
+            match crate::file::from_bytes(data.as_bytes()) {
+                Ok(m) => {
+                    app.document = Document::from_model(m, app.background_jobs.clone());
+                    app.document.fileinfo.update_window_title();
+                },
+                Err(e) => { log::error!("Could not load template {:?}: {}", name, e); },
+            }
+        },
         PendingAction::Load => {
             mainmenu::load(app);
         },
+        PendingAction::LoadFile(filename) => {
+            mainmenu::load_file(app, filename);
+        },
+        PendingAction::OpenUrl(url) => {
+            app.windows.download.start(&mut app.background_jobs, url, windows::download::DownloadKind::Document);
+        },
+        PendingAction::ImportUrl(url) => {
+            app.windows.download.start(&mut app.background_jobs, url, windows::download::DownloadKind::RailML);
+        },
         PendingAction::Import => {
             app.windows.import_window.open();
         },
+        PendingAction::ImportFile(filename) => {
+            app.windows.import_window.open();
+            app.windows.import_window.background_load_file(filename);
+        },
+        PendingAction::ReloadSource => {
+            // The file the document was imported from is read again, and the
+            // railML source embedded in the document is used only when that
+            // file is gone.
+            let model = app.document.analysis.model();
+            let filename = model.file_settings.import.as_ref().map(|s| s.filename.clone())
+                .filter(|f| std::path::Path::new(f).exists());
+            match (filename, model.railml_source.clone()) {
+                (Some(filename), _) => {
+                    app.windows.import_window.open();
+                    app.windows.import_window.background_load_file(filename);
+                },
+                (None, Some(source)) => { app.windows.import_window.reload_source(source); },
+                (None, None) => { log::warn!("The document has no railML source to reload."); },
+            }
+        },
+        PendingAction::PluginImport(plugin, format) => {
+            mainmenu::plugin_import(app, plugin, format);
+        },
+        PendingAction::CloseTab => {
+            app.tabs.close_active(&mut app.document, app.background_jobs.clone());
+            app.document.fileinfo.update_window_title();
+        },
         PendingAction::Quit => {
+            // Ask about the other documents with unsaved changes one at a
+            // time, closing the ones already handled.
+            if let Some(tab) = app.tabs.unsaved_tab() {
+                let tab = if tab > app.tabs.active { tab - 1 } else { tab };
+                app.tabs.close_active(&mut app.document, app.background_jobs.clone());
+                app.tabs.select(&mut app.document, tab);
+                app.document.fileinfo.update_window_title();
+                app.windows.pending_action = Some(PendingAction::Quit);
+                return false;
+            }
             return true;
         },
     }
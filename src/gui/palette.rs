@@ -0,0 +1,267 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use std::ffi::CString;
+
+use crate::app::{App, PendingAction};
+use crate::config::KeyCommand;
+use crate::document::infview::{Action, NormalState};
+use crate::export::RailMLVersion;
+use crate::gui::{keys, mainmenu, widgets, windows};
+use crate::gui::infrastructure::{Transform, transform_selection, zoom_to_selection, add_view_bookmark};
+use crate::i18n;
+
+/// Maximum number of matches listed.
+const MAX_SHOWN :usize = 20;
+
+pub struct Command {
+    pub name :&'static str,
+    pub shortcut :Option<KeyCommand>,
+    pub run :fn(&mut App),
+}
+
+fn command(name :&'static str, shortcut :Option<KeyCommand>, run :fn(&mut App)) -> Command {
+    Command { name, shortcut, run }
+}
+
+fn transform(app :&mut App, transform :Transform) {
+    transform_selection(&mut app.document.analysis, &mut app.document.inf_view, transform);
+}
+
+/// All actions available from the palette.
+pub fn commands() -> Vec<Command> {
+    vec![
+        command("File: New file", None, |app| app.windows.pending_action = Some(PendingAction::New)),
+        command("File: Load file", Some(KeyCommand::Open), |app| app.windows.pending_action = Some(PendingAction::Load)),
+        command("File: New tab", None, |app| mainmenu::new_tab(app)),
+        command("File: Open in new tab", None, |app| mainmenu::load_in_new_tab(app)),
+        command("File: Duplicate in new tab", None, |app| mainmenu::duplicate_tab(app)),
+        command("File: Close tab", None, |app| app.windows.pending_action = Some(PendingAction::CloseTab)),
+        command("File: Open URL", None, |app| {
+            if let Some(a) = windows::download::ask_url(windows::download::DownloadKind::Document) {
+                app.windows.pending_action = Some(a);
+            }
+        }),
+        command("File: Save", Some(KeyCommand::Save), |app| mainmenu::save(app, false)),
+        command("File: Save as", Some(KeyCommand::SaveAs), |app| mainmenu::save(app, true)),
+        command("File: Import from railML", None, |app| app.windows.pending_action = Some(PendingAction::Import)),
+        command("File: Import railML from URL", None, |app| {
+            if let Some(a) = windows::download::ask_url(windows::download::DownloadKind::RailML) {
+                app.windows.pending_action = Some(a);
+            }
+        }),
+        command("File: Merge documents", None, |app| app.windows.merge.open = !app.windows.merge.open),
+        command("File: Export to railML", None, |app| mainmenu::export_railml(app, RailMLVersion::V25)),
+        command("File: Export to railML 3", None, |app| mainmenu::export_railml(app, RailMLVersion::V3)),
+        command("File: Export part to railML", None, |app| app.windows.export_scope.open = true),
+        command("File: Export to SUMO", None, |app| mainmenu::export_sumo(app)),
+        command("File: Import from GeoJSON", None, |app| mainmenu::import_geojson(app)),
+        command("File: Export to GeoJSON", None, |app| mainmenu::export_geojson(app)),
+        command("File: Export to DXF", None, |app| mainmenu::export_dxf(app)),
+        command("File: Print layout", None, |app| app.windows.print.open = true),
+        command("File: Open project", None, |app| {
+            windows::project::open_project_interactive(&mut app.project, &mut app.config);
+            app.windows.project = app.project.is_some();
+        }),
+        command("File: Quit", None, |app| app.windows.pending_action = Some(PendingAction::Quit)),
+
+        command("Edit: Undo", Some(KeyCommand::Undo), |app| app.document.analysis.undo()),
+        command("Edit: Redo", Some(KeyCommand::Redo), |app| app.document.analysis.redo()),
+        command("Edit: Copy", Some(KeyCommand::Copy), |app| keys::copy(app)),
+        command("Edit: Copy as railML", None, |app| mainmenu::copy_as_railml(app)),
+        command("Edit: Select all", Some(KeyCommand::SelectAll), |app| keys::select_all(&mut app.document)),
+        command("Edit: Delete selection", Some(KeyCommand::Delete), |app| {
+            crate::gui::infrastructure::delete_selection(&mut app.document.analysis, &mut app.document.inf_view);
+        }),
+        command("Edit: Delete all objects", None, |app| app.document.analysis.edit_model(|m| {
+            m.objects.clear();
+            None
+        })),
+        command("Edit: Mirror selection horizontally", Some(KeyCommand::MirrorHorizontal), |app| {
+            transform(app, Transform::MirrorHorizontal)
+        }),
+        command("Edit: Mirror selection vertically", Some(KeyCommand::MirrorVertical), |app| {
+            transform(app, Transform::MirrorVertical)
+        }),
+        command("Edit: Rotate selection clockwise", Some(KeyCommand::RotateClockwise), |app| {
+            transform(app, Transform::RotateClockwise)
+        }),
+        command("Edit: Rotate selection counterclockwise", Some(KeyCommand::RotateCounterClockwise), |app| {
+            transform(app, Transform::RotateCounterClockwise)
+        }),
+        command("Edit: Rotate selection 45° clockwise", Some(KeyCommand::Rotate45Clockwise), |app| {
+            transform(app, Transform::Rotate45Clockwise)
+        }),
+        command("Edit: Rotate selection 45° counterclockwise", Some(KeyCommand::Rotate45CounterClockwise), |app| {
+            transform(app, Transform::Rotate45CounterClockwise)
+        }),
+        command("Edit: Vehicles", None, |app| app.windows.vehicles = !app.windows.vehicles),
+
+        command("Tool: Select and move", Some(KeyCommand::SelectTool), |app| {
+            app.document.inf_view.action = Action::Normal(NormalState::Default);
+        }),
+        command("Tool: Draw tracks", Some(KeyCommand::DrawTool), |app| app.document.inf_view.action = Action::DrawingLine(None)),
+        command("Tool: Insert object", Some(KeyCommand::InsertObjectTool), |app| app.document.inf_view.action = Action::SelectObjectType),
+        command("Tool: Erase tracks", Some(KeyCommand::EraserTool), |app| app.document.inf_view.action = Action::Erasing(Default::default())),
+
+        command("View: Fit to view", Some(KeyCommand::FitView), |app| app.document.inf_view.pending_fit_view = true),
+        command("View: Zoom to selection", Some(KeyCommand::ZoomToSelection), |app| zoom_to_selection(&mut app.document.inf_view)),
+        command("View: Add bookmark", None, |app| add_view_bookmark(&mut app.document.analysis, &app.document.inf_view, "")),
+        command("View: Catenary", None, |app| {
+            app.config.show_catenary = !app.config.show_catenary;
+            app.config.save();
+        }),
+        command("View: Grid", None, |app| {
+            app.config.grid.visible = !app.config.grid.visible;
+            app.config.save();
+        }),
+        command("View: Smooth curves", None, |app| {
+            app.config.smooth_curves = !app.config.smooth_curves;
+            app.config.save();
+        }),
+        command("View: Log window", None, |app| app.windows.log = !app.windows.log),
+        command("View: Object tree", None, |app| app.windows.object_tree.open = !app.windows.object_tree.open),
+        command("View: Selection groups", None, |app| app.windows.groups.open = !app.windows.groups.open),
+        command("View: Track properties", None, |app| {
+            app.windows.track_properties.open = !app.windows.track_properties.open;
+        }),
+        command("Edit: Find", Some(KeyCommand::Find), |app| app.windows.search.show()),
+
+        command("Tools: View data", None, |app| app.windows.debug = !app.windows.debug),
+        command("Tools: Model issues", None, |app| app.windows.lint.open = !app.windows.lint.open),
+        command("Tools: Path query", None, |app| app.windows.path_query.open = !app.windows.path_query.open),
+        command("Tools: Script console", None, |app| app.windows.scripts.toggle()),
+        command("Tools: Statistics", None, |app| app.windows.stats = !app.windows.stats),
+        command("Tools: Detector placement", None, |app| {
+            app.windows.detector_placement = !app.windows.detector_placement;
+        }),
+        command("Tools: Configure", None, |app| app.windows.config = !app.windows.config),
+        command("Tools: Project window", None, |app| app.windows.project = !app.windows.project),
+        command("Tools: API server on/off", None, |app| {
+            if app.server.is_some() { app.server = None; } else { app.start_server(); }
+        }),
+        command("Tools: Train position feed on/off", None, |app| {
+            if app.feed.is_some() { app.feed = None; } else { app.start_feed(); }
+        }),
+    ]
+}
+
+/// Score for how well `query` matches `text`, or None if the characters of
+/// the query do not all appear in order in the text. Consecutive characters
+/// and matches at the start of words score higher.
+pub fn fuzzy_score(query :&str, text :&str) -> Option<i32> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match :Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let i = pos + text[pos..].iter().position(|c| *c == q)?;
+        score += 1;
+        if last_match == Some(i.wrapping_sub(1)) { score += 5; }
+        if i == 0 || !text[i-1].is_alphanumeric() { score += 3; }
+        last_match = Some(i);
+        pos = i + 1;
+    }
+    // Prefer shorter names among equal matches.
+    Some(score * 100 - text.len() as i32)
+}
+
+pub struct CommandPalette {
+    pub open :bool,
+    query :String,
+    selected :usize,
+    focus :bool,
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        CommandPalette { open: false, query: String::new(), selected: 0, focus: false }
+    }
+
+    pub fn show(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.selected = 0;
+        self.focus = true;
+    }
+}
+
+pub fn command_palette(app :&mut App) {
+    if !app.windows.palette.open { return; }
+    let commands = commands();
+    let mut run = None;
+    unsafe {
+        let config = &app.config;
+        let palette = &mut app.windows.palette;
+        let mut matches = commands.iter().enumerate()
+            .filter_map(|(i,c)| fuzzy_score(&palette.query, i18n::tr(c.name)).map(|s| (s,i)))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(s,i)| (-s, *i));
+        matches.truncate(MAX_SHOWN);
+        palette.selected = palette.selected.min(matches.len().saturating_sub(1));
+
+        let name = tr!("Command palette");
+        if !igIsPopupOpen(name) { igOpenPopup(name); }
+        let display_size = (*igGetIO()).DisplaySize;
+        igSetNextWindowPos(ImVec2 { x: display_size.x / 2.0, y: display_size.y / 6.0 },
+                           ImGuiCond__ImGuiCond_Appearing as _, ImVec2 { x: 0.5, y: 0.0 });
+        igSetNextWindowSize(ImVec2 { x: display_size.x / 3.0, y: 0.0 }, ImGuiCond__ImGuiCond_Appearing as _);
+        if igBeginPopupModal(name, std::ptr::null_mut(), 0 as _) {
+            if palette.focus { igSetKeyboardFocusHere(0); palette.focus = false; }
+            if let Some(q) = widgets::edit_text(const_cstr!("##query").as_ptr(), palette.query.clone()) {
+                palette.query = q;
+                palette.selected = 0;
+            }
+
+            if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_DownArrow as _), true) &&
+                palette.selected + 1 < matches.len() {
+                palette.selected += 1;
+            }
+            if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_UpArrow as _), true) {
+                palette.selected = palette.selected.saturating_sub(1);
+            }
+            if igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_Enter as _), false) {
+                run = matches.get(palette.selected).map(|(_,i)| *i);
+            }
+            let escape = igIsKeyPressed(igGetKeyIndex(ImGuiKey__ImGuiKey_Escape as _), false);
+
+            widgets::sep();
+            for (n,(_,i)) in matches.iter().enumerate() {
+                let c = &commands[*i];
+                igPushIDInt(*i as _);
+                let label = CString::new(i18n::tr(c.name)).unwrap();
+                if igSelectable(label.as_ptr(), n == palette.selected, 0 as _, ImVec2::zero()) {
+                    run = Some(*i);
+                }
+                let shortcut = c.shortcut.map(|k| config.key_text(k)).unwrap_or_default();
+                if !shortcut.is_empty() {
+                    igSameLine(0.0, -1.0);
+                    igTextDisabled(const_cstr!("%s").as_ptr(), CString::new(shortcut).unwrap().as_ptr());
+                }
+                igPopID();
+            }
+            if matches.is_empty() { widgets::show_text(i18n::tr("No matching commands")); }
+
+            if run.is_some() || escape {
+                palette.open = false;
+                igCloseCurrentPopup();
+            }
+            igEndPopup();
+        }
+    }
+    if let Some(i) = run { (commands[i].run)(app); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matching() {
+        assert!(fuzzy_score("xyz", "File: Save").is_none());
+        assert!(fuzzy_score("", "File: Save").is_some());
+        assert!(fuzzy_score("fsave", "File: Save").is_some());
+        // Word starts and consecutive characters rank higher than scattered matches.
+        assert!(fuzzy_score("save", "File: Save").unwrap() > fuzzy_score("save", "Show vehicles").unwrap_or(-1000));
+        assert!(fuzzy_score("ab", "ab x").unwrap() > fuzzy_score("ab", "a x b").unwrap());
+    }
+}
@@ -13,6 +13,7 @@ use crate::config::*;
 use crate::document::infview::{InfView, unround_coord};
 use crate::gui::infrastructure::draw::box_around;
 use crate::document::dispatch::DispatchOutput;
+use crate::i18n;
 
 enum Action { 
     VisitDelete { key :VisitKey },
@@ -46,12 +47,13 @@ pub fn edit_plan(config :&Config,
 
 
         igSameLine(0.0,-1.0);
-        if igButton(const_cstr!("\u{f0fe} \u{f239} Train").as_ptr(), ImVec2::zero()) {
+        if igButton(tr!("\u{f0fe} \u{f239} Train"), ImVec2::zero()) {
             action = Some(Action::NewTrain);
         }
 
         igSameLine(0.0,-1.0);
         plan_dispatches(config, analysis, auto_dispatch);
+        plan_feasibility(config, analysis, plan_idx);
 
         widgets::sep();
 
@@ -336,6 +338,30 @@ pub fn planning_icon(config :&Config, analysis :&Analysis, generation :usize, di
     }
 }
 
+fn plan_feasibility(config :&Config, analysis :&Analysis, plan_idx :usize) {
+    let (plan, issues) = match (analysis.model().plans.get(plan_idx),
+                                analysis.data().plan_feasibility.get(plan_idx)) {
+        (Some(plan), Some(Some((generation, issues)))) 
+            if *generation == *analysis.generation() && issues.len() > 0 => (plan, issues),
+        _ => return,
+    };
+    unsafe {
+        igPushStyleColorU32(ImGuiCol__ImGuiCol_Text as _, 
+                            config.color_u32(RailUIColorName::CanvasSignalStop));
+        widgets::show_text(i18n::tr("Unreachable visits:"));
+        for issue in issues.iter() {
+            let train_no = plan.trains.iter().position(|(t,_)| *t == issue.train);
+            let visit_no = plan.trains.get(issue.train)
+                .and_then(|(_,vs)| vs.iter().position(|(v,_)| *v == issue.visit));
+            if let (Some(t), Some(v)) = (train_no, visit_no) {
+                widgets::show_text(&format!("  Train {}, visit {}: {}", 
+                                            t+1, v+1, issue.reason.description()));
+            }
+        }
+        igPopStyleColor(1);
+    }
+}
+
 fn plan_dispatches(config :&Config, analysis :&Analysis, adv :&mut AutoDispatchView)  {
     unsafe {
         if let Some(Some((generation,dispatches))) = analysis.data().plandispatches.get(adv.plan_idx) {
@@ -349,7 +375,7 @@ fn plan_dispatches(config :&Config, analysis :&Analysis, adv :&mut AutoDispatchV
             } else { CString::new(format!("None")).unwrap() };
 
             if igBeginCombo(const_cstr!("##chtr").as_ptr(), dispatch_name.as_ptr(), 0) {
-                if igSelectable(const_cstr!("None").as_ptr(), dispatch_idx.is_none(), 0 as _, ImVec2::zero()) {
+                if igSelectable(tr!("None"), dispatch_idx.is_none(), 0 as _, ImVec2::zero()) {
 
                     adv.dispatch = None;
                 }
@@ -492,7 +518,7 @@ pub fn select_train(model :&Model, current_id :&Option<usize>) -> Option<ListId>
         }
     }
 
-    if !any { widgets::show_text("No vehicles."); }
+    if !any { widgets::show_text(i18n::tr("No vehicles.")); }
     retval
 }
 
@@ -508,7 +534,7 @@ fn yellow_button(name :*const i8) -> bool{
         let clicked = igButton( name , ImVec2 { x: 0.0, y: 0.0 } );
         if igIsItemHovered(0) {
             igBeginTooltip();
-            widgets::show_text("Train has no visits. Right click on the infrastructure to add visits to this train.");
+            widgets::show_text(i18n::tr("Train has no visits. Right click on the infrastructure to add visits to this train."));
             igEndTooltip();
         }
         igPopStyleColor(3);
@@ -615,26 +641,26 @@ unsafe {
     if igBeginPopup(const_cstr!("pctx").as_ptr(), 0 as _) {
         match auto_dispatch.action {
             PlanViewAction::Menu(key, pos) => {
-                if igSelectable(const_cstr!("\u{f0dc} Add ordering constraint...").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                if igSelectable(tr!("\u{f0dc} Add ordering constraint..."), false, 0 as _, ImVec2::zero()) {
                     auto_dispatch.action = PlanViewAction::DragFrom(key,pos);
                 }
 
                 widgets::sep();
                 
                 if key.location.is_some() {
-                    if igSelectable(const_cstr!("\u{f55a} Remove location").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                    if igSelectable(tr!("\u{f55a} Remove location"), false, 0 as _, ImVec2::zero()) {
                         *action = Some(Action::VisitDelete { key });
                     }
                 }
-                if igSelectable(const_cstr!("\u{f55a} Remove visit").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                if igSelectable(tr!("\u{f55a} Remove visit"), false, 0 as _, ImVec2::zero()) {
                     *action = Some(Action::VisitDelete { key: VisitKey { location: None, .. key } } );
                 }
-                if igSelectable(const_cstr!("\u{f55a} Remove ordering constraints").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                if igSelectable(tr!("\u{f55a} Remove ordering constraints"), false, 0 as _, ImVec2::zero()) {
                     *action = Some(Action::OrderDeleteAt { key });
                 }
             },
             _ => {
-                widgets::show_text("No visit selected.");
+                widgets::show_text(i18n::tr("No visit selected."));
             }
         }
         igEndPopup();
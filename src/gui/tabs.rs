@@ -0,0 +1,41 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use std::ffi::CString;
+
+use crate::app::*;
+
+/// Tabs for switching between the open documents, shown when more than
+/// one is open. Closing a tab goes through the unsaved changes dialog.
+pub fn tab_bar(app :&mut App) {
+    if app.tabs.len() < 2 { return; }
+    unsafe {
+        if !igBeginTabBar(const_cstr!("documents").as_ptr(), 0 as _) { return; }
+        let mut select = None;
+        let mut close = None;
+        for tab in 0..app.tabs.len() {
+            let doc = app.tabs.get(&app.document, tab);
+            let mut flags = 0;
+            if doc.fileinfo.unsaved { flags |= ImGuiTabItemFlags__ImGuiTabItemFlags_UnsavedDocument; }
+            if app.tabs.changed && tab == app.tabs.active { flags |= ImGuiTabItemFlags__ImGuiTabItemFlags_SetSelected; }
+            let label = CString::new(format!("{}###document{}", doc.fileinfo.name(), tab)).unwrap();
+            let mut open = true;
+            if igBeginTabItem(label.as_ptr(), &mut open, flags as _) {
+                // The tab bar shows the old tab for one more frame after
+                // the program changes the active tab.
+                if tab != app.tabs.active && !app.tabs.changed { select = Some(tab); }
+                igEndTabItem();
+            }
+            if !open { close = Some(tab); }
+        }
+        igEndTabBar();
+        app.tabs.changed = false;
+
+        if let Some(tab) = close.or(select) {
+            app.tabs.select(&mut app.document, tab);
+            app.document.fileinfo.update_window_title();
+        }
+        if close.is_some() {
+            app.windows.pending_action = Some(PendingAction::CloseTab);
+        }
+    }
+}
@@ -36,6 +36,25 @@ pub fn edit_text(name :*const i8, s :impl Into<Vec<u8>>) -> Option<String> {
     None
 }
 
+/// Multi-line text editor. The buffer is padded on every frame, so any
+/// amount of text can be entered.
+pub fn edit_text_multiline(name :*const i8, s :&mut String, size :ImVec2) -> bool {
+    let mut buf :Vec<u8> = s.as_bytes().to_vec();
+    buf.extend((0..1024).map(|_| 0));
+    unsafe {
+        igInputTextMultiline(name, buf.as_ptr() as *mut _, buf.len(), size,
+            0 as _, None, std::ptr::null_mut());
+
+        if igIsItemEdited() {
+            let terminator = buf.iter().position(|&c| c == 0).unwrap();
+            buf.truncate(terminator);
+            *s = String::from_utf8_unchecked(buf);
+            return true;
+        }
+    }
+    false
+}
+
 pub fn in_root_window(f :impl FnOnce()) {
     unsafe{
         let zero = ImVec2 { x: 0.0, y: 0.0 };
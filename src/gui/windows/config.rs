@@ -2,48 +2,43 @@ use const_cstr::*;
 use crate::config::*;
 use backend_glfw::imgui::*;
 use log::*;
+use std::ffi::CString;
 
 use crate::gui::widgets;
+use crate::gui::windows::theme;
+use crate::units::*;
+use crate::feed::FeedProtocol;
+use crate::i18n;
 
 pub fn edit_config_window(popen :&mut bool, config :&mut Config) {
     if !*popen { return; }
     unsafe {
         let win_flags = ImGuiWindowFlags__ImGuiWindowFlags_MenuBar;
         widgets::next_window_center_when_appearing();
-        if igBegin(const_cstr!("Configuration").as_ptr(), popen as _, win_flags as _) {
+        if igBegin(tr!("Configuration"), popen as _, win_flags as _) {
 
             if igBeginMenuBar() {
-                if igBeginMenu(const_cstr!("Load").as_ptr(), true) {
-                    if igMenuItemBool(const_cstr!("Save configuration").as_ptr(), std::ptr::null(), false, true) {
+                if igBeginMenu(tr!("Load"), true) {
+                    if igMenuItemBool(tr!("Save configuration"), std::ptr::null(), false, true) {
                         config.save();
                     }
-                    if igMenuItemBool(const_cstr!("Revert to saved configuration").as_ptr(), std::ptr::null(), false, true) {
+                    if igMenuItemBool(tr!("Revert to saved configuration"), std::ptr::null(), false, true) {
                         *config = Config::load();
+                        i18n::set_language(&config.language);
                     }
                     igEndMenu();
                 }
-                if igBeginMenu(const_cstr!("Themes").as_ptr(), true) {
-                    if igMenuItemBool(const_cstr!("Junction-gray").as_ptr(), std::ptr::null(), false, true) {
-                        let s = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/gray.toml"));
-                        import_string(config, s);
-                    }
-                    if igMenuItemBool(const_cstr!("Junction-dark").as_ptr(), std::ptr::null(), false, true) {
-                        let s = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/dark.toml"));
-                        import_string(config, s);
-                    }
-                    if igMenuItemBool(const_cstr!("Junction-light").as_ptr(), std::ptr::null(), false, true) {
-                        let s = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/light.toml"));
-                        import_string(config, s);
-                    }
+                if igBeginMenu(tr!("Themes"), true) {
+                    theme::themes_menu(config);
 
                     widgets::sep();
 
-                    if igMenuItemBool(const_cstr!("Import configuration...").as_ptr(), std::ptr::null(), false, true) {
+                    if igMenuItemBool(tr!("Import configuration..."), std::ptr::null(), false, true) {
                         if let Err(e) = import(config) {
                             error!("Could not import configuration: {}", e);
                         }
                     }
-                    if igMenuItemBool(const_cstr!("Export configuration...").as_ptr(), std::ptr::null(), false, true) {
+                    if igMenuItemBool(tr!("Export configuration..."), std::ptr::null(), false, true) {
                         if let Err(e) = export(config) {
                             error!("Could not export configuration: {}", e);
                         }
@@ -60,18 +55,13 @@ pub fn edit_config_window(popen :&mut bool, config :&mut Config) {
     }
 }
 
-fn import_string(config :&mut Config, s: &str) -> Result<(), ()> {
-    let data : ConfigString = toml::from_str(s).map_err(|e| ())?;
-    *config = Config::from_config_string(&data);
-    Ok(())
-}
-
 fn import(config :&mut Config) -> Result<(), std::io::Error> {
     if let Some(filename) = tinyfiledialogs::open_file_dialog("Import Junction config file", "",
                                              Some((&["*.toml"],"TOML files"))) {
         let data : ConfigString = toml::from_str(&std::fs::read_to_string(filename)?)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, "TOML conversion error"))?;
         *config = Config::from_config_string(&data);
+        i18n::set_language(&config.language);
     }
     Ok(())
 }
@@ -88,12 +78,148 @@ fn export(config :&Config) -> Result<(), std::io::Error> {
 
 pub fn edit_config(config :&mut Config) {
     unsafe {
-        for (name,color) in config.colors.iter_mut() {
-            let name = COLORNAMES[name].as_ptr();
-            igColorEdit4(name, &mut color.color.red as _, 0 as _);
+        let languages = i18n::languages();
+        let current = languages.iter().find(|(c,_)| *c == config.language)
+            .map(|(_,n)| n.clone()).unwrap_or_else(|| config.language.clone());
+        let current = CString::new(current).unwrap();
+        if igBeginCombo(tr!("Language"), current.as_ptr(), 0 as _) {
+            for (code, name) in &languages {
+                let label = CString::new(name.as_str()).unwrap();
+                if igSelectable(label.as_ptr(), *code == config.language, 0 as _, ImVec2::zero()) {
+                    config.language = code.clone();
+                    i18n::set_language(code);
+                }
+            }
+            igEndCombo();
+        }
+
+        if igCollapsingHeader(tr!("Signal spacing check"), 0) {
+            igCheckbox(tr!("Show violations on canvas"), &mut config.show_signal_spacing);
+            // The design speed is stored in km/h and edited in the chosen unit.
+            let speed_unit = config.units.speed;
+            let mut speed = speed_unit.from_mps(config.design_speed as f64 / 3.6) as f32;
+            let label = CString::new(format!("{} ({})##speed", i18n::tr("Design speed"), speed_unit.symbol())).unwrap();
+            if igInputFloat(label.as_ptr(), &mut speed, 0.0, 0.0, const_cstr!("%.1f").as_ptr(), 0 as _) {
+                config.design_speed = (speed_unit.to_mps(speed as f64) * 3.6) as f32;
+            }
+            igInputFloat(tr!("Deceleration (m/s^2)"), &mut config.design_deceleration,
+                         0.0, 0.0, const_cstr!("%.2f").as_ptr(), 0 as _);
+            widgets::sep();
         }
 
-        widgets::sep();
+        if igCollapsingHeader(tr!("Units"), 0) {
+            let length = CString::new(i18n::tr(config.units.length.name())).unwrap();
+            if igBeginCombo(tr!("Lengths"), length.as_ptr(), 0 as _) {
+                for unit in LengthUnit::ALL {
+                    let label = CString::new(i18n::tr(unit.name())).unwrap();
+                    if igSelectable(label.as_ptr(), *unit == config.units.length, 0 as _, ImVec2::zero()) {
+                        config.units.length = *unit;
+                    }
+                }
+                igEndCombo();
+            }
+            let speed = CString::new(i18n::tr(config.units.speed.name())).unwrap();
+            if igBeginCombo(tr!("Speeds"), speed.as_ptr(), 0 as _) {
+                for unit in SpeedUnit::ALL {
+                    let label = CString::new(i18n::tr(unit.name())).unwrap();
+                    if igSelectable(label.as_ptr(), *unit == config.units.speed, 0 as _, ImVec2::zero()) {
+                        config.units.speed = *unit;
+                    }
+                }
+                igEndCombo();
+            }
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("Grid"), 0) {
+            igCheckbox(tr!("Show grid"), &mut config.grid.visible);
+            igInputInt(tr!("Major grid lines every (0 = none)"), &mut config.grid.major, 1, 5, 0 as _);
+            config.grid.major = config.grid.major.max(0);
+            igInputInt(tr!("Divisions per grid unit"), &mut config.grid.minor, 1, 5, 0 as _);
+            config.grid.minor = config.grid.minor.max(1).min(50);
+            igCheckbox(tr!("Snap inserted objects to divisions"), &mut config.grid.snap_objects);
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("Editing"), 0) {
+            igInputInt(tr!("Undo steps (0 = unlimited)"), &mut config.undo_depth, 10, 100, 0 as _);
+            config.undo_depth = config.undo_depth.max(0);
+            igInputInt(tr!("Backups kept on save"), &mut config.backup_count, 1, 5, 0 as _);
+            config.backup_count = config.backup_count.max(0);
+            igInputInt(tr!("Undo steps saved in the document"), &mut config.saved_undo_steps, 10, 100, 0 as _);
+            config.saved_undo_steps = config.saved_undo_steps.max(0);
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("API server"), 0) {
+            igInputInt(tr!("HTTP port"), &mut config.api_port, 1, 100, 0 as _);
+            config.api_port = config.api_port.max(1).min(65534);
+            widgets::show_text(&i18n::tr_fmt("WebSocket port: {}", &[&(config.api_port + 1).to_string()]));
+            if let Some(s) = widgets::edit_text(tr!("Allowed web page origins"), config.api_origins.join(", ")) {
+                config.api_origins = s.split(',').map(|o| o.trim().to_string()).collect();
+            }
+            if igIsItemHovered(0) {
+                igSetTooltip(tr!("Comma-separated, like http://localhost:8080. Takes effect when the server is restarted."));
+            }
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("Train position feed"), 0) {
+            widgets::show_text(i18n::tr("Sends trains, signals and routes during dispatch playback."));
+            let protocol = CString::new(config.feed.protocol.name()).unwrap();
+            if igBeginCombo(tr!("Protocol"), protocol.as_ptr(), 0 as _) {
+                for p in FeedProtocol::ALL {
+                    let label = CString::new(p.name()).unwrap();
+                    if igSelectable(label.as_ptr(), *p == config.feed.protocol, 0 as _, ImVec2::zero()) {
+                        config.feed.protocol = *p;
+                    }
+                }
+                igEndCombo();
+            }
+            if let Some(s) = widgets::edit_text(tr!("Address (host:port)"), config.feed.address.clone()) {
+                config.feed.address = s;
+            }
+            if config.feed.protocol == FeedProtocol::Mqtt {
+                if let Some(s) = widgets::edit_text(tr!("MQTT topic"), config.feed.topic.clone()) {
+                    config.feed.topic = s;
+                }
+            }
+            igInputFloat(tr!("Messages per second"), &mut config.feed.rate,
+                         1.0, 5.0, const_cstr!("%.1f").as_ptr(), 0 as _);
+            config.feed.rate = config.feed.rate.max(0.1).min(100.0);
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("Keyboard shortcuts"), 0) {
+            widgets::show_text(i18n::tr("Keys are written like Ctrl+Shift+Z, Space or Del. Leave empty for no key."));
+            let commands = config.keys.iter().map(|(c,_)| c).collect::<Vec<_>>();
+            for (i,cmd) in commands.into_iter().enumerate() {
+                igPushIDInt(i as _);
+                let label = CString::new(format!("{}##key", i18n::tr(cmd.name()))).unwrap();
+                // Invalid text is ignored; the field keeps it while it is being edited.
+                if let Some(s) = widgets::edit_text(label.as_ptr(), config.key_text(cmd)) {
+                    if s.trim().is_empty() {
+                        config.keys[cmd] = None;
+                    } else if let Some(k) = KeyBinding::parse(&s) {
+                        config.keys[cmd] = Some(k);
+                    }
+                }
+                igPopID();
+            }
+            for (c1,c2) in config.key_conflicts() {
+                widgets::show_text(&i18n::tr_fmt("Conflict: \"{}\" and \"{}\" both use {}",
+                                                 &[i18n::tr(c1.name()), i18n::tr(c2.name()), &config.key_text(c1)]));
+            }
+            if igButton(tr!("Reset to default keys"), ImVec2::zero()) {
+                config.keys = default_keys();
+            }
+            widgets::sep();
+        }
+
+        if igCollapsingHeader(tr!("Colors"), ImGuiTreeNodeFlags__ImGuiTreeNodeFlags_DefaultOpen as _) {
+            theme::edit_theme(config);
+            widgets::sep();
+        }
 
         igPushIDInt(9123 as _);
         igShowStyleEditor(std::ptr::null_mut());
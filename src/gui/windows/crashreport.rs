@@ -0,0 +1,31 @@
+use backend_glfw::imgui::*;
+use std::ffi::CString;
+
+use crate::gui::widgets;
+use crate::i18n;
+
+/// Tell the user where the crash report from the previous run was written.
+pub fn crash_report_window(report :&mut Option<String>) {
+    let dir = match report { Some(d) => d.clone(), None => return };
+    unsafe {
+        let name = tr!("Junction closed unexpectedly");
+        if !igIsPopupOpen(name) { igOpenPopup(name); }
+        if igBeginPopupModal(name, std::ptr::null_mut(), 0 as _) {
+            widgets::show_text(i18n::tr("Junction crashed the last time it was used. A crash report was saved in:"));
+            widgets::show_text(&dir);
+            widgets::show_text(i18n::tr("Please send the files in this folder with your bug report."));
+            widgets::show_text(i18n::tr("The folder contains the model that was open."));
+
+            if igButton(tr!("Copy path"), ImVec2 { x: 80.0, y: 0.0 }) {
+                let path = CString::new(dir).unwrap();
+                igSetClipboardText(path.as_ptr());
+            }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Close"), ImVec2 { x: 80.0, y: 0.0 }) {
+                *report = None;
+                igCloseCurrentPopup();
+            }
+            igEndPopup();
+        }
+    }
+}
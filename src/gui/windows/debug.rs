@@ -14,20 +14,20 @@ pub fn debug_window(mut popen :bool, app :&app::App, inf_canvas :Option<&Draw>,
     if !popen { return popen; }
     unsafe {
     widgets::next_window_center_when_appearing();
-    igBegin(const_cstr!("View data").as_ptr(), &mut popen as _, 0 as _);
+    igBegin(tr!("View data"), &mut popen as _, 0 as _);
     igPushTextWrapPos(0.0);
 
     let defaultopen = ImGuiTreeNodeFlags__ImGuiTreeNodeFlags_DefaultOpen;
 
-    if igTreeNodeExStr(const_cstr!("Application state").as_ptr(), defaultopen as _) {
+    if igTreeNodeExStr(tr!("Application state"), defaultopen as _) {
         widgets::show_text(&format!("{:#?}", app.document.fileinfo));
         //ui::show_text(&app.document.viewmodel.info());
 
-        //if igTreeNodeStr(const_cstr!("Canvas").as_ptr()) {
+        //if igTreeNodeStr(tr!("Canvas")) {
         //    ui::show_text(&format!("{:#?}", allstate.canvas));
         //    igTreePop();
         //}
-        //if igTreeNodeStr(const_cstr!("Diagram").as_ptr()) {
+        //if igTreeNodeStr(tr!("Diagram")) {
         //    ui::show_text(&format!("{:#?}", allstate.diagram));
         //    igTreePop();
         //}
@@ -35,42 +35,42 @@ pub fn debug_window(mut popen :bool, app :&app::App, inf_canvas :Option<&Draw>,
     }
 
 
-    if igTreeNodeStr(const_cstr!("Model").as_ptr()) {
+    if igTreeNodeStr(tr!("Model")) {
         // TODO threads 
         //ui::show_text(&allstate.viewmodel.get_undoable().info());
 
         let model = app.document.analysis.model();
 
-        if igTreeNodeStr(const_cstr!("Line segments").as_ptr()) {
+        if igTreeNodeStr(tr!("Line segments")) {
             widgets::show_text(&format!("{:#?}", model.linesegs));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Objects").as_ptr()) {
+        if igTreeNodeStr(tr!("Objects")) {
             widgets::show_text(&format!("{:#?}", model.objects));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Node data override").as_ptr()) {
+        if igTreeNodeStr(tr!("Node data override")) {
             widgets::show_text(&format!("{:#?}", model.node_data));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Vehicles").as_ptr()) {
+        if igTreeNodeStr(tr!("Vehicles")) {
             widgets::show_text(&format!("{:#?}", model.vehicles));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Dispatches").as_ptr()) {
+        if igTreeNodeStr(tr!("Dispatches")) {
             widgets::show_text(&format!("{:#?}", model.dispatches));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Plans").as_ptr()) {
+        if igTreeNodeStr(tr!("Plans")) {
             widgets::show_text(&format!("{:#?}", model.plans));
             igTreePop();
         }
         igTreePop();
     }
 
-    if igTreeNodeStr(const_cstr!("Derived data / view model").as_ptr()) {
+    if igTreeNodeStr(tr!("Derived data / view model")) {
         let derived = app.document.analysis.data();
-        if igTreeNodeStr(const_cstr!("Topology").as_ptr()) {
+        if igTreeNodeStr(tr!("Topology")) {
             widgets::show_text(&format!("{:#?}", derived.topology));
             igTreePop();
         }
@@ -78,7 +78,7 @@ pub fn debug_window(mut popen :bool, app :&app::App, inf_canvas :Option<&Draw>,
             widgets::show_text(&format!("{:#?}", derived.dgraph));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("All paths").as_ptr()) {
+        if igTreeNodeStr(tr!("All paths")) {
             if let Some((_,dgraph)) = &derived.dgraph {
                 let (l,paths) = &dgraph.all_paths;
                 widgets::show_text(&format!("{} paths, with equality length margin {:.3}m", paths.len(), l));
@@ -90,11 +90,11 @@ pub fn debug_window(mut popen :bool, app :&app::App, inf_canvas :Option<&Draw>,
             }
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Interlocking").as_ptr()) {
+        if igTreeNodeStr(tr!("Interlocking")) {
             widgets::show_text(&format!("{:#?}", derived.interlocking));
             igTreePop();
         }
-        if igTreeNodeStr(const_cstr!("Dispatch").as_ptr()) {
+        if igTreeNodeStr(tr!("Dispatch")) {
             widgets::show_text(&format!("{:#?}", derived.dispatch));
             igTreePop();
         }
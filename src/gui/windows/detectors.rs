@@ -0,0 +1,60 @@
+use backend_glfw::imgui::*;
+use crate::gui::widgets;
+use crate::gui::widgets::Draw;
+use crate::config::*;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::detector_placement::*;
+use crate::i18n;
+
+/// Show proposed detector locations. While the window is open, the
+/// proposals are drawn on the canvas, and tracks which would be part of
+/// an undetectable section are highlighted.
+pub fn detector_placement_window(popen :&mut bool, config :&Config, analysis :&mut Analysis,
+                                 inf_canvas :Option<&Draw>, inf_view :&InfView) {
+    if !*popen { return; }
+    let topo = match &analysis.data().topology { Some((_,t)) => t.clone(), None => return };
+    let proposals = propose(&topo);
+
+    unsafe {
+        widgets::next_window_center_when_appearing();
+        igBegin(tr!("Detector placement"), popen as _, 0 as _);
+
+        let between = proposals.iter().filter(|p| p.reason == Reason::BetweenSwitches).count();
+        let at_signal = proposals.iter().filter(|p| p.reason == Reason::AtSignal).count();
+        widgets::show_text(&format!("{} tracks between switches without detectors.", between));
+        widgets::show_text(&format!("{} signals without a section boundary.", at_signal));
+
+        if proposals.len() > 0 {
+            if igButton(tr!("Insert proposed detectors"), ImVec2::zero()) {
+                analysis.edit_model(|m| {
+                    apply(m, &proposals);
+                    None
+                });
+            }
+        } else {
+            widgets::show_text(i18n::tr("No detectors needed."));
+        }
+
+        igEnd();
+    }
+
+    if let Some(canvas) = inf_canvas {
+        let track_color = config.color_u32(RailUIColorName::CanvasNodeError);
+        let detector_color = config.color_u32(RailUIColorName::CanvasTrackDrawing);
+        for p in proposals.iter() {
+            unsafe {
+                if p.reason == Reason::BetweenSwitches {
+                    for (a,b) in topo.track_segments[p.track_idx].iter() {
+                        ImDrawList_AddLine(canvas.draw_list,
+                                           canvas.pos + inf_view.view.world_pt_to_screen(*a),
+                                           canvas.pos + inf_view.view.world_pt_to_screen(*b),
+                                           track_color, 6.0);
+                    }
+                }
+                let pos = canvas.pos + inf_view.view.world_ptc_to_screen(p.loc);
+                ImDrawList_AddCircle(canvas.draw_list, pos, 8.0, detector_color, 12, 2.0);
+            }
+        }
+    }
+}
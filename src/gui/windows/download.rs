@@ -0,0 +1,144 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::sync::mpsc;
+
+use crate::app::{App, BackgroundJobs, PendingAction};
+use crate::config::RecentFileKind;
+use crate::document::Document;
+use crate::file;
+use crate::gui::widgets;
+use crate::i18n;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DownloadKind {
+    Document,
+    RailML,
+}
+
+/// Downloads a document or railML file from a URL in the background and
+/// opens it when done.
+pub struct DownloadWindow {
+    url :String,
+    kind :DownloadKind,
+    rx :Option<mpsc::Receiver<Result<Vec<u8>, String>>>,
+    error :Option<String>,
+}
+
+impl DownloadWindow {
+    pub fn new() -> Self {
+        DownloadWindow { url: String::new(), kind: DownloadKind::Document, rx: None, error: None }
+    }
+
+    pub fn start(&mut self, bg :&mut BackgroundJobs, url :String, kind :DownloadKind) {
+        let (tx, rx) = mpsc::channel();
+        self.url = url.clone();
+        self.kind = kind;
+        self.rx = Some(rx);
+        self.error = None;
+        bg.execute(move || { let _ = tx.send(file::download(&url)); });
+    }
+
+    fn is_open(&self) -> bool { self.rx.is_some() || self.error.is_some() }
+}
+
+/// Ask the user for a URL to open or import.
+pub fn ask_url(kind :DownloadKind) -> Option<PendingAction> {
+    let title = match kind {
+        DownloadKind::Document => "Open URL",
+        DownloadKind::RailML => "Import railML from URL",
+    };
+    let url = tinyfiledialogs::input_box(title, "http(s) URL:", "")?;
+    let url = url.trim().to_string();
+    if url.is_empty() { return None; }
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        error!("Not an http(s) URL: {:?}", url);
+        return None;
+    }
+    Some(match kind {
+        DownloadKind::Document => PendingAction::OpenUrl(url),
+        DownloadKind::RailML => PendingAction::ImportUrl(url),
+    })
+}
+
+/// The last part of the URL path, without the query and fragment, for
+/// naming the downloaded file.
+fn file_name(url :&str) -> &str {
+    let rest = url.splitn(2, "://").last().unwrap_or(url);
+    let path = rest.split(|c| c == '?' || c == '#').next().unwrap_or("");
+    match path.find('/') {
+        Some(i) => path[i..].rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("download.railml"),
+        None => "download.railml",
+    }
+}
+
+fn finish(app :&mut App, url :String, kind :DownloadKind, data :Vec<u8>) -> Result<(), String> {
+    match kind {
+        DownloadKind::Document => {
+            let model = file::from_bytes(&data)?;
+            app.document = Document::from_model(model, app.background_jobs.clone());
+            app.document.fileinfo.set_unsaved();
+            app.config.add_recent_file(url, RecentFileKind::Document);
+        },
+        DownloadKind::RailML => {
+            // The importer reads from a file, which also handles compressed downloads.
+            let path = std::env::temp_dir().join(format!("junction-{}", file_name(&url)));
+            std::fs::write(&path, &data).map_err(|e| format!("{}", e))?;
+            app.windows.import_window.open();
+            app.windows.import_window.background_load_file_as(path.to_string_lossy().to_string(), url);
+        },
+    }
+    Ok(())
+}
+
+pub fn download_window(app :&mut App) {
+    let win = &mut app.windows.download;
+    let received = match win.rx.as_ref().map(|rx| rx.try_recv()) {
+        Some(Ok(result)) => Some(result),
+        // The download job ended without a result, e.g. by panicking.
+        Some(Err(mpsc::TryRecvError::Disconnected)) => Some(Err("the download stopped unexpectedly".to_string())),
+        Some(Err(mpsc::TryRecvError::Empty)) | None => None,
+    };
+    if let Some(result) = received {
+        win.rx = None;
+        let (url, kind) = (win.url.clone(), win.kind);
+        match result.and_then(|data| finish(app, url, kind, data)) {
+            Ok(()) => {},
+            Err(e) => {
+                error!("Could not open {:?}: {}", app.windows.download.url, e);
+                app.windows.download.error = Some(e);
+            },
+        }
+    }
+
+    let win = &mut app.windows.download;
+    if !win.is_open() { return; }
+    unsafe {
+        let mut open = true;
+        widgets::next_window_center_when_appearing();
+        igBegin(tr!("Download"), &mut open as _, 0 as _);
+        widgets::show_text(&win.url);
+        match &win.error {
+            Some(e) => widgets::show_text(&format!("Download failed: {}", e)),
+            None => widgets::show_text(i18n::tr("Downloading...")),
+        }
+        igEnd();
+        if !open {
+            // A running download finishes in the background, but its result is dropped.
+            win.rx = None;
+            win.error = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_without_query() {
+        assert_eq!(file_name("https://example.com/data/station.railml?token=1#top"), "station.railml");
+        assert_eq!(file_name("https://example.com/a.xml#x"), "a.xml");
+        assert_eq!(file_name("https://example.com/?file=a.xml"), "download.railml");
+        assert_eq!(file_name("https://example.com"), "download.railml");
+    }
+}
@@ -0,0 +1,72 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
+
+use crate::export::IdReport;
+use crate::gui::widgets;
+use crate::i18n;
+
+/// What became of the imported ids in the last railML export, so that
+/// changed and lost ids can be checked before the file is used elsewhere.
+pub struct ExportReportWindow {
+    pub open :bool,
+    filename :String,
+    report :IdReport,
+}
+
+impl ExportReportWindow {
+    pub fn new() -> Self {
+        ExportReportWindow {
+            open: false,
+            filename: String::new(),
+            report: IdReport::default(),
+        }
+    }
+
+    pub fn show(&mut self, filename :String, report :IdReport) {
+        self.filename = filename;
+        self.report = report;
+        self.open = true;
+    }
+
+    pub fn draw(&mut self) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Exported ids"), &mut self.open as _, 0 as _);
+            widgets::show_text(&i18n::tr_fmt("Exported to {}", &[&self.filename]));
+            let changed = self.report.changed().collect::<Vec<_>>();
+            widgets::show_text(&i18n::tr_fmt("{} imported ids kept, {} changed and {} lost. {} new ids.", &[
+                &self.report.kept().to_string(),
+                &changed.len().to_string(),
+                &self.report.lost.len().to_string(),
+                &self.report.generated.len().to_string(),
+            ]));
+            if igButton(tr!("Copy as CSV"), ImVec2::zero()) {
+                match CString::new(self.report.to_csv()) {
+                    Ok(csv) => { igSetClipboardText(csv.as_ptr()); },
+                    Err(e) => { error!("Error copying id report: {}", e); },
+                }
+            }
+            widgets::sep();
+
+            let open = ImGuiTreeNodeFlags__ImGuiTreeNodeFlags_DefaultOpen as _;
+            if !changed.is_empty() && igCollapsingHeader(tr!("Changed ids"), open) {
+                for (original, exported) in &changed {
+                    widgets::show_text(&format!("{} \u{2192} {}", original, exported));
+                }
+            }
+            if !self.report.lost.is_empty() && igCollapsingHeader(tr!("Lost ids"), open) {
+                for id in &self.report.lost {
+                    widgets::show_text(id);
+                }
+            }
+            if !self.report.generated.is_empty() && igCollapsingHeader(tr!("New ids"), 0) {
+                for id in &self.report.generated {
+                    widgets::show_text(id);
+                }
+            }
+            igEnd();
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::collections::HashSet;
+use std::ffi::CString;
+
+use crate::document::Document;
+use crate::export::{self, ExportScope, RailMLVersion};
+use crate::gui::widgets;
+use crate::i18n;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ScopeKind { Selection, Groups, Ocps, Lines }
+
+/// Export of a part of the infrastructure to railML: the selection, chosen
+/// selection groups, or the tracks of chosen stations or lines.
+pub struct ExportScopeWindow {
+    pub open :bool,
+    version :RailMLVersion,
+    kind :ScopeKind,
+    /// Ids of the chosen selection groups, as strings for `choose`.
+    groups :HashSet<String>,
+    ocps :HashSet<String>,
+    lines :HashSet<String>,
+}
+
+/// Checkboxes for choosing some of the `(id, name)` items, or the text
+/// `none` if there are no items.
+fn choose(items :impl Iterator<Item = (String, String)>, chosen :&mut HashSet<String>, none :&str) {
+    let mut any = false;
+    for (i, (id, name)) in items.enumerate() {
+        any = true;
+        unsafe {
+            igPushIDInt(i as _);
+            let mut on = chosen.contains(&id);
+            let label = CString::new(name).unwrap();
+            if igCheckbox(label.as_ptr(), &mut on) {
+                if on { chosen.insert(id); } else { chosen.remove(&id); }
+            }
+            igPopID();
+        }
+    }
+    if !any { widgets::show_text(none); }
+}
+
+impl ExportScopeWindow {
+    pub fn new() -> Self {
+        ExportScopeWindow {
+            open: false,
+            version: RailMLVersion::V25,
+            kind: ScopeKind::Selection,
+            groups: HashSet::new(),
+            ocps: HashSet::new(),
+            lines: HashSet::new(),
+        }
+    }
+
+    fn scope(&self, document :&Document) -> ExportScope {
+        match self.kind {
+            ScopeKind::Selection => ExportScope::Selection(document.inf_view.selection.clone()),
+            ScopeKind::Groups => {
+                let model = document.analysis.model();
+                ExportScope::Selection(model.selection_groups.iter()
+                    .filter(|(id,_)| self.groups.contains(&id.to_string()))
+                    .flat_map(|(_,g)| model.group_selection(g)).collect())
+            },
+            ScopeKind::Ocps => ExportScope::Ocps(self.ocps.iter().cloned().collect()),
+            ScopeKind::Lines => ExportScope::Lines(self.lines.iter().cloned().collect()),
+        }
+    }
+
+    fn export(&self, document :&Document) {
+        let model = document.analysis.model();
+        let title = format!("Export part to {}", self.version.name());
+        let filename = match tinyfiledialogs::save_file_dialog(&title, "") {
+            Some(f) => f,
+            None => { info!("User cancelled railML export"); return; },
+        };
+        let result = export::scoped_model(model, &self.scope(document))
+            .and_then(|part| export::export_railml_to_file(&filename, &part, self.version));
+        match result {
+            Ok(_) => info!("Exported part of the model to {:?}", filename),
+            Err(e) => error!("Error exporting railML: {}", e),
+        }
+    }
+
+    pub fn draw(&mut self, document :&Document) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Export part to railML"), &mut self.open as _, 0 as _);
+            if let Some(v) = widgets::radio_select(&[
+                    (tr!("railML 2.5"), self.version == RailMLVersion::V25, RailMLVersion::V25),
+                    (tr!("railML 3.2"), self.version == RailMLVersion::V3, RailMLVersion::V3)]) {
+                self.version = *v;
+            }
+            widgets::sep();
+            if let Some(k) = widgets::radio_select(&[
+                    (tr!("Selection"), self.kind == ScopeKind::Selection, ScopeKind::Selection),
+                    (tr!("Selection groups"), self.kind == ScopeKind::Groups, ScopeKind::Groups),
+                    (tr!("Stations"), self.kind == ScopeKind::Ocps, ScopeKind::Ocps),
+                    (tr!("Lines"), self.kind == ScopeKind::Lines, ScopeKind::Lines)]) {
+                self.kind = *k;
+            }
+
+            let model = document.analysis.model();
+            let ready = match self.kind {
+                ScopeKind::Selection => {
+                    let n = document.inf_view.selection.len();
+                    widgets::show_text(&i18n::tr_fmt("{} elements selected.", &[&n.to_string()]));
+                    n > 0
+                },
+                ScopeKind::Groups => {
+                    choose(model.selection_groups.iter().map(|(id,g)| (id.to_string(), g.name.clone())),
+                           &mut self.groups, i18n::tr("No selection groups."));
+                    !self.groups.is_empty()
+                },
+                ScopeKind::Ocps => {
+                    choose(model.railml_ocps.iter().map(|o| (o.id.clone(), o.name.clone().unwrap_or_else(|| o.id.clone()))),
+                           &mut self.ocps, i18n::tr("None were imported."));
+                    !self.ocps.is_empty()
+                },
+                ScopeKind::Lines => {
+                    choose(model.railml_track_groups.iter().map(|g| {
+                        (g.id.clone(), g.name.clone().or(g.code.clone()).unwrap_or_else(|| g.id.clone()))
+                    }), &mut self.lines, i18n::tr("None were imported."));
+                    !self.lines.is_empty()
+                },
+            };
+            widgets::show_text(i18n::tr("Tracks cut off at the edge of the part become open ends."));
+
+            widgets::sep();
+            if ready {
+                if igButton(tr!("Export..."), ImVec2::zero()) { self.export(document); }
+            } else {
+                widgets::show_text(i18n::tr("Nothing to export."));
+            }
+            igEnd();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::time::{Instant, Duration, SystemTime};
+
+use crate::app::PendingAction;
+use crate::document::Document;
+use crate::file;
+use crate::gui::widgets;
+use crate::i18n;
+
+const CHECK_INTERVAL :Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+enum Changed {
+    Document(String),
+    Source(String),
+}
+
+/// Polls the open document and the railML file it was imported from for
+/// modifications made by other programs, and asks the user whether to
+/// reload.
+pub struct FileChangeWindow {
+    last_check :Instant,
+    /// Imported source file and its modification time when first seen.
+    source :Option<(String, Option<SystemTime>)>,
+    changed :Option<Changed>,
+}
+
+impl FileChangeWindow {
+    pub fn new() -> Self {
+        FileChangeWindow {
+            last_check: Instant::now(),
+            source: None,
+            changed: None,
+        }
+    }
+
+    fn check(&mut self, doc :&mut Document) {
+        if self.changed.is_some() || self.last_check.elapsed() < CHECK_INTERVAL { return; }
+        self.last_check = Instant::now();
+
+        if let Some(filename) = &doc.fileinfo.filename {
+            let modified = file::modified_time(filename);
+            if modified.is_some() && modified != doc.fileinfo.modified {
+                info!("Document {:?} was changed by another program", filename);
+                doc.fileinfo.modified = modified;
+                self.changed = Some(Changed::Document(filename.clone()));
+                return;
+            }
+        }
+
+        // The file the document was imported from, or else the file name
+        // of the embedded railML source.
+        let model = doc.analysis.model();
+        let source = model.file_settings.import.as_ref().map(|s| s.filename.clone())
+            .or_else(|| model.railml_source.as_ref().map(|s| s.filename.clone()));
+        match (source, &mut self.source) {
+            (Some(filename), Some((watched, time))) if filename == *watched => {
+                let modified = file::modified_time(&filename);
+                if modified.is_some() && modified != *time {
+                    info!("railML source {:?} was changed by another program", filename);
+                    *time = modified;
+                    self.changed = Some(Changed::Source(filename));
+                }
+            },
+            (Some(filename), _) => {
+                let modified = file::modified_time(&filename);
+                self.source = Some((filename, modified));
+            },
+            (None, _) => { self.source = None; },
+        }
+    }
+
+    pub fn draw(&mut self, doc :&mut Document, pending_action :&mut Option<PendingAction>) {
+        self.check(doc);
+        let changed = match &self.changed { Some(c) => c.clone(), None => return };
+        unsafe {
+            let name = tr!("File changed");
+            if !igIsPopupOpen(name) { igOpenPopup(name); }
+            if igBeginPopupModal(name, std::ptr::null_mut(), 0 as _) {
+                match &changed {
+                    Changed::Document(f) => {
+                        widgets::show_text(&format!("The document {} was changed by another program.", f));
+                        if doc.fileinfo.unsaved {
+                            widgets::show_text(i18n::tr("Reloading will discard your unsaved changes."));
+                        }
+                    },
+                    Changed::Source(f) => {
+                        widgets::show_text(&format!("The railML file {} was changed by another program.", f));
+                        widgets::show_text(i18n::tr("Import it again?"));
+                    },
+                }
+
+                if igButton(tr!("Reload"), ImVec2 { x: 80.0, y: 0.0 }) {
+                    *pending_action = Some(match changed {
+                        Changed::Document(f) => {
+                            // The user has already chosen to discard changes here.
+                            doc.fileinfo.unsaved = false;
+                            PendingAction::LoadFile(f)
+                        },
+                        Changed::Source(_) => PendingAction::ReloadSource,
+                    });
+                    self.changed = None;
+                    igCloseCurrentPopup();
+                }
+                igSameLine(0.0, -1.0);
+                if igButton(tr!("Ignore"), ImVec2 { x: 80.0, y: 0.0 }) {
+                    self.changed = None;
+                    igCloseCurrentPopup();
+                }
+                igEndPopup();
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::model::*;
+use crate::gui::infrastructure::delete_selection;
+use crate::gui::widgets;
+use crate::i18n;
+
+/// Named selection groups saved in the document. A group is selected again
+/// on the canvas, where it can be moved, transformed or edited like any
+/// selection, and it can be exported from the railML part export window.
+pub struct GroupsWindow {
+    pub open :bool,
+    new_name :String,
+}
+
+enum Action {
+    Select(usize),
+    AddSelection(usize),
+    DeleteElements(usize),
+    Rename(usize, String),
+    Remove(usize),
+}
+
+impl GroupsWindow {
+    pub fn new() -> Self {
+        GroupsWindow { open: false, new_name: String::new() }
+    }
+
+    pub fn draw(&mut self, analysis :&mut Analysis, inf_view :&mut InfView) {
+        if !self.open { return; }
+        let mut action = None;
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Selection groups"), &mut self.open as _, 0 as _);
+
+            if let Some(name) = widgets::edit_text(tr!("Name"), self.new_name.clone()) {
+                self.new_name = name;
+            }
+            let can_save = !inf_view.selection.is_empty() && !self.new_name.trim().is_empty();
+            if can_save {
+                if igButton(tr!("Save selection as group"), ImVec2::zero()) {
+                    let group = SelectionGroup {
+                        name: self.new_name.trim().to_string(),
+                        refs: inf_view.selection.iter().cloned().collect(),
+                    };
+                    analysis.edit_model(|m| { m.selection_groups.insert(group); None });
+                    self.new_name.clear();
+                }
+            } else {
+                widgets::show_text(i18n::tr("Select elements and enter a name to save them as a group."));
+            }
+
+            widgets::sep();
+            let model = analysis.model();
+            let mut any = false;
+            for (id,group) in model.selection_groups.iter() {
+                any = true;
+                igPushIDInt(*id as _);
+                if let Some(name) = widgets::edit_text(const_cstr!("##gnm").as_ptr(), group.name.clone()) {
+                    action = Some(Action::Rename(*id, name));
+                }
+                igSameLine(0.0,-1.0);
+                let n = model.group_selection(group).len();
+                widgets::show_text(&i18n::tr_fmt("{} elements", &[&n.to_string()]));
+                if igButton(tr!("Select"), ImVec2::zero()) { action = Some(Action::Select(*id)); }
+                igSameLine(0.0,-1.0);
+                if igButton(tr!("Add selection"), ImVec2::zero()) { action = Some(Action::AddSelection(*id)); }
+                igSameLine(0.0,-1.0);
+                if igButton(tr!("Delete elements"), ImVec2::zero()) { action = Some(Action::DeleteElements(*id)); }
+                igSameLine(0.0,-1.0);
+                if igButton(tr!("Delete group"), ImVec2::zero()) { action = Some(Action::Remove(*id)); }
+                igPopID();
+            }
+            if !any { widgets::show_text(i18n::tr("No selection groups.")); }
+            igEnd();
+        }
+
+        match action {
+            Some(Action::Select(id)) => {
+                if let Some(group) = analysis.model().selection_groups.get(id) {
+                    inf_view.selection = analysis.model().group_selection(group);
+                }
+            },
+            Some(Action::AddSelection(id)) => {
+                let selection = inf_view.selection.clone();
+                analysis.edit_model(|m| {
+                    if let Some(group) = m.selection_groups.get_mut(id) {
+                        for r in selection {
+                            if !group.refs.contains(&r) { group.refs.push(r); }
+                        }
+                    }
+                    None
+                });
+            },
+            Some(Action::DeleteElements(id)) => {
+                let model = analysis.model();
+                let selection = model.selection_groups.get(id).map(|g| model.group_selection(g)).unwrap_or_default();
+                if !selection.is_empty() {
+                    inf_view.selection = selection;
+                    delete_selection(analysis, inf_view);
+                }
+            },
+            Some(Action::Rename(id, name)) => {
+                analysis.edit_model(|m| {
+                    if let Some(g) = m.selection_groups.get_mut(id) { g.name = name; }
+                    Some(EditClass::GroupName(id))
+                });
+            },
+            Some(Action::Remove(id)) => {
+                analysis.edit_model(|m| { m.selection_groups.remove(id); None });
+            },
+            None => {},
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use backend_glfw::imgui::*;
+use std::ffi::CString;
+use crate::gui::widgets;
+use crate::config::Config;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::lint::*;
+use crate::document::analysis::Generation;
+use crate::plugins::Plugins;
+use crate::i18n;
+
+pub struct LintWindow {
+    pub open :bool,
+    pub show_errors :bool,
+    pub show_warnings :bool,
+    pub show_info :bool,
+    /// Issues from the last run of the plugin checks.
+    plugin_issues :Option<(Generation, Vec<Issue>)>,
+}
+
+impl LintWindow {
+    pub fn new() -> Self {
+        LintWindow {
+            open: false,
+            show_errors: true,
+            show_warnings: true,
+            show_info: true,
+            plugin_issues: None,
+        }
+    }
+
+    fn shown(&self, severity :Severity) -> bool {
+        match severity {
+            Severity::Error => self.show_errors,
+            Severity::Warning => self.show_warnings,
+            Severity::Info => self.show_info,
+        }
+    }
+
+    /// List the model issues. Clicking an issue selects the referenced
+    /// element and centers the infrastructure view on it.
+    pub fn draw(&mut self, config :&Config, analysis :&Analysis, inf_view :&mut InfView, plugins :&Plugins) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Model issues"), &mut self.open as _, 0 as _);
+
+            let mut issues = match &analysis.data().lint {
+                Some((_,issues)) => (**issues).clone(),
+                None => {
+                    widgets::show_text(i18n::tr("Checking model..."));
+                    igEnd();
+                    return;
+                },
+            };
+
+            if config.show_signal_spacing {
+                if let Some((_,il)) = &analysis.data().interlocking {
+                    issues.extend(signal_spacing(analysis.model(), il,
+                        config.design_speed as f64, config.design_deceleration as f64, &config.units));
+                    issues.sort_by_key(|i| i.severity);
+                }
+            }
+
+            // Plugin checks can be slow, so they are run on request and kept
+            // until the model changes.
+            if plugins.plugins.iter().any(|p| p.info.checks) {
+                if igButton(tr!("Run plugin checks"), ImVec2::zero()) {
+                    self.plugin_issues = Some((*analysis.generation(), plugins.check(analysis.model())));
+                }
+            }
+            if let Some((generation, plugin_issues)) = &self.plugin_issues {
+                if generation == analysis.generation() {
+                    issues.extend(plugin_issues.iter().cloned());
+                    issues.sort_by_key(|i| i.severity);
+                }
+            }
+
+            let count = |s :Severity| issues.iter().filter(|i| i.severity == s).count();
+            let label = CString::new(format!("Errors ({})", count(Severity::Error))).unwrap();
+            igCheckbox(label.as_ptr(), &mut self.show_errors);
+            igSameLine(0.0,-1.0);
+            let label = CString::new(format!("Warnings ({})", count(Severity::Warning))).unwrap();
+            igCheckbox(label.as_ptr(), &mut self.show_warnings);
+            igSameLine(0.0,-1.0);
+            let label = CString::new(format!("Info ({})", count(Severity::Info))).unwrap();
+            igCheckbox(label.as_ptr(), &mut self.show_info);
+            widgets::sep();
+
+            if issues.is_empty() {
+                widgets::show_text(i18n::tr("No issues found."));
+            }
+
+            for (i,issue) in issues.iter().enumerate() {
+                if !self.shown(issue.severity) { continue; }
+                igPushIDInt(i as _);
+                let text = CString::new(format!("{}: {}", issue.severity.name(), issue.message)).unwrap();
+                let selected = inf_view.selection.len() == 1 &&
+                    inf_view.selection.contains(&issue.location);
+                if igSelectable(text.as_ptr(), selected, 0 as _, ImVec2::zero()) {
+                    inf_view.selection = std::iter::once(issue.location).collect();
+                    inf_view.pending_center = Some(issue.pos());
+                }
+                igPopID();
+            }
+
+            igEnd();
+        }
+    }
+}
@@ -1,18 +1,18 @@
 use backend_glfw::imgui::*;
-use const_cstr::*;
 use std::collections::VecDeque;
 use log::*;
 use std::sync::Arc;
 use std::sync::Mutex;
 use crate::gui::widgets;
+use crate::i18n;
 
 pub fn view_log(popen :&mut bool, logstring :&Arc<Mutex<VecDeque<u8>>>) {
     if !*popen { return; }
 
     unsafe {
         widgets::next_window_center_when_appearing();
-        igBegin(const_cstr!("Log").as_ptr(), popen as _, 0 as _);
-        widgets::show_text("Log:");
+        igBegin(tr!("Log"), popen as _, 0 as _);
+        widgets::show_text(i18n::tr("Log:"));
         let buf = logstring.lock().unwrap();
         let (s1,s2) = buf.as_slices();
         widgets::long_text(std::str::from_utf8_unchecked(s1));
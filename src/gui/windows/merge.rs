@@ -0,0 +1,116 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
+
+use crate::document::analysis::Analysis;
+use crate::document::merge::*;
+use crate::file;
+use crate::gui::widgets;
+use crate::i18n;
+
+/// Merge the open document with another version of it, given their common
+/// ancestor. The open document is "ours" and the other version "theirs".
+pub struct MergeWindow {
+    pub open :bool,
+    base_file :String,
+    their_file :String,
+    result :Option<(MergeResult, Vec<Choice>)>,
+    error :Option<String>,
+}
+
+fn browse(title :&str, current :&mut String) {
+    if let Some(f) = tinyfiledialogs::open_file_dialog(title, current.as_str(), None) {
+        *current = f;
+    }
+}
+
+impl MergeWindow {
+    pub fn new() -> Self {
+        MergeWindow {
+            open: false,
+            base_file: String::new(),
+            their_file: String::new(),
+            result: None,
+            error: None,
+        }
+    }
+
+    fn run_merge(&mut self, analysis :&Analysis) {
+        let load = |f :&str| file::load(f).map_err(|e| i18n::tr_fmt("Could not open {}: {}", &[f, &e.to_string()]));
+        match load(&self.base_file).and_then(|base| Ok((base, load(&self.their_file)?))) {
+            Ok((base, theirs)) => {
+                let result = merge(&base, analysis.model(), &theirs);
+                info!("Merged documents with {} conflicts", result.conflicts.len());
+                let choices = vec![Choice::Ours; result.conflicts.len()];
+                self.result = Some((result, choices));
+                self.error = None;
+            },
+            Err(e) => {
+                error!("{}", e);
+                self.error = Some(e);
+            },
+        }
+    }
+
+    pub fn draw(&mut self, analysis :&mut Analysis) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Merge documents"), &mut self.open as _, 0 as _);
+            widgets::show_text(i18n::tr("Combines the changes in the open document and another version of it."));
+
+            igPushIDInt(0);
+            if let Some(f) = widgets::edit_text(tr!("Common ancestor"), self.base_file.clone()) { self.base_file = f; }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Browse..."), ImVec2::zero()) { browse("Common ancestor", &mut self.base_file); }
+            igPopID();
+            igPushIDInt(1);
+            if let Some(f) = widgets::edit_text(tr!("Other version"), self.their_file.clone()) { self.their_file = f; }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Browse..."), ImVec2::zero()) { browse("Other version", &mut self.their_file); }
+            igPopID();
+
+            if igButton(tr!("Merge"), ImVec2::zero()) { self.run_merge(analysis); }
+            if let Some(e) = &self.error { widgets::show_text(e); }
+            widgets::sep();
+
+            let mut apply = false;
+            if let Some((result, choices)) = &mut self.result {
+                if result.conflicts.is_empty() {
+                    widgets::show_text(i18n::tr("No conflicts."));
+                } else {
+                    widgets::show_text(&i18n::tr_fmt("{} conflicts. Choose which version to keep:",
+                                                     &[&result.conflicts.len().to_string()]));
+                    if igButton(tr!("Keep ours for all"), ImVec2::zero()) {
+                        for c in choices.iter_mut() { *c = Choice::Ours; }
+                    }
+                    igSameLine(0.0, -1.0);
+                    if igButton(tr!("Keep theirs for all"), ImVec2::zero()) {
+                        for c in choices.iter_mut() { *c = Choice::Theirs; }
+                    }
+                    for (i,(conflict, choice)) in result.conflicts.iter().zip(choices.iter_mut()).enumerate() {
+                        igPushIDInt(i as _);
+                        widgets::show_text(&conflict.describe());
+                        for (c, label) in &[(Choice::Ours, i18n::tr("Ours")), (Choice::Theirs, i18n::tr("Theirs"))] {
+                            let text = CString::new(format!("{}: {}", label, conflict.describe_version(*c))).unwrap();
+                            if igRadioButtonBool(text.as_ptr(), *choice == *c) { *choice = *c; }
+                        }
+                        igPopID();
+                    }
+                }
+                widgets::sep();
+                apply = igButton(tr!("Apply merge"), ImVec2::zero());
+            }
+
+            if apply {
+                if let Some((result, choices)) = self.result.take() {
+                    let merged = result.resolve(&choices);
+                    analysis.edit_model(|m| { *m = merged; None });
+                    self.open = false;
+                }
+            }
+
+            igEnd();
+        }
+    }
+}
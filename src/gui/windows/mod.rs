@@ -5,3 +5,21 @@ pub mod unsaved;
 pub mod logview;
 pub mod synthesis;
 
+pub mod lint;
+pub mod paths;
+pub mod stats;
+pub mod detectors;
+pub mod project;
+pub mod filechange;
+pub mod download;
+pub mod scripts;
+pub mod theme;
+pub mod objecttree;
+pub mod crashreport;
+pub mod merge;
+pub mod exportreport;
+pub mod print;
+pub mod exportscope;
+pub mod groups;
+pub mod search;
+pub mod trackprops;
@@ -0,0 +1,215 @@
+use backend_glfw::imgui::*;
+use std::collections::HashSet;
+use std::ffi::CString;
+
+use crate::config::Config;
+use crate::document::analysis::{Analysis, Generation};
+use crate::document::infview::InfView;
+use crate::document::lint::ref_pos;
+use crate::document::model::Ref;
+use crate::document::objecttree::*;
+use crate::document::{DispatchView, ManualDispatchView};
+use crate::gui::widgets;
+use crate::units::Units;
+
+/// Hierarchical list of stations, tracks, objects, dispatches and commands.
+/// The list is operated with the arrow keys while the window is focused,
+/// and the cursor follows the selection on the canvas.
+pub struct ObjectTreeWindow {
+    pub open :bool,
+    /// The window had keyboard focus in the last frame. The arrow keys and
+    /// Space are then used for the tree instead of the canvas.
+    pub focused :bool,
+    expanded :HashSet<TreeKey>,
+    cursor :Option<TreeKey>,
+    scroll_to_cursor :bool,
+    /// The canvas selection seen in the last frame.
+    last_selection :HashSet<Ref>,
+    tree :Option<((Generation, Units), Vec<TreeNode>)>,
+}
+
+struct Row<'a> {
+    node :&'a TreeNode,
+    depth :usize,
+    parent :Option<TreeKey>,
+}
+
+fn rows<'a>(nodes :&'a [TreeNode], expanded :&HashSet<TreeKey>, depth :usize,
+            parent :Option<TreeKey>, out :&mut Vec<Row<'a>>) {
+    for node in nodes {
+        out.push(Row { node, depth, parent });
+        if expanded.contains(&node.key) {
+            rows(&node.children, expanded, depth + 1, Some(node.key), out);
+        }
+    }
+}
+
+enum TreeAction {
+    Move(TreeKey),
+    Toggle(TreeKey),
+    Activate(TreeKey),
+}
+
+fn key_pressed(key :ImGuiKey_) -> bool {
+    unsafe { igIsKeyPressed(igGetKeyIndex(key as _), true) }
+}
+
+impl ObjectTreeWindow {
+    pub fn new() -> Self {
+        ObjectTreeWindow {
+            open: false,
+            focused: false,
+            expanded: vec![TreeKey::Tracks, TreeKey::Dispatches].into_iter().collect(),
+            cursor: None,
+            scroll_to_cursor: false,
+            last_selection: HashSet::new(),
+            tree: None,
+        }
+    }
+
+    /// Move the cursor to the element selected on the canvas, opening the
+    /// nodes above it.
+    fn follow_selection(&mut self, tree :&[TreeNode], selection :&HashSet<Ref>) {
+        if *selection == self.last_selection { return; }
+        self.last_selection = selection.clone();
+        if selection.len() != 1 { return; }
+        if let Some(path) = TreeNode::path_to(tree, selection.iter().next().unwrap()) {
+            self.expanded.extend(path[..path.len()-1].iter().cloned());
+            self.cursor = path.last().cloned();
+            self.scroll_to_cursor = true;
+        }
+    }
+
+    fn keyboard(&self, rows :&[Row]) -> Option<TreeAction> {
+        if rows.is_empty() { return None; }
+        let pos = self.cursor.and_then(|c| rows.iter().position(|r| r.node.key == c));
+        let pos = match pos {
+            Some(p) => p,
+            None => {
+                let any = [ImGuiKey__ImGuiKey_UpArrow, ImGuiKey__ImGuiKey_DownArrow,
+                           ImGuiKey__ImGuiKey_Home, ImGuiKey__ImGuiKey_End];
+                if any.iter().any(|k| key_pressed(*k)) { return Some(TreeAction::Move(rows[0].node.key)); }
+                return None;
+            },
+        };
+        let row = &rows[pos];
+        let has_children = !row.node.children.is_empty();
+        let is_open = self.expanded.contains(&row.node.key);
+        if key_pressed(ImGuiKey__ImGuiKey_DownArrow) && pos + 1 < rows.len() {
+            return Some(TreeAction::Move(rows[pos+1].node.key));
+        }
+        if key_pressed(ImGuiKey__ImGuiKey_UpArrow) && pos > 0 {
+            return Some(TreeAction::Move(rows[pos-1].node.key));
+        }
+        if key_pressed(ImGuiKey__ImGuiKey_Home) { return Some(TreeAction::Move(rows[0].node.key)); }
+        if key_pressed(ImGuiKey__ImGuiKey_End) { return Some(TreeAction::Move(rows[rows.len()-1].node.key)); }
+        if key_pressed(ImGuiKey__ImGuiKey_RightArrow) && has_children {
+            return Some(if is_open { TreeAction::Move(row.node.children[0].key) }
+                        else { TreeAction::Toggle(row.node.key) });
+        }
+        if key_pressed(ImGuiKey__ImGuiKey_LeftArrow) {
+            if has_children && is_open { return Some(TreeAction::Toggle(row.node.key)); }
+            if let Some(parent) = row.parent { return Some(TreeAction::Move(parent)); }
+        }
+        if key_pressed(ImGuiKey__ImGuiKey_Space) && has_children {
+            return Some(TreeAction::Toggle(row.node.key));
+        }
+        if key_pressed(ImGuiKey__ImGuiKey_Enter) { return Some(TreeAction::Activate(row.node.key)); }
+        None
+    }
+
+    /// Select the elements of the node on the canvas.
+    fn select(&mut self, node :&TreeNode, inf_view :&mut InfView) {
+        if node.refs.is_empty() { return; }
+        inf_view.selection = node.refs.iter().cloned().collect();
+        self.last_selection = inf_view.selection.clone();
+    }
+
+    /// Open dispatches and commands in the dispatch view, and center the
+    /// canvas on other elements.
+    fn activate(node :&TreeNode, inf_view :&mut InfView, dispatch_view :&mut Option<DispatchView>) {
+        match node.key {
+            TreeKey::Dispatch(id) => {
+                *dispatch_view = Some(DispatchView::Manual(ManualDispatchView::new(id)));
+            },
+            TreeKey::Command(id, cmd) => {
+                let mut view = ManualDispatchView::new(id);
+                view.selected_command = Some(cmd);
+                *dispatch_view = Some(DispatchView::Manual(view));
+            },
+            _ => {
+                if !node.refs.is_empty() {
+                    inf_view.pending_center = Some(ref_pos(&node.refs[node.refs.len() / 2]));
+                }
+            },
+        }
+    }
+
+    pub fn draw(&mut self, config :&Config, analysis :&Analysis, inf_view :&mut InfView,
+                dispatch_view :&mut Option<DispatchView>) {
+        if !self.open { self.focused = false; return; }
+
+        let tree_id = (*analysis.generation(), config.units);
+        if self.tree.as_ref().map(|(id,_)| *id != tree_id).unwrap_or(true) {
+            let topo = analysis.data().topology.as_ref().map(|(_,t)| &**t);
+            self.tree = Some((tree_id, build(analysis.model(), topo, &config.units)));
+        }
+        let (_,tree) = self.tree.take().unwrap();
+        self.follow_selection(&tree, &inf_view.selection);
+
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Object tree"), &mut self.open as _, 0 as _);
+            self.focused = igIsWindowFocused(0 as _) && !igIsAnyItemActive();
+
+            let mut list = Vec::new();
+            rows(&tree, &self.expanded, 0, None, &mut list);
+
+            let mut action = if self.focused { self.keyboard(&list) } else { None };
+
+            for (i,row) in list.iter().enumerate() {
+                igPushIDInt(i as _);
+                let marker = if row.node.children.is_empty() { "  " }
+                    else if self.expanded.contains(&row.node.key) { "- " } else { "+ " };
+                let text = CString::new(format!("{}{}{}", "    ".repeat(row.depth), marker, row.node.label)).unwrap();
+                let selected = self.cursor == Some(row.node.key);
+                if igSelectable(text.as_ptr(), selected, 0 as _, ImVec2::zero()) {
+                    action = Some(if !row.node.children.is_empty() && selected {
+                        TreeAction::Toggle(row.node.key)
+                    } else {
+                        TreeAction::Move(row.node.key)
+                    });
+                }
+                if igIsItemHovered(0) && igIsMouseDoubleClicked(0) {
+                    action = Some(TreeAction::Activate(row.node.key));
+                }
+                if selected && self.scroll_to_cursor {
+                    igSetScrollHereY(0.5);
+                    self.scroll_to_cursor = false;
+                }
+                igPopID();
+            }
+
+            let find = |key :TreeKey| list.iter().find(|r| r.node.key == key).map(|r| r.node);
+            match action {
+                Some(TreeAction::Move(key)) => {
+                    if let Some(node) = find(key) { self.select(node, inf_view); }
+                    self.cursor = Some(key);
+                    self.scroll_to_cursor = true;
+                },
+                Some(TreeAction::Toggle(key)) => {
+                    if !self.expanded.remove(&key) { self.expanded.insert(key); }
+                    self.cursor = Some(key);
+                },
+                Some(TreeAction::Activate(key)) => {
+                    if let Some(node) = find(key) { ObjectTreeWindow::activate(node, inf_view, dispatch_view); }
+                    self.cursor = Some(key);
+                },
+                None => {},
+            }
+
+            igEnd();
+        }
+        self.tree = Some((tree_id, tree));
+    }
+}
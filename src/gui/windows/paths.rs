@@ -0,0 +1,140 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
+use crate::gui::widgets;
+use crate::gui::widgets::Draw;
+use crate::config::*;
+use crate::document::model::*;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::paths::*;
+use crate::i18n;
+
+pub struct PathQueryWindow {
+    pub open :bool,
+    pub from :Option<Pt>,
+    pub to :Option<Pt>,
+    pub max_paths :i32,
+    pub results :Option<Vec<TopoPath>>,
+}
+
+impl PathQueryWindow {
+    pub fn new() -> Self {
+        PathQueryWindow {
+            open: false,
+            from: None,
+            to: None,
+            max_paths: 100,
+            results: None,
+        }
+    }
+
+    /// Pick the two end nodes from the current selection and list the
+    /// paths between them. Hovering a path highlights it on the canvas.
+    pub fn draw(&mut self, config :&Config, analysis :&Analysis, inf_canvas :Option<&Draw>, inf_view :&InfView) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Path query"), &mut self.open as _, 0 as _);
+
+            let selected_node = {
+                let mut nodes = inf_view.selection.iter().filter_map(|r| match r {
+                    Ref::Node(pt) => Some(*pt), _ => None });
+                match (nodes.next(), nodes.next()) {
+                    (Some(pt), None) => Some(pt),
+                    _ => None,
+                }
+            };
+
+            widgets::show_text(&format!("From: {}", fmt_node(self.from)));
+            igSameLine(0.0,-1.0);
+            igPushIDInt(1);
+            if igButton(tr!("Set to selected node"), ImVec2::zero()) {
+                if selected_node.is_some() { self.from = selected_node; self.results = None; }
+            }
+            igPopID();
+            widgets::show_text(&format!("To:   {}", fmt_node(self.to)));
+            igSameLine(0.0,-1.0);
+            igPushIDInt(2);
+            if igButton(tr!("Set to selected node"), ImVec2::zero()) {
+                if selected_node.is_some() { self.to = selected_node; self.results = None; }
+            }
+            igPopID();
+            if selected_node.is_none() {
+                widgets::show_text(i18n::tr("Select a single node in the infrastructure view."));
+            }
+
+            igInputInt(tr!("Max. paths"), &mut self.max_paths, 1, 10, 0 as _);
+            self.max_paths = self.max_paths.max(1);
+
+            let topo = analysis.data().topology.as_ref().map(|(_,t)| t.clone());
+            if let (Some(from), Some(to), Some(topo)) = (self.from, self.to, &topo) {
+                if igButton(tr!("Find paths"), ImVec2::zero()) {
+                    self.results = Some(find_paths(topo, from, to, self.max_paths as usize));
+                }
+            }
+
+            if let Some(paths) = &self.results {
+                igSameLine(0.0,-1.0);
+                if igButton(tr!("Export CSV..."), ImVec2::zero()) {
+                    if let Err(e) = export_csv(paths) {
+                        error!("Could not export paths: {}", e);
+                    }
+                }
+                widgets::sep();
+                widgets::show_text(&format!("{} paths found.", paths.len()));
+                for (i,path) in paths.iter().enumerate() {
+                    igPushIDInt(i as _);
+                    let text = CString::new(format!("Path {}: {}, {} tracks",
+                                                    i+1, config.units.length(path.length), path.tracks.len())).unwrap();
+                    if igTreeNodeStr(text.as_ptr()) {
+                        if path.switches.is_empty() {
+                            widgets::show_text(i18n::tr("No switches."));
+                        }
+                        for (pt,side) in path.switches.iter() {
+                            widgets::show_text(&format!("Switch at ({},{}): {:?}", pt.x, pt.y, side));
+                        }
+                        igTreePop();
+                    }
+                    if igIsItemHovered(0) {
+                        if let (Some(canvas), Some(topo)) = (inf_canvas, &topo) {
+                            highlight_path(config, canvas, inf_view, topo, path);
+                        }
+                    }
+                    igPopID();
+                }
+            }
+
+            igEnd();
+        }
+    }
+}
+
+fn fmt_node(pt :Option<Pt>) -> String {
+    match pt {
+        Some(pt) => format!("({},{})", pt.x, pt.y),
+        None => "none".to_string(),
+    }
+}
+
+fn highlight_path(config :&Config, canvas :&Draw, inf_view :&InfView,
+                  topo :&crate::document::topology::Topology, path :&TopoPath) {
+    let color = config.color_u32(RailUIColorName::CanvasRoutePath);
+    for track_idx in path.tracks.iter() {
+        for (a,b) in topo.track_segments[*track_idx].iter() {
+            unsafe {
+                ImDrawList_AddLine(canvas.draw_list,
+                                   canvas.pos + inf_view.view.world_pt_to_screen(*a),
+                                   canvas.pos + inf_view.view.world_pt_to_screen(*b),
+                                   color, 10.0);
+            }
+        }
+    }
+}
+
+fn export_csv(paths :&[TopoPath]) -> Result<(), std::io::Error> {
+    if let Some(filename) = tinyfiledialogs::save_file_dialog("Export paths as CSV", "") {
+        std::fs::write(filename, to_csv(paths))?;
+    }
+    Ok(())
+}
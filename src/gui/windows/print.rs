@@ -0,0 +1,104 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
+
+use crate::config::Config;
+use crate::document::Document;
+use crate::document::dispatch::Instant;
+use crate::gui::widgets;
+use crate::i18n;
+use crate::print::*;
+
+/// Page layout of printed schematics, exported as PDF, SVG or PNG files.
+pub struct PrintWindow {
+    pub open :bool,
+}
+
+/// The name and state of the trains in the dispatch shown in the dispatch view.
+fn current_dispatch(document :&Document) -> Option<(String, Instant)> {
+    let analysis = &document.analysis;
+    let (dispatch, time) = document.dispatch_view.as_ref()?.dispatch_ref()?;
+    let (_, dgraph) = analysis.data().dgraph.as_ref()?;
+    let (name, output) = match dispatch {
+        Ok(d) => {
+            let name = analysis.model().dispatches.get(d)?.name.clone();
+            (name, &analysis.data().dispatch.get(d)?.as_ref()?.1)
+        },
+        Err((p, d)) => {
+            let name = format!("{} #{}", analysis.model().plans.get(p)?.name, d + 1);
+            (name, analysis.data().plandispatches.get(p)?.as_ref()?.1.get(d)?)
+        },
+    };
+    Some((name, Instant::from(time, &output.history, dgraph)))
+}
+
+fn export(document :&Document, config :&Config, format :PrintFormat) {
+    let default = format!("schematic.{}", format.extension());
+    let mut filename = match tinyfiledialogs::save_file_dialog(&format!("Export {}", format.name()), &default) {
+        Some(f) => f,
+        None => { info!("User cancelled {} export", format.name()); return; },
+    };
+    if !filename.to_lowercase().ends_with(&format!(".{}", format.extension())) {
+        filename.push_str(&format!(".{}", format.extension()));
+    }
+
+    let dispatch = current_dispatch(document);
+    let annotation = dispatch.as_ref().map(|(name, instant)| DispatchAnnotation { name: name.clone(), instant });
+    let sheet = sheet(document.analysis.model(), &config.print, &config.units, config.smooth_curves, annotation.as_ref());
+    info!("Exporting {} to {:?}", format.name(), filename);
+    if let Err(e) = print_to_file(&filename, format, &sheet, config.print.dpi) {
+        error!("Error exporting {}: {}", format.name(), e);
+    }
+}
+
+impl PrintWindow {
+    pub fn new() -> Self {
+        PrintWindow { open: false }
+    }
+
+    pub fn draw(&mut self, document :&Document, config :&mut Config) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Print layout"), &mut self.open as _, 0 as _);
+            let mut changed = false;
+            let settings = &mut config.print;
+
+            let current = CString::new(settings.page_size.name()).unwrap();
+            if igBeginCombo(tr!("Page size"), current.as_ptr(), 0 as _) {
+                for size in PageSize::all() {
+                    let label = CString::new(size.name()).unwrap();
+                    if igSelectable(label.as_ptr(), *size == settings.page_size, 0 as _, ImVec2::zero()) {
+                        settings.page_size = *size;
+                        changed = true;
+                    }
+                }
+                igEndCombo();
+            }
+            changed |= igCheckbox(tr!("Landscape"), &mut settings.landscape);
+            if let Some(title) = widgets::edit_text(tr!("Title"), settings.title.clone()) {
+                settings.title = title;
+                changed = true;
+            }
+            changed |= igCheckbox(tr!("Scale bar"), &mut settings.scale_bar);
+            changed |= igCheckbox(tr!("Show dispatch time"), &mut settings.dispatch_time);
+            if settings.dispatch_time && document.dispatch_view.as_ref().and_then(|d| d.dispatch_ref()).is_none() {
+                widgets::show_text(i18n::tr("Open a dispatch to show its trains on the page."));
+            }
+            let mut dpi = settings.dpi as i32;
+            if igInputInt(tr!("PNG resolution (dpi)"), &mut dpi, 25, 100, 0 as _) {
+                settings.dpi = dpi.max(25).min(600) as u32;
+                changed = true;
+            }
+            if changed { config.save(); }
+
+            widgets::sep();
+            for (i, format) in PrintFormat::all().iter().enumerate() {
+                if i > 0 { igSameLine(0.0, -1.0); }
+                let label = CString::new(i18n::tr_fmt("Export {}...", &[format.name()])).unwrap();
+                if igButton(label.as_ptr(), ImVec2::zero()) { export(document, config, *format); }
+            }
+            igEnd();
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
+
+use crate::app::PendingAction;
+use crate::config::Config;
+use crate::document::Document;
+use crate::gui::widgets;
+use crate::project::OpenProject;
+use crate::i18n;
+
+const PROJECT_FILTER :&[&str] = &["*.junctionproject"];
+
+pub fn open_project_interactive(project :&mut Option<OpenProject>, config :&mut Config) {
+    if let Some(filename) = tinyfiledialogs::open_file_dialog("Open project", "",
+                                              Some((PROJECT_FILTER, "Junction projects"))) {
+        match OpenProject::load(filename) {
+            Ok(p) => {
+                if !p.project.colors.is_empty() { config.set_color_strings(&p.project.colors); }
+                *project = Some(p);
+            },
+            Err(e) => { error!("Could not load project: {}", e); },
+        }
+    }
+}
+
+pub fn project_window(popen :&mut bool, project :&mut Option<OpenProject>, document :&mut Document,
+                      config :&mut Config, pending_action :&mut Option<PendingAction>) {
+    if !*popen { return; }
+    unsafe {
+        widgets::next_window_center_when_appearing();
+        igBegin(tr!("Project"), popen as _, 0 as _);
+
+        let p = match project {
+            Some(p) => p,
+            None => {
+                widgets::show_text(i18n::tr("No project is open."));
+                if igButton(tr!("New project..."), ImVec2::zero()) {
+                    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("New project", "") {
+                        if !filename.ends_with(".junctionproject") { filename.push_str(".junctionproject"); }
+                        let mut p = OpenProject::new(filename);
+                        if let Some(doc) = &document.fileinfo.filename { p.add_document(doc); }
+                        *project = Some(p);
+                    }
+                }
+                igSameLine(0.0, -1.0);
+                if igButton(tr!("Open project..."), ImVec2::zero()) {
+                    open_project_interactive(project, config);
+                }
+                igEnd();
+                return;
+            }
+        };
+
+        widgets::show_text(&format!("{}{}", if p.unsaved { "*" } else { "" }, p.filename));
+        widgets::sep();
+
+        // Document switcher
+        widgets::show_text(i18n::tr("Documents"));
+        let mut remove = None;
+        for (i,doc) in p.project.documents.iter().enumerate() {
+            igPushIDInt(i as _);
+            let current = document.fileinfo.filename.as_ref()
+                .map(|f| p.is_document(doc, f)).unwrap_or(false);
+            let label = CString::new(doc.as_str()).unwrap();
+            if igSelectable(label.as_ptr(), current, 0 as _, ImVec2::zero()) && !current {
+                *pending_action = Some(PendingAction::LoadFile(p.document_path(doc)));
+            }
+            if igIsItemHovered(0) && igIsMouseClicked(1, false) {
+                remove = Some(i);
+            }
+            igPopID();
+        }
+        if let Some(i) = remove {
+            p.project.documents.remove(i);
+            p.unsaved = true;
+        }
+        widgets::show_text(i18n::tr("(right-click to remove from project)"));
+
+        let has_filename = document.fileinfo.filename.is_some();
+        if igButton(tr!("Add current document"), ImVec2::zero()) && has_filename {
+            p.add_document(document.fileinfo.filename.as_ref().unwrap());
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Add file..."), ImVec2::zero()) {
+            if let Some(filename) = tinyfiledialogs::open_file_dialog("Add document to project", "", None) {
+                p.add_document(&filename);
+            }
+        }
+
+        widgets::sep();
+        widgets::show_text(&format!("Vehicle library: {} vehicles", p.project.vehicles.len()));
+        if igButton(tr!("Copy from document"), ImVec2::zero()) {
+            p.project.vehicles = document.analysis.model().vehicles.iter()
+                .map(|(_,v)| v.clone()).collect();
+            p.unsaved = true;
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Add to document"), ImVec2::zero()) {
+            let mut added = 0;
+            document.analysis.edit_model(|m| {
+                added = p.add_vehicles_to(m);
+                None
+            });
+            info!("Added {} vehicles from the project library", added);
+        }
+
+        widgets::sep();
+        widgets::show_text(if p.project.colors.is_empty() { "Colors: from configuration" }
+                           else { "Colors: project color scheme" });
+        if igButton(tr!("Use current colors"), ImVec2::zero()) {
+            p.project.colors = config.color_strings();
+            p.unsaved = true;
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Clear colors"), ImVec2::zero()) {
+            p.project.colors.clear();
+            p.unsaved = true;
+            config.colors = Config::load().colors;
+        }
+
+        widgets::sep();
+        if igButton(tr!("Save project"), ImVec2::zero()) {
+            if let Err(e) = p.save() {
+                error!("Could not save project: {}", e);
+            }
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Close project"), ImVec2::zero()) {
+            if !p.project.colors.is_empty() { config.colors = Config::load().colors; }
+            *project = None;
+        }
+
+        igEnd();
+    }
+}
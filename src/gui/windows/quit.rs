@@ -1,21 +1,21 @@
-use const_cstr::*;
 use crate::document::Document;
 use crate::app::Windows;
 use crate::gui::widgets;
 use crate::file;
 use log::*;
+use crate::i18n;
 
 pub fn quit_window(doc :&mut Document, show_windows :&mut Windows) -> bool {
     unsafe {
     use backend_glfw::imgui::*;
     let mut quit = false;
-    let name = const_cstr!("Save before exit?").as_ptr();
+    let name = tr!("Save before exit?");
     if !igIsPopupOpen(name) { igOpenPopup(name); }
     if igBeginPopupModal(name, &mut show_windows.quit, 0 as _) {
-        widgets::show_text("Save file before closing program?");
-        let yes = const_cstr!("Yes").as_ptr();
-        let no = const_cstr!("No").as_ptr();
-        let cancel = const_cstr!("Cancel").as_ptr();
+        widgets::show_text(i18n::tr("Save file before closing program?"));
+        let yes = tr!("Yes");
+        let no = tr!("No");
+        let cancel = tr!("Cancel");
         if igButton(yes, ImVec2{ x: 80.0, y: 0.0 }) {
             let model = doc.analysis.model().clone();
             match file::save_interactive(model) {
@@ -0,0 +1,148 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use log::*;
+use std::ffi::CString;
+use std::path::PathBuf;
+
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::model::Ref;
+use crate::document::lint;
+use crate::gui::widgets;
+use crate::scripting;
+use crate::i18n;
+
+/// Script console: edit and run scripts on the current document, and load
+/// or save them in the scripts folder.
+pub struct ScriptWindow {
+    pub open :bool,
+    source :String,
+    filename :Option<PathBuf>,
+    scripts :Vec<PathBuf>,
+    output :Vec<String>,
+    issues :Vec<(Ref, String)>,
+}
+
+impl ScriptWindow {
+    pub fn new() -> Self {
+        ScriptWindow {
+            open: false,
+            source: String::new(),
+            filename: None,
+            scripts: Vec::new(),
+            output: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        if self.open { self.scripts = scripting::list_scripts(); }
+    }
+
+    fn load(&mut self, path :PathBuf) {
+        match std::fs::read_to_string(&path) {
+            Ok(s) => { self.source = s; self.filename = Some(path); },
+            Err(e) => { error!("Could not read script {:?}: {}", path, e); },
+        }
+    }
+
+    fn save_as(&mut self) {
+        let dir = scripting::scripts_dir();
+        if let Some(dir) = &dir {
+            if let Err(e) = std::fs::create_dir_all(dir) { warn!("Could not create scripts folder: {}", e); }
+        }
+        let default = dir.map(|d| d.join("script.rhai").to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Save script", &default) {
+            if !filename.ends_with(".rhai") { filename.push_str(".rhai"); }
+            match std::fs::write(&filename, &self.source) {
+                Ok(()) => {
+                    self.filename = Some(filename.into());
+                    self.scripts = scripting::list_scripts();
+                },
+                Err(e) => { error!("Could not save script: {}", e); },
+            }
+        }
+    }
+
+    fn run(&mut self, analysis :&mut Analysis, inf_view :&mut InfView) {
+        let result = scripting::run(&self.source, analysis.model(), &inf_view.selection);
+        self.output = result.output;
+        self.issues = result.issues;
+        if let Some(e) = result.error { self.output.push(format!("Error: {}", e)); }
+        if let Some(model) = result.model { analysis.set_model(model, None); }
+        inf_view.selection = result.selection;
+    }
+
+    pub fn draw(&mut self, analysis :&mut Analysis, inf_view :&mut InfView) {
+        if !self.open { return; }
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Script console"), &mut self.open as _, 0 as _);
+
+            let current = self.filename.as_ref().and_then(|f| f.file_name())
+                .map(|f| f.to_string_lossy().to_string()).unwrap_or("(unsaved script)".to_string());
+            let current = CString::new(current).unwrap();
+            if igBeginCombo(tr!("Script"), current.as_ptr(), 0 as _) {
+                let mut load = None;
+                for path in &self.scripts {
+                    let name = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+                    let name = CString::new(name).unwrap();
+                    if igSelectable(name.as_ptr(), Some(path) == self.filename.as_ref(), 0 as _, ImVec2::zero()) {
+                        load = Some(path.clone());
+                    }
+                }
+                if self.scripts.is_empty() { widgets::show_text(i18n::tr("No scripts in the scripts folder.")); }
+                igEndCombo();
+                if let Some(path) = load { self.load(path); }
+            }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Refresh"), ImVec2::zero()) {
+                self.scripts = scripting::list_scripts();
+            }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Save as..."), ImVec2::zero()) {
+                self.save_as();
+            }
+            if let Some(filename) = self.filename.clone() {
+                igSameLine(0.0, -1.0);
+                if igButton(tr!("Save"), ImVec2::zero()) {
+                    if let Err(e) = std::fs::write(&filename, &self.source) {
+                        error!("Could not save script: {}", e);
+                    }
+                }
+            }
+
+            widgets::edit_text_multiline(const_cstr!("##script").as_ptr(), &mut self.source,
+                                         ImVec2 { x: -1.0, y: 200.0 });
+
+            if igButton(tr!("Run"), ImVec2 { x: 80.0, y: 0.0 }) {
+                self.run(analysis, inf_view);
+            }
+            igSameLine(0.0, -1.0);
+            if igButton(tr!("Clear output"), ImVec2::zero()) {
+                self.output.clear();
+                self.issues.clear();
+            }
+            widgets::sep();
+
+            for line in &self.output {
+                widgets::show_text(line);
+            }
+
+            // Issues from custom checks, selectable like in the model issues window.
+            for (i,(location, message)) in self.issues.iter().enumerate() {
+                igPushIDInt(i as _);
+                let text = CString::new(format!("Issue: {}", message)).unwrap_or_default();
+                let selected = inf_view.selection.len() == 1 && inf_view.selection.contains(location);
+                if igSelectable(text.as_ptr(), selected, 0 as _, ImVec2::zero()) {
+                    inf_view.selection = std::iter::once(*location).collect();
+                    inf_view.pending_center = Some(lint::ref_pos(location));
+                }
+                igPopID();
+            }
+
+            igEnd();
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use nalgebra_glm as glm;
+use std::ffi::CString;
+
+use crate::document::analysis::{Analysis, Generation};
+use crate::document::infview::InfView;
+use crate::document::search::*;
+use crate::gui::widgets;
+use crate::i18n;
+
+/// Maximum number of results listed.
+const MAX_SHOWN :usize = 100;
+
+/// Seconds the chosen result stays marked on the canvas.
+const HIGHLIGHT_TIME :f64 = 2.0;
+
+/// Grid units shown around a result when zooming to it.
+const MARGIN :f32 = 3.0;
+
+/// Search for elements by id, name, signal code or object function. The
+/// chosen result is selected, and the view zooms to it and marks it.
+pub struct SearchWindow {
+    pub open :bool,
+    query :String,
+    selected :usize,
+    focus :bool,
+    results :Option<((Generation, String), Vec<SearchResult>)>,
+}
+
+fn key_pressed(key :ImGuiKey_) -> bool {
+    unsafe { igIsKeyPressed(igGetKeyIndex(key as _), true) }
+}
+
+impl SearchWindow {
+    pub fn new() -> Self {
+        SearchWindow { open: false, query: String::new(), selected: 0, focus: false, results: None }
+    }
+
+    /// Open the window with the keyboard in the search field.
+    pub fn show(&mut self) {
+        self.open = true;
+        self.focus = true;
+    }
+
+    fn choose(result :&SearchResult, inf_view :&mut InfView) {
+        inf_view.selection = result.refs.iter().cloned().collect();
+        inf_view.selected_ocp = result.ocp;
+        let (min, max) = result.bounds;
+        let margin = glm::vec2(MARGIN, MARGIN);
+        inf_view.pending_fit_bounds = Some((min - margin, max + margin));
+        inf_view.highlight = Some((min, max, unsafe { igGetTime() } + HIGHLIGHT_TIME));
+    }
+
+    pub fn draw(&mut self, analysis :&Analysis, inf_view :&mut InfView) {
+        if !self.open { return; }
+        let id = (*analysis.generation(), self.query.clone());
+        if self.results.as_ref().map(|(x,_)| *x != id).unwrap_or(true) {
+            self.results = Some((id, search(analysis.model(), &self.query)));
+        }
+        let (_,results) = self.results.as_ref().unwrap();
+        self.selected = self.selected.min(results.len().saturating_sub(1));
+        let mut chosen = None;
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(tr!("Find"), &mut self.open as _, 0 as _);
+            if self.focus { igSetKeyboardFocusHere(0); self.focus = false; }
+            let mut query = None;
+            if let Some(q) = widgets::edit_text(const_cstr!("##find").as_ptr(), self.query.clone()) {
+                query = Some(q);
+            }
+            let typing = igIsItemActive();
+            if typing && key_pressed(ImGuiKey__ImGuiKey_DownArrow) && self.selected + 1 < results.len().min(MAX_SHOWN) {
+                self.selected += 1;
+            }
+            if typing && key_pressed(ImGuiKey__ImGuiKey_UpArrow) {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            if igIsWindowFocused(0 as _) && key_pressed(ImGuiKey__ImGuiKey_Enter) {
+                chosen = results.get(self.selected);
+            }
+
+            widgets::sep();
+            if !self.query.trim().is_empty() {
+                widgets::show_text(&i18n::tr_fmt("{} found.", &[&results.len().to_string()]));
+            }
+            for (i,result) in results.iter().take(MAX_SHOWN).enumerate() {
+                igPushIDInt(i as _);
+                let label = CString::new(result.label.as_str()).unwrap();
+                if igSelectable(label.as_ptr(), i == self.selected, 0 as _, ImVec2::zero()) {
+                    self.selected = i;
+                    chosen = Some(result);
+                }
+                igPopID();
+            }
+            igEnd();
+
+            if let Some(q) = query {
+                self.query = q;
+                self.selected = 0;
+            }
+        }
+        if let Some(result) = chosen { SearchWindow::choose(result, inf_view); }
+    }
+}
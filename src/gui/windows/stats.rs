@@ -0,0 +1,47 @@
+use backend_glfw::imgui::*;
+use crate::gui::widgets;
+use crate::document::analysis::Analysis;
+use crate::document::stats;
+use crate::config::Config;
+use crate::i18n;
+
+pub fn stats_window(popen :&mut bool, config :&Config, analysis :&Analysis) {
+    if !*popen { return; }
+    unsafe {
+        widgets::next_window_center_when_appearing();
+        igBegin(tr!("Statistics"), popen as _, 0 as _);
+
+        let data = analysis.data();
+        if let Some((_,topo)) = &data.topology {
+            let s = stats::calc(topo,
+                                data.dgraph.as_ref().map(|(_,d)| &**d),
+                                data.interlocking.as_ref().map(|(_,il)| &**il));
+            let pending = |x :Option<usize>| x.map(|n| n.to_string()).unwrap_or("...".to_string());
+
+            widgets::show_text(&format!("Tracks: {}", s.tracks));
+            widgets::show_text(&format!("Total track length: {}", config.units.length(s.total_length)));
+            widgets::sep();
+            widgets::show_text(&format!("Switches (left): {}", s.switches_left));
+            widgets::show_text(&format!("Switches (right): {}", s.switches_right));
+            widgets::show_text(&format!("Crossings: {}", s.crossovers));
+            widgets::show_text(&format!("Single slips: {}", s.single_slips));
+            widgets::show_text(&format!("Double slips: {}", s.double_slips));
+            widgets::show_text(&format!("Open ends: {}", s.open_ends));
+            widgets::show_text(&format!("Buffer stops: {}", s.buffer_stops));
+            widgets::sep();
+            widgets::show_text(&format!("Main signals: {}", s.signals));
+            widgets::show_text(&format!("Detectors: {}", s.detectors));
+            match s.longest_signal_free {
+                Some((l,track)) => widgets::show_text(
+                    &format!("Longest signal-free section: {} (track {})", config.units.length(l), track)),
+                None => widgets::show_text(i18n::tr("Longest signal-free section: -")),
+            }
+            widgets::show_text(&format!("Train detection sections: {}", pending(s.tvds)));
+            widgets::show_text(&format!("Routes: {}", pending(s.routes)));
+        } else {
+            widgets::show_text(i18n::tr("No topology available."));
+        }
+
+        igEnd();
+    }
+}
@@ -15,6 +15,7 @@ use crate::document::objects::*;
 use crate::document::infview::round_coord;
 use crate::synthesis::*;
 use crate::app::*;
+use crate::i18n;
 
 pub struct SynthesisWindow {
     model :Arc<Model>,
@@ -99,12 +100,12 @@ impl SynthesisWindow {
         use backend_glfw::imgui::*;
         unsafe {
             widgets::next_window_center_when_appearing();
-            igBegin(const_cstr!("Signal designer").as_ptr(), &mut keep_open as _, 0 as _);
+            igBegin(tr!("Signal designer"), &mut keep_open as _, 0 as _);
             let mut window_size = igGetContentRegionAvail_nonUDT2();
             igBeginChild(const_cstr!("sdl").as_ptr(), 
                          ImVec2 { x: window_size.x/2.0, y: -150.0 }, true, 0 as _);
 
-            widgets::show_text("\u{f0d0} Use plans:");
+            widgets::show_text(i18n::tr("\u{f0d0} Use plans:"));
 
             for (plan_id,plan) in current_doc.model().plans.iter() {
                 igPushIDInt(*plan_id as _);
@@ -123,12 +124,12 @@ impl SynthesisWindow {
             igSameLine(0.0,-1.0);
             igBeginChild(const_cstr!("sdr").as_ptr(), ImVec2 { x: 0.0, y: -150.0 }, true, 0 as _);
             if self.thread.is_some() {
-                widgets::show_text("\u{f110} Running.");
+                widgets::show_text(i18n::tr("\u{f110} Running."));
             } else {
                 if self.result_models.len() > 0 {
-                    widgets::show_text("\u{f00c} Designs available.");
+                    widgets::show_text(i18n::tr("\u{f00c} Designs available."));
                 } else {
-                    widgets::show_text("\u{f00d} No solutions found.");
+                    widgets::show_text(i18n::tr("\u{f00d} No solutions found."));
                 }
             }
 
@@ -179,7 +180,7 @@ impl SynthesisWindow {
                 Some(p) } else { None })
             .cloned().collect::<Vec<_>>();
 
-        self.thread_pool.execute(move || {
+        self.thread_pool.execute_with(Priority::Low, CancelToken::new(), move || {
             use crate::document::topology;
             let topo = topology::convert(&model, 50.0).unwrap();
             let vehicles = model.vehicles.iter().cloned().collect::<Vec<_>>();
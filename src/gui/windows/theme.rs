@@ -0,0 +1,201 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use log::*;
+use std::ffi::CString;
+
+use crate::config::*;
+use crate::gui::widgets;
+use crate::i18n;
+
+const THEME_FILTER :&[&str] = &["*.toml"];
+
+/// Themes bundled with the program, as (name, theme file) pairs.
+pub const BUILTIN_THEMES :&[(&str, &str)] = &[
+    ("Junction-gray", include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/gray.toml"))),
+    ("Junction-dark", include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/dark.toml"))),
+    ("Junction-light", include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/themes/light.toml"))),
+];
+
+/// Theme files in the themes folder next to the config file.
+pub fn user_themes() -> Vec<std::path::PathBuf> {
+    let dir = match Config::subdir("themes") { Some(d) => d, None => return Vec::new() };
+    let mut themes = std::fs::read_dir(&dir).into_iter().flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|e| e == "toml").unwrap_or(false))
+        .collect::<Vec<_>>();
+    themes.sort();
+    themes
+}
+
+/// Menu items for the bundled and user themes.
+pub fn themes_menu(config :&mut Config) {
+    unsafe {
+        for (name, theme) in BUILTIN_THEMES {
+            let label = CString::new(*name).unwrap();
+            if igMenuItemBool(label.as_ptr(), std::ptr::null(), false, true) {
+                if let Err(e) = config.set_theme_string(theme) {
+                    error!("Could not load theme {}: {}", name, e);
+                }
+            }
+        }
+        let user = user_themes();
+        if !user.is_empty() { widgets::sep(); }
+        for path in user {
+            let name = path.file_stem().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+            let label = CString::new(name).unwrap();
+            if igMenuItemBool(label.as_ptr(), std::ptr::null(), false, true) {
+                if let Err(e) = load_theme(config, &path.to_string_lossy()) {
+                    error!("Could not load theme {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+fn load_theme(config :&mut Config, filename :&str) -> Result<(), String> {
+    let s = std::fs::read_to_string(filename).map_err(|e| format!("{}", e))?;
+    config.set_theme_string(&s)
+}
+
+fn import_theme(config :&mut Config) -> Result<(), String> {
+    let dir = Config::subdir("themes").map(|d| d.to_string_lossy().to_string()).unwrap_or_default();
+    if let Some(filename) = tinyfiledialogs::open_file_dialog("Import theme", &dir,
+                                                              Some((THEME_FILTER, "Theme files"))) {
+        load_theme(config, &filename)?;
+    }
+    Ok(())
+}
+
+fn export_theme(config :&Config) -> Result<(), String> {
+    let dir = Config::subdir("themes");
+    if let Some(dir) = &dir {
+        if let Err(e) = std::fs::create_dir_all(dir) { warn!("Could not create themes folder: {}", e); }
+    }
+    let default = dir.map(|d| d.join("theme.toml").to_string_lossy().to_string()).unwrap_or_default();
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export theme", &default) {
+        if !filename.ends_with(".toml") { filename.push_str(".toml"); }
+        std::fs::write(&filename, config.theme_string()?).map_err(|e| format!("{}", e))?;
+    }
+    Ok(())
+}
+
+/// Color pickers for all `RailUIColorName`s with a preview of the canvas
+/// and graph, and import/export of theme files.
+pub fn edit_theme(config :&mut Config) {
+    unsafe {
+        if igButton(tr!("Import theme..."), ImVec2::zero()) {
+            if let Err(e) = import_theme(config) { error!("Could not import theme: {}", e); }
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Export theme..."), ImVec2::zero()) {
+            if let Err(e) = export_theme(config) { error!("Could not export theme: {}", e); }
+        }
+        igSameLine(0.0, -1.0);
+        if igButton(tr!("Reset to default colors"), ImVec2::zero()) {
+            config.colors = default_colors();
+        }
+
+        preview(config);
+
+        let flags = ImGuiColorEditFlags__ImGuiColorEditFlags_AlphaBar
+            | ImGuiColorEditFlags__ImGuiColorEditFlags_AlphaPreviewHalf;
+        let mut last_group = None;
+        for (name,color) in config.colors.iter_mut() {
+            let group = if format!("{:?}", name).starts_with("Graph") { "Graph" } else { "Canvas" };
+            if last_group != Some(group) {
+                widgets::show_text(i18n::tr(group));
+                last_group = Some(group);
+            }
+            igColorEdit4(i18n::tr_cstr(COLORNAMES[name]), &mut color.color.red as _, flags as _);
+        }
+    }
+}
+
+/// Sample drawings of the infrastructure canvas and the train graph
+/// using the current colors.
+fn preview(config :&Config) {
+    unsafe {
+        let c = |name| config.color_u32(name);
+        let avail :ImVec2 = igGetContentRegionAvail_nonUDT2().into();
+        let size = ImVec2 { x: avail.x.max(200.0), y: 110.0 };
+        let pos :ImVec2 = igGetCursorScreenPos_nonUDT2().into();
+        let dl = igGetWindowDrawList();
+        let w = size.x / 2.0 - 4.0;
+        let h = size.y;
+        let p = |x :f32, y :f32| pos + ImVec2 { x, y };
+
+        // Infrastructure canvas
+        ImDrawList_AddRectFilled(dl, p(0.0, 0.0), p(w, h), c(RailUIColorName::CanvasBackground), 0.0, 0);
+        let mut gx = 10.0;
+        while gx < w {
+            let mut gy = 10.0;
+            while gy < h {
+                ImDrawList_AddCircleFilled(dl, p(gx, gy), 2.0, c(RailUIColorName::CanvasGridPoint), 4);
+                gy += 20.0;
+            }
+            gx += 20.0;
+        }
+        let y1 = 70.0;
+        let y2 = 30.0;
+        let sw = 0.35*w;
+        ImDrawList_AddLine(dl, p(10.0, y1), p(sw, y1), c(RailUIColorName::CanvasTVDFree), 2.0);
+        ImDrawList_AddLine(dl, p(sw, y1), p(0.6*w, y1), c(RailUIColorName::CanvasTVDOccupied), 4.0);
+        ImDrawList_AddLine(dl, p(0.6*w, y1), p(0.8*w, y1), c(RailUIColorName::CanvasTVDReserved), 4.0);
+        ImDrawList_AddLine(dl, p(0.8*w, y1), p(w - 10.0, y1), c(RailUIColorName::CanvasTVDReleased), 4.0);
+        ImDrawList_AddLine(dl, p(sw, y1), p(sw + 40.0, y2), c(RailUIColorName::CanvasTrack), 2.0);
+        ImDrawList_AddLine(dl, p(sw + 40.0, y2), p(0.7*w, y2), c(RailUIColorName::CanvasTrackSelected), 2.0);
+        ImDrawList_AddLine(dl, p(0.7*w, y2), p(w - 10.0, y2), c(RailUIColorName::CanvasTrackDrawing), 2.0);
+        ImDrawList_AddLine(dl, p(10.0, y1 + 20.0), p(sw, y1 + 20.0), c(RailUIColorName::CanvasRoutePath), 2.0);
+        ImDrawList_AddLine(dl, p(sw, y1 + 20.0), p(w - 10.0, y1 + 20.0), c(RailUIColorName::CanvasRouteSection), 2.0);
+
+        ImDrawList_AddCircleFilled(dl, p(10.0, y1), 4.0, c(RailUIColorName::CanvasNode), 8);
+        ImDrawList_AddCircleFilled(dl, p(w - 10.0, y1), 4.0, c(RailUIColorName::CanvasNodeSelected), 8);
+        ImDrawList_AddRect(dl, p(w - 16.0, y2 - 6.0), p(w - 4.0, y2 + 6.0), c(RailUIColorName::CanvasNodeError), 0.0, 0, 2.0);
+
+        // Signals: stop and proceed aspects, and plain and selected symbols.
+        let signal = |x :f32, y :f32, col :u32| {
+            ImDrawList_AddTriangle(dl, p(x, y - 6.0), p(x, y + 6.0), p(x + 10.0, y), col, 2.0);
+        };
+        signal(0.2*w, y1 - 12.0, c(RailUIColorName::CanvasSignalStop));
+        signal(0.45*w, y1 - 12.0, c(RailUIColorName::CanvasSignalProceed));
+        signal(0.55*w, y2 - 12.0, c(RailUIColorName::CanvasSymbol));
+        signal(0.65*w, y2 - 12.0, c(RailUIColorName::CanvasSymbolSelected));
+        ImDrawList_AddRect(dl, p(0.75*w - 6.0, y2 - 18.0), p(0.75*w + 6.0, y2 - 6.0),
+                           c(RailUIColorName::CanvasSymbolLocError), 0.0, 0, 2.0);
+
+        ImDrawList_AddLine(dl, p(sw + 10.0, y1), p(0.55*w, y1), c(RailUIColorName::CanvasTrain), 6.0);
+        ImDrawList_AddLine(dl, p(0.55*w, y1), p(0.45*w, y1 - 12.0), c(RailUIColorName::CanvasTrainSight), 1.0);
+        ImDrawList_AddRect(dl, p(0.05*w, 5.0), p(0.3*w, y1 - 25.0), c(RailUIColorName::CanvasSelectionWindow), 0.0, 0, 1.0);
+
+        // Train graph
+        let x0 = w + 8.0;
+        let p = |x :f32, y :f32| pos + ImVec2 { x: x0 + x, y };
+        ImDrawList_AddRectFilled(dl, p(0.0, 0.0), p(w, h), c(RailUIColorName::GraphBackground), 0.0, 0);
+        let block = |x1 :f32, x2 :f32, y1 :f32, y2 :f32, occupied :bool| {
+            let col = if occupied { RailUIColorName::GraphBlockOccupied } else { RailUIColorName::GraphBlockReserved };
+            ImDrawList_AddRectFilled(dl, p(x1, y1), p(x2, y2), c(col), 0.0, 0);
+            ImDrawList_AddRect(dl, p(x1, y1), p(x2, y2), c(RailUIColorName::GraphBlockBorder), 0.0, 0, 1.0);
+        };
+        block(0.1*w, 0.4*w, 5.0, 45.0, false);
+        block(0.2*w, 0.35*w, 15.0, 40.0, true);
+        block(0.4*w, 0.8*w, 45.0, 100.0, false);
+        block(0.5*w, 0.7*w, 55.0, 90.0, true);
+        ImDrawList_AddLine(dl, p(0.2*w, 15.0), p(0.5*w, 55.0), c(RailUIColorName::GraphTrainFront), 2.0);
+        ImDrawList_AddLine(dl, p(0.35*w, 15.0), p(0.7*w, 55.0), c(RailUIColorName::GraphTrainRear), 2.0);
+
+        let command = |y :f32, col :RailUIColorName| {
+            ImDrawList_AddRectFilled(dl, p(4.0, y), p(14.0, y + 10.0), c(col), 0.0, 0);
+            ImDrawList_AddRect(dl, p(4.0, y), p(14.0, y + 10.0), c(RailUIColorName::GraphCommandBorder), 0.0, 0, 1.0);
+        };
+        command(20.0, RailUIColorName::GraphCommandTrain);
+        command(50.0, RailUIColorName::GraphCommandRoute);
+        command(80.0, RailUIColorName::GraphCommandError);
+
+        ImDrawList_AddLine(dl, p(0.0, 0.6*h), p(w, 0.6*h), c(RailUIColorName::GraphTimeSlider), 1.0);
+        let text = const_cstr!("00:12:30");
+        ImDrawList_AddText(dl, p(w - 60.0, 0.6*h - 16.0), c(RailUIColorName::GraphTimeSliderText),
+                           text.as_ptr(), std::ptr::null());
+
+        igDummy(size);
+    }
+}
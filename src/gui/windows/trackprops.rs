@@ -0,0 +1,164 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use std::ffi::CString;
+
+use crate::config::Config;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::model::*;
+use crate::gui::widgets;
+use crate::i18n;
+
+/// Width of the panel when it first appears at the right edge of the window.
+const PANEL_WIDTH :f32 = 320.0;
+
+/// Side panel with the attributes of the selected line segments: real
+/// length, maximum speed, electrification and the name and code of their
+/// track. It is shown while line segments are selected, and an edit applies
+/// to all of them. Values which differ between the line segments are shown
+/// empty.
+pub struct TrackPropertiesWindow {
+    pub open :bool,
+}
+
+enum Edit {
+    Length(Option<f64>),
+    MaxSpeed(Option<f64>),
+    Electrification(Option<String>),
+    Voltage(Option<f64>),
+    Frequency(Option<f64>),
+    TrackName(Option<String>),
+    TrackCode(Option<String>),
+}
+
+impl Edit {
+    fn apply(&self, attrs :&mut LineSegAttributes) {
+        match self {
+            Edit::Length(x) => attrs.length = *x,
+            Edit::MaxSpeed(x) => attrs.max_speed = *x,
+            Edit::Electrification(x) => attrs.electrification = x.clone(),
+            Edit::Voltage(x) => attrs.voltage = *x,
+            Edit::Frequency(x) => attrs.frequency = *x,
+            Edit::TrackName(x) => attrs.track_name = x.clone(),
+            Edit::TrackCode(x) => attrs.track_code = x.clone(),
+        }
+    }
+
+    /// Typed text is merged into one undo step, numbers are applied with Enter.
+    fn class(&self, segs :&[(Pt,Pt)]) -> Option<EditClass> {
+        match self {
+            Edit::Electrification(_) => Some(EditClass::Electrification(segs.to_vec())),
+            Edit::TrackName(_) => Some(EditClass::TrackName(segs.to_vec())),
+            Edit::TrackCode(_) => Some(EditClass::TrackCode(segs.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+/// The value of all the attributes, if they have the same.
+fn shared<T :PartialEq>(attrs :&[LineSegAttributes], f :impl Fn(&LineSegAttributes) -> Option<T>) -> Option<T> {
+    let mut values = attrs.iter().map(f);
+    let first = values.next().flatten()?;
+    if values.all(|v| v.as_ref() == Some(&first)) { Some(first) } else { None }
+}
+
+/// Number input applied with Enter. Gives the typed value.
+unsafe fn edit_number(label :*const i8, value :Option<f64>, step :f32, decimals :usize) -> Option<f64> {
+    let mut v = value.unwrap_or(0.0) as f32;
+    let format = CString::new(format!("%.{}f", decimals)).unwrap();
+    if igInputFloat(label, &mut v, step, 10.0 * step, format.as_ptr(),
+                    ImGuiInputTextFlags__ImGuiInputTextFlags_EnterReturnsTrue as _) {
+        Some(v as f64)
+    } else { None }
+}
+
+fn edit_opt_text(label :*const i8, value :Option<String>) -> Option<Option<String>> {
+    widgets::edit_text(label, value.unwrap_or_default())
+        .map(|s| Some(s).filter(|s| !s.trim().is_empty()))
+}
+
+impl TrackPropertiesWindow {
+    pub fn new() -> Self {
+        TrackPropertiesWindow { open: true }
+    }
+
+    pub fn draw(&mut self, config :&Config, analysis :&mut Analysis, inf_view :&InfView) {
+        if !self.open { return; }
+        let mut segs = inf_view.selection.iter().filter_map(|r| match r {
+            Ref::LineSeg(a,b) => Some((*a,*b)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        if segs.is_empty() { return; }
+        segs.sort_by_key(|(a,b)| (a.x, a.y, b.x, b.y));
+        let attrs = segs.iter()
+            .map(|(a,b)| analysis.model().lineseg_attrs(*a,*b).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let mut edit = None;
+        unsafe {
+            let display_size = (*igGetIO()).DisplaySize;
+            igSetNextWindowPos(ImVec2 { x: display_size.x, y: 0.0 }, ImGuiCond__ImGuiCond_FirstUseEver as _,
+                               ImVec2 { x: 1.0, y: 0.0 });
+            igSetNextWindowSize(ImVec2 { x: PANEL_WIDTH, y: 0.0 }, ImGuiCond__ImGuiCond_FirstUseEver as _);
+            igBegin(tr!("Track properties"), &mut self.open as _, 0 as _);
+            widgets::show_text(&i18n::tr_fmt("{} line segments", &[&segs.len().to_string()]));
+
+            if let Some(x) = edit_opt_text(tr!("Track name"), shared(&attrs, |a| a.track_name.clone())) {
+                edit = Some(Edit::TrackName(x));
+            }
+            if let Some(x) = edit_opt_text(tr!("Track code"), shared(&attrs, |a| a.track_code.clone())) {
+                edit = Some(Edit::TrackCode(x));
+            }
+
+            widgets::sep();
+            // Lengths and speeds are edited in the configured units. The
+            // maximum speed is stored in km/h.
+            let (lu, su) = (config.units.length, config.units.speed);
+            let length_label = CString::new(format!("{} ({})", i18n::tr("Length"), lu.symbol())).unwrap();
+            let length = shared(&attrs, |a| a.length).map(|x| lu.from_meters(x));
+            if let Some(x) = edit_number(length_label.as_ptr(), length, lu.from_meters(10.0) as f32, lu.decimals()) {
+                edit = Some(Edit::Length(Some(lu.to_meters(x)).filter(|x| *x > 0.0)));
+            }
+            if igIsItemHovered(0) {
+                igSetTooltip(tr!("Real length of each line segment. Zero uses the default length."));
+            }
+            let speed_label = CString::new(format!("{} ({})", i18n::tr("Max. speed"), su.symbol())).unwrap();
+            let speed = shared(&attrs, |a| a.max_speed).map(|x| su.from_mps(x / 3.6));
+            if let Some(x) = edit_number(speed_label.as_ptr(), speed, 10.0, 0) {
+                edit = Some(Edit::MaxSpeed(Some(su.to_mps(x) * 3.6).filter(|x| *x > 0.0)));
+            }
+
+            widgets::sep();
+            if let Some(x) = edit_opt_text(tr!("Electrification"), shared(&attrs, |a| a.electrification.clone())) {
+                edit = Some(Edit::Electrification(x));
+            }
+            if igIsItemHovered(0) {
+                igSetTooltip(tr!("Type of electrification, like \"overhead\", or \"none\"."));
+            }
+            if let Some(x) = edit_number(tr!("Voltage (V)"), shared(&attrs, |a| a.voltage), 100.0, 0) {
+                edit = Some(Edit::Voltage(Some(x).filter(|x| *x > 0.0)));
+            }
+            // Zero frequency is direct current, and negative values clear it.
+            if let Some(x) = edit_number(tr!("Frequency (Hz)"), shared(&attrs, |a| a.frequency), 1.0, 0) {
+                edit = Some(Edit::Frequency(Some(x).filter(|x| *x >= 0.0)));
+            }
+            igEnd();
+        }
+
+        if let Some(edit) = edit {
+            analysis.edit_model(|m| {
+                for (a,b) in segs.iter() {
+                    let mut attrs = m.lineseg_attrs(*a,*b).cloned().unwrap_or_default();
+                    edit.apply(&mut attrs);
+                    m.lineseg_attributes.remove(&(*b,*a));
+                    if attrs == LineSegAttributes::default() {
+                        m.lineseg_attributes.remove(&(*a,*b));
+                    } else {
+                        m.lineseg_attributes.insert((*a,*b), attrs);
+                    }
+                }
+                edit.class(&segs)
+            });
+        }
+    }
+}
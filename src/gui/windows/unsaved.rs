@@ -1,36 +1,44 @@
-use const_cstr::*;
 use crate::document::Document;
+use crate::config::{Config, RecentFileKind};
 use crate::app::{Windows, PendingAction};
 use crate::gui::widgets;
 use crate::file;
 use log::*;
 
-pub fn unsaved_changes_window(doc :&mut Document, show_windows :&mut Windows) -> Option<bool> {
+pub fn unsaved_changes_window(doc :&mut Document, config :&mut Config, show_windows :&mut Windows) -> Option<bool> {
     unsafe {
     use backend_glfw::imgui::*;
     let mut result = None;
-    let action = show_windows.pending_action.unwrap();
+    let action = show_windows.pending_action.clone().unwrap();
 
-    let name = const_cstr!("Unsaved changes").as_ptr();
+    let name = tr!("Unsaved changes");
     if !igIsPopupOpen(name) { igOpenPopup(name); }
 
     if igBeginPopupModal(name, &mut true as *mut bool, 0 as _) {
         let msg = match action {
-            PendingAction::New => "Create new file? Unsaved changes will be lost.",
-            PendingAction::Load => "Load file? Unsaved changes will be lost.",
-            PendingAction::Import => "Import from railML? Unsaved changes will be lost.",
+            PendingAction::New | PendingAction::NewFromTemplate(_) => 
+                "Create new file? Unsaved changes will be lost.",
+            PendingAction::Load | PendingAction::LoadFile(_) | PendingAction::OpenUrl(_) => 
+                "Load file? Unsaved changes will be lost.",
+            PendingAction::Import | PendingAction::ImportFile(_) | PendingAction::ImportUrl(_) |
+            PendingAction::ReloadSource => 
+                "Import from railML? Unsaved changes will be lost.",
+            PendingAction::PluginImport(_,_) =>
+                "Import file? Unsaved changes will be lost.",
+            PendingAction::CloseTab => "Close document? Unsaved changes will be lost.",
             PendingAction::Quit => "Quit program? Unsaved changes will be lost.",
         };
         widgets::show_text(msg);
 
-        let yes = const_cstr!("Save").as_ptr();
-        let no = const_cstr!("Discard").as_ptr();
-        let cancel = const_cstr!("Cancel").as_ptr();
+        let yes = tr!("Save");
+        let no = tr!("Discard");
+        let cancel = tr!("Cancel");
 
         if igButton(yes, ImVec2{ x: 80.0, y: 0.0 }) {
             let model = doc.analysis.model().clone();
-            match file::save_interactive(model) {
+            match file::save_document_interactive(model, doc.saved_history(config).as_ref(), config.backup_count as usize) {
                 Ok(Some(filename)) => { 
+                    config.add_recent_file(filename.clone(), RecentFileKind::Document);
                     doc.set_saved_file(filename);
                     result = Some(true); 
                 },
@@ -3,8 +3,11 @@ use crate::document::model::*;
 use const_cstr::*;
 use backend_glfw::imgui::*;
 use crate::gui::widgets;
+use crate::i18n;
+use crate::units::Units;
+use std::ffi::CString;
 
-pub fn edit_vehicles(doc :&mut Document) {
+pub fn edit_vehicles(doc :&mut Document, units :&Units) {
     unsafe {
     let mut new_model = doc.analysis.model().clone();
     let mut modified = None;
@@ -16,38 +19,42 @@ pub fn edit_vehicles(doc :&mut Document) {
         name.push(0);
         if igCollapsingHeader(name.as_ptr() as _, 0) {
             for i in 0..(3+1) { name.pop(); }
-            if let Some(new_name) = widgets::edit_text(const_cstr!("Name").as_ptr(), name) {
+            if let Some(new_name) = widgets::edit_text(tr!("Name"), name) {
                 new_model.vehicles.get_mut(*i).unwrap().name = new_name;
                 modified = Some(EditClass::VehicleName(*i));
             }
 
             let format = const_cstr!("%.3f");
-            let mut len = v.length;
+            // Length and speed are edited in the configured units.
+            let (lu, su) = (units.length, units.speed);
+            let mut len = lu.from_meters(v.length as f64) as f32;
             let mut acc = v.max_acc;
             let mut brk = v.max_brk;
-            let mut vel = v.max_vel;
-            igSliderFloat(const_cstr!("Length").as_ptr(), 
-                          &mut len as *mut _, 1.0, 1000.0, format.as_ptr(), 1.0);
+            let mut vel = su.from_mps(v.max_vel as f64) as f32;
+            let len_label = CString::new(format!("{} ({})", i18n::tr("Length"), lu.symbol())).unwrap();
+            igSliderFloat(len_label.as_ptr(), &mut len as *mut _,
+                          lu.from_meters(1.0) as f32, lu.from_meters(1000.0) as f32, format.as_ptr(), 1.0);
             if igIsItemEdited() {
-                new_model.vehicles.get_mut(*i).unwrap().length = len;
+                new_model.vehicles.get_mut(*i).unwrap().length = lu.to_meters(len as f64) as f32;
                 modified = Some(EditClass::VehicleLen(*i));
             }
-            igSliderFloat(const_cstr!("Accel").as_ptr(), 
+            igSliderFloat(tr!("Accel"), 
                           &mut acc as *mut _, 0.05, 1.5, format.as_ptr(), 1.0);
             if igIsItemEdited() {
                 new_model.vehicles.get_mut(*i).unwrap().max_acc = acc;
                 modified = Some(EditClass::VehicleAcc(*i));
             }
-            igSliderFloat(const_cstr!("Brake").as_ptr(), 
+            igSliderFloat(tr!("Brake"), 
                           &mut brk as *mut _, 0.05, 1.5, format.as_ptr(), 1.0);
             if igIsItemEdited() {
                 new_model.vehicles.get_mut(*i).unwrap().max_brk = brk;
                 modified = Some(EditClass::VehicleBrk(*i));
             }
-            igSliderFloat(const_cstr!("Max.vel").as_ptr(), 
-                          &mut vel as *mut _, 1.0, 200.0, format.as_ptr(), 1.0);
+            let vel_label = CString::new(format!("{} ({})", i18n::tr("Max.vel"), su.symbol())).unwrap();
+            igSliderFloat(vel_label.as_ptr(), &mut vel as *mut _,
+                          su.from_mps(1.0) as f32, su.from_mps(200.0) as f32, format.as_ptr(), 1.0);
             if igIsItemEdited() {
-                new_model.vehicles.get_mut(*i).unwrap().max_vel = vel;
+                new_model.vehicles.get_mut(*i).unwrap().max_vel = su.to_mps(vel as f64) as f32;
                 modified = Some(EditClass::VehicleVel(*i));
             }
         }
@@ -60,10 +67,10 @@ pub fn edit_vehicles(doc :&mut Document) {
     }
 
     if doc.analysis.model().vehicles.iter().next().is_none() {
-        widgets::show_text("No vehicles defined.");
+        widgets::show_text(i18n::tr("No vehicles defined."));
     }
 
-    if igButton(const_cstr!("Add vehicle").as_ptr(), ImVec2 { x: 0.0, y: 0.0 }) {
+    if igButton(tr!("Add vehicle"), ImVec2 { x: 0.0, y: 0.0 }) {
         doc.analysis.edit_model(|m| {
             let id = m.vehicles.insert( Vehicle {
                 name: String::new(),
@@ -81,13 +88,13 @@ pub fn edit_vehicles(doc :&mut Document) {
 }
 
 
-pub fn edit_vehicles_window(popen :&mut bool, doc :&mut Document) {
+pub fn edit_vehicles_window(popen :&mut bool, doc :&mut Document, units :&Units) {
     if !*popen { return; }
     unsafe {
     widgets::next_window_center_when_appearing();
-    igBegin(const_cstr!("Vehicles").as_ptr(), popen as *mut bool, 0 as _);
+    igBegin(tr!("Vehicles"), popen as *mut bool, 0 as _);
 
-    edit_vehicles(doc);
+    edit_vehicles(doc, units);
 
     igEnd();
     }
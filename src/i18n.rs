@@ -0,0 +1,164 @@
+//! Translation of user-visible text. The English text is the key: a
+//! locale file maps English strings to their translation, and text
+//! without a translation is shown in English.
+//!
+//! Locale files are TOML files named after the language code, e.g. `nb.toml`:
+//!
+//! ```toml
+//! name = "Norsk bokmål"
+//! [strings]
+//! "Save" = "Lagre"
+//! ```
+//!
+//! Locales are built in from the `locales` folder, and more can be added to
+//! the `locales` folder next to the config file.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::sync::RwLock;
+use lazy_static::*;
+use log::*;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// Translated C string for an imgui label, e.g. `igButton(tr!("Save"), ...)`.
+macro_rules! tr {
+    ($s:expr) => { $crate::i18n::tr_cstr(const_cstr::const_cstr!($s)) };
+}
+
+pub const DEFAULT_LANGUAGE :&str = "en";
+
+const BUILTIN :&[(&str, &str)] = &[
+    ("nb", include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/locales/nb.toml"))),
+];
+
+#[derive(Deserialize)]
+struct LocaleFile {
+    name :String,
+    #[serde(default)]
+    strings :HashMap<String,String>,
+}
+
+#[derive(Default)]
+struct Catalog {
+    strings :HashMap<String, &'static str>,
+    /// Translated imgui labels, created on first use.
+    labels :HashMap<String, &'static CStr>,
+}
+
+lazy_static! {
+    static ref CATALOG :RwLock<Catalog> = RwLock::new(Default::default());
+}
+
+/// Available languages as (code, name) pairs, English first.
+pub fn languages() -> Vec<(String,String)> {
+    let mut languages = vec![(DEFAULT_LANGUAGE.to_string(), "English".to_string())];
+    for (code, text) in BUILTIN {
+        if let Ok(f) = toml::from_str::<LocaleFile>(text) { languages.push((code.to_string(), f.name)); }
+    }
+    for (code, path) in user_locales() {
+        if languages.iter().any(|(c,_)| *c == code) { continue; }
+        match read_locale(&path) {
+            Ok(f) => languages.push((code, f.name)),
+            Err(e) => { warn!("Invalid locale file {:?}: {}", path, e); },
+        }
+    }
+    languages
+}
+
+fn user_locales() -> Vec<(String, std::path::PathBuf)> {
+    let dir = match Config::subdir("locales") { Some(d) => d, None => return Vec::new() };
+    let mut locales = std::fs::read_dir(&dir).into_iter().flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|e| e == "toml").unwrap_or(false))
+        .filter_map(|p| Some((p.file_stem()?.to_string_lossy().to_string(), p)))
+        .collect::<Vec<_>>();
+    locales.sort();
+    locales
+}
+
+fn read_locale(path :&std::path::Path) -> Result<LocaleFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}", e))?;
+    toml::from_str(&text).map_err(|e| format!("{}", e))
+}
+
+fn load_strings(language :&str) -> Result<HashMap<String,String>, String> {
+    if language == DEFAULT_LANGUAGE { return Ok(HashMap::new()); }
+    // A user locale replaces a built-in locale with the same code.
+    if let Some((_,path)) = user_locales().into_iter().find(|(c,_)| c == language) {
+        return Ok(read_locale(&path)?.strings);
+    }
+    let (_,text) = BUILTIN.iter().find(|(c,_)| *c == language)
+        .ok_or_else(|| format!("Unknown language {:?}", language))?;
+    Ok(toml::from_str::<LocaleFile>(text).map_err(|e| format!("{}", e))?.strings)
+}
+
+/// Switch the language of the user interface. The strings of the previous
+/// language are not freed, as labels may still point to them.
+pub fn set_language(language :&str) {
+    let strings = match load_strings(language) {
+        Ok(s) => s,
+        Err(e) => { error!("Could not load language: {}", e); HashMap::new() },
+    };
+    let mut catalog = CATALOG.write().unwrap();
+    catalog.labels.clear();
+    catalog.strings = strings.into_iter().map(|(k,v)| (k, &*Box::leak(v.into_boxed_str()))).collect();
+}
+
+/// Translate a text, or return it unchanged if there is no translation.
+pub fn tr<'a>(s :&'a str) -> &'a str {
+    CATALOG.read().unwrap().strings.get(s).map(|t| *t).unwrap_or(s)
+}
+
+/// Translate a text with `{}` placeholders and fill them in order.
+pub fn tr_fmt(s :&str, args :&[&str]) -> String {
+    let mut parts = tr(s).split("{}");
+    let mut out = parts.next().unwrap_or("").to_string();
+    for (i,part) in parts.enumerate() {
+        out.push_str(args.get(i).copied().unwrap_or(""));
+        out.push_str(part);
+    }
+    out
+}
+
+/// Translated label for imgui. Anything after `##` is kept, as it is
+/// part of the widget ID and not shown.
+pub fn tr_cstr(s :const_cstr::ConstCStr) -> *const c_char {
+    let text = match s.as_cstr().to_str() { Ok(t) => t, Err(_) => return s.as_ptr() };
+    {
+        let catalog = CATALOG.read().unwrap();
+        if catalog.strings.is_empty() { return s.as_ptr(); }
+        if let Some(l) = catalog.labels.get(text) { return l.as_ptr(); }
+    }
+    let (shown, id) = match text.find("##") {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+    let translated = tr(shown);
+    if translated == shown { return s.as_ptr(); }
+    let label :&'static CStr = match CString::new(format!("{}{}", translated, id)) {
+        Ok(l) => Box::leak(l.into_boxed_c_str()),
+        Err(_) => return s.as_ptr(),
+    };
+    CATALOG.write().unwrap().labels.insert(text.to_string(), label);
+    label.as_ptr()
+}
+
+#[test]
+pub fn format_translation() {
+    assert_eq!(tr_fmt("Could not open {}: {}", &["a.txt", "not found"]), "Could not open a.txt: not found");
+}
+
+#[test]
+pub fn builtin_locales() {
+    for (code, text) in BUILTIN {
+        let f :LocaleFile = toml::from_str(text).unwrap();
+        assert!(!f.strings.is_empty(), "locale {} has no strings", code);
+        // Translations may not drop the format placeholders of the English text.
+        for (en, t) in &f.strings {
+            assert_eq!(en.matches("{}").count(), t.matches("{}").count(), "{} in {}", en, code);
+        }
+    }
+}
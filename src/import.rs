@@ -1,7 +1,6 @@
 use std::collections::{HashMap, HashSet};
 use log::*;
 use matches::matches;
-use const_cstr::const_cstr;
 use crate::document::model::*;
 use crate::document::model;
 use crate::document::analysis::*;
@@ -10,6 +9,12 @@ use crate::file;
 use crate::app::*;
 use crate::gui::widgets;
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use crate::i18n;
+use railmlio::geo::{Crs, Projection};
 
 pub enum ImportError {
 }
@@ -21,6 +26,26 @@ pub struct ImportWindow {
     thread :Option<mpsc::Receiver<ImportState>>,
     thread_pool :BackgroundJobs,
     auto_scale :bool,
+    bundle_source :bool,
+    filename :Option<String>,
+    default_path :Option<String>,
+    /// Add the imported infrastructure to the current model at
+    /// `merge_offset`, instead of replacing the model.
+    merge :bool,
+    merge_offset :[i32; 2],
+    /// Show the lines, tracks and OCPs of the file to choose from before
+    /// importing.
+    select_parts :bool,
+    selected_tracks :HashSet<String>,
+    /// File being imported, which `filename` may name differently.
+    path :Option<String>,
+    /// Set to stop the running background job.
+    cancel :Arc<AtomicBool>,
+    /// Copy of the solver settings in the config, for the background jobs.
+    solver :SolverSettings,
+    /// The layout solver of the background jobs, which may still be running
+    /// after its import was cancelled.
+    solver_thread :SolverThread,
 }
 
 impl ImportWindow {
@@ -31,21 +56,268 @@ impl ImportWindow {
             thread: None,
             thread_pool:thread_pool,
             auto_scale: true,
+            bundle_source: false,
+            filename: None,
+            default_path: None,
+            merge: false,
+            merge_offset: [0, 0],
+            select_parts: false,
+            selected_tracks: HashSet::new(),
+            path: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            solver: SolverSettings::default(),
+            solver_thread: SolverThread::default(),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ImportState {
-    Ping,
+    /// The import is at the given stage, with the fraction of the stage
+    /// done if known, and the time since the import started.
+    Progress(ImportStage, Option<f32>, Duration),
     ChooseFile,
     ReadingFile,
+    /// The contents of the file, to choose which tracks to import.
+    ChooseParts(ImportContents),
     SourceFileError(String),
+    /// The railML document has errors that prevent importing it.
+    ValidationFailed(Vec<railmlio::validate::ValidationIssue>),
     PlotError(String),
     WaitForDrawing,
     Available(Model),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImportStage {
+    Reading,
+    Parsing,
+    Validating,
+    Topology,
+    Mileage,
+    /// Solving the layout, where the second attempt uses estimated mileage.
+    Solving { attempt :usize },
+    Drawing,
+    Converting,
+}
+
+impl ImportStage {
+    pub fn description(&self) -> String {
+        match self {
+            ImportStage::Reading => i18n::tr("Reading file").to_string(),
+            ImportStage::Parsing => i18n::tr("Parsing railML").to_string(),
+            ImportStage::Validating => i18n::tr("Validating").to_string(),
+            ImportStage::Topology => i18n::tr("Converting topology").to_string(),
+            ImportStage::Mileage => i18n::tr("Calculating mileage").to_string(),
+            ImportStage::Solving { attempt } => i18n::tr_fmt("Running solver (attempt {})", &[&attempt.to_string()]),
+            ImportStage::Drawing => i18n::tr("Drawing layout").to_string(),
+            ImportStage::Converting => i18n::tr("Converting to model").to_string(),
+        }
+    }
+}
+
+/// Layout goals of the schematic solver, see `railplotlib::solvers::Goal`.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SolverGoal {
+    Bends,
+    Height,
+    Width,
+    LocalX,
+    LocalY,
+}
+
+impl SolverGoal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SolverGoal::Bends => "Fewest bends",
+            SolverGoal::Height => "Least height",
+            SolverGoal::Width => "Least width",
+            SolverGoal::LocalX => "Short horizontal distances",
+            SolverGoal::LocalY => "Short vertical distances",
+        }
+    }
+
+    fn goal(&self) -> railplotlib::solvers::Goal {
+        match self {
+            SolverGoal::Bends => railplotlib::solvers::Goal::Bends,
+            SolverGoal::Height => railplotlib::solvers::Goal::Height,
+            SolverGoal::Width => railplotlib::solvers::Goal::Width,
+            SolverGoal::LocalX => railplotlib::solvers::Goal::LocalX,
+            SolverGoal::LocalY => railplotlib::solvers::Goal::LocalY,
+        }
+    }
+}
+
+/// Settings of the schematic layout solver used at import.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SolverSettings {
+    /// Goals in order of priority, and whether each is used.
+    pub goals :Vec<(SolverGoal, bool)>,
+    pub nodes_distinct :bool,
+    /// Seconds before the solver is given up for the simpler layouts,
+    /// 0 for no limit.
+    pub time_limit :f32,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        SolverSettings {
+            goals: vec![
+                (SolverGoal::Bends, true),
+                (SolverGoal::Height, true),
+                (SolverGoal::Width, true),
+                (SolverGoal::LocalX, true),
+                (SolverGoal::LocalY, true),
+            ],
+            nodes_distinct: false,
+            time_limit: 0.0,
+        }
+    }
+}
+
+impl SolverSettings {
+    /// The solver only takes the plot model, without an initial solution or
+    /// preferred node positions, so every import is solved from scratch.
+    fn solver(&self) -> railplotlib::solvers::LevelsSatSolver {
+        railplotlib::solvers::LevelsSatSolver {
+            criteria: self.goals.iter().filter(|(_,used)| *used).map(|(g,_)| g.goal()).collect(),
+            nodes_distinct: self.nodes_distinct,
+        }
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        if self.time_limit > 0.0 { Some(Duration::from_secs_f32(self.time_limit)) } else { None }
+    }
+}
+
+/// The channel from a background import to the import window, the flag
+/// the window sets to cancel it, and the solver settings to use.
+#[derive(Clone)]
+pub struct ImportJob {
+    tx :mpsc::Sender<ImportState>,
+    cancel :Arc<AtomicBool>,
+    started :Instant,
+    solver :SolverSettings,
+    solver_thread :SolverThread,
+}
+
+impl ImportJob {
+    pub fn new(tx :mpsc::Sender<ImportState>) -> Self {
+        ImportJob { 
+            tx, 
+            cancel: Arc::new(AtomicBool::new(false)), 
+            started: Instant::now(), 
+            solver: SolverSettings::default(),
+            solver_thread: SolverThread::default(),
+        }
+    }
+
+    pub fn with_solver(mut self, solver :SolverSettings) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    /// Run the layout solver in `solver_thread`, so that no solver is started
+    /// while one from an earlier job is still running.
+    pub fn with_solver_thread(mut self, solver_thread :SolverThread) -> Self {
+        self.solver_thread = solver_thread;
+        self
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Report progress, and whether the import should continue.
+    pub fn progress(&self, stage :ImportStage, fraction :Option<f32>) -> bool {
+        !self.cancelled() && self.tx.send(ImportState::Progress(stage, fraction, self.started.elapsed())).is_ok()
+    }
+
+    pub fn send(&self, state :ImportState) {
+        let _ = self.tx.send(state);
+    }
+}
+
+/// The text of a file being parsed, read in chunks so that the fraction
+/// read can be reported, and failing when the import is cancelled.
+struct ProgressReader<'a> {
+    data :&'a [u8],
+    pos :usize,
+    reported :usize,
+    job :&'a ImportJob,
+}
+
+impl<'a> ProgressReader<'a> {
+    const CHUNK :usize = 64*1024;
+    const REPORT_EVERY :usize = 1024*1024;
+
+    fn new(data :&'a [u8], job :&'a ImportJob) -> Self {
+        ProgressReader { data, pos: 0, reported: 0, job }
+    }
+}
+
+impl<'a> std::io::Read for ProgressReader<'a> {
+    fn read(&mut self, buf :&mut [u8]) -> std::io::Result<usize> {
+        use std::io::BufRead;
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a> std::io::BufRead for ProgressReader<'a> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.job.cancelled() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "import cancelled"));
+        }
+        let end = (self.pos + Self::CHUNK).min(self.data.len());
+        Ok(&self.data[self.pos..end])
+    }
+
+    fn consume(&mut self, amt :usize) {
+        self.pos += amt;
+        if self.pos - self.reported >= Self::REPORT_EVERY {
+            self.reported = self.pos;
+            self.job.progress(ImportStage::Parsing, Some(self.pos as f32 / self.data.len() as f32));
+        }
+    }
+}
+
+/// Lines, tracks and OCPs of a railML document, to choose what to import.
+#[derive(Debug, Clone, Default)]
+pub struct ImportContents {
+    /// Lines as id, name and the ids of their tracks.
+    pub lines :Vec<(String, Option<String>, Vec<String>)>,
+    pub tracks :Vec<(String, Option<String>)>,
+    /// OCPs as id, name and the ids of the tracks in their equipment.
+    pub ocps :Vec<(String, Option<String>, Vec<String>)>,
+}
+
+impl ImportContents {
+    pub fn from_railml(railml :&railmlio::model::RailML) -> Self {
+        let mut contents = ImportContents::default();
+        if let Some(inf) = railml.infrastructure.as_ref() {
+            contents.lines = inf.track_groups.iter()
+                .map(|g| (g.id.clone(), g.name.clone(), g.track_refs.iter().map(|r| r.r#ref.clone()).collect()))
+                .collect();
+            contents.tracks = inf.tracks.iter().map(|t| (t.id.clone(), t.name.clone())).collect();
+            contents.ocps = inf.ocps.iter()
+                .map(|o| (o.id.clone(), o.name.clone(), o.prop_equipment.iter()
+                          .flat_map(|e| e.track_refs.iter().cloned()).collect()))
+                .collect();
+        }
+        contents
+    }
+}
+
 impl ImportWindow {
     pub fn open(&mut self) {
         self.open = true;
@@ -53,6 +325,13 @@ impl ImportWindow {
         self.thread = None;
     }
 
+    /// Use the import options stored in a document.
+    pub fn use_settings(&mut self, settings :&ImportSettings) {
+        self.auto_scale = settings.auto_scale;
+        self.bundle_source = settings.bundle_source;
+        self.default_path = Some(settings.filename.clone());
+    }
+
     pub fn update(&mut self) {
         while let Some(Ok(msg)) = self.thread.as_mut().map(|rx| rx.try_recv()) {
             println!("import window new  state: {:?}", msg);
@@ -60,59 +339,455 @@ impl ImportWindow {
         }
     }
 
-    pub fn draw(&mut self, doc :&mut Analysis) {
+    pub fn draw(&mut self, doc :&mut Analysis, config :&mut crate::config::Config) {
+        self.solver = config.solver.clone();
         if !self.open { return; }
         use backend_glfw::imgui::*;
         unsafe {
         widgets::next_window_center_when_appearing();
-        igBegin(const_cstr!("Import from railML file").as_ptr(), &mut self.open as _, 0 as _);
+        igBegin(tr!("Import from railML file"), &mut self.open as _, 0 as _);
 
         let mut auto_scale = self.auto_scale;
-        igCheckbox(const_cstr!("Auto-scale small layouts").as_ptr(), &mut auto_scale);
+        igCheckbox(tr!("Auto-scale small layouts"), &mut auto_scale);
         self.auto_scale = auto_scale;
+        let mut bundle_source = self.bundle_source;
+        igCheckbox(tr!("Embed railML source in document"), &mut bundle_source);
+        self.bundle_source = bundle_source;
+        igCheckbox(tr!("Choose tracks before import"), &mut self.select_parts);
+        igCheckbox(tr!("Merge into current model"), &mut self.merge);
+        if self.merge {
+            igInputInt2(tr!("Offset"), self.merge_offset.as_mut_ptr(), 0 as _);
+        }
+        if igCollapsingHeader(tr!("Layout solver"), 0) && draw_solver_settings(&mut config.solver) {
+            config.save();
+            self.solver = config.solver.clone();
+        }
 
         match &self.state {
+            ImportState::ChooseFile if self.solver_thread.is_running() => {
+                widgets::show_text(i18n::tr("Waiting for the layout solver of the cancelled import to finish"));
+            },
             ImportState::ChooseFile => {
-                if igButton(const_cstr!("Browse for file...").as_ptr(),
+                if igButton(tr!("Browse for file..."),
                             ImVec2 { x: 120.0, y: 0.0 }) {
 
-                    if let Some(filename) = tinyfiledialogs::open_file_dialog("Select railML file.","", None) {
+                    let default_path = self.default_path.clone().unwrap_or_default();
+                    if let Some(filename) = tinyfiledialogs::open_file_dialog("Select railML or OpenStreetMap file.", &default_path, None) {
                         self.background_load_file(filename);
                     }
                 }
             },
 
             ImportState::Available(model) => {
-                if igButton(const_cstr!("Import").as_ptr(), ImVec2 { x: 80.0, y: 0.0 }) {
-                    *doc = Analysis::from_model( model.clone(), self.thread_pool.clone());  
+                if self.merge {
+                    igSameLine(0.0, -1.0);
+                    if igButton(tr!("Below current model"), ImVec2::zero()) {
+                        self.merge_offset = offset_below(doc.model(), model);
+                    }
+                }
+                if self.merge && igButton(tr!("Import"), ImVec2 { x: 80.0, y: 0.0 }) {
+                    let offset = nalgebra_glm::vec2(self.merge_offset[0], self.merge_offset[1]);
+                    if let Some(filename) = self.filename.take() {
+                        config.add_recent_file(filename, crate::config::RecentFileKind::RailML);
+                    }
+                    let model = model.clone();
+                    let mut renames = Vec::new();
+                    doc.edit_model(|m| { renames = m.add_infrastructure(model, offset); None });
+                    for (old, new) in &renames {
+                        info!("Merged railML id {:?} renamed to {:?}", old, new);
+                    }
+                    self.close();
+                } else if !self.merge && igButton(tr!("Import"), ImVec2 { x: 80.0, y: 0.0 }) {
+                    let mut model = model.clone();
+                    let filename = self.filename.take();
+                    if let Some(filename) = &filename {
+                        config.add_recent_file(filename.clone(), crate::config::RecentFileKind::RailML);
+                    }
+                    let filename = filename.or_else(|| model.railml_source.as_ref().map(|s| s.filename.clone()));
+                    if let Some(filename) = filename {
+                        // Importing the same file again keeps the document's export settings.
+                        let prev = &doc.model().file_settings;
+                        if prev.import.as_ref().map(|s| &s.filename) == Some(&filename) {
+                            model.file_settings = prev.clone();
+                        }
+                        model.file_settings.import = Some(ImportSettings {
+                            filename,
+                            auto_scale: self.auto_scale,
+                            bundle_source: self.bundle_source,
+                        });
+                    }
+                    *doc = Analysis::from_model(model, self.thread_pool.clone());  
                     //doc.fileinfo.set_unsaved();
                     self.close();
                 }
             },
-            ImportState::Ping => { widgets::show_text("Running solver"); },
-            x => { widgets::show_text(&format!("{:?}", x)); },
+            ImportState::ChooseParts(contents) => {
+                draw_import_parts(contents, &mut self.selected_tracks);
+                widgets::show_text(&i18n::tr_fmt("{} of {} tracks selected", &[
+                    &self.selected_tracks.len().to_string(),
+                    &contents.tracks.len().to_string(),
+                ]));
+                if !self.selected_tracks.is_empty() && 
+                    igButton(tr!("Import selected"), ImVec2 { x: 120.0, y: 0.0 }) {
+                    self.background_load_selection();
+                }
+            },
+            ImportState::Progress(stage, fraction, elapsed) => {
+                widgets::show_text(&stage.description());
+                if let Some(fraction) = fraction {
+                    igProgressBar(*fraction, ImVec2 { x: -1.0, y: 0.0 }, std::ptr::null());
+                }
+                widgets::show_text(&i18n::tr_fmt("{} s elapsed", &[&format!("{:.1}", elapsed.as_secs_f32())]));
+                if igButton(tr!("Cancel"), ImVec2 { x: 80.0, y: 0.0 }) { self.cancel(); }
+            },
+            ImportState::ReadingFile => { 
+                widgets::show_text(i18n::tr("Reading file")); 
+                if igButton(tr!("Cancel"), ImVec2 { x: 80.0, y: 0.0 }) { self.cancel(); }
+            },
+            ImportState::WaitForDrawing => { widgets::show_text(i18n::tr("Drawing layout")); },
+            ImportState::SourceFileError(e) | ImportState::PlotError(e) => { widgets::show_text(e); },
+            ImportState::ValidationFailed(issues) => { draw_validation_issues(issues); },
         }
 
         igEnd();
         }
     }
 
-    pub fn background_load_file(&mut self, filename :String) {
-        info!("Starting background loading of railml from file {:?}", filename);
+    /// Channel and cancel flag for a new background job, which replaces
+    /// any running job.
+    fn start_job(&mut self) -> ImportJob {
+        self.cancel.store(true, Ordering::Relaxed);
         let (tx,rx) = mpsc::channel();
         self.thread = Some(rx);
+        let job = ImportJob::new(tx).with_solver(self.solver.clone())
+            .with_solver_thread(self.solver_thread.clone());
+        self.cancel = job.cancel.clone();
+        job
+    }
+
+    /// Stop the running background job.
+    pub fn cancel(&mut self) {
+        info!("Import cancelled");
+        self.cancel.store(true, Ordering::Relaxed);
+        self.thread = None;
+        self.state = ImportState::ChooseFile;
+    }
+
+    pub fn background_load_file(&mut self, filename :String) {
+        let job = self.start_job();
+        self.filename = Some(filename.clone());
+        self.path = Some(filename.clone());
+        if is_osm_file(&filename) {
+            info!("Starting background loading of OpenStreetMap extract {:?}", filename);
+            let auto_scale = self.auto_scale;
+            self.thread_pool.execute(move || { load_osm_file(filename, job, auto_scale); });
+            return;
+        }
+        if self.select_parts {
+            info!("Reading contents of railml file {:?}", filename);
+            self.selected_tracks.clear();
+            self.thread_pool.execute(move || { read_railml_contents(filename, job); });
+            return;
+        }
+        info!("Starting background loading of railml from file {:?}", filename);
+        let auto_scale = self.auto_scale;
+        let bundle_source = self.bundle_source;
+        self.thread_pool.execute(move || { load_railml_file(filename, job, auto_scale, bundle_source, None); });
+    }
+
+    /// Import the chosen tracks of the file whose contents are shown.
+    fn background_load_selection(&mut self) {
+        let filename = match self.path.clone() { Some(f) => f, None => return };
+        info!("Starting background loading of {} tracks from file {:?}", self.selected_tracks.len(), filename);
+        let job = self.start_job();
+        self.state = ImportState::ReadingFile;
         let auto_scale = self.auto_scale;
-        self.thread_pool.execute(move || { load_railml_file(filename, tx, auto_scale); });
+        let bundle_source = self.bundle_source;
+        let selection = self.selected_tracks.clone();
+        self.thread_pool.execute(move || { 
+            load_railml_file(filename, job, auto_scale, bundle_source, Some(selection)); 
+        });
+    }
+
+    /// Import again from the document's railML source, using the original
+    /// file if it still exists and the embedded copy otherwise.
+    pub fn reload_source(&mut self, source :Arc<RailMLSourceFile>) {
+        self.open();
+        self.bundle_source = true;
+        if std::path::Path::new(&source.filename).exists() {
+            self.background_load_file(source.filename.clone());
+            return;
+        }
+        info!("Starting background loading of embedded railml {:?}", source.filename);
+        let job = self.start_job();
+        self.filename = None;
+        let auto_scale = self.auto_scale;
+        let bundle_source = self.bundle_source;
+        self.thread_pool.execute(move || { load_railml_bundle(source, job, auto_scale, bundle_source); });
+    }
+
+    /// Load a local copy of a file, but record it as `name` in the recent
+    /// files and the document's import settings.
+    pub fn background_load_file_as(&mut self, filename :String, name :String) {
+        self.background_load_file(filename);
+        self.filename = Some(name);
     }
 
     pub fn close(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
         self.open = false;
         self.state = ImportState::ChooseFile;
         self.thread = None;
+        self.filename = None;
+        self.path = None;
+    }
+}
+
+/// Goals of the layout solver in order of priority, with checkboxes and
+/// buttons for reordering them. Returns true if the settings were changed.
+fn draw_solver_settings(settings :&mut SolverSettings) -> bool {
+    use backend_glfw::imgui::*;
+    let mut changed = false;
+    unsafe {
+        let mut swap = None;
+        let n = settings.goals.len();
+        for (idx, (goal, used)) in settings.goals.iter_mut().enumerate() {
+            igPushIDInt(idx as _);
+            if igButton(const_cstr::const_cstr!("\u{f062}").as_ptr(), ImVec2::zero()) && idx > 0 {
+                swap = Some(idx - 1);
+            }
+            igSameLine(0.0, -1.0);
+            if igButton(const_cstr::const_cstr!("\u{f063}").as_ptr(), ImVec2::zero()) && idx + 1 < n {
+                swap = Some(idx);
+            }
+            igSameLine(0.0, -1.0);
+            let label = std::ffi::CString::new(i18n::tr(goal.name())).unwrap_or_default();
+            changed |= igCheckbox(label.as_ptr(), used);
+            igPopID();
+        }
+        if let Some(idx) = swap {
+            settings.goals.swap(idx, idx + 1);
+            changed = true;
+        }
+        changed |= igCheckbox(tr!("Distinct node positions"), &mut settings.nodes_distinct);
+        changed |= igInputFloat(tr!("Time limit (s, 0 = none)"), &mut settings.time_limit, 1.0, 10.0, 
+                                const_cstr::const_cstr!("%.0f").as_ptr(), 0 as _);
+        settings.time_limit = settings.time_limit.max(0.0);
+    }
+    changed
+}
+
+/// Checkbox for a set of tracks, checked when all of them are selected.
+unsafe fn tracks_checkbox(label :&str, tracks :&[String], selected :&mut HashSet<String>) {
+    use backend_glfw::imgui::*;
+    let mut all = !tracks.is_empty() && tracks.iter().all(|t| selected.contains(t));
+    let label = std::ffi::CString::new(label).unwrap_or_default();
+    if igCheckbox(label.as_ptr(), &mut all) {
+        for t in tracks {
+            if all { selected.insert(t.clone()); } else { selected.remove(t); }
+        }
+    }
+}
+
+/// Tree of lines, their tracks and the OCPs on the tracks, with checkboxes
+/// for choosing the tracks to import.
+fn draw_import_parts(contents :&ImportContents, selected :&mut HashSet<String>) {
+    use backend_glfw::imgui::*;
+    let label = |id :&str, name :&Option<String>| match name {
+        Some(name) => format!("{} ({})", name, id),
+        None => id.to_string(),
+    };
+    let draw_track = |idx :usize, id :&str, selected :&mut HashSet<String>| unsafe {
+        igPushIDInt(idx as _);
+        let name = contents.tracks.iter().find(|(t,_)| t == id).and_then(|(_,n)| n.clone());
+        tracks_checkbox(&label(id, &name), &[id.to_string()], selected);
+        igIndent(14.0);
+        for (ocp_idx, (ocp, name, tracks)) in contents.ocps.iter().enumerate() {
+            if !tracks.iter().any(|t| t == id) { continue; }
+            igPushIDInt(ocp_idx as _);
+            tracks_checkbox(&format!("\u{f015} {}", label(ocp, name)), tracks, selected);
+            igPopID();
+        }
+        igUnindent(14.0);
+        igPopID();
+    };
+    unsafe {
+        igBeginChild(tr!("Tracks to import"), ImVec2 { x: 0.0, y: 300.0 }, true, 0 as _);
+        for (line_idx, (id, name, tracks)) in contents.lines.iter().enumerate() {
+            igPushIDInt(line_idx as _);
+            tracks_checkbox("", tracks, selected);
+            igSameLine(0.0, -1.0);
+            let text = std::ffi::CString::new(label(id, name)).unwrap_or_default();
+            if igTreeNodeStr(text.as_ptr()) {
+                for (idx, track) in tracks.iter().enumerate() { draw_track(idx, track, selected); }
+                igTreePop();
+            }
+            igPopID();
+        }
+        let other = contents.tracks.iter().enumerate()
+            .filter(|(_,(t,_))| !contents.lines.iter().any(|(_,_,ts)| ts.contains(t)))
+            .collect::<Vec<_>>();
+        if !other.is_empty() && igTreeNodeStr(tr!("Tracks not in a line")) {
+            for (idx, (track,_)) in other { draw_track(idx, track, selected); }
+            igTreePop();
+        }
+        igEndChild();
     }
 }
 
-pub fn load_railml_file(filename :String, tx :mpsc::Sender<ImportState>, auto_scale: bool)  {
+/// Keep only the given tracks of the infrastructure. Connections to other
+/// tracks become open ends, switches left without connections are removed,
+/// and lines and OCPs are kept if they refer to one of the tracks.
+fn select_tracks(inf :&mut railmlio::model::Infrastructure, keep :&HashSet<String>) {
+    use railmlio::model::{Switch, TrackEndConnection};
+    fn switch_connections(sw :&mut Switch) -> &mut Vec<railmlio::model::SwitchConnection> {
+        match sw { Switch::Switch { connections, .. } | Switch::Crossing { connections, .. } => connections }
+    }
+
+    inf.tracks.retain(|t| keep.contains(&t.id));
+    let mut kept_connections = HashSet::new();
+    for track in inf.tracks.iter_mut() {
+        for node in &[&track.begin, &track.end] {
+            if let TrackEndConnection::Connection(id, _) = &node.connection { kept_connections.insert(id.clone()); }
+        }
+        for sw in track.switches.iter_mut() {
+            kept_connections.extend(switch_connections(sw).iter().map(|c| c.id.clone()));
+        }
+    }
+    for track in inf.tracks.iter_mut() {
+        for node in vec![&mut track.begin, &mut track.end] {
+            if let TrackEndConnection::Connection(_, r) = &node.connection {
+                if !kept_connections.contains(r) { node.connection = TrackEndConnection::OpenEnd; }
+            }
+        }
+        for sw in track.switches.iter_mut() {
+            switch_connections(sw).retain(|c| kept_connections.contains(&c.r#ref));
+        }
+        let track_id = &track.id;
+        track.switches.retain(|sw| {
+            let (id, connections) = match sw { 
+                Switch::Switch { id, connections, .. } | Switch::Crossing { id, connections, .. } => (id, connections) 
+            };
+            if connections.is_empty() { info!("Switch {:?} on track {:?} is left out of the import", id, track_id); }
+            !connections.is_empty()
+        });
+    }
+    for group in inf.track_groups.iter_mut() {
+        group.track_refs.retain(|r| keep.contains(&r.r#ref));
+    }
+    inf.track_groups.retain(|g| !g.track_refs.is_empty());
+    inf.ocps.retain(|o| o.prop_equipment.iter().flat_map(|e| e.track_refs.iter()).any(|r| keep.contains(r)));
+}
+
+/// Offset which places the infrastructure of `other` below that of `model`,
+/// aligned at the left, with some space between them.
+fn offset_below(model :&Model, other :&Model) -> [i32; 2] {
+    let pts = |m :&Model| m.linesegs.iter().flat_map(|(a,b)| vec![*a,*b]).collect::<Vec<_>>();
+    let (pts, other_pts) = (pts(model), pts(other));
+    if pts.is_empty() || other_pts.is_empty() { return [0, 0]; }
+    let min_x = pts.iter().map(|p| p.x).min().unwrap();
+    let min_y = pts.iter().map(|p| p.y).min().unwrap();
+    let other_min_x = other_pts.iter().map(|p| p.x).min().unwrap();
+    let other_max_y = other_pts.iter().map(|p| p.y).max().unwrap();
+    [min_x - other_min_x, min_y - other_max_y - 5]
+}
+
+/// Table of the problems found in the railML document.
+fn draw_validation_issues(issues :&[railmlio::validate::ValidationIssue]) {
+    use backend_glfw::imgui::*;
+    unsafe {
+        widgets::show_text(i18n::tr("The railML file has errors:"));
+        igColumns(4, std::ptr::null(), true);
+        for header in &[i18n::tr("Severity"), i18n::tr("Id"), i18n::tr("Byte offset"), i18n::tr("Message")] {
+            widgets::show_text(header);
+            igNextColumn();
+        }
+        igSeparator();
+        for issue in issues {
+            widgets::show_text(i18n::tr(issue.severity.name()));
+            igNextColumn();
+            widgets::show_text(issue.id.as_deref().unwrap_or(""));
+            igNextColumn();
+            widgets::show_text(&issue.byte_offset.map(|o| o.to_string()).unwrap_or_default());
+            igNextColumn();
+            widgets::show_text(&issue.message);
+            igNextColumn();
+        }
+        igColumns(1, std::ptr::null(), false);
+    }
+}
+
+/// The text of a railML file. Plain files are memory-mapped and parsed in
+/// place; gzip files and zip archives are decompressed into memory.
+pub enum RailMLSource {
+    Mapped(memmap::Mmap),
+    Decoded(String),
+}
+
+impl RailMLSource {
+    pub fn open(filename :&str) -> Result<Self, String> {
+        let file = std::fs::File::open(filename).map_err(|e| format!("{}", e))?;
+        let len = file.metadata().map_err(|e| format!("{}", e))?.len();
+        if len == 0 { return Ok(RailMLSource::Decoded(String::new())); }
+        let mmap = unsafe { memmap::Mmap::map(&file) }.map_err(|e| format!("{}", e))?;
+
+        if mmap.starts_with(&[0x1f, 0x8b]) {
+            use std::io::Read;
+            let mut s = String::new();
+            flate2::read::GzDecoder::new(&mmap[..]).read_to_string(&mut s)
+                .map_err(|e| format!("gzip: {}", e))?;
+            Ok(RailMLSource::Decoded(s))
+        } else if mmap.starts_with(b"PK\x03\x04") {
+            Ok(RailMLSource::Decoded(read_zipped_railml(&mmap[..])?))
+        } else {
+            Ok(RailMLSource::Mapped(mmap))
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            RailMLSource::Mapped(m) => std::str::from_utf8(&m[..]).map_err(|e| format!("{}", e)),
+            RailMLSource::Decoded(s) => Ok(s),
+        }
+    }
+}
+
+/// Read the railML document from a zip archive, which is the first entry
+/// with an .xml or .railml extension.
+fn read_zipped_railml(data :&[u8]) -> Result<String, String> {
+    use std::io::Read;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| format!("zip: {}", e))?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| format!("zip: {}", e))?;
+        let name = entry.name().to_lowercase();
+        if name.ends_with(".xml") || name.ends_with(".railml") {
+            info!("Reading {:?} from zip archive", entry.name());
+            let mut s = String::new();
+            entry.read_to_string(&mut s).map_err(|e| format!("zip: {}", e))?;
+            return Ok(s);
+        }
+    }
+    Err("zip archive contains no .xml or .railml file".to_string())
+}
+
+/// Parse a railML file and send its lines, tracks and OCPs, see
+/// `ImportContents`.
+pub fn read_railml_contents(filename :String, job :ImportJob) {
+    if !job.progress(ImportStage::Reading, None) { return; }
+    let parsed = RailMLSource::open(&filename)
+        .and_then(|source| railmlio::stream::parse_railml_reader(ProgressReader::new(source.as_str()?.as_bytes(), &job))
+                  .map_err(|e| format!("{:?}", e)));
+    match parsed {
+        _ if job.cancelled() => {},
+        Ok(parsed) => job.send(ImportState::ChooseParts(ImportContents::from_railml(&parsed))),
+        Err(e) => job.send(ImportState::SourceFileError(i18n::tr_fmt("Read error: {}", &[e.as_str()]))),
+    }
+}
+
+/// Import a railML file, with only the tracks in `selection` if it is given.
+pub fn load_railml_file(filename :String, job :ImportJob, auto_scale: bool, bundle_source :bool,
+                        selection :Option<HashSet<String>>)  {
     // outline of steps
     // 1. read file 
     // 2. convert to railml
@@ -121,97 +796,267 @@ pub fn load_railml_file(filename :String, tx :mpsc::Sender<ImportState>, auto_sc
     // 5. solve railplotlib
     // 6. convert to junction model (linesegments, nodes, objects/wlocations)
 
-    let s = match std::fs::read_to_string(&filename) {
+    let source = match RailMLSource::open(&filename) {
+        Ok(s) => s,
+        Err(e) => {
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Read error: {}", &[&e.to_string()])));
+            return;
+        }
+    };
+    let s = match source.as_str() {
+        Ok(s) => s,
+        Err(e) => {
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Read error: {}", &[&e.to_string()])));
+            return;
+        }
+    };
+    if !job.progress(ImportStage::Parsing, Some(0.0)) { return; }
+    info!("Read file {:?}", filename);
+
+    let bundle = if bundle_source {
+        match RailMLSourceFile::compress(&filename, s) {
+            Ok(b) => Some(Arc::new(b)),
+            Err(e) => {
+                job.send(ImportState::SourceFileError(i18n::tr_fmt("Compression error: {}", &[&e.to_string()])));
+                return;
+            }
+        }
+    } else { None };
+    load_railml_text(s, bundle, job, auto_scale, selection.as_ref());
+}
+
+/// Import from the railML source embedded in a document. The embedded copy
+/// is kept in the new model if `bundle_source` is set.
+pub fn load_railml_bundle(bundle :Arc<RailMLSourceFile>, job :ImportJob, 
+                          auto_scale :bool, bundle_source :bool) {
+    let s = match bundle.decompress() {
         Ok(s) => s,
         Err(e) => {
-            let _ = tx.send(ImportState::SourceFileError(format!("Read error: {}", e)));
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Read error: {}", &[&e.to_string()])));
             return;
         }
     };
-    if tx.send(ImportState::Ping).is_err() { return; }
+    if !job.progress(ImportStage::Parsing, Some(0.0)) { return; }
+    info!("Read embedded railML source {:?}", bundle.filename);
+    load_railml_text(&s, if bundle_source { Some(bundle) } else { None }, job, auto_scale, None);
+}
+
+/// Whether a file is imported as an OpenStreetMap extract instead of railML.
+pub fn is_osm_file(filename :&str) -> bool {
+    let lower = filename.to_lowercase();
+    lower.ends_with(".pbf") || lower.ends_with(".json")
+}
+
+/// Import the railway lines of an OpenStreetMap extract, see `crate::osm`.
+/// The tracks are laid out by the solver from their lengths.
+pub fn load_osm_file(filename :String, job :ImportJob, auto_scale :bool) {
+    if !job.progress(ImportStage::Reading, None) { return; }
+    let data = match std::fs::read(&filename) {
+        Ok(d) => d,
+        Err(e) => {
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Read error: {}", &[&e.to_string()])));
+            return;
+        }
+    };
+    if !job.progress(ImportStage::Parsing, None) { return; }
     info!("Read file {:?}", filename);
+    let osm = match crate::osm::parse(&data) {
+        Ok(osm) => osm,
+        Err(e) => {
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Parse error: {}", &[&e])));
+            return;
+        }
+    };
+    if !job.progress(ImportStage::Topology, None) { return; }
+    let topomodel = match crate::osm::to_topology(&osm) {
+        Ok(m) => m,
+        Err(e) => {
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Model conversion error: {}", &[&e])));
+            return;
+        }
+    };
+    if !job.progress(ImportStage::Mileage, None) { return; }
+
+    // Without absPos values the mileage is estimated from the track lengths.
+    let (mut model, track_segments, _) = match layout_topology(&topomodel, &Projection::planar(), &job, auto_scale, true) {
+        None => return,
+        Some(TopologyLayout::Solved(layout)) => layout,
+        Some(TopologyLayout::Simple(m)) => {
+            job.send(ImportState::Available(m));
+            return;
+        },
+    };
+    if !job.progress(ImportStage::Converting, None) { return; }
+    model.railml_tracks = Arc::new(build_railml_tracks(&topomodel, track_segments));
+    info!("Model available");
+    job.send(ImportState::Available(model));
+}
 
-    let parsed = match railmlio::xml::parse_railml(&s) {
+fn load_railml_text(s :&str, bundle :Option<Arc<RailMLSourceFile>>, 
+                    job :ImportJob, auto_scale :bool, selection :Option<&HashSet<String>>) {
+    // Streamed, so that large files are not held as a document tree.
+    let mut parsed = match railmlio::stream::parse_railml_reader(ProgressReader::new(s.as_bytes(), &job)) {
         Ok(p) => p,
+        Err(_) if job.cancelled() => return,
         Err(e) => {
-            let _ = tx.send(ImportState::SourceFileError(format!("Parse error: {:?}", e)));
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Parse error: {}", &[&format!("{:?}", e)])));
             return;
         },
     };
-    if tx.send(ImportState::Ping).is_err() { return; }
+    if !job.progress(ImportStage::Validating, None) { return; }
     info!("Parsed railml");
+    for (name, count) in &parsed.parse_report.skipped_elements {
+        info!("railML: kept {} unknown <{}> element(s) as written", count, name);
+    }
+    if let (Some(selection), Some(inf)) = (selection, parsed.infrastructure.as_mut()) {
+        select_tracks(inf, selection);
+        info!("Importing {} selected tracks", inf.tracks.len());
+    }
+
+    let mut issues = railmlio::validate::validate(&parsed);
+    for issue in &issues {
+        warn!("railML {}: {}", issue.severity.name(), issue.message);
+    }
+    if issues.iter().any(|i| i.severity == railmlio::validate::Severity::Error) {
+        railmlio::validate::locate(&mut issues, s);
+        job.send(ImportState::ValidationFailed(issues));
+        return;
+    }
+    if !job.progress(ImportStage::Topology, None) { return; }
 
-    let topomodel = match railmlio::topo::convert_railml_topo(parsed.clone()) {
+    let mut topomodel = match railmlio::topo::convert_railml_topo(parsed.clone()) {
         Ok(m) => m,
         Err(e) => {
             println!("TOPMODEL ERR {:?}", e);
-            let _ = tx.send(ImportState::SourceFileError(format!("Model conversion error: {:?}", e)));
+            job.send(ImportState::SourceFileError(i18n::tr_fmt("Model conversion error: {}", &[&format!("{:?}", e)])));
             return;
         },
     };
     validate_topo_positions(&topomodel);
-    if tx.send(ImportState::Ping).is_err() { return; }
+    let projection = geo_projection(parsed.infrastructure.as_ref(), &topomodel);
+    for coord in topomodel.node_coords.iter_mut().flatten() {
+        *coord = projection.project(*coord);
+    }
+    if !job.progress(ImportStage::Mileage, None) { return; }
     info!("Converted to topomodel");
 
-    let plotmodel = match convert_railplot(&topomodel) {
+    let (mut model, track_segments, node_pts) = match layout_topology(&topomodel, &projection, &job, auto_scale, false) {
+        None => return,
+        Some(TopologyLayout::Solved(layout)) => layout,
+        Some(TopologyLayout::Simple(mut m)) => {
+            m.railml_source = bundle;
+            job.send(ImportState::Available(m));
+            return;
+        },
+    };
+    if !job.progress(ImportStage::Converting, None) { return; }
+    model.railml_metadata = parsed.metadata.clone();
+    model.railml_interlocking = parsed.interlocking.clone().map(Arc::new);
+    model.railml_source = bundle;
+    if let Some(inf) = parsed.infrastructure.as_ref() {
+        model.railml_track_groups = Arc::new(inf.track_groups.clone());
+        model.railml_ocps = Arc::new(inf.ocps.clone());
+        model.railml_states = Arc::new(inf.states.clone());
+    }
+    model.railml_nodes = build_railml_nodes(&topomodel, &node_pts);
+    model.lineseg_attributes = build_lineseg_attributes(&topomodel, &track_segments);
+    model.railml_tracks = Arc::new(build_railml_tracks(&topomodel, track_segments));
+    model.ocp_positions = build_ocp_positions(&model.railml_ocps, &topomodel, &node_pts, &model, &projection);
+    let mut vehicle_ids = HashMap::new();
+    if let Some(rs) = parsed.rollingstock.as_ref() {
+        for v in &rs.vehicles {
+            let mut vehicle = Vehicle::default();
+            vehicle.name = v.name.clone().unwrap_or_else(|| v.id.clone());
+            if let Some(length) = v.length {
+                vehicle.length = length as f32;
+            }
+            if let Some(speed) = v.speed {
+                vehicle.max_vel = speed as f32;
+            }
+            vehicle_ids.insert(v.id.clone(), model.vehicles.insert(vehicle));
+        }
+    }
+    if let Some(tt) = parsed.timetable.as_ref() {
+        if let Some(plan) = convert_timetable(tt, &topomodel, &vehicle_ids, &model) {
+            model.plans.insert(plan);
+        }
+    }
+
+    info!("Model available");
+    job.send(ImportState::Available(model));
+}
+
+/// A topology drawn by `layout_topology`.
+enum TopologyLayout {
+    Solved(Layout),
+    /// Only the simple layout could be made, without track segments to
+    /// attach the railML data to.
+    Simple(Model),
+}
+
+/// Lay out a topology with its geoCoords or the layout solver, falling
+/// back to estimated mileage and then to the simple layout. Errors are
+/// sent to the job, and None is returned when it failed or was cancelled.
+fn layout_topology(topomodel :&railmlio::topo::Topological, projection :&Projection, job :&ImportJob,
+                   auto_scale :bool, estimated_mileage :bool) -> Option<TopologyLayout> {
+    let plotmodel = match convert_railplot_with_method(topomodel, estimated_mileage) {
         Ok(m) => m,
         Err(e) => {
-            let _ = tx.send(e);
-            return;
+            job.send(e);
+            return None;
         },
     };
-    if tx.send(ImportState::Ping).is_err() { return; }
+    if !job.progress(ImportStage::Solving { attempt: 1 }, None) { return None; }
     info!("Converted to plotmodel");
 
-    let new_solver = || railplotlib::solvers::LevelsSatSolver {
-        criteria: vec![
-            railplotlib::solvers::Goal::Bends,
-            railplotlib::solvers::Goal::Height,
-            railplotlib::solvers::Goal::Width,
-            railplotlib::solvers::Goal::LocalX,
-            railplotlib::solvers::Goal::LocalY,
-        ],
-        nodes_distinct: false,
-    };
-    use railplotlib::solvers::SchematicSolver;
-    let mut solver = new_solver();
     let fallback_plot = simple_layout_from(&plotmodel);
 
-    let (mut plot, used_geo) = if let Some(plot) = layout_from_geocoord(&plotmodel, &topomodel) {
+    let geo_pos = geo_node_positions(&plotmodel, topomodel);
+    let node_count = plotmodel.nodes.len();
+    let geo_plot = if geo_pos.len() == node_count {
+        layout_from_geocoord(&plotmodel, topomodel, projection, &geo_pos)
+    } else {
+        None
+    };
+    let (mut plot, used_geo) = if let Some(plot) = geo_plot {
         info!("Using geoCoord-based layout");
         (plot, true)
     } else {
         info!("Starting solver");
         info!("plot model {:#?}", plotmodel);
-        let solved = match solver.solve(plotmodel) {
-            Ok(m) => m,
-            Err(e) => {
-                warn!("Solver failed (FromFile): {:?}, retrying Estimated", e);
-                let mut solver = new_solver();
-                let est_plotmodel = match convert_railplot_estimated(&topomodel) {
+        let solved = match solve_cancelable(job, 1, plotmodel) {
+            None => return None,
+            Some(Ok(m)) => m,
+            Some(Err(e)) => {
+                warn!("Solver failed (FromFile): {}, retrying Estimated", e);
+                let est_plotmodel = match convert_railplot_estimated(topomodel) {
                     Ok(m) => m,
                     Err(err) => {
-                        let _ = tx.send(err);
-                        return;
+                        job.send(err);
+                        return None;
                     },
                 };
                 let fallback = simple_layout_from(&est_plotmodel);
-                match solver.solve(est_plotmodel) {
-                    Ok(m2) => m2,
-                    Err(e2) => {
-                        warn!("Solver failed (Estimated): {:?}, using simple layout fallback", e2);
-                        match convert_junction(fallback, auto_scale) {
-                            Ok((m, _)) => {
-                                let _ = tx.send(ImportState::Available(m));
-                            },
-                            Err(err) => { let _ = tx.send(err); }
-                        }
-                        return;
+                match solve_cancelable(job, 2, est_plotmodel) {
+                    None => return None,
+                    Some(Ok(m2)) => m2,
+                    Some(Err(e2)) => {
+                        warn!("Solver failed (Estimated): {}, using simple layout fallback", e2);
+                        return match convert_junction(fallback, auto_scale) {
+                            Ok((m, _, _)) => Some(TopologyLayout::Simple(m)),
+                            Err(err) => { job.send(err); None }
+                        };
                     },
                 }
             },
         };
-        (solved, false)
+        match layout_hybrid(&solved, topomodel, projection) {
+            Some(plot) => {
+                info!("Using geoCoords for {} of {} nodes", geo_pos.len(), node_count);
+                (plot, true)
+            },
+            None => (solved, false),
+        }
     };
     let y_min = plot.nodes.iter().map(|(_,pt)| pt.1).fold(f64::INFINITY, f64::min);
     let y_max = plot.nodes.iter().map(|(_,pt)| pt.1).fold(f64::NEG_INFINITY, f64::max);
@@ -222,41 +1067,89 @@ pub fn load_railml_file(filename :String, tx :mpsc::Sender<ImportState>, auto_sc
         warn!("Solver output is degenerate (flat); using fallback layout");
         plot = fallback_plot;
     }
-    if tx.send(ImportState::Ping).is_err() { return; }
+    if !job.progress(ImportStage::Drawing, None) { return None; }
 
     info!("Found model");
-    let (mut model, track_segments) = match convert_junction(plot, auto_scale && !used_geo) {
+    let layout = match convert_junction(plot, auto_scale && !used_geo) {
         Ok(result) => result,
         Err(e) => {
-            let _ = tx.send(e);
-            return;
+            job.send(e);
+            return None;
         },
     };
-    model.railml_metadata = parsed.metadata.clone();
-    if let Some(inf) = parsed.infrastructure.as_ref() {
-        model.railml_track_groups = inf.track_groups.clone();
-        model.railml_ocps = inf.ocps.clone();
-        model.railml_states = inf.states.clone();
+    Some(TopologyLayout::Solved(layout))
+}
+
+
+/// Run the layout solver in the job's solver thread, reporting the time
+/// spent while waiting for it, as a fraction of the time limit if there is
+/// one. The solver cannot be stopped, so a cancelled or timed out solver is
+/// left to finish in the background and its result is dropped. Until it has
+/// finished, new attempts fail, which gives the simple layout. Returns
+/// `None` if the import was cancelled.
+fn solve_cancelable(job :&ImportJob, attempt :usize, plotmodel :railplotlib::model::SchematicGraph<RailObject>)
+    -> Option<Result<railplotlib::model::SchematicOutput<RailObject>, String>> {
+    let (tx, rx) = mpsc::channel();
+    let mut solver = job.solver.solver();
+    let spawned = job.solver_thread.spawn(move || {
+        use railplotlib::solvers::SchematicSolver;
+        let _ = tx.send(solver.solve(plotmodel).map_err(|e| format!("{:?}", e)));
+    });
+    if !spawned {
+        return Some(Err("the solver of a cancelled or timed out import is still running".to_string()));
     }
-    model.railml_tracks = build_railml_tracks(&topomodel, track_segments);
-    if let Some(rs) = parsed.rollingstock.as_ref() {
-        for v in &rs.vehicles {
-            let mut vehicle = Vehicle::default();
-            vehicle.name = v.name.clone().unwrap_or_else(|| v.id.clone());
-            if let Some(length) = v.length {
-                vehicle.length = length as f32;
-            }
-            if let Some(speed) = v.speed {
-                vehicle.max_vel = speed as f32;
-            }
-            model.vehicles.insert(vehicle);
+    let started = Instant::now();
+    let limit = job.solver.time_limit();
+    loop {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(result) => return Some(result),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let fraction = limit.map(|l| (started.elapsed().as_secs_f32() / l.as_secs_f32()).min(1.0));
+                if !job.progress(ImportStage::Solving { attempt }, fraction) { return None; }
+                if let Some(limit) = job.solver.time_limit().filter(|l| started.elapsed() > *l) {
+                    return Some(Err(format!("time limit of {} s reached", limit.as_secs())));
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Some(Err("solver stopped".to_string())),
         }
     }
+}
 
-    info!("Model available");
-    let _ = tx.send(ImportState::Available(model));
+/// Whether a layout solver is running, shared by the jobs of an import
+/// window. A solver thread cannot be stopped, so at most one is allowed to
+/// run, also after its import was cancelled.
+#[derive(Clone, Default)]
+pub struct SolverThread(Arc<AtomicBool>);
+
+impl SolverThread {
+    pub fn is_running(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Run `solve` in a new thread, unless a solver is already running.
+    /// Returns whether the thread was started.
+    fn spawn(&self, solve :impl FnOnce() + Send + 'static) -> bool {
+        if self.0.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return false;
+        }
+        let running = SolverRunning(self.0.clone());
+        std::thread::spawn(move || {
+            let _running = running;
+            solve();
+        });
+        true
+    }
 }
 
+/// Clears the running flag of a `SolverThread` when the solver ends, also
+/// if it panics.
+struct SolverRunning(Arc<AtomicBool>);
+
+impl Drop for SolverRunning {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum RailObject {
@@ -280,12 +1173,17 @@ fn validate_topo_positions(topo: &railmlio::topo::Topological) {
         };
         for s in &track.objects.signals { check("signal", s.pos.offset); }
         for b in &track.objects.balises { check("balise", b.pos.offset); }
+        for g in &track.objects.balise_groups { check("balise group", g.pos.offset); }
         for d in &track.objects.train_detectors { check("detector", d.pos.offset); }
         for d in &track.objects.track_circuit_borders { check("tcb", d.pos.offset); }
         for d in &track.objects.derailers { check("derailer", d.pos.offset); }
         for e in &track.objects.train_protection_elements { check("tpe", e.pos.offset); }
         for p in &track.track_elements.platform_edges { check("platform", p.pos.offset); }
         for s in &track.track_elements.speed_changes { check("speed", s.pos.offset); }
+        for g in &track.track_elements.gradient_changes { check("gradient", g.pos.offset); }
+        for r in &track.track_elements.radius_changes { check("radius", r.pos.offset); }
+        for e in &track.track_elements.electrification_changes { check("electrification", e.pos.offset); }
+        for t in &track.track_elements.track_conditions { check("track_condition", t.pos.offset); }
         for l in &track.track_elements.level_crossings { check("level_crossing", l.pos.offset); }
         for c in &track.track_elements.cross_sections { check("cross_section", c.pos.offset); }
         for g in &track.track_elements.geo_mappings { check("geo_mapping", g.pos.offset); }
@@ -305,6 +1203,115 @@ pub fn convert_railplot_estimated(topo :&railmlio::topo::Topological)
     convert_railplot_with_method(topo, true)
 }
 
+/// Reconcile the roughly estimated node mileages with the track lengths and
+/// the absPos values at track ends, by least squares over the connection
+/// graph. The anchors, the start nodes of the connected parts, keep their
+/// mileage exactly, as they are fixed instead of solved for. absPos values
+/// only count relative to each other, in the direction that agrees with the
+/// estimate. Returns `None` if the solution would reverse the direction of
+/// a track.
+fn least_squares_mileage(topo :&railmlio::topo::Topological,
+                         km0 :&HashMap<usize, (isize, f64)>,
+                         anchors :&[usize]) -> Option<HashMap<usize, f64>> {
+    use railmlio::topo::AB;
+    use petgraph::unionfind::UnionFind;
+
+    let fixed :HashMap<usize, f64> = anchors.iter().filter_map(|a| Some((*a, km0.get(a)?.1))).collect();
+    let mut nodes = km0.keys().filter(|n| !fixed.contains_key(n)).cloned().collect::<Vec<_>>();
+    nodes.sort();
+    let var :HashMap<usize, usize> = nodes.iter().enumerate().map(|(i,n)| (*n,i)).collect();
+    let track_node :HashMap<(usize,AB), usize> = topo.connections.iter()
+        .map(|((t,ab),(n,_))| ((*t,*ab),*n)).collect();
+
+    // Sparse rows of the system, as (variable, coefficient) and right hand
+    // side, where the anchors are moved to the right hand side.
+    let mut rows :Vec<(Vec<(usize,f64)>, f64)> = Vec::new();
+    let mut push_row = |terms :&[(usize,f64)], mut rhs :f64| {
+        let mut row = Vec::new();
+        for (n,c) in terms {
+            match fixed.get(n) {
+                Some(x) => rhs -= c * x,
+                None => row.push((var[n], *c)),
+            }
+        }
+        if !row.is_empty() { rows.push((row, rhs)); }
+    };
+    let mut track_dirs = Vec::new();
+    let mut uf = UnionFind::new(topo.nodes.len());
+    for (track_idx, track) in topo.tracks.iter().enumerate() {
+        let (a,b) = match (track_node.get(&(track_idx,AB::A)), track_node.get(&(track_idx,AB::B))) {
+            (Some(a), Some(b)) if km0.contains_key(a) && km0.contains_key(b) => (*a,*b),
+            _ => continue,
+        };
+        let dir = if km0[&b].1 >= km0[&a].1 { 1.0 } else { -1.0 };
+        push_row(&[(b,1.0), (a,-1.0)], dir*track.length);
+        track_dirs.push((a,b,dir,track.length));
+        uf.union(a,b);
+    }
+
+    // absPos values at the ends of the railML tracks.
+    let last_segment = topo.tracks.iter().fold(HashMap::new(), |mut last, t| {
+        let i = last.entry(t.source.id.as_str()).or_insert(0);
+        *i = (*i).max(t.segment_index);
+        last
+    });
+    let mut observed :HashMap<usize, Vec<(usize,f64)>> = HashMap::new();
+    for (track_idx, track) in topo.tracks.iter().enumerate() {
+        let mut observe = |ab :AB, mileage :Option<f64>| {
+            if let (Some(n), Some(m)) = (track_node.get(&(track_idx,ab)), mileage) {
+                if km0.contains_key(n) { observed.entry(uf.find(*n)).or_default().push((*n,m)); }
+            }
+        };
+        if track.segment_index == 0 { observe(AB::A, track.source.abs_pos_begin); }
+        if track.segment_index == last_segment[track.source.id.as_str()] {
+            observe(AB::B, track.source.abs_pos_end);
+        }
+    }
+    let mut parts = observed.into_iter().collect::<Vec<_>>();
+    parts.sort_by_key(|(part,_)| *part);
+    for (_, obs) in parts {
+        let (r, mr) = obs[0];
+        let agreement :f64 = obs.iter().map(|(n,m)| (km0[n].1 - km0[&r].1) * (m - mr)).sum();
+        let dir = if agreement < 0.0 { -1.0 } else { 1.0 };
+        for (n,m) in obs.iter().skip(1).filter(|(n,_)| *n != r) {
+            push_row(&[(*n,1.0), (r,-1.0)], dir*(m - mr));
+        }
+    }
+
+    let mut sol = vec![0.0; nodes.len()];
+    if !rows.is_empty() && !nodes.is_empty() {
+        let mut rhs = rows.iter().map(|(_,r)| *r).collect::<Vec<_>>();
+        let params = lsqr::Params {
+            damp: 0.0,
+            rel_mat_err: 1e-6,
+            rel_rhs_err: 1e-6,
+            condlim: 0.0,
+            iterlim: 4*nodes.len() + 10,
+        };
+        sol = lsqr::lsqr(|_msg| {}, rows.len(), nodes.len(), params, |prod| {
+            match prod {
+                lsqr::Product::YAddAx { x, y } => {
+                    for (i,(row,_)) in rows.iter().enumerate() {
+                        for (v,c) in row { y[i] += c * x[*v]; }
+                    }
+                },
+                lsqr::Product::XAddATy { x, y } => {
+                    for (i,(row,_)) in rows.iter().enumerate() {
+                        for (v,c) in row { x[*v] += c * y[i]; }
+                    }
+                },
+            }
+        }, &mut rhs).0;
+    }
+
+    if sol.iter().any(|x| !x.is_finite()) { return None; }
+    let value = |n :&usize| fixed.get(n).cloned().unwrap_or_else(|| sol[var[n]]);
+    let reversed = track_dirs.iter()
+        .any(|(a,b,dir,length)| *length > 0.0 && dir * (value(b) - value(a)) <= 0.0);
+    if reversed { return None; }
+    Some(km0.keys().map(|n| (*n, value(n))).collect())
+}
+
 pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_estimated: bool) 
     -> Result<railplotlib::model::SchematicGraph<RailObject>, ImportState> {
 
@@ -327,12 +1334,12 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
     let has_abs = topo.tracks.iter().any(|t| t.offset != 0.0 || t.length > 0.0);
     let method = if force_estimated { MileageMethod::Estimated } else if has_abs { MileageMethod::FromFile } else { MileageMethod::Estimated };
 
-    match method {
+    let mut model = match method {
         MileageMethod::FromFile => {
             // Use absPos on track ends/switches to set km0 directly.
             let mut model = plot::SchematicGraph {
-                nodes: Vec::new(),
-                edges: Vec::new(),
+                nodes: Vec::with_capacity(topo.nodes.len()),
+                edges: Vec::with_capacity(topo.tracks.len()),
                 main_tracks_edges: Vec::new(),
             };
 
@@ -344,7 +1351,7 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
             }
 
             // nodes: track ends, switches, crossings, continuations
-            let mut node_map: HashMap<usize, usize> = HashMap::new();
+            let mut node_map: HashMap<usize, usize> = HashMap::with_capacity(topo.nodes.len());
             for (node_idx,node_type) in topo.nodes.iter().enumerate() {
                 let km0 = 0.0; // will adjust from track offsets
                 if let topo::TopoNode::Continuation = node_type { continue; }
@@ -355,7 +1362,7 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                     shape: match node_type {
                         topo::TopoNode::BufferStop | 
                         topo::TopoNode::OpenEnd | 
-                        topo::TopoNode::MacroscopicNode => plot::Shape::Begin, // may flip to End later
+                        topo::TopoNode::MacroscopicNode(_, _) => plot::Shape::Begin, // may flip to End later
                         topo::TopoNode::Switch(topo::Side::Left) => 
                             plot::Shape::Switch(plot::Side::Left, plot::Dir::Up), // dir adjusted later
                         topo::TopoNode::Switch(topo::Side::Right) => 
@@ -372,8 +1379,8 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
             let node_connections :HashMap<(usize,topo::Port),(usize,topo::AB)> = 
                 topo.connections.iter().map(|(a,b)| (*b,*a)).collect();
 
-            let mut edges_done = HashSet::new();
-            let mut node_pos: HashMap<usize, f64> = HashMap::new();
+            let mut edges_done = HashSet::with_capacity(topo.tracks.len());
+            let mut node_pos: HashMap<usize, f64> = HashMap::with_capacity(topo.nodes.len());
 
             for (track_idx,track) in topo.tracks.iter().enumerate() {
                 let mut na = track_connections.get(&(track_idx,topo::AB::A))
@@ -447,6 +1454,8 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                             switchable: s.switchable,
                             ocp_station_ref: s.ocp_station_ref.clone(),
                             dir: s.dir,
+                            speeds: s.speeds.clone(),
+                            etcs: s.etcs.clone(),
                         })));
                     }
                     for d in &topo.tracks[track_idx].objects.train_detectors {
@@ -527,6 +1536,33 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                             signalised: s.signalised,
                         })));
                     }
+                    for g in &topo.tracks[track_idx].track_elements.gradient_changes {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + g.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::GradientChange {
+                            id: g.id.clone(),
+                            dir: g.dir,
+                            slope: g.slope,
+                            transition_length: g.transition_length,
+                            transition_radius: g.transition_radius,
+                        })));
+                    }
+                    for r in &topo.tracks[track_idx].track_elements.radius_changes {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + r.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::RadiusChange {
+                            id: r.id.clone(),
+                            dir: r.dir,
+                            radius: r.radius,
+                            superelevation: r.superelevation,
+                        })));
+                    }
                     for l in &topo.tracks[track_idx].track_elements.level_crossings {
                         objects.push((plot::Symbol {
                             pos: pos_a + l.pos.offset,
@@ -561,6 +1597,23 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                         }, RailObject::Info(crate::document::model::RailMLObjectInfo::Balise {
                             id: b.id.clone(),
                             name: b.name.clone(),
+                            group_ref: b.group_ref.clone(),
+                        })));
+                    }
+                    for g in &topo.tracks[track_idx].objects.balise_groups {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + g.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::BaliseGroup {
+                            id: g.id.clone(),
+                            name: g.name.clone(),
+                            dir: g.dir,
+                            country_id: g.country_id.clone(),
+                            group_id: g.group_id.clone(),
+                            ndx: g.ndx,
+                            linking_accuracy: g.linking_accuracy,
                         })));
                     }
                     if let Some(&mi) = node_map.get(&na.0) {
@@ -620,7 +1673,7 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
             let start_node = topo.nodes.iter().position(|n| 
                                 matches!(n, topo::TopoNode::BufferStop |
                                             topo::TopoNode::OpenEnd |
-                                            topo::TopoNode::MacroscopicNode)).
+                                            topo::TopoNode::MacroscopicNode(_, _))).
                 ok_or(ImportState::SourceFileError(format!("No entry/exit nodes found.")))?;
 
             type NodeId = usize; // index into topo.nodes
@@ -635,9 +1688,11 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
             let mut km0 : HashMap<NodeId, (isize, f64)> = HashMap::new();
             let mut component_offset = 0.0;
 
+            let mut anchors = Vec::new();
+
             let mut node_indices : Vec<usize> = (0..topo.nodes.len()).collect();
             node_indices.sort_by_key(|&idx| !matches!(topo.nodes[idx], 
-                topo::TopoNode::BufferStop | topo::TopoNode::OpenEnd | topo::TopoNode::MacroscopicNode));
+                topo::TopoNode::BufferStop | topo::TopoNode::OpenEnd | topo::TopoNode::MacroscopicNode(_, _)));
 
             for &start_candidate in &node_indices {
                 if km0.contains_key(&start_candidate) { continue; }
@@ -653,6 +1708,7 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
 
                 if let Some((start_port, (start_track, start_trackend))) = start_track_info {
                     km0.insert(start_candidate, (1, component_offset));
+                    anchors.push(start_candidate);
                     let start_l = topo.tracks[*start_track].length;
                     let other_node_port = track_connections.get(&(*start_track, start_trackend.opposite())).unwrap();
 
@@ -696,7 +1752,12 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
 
             // now we have roughly estimated mileages and have switch orientations
             // (incoming/outgoing = increasing/decreasing milage)
-            // TODO add lsqr calculations with track lengths and unknown kms.
+            match least_squares_mileage(topo, &km0, &anchors) {
+                Some(km) => for (node, pos) in km {
+                    if let Some(x) = km0.get_mut(&node) { x.1 = pos; }
+                },
+                None => warn!("Mileage calibration would reverse a track, using the estimated mileage."),
+            }
 
             let mut model = plot::SchematicGraph {
                 nodes: Vec::new(),
@@ -722,7 +1783,7 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                     shape: match node_type {
                         topo::TopoNode::BufferStop | 
                         topo::TopoNode::OpenEnd | 
-                        topo::TopoNode::MacroscopicNode => 
+                        topo::TopoNode::MacroscopicNode(_, _) => 
                             if dir == 1 { plot::Shape::Begin } else { plot::Shape::End },
                         topo::TopoNode::Switch(topo::Side::Left) => 
                             plot::Shape::Switch(plot::Side::Left, to_dir(dir)),
@@ -884,6 +1945,8 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                             switchable: s.switchable,
                             ocp_station_ref: s.ocp_station_ref.clone(),
                             dir: s.dir,
+                            speeds: s.speeds.clone(),
+                            etcs: s.etcs.clone(),
                         })));
                     }
                     for d in &topo.tracks[track_idx].objects.train_detectors {
@@ -984,6 +2047,33 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                             signalised: s.signalised,
                         })));
                     }
+                    for g in &topo.tracks[track_idx].track_elements.gradient_changes {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + g.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::GradientChange {
+                            id: g.id.clone(),
+                            dir: g.dir,
+                            slope: g.slope,
+                            transition_length: g.transition_length,
+                            transition_radius: g.transition_radius,
+                        })));
+                    }
+                    for r in &topo.tracks[track_idx].track_elements.radius_changes {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + r.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::RadiusChange {
+                            id: r.id.clone(),
+                            dir: r.dir,
+                            radius: r.radius,
+                            superelevation: r.superelevation,
+                        })));
+                    }
                     for l in &topo.tracks[track_idx].track_elements.level_crossings {
                         objects.push((plot::Symbol {
                             pos: pos_a + l.pos.offset,
@@ -1018,6 +2108,23 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
                         }, RailObject::Info(crate::document::model::RailMLObjectInfo::Balise {
                             id: b.id.clone(),
                             name: b.name.clone(),
+                            group_ref: b.group_ref.clone(),
+                        })));
+                    }
+                    for g in &topo.tracks[track_idx].objects.balise_groups {
+                        objects.push((plot::Symbol {
+                            pos: pos_a + g.pos.offset,
+                            width: 0.1,
+                            origin: 0.0,
+                            level: 1,
+                        }, RailObject::Info(crate::document::model::RailMLObjectInfo::BaliseGroup {
+                            id: g.id.clone(),
+                            name: g.name.clone(),
+                            dir: g.dir,
+                            country_id: g.country_id.clone(),
+                            group_id: g.group_id.clone(),
+                            ndx: g.ndx,
+                            linking_accuracy: g.linking_accuracy,
                         })));
                     }
                     model.edges.push(plot::Edge { a, b, objects });
@@ -1026,7 +2133,19 @@ pub fn convert_railplot_with_method(topo :&railmlio::topo::Topological, force_es
 
             Ok(model)
         }
-    }
+    }?;
+    canonical_order(&mut model);
+    Ok(model)
+}
+
+/// Sort the nodes of a plot model by position, then by name, and the edges
+/// by the names of their nodes. The solver's variables follow the order of
+/// the nodes and edges, and it breaks ties between equally good layouts by
+/// that order, so this makes the layout depend only on the topology.
+fn canonical_order(model :&mut railplotlib::model::SchematicGraph<RailObject>) {
+    model.nodes.sort_by(|a, b| a.pos.partial_cmp(&b.pos).unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.name.cmp(&b.name)));
+    model.edges.sort_by(|a, b| (&a.a.0, &a.b.0).cmp(&(&b.a.0, &b.b.0)));
 }
 
 
@@ -1046,7 +2165,7 @@ fn build_track_segments(plot: &railplotlib::model::SchematicOutput<RailObject>)
             .iter()
             .map(|x| round_pt_tol(*x))
             .collect::<Result<Vec<_>, ()>>()
-            .map_err(|_| ImportState::PlotError("Solution contains point not on grid".to_string()))?;
+            .map_err(|_| ImportState::PlotError(i18n::tr("Solution contains point not on grid").to_string()))?;
         let mut segs = Vec::new();
         for (p1, p2) in pts.iter().zip(pts.iter().skip(1)) {
             let segs_raw = line_segments(*p1, *p2).or_else(|_| manhattan_segments(*p1, *p2));
@@ -1061,6 +2180,69 @@ fn build_track_segments(plot: &railplotlib::model::SchematicOutput<RailObject>)
     Ok(track_segments)
 }
 
+/// Positions of the OCP station markers: the geoCoord of the OCP when the
+/// layout is made from geoCoords, and otherwise the centroid of the tracks
+/// in its equipment.
+fn build_ocp_positions(
+    ocps: &[railmlio::model::Ocp],
+    topo: &railmlio::topo::Topological,
+    node_pts: &HashMap<String,Pt>,
+    model: &Model,
+    projection: &Projection,
+) -> im::HashMap<String, PtC> {
+    // The layout is made from geoCoords when the nodes are drawn at theirs.
+    let mut node_coords = topo.node_coords.iter().enumerate()
+        .filter_map(|(idx, c)| Some((*node_pts.get(&format!("n{}", idx))?, (*c)?)))
+        .peekable();
+    let geo_layout = node_coords.peek().is_some() && node_coords
+        .all(|(pt, (x, y))| (pt.x as f64 - x).abs() <= 1.0 && (pt.y as f64 - y).abs() <= 1.0);
+
+    let mut positions = im::HashMap::new();
+    for ocp in ocps {
+        let geo = ocp.geo_coord.as_ref()
+            .filter(|_| geo_layout)
+            .and_then(|g| railmlio::topo::parse_geo_coord(&g.coord))
+            .map(|c| projection.project(c))
+            .map(|(x, y)| nalgebra_glm::vec2(x as f32, y as f32));
+        let centroid = || {
+            let refs = ocp.prop_equipment.iter()
+                .flat_map(|e| e.track_refs.iter().map(|r| r.as_str()))
+                .collect::<HashSet<_>>();
+            let pts = topo.tracks.iter().zip(model.railml_tracks.iter())
+                .filter(|(t, _)| refs.contains(t.source.id.as_str()))
+                .flat_map(|(_, info)| info.segments.iter().flat_map(|(a, b)| vec![*a, *b]))
+                .collect::<Vec<_>>();
+            if pts.is_empty() { return None; }
+            let n = pts.len() as f32;
+            Some(nalgebra_glm::vec2(pts.iter().map(|p| p.x as f32).sum::<f32>() / n,
+                                    pts.iter().map(|p| p.y as f32).sum::<f32>() / n))
+        };
+        if let Some(p) = geo.or_else(centroid) {
+            positions.insert(ocp.id.clone(), p);
+        }
+    }
+    positions
+}
+
+/// Ids and OCP references of the macroscopic nodes, at the points of their
+/// track ends in the layout.
+fn build_railml_nodes(
+    topo: &railmlio::topo::Topological,
+    node_pts: &HashMap<String,Pt>,
+) -> im::HashMap<Pt, crate::document::model::RailMLNodeInfo> {
+    topo.nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, node)| match node {
+            railmlio::topo::TopoNode::MacroscopicNode(id, ocp_ref) => {
+                let pt = node_pts.get(&format!("n{}", idx))?;
+                Some((*pt, crate::document::model::RailMLNodeInfo { id: id.clone(), ocp_ref: ocp_ref.clone() }))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 fn build_railml_tracks(
     topo: &railmlio::topo::Topological,
     track_segments: Vec<Vec<(Pt,Pt)>>,
@@ -1089,7 +2271,123 @@ fn build_railml_tracks(
         .collect()
 }
 
-pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, auto_scale: bool) -> Result<(Model, Vec<Vec<(Pt,Pt)>>), ImportState> {
+/// Electrification and track conditions of the line segments of each
+/// track. The line segments are drawn with equal lengths, so the position
+/// of a line segment on its track is its share of the track length.
+fn build_lineseg_attributes(
+    topo: &railmlio::topo::Topological,
+    track_segments: &[Vec<(Pt,Pt)>],
+) -> im::HashMap<(Pt,Pt), LineSegAttributes> {
+    let mut attributes = im::HashMap::new();
+    for (track, segments) in topo.tracks.iter().zip(track_segments.iter()) {
+        let elements = &track.track_elements;
+        let n = segments.len() as f64;
+        for (i, seg) in segments.iter().enumerate() {
+            let (start, end) = (i as f64 / n * track.length, (i + 1) as f64 / n * track.length);
+            let mid = 0.5 * (start + end);
+            let mut attrs = LineSegAttributes::default();
+            if let Some(e) = elements.electrification_changes.iter().filter(|e| e.pos.offset <= mid).last() {
+                attrs.electrification = Some(e.electrification_type.clone().unwrap_or_else(|| "unknown".to_string()));
+                attrs.voltage = e.voltage;
+                attrs.frequency = e.frequency;
+            }
+            for c in &elements.track_conditions {
+                let c_end = c.pos.offset + c.length.unwrap_or(0.0);
+                if c.pos.offset < end && c_end >= start {
+                    let t = c.condition_type.clone().unwrap_or_else(|| "unknown".to_string());
+                    if !attrs.track_conditions.contains(&t) { attrs.track_conditions.push(t); }
+                }
+            }
+            if attrs != LineSegAttributes::default() {
+                attributes.insert(*seg, attrs);
+            }
+        }
+    }
+    attributes
+}
+
+/// Plan with a train for each train part of the timetable, visiting the
+/// OCPs in the timetable. An OCP is visited at any switch or boundary on
+/// its tracks, and OCPs without one are left out. Trains passing the same
+/// OCP are ordered by their times there.
+fn convert_timetable(tt :&railmlio::model::Timetable, topo :&railmlio::topo::Topological,
+                     vehicle_ids :&HashMap<String, ListId>, model :&Model) -> Option<PlanSpec> {
+    let topology = crate::document::topology::convert(model, 50.0).ok()?;
+    let is_visitable = |pt :&Pt| matches!(topology.locations.get(pt),
+                                          Some((NDType::OpenEnd, _)) | Some((NDType::Sw(_), _)));
+
+    // The tracks of an OCP are those in its equipment, and those with
+    // signals referring to it as their station.
+    let mut ocp_tracks :HashMap<&str, HashSet<&str>> = HashMap::new();
+    for ocp in model.railml_ocps.iter() {
+        for r in ocp.prop_equipment.iter().flat_map(|e| e.track_refs.iter()) {
+            ocp_tracks.entry(ocp.id.as_str()).or_default().insert(r.as_str());
+        }
+    }
+    for track in &topo.tracks {
+        for signal in &track.objects.signals {
+            if let Some(r) = &signal.ocp_station_ref {
+                ocp_tracks.entry(r.as_str()).or_default().insert(track.source.id.as_str());
+            }
+        }
+    }
+    let ocp_nodes = |ocp :&str| {
+        let tracks = ocp_tracks.get(ocp);
+        let mut pts = topo.tracks.iter().zip(model.railml_tracks.iter())
+            .filter(|(t,_)| tracks.map(|ts| ts.contains(t.source.id.as_str())).unwrap_or(false))
+            .flat_map(|(_,info)| info.segments.iter().flat_map(|(a,b)| vec![*a, *b]))
+            .filter(|pt| is_visitable(pt))
+            .collect::<Vec<Pt>>();
+        pts.sort_by_key(|pt| (pt.x, pt.y));
+        pts.dedup();
+        pts
+    };
+
+    let name = tt.name.clone().or_else(|| tt.id.clone()).unwrap_or_else(|| "Timetable".to_string());
+    let mut plan = PlanSpec::new_empty(name);
+    let mut ocp_visits :std::collections::BTreeMap<&str, Vec<(f64, VisitRef)>> = Default::default();
+    for part in &tt.train_parts {
+        let mut ocps = part.ocps.iter().collect::<Vec<_>>();
+        ocps.sort_by_key(|o| o.sequence);
+        let mut visits = ImShortGenList::new();
+        let mut times = Vec::new();
+        for ocp in ocps {
+            let locs = ocp_nodes(&ocp.ocp_ref);
+            if locs.is_empty() {
+                warn!("Train part {}: OCP {} has no switch or boundary to visit", part.id, ocp.ocp_ref);
+                continue;
+            }
+            let t = ocp.scheduled_times();
+            let dwell = t.filter(|_| ocp.is_stop()).and_then(|t| Some(t.departure? - t.arrival?));
+            let visit = visits.insert(Visit { locs: locs.into_iter().map(|pt| Ok(Ref::Node(pt))).collect(), dwell });
+            if let Some(time) = t.and_then(|t| t.arrival.or(t.departure)) {
+                times.push((ocp.ocp_ref.as_str(), time, visit));
+            }
+        }
+        if visits.data().is_empty() { continue; }
+        let vehicle = part.formation_ref.as_ref().and_then(|f| vehicle_ids.get(f)).cloned();
+        let train = plan.trains.insert((vehicle, visits));
+        for (ocp, time, visit) in times {
+            ocp_visits.entry(ocp).or_default().push((time, (train, visit)));
+        }
+    }
+    for visits in ocp_visits.values_mut() {
+        visits.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        for w in visits.windows(2) {
+            if (w[0].1).0 != (w[1].1).0 { plan.order.push((w[0].1, w[1].1, None)); }
+        }
+    }
+
+    if plan.trains.data().is_empty() { return None; }
+    info!("Converted timetable to plan with {} trains", plan.trains.data().len());
+    Some(plan)
+}
+
+/// A converted layout: the model, the line segments of each track, and the
+/// point of each node by its name in the plot model.
+pub type Layout = (Model, Vec<Vec<(Pt,Pt)>>, HashMap<String,Pt>);
+
+pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, auto_scale: bool) -> Result<Layout, ImportState> {
     debug!("Starting conversion of railplotlib schematic output");
 
     // Heuristic scaling: scale up tiny outputs and scale down huge outputs to keep grid reasonable.
@@ -1131,10 +2429,12 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, a
 
     let track_segments = build_track_segments(&plot)?;
     let mut model :Model = Default::default();
+    let mut node_pts = HashMap::new();
 
     for (n,pt) in plot.nodes {
         let pt = round_pt_tol(pt)
-            .map_err(|_| ImportState::PlotError(format!("Solution contains point not on grid, {:?}", pt)))?;
+            .map_err(|_| ImportState::PlotError(i18n::tr_fmt("Solution contains point not on grid, {}", &[&format!("{:?}", pt)])))?;
+        node_pts.insert(n.name.clone(), pt);
         use railplotlib::model::Shape;
         let nd = match n.shape {
             Shape::Begin => Some(NDType::OpenEnd),
@@ -1157,7 +2457,7 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, a
 
     for (e,pts) in plot.lines {
         let pts = pts.into_iter().map(|x| round_pt_tol(x)).collect::<Result<Vec<_>,()>>()
-            .map_err(|_| ImportState::PlotError(format!("Solution contains point not on grid")))?;
+            .map_err(|_| ImportState::PlotError(i18n::tr("Solution contains point not on grid").to_string()))?;
         for (p1,p2) in pts.iter().zip(pts.iter().skip(1)) {
             let segs = line_segments(*p1,*p2).or_else(|_| manhattan_segments(*p1,*p2));
             let segs = segs.unwrap_or_default();
@@ -1232,6 +2532,7 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, a
         
         let mut functions = Vec::new();
         let mut signal_dir: Option<railmlio::model::TrackDirection> = None;
+        let mut uphill: Option<Pt> = None;
         let info = match obj {
             RailObject::Info(info) => info,
         };
@@ -1270,12 +2571,23 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, a
             crate::document::model::RailMLObjectInfo::Balise { .. } => {
                 functions.push(crate::document::objects::Function::Balise);
             }
+            crate::document::model::RailMLObjectInfo::BaliseGroup { .. } => {
+                functions.push(crate::document::objects::Function::BaliseGroup);
+            }
             crate::document::model::RailMLObjectInfo::PlatformEdge { .. } => {
                 functions.push(crate::document::objects::Function::PlatformEdge);
             }
             crate::document::model::RailMLObjectInfo::SpeedChange { .. } => {
                 functions.push(crate::document::objects::Function::SpeedChange);
             }
+            crate::document::model::RailMLObjectInfo::GradientChange { dir, slope, .. } => {
+                functions.push(crate::document::objects::Function::GradientChange);
+                let rising = matches!(dir, railmlio::model::TrackDirection::Up) == (*slope >= 0.0);
+                uphill = Some(if rising { tangent } else { -tangent });
+            }
+            crate::document::model::RailMLObjectInfo::RadiusChange { .. } => {
+                functions.push(crate::document::objects::Function::RadiusChange);
+            }
             crate::document::model::RailMLObjectInfo::LevelCrossing { .. } => {
                 functions.push(crate::document::objects::Function::LevelCrossing);
             }
@@ -1299,13 +2611,19 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<RailObject>, a
         } else {
             let _ = obj.move_to(&model, obj.loc);
         }
+        // Gradient changes point uphill, which gives the sign of the slope.
+        if let Some(uphill) = uphill {
+            if obj.tangent.x*uphill.x + obj.tangent.y*uphill.y < 0 {
+                obj.tangent = -obj.tangent;
+            }
+        }
         let base_key = round_coord(obj.loc);
         let key = find_free_key(base_key, &model.objects);
         model.objects.insert(key, obj);
         model.railml_objects.entry(key).or_insert_with(Vec::new).push(info);
     }
 
-    Ok((model, track_segments))
+    Ok((model, track_segments, node_pts))
 }
 
 pub fn line_segments(a :Pt, b :Pt) -> Result<Vec<(Pt,Pt)>, ()> {
@@ -1469,18 +2787,137 @@ fn simple_layout_from(plotmodel: &railplotlib::model::SchematicGraph<RailObject>
     railplotlib::model::SchematicOutput { nodes, lines, symbols }
 }
 
-fn layout_from_geocoord(
+/// The projection of the geoCoords in the document to the plane, by the
+/// epsgCode of its OCPs, with its origin among the coordinates of the
+/// nodes and OCPs.
+fn geo_projection(
+    inf: Option<&railmlio::model::Infrastructure>,
+    topo: &railmlio::topo::Topological,
+) -> Projection {
+    let inf = match inf {
+        Some(inf) => inf,
+        None => return Projection::planar(),
+    };
+    let crs = Crs::of_infrastructure(inf);
+    let ocp_coords = inf.ocps.iter()
+        .filter_map(|o| railmlio::topo::parse_geo_coord(&o.geo_coord.as_ref()?.coord));
+    let coords = topo.node_coords.iter().flatten().cloned().chain(ocp_coords);
+    let projection = Projection::new(crs, coords);
+    if crs == Crs::Geographic {
+        info!("Projecting geographic coordinates to transverse Mercator");
+    }
+    projection
+}
+
+/// The positions of the nodes that have a geoCoord, by node name.
+fn geo_node_positions(
     plotmodel: &railplotlib::model::SchematicGraph<RailObject>,
     topo: &railmlio::topo::Topological,
+) -> HashMap<String, (f64, f64)> {
+    plotmodel.nodes.iter().filter_map(|n| {
+        let idx = n.name.strip_prefix('n')?.parse::<usize>().ok()?;
+        let coord = topo.node_coords.get(idx)?.as_ref()?;
+        Some((n.name.clone(), *coord))
+    }).collect()
+}
+
+/// Move the solver's node positions onto the pinned positions of the nodes
+/// with geoCoords. The solver output is first fitted to the pinned nodes by
+/// least squares over scale, rotation, translation and a possible mirroring,
+/// and what remains of the difference at the pinned nodes is spread to the
+/// other nodes by inverse distance weighting.
+fn fit_to_pinned(
+    solved: &HashMap<String, (f64, f64)>,
+    pinned: &HashMap<String, (f64, f64)>,
+) -> HashMap<String, (f64, f64)> {
+    let mut pairs = pinned.iter()
+        .filter_map(|(name, g)| Some((*solved.get(name)?, *g)))
+        .collect::<Vec<_>>();
+    // In a fixed order, so that the sums below come out the same every time.
+    pairs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    if pairs.is_empty() {
+        return solved.clone();
+    }
+
+    let n = pairs.len() as f64;
+    let mean = |f: &dyn Fn(&((f64, f64), (f64, f64))) -> f64| pairs.iter().map(f).sum::<f64>() / n;
+    let (px, py) = (mean(&|p| (p.0).0), mean(&|p| (p.0).1));
+    let (gx, gy) = (mean(&|p| (p.1).0), mean(&|p| (p.1).1));
+
+    // As complex numbers, g = a*p + t, or a*conj(p) + t when mirrored.
+    let fit = |mirror: bool| {
+        let (mut re, mut im, mut den) = (0.0, 0.0, 0.0);
+        for ((x, y), (u, v)) in &pairs {
+            let (x, y) = (x - px, if mirror { py - y } else { y - py });
+            let (u, v) = (u - gx, v - gy);
+            re += x * u + y * v;
+            im += x * v - y * u;
+            den += x * x + y * y;
+        }
+        let (a, b) = if den > 1e-9 { (re / den, im / den) } else { (1.0, 0.0) };
+        move |(x, y): (f64, f64)| {
+            let (x, y) = (x - px, if mirror { py - y } else { y - py });
+            (a * x - b * y + gx, b * x + a * y + gy)
+        }
+    };
+    let error = |t: &dyn Fn((f64, f64)) -> (f64, f64)| pairs.iter()
+        .map(|(p, g)| { let q = t(*p); (q.0 - g.0).powi(2) + (q.1 - g.1).powi(2) })
+        .sum::<f64>();
+    let (plain, mirrored) = (fit(false), fit(true));
+    let transform :Box<dyn Fn((f64, f64)) -> (f64, f64)> = if error(&mirrored) < error(&plain) {
+        Box::new(mirrored)
+    } else {
+        Box::new(plain)
+    };
+
+    let residuals = pairs.iter()
+        .map(|(p, g)| { let q = transform(*p); (*p, (g.0 - q.0, g.1 - q.1)) })
+        .collect::<Vec<_>>();
+    solved.iter().map(|(name, p)| {
+        if let Some(g) = pinned.get(name) {
+            return (name.clone(), *g);
+        }
+        let (mut wsum, mut dx, mut dy) = (0.0, 0.0, 0.0);
+        for (q, (rx, ry)) in &residuals {
+            let w = 1.0 / ((p.0 - q.0).powi(2) + (p.1 - q.1).powi(2)).max(1e-9);
+            wsum += w;
+            dx += w * rx;
+            dy += w * ry;
+        }
+        let t = transform(*p);
+        (name.clone(), (t.0 + dx / wsum, t.1 + dy / wsum))
+    }).collect()
+}
+
+/// Layout for files with geoCoords on only some of the nodes, such as
+/// on the main lines. The nodes with geoCoords are pinned to them, and the
+/// others are placed by the solver and moved to fit the pinned ones. The
+/// solver has no input for fixed node positions (see
+/// `SolverSettings::solver`), so the pins are applied to its output.
+fn layout_hybrid(
+    solved: &railplotlib::model::SchematicOutput<RailObject>,
+    topo: &railmlio::topo::Topological,
+    projection: &Projection,
 ) -> Option<railplotlib::model::SchematicOutput<RailObject>> {
-    fn parse_geo_coord(value: &str) -> Option<(f64, f64)> {
-        let cleaned = value.replace(',', " ");
-        let mut it = cleaned.split_whitespace();
-        let x: f64 = it.next()?.parse().ok()?;
-        let y: f64 = it.next()?.parse().ok()?;
-        Some((x, y))
+    let graph = railplotlib::model::SchematicGraph {
+        nodes: solved.nodes.iter().map(|(n, _)| n.clone()).collect(),
+        edges: solved.lines.iter().map(|(e, _)| e.clone()).collect(),
+        main_tracks_edges: Vec::new(),
+    };
+    let pinned = geo_node_positions(&graph, topo);
+    if pinned.is_empty() || pinned.len() == graph.nodes.len() {
+        return None;
     }
+    let solved_pos :HashMap<_, _> = solved.nodes.iter().map(|(n, p)| (n.name.clone(), *p)).collect();
+    layout_from_geocoord(&graph, topo, projection, &fit_to_pinned(&solved_pos, &pinned))
+}
 
+fn layout_from_geocoord(
+    plotmodel: &railplotlib::model::SchematicGraph<RailObject>,
+    topo: &railmlio::topo::Topological,
+    projection: &Projection,
+    node_pos: &HashMap<String, (f64, f64)>,
+) -> Option<railplotlib::model::SchematicOutput<RailObject>> {
     fn push_unique(points: &mut Vec<(f64, f64)>, pt: (f64, f64)) {
         let eps = 1e-6;
         if let Some(last) = points.last() {
@@ -1588,13 +3025,6 @@ fn layout_from_geocoord(
         node_index.insert(n.name.clone(), idx);
     }
 
-    let mut node_pos = HashMap::new();
-    for n in &plotmodel.nodes {
-        let idx = n.name.strip_prefix('n')?.parse::<usize>().ok()?;
-        let coord = topo.node_coords.get(idx)?.as_ref()?;
-        node_pos.insert(n.name.clone(), *coord);
-    }
-
     let mut nodes = Vec::new();
     for n in &plotmodel.nodes {
         let mut shape = n.shape;
@@ -1635,8 +3065,8 @@ fn layout_from_geocoord(
     for (idx, track) in topo.tracks.iter().enumerate() {
         let mut points = Vec::new();
         for gm in &track.track_elements.geo_mappings {
-            if let Some(coord) = gm.pos.geo_coord.as_ref().and_then(|v| parse_geo_coord(v)) {
-                points.push((gm.pos.offset, coord));
+            if let Some(coord) = gm.pos.geo_coord.as_ref().and_then(|v| railmlio::topo::parse_geo_coord(v)) {
+                points.push((gm.pos.offset, projection.project(coord)));
             }
         }
         points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
@@ -1758,14 +3188,14 @@ fn layout_from_geocoord(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::export::export_railml_to_file;
+    use crate::export::{export_railml_to_file, RailMLVersion};
 
     #[test]
     fn test_nest_sample_import() {
         let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml".to_string();
         let (tx, rx) = std::sync::mpsc::channel();
         
-        load_railml_file(filename, tx, true);
+        load_railml_file(filename, ImportJob::new(tx), true, false, None);
 
         let mut available_model = None;
         while let Ok(state) = rx.recv() {
@@ -1791,7 +3221,7 @@ mod tests {
         let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml".to_string();
         let (tx, rx) = std::sync::mpsc::channel();
 
-        load_railml_file(filename, tx, true);
+        load_railml_file(filename.clone(), ImportJob::new(tx), true, false, None);
 
         let mut available_model = None;
         while let Ok(state) = rx.recv() {
@@ -1808,7 +3238,7 @@ mod tests {
 
         let model = available_model.expect("Model should be available");
         let tmp_path = std::env::temp_dir().join(format!("junction_roundtrip_{}.railml", std::process::id()));
-        export_railml_to_file(tmp_path.to_str().expect("temp path"), &model)
+        export_railml_to_file(tmp_path.to_str().expect("temp path"), &model, RailMLVersion::V25)
             .expect("export should succeed");
 
         let xml = std::fs::read_to_string(&tmp_path).expect("exported file should exist");
@@ -1816,12 +3246,446 @@ mod tests {
         let parsed = railmlio::xml::parse_railml(&xml).expect("exported railML should parse");
         let has_tracks = parsed
             .infrastructure
+            .as_ref()
             .map(|inf| !inf.tracks.is_empty())
             .unwrap_or(false);
         assert!(has_tracks, "exported railML should contain tracks");
 
+        // OCPs, lines and states are written as imported, except for the
+        // position of OCPs, and the ids reported as kept are in the export.
+        use railmlio::diff::Change;
+        let original = std::fs::read_to_string(&filename).expect("sample railml 2.5 not found");
+        let original = railmlio::xml::parse_railml(&original).expect("railml 2.5 parse failed");
+        let (_, report) = crate::export::railml_string_with_report(&model, RailMLVersion::V25)
+            .expect("export should succeed");
+        for d in railmlio::diff::diff(&original, &parsed) {
+            let expected = match (d.element, &d.change) {
+                ("line", _) | ("state", _) => false,
+                ("ocp", Change::Changed(fields)) => fields.iter().all(|(f, _, _)| *f == "geo_coord"),
+                ("ocp", _) => false,
+                (_, Change::Removed) => !report.mapping.iter().any(|(a, b)| a == b && *a == d.id),
+                _ => true,
+            };
+            assert!(expected, "unexpected difference after export: {}", d);
+        }
+
         let _ = std::fs::remove_file(tmp_path);
     }
+
+    #[test]
+    fn test_railml_export_id_report() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml".to_string();
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        load_railml_file(filename, ImportJob::new(tx), true, false, None);
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                _ => {}
+            }
+        }
+
+        let mut model = available_model.expect("Model should be available");
+        let (_, report) = crate::export::railml_string_with_report(&model, RailMLVersion::V25)
+            .expect("export should succeed");
+        assert!(report.kept() > 0, "imported ids should be kept");
+
+        let (pta, infos) = model.railml_objects.iter()
+            .find(|(pta, _)| model.objects.contains_key(pta))
+            .map(|(pta, infos)| (*pta, infos.clone()))
+            .expect("an imported object");
+        model.objects.remove(&pta);
+        let (_, report) = crate::export::railml_string_with_report(&model, RailMLVersion::V25)
+            .expect("export should succeed");
+        for info in &infos {
+            assert!(report.lost.iter().any(|id| id == info.id()), "deleted object's id should be lost");
+            assert!(report.mapping.iter().all(|(a, _)| a != info.id()));
+        }
+    }
+
+    #[test]
+    fn test_railml_export_selection_scope() {
+        use crate::export::{railml_string, scoped_model, ExportScope};
+        let v = |x, y| nalgebra_glm::vec2(x, y);
+        let mut model = Model::empty();
+        model.linesegs.insert((v(0,0), v(1,0)));
+        model.linesegs.insert((v(1,0), v(2,0)));
+        model.linesegs.insert((v(1,0), v(2,1)));
+        model.node_data.insert(v(0,0), NDType::BufferStop);
+        model.node_data.insert(v(1,0), NDType::Sw(Side::Left));
+
+        // Without its branch, the switch is found to be a continuation.
+        let straight = vec![Ref::LineSeg(v(0,0), v(1,0)), Ref::LineSeg(v(1,0), v(2,0))].into_iter().collect();
+        let part = scoped_model(&model, &ExportScope::Selection(straight)).expect("scope should convert");
+        assert_eq!(part.linesegs.len(), 2);
+        assert_eq!(part.node_data.get(&v(0,0)), Some(&NDType::BufferStop));
+        assert_eq!(part.node_data.get(&v(1,0)), None);
+
+        // A track cut off at the switch ends there.
+        let first = vec![Ref::LineSeg(v(0,0), v(1,0))].into_iter().collect();
+        let part = scoped_model(&model, &ExportScope::Selection(first)).expect("scope should convert");
+        assert_eq!(part.node_data.get(&v(1,0)), Some(&NDType::OpenEnd));
+        let xml = railml_string(&part, RailMLVersion::V25).expect("export should succeed");
+        assert!(xml.contains("<bufferStop/>") && xml.contains("<openEnd/>") && !xml.contains("<switch"));
+
+        // Annotations and groups referring to the cut-off branch are left out.
+        let note = |r| Annotation { text: "note".to_string(), pos: nalgebra_glm::vec2(0.0, 0.0),
+                                    anchor: Some(AnnotationAnchor::Element(r)), dimension: false };
+        model.annotations.insert(note(Ref::LineSeg(v(0,0), v(1,0))));
+        model.annotations.insert(note(Ref::LineSeg(v(1,0), v(2,1))));
+        model.selection_groups.insert(SelectionGroup { name: "both".to_string(),
+            refs: vec![Ref::LineSeg(v(0,0), v(1,0)), Ref::LineSeg(v(1,0), v(2,1))] });
+        let first = vec![Ref::LineSeg(v(0,0), v(1,0))].into_iter().collect();
+        let part = scoped_model(&model, &ExportScope::Selection(first)).expect("scope should convert");
+        assert_eq!(part.annotations.iter().count(), 1);
+        let groups = part.selection_groups.iter().map(|(_, g)| g.refs.clone()).collect::<Vec<_>>();
+        assert_eq!(groups, vec![vec![Ref::LineSeg(v(0,0), v(1,0))]]);
+    }
+
+    #[test]
+    fn test_railml_macroscopic_node_names() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let data = std::fs::read_to_string(filename).expect("sample railml 2.5 not found");
+        let data = data.replacen("<bufferStop id=\"tr01bs01\"/>", "<macroscopicNode id=\"mn01\" ocpRef=\"ocp01\"/>", 1);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        load_railml_text(&data, None, ImportJob::new(tx), true, None);
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                _ => {}
+            }
+        }
+
+        let model = available_model.expect("Model should be available");
+        let nodes = model.railml_nodes.values().collect::<Vec<_>>();
+        assert_eq!(nodes, vec![&RailMLNodeInfo { id: "mn01".to_string(), ocp_ref: Some("ocp01".to_string()) }]);
+        let pt = model.railml_nodes.keys().next().unwrap();
+        assert!(matches!(model.node_data.get(pt), Some(NDType::OpenEnd) | Some(NDType::BufferStop)));
+
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        assert!(xml.contains("<macroscopicNode id=\"mn01\" ocpRef=\"ocp01\"/>"), "boundary should keep its name");
+    }
+
+    #[test]
+    fn test_railml_signal_speeds_roundtrip() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let data = std::fs::read_to_string(filename).expect("sample railml 2.5 not found");
+        let data = data.replacen("</platformEdges>",
+            "</platformEdges><speedChanges><speedChange id=\"tr01sc01\" pos=\"300\" dir=\"up\" vMax=\"60\"/></speedChanges>", 1);
+        let data = data.replacen("<etcs level_2=\"true\"/>",
+            "<etcs level_2=\"true\"/><speed kind=\"announcement\" switchable=\"true\"><speedChangeRef ref=\"tr01sc01\"/></speed>", 1);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        load_railml_text(&data, None, ImportJob::new(tx), true, None);
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                _ => {}
+            }
+        }
+
+        let model = available_model.expect("Model should be available");
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        let parsed = railmlio::xml::parse_railml(&xml).expect("exported railML should parse");
+        let signal = parsed.infrastructure.iter().flat_map(|inf| inf.tracks.iter())
+            .flat_map(|t| t.objects.signals.iter())
+            .find(|s| s.id == "sig02")
+            .expect("signal should be exported");
+        assert_eq!(signal.speeds.len(), 1);
+        assert_eq!(signal.speeds[0].switchable, Some(true));
+        assert_eq!(signal.speeds[0].speed_change_ref.as_deref(), Some("tr01sc01"));
+        assert_eq!(signal.etcs.as_ref().and_then(|e| e.level_2), Some(true));
+    }
+
+    #[test]
+    fn test_railml_ocp_positions() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        load_railml_file(filename.to_string(), ImportJob::new(tx), true, false, None);
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                _ => {}
+            }
+        }
+
+        let mut model = available_model.expect("Model should be available");
+        let p = *model.ocp_positions.get("ocp02").expect("ocp02 should be placed at its tracks");
+        let moved = nalgebra_glm::vec2(p.x + 3.0, p.y);
+        model.ocp_positions.insert("ocp02".to_string(), moved);
+
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        let parsed = railmlio::xml::parse_railml(&xml).expect("exported railML should parse");
+        let ocp = parsed.infrastructure.iter().flat_map(|inf| inf.ocps.iter())
+            .find(|o| o.id == "ocp02")
+            .expect("ocp should be exported");
+        let (x, y) = ocp.geo_coord.as_ref()
+            .and_then(|g| railmlio::topo::parse_geo_coord(&g.coord))
+            .expect("ocp should have a geoCoord");
+        assert!((x as f32 - moved.x).abs() < 1e-3 && (y as f32 - moved.y).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_fit_to_pinned() {
+        // A straight line from the solver, pinned at its ends to a line
+        // turned a quarter round and twice as long.
+        let solved = vec![("n0", (0.0, 0.0)), ("n1", (100.0, 0.0)), ("n2", (200.0, 0.0)), ("n3", (300.0, 0.0))]
+            .into_iter().map(|(n, p)| (n.to_string(), p)).collect::<HashMap<_, _>>();
+        let pinned = vec![("n0", (1000.0, 500.0)), ("n2", (1000.0, 900.0))]
+            .into_iter().map(|(n, p)| (n.to_string(), p)).collect::<HashMap<_, _>>();
+        let fitted = fit_to_pinned(&solved, &pinned);
+        assert_eq!(fitted["n0"], (1000.0, 500.0));
+        assert_eq!(fitted["n2"], (1000.0, 900.0));
+        for (name, expected) in &[("n1", (1000.0, 700.0)), ("n3", (1000.0, 1100.0))] {
+            let (x, y) = fitted[*name];
+            assert!((x - expected.0).abs() < 1e-6 && (y - expected.1).abs() < 1e-6, "{} at {} {}", name, x, y);
+        }
+    }
+
+    #[test]
+    fn test_estimated_mileage_follows_abs_pos() {
+        // Two 100 m tracks in a line, with absPos values 220 m apart.
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<railml xmlns="https://www.railml.org/schemas/2021" version="2.5">
+  <infrastructure id="inf01">
+    <tracks>
+      <track id="tr01">
+        <trackTopology>
+          <trackBegin pos="0" absPos="0" id="tr01tb"><bufferStop id="tr01bs"/></trackBegin>
+          <trackEnd pos="100" id="tr01te"><connection ref="tr02c" id="tr01c"/></trackEnd>
+        </trackTopology>
+      </track>
+      <track id="tr02">
+        <trackTopology>
+          <trackBegin pos="0" id="tr02tb"><connection ref="tr01c" id="tr02c"/></trackBegin>
+          <trackEnd pos="100" absPos="220" id="tr02te"><bufferStop id="tr02bs"/></trackEnd>
+        </trackTopology>
+      </track>
+    </tracks>
+  </infrastructure>
+</railml>"#;
+        let parsed = railmlio::xml::parse_railml(data).expect("railML should parse");
+        let topo = railmlio::topo::convert_railml_topo(parsed).expect("topology should convert");
+        let graph = match convert_railplot_with_method(&topo, true) {
+            Ok(graph) => graph,
+            Err(_) => panic!("mileage should be estimated"),
+        };
+        let pos = graph.nodes.iter().map(|n| n.pos).collect::<Vec<_>>();
+        let span = pos.iter().cloned().fold(f64::MIN, f64::max) - pos.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(span > 205.0 && span < 220.0, "span {}", span);
+        // The start node is fixed by the calibration.
+        assert!(pos.contains(&0.0), "{:?}", pos);
+    }
+
+    #[test]
+    fn test_railml_merge_into_model() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        load_railml_file(filename.to_string(), ImportJob::new(tx), true, false, None);
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                _ => {}
+            }
+        }
+
+        let model = available_model.expect("Model should be available");
+        let mut merged = model.clone();
+        let renames = merged.add_infrastructure(model.clone(), nalgebra_glm::vec2(0, -100));
+
+        assert_eq!(merged.linesegs.len(), 2 * model.linesegs.len());
+        assert_eq!(merged.railml_tracks.len(), 2 * model.railml_tracks.len());
+        assert!(renames.iter().any(|(old, new)| old == "tr01" && new == "tr01_2"));
+        assert!(merged.railml_tracks.iter().any(|t| t.id == "tr01_2"));
+        assert_eq!(merged.dispatches.iter().count(), model.dispatches.iter().count());
+    }
+
+    #[test]
+    fn test_railml_selective_import() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let (tx, rx) = std::sync::mpsc::channel();
+        let selection = vec!["tr02".to_string(), "tr03".to_string()].into_iter().collect::<HashSet<_>>();
+
+        load_railml_file(filename.to_string(), ImportJob::new(tx), true, false, Some(selection));
+
+        let mut available_model = None;
+        while let Ok(state) = rx.recv() {
+            match state {
+                ImportState::Available(model) => {
+                    available_model = Some(model);
+                    break;
+                }
+                ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                ImportState::ValidationFailed(issues) => panic!("Validation failed: {:?}", issues),
+                _ => {}
+            }
+        }
+
+        let model = available_model.expect("Model should be available");
+        let mut track_ids = model.railml_tracks.iter().map(|t| t.id.as_str()).collect::<Vec<_>>();
+        track_ids.sort();
+        track_ids.dedup();
+        assert_eq!(track_ids, vec!["tr02", "tr03"]);
+        assert_eq!(model.railml_ocps.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(), vec!["ocp02"]);
+    }
+
+    #[test]
+    fn test_railml_import_cancelled() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let (tx, rx) = std::sync::mpsc::channel();
+        let job = ImportJob::new(tx);
+        job.cancel.store(true, Ordering::Relaxed);
+
+        load_railml_file(filename.to_string(), job, true, false, None);
+
+        assert!(rx.try_iter().all(|state| !matches!(state, ImportState::Available(_))));
+    }
+
+    #[test]
+    fn test_railml_import_deterministic() {
+        let filename = "railML/IS NEST view/2024-07-19_railML_SimpleExample_v13_NEST_railML2.5.xml";
+        let settings = SolverSettings { time_limit: 600.0, ..SolverSettings::default() };
+        let import = || {
+            let (tx, rx) = std::sync::mpsc::channel();
+            load_railml_file(filename.to_string(), ImportJob::new(tx).with_solver(settings.clone()), true, false, None);
+            while let Ok(state) = rx.recv() {
+                match state {
+                    ImportState::Available(model) => {
+                        return crate::file::to_text_document(&model).expect("model should be written as text");
+                    }
+                    ImportState::SourceFileError(e) => panic!("Source file error: {}", e),
+                    ImportState::PlotError(e) => panic!("Plot error: {}", e),
+                    ImportState::ValidationFailed(issues) => panic!("Validation failed: {:?}", issues),
+                    _ => {}
+                }
+            }
+            panic!("Model should be available");
+        };
+        assert!(import() == import(), "importing the same file twice should give the same model");
+    }
+
+    #[test]
+    fn test_canonical_plot_order() {
+        use railplotlib::model as plot;
+        let node = |name :&str, pos| plot::Node { name: name.to_string(), pos, shape: plot::Shape::Continuation };
+        let edge = |a :&str, b :&str| plot::Edge { a: (a.to_string(), plot::Port::Out),
+                                                    b: (b.to_string(), plot::Port::In), objects: Vec::new() };
+        let graph = |nodes, edges| plot::SchematicGraph::<RailObject> { nodes, edges, main_tracks_edges: Vec::new() };
+        let mut a = graph(vec![node("n2", 10.0), node("n0", 0.0), node("n1", 10.0)],
+                          vec![edge("n1", "n2"), edge("n0", "n1")]);
+        let mut b = graph(vec![node("n1", 10.0), node("n2", 10.0), node("n0", 0.0)],
+                          vec![edge("n0", "n1"), edge("n1", "n2")]);
+        canonical_order(&mut a);
+        canonical_order(&mut b);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        assert_eq!(a.nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), vec!["n0", "n1", "n2"]);
+    }
+
+    #[test]
+    fn test_one_solver_thread() {
+        let solver_thread = SolverThread::default();
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        assert!(solver_thread.spawn(move || { let _ = rx.recv(); }));
+        assert!(solver_thread.is_running());
+        assert!(!solver_thread.spawn(|| {}));
+        drop(tx);
+        let started = Instant::now();
+        while solver_thread.is_running() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!solver_thread.is_running());
+        assert!(solver_thread.spawn(|| {}));
+    }
+
+    #[test]
+    fn test_solver_settings() {
+        let mut settings = SolverSettings::default();
+        assert_eq!(settings.solver().criteria.len(), 5);
+        settings.goals[0].1 = false;
+        settings.goals.swap(1, 2);
+        assert_eq!(settings.solver().criteria.len(), 4);
+    }
+
+    #[test]
+    fn test_lineseg_length_and_speed_export() {
+        use nalgebra_glm as glm;
+        let mut model = Model::empty();
+        let (a, b) = ((glm::vec2(0, 0), glm::vec2(1, 0)), (glm::vec2(1, 0), glm::vec2(2, 0)));
+        model.linesegs.insert(a);
+        model.linesegs.insert(b);
+        model.lineseg_attributes.insert(a, LineSegAttributes {
+            length: Some(120.0), max_speed: Some(80.0), track_name: Some("Main".to_string()), ..Default::default()
+        });
+        model.lineseg_attributes.insert(b, LineSegAttributes { max_speed: Some(60.0), ..Default::default() });
+
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        let parsed = railmlio::xml::parse_railml(&xml).expect("exported railML should parse");
+        let track = parsed.infrastructure.iter().flat_map(|inf| inf.tracks.iter()).next()
+            .expect("track should be exported");
+        assert_eq!(track.name.as_deref(), Some("Main"));
+        assert_eq!(track.end.pos.offset - track.begin.pos.offset, 170.0);
+        // Up and down the track at its ends and where the speed changes,
+        // which depends on the direction the track was found in.
+        let changes = &track.track_elements.speed_changes;
+        let mut offsets = changes.iter().map(|sc| sc.pos.offset).collect::<Vec<_>>();
+        offsets.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert!(offsets == vec![0.0, 120.0, 120.0, 170.0] || offsets == vec![0.0, 50.0, 50.0, 170.0]);
+        assert_eq!(changes.iter().filter(|sc| sc.vmax.as_deref() == Some("80")).count(), 2);
+    }
+
+    #[test]
+    fn test_export_uses_document_write_options() {
+        use nalgebra_glm as glm;
+        let indents = |xml :&str| xml.lines().map(|l| l.len() - l.trim_start().len()).collect::<Vec<_>>();
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0, 0), glm::vec2(1, 0)));
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        assert!(indents(&xml).contains(&2));
+
+        model.file_settings.export = Some(railmlio::write::WriteOptions { indent: 4, ..Default::default() });
+        let xml = crate::export::railml_string(&model, RailMLVersion::V25).expect("export should succeed");
+        assert!(indents(&xml).contains(&4));
+        assert!(indents(&xml).iter().all(|n| n % 4 == 0));
+    }
 }
 
 
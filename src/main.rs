@@ -1,11 +1,29 @@
+#[macro_use]
+mod i18n;
 mod app;
 mod config;
 mod document;
 mod file;
 mod gui;
 mod util;
+mod units;
 mod import;
+mod osm;
 mod export;
+mod route_table;
+mod event_table;
+mod sumo;
+mod geojson;
+mod dxf;
+mod print;
+mod project;
+mod session;
+mod scripting;
+mod plugins;
+mod server;
+mod crashreport;
+mod feed;
+mod cli;
 
 mod synthesis;
 
@@ -14,58 +32,57 @@ use crate::app::*;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if let Some(pos) = args.iter().position(|x| x == "--dump-json") {
-        let input = match args.get(pos + 1) {
-            Some(val) => val,
-            None => {
-                eprintln!("Missing input file. Usage: junction --dump-json <input> <output>");
-                std::process::exit(2);
-            }
-        };
-        let output = match args.get(pos + 2) {
-            Some(val) => val,
-            None => {
-                eprintln!("Missing output file. Usage: junction --dump-json <input> <output>");
-                std::process::exit(2);
-            }
-        };
-        let model = match file::load(input) {
-            Ok(m) => m,
-            Err(err) => {
-                eprintln!("Failed to load {:?}: {}", input, err);
-                std::process::exit(1);
-            }
-        };
-        if let Err(err) = file::dump_json(output, &model) {
-            eprintln!("Failed to write {:?}: {}", output, err);
-            std::process::exit(1);
-        }
-        return;
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
     }
 
     // Init logging
     let logstring = gui::windows::logview::StringLogger::init(log::LevelFilter::Trace).unwrap();
     info!("Starting {} v{}.", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let last_crash = crashreport::take_last_crash();
+    crashreport::install(logstring.clone());
 
     // User config not directly related to model or ui state. (colors, fonts, etc.)
     let config = config::Config::load();
+    i18n::set_language(&config.language);
     let background_jobs = app::BackgroundJobs::new();
 
     // Create an empty, untitled document
-    // TODO: command line read from file
     let document = document::Document::empty(background_jobs.clone());
 
     // Additional windows are closed.
-    let windows = app::Windows::closed(background_jobs.clone());
+    let mut windows = app::Windows::closed(background_jobs.clone());
+    windows.crash_report = last_crash;
 
     let mut app = app::App {
         document: document,
+        tabs: app::DocumentTabs::new(),
+        clipboard: crate::document::model::Model::empty(),
         log: logstring,
         config :config,
         windows: windows,
         background_jobs: background_jobs,
+        project: None,
+        plugins: plugins::Plugins::load(),
+        server: None,
+        feed: None,
     };
 
+    // A file given on the command line is opened instead of the previous session.
+    let session = session::Session::load();
+    match args.iter().skip(1).find(|x| !x.starts_with("--")) {
+        Some(filename) => {
+            session.restore_windows(&mut app);
+            if session::open_document(&mut app, filename.clone()) {
+                app.config.add_recent_file(filename.clone(), config::RecentFileKind::Document);
+            }
+        },
+        None => session.restore(&mut app),
+    }
+
+    if args.iter().any(|x| x == "--serve") {
+        app.start_server();
+    }
 
    let big = args.iter().find(|x| *x == "--big").is_some();
 
@@ -83,6 +100,9 @@ fn main() {
         // Check background threads for updates
         app.document.check();
         app.windows.import_window.update();
+        if let Some(server) = &mut app.server { server.update(&app.document); }
+        if app.feed.as_ref().map(|f| *f.settings() != app.config.feed).unwrap_or(false) { app.start_feed(); }
+        if let Some(feed) = &mut app.feed { feed.update(&app.document); }
         if let Some(win) = &mut app.windows.synthesis_window { win.check(); }
 
         // Advance time in animations
@@ -92,6 +112,10 @@ fn main() {
 
         // Draw and interact with GUI
         let continue_running = gui::main(&mut app);
+        crashreport::update(&app);
+        if !continue_running {
+            session::Session::from_app(&app).save();
+        }
         return continue_running;
     }).unwrap();
 }
@@ -126,10 +150,16 @@ mod tests {
 
         let mut app = app::App {
             document: document,
+            tabs: app::DocumentTabs::new(),
+            clipboard: crate::document::model::Model::empty(),
             log: logstring,
             config :config,
             windows: windows,
             background_jobs: background_jobs,
+            project: None,
+            plugins: Default::default(),
+            server: None,
+            feed: None,
         };
 
 
@@ -0,0 +1,619 @@
+//! Import of OpenStreetMap railway extracts, as Overpass JSON or `.osm.pbf`.
+//!
+//! The ways tagged `railway=rail` are split into tracks at the nodes they
+//! share with other railway ways. Where two tracks meet they continue into
+//! each other, where three meet there is a switch, with its trunk and the
+//! side of its deviating track found from the directions of the tracks,
+//! and where four meet there is a crossing. The topology is then laid out
+//! by the schematic solver like a railML file without absPos values.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use log::*;
+
+use railmlio::geo::{Crs, Projection};
+use railmlio::model::{Objects, TrackElements};
+use railmlio::topo::{self, Port, Side, TopoNode, TopoTrack, Topological, TrackSource, AB};
+
+pub struct OsmWay {
+    pub id :i64,
+    pub tags :HashMap<String, String>,
+    pub nodes :Vec<i64>,
+}
+
+/// The railway ways of an extract, and the latitude and longitude of
+/// their nodes.
+#[derive(Default)]
+pub struct OsmData {
+    pub nodes :HashMap<i64, (f64, f64)>,
+    pub ways :Vec<OsmWay>,
+}
+
+fn is_railway(tags :&HashMap<String, String>) -> bool {
+    tags.get("railway").map(|v| v == "rail").unwrap_or(false)
+}
+
+/// Read an extract, as `.osm.pbf` if the data starts like one and as
+/// Overpass JSON otherwise.
+pub fn parse(data :&[u8]) -> Result<OsmData, String> {
+    let json = data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if json { parse_overpass_json(data) } else { parse_pbf(data) }
+}
+
+/// Overpass JSON, from `[out:json]` queries with `out body;` or `out geom;`.
+pub fn parse_overpass_json(data :&[u8]) -> Result<OsmData, String> {
+    let value :serde_json::Value = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    let elements = value.get("elements").and_then(|e| e.as_array())
+        .ok_or_else(|| "No elements in Overpass JSON".to_string())?;
+    let mut osm = OsmData::default();
+    for e in elements {
+        let id = e.get("id").and_then(|x| x.as_i64());
+        match (e.get("type").and_then(|t| t.as_str()), id) {
+            (Some("node"), Some(id)) => {
+                if let (Some(lat), Some(lon)) = (e.get("lat").and_then(|x| x.as_f64()),
+                                                 e.get("lon").and_then(|x| x.as_f64())) {
+                    osm.nodes.insert(id, (lat, lon));
+                }
+            },
+            (Some("way"), Some(id)) => {
+                let tags = e.get("tags").and_then(|t| t.as_object()).map(|t| t.iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                    .collect()).unwrap_or_default();
+                if !is_railway(&tags) { continue; }
+                let nodes = e.get("nodes").and_then(|n| n.as_array())
+                    .map(|n| n.iter().filter_map(|x| x.as_i64()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                // With `out geom;` the coordinates come with the way.
+                if let Some(geometry) = e.get("geometry").and_then(|g| g.as_array()) {
+                    for (node, g) in nodes.iter().zip(geometry.iter()) {
+                        if let (Some(lat), Some(lon)) = (g.get("lat").and_then(|x| x.as_f64()),
+                                                         g.get("lon").and_then(|x| x.as_f64())) {
+                            osm.nodes.insert(*node, (lat, lon));
+                        }
+                    }
+                }
+                osm.ways.push(OsmWay { id, tags, nodes });
+            },
+            _ => {},
+        }
+    }
+    Ok(osm)
+}
+
+//
+// Protocol buffers, as much as is needed for the OSM PBF format.
+//
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+struct Message<'a> {
+    data :&'a [u8],
+    pos :usize,
+}
+
+impl<'a> Message<'a> {
+    fn new(data :&'a [u8]) -> Self { Message { data, pos: 0 } }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = *self.data.get(self.pos).ok_or("Truncated varint")?;
+            self.pos += 1;
+            value |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 { return Ok(value); }
+        }
+        Err("Invalid varint".to_string())
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, Field<'a>)>, String> {
+        if self.pos >= self.data.len() { return Ok(None); }
+        let key = self.varint()?;
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => { self.pos += 8; Field::Fixed },
+            2 => {
+                let len = self.varint()? as usize;
+                let end = self.pos.checked_add(len).ok_or("Invalid field length")?;
+                let bytes = self.data.get(self.pos..end).ok_or("Truncated field")?;
+                self.pos += len;
+                Field::Bytes(bytes)
+            },
+            5 => { self.pos += 4; Field::Fixed },
+            t => return Err(format!("Unsupported wire type {}", t)),
+        };
+        Ok(Some((key >> 3, field)))
+    }
+
+    fn fields(data :&'a [u8]) -> Result<Vec<(u64, Field<'a>)>, String> {
+        let mut message = Message::new(data);
+        let mut fields = Vec::new();
+        while let Some(f) = message.next_field()? { fields.push(f); }
+        Ok(fields)
+    }
+}
+
+fn zigzag(x :u64) -> i64 { ((x >> 1) as i64) ^ -((x & 1) as i64) }
+
+fn packed(data :&[u8]) -> Result<Vec<u64>, String> {
+    let mut message = Message::new(data);
+    let mut xs = Vec::new();
+    while message.pos < data.len() { xs.push(message.varint()?); }
+    Ok(xs)
+}
+
+/// Packed, zigzag encoded and delta coded values.
+fn packed_delta(data :&[u8]) -> Result<Vec<i64>, String> {
+    let mut acc = 0i64;
+    Ok(packed(data)?.into_iter().map(|x| { acc += zigzag(x); acc }).collect())
+}
+
+/// Call `f` with each `OSMData` block of a PBF file. The blocks are
+/// decompressed one at a time, so that only one is held in memory.
+fn for_each_block(data :&[u8], mut f :impl FnMut(&[u8]) -> Result<(), String>) -> Result<(), String> {
+    let slice = |pos :usize, len :usize, what :&str| pos.checked_add(len)
+        .and_then(|end| data.get(pos..end)).ok_or_else(|| format!("Truncated {}", what));
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = slice(pos, 4, "blob header length")?;
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        pos += 4;
+        let header = slice(pos, len, "blob header")?;
+        pos += len;
+        let (mut kind, mut size) = ("", 0);
+        for (n, f) in Message::fields(header)? {
+            match (n, f) {
+                (1, Field::Bytes(b)) => kind = std::str::from_utf8(b).map_err(|e| e.to_string())?,
+                (3, Field::Varint(x)) => size = x as usize,
+                _ => {},
+            }
+        }
+        let blob = slice(pos, size, "blob")?;
+        pos += size;
+        if kind != "OSMData" { continue; }
+        for (n, field) in Message::fields(blob)? {
+            match (n, field) {
+                (1, Field::Bytes(raw)) => f(raw)?,
+                (3, Field::Bytes(zlib)) => {
+                    let mut block = Vec::new();
+                    flate2::read::ZlibDecoder::new(zlib).read_to_end(&mut block).map_err(|e| e.to_string())?;
+                    f(&block)?;
+                },
+                (4, Field::Bytes(_)) | (5, Field::Bytes(_)) | (6, Field::Bytes(_)) => {
+                    return Err("Only uncompressed and zlib compressed PBF blobs are supported".to_string());
+                },
+                _ => {},
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The string table, and the groups of a primitive block with the
+/// granularity and offsets of its coordinates.
+struct PrimitiveBlock<'a> {
+    strings :Vec<&'a [u8]>,
+    groups :Vec<&'a [u8]>,
+    granularity :i64,
+    lat_offset :i64,
+    lon_offset :i64,
+}
+
+impl<'a> PrimitiveBlock<'a> {
+    fn new(data :&'a [u8]) -> Result<Self, String> {
+        let mut block = PrimitiveBlock { strings: Vec::new(), groups: Vec::new(),
+                                         granularity: 100, lat_offset: 0, lon_offset: 0 };
+        for (n, f) in Message::fields(data)? {
+            match (n, f) {
+                (1, Field::Bytes(table)) => {
+                    for (n, f) in Message::fields(table)? {
+                        if let (1, Field::Bytes(s)) = (n, f) { block.strings.push(s); }
+                    }
+                },
+                (2, Field::Bytes(group)) => block.groups.push(group),
+                (17, Field::Varint(x)) => block.granularity = x as i64,
+                (19, Field::Varint(x)) => block.lat_offset = x as i64,
+                (20, Field::Varint(x)) => block.lon_offset = x as i64,
+                _ => {},
+            }
+        }
+        Ok(block)
+    }
+
+    fn string(&self, idx :u64) -> String {
+        self.strings.get(idx as usize).map(|s| String::from_utf8_lossy(s).into_owned()).unwrap_or_default()
+    }
+
+    fn coord(&self, lat :i64, lon :i64) -> (f64, f64) {
+        (1e-9 * (self.lat_offset + self.granularity * lat) as f64,
+         1e-9 * (self.lon_offset + self.granularity * lon) as f64)
+    }
+}
+
+/// OSM PBF, read in two passes over the blocks: first the railway ways,
+/// and then only the nodes they use.
+pub fn parse_pbf(data :&[u8]) -> Result<OsmData, String> {
+    let mut osm = OsmData::default();
+    for_each_block(data, |block| {
+        let block = PrimitiveBlock::new(block)?;
+        for group in &block.groups {
+            for (n, f) in Message::fields(group)? {
+                let way = match (n, f) { (3, Field::Bytes(way)) => way, _ => continue };
+                let (mut id, mut keys, mut vals, mut refs) = (0, Vec::new(), Vec::new(), Vec::new());
+                for (n, f) in Message::fields(way)? {
+                    match (n, f) {
+                        (1, Field::Varint(x)) => id = x as i64,
+                        (2, Field::Bytes(b)) => keys = packed(b)?,
+                        (3, Field::Bytes(b)) => vals = packed(b)?,
+                        (8, Field::Bytes(b)) => refs = packed_delta(b)?,
+                        _ => {},
+                    }
+                }
+                let tags = keys.iter().zip(vals.iter())
+                    .map(|(k, v)| (block.string(*k), block.string(*v)))
+                    .collect();
+                if is_railway(&tags) {
+                    osm.ways.push(OsmWay { id, tags, nodes: refs });
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let used = osm.ways.iter().flat_map(|w| w.nodes.iter().cloned()).collect::<HashSet<_>>();
+    for_each_block(data, |block| {
+        let block = PrimitiveBlock::new(block)?;
+        for group in &block.groups {
+            for (n, f) in Message::fields(group)? {
+                match (n, f) {
+                    (1, Field::Bytes(node)) => {
+                        let (mut id, mut lat, mut lon) = (0, 0, 0);
+                        for (n, f) in Message::fields(node)? {
+                            match (n, f) {
+                                (1, Field::Varint(x)) => id = zigzag(x),
+                                (8, Field::Varint(x)) => lat = zigzag(x),
+                                (9, Field::Varint(x)) => lon = zigzag(x),
+                                _ => {},
+                            }
+                        }
+                        if used.contains(&id) { osm.nodes.insert(id, block.coord(lat, lon)); }
+                    },
+                    (2, Field::Bytes(dense)) => {
+                        let (mut ids, mut lats, mut lons) = (Vec::new(), Vec::new(), Vec::new());
+                        for (n, f) in Message::fields(dense)? {
+                            match (n, f) {
+                                (1, Field::Bytes(b)) => ids = packed_delta(b)?,
+                                (8, Field::Bytes(b)) => lats = packed_delta(b)?,
+                                (9, Field::Bytes(b)) => lons = packed_delta(b)?,
+                                _ => {},
+                            }
+                        }
+                        for ((id, lat), lon) in ids.iter().zip(lats.iter()).zip(lons.iter()) {
+                            if used.contains(id) { osm.nodes.insert(*id, block.coord(*lat, *lon)); }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(osm)
+}
+
+//
+// Conversion to a topology.
+//
+
+fn sub(a :(f64, f64), b :(f64, f64)) -> (f64, f64) { (a.0 - b.0, a.1 - b.1) }
+fn dot(a :(f64, f64), b :(f64, f64)) -> f64 { a.0 * b.0 + a.1 * b.1 }
+fn cross(a :(f64, f64), b :(f64, f64)) -> f64 { a.0 * b.1 - a.1 * b.0 }
+
+fn unit(a :(f64, f64)) -> (f64, f64) {
+    let len = dot(a, a).sqrt();
+    if len > 0.0 { (a.0 / len, a.1 / len) } else { (0.0, 0.0) }
+}
+
+/// A track from an OSM way, between two nodes where it meets other tracks
+/// or ends.
+struct Piece<'a> {
+    way :&'a OsmWay,
+    index :usize,
+    /// Points in meters, see `to_topology`.
+    points :Vec<(f64, f64)>,
+    ends :(i64, i64),
+}
+
+impl<'a> Piece<'a> {
+    fn length(&self) -> f64 {
+        self.points.windows(2).map(|w| { let d = sub(w[1], w[0]); dot(d, d).sqrt() }).sum()
+    }
+
+    /// Direction of the track leaving its node at the given end.
+    fn direction(&self, end :AB) -> (f64, f64) {
+        let n = self.points.len();
+        match end {
+            AB::A => unit(sub(self.points[1], self.points[0])),
+            AB::B => unit(sub(self.points[n - 2], self.points[n - 1])),
+        }
+    }
+}
+
+/// Ports of the track ends at a node, from the directions of the tracks
+/// leaving it, with the coordinates in meters east and north.
+fn node_ports(dirs :&[(f64, f64)]) -> Option<(TopoNode, Vec<Port>)> {
+    match dirs.len() {
+        1 => Some((TopoNode::OpenEnd, vec![Port::Single])),
+        2 => Some((TopoNode::Continuation, vec![Port::ContA, Port::ContB])),
+        3 => {
+            // The trunk points away from both of the other tracks, and the
+            // straight track is the one most opposite the trunk.
+            let trunk = (0..3).min_by(|&i, &j| {
+                let away = |i :usize| (0..3).filter(|k| *k != i).map(|k| dot(dirs[i], dirs[k])).sum::<f64>();
+                away(i).partial_cmp(&away(j)).unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+            let mut others = (0..3).filter(|k| *k != trunk).collect::<Vec<_>>();
+            others.sort_by(|&i, &j| dot(dirs[trunk], dirs[i]).partial_cmp(&dot(dirs[trunk], dirs[j]))
+                           .unwrap_or(std::cmp::Ordering::Equal));
+            let (straight, deviating) = (others[0], others[1]);
+            // Seen from the trunk, counterclockwise is to the left.
+            let travel = (-dirs[trunk].0, -dirs[trunk].1);
+            let side = if cross(travel, dirs[deviating]) > 0.0 { Side::Left } else { Side::Right };
+            let mut ports = vec![Port::Trunk; 3];
+            ports[straight] = side.opposite().to_port();
+            ports[deviating] = side.to_port();
+            Some((TopoNode::Switch(side), ports))
+        },
+        4 => {
+            // Each track continues into the one most opposite to it.
+            let opposite = |i :usize| (0..4).filter(|k| *k != i)
+                .min_by(|&a, &b| dot(dirs[i], dirs[a]).partial_cmp(&dot(dirs[i], dirs[b]))
+                        .unwrap_or(std::cmp::Ordering::Equal));
+            let a0 = 0;
+            let b0 = opposite(a0)?;
+            let rest = (0..4).filter(|k| *k != a0 && *k != b0).collect::<Vec<_>>();
+            if opposite(rest[0])? != rest[1] { return None; }
+            let (mut a1, mut b1) = (rest[0], rest[1]);
+            if dot(dirs[a0], dirs[a1]) < dot(dirs[a0], dirs[b1]) { std::mem::swap(&mut a1, &mut b1); }
+            // The A ends of both lines are on the same side of the crossing.
+            let mut ports = vec![Port::Single; 4];
+            ports[a0] = Port::Crossing(AB::A, 0);
+            ports[b0] = Port::Crossing(AB::B, 0);
+            ports[a1] = Port::Crossing(AB::A, 1);
+            ports[b1] = Port::Crossing(AB::B, 1);
+            Some((TopoNode::Crossing, ports))
+        },
+        _ => None,
+    }
+}
+
+/// Split the railway ways into tracks at the nodes they share, and connect
+/// the tracks through continuations, switches and crossings. Lengths are
+/// measured in a transverse Mercator projection around the extract.
+pub fn to_topology(osm :&OsmData) -> Result<Topological, String> {
+    let mut ways = osm.ways.iter().filter(|w| is_railway(&w.tags)).collect::<Vec<_>>();
+    ways.sort_by_key(|w| w.id);
+    let projection = Projection::new(Crs::Geographic, ways.iter()
+        .flat_map(|w| w.nodes.iter().filter_map(|n| osm.nodes.get(n).cloned())));
+
+    let mut uses :HashMap<i64, usize> = HashMap::new();
+    for w in &ways {
+        for n in w.nodes.iter().filter(|n| osm.nodes.contains_key(n)) {
+            *uses.entry(*n).or_insert(0) += 1;
+        }
+    }
+
+    let mut pieces = Vec::new();
+    for w in &ways {
+        let nodes = w.nodes.iter().filter(|n| osm.nodes.contains_key(n)).collect::<Vec<_>>();
+        let (mut start, mut index) = (0, 0);
+        for i in 1..nodes.len() {
+            if i + 1 == nodes.len() || uses[nodes[i]] > 1 {
+                let points = nodes[start..=i].iter().map(|n| projection.project(osm.nodes[n])).collect();
+                pieces.push(Piece { way: w, index, points, ends: (*nodes[start], *nodes[i]) });
+                start = i;
+                index += 1;
+            }
+        }
+    }
+    if pieces.is_empty() {
+        return Err("No railway=rail ways with node coordinates found".to_string());
+    }
+
+    let mut topo = Topological { tracks: Vec::new(), nodes: Vec::new(), connections: Vec::new(), node_coords: Vec::new() };
+    let mut node_ends :HashMap<i64, Vec<(usize, AB)>> = HashMap::new();
+    for (idx, piece) in pieces.iter().enumerate() {
+        let id = if piece.index == 0 { format!("w{}", piece.way.id) }
+                 else { format!("w{}-{}", piece.way.id, piece.index) };
+        let source = TrackSource {
+            id: id.clone(),
+            code: piece.way.tags.get("ref").cloned(),
+            name: piece.way.tags.get("name").cloned(),
+            description: None,
+            track_type: None,
+            main_dir: None,
+            begin_id: format!("{}-b", id),
+            end_id: format!("{}-e", id),
+            abs_pos_begin: None,
+            abs_pos_end: None,
+        };
+        topo::new_track(&mut topo, TopoTrack {
+            objects: Objects::empty(),
+            track_elements: TrackElements::empty(),
+            length: piece.length(),
+            offset: 0.0,
+            segment_index: 0,
+            segment_id: id,
+            begin_id: source.begin_id.clone(),
+            end_id: source.end_id.clone(),
+            source,
+        });
+        node_ends.entry(piece.ends.0).or_default().push((idx, AB::A));
+        node_ends.entry(piece.ends.1).or_default().push((idx, AB::B));
+    }
+
+    let mut nodes = node_ends.into_iter().collect::<Vec<_>>();
+    nodes.sort_by_key(|(id, _)| *id);
+    for (id, ends) in nodes {
+        let dirs = ends.iter().map(|(t, ab)| pieces[*t].direction(*ab)).collect::<Vec<_>>();
+        match node_ports(&dirs) {
+            Some((node, ports)) => {
+                let nd = topo::new_node(&mut topo, node);
+                for (end, port) in ends.into_iter().zip(ports) {
+                    topo.connections.push((end, (nd, port)));
+                }
+            },
+            None => {
+                warn!("OSM node {} joins {} tracks, which is not supported; the tracks end there", id, ends.len());
+                for end in ends {
+                    let nd = topo::new_node(&mut topo, TopoNode::OpenEnd);
+                    topo.connections.push((end, (nd, Port::Single)));
+                }
+            },
+        }
+    }
+    info!("Converted {} OSM ways to {} tracks", ways.len(), topo.tracks.len());
+    Ok(topo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_from_overpass_json() {
+        // A line going east, with a branch to the north-east from its middle
+        // node, and a road that is left out.
+        let data = r#"{"elements": [
+            {"type": "node", "id": 1, "lat": 60.0, "lon": 10.000},
+            {"type": "node", "id": 2, "lat": 60.0, "lon": 10.001},
+            {"type": "node", "id": 3, "lat": 60.0, "lon": 10.002},
+            {"type": "node", "id": 4, "lat": 60.0003, "lon": 10.002},
+            {"type": "way", "id": 10, "nodes": [1, 2, 3], "tags": {"railway": "rail", "name": "Main"}},
+            {"type": "way", "id": 11, "nodes": [2, 4], "tags": {"railway": "rail"}},
+            {"type": "way", "id": 12, "nodes": [1, 4], "tags": {"highway": "road"}}
+        ]}"#;
+        let osm = parse(data.as_bytes()).unwrap();
+        assert_eq!(osm.ways.len(), 2);
+        let topo = to_topology(&osm).unwrap();
+
+        let ids = topo.tracks.iter().map(|t| t.source.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["w10", "w10-1", "w11"]);
+        assert!((topo.tracks[0].length - 55.8).abs() < 0.5, "{}", topo.tracks[0].length);
+        assert_eq!(topo.tracks[0].source.name.as_deref(), Some("Main"));
+
+        let switches = topo.nodes.iter().filter(|n| matches!(n, TopoNode::Switch(Side::Left))).count();
+        let ends = topo.nodes.iter().filter(|n| matches!(n, TopoNode::OpenEnd)).count();
+        assert_eq!((switches, ends, topo.nodes.len()), (1, 3, 4));
+        let port_of = |track :usize, ab :AB| topo.connections.iter()
+            .find(|(end, _)| *end == (track, ab)).map(|(_, (_, port))| *port);
+        assert_eq!(port_of(0, AB::B), Some(Port::Trunk));
+        assert_eq!(port_of(1, AB::A), Some(Port::Right));
+        assert_eq!(port_of(2, AB::A), Some(Port::Left));
+    }
+
+    // A minimal encoder for PBF test files.
+
+    fn varint(mut x :u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        while x >= 0x80 { out.push((x as u8) | 0x80); x >>= 7; }
+        out.push(x as u8);
+        out
+    }
+
+    fn varint_field(n :u64, x :u64) -> Vec<u8> {
+        let mut out = varint(n << 3);
+        out.extend(varint(x));
+        out
+    }
+
+    fn bytes_field(n :u64, bytes :&[u8]) -> Vec<u8> {
+        let mut out = varint(n << 3 | 2);
+        out.extend(varint(bytes.len() as u64));
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn packed_delta_field(n :u64, xs :&[i64]) -> Vec<u8> {
+        let mut prev = 0;
+        let data = xs.iter().flat_map(|x| {
+            let d = x - prev;
+            prev = *x;
+            varint(((d << 1) ^ (d >> 63)) as u64)
+        }).collect::<Vec<_>>();
+        bytes_field(n, &data)
+    }
+
+    fn block(strings :&[&str], groups :&[Vec<u8>]) -> Vec<u8> {
+        let table = strings.iter().flat_map(|s| bytes_field(1, s.as_bytes())).collect::<Vec<_>>();
+        let mut out = bytes_field(1, &table);
+        for g in groups { out.extend(bytes_field(2, g)); }
+        out
+    }
+
+    fn blob(kind :&str, blob :&[u8]) -> Vec<u8> {
+        let mut header = bytes_field(1, kind.as_bytes());
+        header.extend(varint_field(3, blob.len() as u64));
+        let mut out = (header.len() as u32).to_be_bytes().to_vec();
+        out.extend(header);
+        out.extend_from_slice(blob);
+        out
+    }
+
+    /// A header blob, a raw block with the nodes, and a zlib compressed
+    /// block with a railway and a road.
+    fn pbf_fixture() -> Vec<u8> {
+        use std::io::Write;
+        let degrees = |d :f64| (d * 1e7).round() as i64;
+        let dense = [packed_delta_field(1, &[1, 2, 3]),
+                     packed_delta_field(8, &[degrees(60.0); 3]),
+                     packed_delta_field(9, &[degrees(10.0), degrees(10.001), degrees(10.002)])].concat();
+        let nodes = block(&[""], &[bytes_field(2, &dense)]);
+        let way = |id, key, value, refs :&[i64]| bytes_field(3, &[
+            varint_field(1, id), bytes_field(2, &varint(key)), bytes_field(3, &varint(value)),
+            packed_delta_field(8, refs)].concat());
+        let ways = block(&["", "railway", "rail", "highway", "road"],
+                         &[[way(10, 1, 2, &[1, 2, 3]), way(12, 3, 4, &[1, 3])].concat()]);
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&ways).unwrap();
+        let ways = zlib.finish().unwrap();
+
+        [blob("OSMHeader", &bytes_field(1, b"ignored")),
+         blob("OSMData", &bytes_field(1, &nodes)),
+         blob("OSMData", &[varint_field(2, 0), bytes_field(3, &ways)].concat())].concat()
+    }
+
+    #[test]
+    fn railway_from_pbf() {
+        let osm = parse(&pbf_fixture()).unwrap();
+        assert_eq!(osm.ways.len(), 1);
+        assert_eq!(osm.ways[0].id, 10);
+        assert_eq!(osm.ways[0].nodes, vec![1, 2, 3]);
+        assert_eq!(osm.nodes.len(), 3);
+        let (lat, lon) = osm.nodes[&2];
+        assert!((lat - 60.0).abs() < 1e-9 && (lon - 10.001).abs() < 1e-9, "{} {}", lat, lon);
+        let topo = to_topology(&osm).unwrap();
+        assert_eq!(topo.tracks.len(), 1);
+    }
+
+    #[test]
+    fn malformed_pbf() {
+        let data = pbf_fixture();
+        for len in [1, 3, 10, data.len() - 5, data.len() - 1].iter() {
+            assert!(parse_pbf(&data[..*len]).is_err(), "truncated to {} bytes", len);
+        }
+        // Lengths beyond the end of the data.
+        assert!(parse_pbf(&[0xff, 0xff, 0xff, 0xff, 0]).is_err());
+        let huge = [bytes_field(1, b"OSMData"), varint_field(3, u64::MAX)].concat();
+        let mut data = (huge.len() as u32).to_be_bytes().to_vec();
+        data.extend(huge);
+        assert!(parse_pbf(&data).is_err());
+        // Compression which is not supported.
+        assert!(parse_pbf(&blob("OSMData", &bytes_field(4, b"lzma"))).is_err());
+        // Not a zlib stream.
+        assert!(parse_pbf(&blob("OSMData", &bytes_field(3, b"not zlib"))).is_err());
+    }
+}
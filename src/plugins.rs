@@ -0,0 +1,215 @@
+//! Plugins are dynamic libraries in the plugins folder, loaded at startup.
+//! A plugin exports three C functions:
+//!
+//! - `junction_plugin_info() -> *mut c_char` returns a JSON `PluginInfo`
+//!   listing the file formats, checks and context menu actions it provides.
+//! - `junction_plugin_call(request :*const c_char) -> *mut c_char` takes a
+//!   JSON `Request` and returns a JSON `Response`.
+//! - `junction_plugin_free(s :*mut c_char)` frees the strings returned by
+//!   the other two functions.
+//!
+//! Documents are passed in the text save format (see `file::to_text_document`),
+//! and model elements as `{"Node":[x,y]}`, `{"LineSeg":[[x1,y1],[x2,y2]]}` or
+//! `{"Object":[x,y]}`.
+
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::path::Path;
+use log::*;
+use serde::{Serialize, Deserialize};
+
+use crate::config::Config;
+use crate::document::model::{Model, Ref};
+use crate::document::lint::{Issue, IssueKind, Severity};
+use crate::file;
+
+#[derive(Deserialize)]
+#[derive(Debug, Clone)]
+pub struct PluginInfo {
+    pub name :String,
+    #[serde(default)]
+    pub importers :Vec<FileFormat>,
+    #[serde(default)]
+    pub exporters :Vec<FileFormat>,
+    /// The plugin provides model checks.
+    #[serde(default)]
+    pub checks :bool,
+    /// Names of actions on the selection, shown in the context menu.
+    #[serde(default)]
+    pub actions :Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[derive(Debug, Clone)]
+pub struct FileFormat {
+    pub name :String,
+    /// File name patterns for the file dialog, e.g. `*.dxf`.
+    #[serde(default)]
+    pub patterns :Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "hook", rename_all = "snake_case")]
+enum Request<'a> {
+    Import { format :&'a str, filename :&'a str },
+    Export { format :&'a str, filename :&'a str, document :serde_json::Value },
+    Check { document :serde_json::Value },
+    Action { action :&'a str, document :serde_json::Value, selection :Vec<Ref> },
+}
+
+#[derive(Deserialize)]
+#[derive(Default)]
+struct Response {
+    #[serde(default)]
+    error :Option<String>,
+    #[serde(default)]
+    document :Option<serde_json::Value>,
+    #[serde(default)]
+    selection :Option<Vec<Ref>>,
+    #[serde(default)]
+    issues :Vec<PluginIssue>,
+}
+
+#[derive(Deserialize)]
+struct PluginIssue {
+    severity :String,
+    location :Ref,
+    message :String,
+}
+
+type InfoFn = unsafe extern "C" fn() -> *mut c_char;
+type CallFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+pub struct Plugin {
+    pub filename :String,
+    pub info :PluginInfo,
+    lib :libloading::Library,
+}
+
+impl Plugin {
+    pub fn load(filename :&Path) -> Result<Plugin, String> {
+        let lib = unsafe { libloading::Library::new(filename) }.map_err(|e| format!("{}", e))?;
+        let info = unsafe {
+            let info_fn = lib.get::<InfoFn>(b"junction_plugin_info\0").map_err(|e| format!("{}", e))?;
+            let free_fn = lib.get::<FreeFn>(b"junction_plugin_free\0").map_err(|e| format!("{}", e))?;
+            take_string(info_fn(), *free_fn)?
+        };
+        let info = serde_json::from_str(&info).map_err(|e| format!("Invalid plugin info: {}", e))?;
+        Ok(Plugin { filename: filename.to_string_lossy().to_string(), info, lib })
+    }
+
+    fn call(&self, request :&Request) -> Result<Response, String> {
+        let request = CString::new(serde_json::to_string(request).map_err(|e| format!("{}", e))?)
+            .map_err(|e| format!("{}", e))?;
+        let response = unsafe {
+            let call_fn = self.lib.get::<CallFn>(b"junction_plugin_call\0").map_err(|e| format!("{}", e))?;
+            let free_fn = self.lib.get::<FreeFn>(b"junction_plugin_free\0").map_err(|e| format!("{}", e))?;
+            take_string(call_fn(request.as_ptr()), *free_fn)?
+        };
+        let response :Response = serde_json::from_str(&response)
+            .map_err(|e| format!("Invalid response from plugin {}: {}", self.info.name, e))?;
+        match &response.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(response),
+        }
+    }
+
+    pub fn import(&self, format :&str, filename :&str) -> Result<Model, String> {
+        let response = self.call(&Request::Import { format, filename })?;
+        response_model(response.document)
+    }
+
+    pub fn export(&self, format :&str, filename :&str, model :&Model) -> Result<(), String> {
+        self.call(&Request::Export { format, filename, document: document_value(model)? })?;
+        Ok(())
+    }
+
+    pub fn check(&self, model :&Model) -> Result<Vec<Issue>, String> {
+        let response = self.call(&Request::Check { document: document_value(model)? })?;
+        Ok(response.issues.into_iter().map(|i| Issue {
+            severity: match i.severity.to_lowercase().as_str() {
+                "error" => Severity::Error,
+                "warning" => Severity::Warning,
+                _ => Severity::Info,
+            },
+            kind: IssueKind::Plugin,
+            location: i.location,
+            message: format!("{} ({})", i.message, self.info.name),
+        }).collect())
+    }
+
+    /// Run a context menu action. Returns the new model, if the action
+    /// changed it, and the new selection.
+    pub fn action(&self, action :&str, model :&Model, selection :&HashSet<Ref>)
+        -> Result<(Option<Model>, Option<HashSet<Ref>>), String> {
+        let response = self.call(&Request::Action {
+            action,
+            document: document_value(model)?,
+            selection: selection.iter().cloned().collect(),
+        })?;
+        let model = match response.document {
+            Some(d) => Some(response_model(Some(d))?),
+            None => None,
+        };
+        Ok((model, response.selection.map(|s| s.into_iter().collect())))
+    }
+}
+
+unsafe fn take_string(s :*mut c_char, free :FreeFn) -> Result<String, String> {
+    if s.is_null() { return Err("Plugin returned no data".to_string()); }
+    let result = CStr::from_ptr(s).to_str().map(|s| s.to_string()).map_err(|e| format!("{}", e));
+    free(s);
+    result
+}
+
+fn document_value(model :&Model) -> Result<serde_json::Value, String> {
+    serde_json::from_str(&file::to_text_document(model)?).map_err(|e| format!("{}", e))
+}
+
+fn response_model(document :Option<serde_json::Value>) -> Result<Model, String> {
+    let document = document.ok_or("Plugin returned no document")?;
+    file::from_bytes(&serde_json::to_vec(&document).map_err(|e| format!("{}", e))?)
+}
+
+/// The plugins loaded from the plugins folder.
+#[derive(Default)]
+pub struct Plugins {
+    pub plugins :Vec<Plugin>,
+}
+
+impl Plugins {
+    pub fn load() -> Self {
+        let dir = match Config::subdir("plugins") { Some(d) => d, None => return Default::default() };
+        let mut paths = std::fs::read_dir(&dir).into_iter().flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map(|e| e == std::env::consts::DLL_EXTENSION).unwrap_or(false))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut plugins = Vec::new();
+        for path in paths {
+            match Plugin::load(&path) {
+                Ok(p) => {
+                    info!("Loaded plugin {:?} from {:?}", p.info.name, path);
+                    plugins.push(p);
+                },
+                Err(e) => { error!("Could not load plugin {:?}: {}", path, e); },
+            }
+        }
+        Plugins { plugins }
+    }
+
+    /// Run the checks of all plugins which provide them.
+    pub fn check(&self, model :&Model) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for p in self.plugins.iter().filter(|p| p.info.checks) {
+            match p.check(model) {
+                Ok(i) => issues.extend(i),
+                Err(e) => { error!("Plugin {} check failed: {}", p.info.name, e); },
+            }
+        }
+        issues
+    }
+}
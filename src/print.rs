@@ -0,0 +1,648 @@
+//! Print layouts of the infrastructure schematic and of the train graph of
+//! a dispatch, written as PDF, SVG or PNG.
+//!
+//! A sheet is laid out in millimeters on the chosen page: the schematic is
+//! fitted into a frame above a title block, with an optional scale bar and
+//! the trains of a dispatch at a given time. The train graph has position
+//! across and time downwards, as in the dispatch view. The PDF and SVG
+//! writers use the standard Helvetica font, so nothing is embedded. The PNG
+//! writer draws labels with a small stroke font in capitals.
+
+use std::io::{self, Write};
+use serde::{Serialize, Deserialize};
+
+use crate::document::curves;
+use crate::document::dispatch::{DispatchOutput, Instant};
+use crate::document::model::*;
+use crate::document::objects::{symbol_geometry, SymbolPrim};
+use crate::gui::ImVec2;
+use crate::i18n;
+use crate::units::Units;
+
+/// Nominal length of a line segment between grid points, in meters.
+const SEGMENT_LENGTH :f64 = 50.0;
+const MARGIN :f64 = 10.0;
+const TITLE_BLOCK :(f64, f64) = (110.0, 22.0);
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageSize { A4, A3, A2, Letter }
+
+impl PageSize {
+    pub fn all() -> &'static [PageSize] { &[PageSize::A4, PageSize::A3, PageSize::A2, PageSize::Letter] }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PageSize::A4 => "A4",
+            PageSize::A3 => "A3",
+            PageSize::A2 => "A2",
+            PageSize::Letter => "Letter",
+        }
+    }
+
+    /// Width and height in portrait orientation, in millimeters.
+    fn size(&self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::A3 => (297.0, 420.0),
+            PageSize::A2 => (420.0, 594.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PrintFormat { Pdf, Svg, Png }
+
+impl PrintFormat {
+    pub fn all() -> &'static [PrintFormat] { &[PrintFormat::Pdf, PrintFormat::Svg, PrintFormat::Png] }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrintFormat::Pdf => "PDF",
+            PrintFormat::Svg => "SVG",
+            PrintFormat::Png => "PNG",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PrintFormat::Pdf => "pdf",
+            PrintFormat::Svg => "svg",
+            PrintFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PrintSettings {
+    pub page_size :PageSize,
+    pub landscape :bool,
+    pub title :String,
+    pub scale_bar :bool,
+    /// Draw the trains of the shown dispatch, and its time in the title block.
+    pub dispatch_time :bool,
+    /// Resolution of PNG files, in dots per inch.
+    pub dpi :u32,
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        PrintSettings {
+            page_size: PageSize::A3,
+            landscape: true,
+            title: String::new(),
+            scale_bar: true,
+            dispatch_time: false,
+            dpi: 150,
+        }
+    }
+}
+
+/// The dispatch shown when printing, for `PrintSettings::dispatch_time`.
+pub struct DispatchAnnotation<'a> {
+    pub name :String,
+    pub instant :&'a Instant,
+}
+
+type P = (f64, f64);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Rgb(u8, u8, u8);
+
+const BLACK :Rgb = Rgb(0, 0, 0);
+const TRAIN :Rgb = Rgb(200, 30, 30);
+const GRID :Rgb = Rgb(200, 200, 200);
+const RESERVED :Rgb = Rgb(220, 225, 240);
+const OCCUPIED :Rgb = Rgb(170, 180, 215);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mark {
+    /// Line with its width.
+    Line(P, P, f64, Rgb),
+    Disk(P, f64, Rgb),
+    /// Filled rectangle between two corners.
+    Box(P, P, Rgb),
+    /// Text from its left end on the baseline, with the height of capitals.
+    Text(P, f64, String),
+}
+
+/// A laid out page, in millimeters from the top left corner.
+pub struct Sheet {
+    size :P,
+    marks :Vec<Mark>,
+}
+
+impl Sheet {
+    /// An empty page with its frame.
+    fn page(settings :&PrintSettings) -> Sheet {
+        let (w, h) = settings.page_size.size();
+        let (w, h) = if settings.landscape { (h, w) } else { (w, h) };
+        let mut sheet = Sheet { size: (w, h), marks: Vec::new() };
+        sheet.rect((MARGIN, MARGIN), (w - MARGIN, h - MARGIN), 0.5);
+        sheet
+    }
+
+    /// The title block in the bottom right corner, with two lines of notes.
+    fn title_block(&mut self, title :&str, notes :&[String]) {
+        let (w, h) = self.size;
+        let (x0, y0) = (w - MARGIN - TITLE_BLOCK.0, h - MARGIN - TITLE_BLOCK.1);
+        self.rect((x0, y0), (w - MARGIN, h - MARGIN), 0.5);
+        self.line((x0, y0 + 10.0), (w - MARGIN, y0 + 10.0), 0.3);
+        // Long titles are made smaller to fit the title block.
+        let chars = title.chars().count().max(1) as f64;
+        self.text((x0 + 3.0, y0 + 7.0), (4.0f64).min((TITLE_BLOCK.0 - 6.0) / (0.85 * chars)), title.to_string());
+        for (i, note) in notes.iter().take(2).enumerate() {
+            self.text((x0 + 3.0, y0 + 15.0 + 5.0 * i as f64), 2.5, note.clone());
+        }
+    }
+
+    fn line(&mut self, a :P, b :P, width :f64) { self.marks.push(Mark::Line(a, b, width, BLACK)); }
+
+    fn rect(&mut self, a :P, b :P, width :f64) {
+        self.line(a, (b.0, a.1), width);
+        self.line((b.0, a.1), b, width);
+        self.line(b, (a.0, b.1), width);
+        self.line((a.0, b.1), a, width);
+    }
+
+    fn text(&mut self, p :P, height :f64, text :String) { self.marks.push(Mark::Text(p, height, text)); }
+
+    /// An object symbol at `c`, with `k` millimeters per pixel of the
+    /// symbol as drawn on screen.
+    fn symbol(&mut self, c :P, k :f64, prims :&[SymbolPrim]) {
+        let at = |v :ImVec2| (c.0 + k * v.x as f64, c.1 + k * v.y as f64);
+        for prim in prims {
+            match *prim {
+                SymbolPrim::Line(a, b, w) => self.line(at(a), at(b), k * w as f64),
+                SymbolPrim::Rect(a, b, w) => self.rect(at(a), at(b), k * w as f64),
+                SymbolPrim::Triangle(a, b, d, w) => {
+                    self.line(at(a), at(b), k * w as f64);
+                    self.line(at(b), at(d), k * w as f64);
+                    self.line(at(d), at(a), k * w as f64);
+                },
+                SymbolPrim::Circle(a, r, w) => {
+                    let (p, r) = (at(a), k * r as f64);
+                    let pt = |i :usize| {
+                        let angle = i as f64 / 16.0 * 2.0 * std::f64::consts::PI;
+                        (p.0 + r * angle.cos(), p.1 + r * angle.sin())
+                    };
+                    for i in 0..16 { self.line(pt(i), pt(i + 1), k * w as f64); }
+                },
+                SymbolPrim::CircleFilled(a, r) => self.marks.push(Mark::Disk(at(a), k * r as f64, BLACK)),
+            }
+        }
+    }
+}
+
+/// The largest of 1, 2 or 5 times a power of ten that is at most `max`.
+fn nice_length(max :f64) -> f64 {
+    let e = 10f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0].iter().map(|m| m * e).find(|l| *l <= max).unwrap_or(e)
+}
+
+/// Lay out the schematic of the model on a page, with the 45° corners of
+/// the tracks drawn as curves if `smooth_curves` is set.
+pub fn sheet(model :&Model, settings :&PrintSettings, units :&Units, smooth_curves :bool,
+             dispatch :Option<&DispatchAnnotation>) -> Sheet {
+    let mut sheet = Sheet::page(settings);
+    let (w, h) = sheet.size;
+
+    let trains = dispatch.filter(|_| settings.dispatch_time).map(|d| &d.instant.trains[..]).unwrap_or(&[]);
+    let mut pts = Vec::new();
+    for (a, b) in model.linesegs.iter() {
+        pts.push((a.x as f64, a.y as f64));
+        pts.push((b.x as f64, b.y as f64));
+    }
+    pts.extend(model.objects.values().map(|o| (o.loc.x as f64, o.loc.y as f64)));
+    let min = pts.iter().fold((std::f64::INFINITY, std::f64::INFINITY), |m, p| (m.0.min(p.0), m.1.min(p.1)));
+    let max = pts.iter().fold((-std::f64::INFINITY, -std::f64::INFINITY), |m, p| (m.0.max(p.0), m.1.max(p.1)));
+
+    // The schematic is centered in the frame, with y upwards as on screen.
+    let (left, top) = (MARGIN + 5.0, MARGIN + 5.0);
+    let (area_w, area_h) = (w - 2.0 * (MARGIN + 5.0), h - 2.0 * (MARGIN + 5.0) - TITLE_BLOCK.1);
+    let size = ((max.0 - min.0).max(1.0), (max.1 - min.1).max(1.0));
+    let s = (area_w / size.0).min(area_h / size.1).min(20.0);
+    let origin = (left + 0.5 * (area_w - s * size.0), top + 0.5 * (area_h - s * size.1));
+    let map = |x :f64, y :f64| (origin.0 + s * (x - min.0), origin.1 + s * (max.1 - y));
+
+    if !pts.is_empty() {
+        let corners = if smooth_curves { curves::corners(&model.linesegs) } else { Default::default() };
+        for (a, b) in model.linesegs.iter() {
+            let pts = curves::polyline(*a, *b, &corners);
+            for (p, q) in pts.iter().zip(pts.iter().skip(1)) {
+                sheet.line(map(p.x as f64, p.y as f64), map(q.x as f64, q.y as f64), 0.6);
+            }
+        }
+        let mut objects = model.objects.iter().collect::<Vec<_>>();
+        objects.sort_by_key(|(p, _)| (p.x, p.y));
+        let k = (s / 25.0).max(0.05).min(0.3);
+        for (_, obj) in objects {
+            let prims = symbol_geometry(&obj.functions, obj.tangent);
+            sheet.symbol(map(obj.loc.x as f64, obj.loc.y as f64), k, &prims);
+        }
+        for train in trains {
+            for (a, b) in &train.lines {
+                sheet.marks.push(Mark::Line(map(a.x as f64, a.y as f64), map(b.x as f64, b.y as f64), 1.5, TRAIN));
+            }
+        }
+    }
+
+    let scale = (SEGMENT_LENGTH * 1000.0 / s).round();
+    let mut notes = vec![i18n::tr_fmt("Scale 1:{} on {}, {} per grid unit", &[
+        &scale.to_string(), settings.page_size.name(), &units.length(SEGMENT_LENGTH)])];
+    if let Some(d) = dispatch.filter(|_| settings.dispatch_time) {
+        notes.push(i18n::tr_fmt("{} at {} s", &[&d.name, &format!("{:.1}", d.instant.time)]));
+    }
+    sheet.title_block(&settings.title, &notes);
+
+    if settings.scale_bar && !pts.is_empty() {
+        let length = nice_length(50.0 / s * SEGMENT_LENGTH);
+        let bar = length / SEGMENT_LENGTH * s;
+        let (x, y) = (MARGIN + 5.0, h - MARGIN - 8.0);
+        sheet.line((x, y), (x + bar, y), 0.5);
+        for t in &[0.0, 0.5, 1.0] {
+            sheet.line((x + t * bar, y - 1.5), (x + t * bar, y), 0.3);
+        }
+        sheet.text((x, y + 4.0), 2.5, "0".to_string());
+        sheet.text((x + bar, y + 4.0), 2.5, units.length(length));
+    }
+    sheet
+}
+
+/// Lay out the train graph of a dispatch on a page, over the blocks
+/// reserved and occupied by the trains. `name` is the title of the page.
+pub fn train_graph(output :&DispatchOutput, name :&str, settings :&PrintSettings, units :&Units) -> Sheet {
+    let mut sheet = Sheet::page(settings);
+    let (w, h) = sheet.size;
+    let (t0, t1) = (output.time_interval.0 as f64, output.time_interval.1 as f64);
+    let (x0, x1) = (output.pos_interval.0 as f64, output.pos_interval.1 as f64);
+    // Without any train movements there is no position range.
+    let (x0, x1) = if x0 < x1 { (x0, x1) } else { (0.0, 1000.0) };
+    let t1 = t1.max(t0 + 1.0);
+
+    // Room for the time labels to the left and the positions above.
+    let (left, top) = (MARGIN + 22.0, MARGIN + 12.0);
+    let (right, bottom) = (w - MARGIN - 15.0, h - MARGIN - TITLE_BLOCK.1 - 5.0);
+    let map = |t :f64, x :f64| {
+        let x = x.max(x0).min(x1);
+        (left + (right - left) * (x - x0) / (x1 - x0), top + (bottom - top) * (t - t0) / (t1 - t0))
+    };
+
+    for block in &output.diagram.blocks {
+        sheet.marks.push(Mark::Box(map(block.reserved.0, block.pos.0), map(block.reserved.1, block.pos.1), RESERVED));
+        sheet.marks.push(Mark::Box(map(block.occupied.0, block.pos.0), map(block.occupied.1, block.pos.1), OCCUPIED));
+    }
+
+    let time_step = nice_length(20.0 * (t1 - t0) / (bottom - top));
+    for i in (t0 / time_step).ceil() as i64 ..= (t1 / time_step).floor() as i64 {
+        let t = i as f64 * time_step;
+        let y = map(t, x0).1;
+        sheet.marks.push(Mark::Line((left, y), (right, y), 0.1, GRID));
+        sheet.text((MARGIN + 3.0, y + 1.2), 2.5, format!("{} s", t));
+    }
+    let pos_step = nice_length(30.0 * (x1 - x0) / (right - left));
+    for i in (x0 / pos_step).ceil() as i64 ..= (x1 / pos_step).floor() as i64 {
+        let x = map(t0, i as f64 * pos_step).0;
+        sheet.marks.push(Mark::Line((x, top), (x, bottom), 0.1, GRID));
+        sheet.text((x + 0.8, top - 3.0), 2.5, units.length(i as f64 * pos_step));
+    }
+    sheet.rect((left, top), (right, bottom), 0.3);
+
+    for train in &output.diagram.trains {
+        for s in &train.segments {
+            for kms in &[s.kms, s.end_kms] {
+                let pts = (0..4).map(|i| map(s.start_time + i as f64 / 3.0 * s.dt, kms[i])).collect::<Vec<_>>();
+                for p in pts.windows(2) {
+                    sheet.marks.push(Mark::Line(p[0], p[1], 0.4, TRAIN));
+                }
+            }
+        }
+    }
+
+    sheet.title_block(name, &[i18n::tr_fmt("Train graph of {} trains over {} s", &[
+        &output.diagram.trains.len().to_string(), &format!("{:.0}", t1 - t0)])]);
+    sheet
+}
+
+//
+// PDF
+//
+
+/// A PDF string of text in the WinAnsi encoding of the standard fonts,
+/// which agrees with Latin-1 for the letters used in the translations.
+fn pdf_string(text :&str) -> Vec<u8> {
+    let mut out = vec![b'('];
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => { out.push(b'\\'); out.push(c as u8); },
+            c if (c as u32) < 256 => out.push(c as u32 as u8),
+            _ => out.push(b'?'),
+        }
+    }
+    out.push(b')');
+    out
+}
+
+pub fn pdf(sheet :&Sheet) -> Vec<u8> {
+    let k = 72.0 / 25.4;
+    let h = sheet.size.1;
+    let pt = |p :P| format!("{:.2} {:.2}", k * p.0, k * (h - p.1));
+    let rgb = |c :Rgb| format!("{:.3} {:.3} {:.3}", c.0 as f64 / 255.0, c.1 as f64 / 255.0, c.2 as f64 / 255.0);
+
+    let mut content = b"1 J 1 j\n".to_vec();
+    for mark in &sheet.marks {
+        match mark {
+            Mark::Line(a, b, w, c) => {
+                content.extend(format!("{} RG {:.2} w {} m {} l S\n", rgb(*c), k * w, pt(*a), pt(*b)).bytes());
+            },
+            Mark::Box(a, b, c) => {
+                content.extend(format!("{} rg {} {:.2} {:.2} re f\n", rgb(*c), pt((a.0.min(b.0), a.1.max(b.1))),
+                                       k * (b.0 - a.0).abs(), k * (b.1 - a.1).abs()).bytes());
+            },
+            Mark::Disk(p, r, c) => {
+                content.extend(format!("{} rg", rgb(*c)).bytes());
+                for i in 0..16 {
+                    let angle = i as f64 / 16.0 * 2.0 * std::f64::consts::PI;
+                    let q = (p.0 + r * angle.cos(), p.1 + r * angle.sin());
+                    content.extend(format!(" {} {}", pt(q), if i == 0 { "m" } else { "l" }).bytes());
+                }
+                content.extend(b" h f\n");
+            },
+            Mark::Text(p, height, text) => {
+                // Capitals of Helvetica are 0.72 of the font size.
+                content.extend(format!("0 g BT /F1 {:.2} Tf {} Td ", k * height / 0.72, pt(*p)).bytes());
+                content.extend(pdf_string(text));
+                content.extend(b" Tj ET\n");
+            },
+        }
+    }
+
+    let objects :Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>",
+                k * sheet.size.0, k * sheet.size.1).into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        [format!("<< /Length {} >>\nstream\n", content.len()).into_bytes(), content, b"\nendstream".to_vec()].concat(),
+    ];
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n", i + 1).bytes());
+        out.extend(object);
+        out.extend(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).bytes());
+    }
+    out.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).bytes());
+    out
+}
+
+//
+// SVG
+//
+
+fn svg_escape(text :&str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn svg(sheet :&Sheet) -> String {
+    let rgb = |c :Rgb| format!("rgb({},{},{})", c.0, c.1, c.2);
+    let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" \
+                           viewBox=\"0 0 {w} {h}\">\n<rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n",
+                          w = sheet.size.0, h = sheet.size.1);
+    for mark in &sheet.marks {
+        out.push_str(&match mark {
+            Mark::Line(a, b, w, c) => format!("<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" \
+                                               stroke=\"{}\" stroke-width=\"{:.2}\" stroke-linecap=\"round\"/>\n",
+                                              a.0, a.1, b.0, b.1, rgb(*c), w),
+            Mark::Box(a, b, c) => format!("<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>\n",
+                                          a.0.min(b.0), a.1.min(b.1), (b.0 - a.0).abs(), (b.1 - a.1).abs(), rgb(*c)),
+            Mark::Disk(p, r, c) => format!("<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"/>\n",
+                                           p.0, p.1, r, rgb(*c)),
+            // Capitals of Helvetica are 0.72 of the font size.
+            Mark::Text(p, height, text) => format!("<text x=\"{:.2}\" y=\"{:.2}\" \
+                                                    font-family=\"Helvetica, Arial, sans-serif\" \
+                                                    font-size=\"{:.2}\">{}</text>\n",
+                                                   p.0, p.1, height / 0.72, svg_escape(text)),
+        });
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+//
+// PNG
+//
+
+/// Strokes of a capital letter on a grid four units wide and six high,
+/// as polylines of x and y digits separated by `;`.
+fn glyph(c :char) -> &'static str {
+    match c {
+        '0' => "01 05 16 26 35 31 20 10 01;11 35", '1' => "15 26 20;10 30", '2' => "05 16 36 45 44 00 40",
+        '3' => "05 16 36 45 44 33 13;33 42 41 30 10 01", '4' => "30 36 02 42",
+        '5' => "46 06 03 33 42 41 30 00", '6' => "45 36 16 05 01 10 30 41 42 33 03",
+        '7' => "06 46 20", '8' => "13 04 05 16 36 45 44 33 13 02 01 10 30 41 42 33",
+        '9' => "43 13 04 05 16 36 45 41 30 10 01",
+        'A' => "00 04 26 44 40;03 43", 'B' => "00 06 36 45 44 33 03;33 42 41 30 00",
+        'C' => "45 36 16 05 01 10 30 41", 'D' => "00 06 26 44 42 20 00", 'E' => "40 00 06 46;03 33",
+        'F' => "00 06 46;03 33", 'G' => "45 36 16 05 01 10 30 41 43 23", 'H' => "00 06;40 46;03 43",
+        'I' => "10 30;16 36;20 26", 'J' => "46 41 30 10 01", 'K' => "00 06;46 02;13 40",
+        'L' => "06 00 40", 'M' => "00 06 23 46 40", 'N' => "00 06 40 46",
+        'O' => "10 01 05 16 36 45 41 30 10", 'P' => "00 06 36 45 44 33 03",
+        'Q' => "10 01 05 16 36 45 41 30 10;22 40", 'R' => "00 06 36 45 44 33 03;23 40",
+        'S' => "45 36 16 05 04 13 33 42 41 30 10 01", 'T' => "06 46;26 20", 'U' => "06 01 10 30 41 46",
+        'V' => "06 20 46", 'W' => "06 10 23 30 46", 'X' => "00 46;06 40", 'Y' => "06 23 46;23 20",
+        'Z' => "06 46 00 40", 'Æ' => "00 26 46;13 23;26 20 40;23 43",
+        'Ø' => "10 01 05 16 36 45 41 30 10;00 46", 'Å' => "00 03 24 43 40;02 42;15 26 35 24 15",
+        ' ' => "", '.' => "20 21", ',' => "21 10", ':' => "21 22;24 25", '-' => "13 33",
+        '+' => "03 43;21 25", '=' => "02 42;04 44", '/' => "00 46", '(' => "36 24 22 30",
+        ')' => "16 24 22 10", '_' => "00 40", '\'' => "25 24", '!' => "26 22;20 21",
+        _ => "05 16 36 45 44 23 22;20 21",
+    }
+}
+
+struct Canvas {
+    width :usize,
+    height :usize,
+    rgb :Vec<u8>,
+}
+
+impl Canvas {
+    fn blend(&mut self, x :usize, y :usize, c :Rgb, alpha :f64) {
+        let i = 3 * (y * self.width + x);
+        for (j, v) in [c.0, c.1, c.2].iter().enumerate() {
+            let old = self.rgb[i + j] as f64;
+            self.rgb[i + j] = (old + alpha * (*v as f64 - old)).round() as u8;
+        }
+    }
+
+    /// Fill the pixels with their centers in the rectangle between `a` and `b`.
+    fn fill(&mut self, a :P, b :P, c :Rgb) {
+        let px = |v :f64, max :usize| ((v - 0.5).ceil().max(0.0) as usize).min(max);
+        for y in px(a.1.min(b.1), self.height)..px(a.1.max(b.1), self.height) {
+            for x in px(a.0.min(b.0), self.width)..px(a.0.max(b.0), self.width) {
+                self.blend(x, y, c, 1.0);
+            }
+        }
+    }
+
+    /// Cover the pixels closer than `r` to the segment from `a` to `b`,
+    /// with the edge antialiased.
+    fn cover(&mut self, a :P, b :P, r :f64, c :Rgb) {
+        let x0 = (a.0.min(b.0) - r - 1.0).max(0.0) as usize;
+        let y0 = (a.1.min(b.1) - r - 1.0).max(0.0) as usize;
+        let x1 = ((a.0.max(b.0) + r + 1.0).max(0.0) as usize).min(self.width);
+        let y1 = ((a.1.max(b.1) + r + 1.0).max(0.0) as usize).min(self.height);
+        let d = (b.0 - a.0, b.1 - a.1);
+        let len2 = d.0 * d.0 + d.1 * d.1;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = (x as f64 + 0.5 - a.0, y as f64 + 0.5 - a.1);
+                let t = if len2 > 0.0 { ((p.0 * d.0 + p.1 * d.1) / len2).max(0.0).min(1.0) } else { 0.0 };
+                let dist = ((p.0 - t * d.0).powi(2) + (p.1 - t * d.1).powi(2)).sqrt();
+                let alpha = (r + 0.5 - dist).max(0.0).min(1.0);
+                if alpha > 0.0 { self.blend(x, y, c, alpha); }
+            }
+        }
+    }
+
+    fn text(&mut self, p :P, height :f64, text :&str) {
+        let u = height / 6.0;
+        let r = (0.5 * height / 8.0).max(0.5);
+        for (i, c) in text.chars().flat_map(|c| c.to_uppercase()).enumerate() {
+            let x = p.0 + 5.0 * u * i as f64;
+            for stroke in glyph(c).split(';') {
+                let pts = stroke.split_whitespace().filter_map(|xy| {
+                    let mut digits = xy.chars().filter_map(|d| d.to_digit(10));
+                    Some((x + u * digits.next()? as f64, p.1 - u * digits.next()? as f64))
+                }).collect::<Vec<_>>();
+                for w in pts.windows(2) { self.cover(w[0], w[1], r, BLACK); }
+            }
+        }
+    }
+}
+
+fn png_chunk(out :&mut Vec<u8>, kind :&[u8], data :&[u8]) {
+    out.extend(&(data.len() as u32).to_be_bytes());
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend(kind);
+    out.extend(data);
+    out.extend(&crc.sum().to_be_bytes());
+}
+
+pub fn png(sheet :&Sheet, dpi :u32) -> Result<Vec<u8>, io::Error> {
+    let px = dpi as f64 / 25.4;
+    let (width, height) = ((sheet.size.0 * px).round() as usize, (sheet.size.1 * px).round() as usize);
+    let mut canvas = Canvas { width, height, rgb: vec![255; 3 * width * height] };
+    let at = |p :P| (px * p.0, px * p.1);
+    for mark in &sheet.marks {
+        match mark {
+            Mark::Line(a, b, w, c) => canvas.cover(at(*a), at(*b), (0.5 * px * w).max(0.5), *c),
+            Mark::Box(a, b, c) => canvas.fill(at(*a), at(*b), *c),
+            Mark::Disk(p, r, c) => canvas.cover(at(*p), at(*p), px * r, *c),
+            Mark::Text(p, h, text) => canvas.text(at(*p), px * h, text),
+        }
+    }
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    for row in canvas.rgb.chunks(3 * width) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    let data = encoder.finish()?;
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::new();
+    header.extend(&(width as u32).to_be_bytes());
+    header.extend(&(height as u32).to_be_bytes());
+    // 8 bit RGB, default compression and filtering, no interlacing.
+    header.extend(&[8, 2, 0, 0, 0]);
+    png_chunk(&mut out, b"IHDR", &header);
+    png_chunk(&mut out, b"IDAT", &data);
+    png_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+pub fn print_to_file(filename :&str, format :PrintFormat, sheet :&Sheet, dpi :u32) -> Result<(), io::Error> {
+    let data = match format {
+        PrintFormat::Pdf => pdf(sheet),
+        PrintFormat::Svg => svg(sheet).into_bytes(),
+        PrintFormat::Png => png(sheet, dpi)?,
+    };
+    std::fs::write(filename, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::matches;
+    use nalgebra_glm as glm;
+
+    fn model() -> Model {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,1)));
+        model
+    }
+
+    #[test]
+    fn sheet_fits_page() {
+        let settings = PrintSettings { title: "Station".to_string(), ..PrintSettings::default() };
+        let sheet = sheet(&model(), &settings, &Units::default(), false, None);
+        assert_eq!(sheet.size, (420.0, 297.0));
+        for mark in &sheet.marks {
+            if let Mark::Line(a, b, _, _) = mark {
+                for p in &[a, b] {
+                    assert!(p.0 >= MARGIN && p.0 <= 420.0 - MARGIN && p.1 >= MARGIN && p.1 <= 297.0 - MARGIN);
+                }
+            }
+        }
+        assert!(sheet.marks.contains(&Mark::Text((MARGIN + 5.0, 297.0 - MARGIN - 4.0), 2.5, "0".to_string())));
+    }
+
+    #[test]
+    fn pdf_and_png_files() {
+        let settings = PrintSettings { page_size: PageSize::A4, title: "Østby (test)".to_string(),
+                                       dpi: 30, ..PrintSettings::default() };
+        let sheet = sheet(&model(), &settings, &Units::default(), false, None);
+
+        let pdf = pdf(&sheet);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("MediaBox [0 0 841.89 595.28]"));
+        let title = b"(\xd8stby \\(test\\))";
+        assert!(pdf.windows(title.len()).any(|w| w == title));
+        let xref = text.rfind("startxref\n").unwrap();
+        let offset :usize = text[xref + 10..].lines().next().unwrap().parse().unwrap();
+        assert!(pdf[offset..].starts_with(b"xref"));
+
+        let png = png(&sheet, 30).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes([png[16], png[17], png[18], png[19]]), 351);
+        assert_eq!(u32::from_be_bytes([png[20], png[21], png[22], png[23]]), 248);
+    }
+
+    #[test]
+    fn svg_file() {
+        let settings = PrintSettings { page_size: PageSize::A4, landscape: false,
+                                       title: "A & B".to_string(), ..PrintSettings::default() };
+        let svg = svg(&sheet(&model(), &settings, &Units::default(), false, None));
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains("width=\"210mm\" height=\"297mm\" viewBox=\"0 0 210 297\""));
+        assert!(svg.contains(">A &amp; B</text>"));
+        assert_eq!(svg.matches("<line").count(), sheet(&model(), &settings, &Units::default(), false, None)
+                   .marks.iter().filter(|m| matches!(m, Mark::Line(..))).count());
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use log::*;
+use serde::{Serialize, Deserialize};
+
+use crate::document::model::{Model, Vehicle};
+
+/// A project groups several documents with a vehicle library and a color
+/// scheme shared between them. Projects are stored as TOML files which only
+/// reference the documents, so each document can still be opened on its own.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default)]
+pub struct Project {
+    /// Document paths, relative to the project file.
+    #[serde(default)]
+    pub documents :Vec<String>,
+    /// Color scheme as (name, hex color) pairs, see `Config::color_strings`.
+    #[serde(default)]
+    pub colors :Vec<(String,String)>,
+    #[serde(default)]
+    pub vehicles :Vec<Vehicle>,
+}
+
+/// A project opened as the current workspace.
+pub struct OpenProject {
+    pub filename :String,
+    pub project :Project,
+    pub unsaved :bool,
+}
+
+impl OpenProject {
+    pub fn new(filename :String) -> Self {
+        OpenProject { filename, project: Default::default(), unsaved: true }
+    }
+
+    pub fn load(filename :String) -> Result<Self, std::io::Error> {
+        info!("Loading project from {:?}", filename);
+        let project = toml::from_str(&std::fs::read_to_string(&filename)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        Ok(OpenProject { filename, project, unsaved: false })
+    }
+
+    pub fn save(&mut self) -> Result<(), std::io::Error> {
+        info!("Saving project to {:?}", self.filename);
+        let data = toml::to_string(&self.project)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        std::fs::write(&self.filename, data)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    fn dir(&self) -> PathBuf {
+        Path::new(&self.filename).parent().map(|p| p.to_path_buf()).unwrap_or_default()
+    }
+
+    /// Path of a project document, for opening it.
+    pub fn document_path(&self, doc :&str) -> String {
+        self.dir().join(doc).to_string_lossy().to_string()
+    }
+
+    /// Add a document to the project, storing its path relative to the
+    /// project file when it is in the same directory tree.
+    pub fn add_document(&mut self, filename :&str) {
+        let dir = self.dir();
+        let path = Path::new(filename);
+        let rel = path.strip_prefix(&dir).unwrap_or(path).to_string_lossy().to_string();
+        if !self.project.documents.contains(&rel) {
+            self.project.documents.push(rel);
+            self.unsaved = true;
+        }
+    }
+
+    /// Whether the given file is the project document `doc`.
+    pub fn is_document(&self, doc :&str, filename :&str) -> bool {
+        let a = Path::new(&self.document_path(doc)).canonicalize().ok();
+        let b = Path::new(filename).canonicalize().ok();
+        a.is_some() && a == b
+    }
+
+    /// Add the library vehicles that the model does not have, by name.
+    /// Returns the number of vehicles added.
+    pub fn add_vehicles_to(&self, model :&mut Model) -> usize {
+        let mut added = 0;
+        for v in &self.project.vehicles {
+            if !model.vehicles.iter().any(|(_,mv)| mv.name == v.name) {
+                model.vehicles.insert(v.clone());
+                added += 1;
+            }
+        }
+        added
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Serialize, Deserialize};
+use log::*;
+use lazy_static::*;
+use std::io;
+
+use crate::document::model::*;
+use crate::document::dgraph::DGraph;
+use crate::document::interlocking::Interlocking;
+use crate::document::infview::unround_coord;
+use crate::units::Units;
+use rolling::input::staticinfrastructure as rolling_inf;
+
+/// Route table export template, read from a TOML file.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct TableTemplate {
+    pub name :String,
+    #[serde(default = "default_separator")]
+    pub separator :String,
+    #[serde(default = "default_header")]
+    pub header :bool,
+    pub columns :Vec<Column>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Column {
+    /// Column title. `{unit}` is replaced by the configured length unit.
+    pub title :String,
+    pub field :RouteField,
+    /// Number of decimals for numeric fields.
+    #[serde(default)]
+    pub precision :Option<usize>,
+    #[serde(default)]
+    pub prefix :String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RouteField {
+    Index,
+    Entry,
+    Exit,
+    Alternative,
+    Length,
+    Sections,
+    SectionCount,
+    Switches,
+}
+
+fn default_separator() -> String { ",".to_string() }
+fn default_header() -> bool { true }
+
+lazy_static! {
+    pub static ref BUILTIN_TEMPLATES :Vec<TableTemplate> = vec![
+        toml::from_str(include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/route_table_default.toml")))
+            .expect("invalid built-in route table template"),
+        toml::from_str(include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/route_table_no.toml")))
+            .expect("invalid built-in route table template"),
+    ];
+}
+
+pub fn load_template(filename :&str) -> Result<TableTemplate, io::Error> {
+    toml::from_str(&std::fs::read_to_string(filename)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+fn fmt_ref(r :&Ref) -> String {
+    match r {
+        Ref::Node(pt) => format!("boundary ({},{})", pt.x, pt.y),
+        Ref::Object(pta) => {
+            let p = unround_coord(*pta);
+            format!("signal ({:.1},{:.1})", p.x, p.y)
+        },
+        Ref::LineSeg(a,b) => format!("track ({},{})-({},{})", a.x, a.y, b.x, b.y),
+    }
+}
+
+fn fmt_num(x :f64, precision :Option<usize>) -> String {
+    match precision {
+        Some(p) => format!("{:.*}", p, x),
+        None => format!("{}", x),
+    }
+}
+
+fn field_value(col :&Column, idx :usize, il :&Interlocking, dgraph :&DGraph, units :&Units) -> String {
+    let info = &il.routes[idx];
+    let value = match col.field {
+        RouteField::Index => format!("{}", idx+1),
+        RouteField::Entry => fmt_ref(&info.id.from),
+        RouteField::Exit => fmt_ref(&info.id.to),
+        RouteField::Alternative => format!("{}", info.id.alternative+1),
+        RouteField::Length => fmt_num(units.length.from_meters(info.route.length), col.precision),
+        RouteField::Sections => info.route.resources.sections.iter()
+            .map(|s| format!("{}", s)).collect::<Vec<_>>().join(" "),
+        RouteField::SectionCount => format!("{}", info.route.resources.sections.len()),
+        RouteField::Switches => info.route.resources.switch_positions.iter()
+            .map(|(sw,pos)| {
+                let pos = match pos {
+                    rolling_inf::SwitchPosition::Left => "L",
+                    rolling_inf::SwitchPosition::Right => "R",
+                };
+                match dgraph.switch_ids.get_by_left(sw) {
+                    Some(pt) => format!("({},{}){}", pt.x, pt.y, pos),
+                    None => format!("{}{}", sw, pos),
+                }
+            }).collect::<Vec<_>>().join(" "),
+    };
+    format!("{}{}", col.prefix, value)
+}
+
+/// Format the route table using the given template. Lengths are given in `units`.
+pub fn format_table(template :&TableTemplate, il :&Interlocking, dgraph :&DGraph, units :&Units) -> String {
+    let mut out = String::new();
+    if template.header {
+        let titles = template.columns.iter().map(|c| c.title.replace("{unit}", units.length.symbol())).collect::<Vec<_>>();
+        out.push_str(&titles.join(&template.separator));
+        out.push('\n');
+    }
+    for idx in 0..il.routes.len() {
+        let row = template.columns.iter().map(|c| field_value(c, idx, il, dgraph, units)).collect::<Vec<_>>();
+        out.push_str(&row.join(&template.separator));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn export_interactive(template :&TableTemplate, il :&Interlocking, dgraph :&DGraph, units :&Units) -> Result<(), io::Error> {
+    if let Some(filename) = tinyfiledialogs::save_file_dialog("Export route table", "") {
+        std::fs::write(&filename, format_table(template, il, dgraph, units))?;
+        info!("Exported route table to {:?} using template {:?}", filename, template.name);
+    }
+    Ok(())
+}
@@ -0,0 +1,406 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+use log::*;
+use nalgebra_glm as glm;
+use rhai::{Engine, Dynamic, Array, INT, FLOAT};
+
+use crate::document::model::*;
+use crate::document::objects::*;
+use crate::document::infview::{round_coord, unround_coord};
+use crate::util;
+use crate::config::Config;
+
+/// Limit on the number of operations in a script, so that an infinite
+/// loop does not hang the program.
+const MAX_OPERATIONS :u64 = 50_000_000;
+
+pub const SCRIPT_EXTENSION :&str = "rhai";
+
+/// The folder that the script console lists scripts from, next to the
+/// configuration file.
+pub fn scripts_dir() -> Option<PathBuf> {
+    Config::subdir("scripts")
+}
+
+/// Script files in the scripts folder, sorted by name.
+pub fn list_scripts() -> Vec<PathBuf> {
+    let dir = match scripts_dir() { Some(d) => d, None => return Vec::new() };
+    let mut scripts = std::fs::read_dir(&dir).into_iter().flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|e| e == SCRIPT_EXTENSION).unwrap_or(false))
+        .collect::<Vec<_>>();
+    scripts.sort();
+    scripts
+}
+
+/// A model element as seen from a script.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScriptRef(pub Ref);
+
+impl ScriptRef {
+    fn kind(&mut self) -> String {
+        match self.0 {
+            Ref::Node(_) => "node",
+            Ref::LineSeg(_,_) => "track",
+            Ref::Object(_) => "object",
+        }.to_string()
+    }
+
+    fn pos(&self) -> (FLOAT, FLOAT) {
+        match self.0 {
+            Ref::Node(p) | Ref::LineSeg(p,_) => (p.x as _, p.y as _),
+            Ref::Object(p) => { let p = unround_coord(p); (p.x as _, p.y as _) },
+        }
+    }
+
+    fn pos2(&self) -> (FLOAT, FLOAT) {
+        match self.0 {
+            Ref::LineSeg(_,p) => (p.x as _, p.y as _),
+            _ => self.pos(),
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptRef {
+    fn fmt(&self, f :&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Ref::Node(p) => write!(f, "node({},{})", p.x, p.y),
+            Ref::LineSeg(a,b) => write!(f, "track({},{})-({},{})", a.x, a.y, b.x, b.y),
+            Ref::Object(_) => { let (x,y) = self.pos(); write!(f, "object({},{})", x, y) },
+        }
+    }
+}
+
+pub fn function_name(f :&Function) -> &'static str {
+    match f {
+        Function::MainSignal { kind: SignalKind::Main, has_distant: false } => "main signal",
+        Function::MainSignal { kind: SignalKind::Main, has_distant: true } => "combined signal",
+        Function::MainSignal { kind: SignalKind::Combined, .. } => "combined signal",
+        Function::MainSignal { kind: SignalKind::Distant, .. } => "distant signal",
+        Function::MainSignal { kind: SignalKind::Repeater, .. } => "repeater signal",
+        Function::MainSignal { kind: SignalKind::Shunting, .. } => "shunting signal",
+        Function::Detector => "detector",
+        Function::TrackCircuitBorder => "track circuit border",
+        Function::Derailer => "derailer",
+        Function::TrainProtectionElement => "train protection element",
+        Function::TrainProtectionGroup => "train protection group",
+        Function::Balise => "balise",
+        Function::BaliseGroup => "balise group",
+        Function::PlatformEdge => "platform edge",
+        Function::SpeedChange => "speed change",
+        Function::GradientChange => "gradient change",
+        Function::RadiusChange => "radius change",
+        Function::LevelCrossing => "level crossing",
+        Function::CrossSection => "cross section",
+    }
+}
+
+pub fn function_from_name(name :&str) -> Option<Function> {
+    let signal = |kind, has_distant| Some(Function::MainSignal { kind, has_distant });
+    match name {
+        "main signal" | "signal" => signal(SignalKind::Main, false),
+        "combined signal" => signal(SignalKind::Combined, true),
+        "distant signal" => signal(SignalKind::Distant, false),
+        "repeater signal" => signal(SignalKind::Repeater, false),
+        "shunting signal" => signal(SignalKind::Shunting, false),
+        "detector" => Some(Function::Detector),
+        "track circuit border" => Some(Function::TrackCircuitBorder),
+        "derailer" => Some(Function::Derailer),
+        "train protection element" => Some(Function::TrainProtectionElement),
+        "train protection group" => Some(Function::TrainProtectionGroup),
+        "balise" => Some(Function::Balise),
+        "balise group" => Some(Function::BaliseGroup),
+        "platform edge" => Some(Function::PlatformEdge),
+        "speed change" => Some(Function::SpeedChange),
+        "gradient change" => Some(Function::GradientChange),
+        "radius change" => Some(Function::RadiusChange),
+        "level crossing" => Some(Function::LevelCrossing),
+        "cross section" => Some(Function::CrossSection),
+        _ => None,
+    }
+}
+
+pub fn node_type_name(nd :&NDType) -> &'static str {
+    match nd {
+        NDType::OpenEnd => "open end",
+        NDType::BufferStop => "buffer stop",
+        NDType::Cont => "continuation",
+        NDType::Sw(Side::Left) => "switch left",
+        NDType::Sw(Side::Right) => "switch right",
+        NDType::Crossing(CrossingType::Crossover) => "crossover",
+        NDType::Crossing(CrossingType::SingleSlip(Side::Left)) => "single slip left",
+        NDType::Crossing(CrossingType::SingleSlip(Side::Right)) => "single slip right",
+        NDType::Crossing(CrossingType::DoubleSlip) => "double slip",
+        NDType::Err => "error",
+    }
+}
+
+fn node_type_from_name(name :&str) -> Option<NDType> {
+    Some(match name {
+        "open end" => NDType::OpenEnd,
+        "buffer stop" => NDType::BufferStop,
+        "continuation" => NDType::Cont,
+        "switch left" => NDType::Sw(Side::Left),
+        "switch right" => NDType::Sw(Side::Right),
+        "crossover" => NDType::Crossing(CrossingType::Crossover),
+        "single slip left" => NDType::Crossing(CrossingType::SingleSlip(Side::Left)),
+        "single slip right" => NDType::Crossing(CrossingType::SingleSlip(Side::Right)),
+        "double slip" => NDType::Crossing(CrossingType::DoubleSlip),
+        _ => return None,
+    })
+}
+
+/// Model and selection that the script works on, copied back to the
+/// document when the script has finished.
+struct State {
+    model :Model,
+    selection :HashSet<Ref>,
+    changed :bool,
+    output :Vec<String>,
+    issues :Vec<(Ref, String)>,
+}
+
+pub struct ScriptResult {
+    /// The edited model, if the script changed it.
+    pub model :Option<Model>,
+    pub selection :HashSet<Ref>,
+    pub output :Vec<String>,
+    /// Issues reported with `issue(ref, message)`, for custom checks.
+    pub issues :Vec<(Ref, String)>,
+    pub error :Option<String>,
+}
+
+fn refs(it :impl Iterator<Item = Ref>) -> Array {
+    it.map(|r| Dynamic::from(ScriptRef(r))).collect()
+}
+
+fn names<'a>(it :impl Iterator<Item = &'a str>) -> Array {
+    it.map(|n| Dynamic::from(n.to_string())).collect()
+}
+
+/// Run a script on a copy of the model and selection. Edits are applied
+/// all at once by the caller, so that the whole script is a single undo step,
+/// and nothing is changed if the script fails.
+pub fn run(script :&str, model :&Model, selection :&HashSet<Ref>) -> ScriptResult {
+    let state = Rc::new(RefCell::new(State {
+        model: model.clone(),
+        selection: selection.clone(),
+        changed: false,
+        output: Vec::new(),
+        issues: Vec::new(),
+    }));
+
+    let engine = create_engine(&state);
+    let error = engine.consume(script).err().map(|e| format!("{}", e));
+    if let Some(e) = &error { warn!("Script failed: {}", e); }
+    drop(engine);
+
+    let state = Rc::try_unwrap(state).ok().expect("script engine dropped").into_inner();
+    ScriptResult {
+        model: if state.changed && error.is_none() { Some(state.model) } else { None },
+        selection: if error.is_none() { state.selection } else { selection.clone() },
+        output: state.output,
+        issues: state.issues,
+        error,
+    }
+}
+
+fn create_engine(state :&Rc<RefCell<State>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let s = state.clone();
+    engine.on_print(move |x| s.borrow_mut().output.push(x.to_string()));
+    let s = state.clone();
+    engine.on_debug(move |x| s.borrow_mut().output.push(x.to_string()));
+
+    // References to model elements
+    engine.register_type_with_name::<ScriptRef>("Ref");
+    engine.register_get("kind", ScriptRef::kind);
+    engine.register_get("x", |r :&mut ScriptRef| r.pos().0);
+    engine.register_get("y", |r :&mut ScriptRef| r.pos().1);
+    engine.register_get("x2", |r :&mut ScriptRef| r.pos2().0);
+    engine.register_get("y2", |r :&mut ScriptRef| r.pos2().1);
+    engine.register_fn("to_string", |r :&mut ScriptRef| r.to_string());
+    engine.register_fn("==", |a :ScriptRef, b :ScriptRef| a == b);
+    engine.register_fn("!=", |a :ScriptRef, b :ScriptRef| a != b);
+
+    // Model queries
+    let s = state.clone();
+    engine.register_fn("nodes", move || {
+        let s = s.borrow();
+        let mut pts = s.model.linesegs.iter().flat_map(|(a,b)| vec![*a, *b]).collect::<Vec<_>>();
+        pts.sort_by_key(|p| (p.x, p.y));
+        pts.dedup();
+        refs(pts.into_iter().map(Ref::Node))
+    });
+    let s = state.clone();
+    engine.register_fn("tracks", move || {
+        refs(s.borrow().model.linesegs.iter().map(|(a,b)| Ref::LineSeg(*a, *b)))
+    });
+    let s = state.clone();
+    engine.register_fn("objects", move || {
+        refs(s.borrow().model.objects.keys().map(|p| Ref::Object(*p)))
+    });
+    let s = state.clone();
+    engine.register_fn("node_type", move |r :ScriptRef| {
+        match r.0 {
+            Ref::Node(p) => s.borrow().model.node_data.get(&p).map(node_type_name).unwrap_or("").to_string(),
+            _ => String::new(),
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("object_functions", move |r :ScriptRef| {
+        match r.0 {
+            Ref::Object(p) => names(s.borrow().model.objects.get(&p).into_iter()
+                                    .flat_map(|o| o.functions.iter().map(function_name))),
+            _ => Array::new(),
+        }
+    });
+
+    // Selection
+    let s = state.clone();
+    engine.register_fn("selection", move || refs(s.borrow().selection.iter().cloned()));
+    let s = state.clone();
+    engine.register_fn("select", move |a :Array| {
+        s.borrow_mut().selection = a.into_iter().filter_map(|d| d.try_cast::<ScriptRef>()).map(|r| r.0).collect();
+    });
+    let s = state.clone();
+    engine.register_fn("select", move |r :ScriptRef| { s.borrow_mut().selection.insert(r.0); });
+    let s = state.clone();
+    engine.register_fn("clear_selection", move || s.borrow_mut().selection.clear());
+
+    // Model edits
+    let s = state.clone();
+    engine.register_fn("add_track", move |x1 :INT, y1 :INT, x2 :INT, y2 :INT| {
+        let mut s = s.borrow_mut();
+        let (p1, p2) = (glm::vec2(x1 as i32, y1 as i32), glm::vec2(x2 as i32, y2 as i32));
+        for (a,b) in util::route_line(p1, p2) {
+            let unit = util::unit_step_diag_line(a, b);
+            for (pa,pb) in unit.iter().zip(unit.iter().skip(1)) {
+                s.model.linesegs.insert(util::order_ivec(*pa, *pb));
+            }
+        }
+        s.changed = true;
+    });
+    let s = state.clone();
+    engine.register_fn("set_node_type", move |r :ScriptRef, name :&str| -> bool {
+        let mut s = s.borrow_mut();
+        match (r.0, node_type_from_name(name)) {
+            (Ref::Node(p), Some(nd)) => {
+                s.model.node_data.insert(p, nd);
+                s.changed = true;
+                true
+            },
+            _ => false,
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("add_object", move |x :FLOAT, y :FLOAT, name :&str| -> Dynamic {
+        let mut s = s.borrow_mut();
+        let function = match function_from_name(name) { Some(f) => f, None => return Dynamic::UNIT };
+        let mut obj = Object { loc: glm::vec2(0.0, 0.0), tangent: glm::vec2(1, 0), functions: vec![function] };
+        if obj.move_to(&s.model, glm::vec2(x as f32, y as f32)).is_some() {
+            return Dynamic::UNIT; // not on a track
+        }
+        let pta = round_coord(obj.loc);
+        s.model.objects.insert(pta, obj);
+        s.changed = true;
+        Dynamic::from(ScriptRef(Ref::Object(pta)))
+    });
+    let s = state.clone();
+    engine.register_fn("move_object", move |r :ScriptRef, x :FLOAT, y :FLOAT| -> Dynamic {
+        let mut s = s.borrow_mut();
+        let pta = match r.0 { Ref::Object(p) => p, _ => return Dynamic::UNIT };
+        let mut obj = match s.model.objects.get(&pta) { Some(o) => o.clone(), None => return Dynamic::UNIT };
+        if obj.move_to(&s.model, glm::vec2(x as f32, y as f32)).is_some() { return Dynamic::UNIT; }
+        let new_pta = round_coord(obj.loc);
+        s.model.objects.remove(&pta);
+        s.model.objects.insert(new_pta, obj);
+        if s.selection.remove(&r.0) { s.selection.insert(Ref::Object(new_pta)); }
+        s.changed = true;
+        Dynamic::from(ScriptRef(Ref::Object(new_pta)))
+    });
+    let s = state.clone();
+    engine.register_fn("remove", move |r :ScriptRef| -> bool {
+        let mut s = s.borrow_mut();
+        let removed = match r.0 {
+            Ref::Node(p) => s.model.node_data.remove(&p).is_some(),
+            Ref::LineSeg(a,b) => s.model.linesegs.remove(&(a,b)).is_some(),
+            Ref::Object(p) => s.model.objects.remove(&p).is_some(),
+        };
+        s.selection.remove(&r.0);
+        s.changed |= removed;
+        removed
+    });
+
+    // Names of vehicles, dispatches and plans, in list order
+    let s = state.clone();
+    engine.register_fn("vehicles", move || names(s.borrow().model.vehicles.iter().map(|(_,v)| v.name.as_str())));
+    let s = state.clone();
+    engine.register_fn("dispatches", move || names(s.borrow().model.dispatches.iter().map(|(_,d)| d.name.as_str())));
+    let s = state.clone();
+    engine.register_fn("plans", move || names(s.borrow().model.plans.iter().map(|(_,p)| p.name.as_str())));
+    let s = state.clone();
+    engine.register_fn("rename_vehicle", move |idx :INT, name :&str| -> bool {
+        let mut s = s.borrow_mut();
+        let ok = s.model.vehicles.iter_mut().nth(idx as usize).map(|(_,v)| v.name = name.to_string()).is_some();
+        s.changed |= ok;
+        ok
+    });
+    let s = state.clone();
+    engine.register_fn("rename_dispatch", move |idx :INT, name :&str| -> bool {
+        let mut s = s.borrow_mut();
+        let ok = s.model.dispatches.iter_mut().nth(idx as usize).map(|(_,d)| d.name = name.to_string()).is_some();
+        s.changed |= ok;
+        ok
+    });
+    let s = state.clone();
+    engine.register_fn("rename_plan", move |idx :INT, name :&str| -> bool {
+        let mut s = s.borrow_mut();
+        let ok = s.model.plans.iter_mut().nth(idx as usize).map(|(_,p)| p.name = name.to_string()).is_some();
+        s.changed |= ok;
+        ok
+    });
+
+    // Custom checks
+    let s = state.clone();
+    engine.register_fn("issue", move |r :ScriptRef, message :&str| {
+        s.borrow_mut().issues.push((r.0, message.to_string()));
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_edits_are_applied_together() {
+        let script = r#"
+            add_track(0, 0, 4, 0);
+            let d = add_object(2.0, 0.0, "detector");
+            select(d);
+            for t in tracks() { if t.x == 0.0 { issue(t, "first track"); } }
+            print(objects().len());
+        "#;
+        let result = run(script, &Model::default(), &HashSet::new());
+        assert!(result.error.is_none(), "{:?}", result.error);
+        let model = result.model.expect("model was changed");
+        assert_eq!(model.linesegs.len(), 4);
+        assert_eq!(model.objects.len(), 1);
+        assert_eq!(result.selection.len(), 1);
+        assert_eq!(result.issues.len(), 1);
+        assert_eq!(result.output, vec!["1".to_string()]);
+    }
+
+    #[test]
+    fn failed_script_changes_nothing() {
+        let result = run("add_track(0,0,2,0); undefined_function();", &Model::default(), &HashSet::new());
+        assert!(result.error.is_some());
+        assert!(result.model.is_none());
+    }
+}
@@ -0,0 +1,285 @@
+//! HTTP and WebSocket API for mirroring the document and simulation in
+//! other programs. The HTTP server answers
+//!
+//! - `GET /model`: the document in the text save format,
+//! - `GET /state`: the shown dispatch, its time and the train positions,
+//!
+//! and WebSocket clients connecting to the next port receive the state
+//! as it changes, and a `model_changed` message after each edit.
+//!
+//! Requests from web pages carry an `Origin` header, and are only answered
+//! for the origins allowed in the settings, so that other pages open in the
+//! user's browser cannot read the document. Programs not running in a
+//! browser send no `Origin` and are always answered.
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use log::*;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::document::{Document, DispatchView};
+use crate::document::analysis::Generation;
+use crate::document::model::Model;
+use crate::file;
+
+/// Minimum time between state messages to WebSocket clients.
+const STATE_INTERVAL :Duration = Duration::from_millis(100);
+
+/// The latest model and state. The model is serialized by the HTTP thread
+/// when it is first requested after an edit, not on the UI thread.
+#[derive(Default)]
+struct Snapshot {
+    model :Option<(Generation, Model)>,
+    model_text :Option<(Generation, Arc<String>)>,
+    state :String,
+}
+
+/// The latest model in the text save format. The lock is not held while
+/// serializing, so that the UI thread is not kept waiting.
+fn model_text(snapshot :&Mutex<Snapshot>) -> Arc<String> {
+    let (generation, model) = {
+        let s = snapshot.lock().unwrap();
+        match (&s.model, &s.model_text) {
+            (Some((g, _)), Some((tg, text))) if g == tg => return text.clone(),
+            (Some((g, model)), _) => (*g, model.clone()),
+            (None, _) => return Arc::new(String::new()),
+        }
+    };
+    let text = Arc::new(file::to_text_document(&model).unwrap_or_else(|e| {
+        error!("API server could not serialize the model: {}", e);
+        String::new()
+    }));
+    snapshot.lock().unwrap().model_text = Some((generation, text.clone()));
+    text
+}
+
+#[derive(Serialize, PartialEq)]
+struct State {
+    generation :Generation,
+    dispatch :Option<DispatchState>,
+    trains :Vec<TrainState>,
+}
+
+#[derive(Serialize, PartialEq)]
+struct DispatchState {
+    /// "manual" or "plan"
+    kind :&'static str,
+    name :String,
+    plan :Option<String>,
+    time :f64,
+    playing :bool,
+}
+
+#[derive(Serialize, PartialEq)]
+struct TrainState {
+    front :Option<(f32,f32)>,
+    /// Track segments occupied by the train, front last.
+    lines :Vec<((f32,f32),(f32,f32))>,
+}
+
+pub struct ApiServer {
+    pub port :u16,
+    snapshot :Arc<Mutex<Snapshot>>,
+    http :Arc<tiny_http::Server>,
+    stop :Arc<AtomicBool>,
+    broadcast :mpsc::Sender<String>,
+    model_generation :Option<Generation>,
+    last_state :Option<State>,
+    last_sent :Option<std::time::Instant>,
+}
+
+impl ApiServer {
+    /// Listen for HTTP on `port` and for WebSocket clients on the next
+    /// port, answering web pages from the `origins` only.
+    pub fn start(port :u16, origins :Vec<String>) -> Result<ApiServer, String> {
+        let ws_port = port.checked_add(1).ok_or_else(|| format!("no WebSocket port after port {}", port))?;
+        let http = Arc::new(tiny_http::Server::http(("127.0.0.1", port)).map_err(|e| format!("{}", e))?);
+        let ws = TcpListener::bind(("127.0.0.1", ws_port)).map_err(|e| format!("{}", e))?;
+        let origins = Arc::new(origins);
+        ws.set_nonblocking(true).map_err(|e| format!("{}", e))?;
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (broadcast, rx) = mpsc::channel();
+
+        {
+            let http = http.clone();
+            let (snapshot, origins) = (snapshot.clone(), origins.clone());
+            std::thread::spawn(move || serve_http(&http, &snapshot, &origins));
+        }
+        {
+            let (clients, stop) = (clients.clone(), stop.clone());
+            std::thread::spawn(move || accept_websockets(ws, &clients, &stop, &origins));
+        }
+        std::thread::spawn(move || {
+            // Ends when the server is dropped and the sender with it.
+            for msg in rx {
+                let mut clients = clients.lock().unwrap();
+                let mut i = 0;
+                while i < clients.len() {
+                    if clients[i].write_message(tungstenite::Message::Text(msg.clone())).is_ok() {
+                        i += 1;
+                    } else {
+                        clients.remove(i);
+                    }
+                }
+            }
+        });
+
+        info!("API server listening on http://127.0.0.1:{} and ws://127.0.0.1:{}", port, ws_port);
+        Ok(ApiServer {
+            port, snapshot, http, stop, broadcast,
+            model_generation: None,
+            last_state: None,
+            last_sent: None,
+        })
+    }
+
+    /// Publish the current document and simulation state. Called every frame.
+    pub fn update(&mut self, doc :&Document) {
+        let generation = *doc.analysis.generation();
+        if self.model_generation != Some(generation) {
+            self.model_generation = Some(generation);
+            self.snapshot.lock().unwrap().model = Some((generation, doc.analysis.model().clone()));
+            let _ = self.broadcast.send(json!({ "type": "model_changed", "generation": generation }).to_string());
+        }
+
+        if self.last_sent.map(|t| t.elapsed() < STATE_INTERVAL).unwrap_or(false) { return; }
+        let state = current_state(doc);
+        if self.last_state.as_ref() == Some(&state) { return; }
+        let text = serde_json::to_string(&state).unwrap();
+        let _ = self.broadcast.send(json!({ "type": "state", "state": &state }).to_string());
+        self.snapshot.lock().unwrap().state = text;
+        self.last_state = Some(state);
+        self.last_sent = Some(std::time::Instant::now());
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        info!("Stopping API server");
+        self.stop.store(true, Ordering::SeqCst);
+        self.http.unblock();
+    }
+}
+
+fn current_state(doc :&Document) -> State {
+    let model = doc.analysis.model();
+    let dispatch = match &doc.dispatch_view {
+        Some(DispatchView::Manual(m)) => Some(DispatchState {
+            kind: "manual",
+            name: model.dispatches.get(m.dispatch_idx).map(|d| d.name.clone()).unwrap_or_default(),
+            plan: None,
+            time: m.time,
+            playing: m.play,
+        }),
+        Some(DispatchView::Auto(a)) => a.dispatch.as_ref().map(|m| DispatchState {
+            kind: "plan",
+            // Plan dispatches are unnamed alternatives.
+            name: format!("Dispatch {}", m.dispatch_idx + 1),
+            plan: model.plans.get(a.plan_idx).map(|p| p.name.clone()),
+            time: m.time,
+            playing: m.play,
+        }),
+        None => None,
+    };
+
+    let instant = doc.dispatch_view.as_ref().and_then(|d| d.dispatch_ref())
+        .and_then(|r| doc.inf_view.instant_cache.get(r));
+    let trains = instant.map(|i| i.trains.iter().map(|t| TrainState {
+        front: t.get_front().map(|p| (p.x, p.y)),
+        lines: t.lines.iter().map(|(a,b)| ((a.x, a.y), (b.x, b.y))).collect(),
+    }).collect()).unwrap_or_default();
+
+    State { generation: *doc.analysis.generation(), dispatch, trains }
+}
+
+/// Whether a request with the given `Origin` header may be answered.
+fn origin_allowed(origin :Option<&str>, origins :&[String]) -> bool {
+    match origin {
+        None => true,
+        Some(o) => origins.iter().any(|allowed| allowed.trim_end_matches('/') == o),
+    }
+}
+
+fn serve_http(http :&tiny_http::Server, snapshot :&Mutex<Snapshot>, origins :&[String]) {
+    // `recv` fails when the server is unblocked on drop.
+    while let Ok(request) = http.recv() {
+        let origin = request.headers().iter()
+            .find(|h| h.field.equiv("Origin"))
+            .map(|h| h.value.as_str().to_string());
+        let response = if !origin_allowed(origin.as_deref(), origins) {
+            warn!("API server refused a request from {:?}", origin);
+            tiny_http::Response::from_string("Origin not allowed").with_status_code(403)
+        } else {
+            let body = match (request.method(), request.url()) {
+                (tiny_http::Method::Get, "/model") => Some((*model_text(snapshot)).clone()),
+                (tiny_http::Method::Get, "/state") => Some(snapshot.lock().unwrap().state.clone()),
+                _ => None,
+            };
+            let response = match body {
+                Some(body) => tiny_http::Response::from_string(body)
+                    .with_header(header("Content-Type", "application/json")),
+                None => tiny_http::Response::from_string("Not found").with_status_code(404),
+            };
+            match &origin {
+                Some(o) => response.with_header(header("Access-Control-Allow-Origin", o)),
+                None => response,
+            }
+        };
+        if let Err(e) = request.respond(response) {
+            debug!("API server could not respond: {}", e);
+        }
+    }
+}
+
+fn header(name :&str, value :&str) -> tiny_http::Header {
+    tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn accept_websockets(listener :TcpListener, clients :&Mutex<Vec<tungstenite::WebSocket<TcpStream>>>,
+                     stop :&AtomicBool, origins :&[String]) {
+    use tungstenite::handshake::server::{Request, Response, ErrorResponse};
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                let _ = stream.set_nonblocking(false);
+                let check_origin = |request :&Request, response :Response| -> Result<Response, ErrorResponse> {
+                    let origin = request.headers().get("Origin").and_then(|o| o.to_str().ok());
+                    if origin_allowed(origin, origins) { return Ok(response); }
+                    let mut refused = ErrorResponse::new(Some("Origin not allowed".to_string()));
+                    *refused.status_mut() = tungstenite::http::StatusCode::FORBIDDEN;
+                    Err(refused)
+                };
+                match tungstenite::accept_hdr(stream, check_origin) {
+                    Ok(ws) => {
+                        info!("API WebSocket client connected from {}", addr);
+                        clients.lock().unwrap().push(ws);
+                    },
+                    Err(e) => { warn!("WebSocket handshake with {} failed: {}", addr, e); },
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+            },
+            Err(e) => { error!("API server stopped accepting WebSocket clients: {}", e); return; },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins() {
+        let allowed = vec!["http://localhost:8080/".to_string()];
+        assert!(origin_allowed(None, &allowed));
+        assert!(origin_allowed(Some("http://localhost:8080"), &allowed));
+        assert!(!origin_allowed(Some("https://example.com"), &allowed));
+        assert!(!origin_allowed(Some("http://localhost:8080"), &[]));
+    }
+}
@@ -0,0 +1,203 @@
+use log::*;
+use serde::{Serialize, Deserialize};
+
+use crate::app::App;
+use crate::document::{Document, DispatchView, ManualDispatchView, AutoDispatchView, PlanViewAction};
+use crate::file;
+use crate::project::OpenProject;
+
+/// The state of the program when it was last closed, stored next to the
+/// config file and restored on the next launch.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+pub struct Session {
+    // Plain values come before tables, as required by TOML.
+    /// The active document.
+    pub document :Option<String>,
+    /// The documents open in tabs, in tab order. Documents which were
+    /// never saved are left out.
+    #[serde(default)]
+    pub tabs :Vec<String>,
+    /// Index of the active document in `tabs`.
+    #[serde(default)]
+    pub active_tab :usize,
+    pub project :Option<String>,
+    pub diagram_split :Option<f32>,
+    #[serde(default)]
+    pub windows :SessionWindows,
+    pub camera :Option<SessionCamera>,
+    pub dispatch_view :Option<SessionDispatchView>,
+}
+
+/// Scale and translation of the infrastructure view.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct SessionCamera {
+    pub scale :usize,
+    pub x :f32,
+    pub y :f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+pub struct SessionWindows {
+    pub config :bool,
+    pub log :bool,
+    pub vehicles :bool,
+    pub stats :bool,
+    pub detector_placement :bool,
+    pub project :bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub enum SessionDispatchView {
+    Manual { dispatch_idx :usize, time :f64 },
+    Auto { plan_idx :usize, dispatch_idx :Option<usize>, time :f64 },
+}
+
+const SESSION_NAME :&str = concat!(env!("CARGO_PKG_NAME"), "-session");
+
+impl Session {
+    pub fn load() -> Self {
+        confy::load(SESSION_NAME).unwrap_or_else(|e| {
+            error!("Could not load session: {}", e);
+            Default::default()
+        })
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = confy::store(SESSION_NAME, self) {
+            error!("Could not save session: {}", e);
+        }
+    }
+
+    pub fn from_app(app :&App) -> Self {
+        let w = &app.windows;
+        let mut tabs = Vec::new();
+        let mut active_tab = 0;
+        for tab in 0..app.tabs.len() {
+            if let Some(filename) = &app.tabs.get(&app.document, tab).fileinfo.filename {
+                if tab == app.tabs.active { active_tab = tabs.len(); }
+                tabs.push(filename.clone());
+            }
+        }
+        Session {
+            document: app.document.fileinfo.filename.clone(),
+            tabs,
+            active_tab,
+            project: app.project.as_ref().map(|p| p.filename.clone()),
+            windows: SessionWindows {
+                config: w.config,
+                log: w.log,
+                vehicles: w.vehicles,
+                stats: w.stats,
+                detector_placement: w.detector_placement,
+                project: w.project,
+            },
+            diagram_split: w.diagram_split,
+            camera: { 
+                let (scale, x, y) = app.document.inf_view.view.camera();
+                Some(SessionCamera { scale, x, y })
+            },
+            dispatch_view: app.document.dispatch_view.as_ref().map(|v| match v {
+                DispatchView::Manual(m) => SessionDispatchView::Manual {
+                    dispatch_idx: m.dispatch_idx, time: m.time },
+                DispatchView::Auto(a) => SessionDispatchView::Auto {
+                    plan_idx: a.plan_idx,
+                    dispatch_idx: a.dispatch.as_ref().map(|m| m.dispatch_idx),
+                    time: a.dispatch.as_ref().map(|m| m.time).unwrap_or(0.0) },
+            }),
+        }
+    }
+
+    /// Reopen the session's project and documents and restore the view of
+    /// the active document. Files which can no longer be read are skipped.
+    pub fn restore(self, app :&mut App) {
+        self.restore_windows(app);
+        // Sessions saved before tabs have only the active document.
+        let (files, active) = if self.tabs.is_empty() {
+            (self.document.into_iter().collect(), 0)
+        } else { (self.tabs, self.active_tab) };
+
+        let mut active_tab = None;
+        for (i, filename) in files.into_iter().enumerate() {
+            let doc = match load_document(app, filename) { Some(d) => d, None => continue };
+            if app.document.fileinfo.filename.is_none() {
+                app.document = doc;
+            } else {
+                app.tabs.open(&mut app.document, doc);
+            }
+            if i == active { active_tab = Some(app.tabs.active); }
+        }
+        // The view belongs to the active document, and is not restored
+        // if it could not be opened.
+        let active_tab = match active_tab { Some(t) => t, None => return };
+        app.tabs.select(&mut app.document, active_tab);
+        if let Some(c) = self.camera {
+            app.document.inf_view.view.set_camera((c.scale, c.x, c.y));
+        }
+
+        // The view indices are checked when drawing, so stale ones just show nothing.
+        app.document.dispatch_view = self.dispatch_view.map(|v| match v {
+            SessionDispatchView::Manual { dispatch_idx, time } => {
+                let mut m = ManualDispatchView::new(dispatch_idx);
+                m.time = time;
+                DispatchView::Manual(m)
+            },
+            SessionDispatchView::Auto { plan_idx, dispatch_idx, time } => DispatchView::Auto(AutoDispatchView {
+                plan_idx,
+                action: PlanViewAction::None,
+                dispatch: dispatch_idx.map(|idx| {
+                    let mut m = ManualDispatchView::new(idx);
+                    m.time = time;
+                    m
+                }),
+            }),
+        });
+    }
+
+    /// Reopen the session's project and restore the open windows.
+    pub fn restore_windows(&self, app :&mut App) {
+        if let Some(filename) = &self.project {
+            match OpenProject::load(filename.clone()) {
+                Ok(p) => {
+                    if !p.project.colors.is_empty() { app.config.set_color_strings(&p.project.colors); }
+                    app.project = Some(p);
+                },
+                Err(e) => { warn!("Could not restore project: {}", e); },
+            }
+        }
+
+        let w = &mut app.windows;
+        w.config = self.windows.config;
+        w.log = self.windows.log;
+        w.vehicles = self.windows.vehicles;
+        w.stats = self.windows.stats;
+        w.detector_placement = self.windows.detector_placement;
+        w.project = self.windows.project;
+        w.diagram_split = self.diagram_split;
+    }
+}
+
+/// Open a document at startup, before the window is created. Returns false
+/// if the file could not be read.
+pub fn open_document(app :&mut App, filename :String) -> bool {
+    match load_document(app, filename) {
+        Some(doc) => { app.document = doc; true },
+        None => false,
+    }
+}
+
+fn load_document(app :&App, filename :String) -> Option<Document> {
+    let (model, history) = match file::load_document(&filename) {
+        Ok(m) => m,
+        Err(e) => { error!("Could not open document {:?}: {}", filename, e); return None; },
+    };
+    info!("Opening document {:?}", filename);
+    let mut doc = Document::from_saved(model, history, app.background_jobs.clone());
+    // The window does not exist yet, so the title is set when it is created.
+    doc.fileinfo.modified = file::modified_time(&filename);
+    doc.fileinfo.filename = Some(filename);
+    Some(doc)
+}
@@ -0,0 +1,328 @@
+//! Export to a SUMO network file (`.net.xml`) for microscopic simulation.
+//!
+//! Each track is split at its main signals and speed changes. The pieces
+//! become pairs of edges, one for each direction, marked as each other's
+//! `bidi` edge. Track ends become junctions connecting the edges through
+//! the switch and crossing ports, and signals become `rail_signal`
+//! junctions with a signal plan (`tlLogic`) controlling the links in the
+//! signal's direction. Coordinates are scaled so that one grid unit is one
+//! default segment length; the lane lengths are the model lengths.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use log::*;
+use nalgebra_glm as glm;
+
+use crate::document::model::*;
+use crate::document::objects::Function;
+use crate::document::topology::{self, Topology};
+
+/// Length of a line segment between grid points, in meters.
+const SEGMENT_LENGTH :f64 = 50.0;
+/// Track split points are kept at least this far from the track ends.
+const END_MARGIN :f64 = 1.0;
+
+#[derive(Debug, PartialEq)]
+enum JunctionKind { DeadEnd, Rail, Signal }
+
+impl JunctionKind {
+    fn name(&self) -> &'static str {
+        match self {
+            JunctionKind::DeadEnd => "dead_end",
+            JunctionKind::Rail => "priority",
+            JunctionKind::Signal => "rail_signal",
+        }
+    }
+}
+
+struct Junction {
+    pos :(f64,f64),
+    kind :JunctionKind,
+    incoming :Vec<String>,
+}
+
+struct Edge {
+    id :String,
+    from :String,
+    to :String,
+    bidi :String,
+    speed :f64,
+    length :f64,
+    shape :Vec<(f64,f64)>,
+}
+
+struct Connection {
+    from :String,
+    to :String,
+    junction :String,
+    /// Controlled by the signal at the junction.
+    signal :bool,
+}
+
+fn node_id(pt :Pt) -> String { format!("n{}_{}", pt.x, pt.y) }
+
+fn coords(p :glm::Vec2) -> (f64,f64) {
+    (p.x as f64 * SEGMENT_LENGTH, p.y as f64 * SEGMENT_LENGTH)
+}
+
+fn point_at(topo :&Topology, track :usize, pos :f64) -> (f64,f64) {
+    topo.interval_map(track, pos, pos).first().map(|p| coords(*p)).unwrap_or((0.0, 0.0))
+}
+
+fn fmt_shape(shape :&[(f64,f64)]) -> String {
+    shape.iter().map(|(x,y)| format!("{:.2},{:.2}", x, y)).collect::<Vec<_>>().join(" ")
+}
+
+/// Speed limit (m/s) of a speed change object, from its imported railML data.
+fn speed_limit(model :&Model, pta :&PtA) -> Option<f64> {
+    model.railml_objects.get(pta)?.iter().find_map(|info| match info {
+        RailMLObjectInfo::SpeedChange { vmax: Some(v), .. } => v.trim().parse::<f64>().ok().map(|kmh| kmh / 3.6),
+        _ => None,
+    })
+}
+
+/// Split points of a track: the position, and the signal direction for
+/// main signals. Speed limits apply in both directions, from their position
+/// in the track direction.
+fn split_points(model :&Model, topo :&Topology, track :usize) -> Vec<(f64, Option<Option<AB>>, Option<f64>)> {
+    let length = topo.tracks[track].0;
+    let mut points :Vec<(f64, Option<Option<AB>>, Option<f64>)> = Vec::new();
+    for (pos, pta, func, dir) in &topo.trackobjects[track] {
+        let (signal, speed) = match func {
+            Function::MainSignal { .. } => (Some(*dir), None),
+            Function::SpeedChange => match speed_limit(model, pta) {
+                Some(v) => (None, Some(v)),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if length <= 2.0 * END_MARGIN { continue; }
+        let pos = pos.max(END_MARGIN).min(length - END_MARGIN);
+        match points.iter_mut().find(|(p,_,_)| (p - pos).abs() < 1e-3) {
+            Some(p) => {
+                if signal.is_some() { p.1 = signal; }
+                if speed.is_some() { p.2 = speed; }
+            },
+            None => points.push((pos, signal, speed)),
+        }
+    }
+    points.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap());
+    points
+}
+
+fn incoming_edge(track :usize, pieces :usize, end :AB) -> String {
+    match end {
+        AB::A => format!("-t{}_0", track),
+        AB::B => format!("t{}_{}", track, pieces - 1),
+    }
+}
+
+fn outgoing_edge(track :usize, pieces :usize, end :AB) -> String {
+    match end {
+        AB::A => format!("t{}_0", track),
+        AB::B => format!("-t{}_{}", track, pieces - 1),
+    }
+}
+
+/// The SUMO network of the model. `default_speed` (m/s) is used for track
+/// without speed information.
+pub fn sumo_network(model :&Model, default_speed :f64) -> Result<String, io::Error> {
+    let topo = topology::convert(model, SEGMENT_LENGTH).map_err(|_| {
+        io::Error::new(io::ErrorKind::Other, "topology conversion failed")
+    })?;
+
+    let mut junctions :BTreeMap<String, Junction> = BTreeMap::new();
+    let mut edges = Vec::new();
+    let mut connections = Vec::new();
+    let mut piece_counts = Vec::new();
+    let mut signal_count = 0;
+
+    for (ti, (length, (pa,_), (pb,_))) in topo.tracks.iter().enumerate() {
+        let points = split_points(model, &topo, ti);
+        let mut nodes = vec![node_id(*pa)];
+        let mut cuts = vec![0.0];
+        for (pos, signal, _) in &points {
+            let id = if signal.is_some() { signal_count += 1; format!("sig{}", signal_count) }
+                     else { format!("t{}_{:.0}", ti, pos) };
+            junctions.insert(id.clone(), Junction {
+                pos: point_at(&topo, ti, *pos),
+                kind: if signal.is_some() { JunctionKind::Signal } else { JunctionKind::Rail },
+                incoming: Vec::new(),
+            });
+            nodes.push(id);
+            cuts.push(*pos);
+        }
+        nodes.push(node_id(*pb));
+        cuts.push(*length);
+
+        let mut speed = default_speed;
+        for k in 0..(cuts.len()-1) {
+            if k > 0 { if let Some(v) = points[k-1].2 { speed = v; } }
+            let mut shape = topo.interval_map(ti, cuts[k], cuts[k+1]).into_iter().map(coords).collect::<Vec<_>>();
+            if shape.len() < 2 { shape = vec![point_at(&topo, ti, cuts[k]), point_at(&topo, ti, cuts[k+1])]; }
+            let (fwd, rev) = (format!("t{}_{}", ti, k), format!("-t{}_{}", ti, k));
+            let piece_length = cuts[k+1] - cuts[k];
+            edges.push(Edge { id: fwd.clone(), from: nodes[k].clone(), to: nodes[k+1].clone(),
+                              bidi: rev.clone(), speed, length: piece_length, shape: shape.clone() });
+            shape.reverse();
+            edges.push(Edge { id: rev.clone(), from: nodes[k+1].clone(), to: nodes[k].clone(),
+                              bidi: fwd.clone(), speed, length: piece_length, shape });
+        }
+
+        // Through the split points
+        for (k, (_, signal, _)) in points.iter().enumerate() {
+            let junction = nodes[k+1].clone();
+            let controls = |d :AB| match signal { Some(Some(dir)) => *dir == d, Some(None) => true, None => false };
+            connections.push(Connection { from: format!("t{}_{}", ti, k), to: format!("t{}_{}", ti, k+1),
+                                          junction: junction.clone(), signal: controls(AB::A) });
+            connections.push(Connection { from: format!("-t{}_{}", ti, k+1), to: format!("-t{}_{}", ti, k),
+                                          junction, signal: controls(AB::B) });
+        }
+        piece_counts.push(cuts.len() - 1);
+    }
+
+    // Track ends
+    let mut ends :BTreeMap<(i32,i32), Vec<(usize, AB, Port)>> = BTreeMap::new();
+    for (ti, (_, (pa,porta), (pb,portb))) in topo.tracks.iter().enumerate() {
+        ends.entry((pa.x,pa.y)).or_insert_with(Vec::new).push((ti, AB::A, *porta));
+        ends.entry((pb.x,pb.y)).or_insert_with(Vec::new).push((ti, AB::B, *portb));
+    }
+    for ((x,y), track_ends) in &ends {
+        let pt = glm::vec2(*x, *y);
+        let nd = topo.locations.get(&pt).map(|(nd,_)| *nd);
+        let kind = match nd {
+            Some(NDType::OpenEnd) | Some(NDType::BufferStop) | None => JunctionKind::DeadEnd,
+            _ => JunctionKind::Rail,
+        };
+        let id = node_id(pt);
+        junctions.insert(id.clone(), Junction { pos: coords(glm::vec2(*x as f32, *y as f32)), kind, incoming: Vec::new() });
+        for (t1, e1, p1) in track_ends {
+            for (t2, e2, p2) in track_ends {
+                if !p1.is_opposite(p2) { continue; }
+                connections.push(Connection {
+                    from: incoming_edge(*t1, piece_counts[*t1], *e1),
+                    to: outgoing_edge(*t2, piece_counts[*t2], *e2),
+                    junction: id.clone(),
+                    signal: false,
+                });
+            }
+        }
+    }
+
+    for e in &edges {
+        if let Some(j) = junctions.get_mut(&e.to) { j.incoming.push(format!("{}_0", e.id)); }
+    }
+
+    // Link indices of the connections at each junction, in order of the incoming lanes.
+    connections.sort_by(|a,b| (&a.junction, &a.from, &a.to).cmp(&(&b.junction, &b.from, &b.to)));
+    let mut link_counts :BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    let mut links = Vec::new();
+    for c in &connections {
+        let counts = link_counts.entry(c.junction.as_str()).or_insert((0,0));
+        let signal_link = if c.signal { counts.1 += 1; Some(counts.1 - 1) } else { None };
+        counts.0 += 1;
+        links.push(signal_link);
+    }
+
+    let all = junctions.values().map(|j| j.pos).collect::<Vec<_>>();
+    let min_x = all.iter().map(|p| p.0).fold(0.0f64, |a,b| a.min(b));
+    let min_y = all.iter().map(|p| p.1).fold(0.0f64, |a,b| a.min(b));
+    let max_x = all.iter().map(|p| p.0).fold(0.0f64, |a,b| a.max(b));
+    let max_y = all.iter().map(|p| p.1).fold(0.0f64, |a,b| a.max(b));
+    let boundary = format!("{:.2},{:.2},{:.2},{:.2}", min_x, min_y, max_x, max_y);
+
+    let mut out = String::new();
+    let w = |out :&mut String, s :String| { out.push_str(&s); out.push('\n'); };
+    w(&mut out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string());
+    w(&mut out, format!("<!-- generated by {} v{} -->", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+    w(&mut out, "<net version=\"1.9\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+                 xsi:noNamespaceSchemaLocation=\"http://sumo.dlr.de/xsd/net_file.xsd\">".to_string());
+    w(&mut out, format!("    <location netOffset=\"0.00,0.00\" convBoundary=\"{}\" origBoundary=\"{}\" projParameter=\"!\"/>",
+                        boundary, boundary));
+
+    for e in &edges {
+        w(&mut out, format!("    <edge id=\"{}\" from=\"{}\" to=\"{}\" priority=\"-1\" type=\"railway.rail\" \
+                             spreadType=\"center\" bidi=\"{}\">", e.id, e.from, e.to, e.bidi));
+        w(&mut out, format!("        <lane id=\"{}_0\" index=\"0\" allow=\"rail rail_urban rail_electric\" \
+                             speed=\"{:.2}\" length=\"{:.2}\" shape=\"{}\"/>",
+                            e.id, e.speed, e.length.max(0.1), fmt_shape(&e.shape)));
+        w(&mut out, "    </edge>".to_string());
+    }
+
+    // Signal plans
+    for (id, j) in &junctions {
+        if j.kind != JunctionKind::Signal { continue; }
+        let n = link_counts.get(id.as_str()).map(|c| c.1).unwrap_or(0);
+        w(&mut out, format!("    <tlLogic id=\"{}\" type=\"static\" programID=\"0\" offset=\"0\">", id));
+        w(&mut out, format!("        <phase duration=\"86400\" state=\"{}\"/>", "G".repeat(n.max(1))));
+        w(&mut out, "    </tlLogic>".to_string());
+    }
+
+    for (id, j) in &junctions {
+        let mut s = format!("    <junction id=\"{}\" type=\"{}\" x=\"{:.2}\" y=\"{:.2}\" incLanes=\"{}\" intLanes=\"\"",
+                            id, j.kind.name(), j.pos.0, j.pos.1, j.incoming.join(" "));
+        let n = link_counts.get(id.as_str()).map(|c| c.0).unwrap_or(0);
+        if j.kind == JunctionKind::DeadEnd || n == 0 {
+            s.push_str("/>");
+            w(&mut out, s);
+            continue;
+        }
+        s.push('>');
+        w(&mut out, s);
+        // Train routes are kept apart by the signals, so links are never foes.
+        let zeros = "0".repeat(n);
+        for i in 0..n {
+            w(&mut out, format!("        <request index=\"{}\" response=\"{}\" foes=\"{}\" cont=\"0\"/>", i, zeros, zeros));
+        }
+        w(&mut out, "    </junction>".to_string());
+    }
+
+    for (c, signal_link) in connections.iter().zip(links.iter()) {
+        let mut s = format!("    <connection from=\"{}\" to=\"{}\" fromLane=\"0\" toLane=\"0\"", c.from, c.to);
+        match signal_link {
+            Some(i) => { let _ = write!(s, " tl=\"{}\" linkIndex=\"{}\" dir=\"s\" state=\"O\"/>", c.junction, i); },
+            None => s.push_str(" dir=\"s\" state=\"M\"/>"),
+        }
+        w(&mut out, s);
+    }
+    w(&mut out, "</net>".to_string());
+    Ok(out)
+}
+
+pub fn export_sumo_to_file(filename :&str, model :&Model, default_speed :f64) -> Result<(), io::Error> {
+    std::fs::write(filename, sumo_network(model, default_speed)?)
+}
+
+pub fn export_sumo_interactive(model :&Model, default_speed :f64) -> Result<Option<String>, io::Error> {
+    if let Some(mut filename) = tinyfiledialogs::save_file_dialog("Export SUMO network", "network.net.xml") {
+        if !filename.ends_with(".xml") { filename.push_str(".net.xml"); }
+        info!("Exporting SUMO network to {:?}", filename);
+        export_sumo_to_file(&filename, model, default_speed)?;
+        Ok(Some(filename))
+    } else {
+        info!("User cancelled SUMO export");
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::objects::{Object, SignalKind};
+
+    #[test]
+    fn signal_splits_track() {
+        let mut model = Model::empty();
+        model.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        model.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        model.objects.insert(glm::vec2(10,0), Object { loc: glm::vec2(1.0, 0.0), tangent: glm::vec2(1,0),
+            functions: vec![Function::MainSignal { has_distant: false, kind: SignalKind::Main }] });
+        let net = sumo_network(&model, 20.0).unwrap();
+        assert_eq!(net.matches("<edge ").count(), 4);
+        assert_eq!(net.matches("type=\"rail_signal\"").count(), 1);
+        assert_eq!(net.matches("<tlLogic ").count(), 1);
+        assert_eq!(net.matches("type=\"dead_end\"").count(), 2);
+        assert!(net.contains("bidi=\"-t0_0\""));
+    }
+}
@@ -114,11 +114,13 @@ fn convert_signals(topo :&Topology, dgraph :&dgraph::DGraph,
                 | Function::TrainProtectionGroup
                 | Function::PlatformEdge
                 | Function::SpeedChange
+                | Function::GradientChange
+                | Function::RadiusChange
                 | Function::LevelCrossing
                 | Function::CrossSection => {
                     // Not handled by signal optimizer; ignore for now.
                 },
-                Function::Balise => {
+                Function::Balise | Function::BaliseGroup => {
                     // Not handled by signal optimizer; ignore for now.
                 },
             }
@@ -0,0 +1,138 @@
+//! Units for lengths and speeds shown to the user. The model always uses
+//! meters and meters per second.
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LengthUnit {
+    Meters,
+    Kilometers,
+    Miles,
+}
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpeedUnit {
+    KilometersPerHour,
+    MilesPerHour,
+}
+
+const METERS_PER_MILE :f64 = 1609.344;
+
+impl Default for LengthUnit { fn default() -> Self { LengthUnit::Meters } }
+impl Default for SpeedUnit { fn default() -> Self { SpeedUnit::KilometersPerHour } }
+
+impl LengthUnit {
+    pub const ALL :&'static [LengthUnit] = &[LengthUnit::Meters, LengthUnit::Kilometers, LengthUnit::Miles];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "Meters",
+            LengthUnit::Kilometers => "Kilometers",
+            LengthUnit::Miles => "Miles",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "m",
+            LengthUnit::Kilometers => "km",
+            LengthUnit::Miles => "mi",
+        }
+    }
+
+    /// Decimals needed to show about one meter of precision.
+    pub fn decimals(&self) -> usize {
+        match self {
+            LengthUnit::Meters => 0,
+            LengthUnit::Kilometers | LengthUnit::Miles => 3,
+        }
+    }
+
+    pub fn from_meters(&self, m :f64) -> f64 {
+        match self {
+            LengthUnit::Meters => m,
+            LengthUnit::Kilometers => m / 1000.0,
+            LengthUnit::Miles => m / METERS_PER_MILE,
+        }
+    }
+
+    pub fn to_meters(&self, x :f64) -> f64 {
+        match self {
+            LengthUnit::Meters => x,
+            LengthUnit::Kilometers => x * 1000.0,
+            LengthUnit::Miles => x * METERS_PER_MILE,
+        }
+    }
+}
+
+impl SpeedUnit {
+    pub const ALL :&'static [SpeedUnit] = &[SpeedUnit::KilometersPerHour, SpeedUnit::MilesPerHour];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpeedUnit::KilometersPerHour => "Kilometers per hour",
+            SpeedUnit::MilesPerHour => "Miles per hour",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            SpeedUnit::KilometersPerHour => "km/h",
+            SpeedUnit::MilesPerHour => "mph",
+        }
+    }
+
+    /// Convert from meters per second.
+    pub fn from_mps(&self, v :f64) -> f64 {
+        match self {
+            SpeedUnit::KilometersPerHour => v * 3.6,
+            SpeedUnit::MilesPerHour => v * 3600.0 / METERS_PER_MILE,
+        }
+    }
+
+    /// Convert to meters per second.
+    pub fn to_mps(&self, x :f64) -> f64 {
+        match self {
+            SpeedUnit::KilometersPerHour => x / 3.6,
+            SpeedUnit::MilesPerHour => x * METERS_PER_MILE / 3600.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Units {
+    pub length :LengthUnit,
+    pub speed :SpeedUnit,
+}
+
+impl Units {
+    /// Format a length given in meters, e.g. `1.250 km`.
+    pub fn length(&self, m :f64) -> String {
+        format!("{:.*} {}", self.length.decimals(), self.length.from_meters(m), self.length.symbol())
+    }
+
+    /// Format a speed given in meters per second, e.g. `80 km/h`.
+    pub fn speed(&self, v :f64) -> String {
+        format!("{:.0} {}", self.speed.from_mps(v), self.speed.symbol())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let units = Units { length: LengthUnit::Kilometers, speed: SpeedUnit::KilometersPerHour };
+        assert_eq!(units.length(1250.0), "1.250 km");
+        assert_eq!(units.speed(80.0 / 3.6), "80 km/h");
+        let units = Units { length: LengthUnit::Miles, speed: SpeedUnit::MilesPerHour };
+        assert_eq!(units.length(METERS_PER_MILE * 2.0), "2.000 mi");
+        assert_eq!(units.speed(SpeedUnit::MilesPerHour.to_mps(60.0)), "60 mph");
+        for u in LengthUnit::ALL {
+            assert!((u.to_meters(u.from_meters(123.0)) - 123.0).abs() < 1e-9);
+        }
+    }
+}